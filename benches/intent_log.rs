@@ -0,0 +1,69 @@
+//! Measures what `serve --intent-log` adds to a destructive tool call: an
+//! intent and an outcome entry, each appended and synced to disk. Calls that
+//! aren't destructive skip the log.
+//!
+//! Run with `cargo bench --bench intent_log`. The log goes in the system temp
+//! dir; set `TMPDIR` to measure another disk (on tmpfs, syncing is free).
+
+use mcpd::intent::IntentLog;
+use mcpd::mcp::CallToolResult;
+use mcpd::middleware::{CallContext, CallMiddleware, IntentLogger};
+use mcpd::proxy::ToolProxy;
+use mcpd::registry::Tool;
+use mcpd::secrets::SecretScanner;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const CALLS: u32 = 200;
+
+/// Mean time for `before` and `after` of a call to `delete_file`
+async fn time_per_call(logger: &IntentLogger, proxy: &Arc<ToolProxy>, args: &Value) -> Duration {
+    let mut total = Duration::ZERO;
+    for i in 0..CALLS {
+        let mut ctx = CallContext::new(
+            Arc::clone(proxy),
+            "delete_file",
+            "delete_file",
+            &format!("bench-{}", i),
+            args.clone(),
+            None,
+        );
+        let start = Instant::now();
+        logger.before(&mut ctx).await;
+        let result = CallToolResult {
+            content: Vec::new(),
+            is_error: false,
+            structured_content: None,
+            meta: None,
+        };
+        logger.after(&mut ctx, Ok(result)).await.unwrap();
+        total += start.elapsed();
+    }
+    total / CALLS
+}
+
+#[tokio::main]
+async fn main() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let log = Arc::new(IntentLog::open(&dir.path().join("intents.jsonl")).unwrap());
+    let logger = IntentLogger::new(log, vec!["delete".to_string()], SecretScanner::default());
+    let proxy = Arc::new(ToolProxy::new(Tool {
+        name: "bench".to_string(),
+        command: vec!["bench-server".to_string()],
+        ..Default::default()
+    }));
+
+    let cases = [
+        ("small (path arg)", json!({"path": "/home/user/notes.txt"})),
+        (
+            "file write (64 KiB)",
+            json!({"path": "src/main.rs", "content": "fn main() {}\n".repeat(5000)}),
+        ),
+    ];
+    println!("{:<24} {:>12}", "ARGUMENTS", "PER CALL");
+    for (name, args) in &cases {
+        let per_call = time_per_call(&logger, &proxy, args).await;
+        println!("{:<24} {:>12?}", name, per_call);
+    }
+}
@@ -0,0 +1,92 @@
+//! Measures how pipelining writes to a backend affects a burst of concurrent
+//! calls to a backend that answers at once, against the same backend
+//! registered with `no_pipelining`.
+//!
+//! Run with `cargo bench --bench pipelining`. The backend is this binary,
+//! run again with `PIPELINING_BENCH_BACKEND=1`.
+
+use mcpd::proxy::ToolProxy;
+use mcpd::registry::Tool;
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const CALLS: usize = 100;
+const RUNS: u32 = 10;
+
+/// Answer every request straight away: just enough MCP for `call_tool`
+fn backend() {
+    let stdin = std::io::stdin();
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for line in stdin.lock().lines() {
+        let request: Value = serde_json::from_str(&line.unwrap()).unwrap();
+        let Some(id) = request.get("id") else {
+            continue;
+        };
+        let result = match request["method"].as_str() {
+            Some("initialize") => json!({
+                "protocolVersion": "2025-11-25",
+                "capabilities": {},
+                "serverInfo": {"name": "bench", "version": "0"}
+            }),
+            _ => json!({"content": [{"type": "text", "text": "ok"}]}),
+        };
+        let response = json!({"jsonrpc": "2.0", "id": id, "result": result});
+        writeln!(out, "{}", response).unwrap();
+        out.flush().unwrap();
+    }
+}
+
+/// Time for `CALLS` concurrent tool calls to all be answered
+async fn burst(proxy: &Arc<ToolProxy>) -> Duration {
+    let start = Instant::now();
+    let mut calls = tokio::task::JoinSet::new();
+    for n in 0..CALLS {
+        let proxy = Arc::clone(proxy);
+        calls.spawn(async move { proxy.call_tool("echo", json!({"n": n})).await });
+    }
+    for result in calls.join_all().await {
+        result.unwrap();
+    }
+    start.elapsed()
+}
+
+#[tokio::main]
+async fn main() {
+    if std::env::var_os("PIPELINING_BENCH_BACKEND").is_some() {
+        backend();
+        return;
+    }
+
+    let exe = std::env::current_exe().unwrap();
+    println!("{:<16} {:>12} {:>12}", "MODE", "BURST", "CALLS/S");
+    for no_pipelining in [false, true] {
+        let tool = Tool::builder("bench")
+            .command([exe.to_string_lossy().into_owned()])
+            .env("PIPELINING_BENCH_BACKEND", "1")
+            .no_pipelining(no_pipelining)
+            .build()
+            .unwrap();
+        let proxy = Arc::new(ToolProxy::new(tool));
+        // Start the backend and warm up
+        burst(&proxy).await;
+        let mut total = Duration::ZERO;
+        for _ in 0..RUNS {
+            total += burst(&proxy).await;
+        }
+        let mean = total / RUNS;
+        let mode = if no_pipelining {
+            "no_pipelining"
+        } else {
+            "pipelined"
+        };
+        println!(
+            "{:<16} {:>12?} {:>12.0}",
+            mode,
+            mean,
+            CALLS as f64 / mean.as_secs_f64()
+        );
+        proxy.stop().await.unwrap();
+    }
+}
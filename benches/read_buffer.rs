@@ -0,0 +1,87 @@
+//! Measures how the backend read buffer size affects reading large responses
+//! from a subprocess's stdout.
+//!
+//! Run with `cargo bench --bench read_buffer`.
+
+use mcpd::framing::{self, Framing};
+use mcpd::limits::ReadLine;
+use serde_json::json;
+use std::io::Write;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::BufReader;
+use tokio::process::Command;
+
+const RESPONSES: usize = 8;
+const RUNS: u32 = 5;
+
+/// Lines of `RESPONSES` tool results, each with a `size`-byte text block
+fn responses(size: usize) -> Vec<u8> {
+    let text = "x".repeat(size);
+    let mut out = Vec::new();
+    for id in 0..RESPONSES {
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": {"content": [{"type": "text", "text": text}]}
+        });
+        serde_json::to_writer(&mut out, &response).unwrap();
+        out.push(b'\n');
+    }
+    out
+}
+
+/// Time for a subprocess to stream `path` through a pipe and for mcpd's
+/// reader to split it into messages
+async fn read_through_pipe(path: &std::path::Path, capacity: usize) -> Duration {
+    let start = Instant::now();
+    let mut child = Command::new("cat")
+        .arg(path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut reader = BufReader::with_capacity(capacity, child.stdout.take().unwrap());
+    let mut message = Vec::new();
+    let mut count = 0;
+    while let ReadLine::Line = framing::read_message(
+        &mut reader,
+        Framing::Lines,
+        &mut message,
+        usize::MAX,
+        usize::MAX,
+    )
+    .await
+    .unwrap()
+    {
+        count += 1;
+    }
+    assert_eq!(count, RESPONSES);
+    child.wait().await.unwrap();
+    start.elapsed()
+}
+
+#[tokio::main]
+async fn main() {
+    let capacities = [8 << 10, 64 << 10, 256 << 10, 1 << 20];
+    print!("{:<20}", "RESPONSE");
+    for capacity in capacities {
+        print!(" {:>12}", format!("{} KiB", capacity >> 10));
+    }
+    println!();
+
+    for size in [64 << 10, 1 << 20, 4 << 20] {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&responses(size)).unwrap();
+        print!("{:<20}", format!("{} x {} KiB", RESPONSES, size >> 10));
+        for capacity in capacities {
+            // Warm up the page cache
+            read_through_pipe(file.path(), capacity).await;
+            let mut total = Duration::ZERO;
+            for _ in 0..RUNS {
+                total += read_through_pipe(file.path(), capacity).await;
+            }
+            print!(" {:>12?}", total / RUNS);
+        }
+        println!();
+    }
+}
@@ -0,0 +1,60 @@
+//! Measures what the outbound secret scan adds to a tool call.
+//!
+//! Run with `cargo bench --bench secret_scan`.
+
+use mcpd::secrets::SecretScanner;
+use serde_json::{Value, json};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn time_per_call(iterations: u32, mut f: impl FnMut()) -> Duration {
+    // Warm up caches and the regex lazy DFA
+    for _ in 0..100 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    let scanner = SecretScanner::default();
+    let prose = "The quick brown fox jumps over the lazy dog. ".repeat(20);
+
+    let cases: Vec<(&str, Value)> = vec![
+        ("small (path arg)", json!({"path": "/home/user/notes.txt"})),
+        (
+            "typical (~1 KiB, nested)",
+            json!({
+                "owner": "xandwr",
+                "repo": "mcpd",
+                "title": "Scanner benchmark",
+                "body": prose,
+                "labels": ["bug", "perf"],
+                "options": {"draft": false, "reviewers": ["a", "b"]},
+            }),
+        ),
+        (
+            "file write (64 KiB)",
+            json!({"path": "src/main.rs", "content": "fn main() {}\n".repeat(5000)}),
+        ),
+        (
+            "many strings (1000 items)",
+            json!({"items": (0..1000).map(|i| format!("item-{}", i)).collect::<Vec<_>>()}),
+        ),
+    ];
+
+    println!("{:<28} {:>12} {:>12}", "ARGUMENTS", "SCAN", "REDACT");
+    for (name, args) in &cases {
+        let scan = time_per_call(2000, || {
+            black_box(scanner.scan("bench", "tool", black_box(args)));
+        });
+        let redact = time_per_call(2000, || {
+            let mut args = args.clone();
+            black_box(scanner.redact("bench", "tool", &mut args));
+        });
+        println!("{:<28} {:>12?} {:>12?}", name, scan, redact);
+    }
+}
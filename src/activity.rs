@@ -0,0 +1,503 @@
+//! In-flight call tracking and per-backend counters, used to build the
+//! snapshots served by the `mcpd/inspect` control method.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Lifecycle state of a backend subprocess
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendState {
+    Stopped,
+    Starting,
+    Ready,
+    Unavailable,
+}
+
+impl BackendState {
+    pub fn as_u8(self) -> u8 {
+        match self {
+            BackendState::Stopped => 0,
+            BackendState::Starting => 1,
+            BackendState::Ready => 2,
+            BackendState::Unavailable => 3,
+        }
+    }
+
+    pub fn from_u8(n: u8) -> Self {
+        match n {
+            1 => BackendState::Starting,
+            2 => BackendState::Ready,
+            3 => BackendState::Unavailable,
+            _ => BackendState::Stopped,
+        }
+    }
+}
+
+impl std::fmt::Display for BackendState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            BackendState::Stopped => "stopped",
+            BackendState::Starting => "starting",
+            BackendState::Ready => "ready",
+            BackendState::Unavailable => "unavailable",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Point-in-time view of everything mcpd is doing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub pid: u32,
+    pub uptime_secs: u64,
+    pub backends: Vec<BackendSnapshot>,
+    pub totals: Totals,
+    /// Admission queues under `--max-concurrent-calls`, highest priority first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queues: Vec<QueueSnapshot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendSnapshot {
+    pub name: String,
+    pub state: BackendState,
+    pub active_calls: Vec<ActiveCallSnapshot>,
+    pub calls: u64,
+    pub errors: u64,
+    #[serde(default)]
+    pub spawns: u64,
+    #[serde(default)]
+    pub restarts: u64,
+    #[serde(default)]
+    pub crashes: u64,
+    /// Notifications not relayed for going over the backend's rate limits
+    #[serde(default)]
+    pub dropped_notifications: u64,
+    /// Why the backend is failing calls fast, if it answered initialize with an error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init_error: Option<String>,
+    /// The last health check failure, while health checks have it degraded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub degraded: Option<String>,
+    /// Per process, for a backend run with `replicas`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<InstanceSnapshot>,
+}
+
+/// One process of a backend run with `replicas`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceSnapshot {
+    /// Its `MCPD_INSTANCE_INDEX`
+    pub index: usize,
+    pub state: BackendState,
+    /// Went away on its own and hasn't been started again yet
+    #[serde(default)]
+    pub crashed: bool,
+    /// Tool calls it has finished
+    pub calls: u64,
+    /// Tool calls it's working on
+    pub active: u64,
+    /// Total time spent on tool calls, overlapping ones counted separately
+    pub busy_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveCallSnapshot {
+    pub correlation_id: String,
+    pub tool: String,
+    pub elapsed_ms: u64,
+    /// What the call is waiting on, see `CallGuard::enter`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub stage: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    pub priority: String,
+    /// Calls currently waiting for a slot
+    pub waiting: usize,
+    /// Calls admitted so far, with or without waiting
+    pub admitted: u64,
+    pub total_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Totals {
+    pub calls: u64,
+    pub errors: u64,
+    pub active: usize,
+}
+
+struct ActiveCall {
+    correlation_id: String,
+    backend: String,
+    tool: String,
+    started: Instant,
+    stage: &'static str,
+}
+
+impl ActiveCall {
+    fn snapshot(&self, now: Instant) -> ActiveCallSnapshot {
+        ActiveCallSnapshot {
+            correlation_id: self.correlation_id.clone(),
+            tool: self.tool.clone(),
+            elapsed_ms: now.duration_since(self.started).as_millis() as u64,
+            stage: self.stage.to_string(),
+        }
+    }
+}
+
+/// Stage of a call running the middleware chain, on either side of the backend
+pub const STAGE_MIDDLEWARE: &str = "middleware";
+/// Stage of a call waiting for a slot under `--max-concurrent-calls`
+pub const STAGE_QUEUED: &str = "queued";
+/// Stage of a call waiting on its backend (or the backend's fallback)
+pub const STAGE_BACKEND: &str = "backend";
+
+/// Subprocess lifecycle counters for one backend. Shared with its proxy, and
+/// kept by name so they survive the proxy being replaced.
+#[derive(Debug, Default)]
+pub struct LifecycleCounters {
+    spawns: AtomicU64,
+    restarts: AtomicU64,
+    crashes: AtomicU64,
+    dropped_notifications: AtomicU64,
+}
+
+impl LifecycleCounters {
+    /// Record a successful spawn, a restart if it replaces a process that
+    /// crashed or was killed (not one mcpd stopped)
+    pub fn spawned(&self, restart: bool) {
+        self.spawns.fetch_add(1, Ordering::Relaxed);
+        if restart {
+            self.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a restart mcpd was asked for (from the control socket or a
+    /// failed health check), or a zero-downtime replacement
+    pub fn restarted(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the subprocess going away without mcpd stopping it
+    pub fn crashed(&self) {
+        self.crashes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a notification dropped by the backend's rate limits
+    pub fn dropped_notification(&self) {
+        self.dropped_notifications.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn spawns(&self) -> u64 {
+        self.spawns.load(Ordering::Relaxed)
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    pub fn crashes(&self) -> u64 {
+        self.crashes.load(Ordering::Relaxed)
+    }
+
+    pub fn dropped_notifications(&self) -> u64 {
+        self.dropped_notifications.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counters {
+    calls: u64,
+    errors: u64,
+}
+
+/// Tracks in-flight calls and per-backend counters. Locks are only held for
+/// map updates, never across an await.
+pub struct Activity {
+    started: Instant,
+    next_id: AtomicU64,
+    active: Mutex<HashMap<u64, ActiveCall>>,
+    counters: Mutex<HashMap<String, Counters>>,
+    lifecycle: Mutex<HashMap<String, Arc<LifecycleCounters>>>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Activity {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            next_id: AtomicU64::new(1),
+            active: Mutex::new(HashMap::new()),
+            counters: Mutex::new(HashMap::new()),
+            lifecycle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lifecycle counters for a backend, created on first use
+    pub fn lifecycle(&self, backend: &str) -> Arc<LifecycleCounters> {
+        Arc::clone(
+            self.lifecycle
+                .lock()
+                .unwrap()
+                .entry(backend.to_string())
+                .or_default(),
+        )
+    }
+
+    /// Record the start of a call. The returned guard marks it finished when
+    /// dropped (counting it as an error unless `succeeded` was called). The
+    /// correlation id names the client connection the call came from.
+    pub fn begin(&self, connection: u64, backend: &str, tool: &str) -> CallGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let correlation_id = format!("mcpd-{}-{}", connection, id);
+        self.active.lock().unwrap().insert(
+            id,
+            ActiveCall {
+                correlation_id: correlation_id.clone(),
+                backend: backend.to_string(),
+                tool: tool.to_string(),
+                started: Instant::now(),
+                stage: STAGE_MIDDLEWARE,
+            },
+        );
+        CallGuard {
+            activity: self,
+            id,
+            backend: backend.to_string(),
+            correlation_id,
+            ok: false,
+        }
+    }
+
+    /// Every in-flight call as `(backend, call)`, longest-running first.
+    /// Unlike `snapshot` this needs no backend states, so it can be taken
+    /// while the server's own locks are stuck.
+    pub fn active_calls(&self) -> Vec<(String, ActiveCallSnapshot)> {
+        let now = Instant::now();
+        let mut calls: Vec<_> = self
+            .active
+            .lock()
+            .unwrap()
+            .values()
+            .map(|call| (call.backend.clone(), call.snapshot(now)))
+            .collect();
+        calls.sort_by_key(|(_, c)| std::cmp::Reverse(c.elapsed_ms));
+        calls
+    }
+
+    fn enter(&self, id: u64, stage: &'static str) {
+        if let Some(call) = self.active.lock().unwrap().get_mut(&id) {
+            call.stage = stage;
+        }
+    }
+
+    fn finish(&self, id: u64, backend: &str, ok: bool) {
+        self.active.lock().unwrap().remove(&id);
+        let mut counters = self.counters.lock().unwrap();
+        let entry = counters.entry(backend.to_string()).or_default();
+        entry.calls += 1;
+        if !ok {
+            entry.errors += 1;
+        }
+    }
+
+    /// Assemble a snapshot given the current state of each registered backend.
+    /// Backends with activity but no longer registered are omitted.
+    pub fn snapshot(&self, backends: &[(String, BackendState)]) -> Snapshot {
+        let now = Instant::now();
+        let mut active_by_backend: BTreeMap<&str, Vec<ActiveCallSnapshot>> = BTreeMap::new();
+        let active = self.active.lock().unwrap();
+        for call in active.values() {
+            active_by_backend
+                .entry(call.backend.as_str())
+                .or_default()
+                .push(call.snapshot(now));
+        }
+        let counters = self.counters.lock().unwrap().clone();
+        let lifecycle = self.lifecycle.lock().unwrap().clone();
+
+        let mut sorted: Vec<_> = backends.to_vec();
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut totals = Totals::default();
+        let backends = sorted
+            .into_iter()
+            .map(|(name, state)| {
+                let mut active_calls = active_by_backend.remove(name.as_str()).unwrap_or_default();
+                active_calls.sort_by_key(|c| std::cmp::Reverse(c.elapsed_ms));
+                let c = counters.get(&name).copied().unwrap_or_default();
+                totals.calls += c.calls;
+                totals.errors += c.errors;
+                totals.active += active_calls.len();
+                let l = lifecycle.get(&name).cloned().unwrap_or_default();
+                BackendSnapshot {
+                    name,
+                    state,
+                    active_calls,
+                    calls: c.calls,
+                    errors: c.errors,
+                    spawns: l.spawns(),
+                    restarts: l.restarts(),
+                    crashes: l.crashes(),
+                    dropped_notifications: l.dropped_notifications(),
+                    init_error: None,
+                    degraded: None,
+                    instances: Vec::new(),
+                }
+            })
+            .collect();
+
+        Snapshot {
+            pid: std::process::id(),
+            uptime_secs: now.duration_since(self.started).as_secs(),
+            backends,
+            totals,
+            queues: Vec::new(),
+        }
+    }
+}
+
+/// Marks an in-flight call; see `Activity::begin`
+pub struct CallGuard<'a> {
+    activity: &'a Activity,
+    id: u64,
+    backend: String,
+    correlation_id: String,
+    ok: bool,
+}
+
+impl CallGuard<'_> {
+    pub fn correlation_id(&self) -> &str {
+        &self.correlation_id
+    }
+
+    /// Record what the call is waiting on from now on, one of the `STAGE_`
+    /// constants
+    pub fn enter(&self, stage: &'static str) {
+        self.activity.enter(self.id, stage);
+    }
+
+    /// Mark the call as successful
+    pub fn succeeded(&mut self) {
+        self.ok = true;
+    }
+}
+
+impl Drop for CallGuard<'_> {
+    fn drop(&mut self) {
+        self.activity.finish(self.id, &self.backend, self.ok);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_empty() {
+        let activity = Activity::new();
+        let snap = activity.snapshot(&[]);
+        assert!(snap.backends.is_empty());
+        assert_eq!(snap.totals.active, 0);
+    }
+
+    #[test]
+    fn snapshot_tracks_active_calls() {
+        let activity = Activity::new();
+        let guard = activity.begin(1, "fs", "read_file");
+        let snap = activity.snapshot(&[("fs".to_string(), BackendState::Ready)]);
+        assert_eq!(snap.backends.len(), 1);
+        assert_eq!(snap.backends[0].active_calls.len(), 1);
+        assert_eq!(snap.backends[0].active_calls[0].tool, "read_file");
+        assert_eq!(
+            snap.backends[0].active_calls[0].correlation_id,
+            guard.correlation_id()
+        );
+        assert_eq!(snap.totals.active, 1);
+        drop(guard);
+
+        let snap = activity.snapshot(&[("fs".to_string(), BackendState::Ready)]);
+        assert!(snap.backends[0].active_calls.is_empty());
+    }
+
+    #[test]
+    fn active_calls_report_their_stage() {
+        let activity = Activity::new();
+        let first = activity.begin(1, "fs", "read_file");
+        let second = activity.begin(1, "git", "log");
+        second.enter(STAGE_BACKEND);
+
+        let calls = activity.active_calls();
+        let stage = |backend: &str| {
+            let (_, call) = calls.iter().find(|(b, _)| b == backend).unwrap();
+            call.stage.clone()
+        };
+        assert_eq!(stage("fs"), STAGE_MIDDLEWARE);
+        assert_eq!(stage("git"), STAGE_BACKEND);
+        drop((first, second));
+        assert!(activity.active_calls().is_empty());
+    }
+
+    #[test]
+    fn snapshot_counts_calls_and_errors() {
+        let activity = Activity::new();
+        activity.begin(1, "a", "t").succeeded();
+        drop(activity.begin(1, "a", "t"));
+        activity.begin(1, "b", "t").succeeded();
+
+        let snap = activity.snapshot(&[
+            ("b".to_string(), BackendState::Stopped),
+            ("a".to_string(), BackendState::Ready),
+        ]);
+        assert_eq!(snap.backends[0].name, "a");
+        assert_eq!(snap.backends[0].calls, 2);
+        assert_eq!(snap.backends[0].errors, 1);
+        assert_eq!(snap.backends[1].calls, 1);
+        assert_eq!(snap.totals.calls, 3);
+        assert_eq!(snap.totals.errors, 1);
+    }
+
+    #[test]
+    fn lifecycle_counters_in_snapshot() {
+        let activity = Activity::new();
+        let fs = activity.lifecycle("fs");
+        fs.spawned(false);
+        fs.crashed();
+        fs.spawned(true);
+        // Same counters are handed out again for the same backend
+        assert_eq!(activity.lifecycle("fs").spawns(), 2);
+
+        let snap = activity.snapshot(&[
+            ("fs".to_string(), BackendState::Ready),
+            ("idle".to_string(), BackendState::Stopped),
+        ]);
+        assert_eq!(snap.backends[0].spawns, 2);
+        assert_eq!(snap.backends[0].restarts, 1);
+        assert_eq!(snap.backends[0].crashes, 1);
+        assert_eq!(snap.backends[1].spawns, 0);
+    }
+
+    #[test]
+    fn backend_state_u8_roundtrip() {
+        for state in [
+            BackendState::Stopped,
+            BackendState::Starting,
+            BackendState::Ready,
+            BackendState::Unavailable,
+        ] {
+            assert_eq!(BackendState::from_u8(state.as_u8()), state);
+        }
+    }
+}
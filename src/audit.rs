@@ -0,0 +1,275 @@
+//! Audit records: one JSON object per `tools/call`, one per line, written
+//! by `AuditLog` (`serve --audit-log`).
+//!
+//! The reader is forgiving on purpose. An audit file is appended to by a
+//! process that can die mid-write, so the last line may be cut short, and a
+//! file that's been copied around may have picked up blank or mangled lines.
+//! Lines that don't parse are counted and skipped rather than failing the
+//! whole file.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Default for `serve --audit-max-bytes`
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Rotated files kept besides the live one: `<path>.1` (newest) to `<path>.5`
+pub const ROTATED_FILES: usize = 5;
+
+/// One tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// When the call arrived
+    pub timestamp: DateTime<Utc>,
+    /// The call's correlation id, shared with logs and traces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// Tool name as the client saw it
+    pub name: String,
+    pub backend: String,
+    /// Tool name as the backend knows it
+    pub tool: String,
+    /// Call arguments; absent when arguments are redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Value>,
+    pub is_error: bool,
+    /// The call ran out of time waiting for its backend
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// Why the call failed, when it never produced a tool result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    /// The result's content items, as returned to the client
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content: Vec<Value>,
+}
+
+/// An audit file being appended to. Once a record would take the file past
+/// `max_bytes`, it's renamed to `<path>.1` (older rotations move up one,
+/// the oldest is dropped) and a new file is started.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// `None` never rotates
+    max_bytes: Option<u64>,
+    file: Mutex<(File, u64)>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path, max_bytes: Option<u64>) -> Result<Self> {
+        let file = open_append(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append `record` as one line, rotating first if it doesn't fit
+    pub fn append(&self, record: &Record) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        if let Some(max) = self.max_bytes
+            && file.1 > 0
+            && file.1 + line.len() as u64 > max
+        {
+            self.rotate()?;
+            *file = open_append(&self.path)?;
+        }
+        let (current, size) = &mut *file;
+        current
+            .write_all(&line)
+            .with_context(|| format!("Failed to write audit log {}", self.path.display()))?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&self) -> Result<()> {
+        let rotated = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+        let _ = std::fs::remove_file(rotated(ROTATED_FILES));
+        for n in (1..ROTATED_FILES).rev() {
+            let from = rotated(n);
+            if from.exists() {
+                std::fs::rename(&from, rotated(n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(1))
+            .with_context(|| format!("Failed to rotate audit log {}", self.path.display()))
+    }
+}
+
+/// Open `path` for appending, with its current size. A record cut short by
+/// a crash is ended with a newline so the next one starts on its own line.
+fn open_append(path: &Path) -> Result<(File, u64)> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+    let mut size = file.metadata()?.len();
+    if size > 0 {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut last = [0u8];
+        let mut reader = File::open(path)?;
+        reader.seek(SeekFrom::End(-1))?;
+        reader.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            file.write_all(b"\n")?;
+            size += 1;
+        }
+    }
+    Ok((file, size))
+}
+
+/// The records of an audit file
+#[derive(Debug, Default)]
+pub struct Log {
+    pub records: Vec<Record>,
+    /// Lines that weren't records, not counting a cut-off last line
+    pub skipped: usize,
+    /// The file ended partway through a record
+    pub truncated: bool,
+}
+
+/// Read an audit file
+pub fn read(path: &Path) -> Result<Log> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read audit log {}", path.display()))?;
+    Ok(parse(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Parse audit records from JSONL text
+pub fn parse(text: &str) -> Log {
+    let mut log = Log::default();
+    let complete = text.ends_with('\n');
+    let mut lines = text.lines().peekable();
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(record) => log.records.push(record),
+            Err(_) if lines.peek().is_none() && !complete => log.truncated = true,
+            Err(_) => log.skipped += 1,
+        }
+    }
+    log
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn line(correlation_id: &str) -> String {
+        json!({
+            "timestamp": "2026-10-17T09:00:00Z",
+            "correlation_id": correlation_id,
+            "name": "fs__read",
+            "backend": "fs",
+            "tool": "read",
+            "arguments": {"path": "/tmp"},
+            "is_error": false,
+            "latency_ms": 4,
+            "content": [{"type": "text", "text": "hi"}],
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn round_trips_records() {
+        let log = parse(&format!("{}\n", line("mcpd-1-1")));
+        assert_eq!(log.records.len(), 1);
+        let record = &log.records[0];
+        assert_eq!(record.correlation_id.as_deref(), Some("mcpd-1-1"));
+        let again: Record = serde_json::from_str(&serde_json::to_string(record).unwrap()).unwrap();
+        assert_eq!(&again, record);
+    }
+
+    #[test]
+    fn cut_off_last_line_is_not_an_error() {
+        let full = line("mcpd-1-2");
+        let text = format!("{}\n{}", line("mcpd-1-1"), &full[..full.len() / 2]);
+        let log = parse(&text);
+        assert_eq!(log.records.len(), 1);
+        assert!(log.truncated);
+        assert_eq!(log.skipped, 0);
+    }
+
+    #[test]
+    fn mangled_lines_are_skipped() {
+        let text = format!("{}\n\nnot json\n{{}}\n{}\n", line("a"), line("b"));
+        let log = parse(&text);
+        let ids: Vec<_> = log
+            .records
+            .iter()
+            .map(|r| r.correlation_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert_eq!(log.skipped, 2);
+        assert!(!log.truncated);
+    }
+
+    fn record(correlation_id: &str) -> Record {
+        serde_json::from_str(&line(correlation_id)).unwrap()
+    }
+
+    #[test]
+    fn log_rotates_by_size() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let id = |i: usize| format!("mcpd-1-{:02}", i);
+        let size = serde_json::to_vec(&record(&id(0))).unwrap().len() as u64 + 1;
+        // Room for two records per file
+        let log = AuditLog::open(&path, Some(size * 2)).unwrap();
+        let last = 2 * ROTATED_FILES + 2;
+        for i in 0..=last {
+            log.append(&record(&id(i))).unwrap();
+        }
+        let ids = |path: &Path| -> Vec<String> {
+            read(path)
+                .unwrap()
+                .records
+                .into_iter()
+                .map(|r| r.correlation_id.unwrap())
+                .collect()
+        };
+        assert_eq!(ids(&path), [id(last)]);
+        assert_eq!(
+            ids(&dir.path().join("audit.jsonl.1")),
+            [id(last - 2), id(last - 1)]
+        );
+        // Past the last kept file, the oldest records are dropped
+        let rotated = |n: usize| dir.path().join(format!("audit.jsonl.{}", n));
+        assert_eq!(ids(&rotated(ROTATED_FILES)), [id(2), id(3)]);
+        assert!(!rotated(ROTATED_FILES + 1).exists());
+    }
+
+    #[test]
+    fn reopening_after_a_crash_starts_a_fresh_line() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let full = line("mcpd-1-1");
+        std::fs::write(&path, &full[..full.len() / 2]).unwrap();
+        AuditLog::open(&path, None)
+            .unwrap()
+            .append(&record("mcpd-1-2"))
+            .unwrap();
+        let log = read(&path).unwrap();
+        assert_eq!(log.records.len(), 1);
+        assert_eq!(log.skipped, 1);
+        assert!(!log.truncated);
+    }
+}
@@ -0,0 +1,123 @@
+//! Choosing which instance of a replicated backend takes a tool call.
+//!
+//! Calls go to the instances in turn, or, for a backend with `shard_by`, to
+//! the instance the named argument hashes to, so calls about the same thing
+//! land on the same process and find its caches warm. Sharding uses
+//! rendezvous hashing: each instance scores the key and the highest score
+//! wins. When an instance is down its keys go to their second choice while
+//! every other key stays put, and they come back once it's up again.
+//!
+//! Both take the instances' availability, and pass over unavailable ones
+//! unless none are left, in which case the call goes where it would have
+//! gone anyway, to fail or bring the instance back.
+
+use crate::canonical::{self, Nulls};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// The instance whose turn it is. Turns go round the available instances
+/// only, so the rest split a down instance's share evenly.
+pub fn round_robin(turn: usize, available: &[bool]) -> usize {
+    let up: Vec<usize> = (0..available.len()).filter(|&i| available[i]).collect();
+    match up.len() {
+        0 => turn % available.len(),
+        n => up[turn % n],
+    }
+}
+
+/// The available instance `key` hashes to. The same key goes to the same
+/// instance for as long as the instance count doesn't change, in every
+/// mcpd process.
+pub fn by_key(key: &Value, available: &[bool]) -> usize {
+    let key = canonical::to_bytes(key, Nulls::Keep);
+    let scores: Vec<u64> = (0..available.len()).map(|i| score(&key, i)).collect();
+    let best = |only_available: bool| {
+        (0..available.len())
+            .filter(|&i| available[i] || !only_available)
+            .max_by_key(|&i| scores[i])
+    };
+    best(true).or_else(|| best(false)).unwrap_or(0)
+}
+
+/// How much `instance` wants `key`
+fn score(key: &[u8], instance: usize) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(key)
+        .chain_update((instance as u64).to_le_bytes())
+        .finalize();
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn round_robin_is_fair_and_skips_unavailable() {
+        let mut counts = [0; 3];
+        for turn in 0..300 {
+            counts[round_robin(turn, &[true; 3])] += 1;
+        }
+        assert_eq!(counts, [100; 3]);
+
+        let picks: Vec<usize> = (0..6)
+            .map(|turn| round_robin(turn, &[true, false, true]))
+            .collect();
+        assert_eq!(picks, [0, 2, 0, 2, 0, 2]);
+
+        // With nothing available the turn stands
+        assert_eq!(round_robin(4, &[false; 3]), 1);
+    }
+
+    #[test]
+    fn keys_hash_to_the_same_instance_every_time() {
+        let all = [true; 4];
+        // Fixed, so a change to the hash that would move every key in a
+        // deployment shows up here
+        let picks: Vec<usize> = ["src/main.rs", "src/lib.rs", "README.md", "Cargo.toml"]
+            .iter()
+            .map(|path| by_key(&json!(path), &all))
+            .collect();
+        assert_eq!(picks, [2, 1, 3, 0]);
+        // Key order in an object key doesn't matter
+        assert_eq!(
+            by_key(&json!({"a": 1, "b": 2}), &all),
+            by_key(&json!({"b": 2, "a": 1}), &all)
+        );
+    }
+
+    #[test]
+    fn keys_spread_across_instances() {
+        let mut counts = [0; 4];
+        for i in 0..4000 {
+            counts[by_key(&json!(format!("file-{i}.rs")), &[true; 4])] += 1;
+        }
+        assert!(
+            counts.iter().all(|&c| (800..1200).contains(&c)),
+            "{counts:?}"
+        );
+    }
+
+    #[test]
+    fn only_a_down_instances_keys_move() {
+        let keys: Vec<Value> = (0..500).map(|i| json!(i)).collect();
+        let before: Vec<usize> = keys.iter().map(|k| by_key(k, &[true; 4])).collect();
+        let after: Vec<usize> = keys
+            .iter()
+            .map(|k| by_key(k, &[true, false, true, true]))
+            .collect();
+        for (b, a) in before.iter().zip(&after) {
+            if *b == 1 {
+                assert_ne!(*a, 1);
+            } else {
+                assert_eq!(a, b);
+            }
+        }
+        // Back up, its keys return
+        let again: Vec<usize> = keys.iter().map(|k| by_key(k, &[true; 4])).collect();
+        assert_eq!(again, before);
+        // With none available the first choice still gets the call
+        assert_eq!(by_key(&keys[0], &[false; 4]), before[0]);
+    }
+}
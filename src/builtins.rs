@@ -0,0 +1,274 @@
+//! Tools mcpd answers itself instead of a backend. `Server` lists them
+//! after the backends' tools and runs them from `use_tool` before routing,
+//! so they need no subprocess; results still get `StructuredCompat`.
+//!
+//! To add one, implement `BuiltinTool`, naming it `mcpd__<something>` so it
+//! can't be mistaken for a backend's tool. mcpd's own are switched on by a
+//! `ServeOptions` flag in `defaults`; anything else can be handed to
+//! `Server::with_builtin`.
+
+use crate::compose;
+use crate::error_text;
+use crate::mcp::{CallToolResult, Content, Tool as McpTool};
+use crate::middleware::BoxFuture;
+use crate::ping;
+use crate::server::{CallProfile, ServeOptions, Server};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// A tool implemented by mcpd itself
+pub trait BuiltinTool: Send + Sync {
+    /// Name it's listed and called by
+    fn name(&self) -> &str;
+
+    /// Its `list_tools` entry: description and input schema
+    fn tool(&self) -> McpTool;
+
+    /// Answer a call. Failures are error results, as from a backend.
+    fn call<'a>(
+        &'a self,
+        server: &'a Server,
+        call: BuiltinCall<'a>,
+    ) -> BoxFuture<'a, CallToolResult>;
+}
+
+/// One call to a built-in tool
+pub struct BuiltinCall<'a> {
+    pub arguments: Value,
+    /// `_meta` the client sent with the call
+    pub meta: Option<&'a Value>,
+    /// The calling client's connection
+    pub connection: u64,
+}
+
+/// The built-in tools `options` switch on
+pub fn defaults(options: &ServeOptions) -> Vec<Arc<dyn BuiltinTool>> {
+    let mut tools: Vec<Arc<dyn BuiltinTool>> = Vec::new();
+    if options.compose {
+        tools.push(Arc::new(Compose));
+    }
+    if options.ping_tool {
+        tools.push(Arc::new(PingBackends));
+    }
+    if options.list_backends_tool {
+        tools.push(Arc::new(ListBackends));
+    }
+    if options.echo_tool {
+        tools.push(Arc::new(Echo));
+    }
+    tools
+}
+
+/// `mcpd__compose` (see `compose`)
+pub struct Compose;
+
+impl BuiltinTool for Compose {
+    fn name(&self) -> &str {
+        compose::TOOL_NAME
+    }
+
+    fn tool(&self) -> McpTool {
+        compose::tool()
+    }
+
+    fn call<'a>(
+        &'a self,
+        server: &'a Server,
+        call: BuiltinCall<'a>,
+    ) -> BoxFuture<'a, CallToolResult> {
+        Box::pin(async move {
+            let pipeline = match compose::Pipeline::parse(&call.arguments) {
+                Ok(pipeline) => pipeline,
+                Err(e) => return error_text::tool_error(format!("Invalid pipeline: {:#}", e)),
+            };
+            pipeline
+                .run(|tool, arguments| async move {
+                    server
+                        .route_tool_call(
+                            call.connection,
+                            &tool,
+                            arguments,
+                            call.meta,
+                            &mut CallProfile::default(),
+                        )
+                        .await
+                })
+                .await
+        })
+    }
+}
+
+/// `mcpd__ping_backends` (see `ping`)
+pub struct PingBackends;
+
+impl BuiltinTool for PingBackends {
+    fn name(&self) -> &str {
+        ping::TOOL_NAME
+    }
+
+    fn tool(&self) -> McpTool {
+        ping::tool()
+    }
+
+    fn call<'a>(
+        &'a self,
+        server: &'a Server,
+        call: BuiltinCall<'a>,
+    ) -> BoxFuture<'a, CallToolResult> {
+        Box::pin(async move {
+            match ping::Args::parse(&call.arguments) {
+                Ok(args) => ping::result(&server.ping_backends(args.include_stopped).await),
+                Err(e) => error_text::tool_error(format!("Invalid arguments: {:#}", e)),
+            }
+        })
+    }
+}
+
+/// `mcpd__list_backends`: every registered backend and whether it's
+/// running, without starting any
+pub struct ListBackends;
+
+impl ListBackends {
+    pub const NAME: &str = "mcpd__list_backends";
+}
+
+impl BuiltinTool for ListBackends {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn tool(&self) -> McpTool {
+        McpTool {
+            name: Self::NAME.to_string(),
+            title: None,
+            description: Some(
+                "List the backend servers mcpd aggregates, with each one's state \
+                 (stopped, starting, ready or unavailable) and why it's failing, if it is. \
+                 Doesn't start any of them."
+                    .to_string(),
+            ),
+            input_schema: json!({"type": "object", "properties": {}, "additionalProperties": false}),
+            annotations: None,
+        }
+    }
+
+    fn call<'a>(
+        &'a self,
+        server: &'a Server,
+        _call: BuiltinCall<'a>,
+    ) -> BoxFuture<'a, CallToolResult> {
+        Box::pin(async move {
+            let backends = server.snapshot().await.backends;
+            let text = backends
+                .iter()
+                .map(|backend| {
+                    let why = backend.init_error.as_ref().or(backend.degraded.as_ref());
+                    match why {
+                        Some(why) => format!("{}: {:?} ({})", backend.name, backend.state, why),
+                        None => format!("{}: {:?}", backend.name, backend.state),
+                    }
+                    .to_lowercase()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let backends: Vec<Value> = backends
+                .into_iter()
+                .map(|backend| {
+                    json!({
+                        "name": backend.name,
+                        "state": backend.state,
+                        "init_error": backend.init_error,
+                        "degraded": backend.degraded,
+                    })
+                })
+                .collect();
+            CallToolResult {
+                content: vec![Content::Text { text }],
+                is_error: false,
+                structured_content: Some(json!({ "backends": backends })),
+                meta: None,
+            }
+        })
+    }
+}
+
+/// `mcpd__echo`: returns its arguments, for checking a client reaches mcpd
+pub struct Echo;
+
+impl Echo {
+    pub const NAME: &str = "mcpd__echo";
+}
+
+impl BuiltinTool for Echo {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn tool(&self) -> McpTool {
+        McpTool {
+            name: Self::NAME.to_string(),
+            title: None,
+            description: Some(
+                "Return the arguments unchanged, as JSON text and structuredContent. \
+                 For checking that calls reach mcpd, without involving a backend."
+                    .to_string(),
+            ),
+            input_schema: json!({"type": "object"}),
+            annotations: None,
+        }
+    }
+
+    fn call<'a>(
+        &'a self,
+        _server: &'a Server,
+        call: BuiltinCall<'a>,
+    ) -> BoxFuture<'a, CallToolResult> {
+        Box::pin(async move {
+            CallToolResult {
+                content: vec![Content::Text {
+                    text: call.arguments.to_string(),
+                }],
+                is_error: false,
+                structured_content: Some(call.arguments),
+                meta: None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(options: &ServeOptions) -> Vec<String> {
+        defaults(options)
+            .iter()
+            .map(|tool| tool.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn defaults_follow_the_options() {
+        assert!(names(&ServeOptions::default()).is_empty());
+        let options = ServeOptions {
+            compose: true,
+            ping_tool: true,
+            list_backends_tool: true,
+            echo_tool: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&options),
+            [
+                compose::TOOL_NAME,
+                ping::TOOL_NAME,
+                ListBackends::NAME,
+                Echo::NAME
+            ]
+        );
+        // Listed under the name they're called by
+        for tool in defaults(&options) {
+            assert_eq!(tool.tool().name, tool.name());
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Canonical encoding of JSON tool arguments, so that semantically identical
+//! arguments compare (and hash) equal. Meant for anything keyed on "the same
+//! call": result caching, in-flight deduplication, policy predicates.
+//!
+//! The encoding is compact JSON with these rules:
+//!
+//! - Object keys are sorted by their UTF-8 bytes, recursively.
+//! - No insignificant whitespace.
+//! - Numbers are written in plain decimal with the shortest digits that
+//!   round-trip: no exponent, no trailing `.0`, and `-0` is `0`. So `1`,
+//!   `1.0` and `1e0` are all `1`.
+//! - Strings use serde_json's escaping: `"`, `\` and control characters are
+//!   escaped, everything else (including non-ASCII) is written as is.
+//! - Object members whose value is `null` are kept or dropped depending on
+//!   [`Nulls`]. `null` array elements are always kept.
+//!
+//! Deliberately NOT normalized: string case, Unicode normalization forms,
+//! whitespace inside strings, array order, and strings vs. numbers (`"1"` is
+//! not `1`). Those can all be meaningful to a backend.
+//!
+//! The byte format is pinned by tests; changing it invalidates every stored
+//! hash, so treat it as a compatibility break.
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// How to treat object members that are explicitly `null`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Nulls {
+    /// `{"a": null}` and `{}` are different
+    #[default]
+    Keep,
+    /// `{"a": null}` is the same as `{}`
+    Omit,
+}
+
+/// Canonical byte encoding of `value`
+pub fn to_bytes(value: &Value, nulls: Nulls) -> Vec<u8> {
+    let mut out = Vec::new();
+    write(&mut out, value, nulls);
+    out
+}
+
+/// Lowercase hex SHA-256 of the canonical encoding
+pub fn hash(value: &Value, nulls: Nulls) -> String {
+    Sha256::digest(to_bytes(value, nulls))
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Whether two values are equal after canonicalization
+pub fn equivalent(a: &Value, b: &Value, nulls: Nulls) -> bool {
+    to_bytes(a, nulls) == to_bytes(b, nulls)
+}
+
+fn write(out: &mut Vec<u8>, value: &Value, nulls: Nulls) {
+    match value {
+        Value::Null => out.extend_from_slice(b"null"),
+        Value::Bool(b) => out.extend_from_slice(if *b { b"true" } else { b"false" }),
+        Value::Number(n) => write_number(out, n),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write(out, item, nulls);
+            }
+            out.push(b']');
+        }
+        Value::Object(map) => {
+            // serde_json's map is already sorted unless `preserve_order` is
+            // enabled somewhere in the dependency graph, so sort regardless
+            let mut members: Vec<_> = map
+                .iter()
+                .filter(|(_, v)| !(nulls == Nulls::Omit && v.is_null()))
+                .collect();
+            members.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+            out.push(b'{');
+            for (i, (key, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_string(out, key);
+                out.push(b':');
+                write(out, value, nulls);
+            }
+            out.push(b'}');
+        }
+    }
+}
+
+fn write_number(out: &mut Vec<u8>, n: &serde_json::Number) {
+    let text = if let Some(i) = n.as_i64() {
+        i.to_string()
+    } else if let Some(u) = n.as_u64() {
+        u.to_string()
+    } else {
+        // serde_json numbers are always finite. `Display` for f64 prints the
+        // shortest round-trip digits without an exponent, and integral
+        // values without a fraction, so 1.0 and 1 agree.
+        let f = n.as_f64().unwrap_or_default();
+        if f == 0.0 {
+            "0".to_string()
+        } else {
+            f.to_string()
+        }
+    };
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    // Serializing a str can't fail
+    serde_json::to_writer(&mut *out, s).expect("string serialization");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn canonical(value: &Value) -> String {
+        String::from_utf8(to_bytes(value, Nulls::Keep)).unwrap()
+    }
+
+    fn parse(text: &str) -> Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    // Fixtures pin the format. If one of these changes, every stored hash does.
+
+    #[test]
+    fn fixture_nested_document() {
+        let value = parse(
+            r#"{
+                "path": "/tmp/x",
+                "options": {"recursive": true, "depth": 3, "filter": null},
+                "tags": ["b", "a"],
+                "Zed": 1
+            }"#,
+        );
+        assert_eq!(
+            canonical(&value),
+            r#"{"Zed":1,"options":{"depth":3,"filter":null,"recursive":true},"path":"/tmp/x","tags":["b","a"]}"#
+        );
+        assert_eq!(
+            hash(&value, Nulls::Keep),
+            "9f1065824d6db7d211bc7a836e1393ba5fb04106eacc1fb86058aecbd6189dd3"
+        );
+    }
+
+    #[test]
+    fn fixture_numbers() {
+        let value =
+            parse(r#"[1, 1.0, 1e0, -0.0, 0.5, 1.5e3, 1e-7, -42, 18446744073709551615, 1e21]"#);
+        assert_eq!(
+            canonical(&value),
+            "[1,1,1,0,0.5,1500,0.0000001,-42,18446744073709551615,1000000000000000000000]"
+        );
+    }
+
+    #[test]
+    fn fixture_strings() {
+        let value = json!({"q": "Say \"hi\"\n\tcafé \u{1}", "\u{e9}": "", "a": ""});
+        assert_eq!(
+            canonical(&value),
+            "{\"a\":\"\",\"q\":\"Say \\\"hi\\\"\\n\\tcafé \\u0001\",\"é\":\"\"}"
+        );
+    }
+
+    #[test]
+    fn fixture_scalars_and_empty() {
+        assert_eq!(canonical(&json!(null)), "null");
+        assert_eq!(canonical(&json!(true)), "true");
+        assert_eq!(canonical(&json!({})), "{}");
+        assert_eq!(canonical(&json!([])), "[]");
+        assert_eq!(
+            hash(&json!({}), Nulls::Keep),
+            "44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+        );
+    }
+
+    #[test]
+    fn null_policy() {
+        let explicit = json!({"a": 1, "b": null, "c": [null]});
+        let absent = json!({"a": 1, "c": [null]});
+        assert!(!equivalent(&explicit, &absent, Nulls::Keep));
+        assert!(equivalent(&explicit, &absent, Nulls::Omit));
+        // Array positions are meaningful, so nulls there always count
+        assert!(!equivalent(&json!([null]), &json!([]), Nulls::Omit));
+    }
+
+    #[test]
+    fn deliberately_not_normalized() {
+        for (a, b) in [
+            (json!("ABC"), json!("abc")),
+            (json!([1, 2]), json!([2, 1])),
+            (json!("1"), json!(1)),
+            (json!(" x"), json!("x")),
+            (json!("caf\u{e9}"), json!("cafe\u{301}")),
+        ] {
+            assert!(!equivalent(&a, &b, Nulls::Omit), "{} vs {}", a, b);
+        }
+    }
+
+    /// Small deterministic PRNG so the property tests are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_value(rng: &mut Rng, depth: u32) -> Value {
+        let kinds = if depth == 0 { 5 } else { 7 };
+        match rng.below(kinds) {
+            0 => Value::Null,
+            1 => json!(rng.below(2) == 1),
+            2 => json!(rng.below(1000) as i64 - 500),
+            3 => json!(rng.below(1000) as f64 / 8.0),
+            4 => json!(format!("s{}", rng.below(100))),
+            5 => (0..rng.below(4))
+                .map(|_| random_value(rng, depth - 1))
+                .collect(),
+            _ => Value::Object(
+                (0..rng.below(5))
+                    .map(|i| {
+                        (
+                            format!("k{}_{}", i, rng.below(10)),
+                            random_value(rng, depth - 1),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Serialize with object keys in a random order and random whitespace
+    fn shuffled_text(rng: &mut Rng, value: &Value) -> String {
+        let ws = |rng: &mut Rng| {
+            " \n\t"
+                .chars()
+                .take(rng.below(3) as usize)
+                .collect::<String>()
+        };
+        match value {
+            Value::Array(items) => {
+                let items: Vec<_> = items.iter().map(|v| shuffled_text(rng, v)).collect();
+                format!("[{}{}]", ws(rng), items.join(","))
+            }
+            Value::Object(map) => {
+                let mut members: Vec<_> = map.iter().collect();
+                for i in (1..members.len()).rev() {
+                    members.swap(i, rng.below(i as u64 + 1) as usize);
+                }
+                let members: Vec<_> = members
+                    .into_iter()
+                    .map(|(k, v)| format!("{}{:?}:{}", ws(rng), k, shuffled_text(rng, v)))
+                    .collect();
+                format!("{{{}}}", members.join(","))
+            }
+            // Writing integral numbers as `n.0` exercises number normalization too
+            Value::Number(n)
+                if n.as_f64().is_some_and(|f| f.fract() == 0.0) && rng.below(2) == 1 =>
+            {
+                format!("{:.1}", n.as_f64().unwrap())
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// Replace one leaf with a different value. Returns false if there was none.
+    fn mutate_leaf(rng: &mut Rng, value: &mut Value) -> bool {
+        match value {
+            Value::Array(items) if !items.is_empty() => {
+                let i = rng.below(items.len() as u64) as usize;
+                mutate_leaf(rng, &mut items[i])
+            }
+            Value::Object(map) if !map.is_empty() => {
+                let i = rng.below(map.len() as u64) as usize;
+                let v = map.values_mut().nth(i).unwrap();
+                mutate_leaf(rng, v)
+            }
+            Value::Array(_) | Value::Object(_) => false,
+            leaf => {
+                *leaf = match leaf {
+                    Value::String(s) => json!(format!("{}!", s)),
+                    Value::Number(n) => json!(n.as_f64().unwrap() + 1.0),
+                    Value::Bool(b) => json!(!*b),
+                    _ => json!(0),
+                };
+                true
+            }
+        }
+    }
+
+    #[test]
+    fn property_permuted_keys_hash_equal() {
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..500 {
+            let value = random_value(&mut rng, 4);
+            let text = shuffled_text(&mut rng, &value);
+            let reparsed = parse(&text);
+            for nulls in [Nulls::Keep, Nulls::Omit] {
+                assert_eq!(hash(&value, nulls), hash(&reparsed, nulls), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn property_differing_values_hash_differently() {
+        let mut rng = Rng(0xdeadbeefcafef00d);
+        let mut checked = 0;
+        for _ in 0..500 {
+            let value = random_value(&mut rng, 4);
+            let mut changed = value.clone();
+            if !mutate_leaf(&mut rng, &mut changed) {
+                continue;
+            }
+            checked += 1;
+            assert_ne!(
+                hash(&value, Nulls::Keep),
+                hash(&changed, Nulls::Keep),
+                "{} vs {}",
+                value,
+                changed
+            );
+        }
+        assert!(checked > 100);
+    }
+}
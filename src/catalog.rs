@@ -0,0 +1,266 @@
+//! `mcpd catalog`: every aggregated tool with its source backend and full
+//! input schema, for publishing as documentation.
+//!
+//! The catalog comes from the same listing path as `list_tools`, so exposed
+//! names, read-only hiding and schema repair match what clients see. Backends
+//! that fail to list their tools are reported rather than dropped silently.
+//! `mcpd which` looks a single exposed name up in the same catalog.
+
+use crate::output::{Color, Output};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// One tool as the catalog documents it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    /// Name clients call the tool by
+    pub name: String,
+    pub backend: String,
+    /// Name the backend knows the tool by
+    pub tool: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub input_schema: serde_json::Value,
+}
+
+/// A backend whose tools couldn't be listed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Failure {
+    pub backend: String,
+    pub error: String,
+}
+
+/// The tools of every backend, in registry order
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Catalog {
+    pub tools: Vec<Entry>,
+    pub failures: Vec<Failure>,
+}
+
+/// Human-readable catalog, one section per backend
+pub fn render(catalog: &Catalog, out: &Output) -> String {
+    let mut text = String::new();
+    let mut backends: Vec<&str> = catalog.tools.iter().map(|t| t.backend.as_str()).collect();
+    backends.dedup();
+    let _ = writeln!(
+        text,
+        "{} tool(s) from {} backend(s)",
+        catalog.tools.len(),
+        backends.len()
+    );
+
+    for backend in backends {
+        let _ = write!(text, "\n{}\n", out.paint(backend, Color::Bold));
+        for entry in catalog.tools.iter().filter(|t| t.backend == backend) {
+            let _ = write!(text, "\n  {}", out.paint(&entry.name, Color::Bold));
+            if !entry.name.ends_with(&entry.tool) {
+                let _ = write!(
+                    text,
+                    " {}",
+                    out.paint(&format!("({})", entry.tool), Color::Dim)
+                );
+            }
+            text.push('\n');
+            match &entry.description {
+                Some(description) => {
+                    for line in description.trim().lines() {
+                        let _ = writeln!(text, "    {}", line);
+                    }
+                }
+                None => {
+                    let _ = writeln!(text, "    {}", out.paint("(no description)", Color::Dim));
+                }
+            }
+            let schema = serde_json::to_string_pretty(&entry.input_schema).unwrap_or_default();
+            for line in schema.lines() {
+                let _ = writeln!(text, "    {}", line);
+            }
+        }
+    }
+
+    if !catalog.failures.is_empty() {
+        let _ = write!(
+            text,
+            "\n{}\n",
+            out.paint("Backends that failed to list tools:", Color::Red)
+        );
+        for failure in &catalog.failures {
+            let _ = writeln!(text, "  {}: {}", failure.backend, failure.error);
+        }
+    }
+    text
+}
+
+impl Catalog {
+    /// The tool clients call `name`
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.tools.iter().find(|entry| entry.name == name)
+    }
+
+    /// Why no tool is exposed as `name`, as precisely as the catalog can tell
+    pub fn explain_unknown(&self, name: &str) -> String {
+        let Some((backend, _)) = name.split_once("__") else {
+            return format!(
+                "No tool is exposed as '{}' (exposed names look like backend__tool)",
+                name
+            );
+        };
+        if let Some(failure) = self.failures.iter().find(|f| f.backend == backend) {
+            format!(
+                "No tool is exposed as '{}': backend '{}' failed to list its tools: {}",
+                name, backend, failure.error
+            )
+        } else if self.tools.iter().any(|entry| entry.backend == backend) {
+            format!(
+                "No tool is exposed as '{}': backend '{}' has no such tool",
+                name, backend
+            )
+        } else {
+            format!(
+                "No tool is exposed as '{}': there's no backend named '{}'",
+                name, backend
+            )
+        }
+    }
+}
+
+/// `exposed -> backend:original` for `mcpd which`, then the backend's command
+pub fn render_which(entry: &Entry, command: &[String], out: &Output) -> String {
+    format!(
+        "{} -> {}:{}\n{} {:?}\n",
+        out.paint(&entry.name, Color::Bold),
+        entry.backend,
+        entry.tool,
+        out.paint("command:", Color::Dim),
+        command
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(backend: &str, tool: &str, description: Option<&str>) -> Entry {
+        Entry {
+            name: format!("{}__{}", backend, tool),
+            backend: backend.to_string(),
+            tool: tool.to_string(),
+            description: description.map(str::to_string),
+            input_schema: json!({"type": "object"}),
+        }
+    }
+
+    #[test]
+    fn render_groups_tools_by_backend() {
+        let catalog = Catalog {
+            tools: vec![
+                entry("fs", "read", Some("Read a file")),
+                entry("fs", "stat", None),
+                entry("git", "log", Some("Show history")),
+            ],
+            failures: vec![Failure {
+                backend: "web".to_string(),
+                error: "timed out after 30s".to_string(),
+            }],
+        };
+        let text = render(&catalog, &Output::plain());
+        assert_eq!(
+            text,
+            "3 tool(s) from 2 backend(s)
+
+fs
+
+  fs__read
+    Read a file
+    {
+      \"type\": \"object\"
+    }
+
+  fs__stat
+    (no description)
+    {
+      \"type\": \"object\"
+    }
+
+git
+
+  git__log
+    Show history
+    {
+      \"type\": \"object\"
+    }
+
+Backends that failed to list tools:
+  web: timed out after 30s
+"
+        );
+    }
+
+    #[test]
+    fn render_shows_backend_name_when_restyled() {
+        let catalog = Catalog {
+            tools: vec![Entry {
+                name: "fs__readFile".to_string(),
+                ..entry("fs", "read_file", Some("Read"))
+            }],
+            failures: Vec::new(),
+        };
+        let text = render(&catalog, &Output::plain());
+        assert!(text.contains("  fs__readFile (read_file)\n"), "{}", text);
+    }
+
+    #[test]
+    fn json_leaves_out_missing_descriptions() {
+        let value = serde_json::to_value(Catalog {
+            tools: vec![entry("fs", "stat", None)],
+            failures: Vec::new(),
+        })
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "tools": [{
+                    "name": "fs__stat",
+                    "backend": "fs",
+                    "tool": "stat",
+                    "input_schema": {"type": "object"}
+                }],
+                "failures": []
+            })
+        );
+    }
+
+    #[test]
+    fn which_finds_routes_and_explains_misses() {
+        let catalog = Catalog {
+            tools: vec![Entry {
+                name: "fs__readFile".to_string(),
+                ..entry("fs", "read_file", None)
+            }],
+            failures: vec![Failure {
+                backend: "web".to_string(),
+                error: "timed out after 30s".to_string(),
+            }],
+        };
+        let entry = catalog.find("fs__readFile").unwrap();
+        assert_eq!(
+            render_which(entry, &["fs-mcp".to_string()], &Output::plain()),
+            "fs__readFile -> fs:read_file\ncommand: [\"fs-mcp\"]\n"
+        );
+
+        assert!(catalog.find("fs__read_file").is_none());
+        for (name, reason) in [
+            ("fs__write", "backend 'fs' has no such tool"),
+            (
+                "web__get",
+                "backend 'web' failed to list its tools: timed out",
+            ),
+            ("git__log", "there's no backend named 'git'"),
+            ("readFile", "exposed names look like backend__tool"),
+        ] {
+            let message = catalog.explain_unknown(name);
+            assert!(message.contains(reason), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Failure injection for testing clients against a misbehaving aggregator
+//! (`serve --chaos`, refused unless `MCPD_ALLOW_CHAOS=1`).
+//!
+//! Every random decision is a pure function of the seed, the kind of
+//! injection and the call's correlation id (plus the progress value for
+//! notifications), so a seeded run makes the same choices no matter how
+//! calls interleave. Crashes hit every Nth call in arrival order.
+//! Injected events are logged at `warn` with `chaos = true` and the
+//! correlation id so they're never mistaken for real failures.
+//!
+//! Hooks sit around the backend call in `Server::call_backend` and in front
+//! of progress delivery to the client's writer.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// What to inject. Rates are fractions in `0.0..=1.0`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChaosOptions {
+    /// Delay this fraction of tool calls by the given duration
+    pub latency: Option<(f64, Duration)>,
+    /// Turn this fraction of successful results into `is_error` results
+    pub error_rate: f64,
+    /// Drop this fraction of progress notifications
+    pub drop_progress: f64,
+    /// Kill the backend on every Nth tool call, as if it crashed
+    pub crash_every: Option<u64>,
+    /// Seed for every decision; the same seed gives the same injections
+    pub seed: u64,
+}
+
+/// Decides which calls get which injections
+#[derive(Debug)]
+pub struct Chaos {
+    options: ChaosOptions,
+    calls: AtomicU64,
+}
+
+impl Chaos {
+    pub fn new(options: ChaosOptions) -> Self {
+        Self {
+            options,
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    /// Extra latency to add before this call, if any
+    pub fn latency(&self, correlation_id: &str) -> Option<Duration> {
+        let (rate, delay) = self.options.latency?;
+        self.roll("latency", correlation_id, rate).then_some(delay)
+    }
+
+    /// Whether to replace this call's successful result with an error
+    pub fn fail(&self, correlation_id: &str) -> bool {
+        self.roll("error", correlation_id, self.options.error_rate)
+    }
+
+    /// Whether this call is one that crashes its backend. Counts calls, so
+    /// ask exactly once per call.
+    pub fn crash(&self) -> bool {
+        let n = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        self.options
+            .crash_every
+            .is_some_and(|every| n.is_multiple_of(every))
+    }
+
+    /// Whether to drop a progress notification for this call
+    pub fn drop_progress(&self, correlation_id: &str, progress: &serde_json::Value) -> bool {
+        let key = format!("{}/{}", correlation_id, progress);
+        self.roll("progress", &key, self.options.drop_progress)
+    }
+
+    /// Deterministic coin flip that comes up true with probability `rate`
+    fn roll(&self, kind: &str, key: &str, rate: f64) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+        let mut h = self.options.seed ^ 0xcbf29ce484222325;
+        for b in kind.bytes().chain([0]).chain(key.bytes()) {
+            h = (h ^ b as u64).wrapping_mul(0x100000001b3);
+        }
+        ((splitmix64(h) >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+}
+
+/// Final mixing step of SplitMix64, to spread FNV's output over all bits
+fn splitmix64(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ids() -> impl Iterator<Item = String> {
+        (1..=1000).map(|n| format!("mcpd-1-{}", n))
+    }
+
+    fn failures(chaos: &Chaos) -> Vec<String> {
+        ids().filter(|id| chaos.fail(id)).collect()
+    }
+
+    #[test]
+    fn same_seed_same_decisions() {
+        let options = ChaosOptions {
+            error_rate: 0.3,
+            latency: Some((0.5, Duration::from_millis(10))),
+            seed: 42,
+            ..Default::default()
+        };
+        let a = Chaos::new(options.clone());
+        let b = Chaos::new(options);
+        assert_eq!(failures(&a), failures(&b));
+        // Order of asking doesn't matter, only the correlation id
+        let reversed: Vec<_> = ids().collect::<Vec<_>>().into_iter().rev().collect();
+        let mut b_failures: Vec<_> = reversed.into_iter().filter(|id| b.fail(id)).collect();
+        b_failures.reverse();
+        assert_eq!(failures(&a), b_failures);
+        for id in ids() {
+            assert_eq!(a.latency(&id), b.latency(&id));
+        }
+    }
+
+    #[test]
+    fn different_seed_different_decisions() {
+        let chaos = |seed| {
+            Chaos::new(ChaosOptions {
+                error_rate: 0.3,
+                seed,
+                ..Default::default()
+            })
+        };
+        assert_ne!(failures(&chaos(1)), failures(&chaos(2)));
+    }
+
+    #[test]
+    fn rates_are_roughly_respected() {
+        let chaos = Chaos::new(ChaosOptions {
+            error_rate: 0.25,
+            drop_progress: 1.0,
+            seed: 7,
+            ..Default::default()
+        });
+        let failed = failures(&chaos).len();
+        assert!((180..320).contains(&failed), "{}", failed);
+        assert!(chaos.drop_progress("mcpd-1-1", &json!(1)));
+
+        // Each kind rolls independently, and zero never fires
+        let none = Chaos::new(ChaosOptions::default());
+        assert!(failures(&none).is_empty());
+        assert!(ids().all(|id| none.latency(&id).is_none()));
+    }
+
+    #[test]
+    fn crash_every_nth_call() {
+        let chaos = Chaos::new(ChaosOptions {
+            crash_every: Some(3),
+            ..Default::default()
+        });
+        let crashes: Vec<_> = (1..=9).filter(|_| chaos.crash()).collect();
+        assert_eq!(crashes, [3, 6, 9]);
+        let never = Chaos::new(ChaosOptions::default());
+        assert!((0..10).all(|_| !never.crash()));
+    }
+}
@@ -1,9 +1,35 @@
 //! Command-line interface for mcpd.
 
-use crate::registry::{Registry, Tool};
-use crate::server::Server;
-use anyhow::Result;
-use clap::{Parser, Subcommand};
+use crate::chaos::ChaosOptions;
+use crate::error_text::ErrorText;
+use crate::framing::Framing;
+use crate::limits::{self, JsonLimits};
+use crate::mcp::{InitializeResult, LoggingLevel};
+use crate::naming::{ClientProfile, NameStyle};
+use crate::offline::{CatalogFile, SchemaOnly};
+use crate::output::{Cell, Color, ColorChoice, Output, Table};
+use crate::proxy::{ProxyOptions, StderrMode, ToolProxy};
+use crate::registry::{Position, Registry, Tool};
+use crate::scheduler::Priority;
+use crate::secret_env::{self, KeySources};
+use crate::secrets::{
+    Confidence, DEFAULT_SECRET_ENV_PATTERN, EnvMask, SecretPattern, SecretPolicy, SecretScanner,
+};
+use crate::server::{
+    DEFAULT_DESTRUCTIVE_PATTERNS, DEFAULT_MAX_CALL_TIMEOUT, DEFAULT_MAX_TOOLS_WARN, ServeOptions,
+    Server,
+};
+use crate::structured::StructuredCompat;
+use crate::tool_concurrency::ToolConcurrency;
+use crate::transform::Transform;
+use crate::watchdog::WatchdogOptions;
+use anyhow::{Context, Result};
+use clap::{Args, Parser, Subcommand};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tracing::info;
 
 #[derive(Parser)]
@@ -13,6 +39,16 @@ use tracing::info;
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Color output: auto (terminal without NO_COLOR), always or never
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Don't cut tables to the terminal width
+    #[arg(long, global = true)]
+    wide: bool,
+    /// Export traces to this OTLP/HTTP collector (default: $OTEL_EXPORTER_OTLP_ENDPOINT)
+    #[cfg(feature = "otel")]
+    #[arg(long, global = true)]
+    otel_endpoint: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -27,6 +63,147 @@ enum Commands {
         /// Environment variables (KEY=VALUE)
         #[arg(short, long, value_parser = parse_env_var)]
         env: Vec<(String, String)>,
+        /// Environment variable passed on from mcpd's own environment, read
+        /// each time the server starts (e.g. a rotating token)
+        #[arg(long, value_name = "NAME")]
+        dynamic_env: Vec<String>,
+        /// Environment variable stored encrypted under the master key
+        /// (KEY=VALUE), decrypted only when the server starts
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env_var)]
+        secret_env: Vec<(String, String)>,
+        /// Restart by warming up a replacement before switching over (uses extra memory)
+        #[arg(long)]
+        zero_downtime: bool,
+        /// jq expression applied to JSON tool results, e.g. '{name, id}'
+        #[arg(long)]
+        transform: Option<String>,
+        /// Working directory for the server process
+        #[arg(long)]
+        cwd: Option<PathBuf>,
+        /// Run in the client's first root directory when it provides one (falls back to --cwd)
+        #[arg(long)]
+        cwd_from_root: bool,
+        /// Forward the client's request _meta, including trace context, to this server
+        #[arg(long)]
+        forward_trace_context: bool,
+        /// Who goes first when --max-concurrent-calls is reached
+        #[arg(long, value_enum, default_value_t = Priority::Normal)]
+        priority: Priority,
+        /// Run the server under this command prefix, e.g. 'bwrap --ro-bind / / --'.
+        /// Use {command} to place the server's command elsewhere; '' opts out of --default-wrapper
+        #[arg(long, allow_hyphen_values = true)]
+        wrapper: Option<WrapperArg>,
+        /// How the server delimits messages: detected from its first output by default
+        #[arg(long, value_enum, default_value_t = Framing::Auto)]
+        framing: Framing,
+        /// Command run before each call: gets the arguments as JSON on stdin and
+        /// prints the arguments to send (nothing keeps them; non-zero exit rejects the call)
+        #[arg(long, value_name = "CMD")]
+        pre_call: Option<CommandLine>,
+        /// Command run after each call: gets the result as JSON on stdin and
+        /// prints the result to return (nothing keeps it; non-zero exit fails the call)
+        #[arg(long, value_name = "CMD")]
+        post_call: Option<CommandLine>,
+        /// Pin an npx, uvx or 'pipx run' package to the version it runs now
+        /// (e.g. pkg -> pkg@1.2.3), found with a test handshake
+        #[arg(long)]
+        pin_version: bool,
+        /// Lowest level of the server's log messages passed on to clients
+        #[arg(long, value_enum, default_value_t)]
+        log_level: LoggingLevel,
+        /// Send the server one tool call at a time, queueing the rest
+        /// (for servers that aren't safe to call concurrently)
+        #[arg(long)]
+        singleton: bool,
+        /// Don't send the server a request until it has answered the last
+        /// one (for servers that break when requests are pipelined)
+        #[arg(long)]
+        no_pipelining: bool,
+        /// Stop the server when a call the client stopped waiting for isn't
+        /// answered within `serve --abandoned-call-grace`
+        #[arg(long)]
+        kill_on_abandoned: bool,
+        /// Registered server to send a call to when this one won't start,
+        /// initialize or answer (it needs a tool of the same name)
+        #[arg(long, value_name = "NAME")]
+        fallback: Option<String>,
+        /// Bytes buffered per read of the server's stdout (default: `serve
+        /// --read-buffer-size`)
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        read_buffer_size: Option<u64>,
+        /// Reject calls whose arguments serialize to more than this many
+        /// bytes before they reach the server (default: `serve --max-arg-bytes`)
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        max_arg_bytes: Option<u64>,
+        /// Run this many processes of the server and send tool calls to
+        /// each in turn, skipping ones that won't start or have crashed
+        #[arg(long, alias = "instances", value_parser = clap::value_parser!(u32).range(1..))]
+        replicas: Option<u32>,
+        /// Send calls with the same value of this argument to the same
+        /// replica instead of taking turns
+        #[arg(long, value_name = "ARGUMENT", requires = "replicas")]
+        shard_by: Option<String>,
+        /// Send this one tool of the server one call at a time, queueing
+        /// the rest, while its other tools run concurrently (repeatable)
+        #[arg(long, value_name = "TOOL")]
+        serialize_tool: Vec<String>,
+        /// Run at most N calls to this tool of the server at once (repeatable)
+        #[arg(long, value_name = "TOOL=N", value_parser = parse_tool_count)]
+        tool_concurrency: Vec<(String, u32)>,
+        /// Refuse calls to this tool once N are waiting for a turn under
+        /// --serialize-tool or --tool-concurrency (repeatable; default: no limit)
+        #[arg(long, value_name = "TOOL=N", value_parser = parse_tool_count)]
+        tool_queue: Vec<(String, u32)>,
+        /// Put a tool in merge group GROUP, listed once for every server's
+        /// tools in it, with calls going to all of them (repeatable)
+        #[arg(long, value_name = "TOOL=GROUP", value_parser = parse_merge_group)]
+        merge_group: Vec<(String, String)>,
+        /// Relay at most RATE of the server's METHOD notifications a second
+        /// to clients, or `unlimited` (repeatable; log messages default to
+        /// 20/s, e.g. notifications/message=50)
+        #[arg(long, value_name = "METHOD=RATE", value_parser = parse_notification_limit)]
+        notification_limit: Vec<(String, Option<u32>)>,
+        /// Name clients show for the server instead of its registry name
+        #[arg(long)]
+        title: Option<String>,
+        /// The server's icon: an image file (embedded in the registry) or a
+        /// data: URI. PNG, JPEG, GIF, WebP or SVG, up to 64 KiB
+        #[arg(long)]
+        icon: Option<String>,
+        /// Client name mcpd introduces itself to the server with (default:
+        /// `serve --client-name`)
+        #[arg(long)]
+        client_name: Option<String>,
+        /// Client version mcpd introduces itself to the server with (default:
+        /// `serve --client-version`)
+        #[arg(long)]
+        client_version: Option<String>,
+    },
+
+    /// Register a tool server from a JSON spec like
+    /// {"name": "fs", "command": ["npx", "server-fs"], "env": {}},
+    /// or add one to the running mcpd: `mcpd add --ephemeral <name> -- <command>...`
+    Add {
+        /// The spec itself, a file containing it, or an http(s) URL to fetch it from.
+        /// The server's name when a command follows `--`
+        spec: String,
+        /// Command to run the MCP server. Given this, the running mcpd adds the server
+        #[arg(last = true)]
+        command: Vec<String>,
+        /// Environment variables (KEY=VALUE) for a server given by command
+        #[arg(short, long, value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+        /// Add the server to the running mcpd only, without writing the registry.
+        /// It's gone after `mcpd remove` or when that mcpd exits
+        #[arg(long)]
+        ephemeral: bool,
+    },
+
+    /// Remove a tool server from the running mcpd. Servers that aren't
+    /// ephemeral are unregistered too
+    Remove {
+        /// Name of the server to remove
+        name: String,
     },
 
     /// Unregister a tool server
@@ -35,11 +212,1053 @@ enum Commands {
         name: String,
     },
 
+    /// Rename a registered tool server, keeping its id and position
+    Rename {
+        /// Current name of the tool
+        name: String,
+        /// Name to give it
+        new_name: String,
+    },
+
+    /// Move a tool server within the registry, which sets the order of
+    /// tools/list
+    #[command(group = clap::ArgGroup::new("position").required(true))]
+    Reorder {
+        /// Name of the tool to move
+        name: String,
+        /// Place it just before this tool
+        #[arg(long, group = "position", value_name = "OTHER")]
+        before: Option<String>,
+        /// Place it just after this tool
+        #[arg(long, group = "position", value_name = "OTHER")]
+        after: Option<String>,
+        /// Place it first
+        #[arg(long, group = "position")]
+        top: bool,
+        /// Place it last
+        #[arg(long, group = "position")]
+        bottom: bool,
+    },
+
     /// List registered tool servers
-    List,
+    List {
+        /// Show env values whose names look secret instead of masking them
+        #[arg(long)]
+        show_secrets: bool,
+        /// Regex for env names whose values are masked
+        #[arg(long, default_value = DEFAULT_SECRET_ENV_PATTERN)]
+        secret_env_pattern: String,
+        /// Cut env values longer than this many characters
+        #[arg(long, default_value_t = 80)]
+        max_env_value_len: usize,
+        /// Show each tool's id
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Check a registry file and report every problem in it, exiting
+    /// non-zero if there are any
+    Validate {
+        /// Registry file to check (default: the config directory's registry.json)
+        #[arg(long, value_name = "FILE")]
+        config: Option<PathBuf>,
+    },
+
+    /// Start every registered server and report tool names more than one of them exposes
+    Conflicts {
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+
+    /// Start every registered server and print all tools with their backend and full input schema
+    Catalog {
+        /// Print the catalog as JSON
+        #[arg(long)]
+        json: bool,
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// How tool names are restyled, as with `serve --name-style`
+        #[arg(long, value_enum, default_value_t)]
+        name_style: NameStyle,
+    },
+
+    /// Show which backend and backend tool an exposed tool name routes to
+    Which {
+        /// Exposed tool name, like fs__read_file
+        name: String,
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// How tool names are restyled, as with `serve --name-style`
+        #[arg(long, value_enum, default_value_t)]
+        name_style: NameStyle,
+    },
+
+    /// Start one registered server and print the capabilities and protocol
+    /// version it answers initialize with
+    Capabilities {
+        /// Registered server name
+        name: String,
+        /// Print the server's info, protocol version and capabilities as JSON
+        #[arg(long)]
+        json: bool,
+        /// Seconds to wait for the server to initialize
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+
+    /// Check a server against a YAML spec of the tools and prompts it must
+    /// list and the results scripted calls must give. Exits non-zero if any
+    /// assertion fails
+    Verify {
+        /// Spec to check (YAML)
+        spec: PathBuf,
+        /// Also write the results as JUnit XML to this file
+        #[arg(long, value_name = "FILE")]
+        junit: Option<PathBuf>,
+        /// Seconds to wait for the server to initialize, and for each request
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+
+    /// Start every registered server and print all tools as OpenAI or Anthropic
+    /// function-calling definitions (JSON). Renamed tools are listed on stderr
+    ExportFunctions {
+        /// Whose tool schema to emit
+        #[arg(long, value_enum, default_value_t)]
+        format: crate::functions::Format,
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// How tool names are restyled, as with `serve --name-style`
+        #[arg(long, value_enum, default_value_t)]
+        name_style: NameStyle,
+    },
+
+    /// Start every registered server and write its tools, prompts and resources
+    /// to a catalog file for `serve --schema-only`
+    ExportCatalog {
+        /// Catalog file to write
+        file: PathBuf,
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// How tool names are restyled, as with `serve --name-style`
+        #[arg(long, value_enum, default_value_t)]
+        name_style: NameStyle,
+    },
+
+    /// Start every registered server and report how its tools drifted from a catalog file
+    Diff {
+        /// Catalog file written by `mcpd export-catalog`
+        #[arg(long)]
+        catalog: PathBuf,
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+
+    /// Compare the tools clients would see under two configurations, each a
+    /// catalog file or a registry file (whose servers are started). Exits
+    /// non-zero on a breaking change
+    DiffConfig {
+        /// Catalog or registry file of the current configuration
+        #[arg(long)]
+        before: PathBuf,
+        /// Catalog or registry file of the proposed configuration
+        #[arg(long)]
+        after: PathBuf,
+        /// Print the diff as JSON
+        #[arg(long)]
+        json: bool,
+        /// Seconds to wait for each server's tool list
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+        /// How tool names are restyled, as with `serve --name-style`
+        #[arg(long, value_enum, default_value_t)]
+        name_style: NameStyle,
+    },
+
+    /// Report version pins of npx, uvx and 'pipx run' servers against what they run
+    Outdated {
+        /// Also ask the package registry (npm or PyPI) for the latest release
+        #[arg(long)]
+        check_upstream: bool,
+        /// Seconds to wait for each server's handshake
+        #[arg(long, default_value_t = crate::pinning::DEFAULT_HANDSHAKE_TIMEOUT.as_secs())]
+        timeout: u64,
+    },
+
+    /// Re-pin an npx, uvx or 'pipx run' server to its latest release, once
+    /// that release completes a test handshake
+    Upgrade {
+        /// Name of the server to upgrade
+        name: String,
+        /// Seconds to wait for each test handshake
+        #[arg(long, default_value_t = crate::pinning::DEFAULT_HANDSHAKE_TIMEOUT.as_secs())]
+        timeout: u64,
+    },
 
     /// Run the aggregating MCP server (stdio mode)
-    Serve,
+    Serve {
+        #[command(flatten)]
+        args: ServeArgs,
+    },
+
+    /// Run a long-lived server that accepts many clients on a unix socket
+    Daemon {
+        /// Socket path (default: daemon.sock next to the registry)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        #[command(flatten)]
+        args: ServeArgs,
+    },
+
+    /// Bridge stdio to a running daemon (use this as the client's MCP command)
+    Connect {
+        /// Socket path (default: daemon.sock next to the registry)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// Live view of a running server's backends and in-flight calls
+    Top {
+        /// Print a single snapshot and exit
+        #[arg(long)]
+        once: bool,
+        /// With --once, print the snapshot as JSON
+        #[arg(long, requires = "once")]
+        json: bool,
+    },
+
+    /// Render an audit log as a document listing every tool call and its result
+    Transcript {
+        /// Audit log to read (JSONL)
+        audit_file: PathBuf,
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        format: crate::transcript::Format,
+        /// Only calls to this backend
+        #[arg(long)]
+        backend: Option<String>,
+        /// Only calls that failed
+        #[arg(long)]
+        errors_only: bool,
+        /// Only calls since this time: RFC 3339, or a duration ago like 30m, 2h or 1d
+        #[arg(long)]
+        since: Option<String>,
+        /// Cut result text, argument JSON and errors longer than this many characters
+        #[arg(long, default_value_t = crate::transcript::DEFAULT_MAX_BLOB_CHARS)]
+        max_blob_chars: usize,
+    },
+
+    /// Check logs of agent actions
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+
+    /// Manage the master key encrypting `register --secret-env` values
+    Secrets {
+        #[command(subcommand)]
+        command: SecretsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretsCommand {
+    /// Re-encrypt every secret env value under a new master key, stored
+    /// where the current one is (a key from $MCPD_MASTER_KEY is replaced
+    /// by $MCPD_NEW_MASTER_KEY)
+    Rekey,
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Check an intent log (`serve --intent-log`) hasn't been edited or cut
+    /// short, and list destructive calls that never got an outcome
+    Verify {
+        /// Intent log to check (JSONL)
+        file: PathBuf,
+    },
+}
+
+/// Options shared by `serve` and `daemon`
+#[derive(Args)]
+#[command(group = clap::ArgGroup::new("classifies_destructive").args(["read_only", "intent_log"]).multiple(true))]
+struct ServeArgs {
+    /// Read the registry (registry.json format) from this file once at
+    /// startup and keep it in memory, leaving the config directory's alone.
+    /// A pipe works, e.g. <(generate-registry) or /dev/fd/3
+    #[arg(long, value_name = "FILE")]
+    registry_json: Option<PathBuf>,
+    /// Start all backends on initialize and forward their combined instructions
+    #[arg(long)]
+    aggregate_instructions: bool,
+    /// Hide and block tools whose names look destructive
+    #[arg(long)]
+    read_only: bool,
+    /// Comma-separated name patterns that make a tool destructive for
+    /// --read-only and --intent-log (replaces the defaults)
+    #[arg(long, value_delimiter = ',', requires = "classifies_destructive")]
+    destructive_patterns: Option<Vec<String>>,
+    /// Append destructive calls to this file, synced before each is
+    /// forwarded, with their outcomes (check it with `mcpd audit verify`)
+    #[arg(long, value_name = "FILE")]
+    intent_log: Option<PathBuf>,
+    /// Append a JSON record of every tool call to this file (timestamp,
+    /// tool, backend, arguments, outcome, latency)
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+    /// Leave arguments out of audit records
+    #[arg(long, requires = "audit_log")]
+    audit_redact_args: bool,
+    /// Rotate the audit log to FILE.1 once it would grow past this many bytes
+    /// (0 never rotates)
+    #[arg(long, default_value_t = crate::audit::DEFAULT_MAX_BYTES, requires = "audit_log")]
+    audit_max_bytes: u64,
+    /// Maximum nesting depth accepted in any JSON message
+    #[arg(long, default_value_t = JsonLimits::default().max_depth)]
+    max_json_depth: usize,
+    /// Maximum size in bytes of any single JSON message
+    #[arg(long, default_value_t = JsonLimits::default().max_bytes)]
+    max_message_bytes: usize,
+    /// Most bytes read for one message from a backend; past this mcpd stops
+    /// reading, fails the call and restarts the backend
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = crate::proxy::DEFAULT_MAX_RESPONSE_BYTES as u64)]
+    max_response_bytes: u64,
+    /// Seconds a write to a backend's stdin may take before the backend is restarted
+    #[arg(long, default_value_t = 10)]
+    write_timeout: u64,
+    /// Most messages waiting to be written to one backend's stdin; past
+    /// this, calls to it fail at once instead of queueing
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = crate::proxy::DEFAULT_MAX_QUEUED_WRITES as u64)]
+    max_queued_writes: u64,
+    /// Seconds calls to a backend fail fast after it answered initialize with an error
+    #[arg(long, default_value_t = ProxyOptions::default().init_failure_cooldown.as_secs())]
+    init_failure_cooldown: u64,
+    /// Seconds a `kill_on_abandoned` backend gets to answer a call the client
+    /// stopped waiting for before it's stopped
+    #[arg(long, default_value_t = ProxyOptions::default().abandoned_call_grace.as_secs())]
+    abandoned_call_grace: u64,
+    /// Bytes buffered per read of a backend's stdout, for backends that don't set their own
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = ProxyOptions::default().read_buffer_size as u64)]
+    read_buffer_size: u64,
+    /// Fail tool calls that arrive while a backend is still initializing
+    /// with a retriable error, instead of queueing them until it's ready
+    #[arg(long)]
+    fail_fast_uninitialized: bool,
+    /// Seconds list_tools waits for each backend before leaving it out of the result (0 waits indefinitely)
+    #[arg(long, default_value_t = 30)]
+    list_timeout: u64,
+    /// Seconds a tool call waits for its backend to answer before it's cancelled (0 waits indefinitely)
+    #[arg(long, default_value_t = 0)]
+    call_timeout: u64,
+    /// Longest timeout, in seconds, a client may set for one call with
+    /// `_meta.mcpd_timeout_ms` (0 allows any)
+    #[arg(long, default_value_t = DEFAULT_MAX_CALL_TIMEOUT.as_secs())]
+    max_call_timeout: u64,
+    /// Run at most this many tool calls at once; the rest queue by backend priority
+    #[arg(long)]
+    max_concurrent_calls: Option<usize>,
+    /// Warn when list_tools returns more tools than this (0 never warns)
+    #[arg(long, default_value_t = DEFAULT_MAX_TOOLS_WARN)]
+    max_tools_warn: usize,
+    /// Return at most this many tools from list_tools, in registry order
+    #[arg(long)]
+    max_tools: Option<usize>,
+    /// Reject tool calls whose arguments serialize to more than this many
+    /// bytes, for backends that don't set --max-arg-bytes themselves
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_arg_bytes: Option<u64>,
+    /// Pretty-print JSON sent to clients. Pretty messages span several
+    /// lines, so each is framed with a Content-Length header instead of a
+    /// newline; only use this with clients that read that framing
+    #[arg(long)]
+    pretty_output: bool,
+    /// Seconds a backend's --pre-call or --post-call hook may run before the call fails
+    #[arg(long, default_value_t = crate::hooks::DEFAULT_HOOK_TIMEOUT.as_secs())]
+    hook_timeout: u64,
+    /// Leave backend error messages and hook stderr out of errors sent to
+    /// clients, pointing at mcpd's log instead of quoting them
+    #[arg(long)]
+    strip_foreign_error_text: bool,
+    /// Log a timing breakdown of each tool call at debug level: waiting for
+    /// a slot, for the backend to be ready, the backend round trip and
+    /// serializing the response
+    #[arg(long)]
+    profile: bool,
+    /// Seconds mcpd's own event loop may go without answering its internal
+    /// watchdog ping before the stall is logged with what was in flight (0 disables)
+    #[arg(long, default_value_t = crate::watchdog::DEFAULT_THRESHOLD.as_secs())]
+    watchdog_threshold: u64,
+    /// Exit with status 70 after logging a stall, so a supervisor can restart mcpd
+    #[arg(long)]
+    watchdog_abort: bool,
+    /// Add the pretty-printed JSON of a result's structuredContent as text
+    /// when it has no text content, for clients that only read content blocks
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
+    structured_fallback: bool,
+    /// Remove structuredContent from tool results, for clients that reject
+    /// unknown fields. Results still get a text rendering of it.
+    #[arg(long)]
+    strip_structured: bool,
+    /// Offer the mcpd__compose tool, which runs a short sequence of tool
+    /// calls in one use_tool call, feeding earlier results into later steps
+    #[arg(long)]
+    enable_compose: bool,
+    /// Offer the mcpd__ping_backends tool, which reports which backends
+    /// answer a ping right now
+    #[arg(long)]
+    enable_ping_tool: bool,
+    /// Offer the mcpd__list_backends tool, which lists the backends and
+    /// their states without starting any
+    #[arg(long)]
+    enable_list_backends_tool: bool,
+    /// Offer the mcpd__echo tool, which returns its arguments, for checking
+    /// that calls reach mcpd
+    #[arg(long)]
+    enable_echo_tool: bool,
+    /// Name to report to clients in serverInfo, e.g. to tell several mcpd instances apart
+    #[arg(long, default_value = "mcpd")]
+    server_name: String,
+    /// Version to report to clients in serverInfo
+    #[arg(long, default_value = env!("CARGO_PKG_VERSION"))]
+    server_version: String,
+    /// Name to send backends in clientInfo, unless they're registered with their own
+    #[arg(long, default_value = "mcpd")]
+    client_name: String,
+    /// Version to send backends in clientInfo, unless they're registered with their own
+    #[arg(long, default_value = env!("CARGO_PKG_VERSION"))]
+    client_version: String,
+    /// Repair backend input schemas that aren't valid JSON Schema, or replace them with a permissive one
+    #[arg(long)]
+    sanitize_schemas: bool,
+    /// How backend tool names are restyled in list_tools
+    #[arg(long, value_enum, default_value_t = NameStyle::AsIs)]
+    name_style: NameStyle,
+    /// Shorten tool names in list_tools longer than this many characters,
+    /// ending them in a hash of the tool's own name
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_tool_name_length: Option<u64>,
+    /// Fit list_tools to a client's limits: its longest tool name and, if it
+    /// has one, its most tools. --max-tools and --max-tool-name-length win.
+    #[arg(long, value_enum)]
+    client_profile: Option<ClientProfile>,
+    /// Wrapper command for backends registered without --wrapper (see `register --wrapper`)
+    #[arg(long, allow_hyphen_values = true)]
+    default_wrapper: Option<WrapperArg>,
+    /// Send backend stderr straight to this terminal instead of capturing it
+    #[arg(long)]
+    inherit_stderr: bool,
+    /// What to do when tool arguments look like they contain a secret
+    #[arg(long, value_enum, default_value_t = SecretPolicy::Warn)]
+    secret_policy: SecretPolicy,
+    /// Extra secret pattern as NAME=REGEX (repeatable)
+    #[arg(long = "secret-pattern")]
+    secret_patterns: Vec<SecretPattern>,
+    /// Argument path expected to hold a secret, as backend.tool.arg (repeatable, `*` matches one segment)
+    #[arg(long = "secret-allow")]
+    secret_allow: Vec<String>,
+    /// Ignore built-in secret patterns below this confidence
+    #[arg(long, value_enum, default_value_t = Confidence::Medium)]
+    secret_min_confidence: Confidence,
+    /// Catalog file written by `mcpd export-catalog`, served with --schema-only
+    #[arg(long, requires = "schema_only")]
+    catalog: Option<PathBuf>,
+    /// Serve tools, prompts and resources from --catalog without starting
+    /// any backend. Tool calls aren't executed
+    #[arg(long, requires = "catalog")]
+    schema_only: bool,
+    /// With --schema-only, check tool call arguments against the tool's
+    /// input schema and return them as the result
+    #[arg(long, requires = "schema_only")]
+    echo_calls: bool,
+    /// Inject failures to test clients (requires MCPD_ALLOW_CHAOS=1)
+    #[arg(long, hide = true)]
+    chaos: bool,
+    /// Delay PERCENT of tool calls by MS milliseconds, as PERCENT:MS
+    #[arg(long, hide = true, requires = "chaos")]
+    chaos_latency: Option<ChaosLatency>,
+    /// Turn PERCENT of successful tool results into errors
+    #[arg(long, hide = true, requires = "chaos", value_parser = parse_percent, default_value = "0")]
+    chaos_error_rate: f64,
+    /// Drop PERCENT of progress notifications
+    #[arg(long, hide = true, requires = "chaos", value_parser = parse_percent, default_value = "0")]
+    chaos_drop_progress: f64,
+    /// Kill the called backend on every Nth tool call
+    #[arg(long, hide = true, requires = "chaos")]
+    chaos_crash_every: Option<u64>,
+    /// Seed for chaos decisions; the same seed injects the same failures
+    #[arg(long, hide = true, requires = "chaos", default_value_t = 0)]
+    chaos_seed: u64,
+}
+
+/// `--chaos-latency` as PERCENT:MS
+#[derive(Debug, Clone, Copy)]
+struct ChaosLatency(f64, Duration);
+
+impl std::str::FromStr for ChaosLatency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (percent, ms) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Expected PERCENT:MS, got '{}'", s))?;
+        let ms = ms
+            .parse()
+            .map_err(|_| format!("Invalid milliseconds '{}'", ms))?;
+        Ok(ChaosLatency(
+            parse_percent(percent)?,
+            Duration::from_millis(ms),
+        ))
+    }
+}
+
+/// A percentage from 0 to 100, as a fraction
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p / 100.0),
+        _ => Err(format!("Expected a percentage from 0 to 100, got '{}'", s)),
+    }
+}
+
+impl ServeArgs {
+    /// The registry to serve: the config directory's, or `--registry-json`'s
+    fn registry(&self) -> Result<Registry> {
+        let Some(path) = &self.registry_json else {
+            return Registry::load();
+        };
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read registry from {}", path.display()))?;
+        Registry::from_json(&json)
+            .with_context(|| format!("Invalid registry in {}", path.display()))
+    }
+
+    /// Chaos settings, refused unless `allowed` (MCPD_ALLOW_CHAOS=1)
+    fn chaos_options(&self, allowed: bool) -> Result<Option<ChaosOptions>> {
+        if !self.chaos {
+            return Ok(None);
+        }
+        if !allowed {
+            anyhow::bail!(
+                "--chaos injects failures on purpose; set MCPD_ALLOW_CHAOS=1 to confirm you want that"
+            );
+        }
+        Ok(Some(ChaosOptions {
+            latency: self.chaos_latency.map(|l| (l.0, l.1)),
+            error_rate: self.chaos_error_rate,
+            drop_progress: self.chaos_drop_progress,
+            crash_every: self.chaos_crash_every,
+            seed: self.chaos_seed,
+        }))
+    }
+
+    fn into_options(self) -> Result<ServeOptions> {
+        let allowed = std::env::var_os("MCPD_ALLOW_CHAOS").is_some_and(|v| v == "1");
+        let chaos = self.chaos_options(allowed)?;
+        let destructive_patterns = self.destructive_patterns.unwrap_or_else(|| {
+            DEFAULT_DESTRUCTIVE_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        });
+        let read_only_patterns = self.read_only.then(|| destructive_patterns.clone());
+        let intent_log = self
+            .intent_log
+            .map(|path| crate::intent::IntentLog::open(&path).map(Arc::new))
+            .transpose()?;
+        let audit_max_bytes = (self.audit_max_bytes > 0).then_some(self.audit_max_bytes);
+        let audit_log = self
+            .audit_log
+            .map(|path| crate::audit::AuditLog::open(&path, audit_max_bytes).map(Arc::new))
+            .transpose()?;
+        if let Some(patterns) = &read_only_patterns {
+            info!(?patterns, "Read-only mode enabled");
+        }
+
+        let schema_only = match &self.catalog {
+            Some(path) => {
+                let catalog = CatalogFile::load(path)?;
+                info!(catalog = %path.display(), "Schema-only mode: serving a catalog file, no backends will start");
+                Some(SchemaOnly {
+                    catalog: Arc::new(catalog),
+                    echo_calls: self.echo_calls,
+                })
+            }
+            None => None,
+        };
+
+        let json_limits = JsonLimits {
+            max_depth: self.max_json_depth,
+            max_bytes: self.max_message_bytes,
+        };
+        Ok(ServeOptions {
+            aggregate_instructions: self.aggregate_instructions,
+            read_only_patterns,
+            intent_log,
+            intent_log_patterns: destructive_patterns,
+            audit_log,
+            audit_redact_args: self.audit_redact_args,
+            json_limits,
+            proxy: ProxyOptions {
+                json_limits,
+                max_response_bytes: self.max_response_bytes as usize,
+                write_timeout: Duration::from_secs(self.write_timeout),
+                max_queued_writes: self.max_queued_writes as usize,
+                stderr: if self.inherit_stderr {
+                    StderrMode::Inherit
+                } else {
+                    StderrMode::Piped
+                },
+                default_wrapper: self.default_wrapper.map(|w| w.0),
+                init_failure_cooldown: Duration::from_secs(self.init_failure_cooldown),
+                abandoned_call_grace: Duration::from_secs(self.abandoned_call_grace),
+                read_buffer_size: self.read_buffer_size as usize,
+                fail_fast_uninitialized: self.fail_fast_uninitialized,
+                client_info: crate::mcp::ClientInfo {
+                    name: self.client_name,
+                    version: self.client_version,
+                },
+                key_sources: KeySources::from_environment(&Registry::default_config_dir()),
+                env_source: Default::default(),
+            },
+            secrets: SecretScanner::new(
+                self.secret_patterns,
+                self.secret_min_confidence,
+                self.secret_allow,
+            ),
+            secret_policy: self.secret_policy,
+            list_timeout: (self.list_timeout > 0).then(|| Duration::from_secs(self.list_timeout)),
+            call_timeout: (self.call_timeout > 0).then(|| Duration::from_secs(self.call_timeout)),
+            max_call_timeout: (self.max_call_timeout > 0)
+                .then(|| Duration::from_secs(self.max_call_timeout)),
+            max_concurrent_calls: self.max_concurrent_calls,
+            max_tools_warn: (self.max_tools_warn > 0).then_some(self.max_tools_warn),
+            max_tools: self
+                .max_tools
+                .or(self.client_profile.and_then(ClientProfile::max_tools)),
+            max_arg_bytes: self.max_arg_bytes.map(|bytes| bytes as usize),
+            pretty_output: self.pretty_output,
+            name_style: self.name_style,
+            max_tool_name_length: self
+                .max_tool_name_length
+                .map(|length| length as usize)
+                .or(self.client_profile.map(ClientProfile::max_tool_name_length)),
+            chaos,
+            sanitize_schemas: self.sanitize_schemas,
+            hook_timeout: Some(Duration::from_secs(self.hook_timeout)),
+            error_text: ErrorText {
+                strip_foreign: self.strip_foreign_error_text,
+            },
+            profile: self.profile,
+            structured: StructuredCompat {
+                fallback: self.structured_fallback,
+                strip: self.strip_structured,
+            },
+            compose: self.enable_compose,
+            ping_tool: self.enable_ping_tool,
+            list_backends_tool: self.enable_list_backends_tool,
+            echo_tool: self.enable_echo_tool,
+            watchdog: (self.watchdog_threshold > 0).then(|| WatchdogOptions {
+                threshold: Duration::from_secs(self.watchdog_threshold),
+                abort: self.watchdog_abort,
+                ..Default::default()
+            }),
+            server_name: Some(self.server_name),
+            server_version: Some(self.server_version),
+            schema_only,
+        })
+    }
+}
+
+/// A wrapper command line, split like a shell would
+#[derive(Debug, Clone)]
+struct WrapperArg(Vec<String>);
+
+impl std::str::FromStr for WrapperArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        shlex::split(s)
+            .map(WrapperArg)
+            .ok_or_else(|| format!("Invalid wrapper (unbalanced quotes?): {}", s))
+    }
+}
+
+/// A hook command, split like a shell would
+#[derive(Debug, Clone)]
+struct CommandLine(Vec<String>);
+
+impl std::str::FromStr for CommandLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match shlex::split(s) {
+            Some(argv) if !argv.is_empty() => Ok(CommandLine(argv)),
+            Some(_) => Err("Empty command".to_string()),
+            None => Err(format!("Invalid command (unbalanced quotes?): {}", s)),
+        }
+    }
+}
+
+/// `KEY=value` lines for `mcpd list`, sorted, with secret-looking values
+/// masked (unless `mask` is `None`), long values cut and values dimmed
+fn env_lines(
+    env: &HashMap<String, String>,
+    mask: Option<&EnvMask>,
+    max_len: usize,
+    out: &Output,
+) -> Vec<String> {
+    let mut env: Vec<_> = env.iter().collect();
+    env.sort();
+    env.into_iter()
+        .map(|(key, value)| {
+            let value = mask.map_or(value.as_str(), |mask| mask.show(key, value));
+            let value = limits::truncate_for_log(value, max_len);
+            format!("{}={}", key, out.paint(&value, Color::Dim))
+        })
+        .collect()
+}
+
+/// The table `mcpd list` prints: name and command per tool, in registry
+/// order, with its settings underneath
+fn render_list(
+    tools: &[&Tool],
+    mask: Option<&EnvMask>,
+    max_env_value_len: usize,
+    verbose: bool,
+    out: &Output,
+) -> String {
+    let mut table = Table::new(&["NAME", "COMMAND"]).truncate(1);
+    for tool in tools {
+        let mut details = Vec::new();
+        if let (true, Some(id)) = (verbose, tool.id) {
+            details.push(format!("id: {}", id));
+        }
+        details.extend(env_lines(&tool.env, mask, max_env_value_len, out));
+        for key in tool.secret_env.keys() {
+            details.push(format!(
+                "{}={}",
+                key,
+                out.paint(secret_env::ENCRYPTED_MARKER, Color::Dim)
+            ));
+        }
+        for key in &tool.dynamic_env {
+            details.push(format!("{}: from mcpd's environment", key));
+        }
+        if let Some(title) = &tool.title {
+            details.push(format!("title: {}", title));
+        }
+        if let Some(icon) = &tool.icon {
+            let kind = crate::icons::check(icon).unwrap_or("invalid");
+            details.push(format!("icon: {}", kind));
+        }
+        if let Some(cwd) = &tool.cwd {
+            details.push(format!("cwd: {}", cwd.display()));
+        }
+        if tool.cwd_from_root {
+            details.push("cwd: client root".to_string());
+        }
+        if let Some(transform) = &tool.transform {
+            details.push(format!("transform: {}", transform));
+        }
+        if !tool.priority.is_normal() {
+            details.push(format!("priority: {}", tool.priority));
+        }
+        if !tool.framing.is_auto() {
+            details.push(format!("framing: {}", tool.framing));
+        }
+        if !tool.log_level.is_debug() {
+            details.push(format!("log level: {}", tool.log_level));
+        }
+        if tool.singleton {
+            details.push("one call at a time".to_string());
+        }
+        if tool.no_pipelining {
+            details.push("no pipelining".to_string());
+        }
+        if tool.kill_on_abandoned {
+            details.push("stopped when a call is abandoned".to_string());
+        }
+        if let Some(fallback) = &tool.fallback {
+            details.push(format!("fallback: {}", fallback));
+        }
+        if let Some(bytes) = tool.read_buffer_size {
+            details.push(format!("read buffer: {} bytes", bytes));
+        }
+        if let Some(bytes) = tool.max_arg_bytes {
+            details.push(format!("max arguments: {} bytes", bytes));
+        }
+        if let Some(count) = tool.replicas {
+            details.push(format!("{} replicas", count));
+        }
+        if let Some(argument) = &tool.shard_by {
+            details.push(format!("shard by: {}", argument));
+        }
+        for (name, limits) in &tool.tool_concurrency {
+            let mut detail = match limits.limit() {
+                Some(1) => format!("{}: one call at a time", name),
+                Some(max) => format!("{}: {} calls at a time", name, max),
+                None => format!("{}: no concurrency limit", name),
+            };
+            if let Some(queue) = limits.max_queue {
+                detail.push_str(&format!(", at most {} waiting", queue));
+            }
+            details.push(detail);
+        }
+        for (name, group) in &tool.merge_groups {
+            details.push(format!("{}: merged into {}", name, group));
+        }
+        for (method, limit) in &tool.notification_limits {
+            match limit {
+                Some(rate) => details.push(format!("{}: {}/s", method, rate)),
+                None => details.push(format!("{}: unlimited", method)),
+            }
+        }
+        if tool.client_name.is_some() || tool.client_version.is_some() {
+            let name = tool.client_name.as_deref().unwrap_or("(default)");
+            let version = tool.client_version.as_deref().unwrap_or("(default)");
+            details.push(format!("client info: {} {}", name, version));
+        }
+        if let Some(check) = &tool.health_check {
+            details.push(format!(
+                "health check: {} every {}s",
+                check.tool, check.interval_secs
+            ));
+        }
+        for (hook, command) in [("pre_call", &tool.pre_call), ("post_call", &tool.post_call)] {
+            if let Some(command) = command {
+                details.push(format!("{}: {:?}", hook, command));
+            }
+        }
+        if tool.wrapper.as_ref().is_some_and(|w| !w.is_empty()) {
+            details.push(format!(
+                "runs as: {:?}",
+                tool.wrapped_command(None, tool.cwd.as_deref())
+            ));
+        }
+        table.row_with(
+            None,
+            vec![
+                Cell::new(tool.name.as_str()),
+                Cell::new(format!("{:?}", tool.command)),
+            ],
+            details,
+        );
+    }
+    table.render(out)
+}
+
+/// What `mcpd capabilities` prints: the server, its protocol version and a
+/// line per capability, with the options it turned on
+fn render_capabilities(result: &InitializeResult, out: &Output) -> String {
+    let caps = &result.capabilities;
+    let flags = |on: &[(&str, bool)]| {
+        let on: Vec<&str> = on.iter().filter(|(_, on)| *on).map(|(f, _)| *f).collect();
+        if on.is_empty() {
+            "yes".to_string()
+        } else {
+            format!("yes ({})", on.join(", "))
+        }
+    };
+    let lines = [
+        (
+            "tools",
+            caps.tools
+                .as_ref()
+                .map(|t| flags(&[("listChanged", t.list_changed)])),
+        ),
+        (
+            "resources",
+            caps.resources
+                .as_ref()
+                .map(|r| flags(&[("listChanged", r.list_changed), ("subscribe", r.subscribe)])),
+        ),
+        (
+            "prompts",
+            caps.prompts
+                .as_ref()
+                .map(|p| flags(&[("listChanged", p.list_changed)])),
+        ),
+        ("logging", caps.logging.as_ref().map(|_| flags(&[]))),
+        ("completions", caps.completions.as_ref().map(|_| flags(&[]))),
+    ];
+    let mut text = format!(
+        "{} {}\n{} {}\n",
+        out.paint(&result.server_info.name, Color::Bold),
+        result.server_info.version,
+        out.paint("protocol:", Color::Dim),
+        result.protocol_version
+    );
+    for (name, state) in lines {
+        let state = match state {
+            Some(state) => out.paint(&state, Color::Green),
+            None => out.paint("no", Color::Dim),
+        };
+        text.push_str(&format!(
+            "{} {}\n",
+            out.paint(&format!("{}:", name), Color::Dim),
+            state
+        ));
+    }
+    if let Some(experimental) = caps.experimental.as_ref().filter(|e| !e.is_empty()) {
+        let names: Vec<&str> = experimental.keys().map(String::as_str).collect();
+        text.push_str(&format!(
+            "{} {}\n",
+            out.paint("experimental:", Color::Dim),
+            names.join(", ")
+        ));
+    }
+    text
+}
+
+/// Validate a tool before it's saved: check the name and command, compile
+/// its transform, make `cwd` absolute and resolve the program on `PATH`
+fn prepare_tool(mut tool: Tool) -> Result<Tool> {
+    tool.icon = tool
+        .icon
+        .map(|icon| crate::icons::embed(&icon))
+        .transpose()?;
+    tool.validate()?;
+    if let Some(program) = tool.wrapper.as_ref().and_then(|w| w.first())
+        && !Path::new(program).is_file()
+        && which::which(program).is_err()
+    {
+        anyhow::bail!("Wrapper '{}' not found", program);
+    }
+    for program in [&tool.pre_call, &tool.post_call]
+        .into_iter()
+        .filter_map(|hook| hook.as_ref()?.first())
+    {
+        if !Path::new(program).is_file() && which::which(program).is_err() {
+            anyhow::bail!("Hook command '{}' not found", program);
+        }
+    }
+    if let Some(expr) = &tool.transform {
+        Transform::compile(expr)?;
+    }
+    tool.cwd = tool
+        .cwd
+        .map(|dir| {
+            dir.canonicalize()
+                .with_context(|| format!("Invalid cwd: {}", dir.display()))
+        })
+        .transpose()?;
+    if !tool.command[0].contains('/')
+        && let Ok(path) = which::which(&tool.command[0])
+    {
+        tool.command[0] = path.to_string_lossy().to_string();
+    }
+    Ok(tool)
+}
+
+/// Hand a tool to the running mcpd over its control socket
+#[cfg(unix)]
+async fn add_to_running(tool: Tool, ephemeral: bool) -> Result<()> {
+    let socket = crate::control::find_socket(&Registry::default_path()?)?;
+    let mut params = serde_json::to_value(&tool)?;
+    params["ephemeral"] = ephemeral.into();
+    crate::control::call(&socket, "mcpd/addBackend", Some(params)).await?;
+    if ephemeral {
+        println!(
+            "Added ephemeral tool '{}' to the running mcpd: {:?}",
+            tool.name, tool.command
+        );
+    } else {
+        println!("Registered tool '{}': {:?}", tool.name, tool.command);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn add_to_running(_tool: Tool, _ephemeral: bool) -> Result<()> {
+    anyhow::bail!("Adding to a running mcpd requires unix domain sockets")
+}
+
+/// Make a registry change through the running mcpd, if there is one, so it
+/// stays the only writer of the registry file. `None` means no mcpd took the
+/// change and the caller should write the file itself: none is running, it
+/// stopped before answering, or it's too old to know `method`.
+#[cfg(unix)]
+async fn via_running_server(
+    method: &str,
+    params: serde_json::Value,
+) -> Result<Option<serde_json::Value>> {
+    let Ok(socket) = crate::control::find_socket(&Registry::default_path()?) else {
+        return Ok(None);
+    };
+    match crate::control::call(&socket, method, Some(params)).await {
+        Ok(result) => Ok(Some(result)),
+        Err(e) => match e.downcast_ref::<crate::control::ControlError>() {
+            Some(error) if error.code == -32601 => {
+                eprintln!(
+                    "The running mcpd doesn't support {}; writing the registry directly",
+                    method
+                );
+                Ok(None)
+            }
+            Some(_) => Err(e),
+            None => {
+                eprintln!(
+                    "The running mcpd stopped before applying the change ({:#}); writing the registry directly",
+                    e
+                );
+                Ok(None)
+            }
+        },
+    }
+}
+
+#[cfg(not(unix))]
+async fn via_running_server(
+    _method: &str,
+    _params: serde_json::Value,
+) -> Result<Option<serde_json::Value>> {
+    Ok(None)
+}
+
+/// Register `tool` through the running mcpd, or in the registry file when none runs
+async fn register(tool: Tool) -> Result<()> {
+    let summary = format!("Registered tool '{}': {:?}", tool.name, tool.command);
+    if via_running_server("mcpd/register", serde_json::to_value(&tool)?)
+        .await?
+        .is_none()
+    {
+        Registry::load()?.register(tool)?;
+    }
+    println!("{}", summary);
+    Ok(())
+}
+
+/// The text of a tool spec given to `mcpd add`: inline JSON, an http(s) URL,
+/// or a file path
+async fn read_spec(source: &str) -> Result<String> {
+    let source = source.trim();
+    if source.starts_with('{') {
+        return Ok(source.to_string());
+    }
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let url = source.to_string();
+        return tokio::task::spawn_blocking(move || -> Result<String> {
+            let mut response = ureq::get(&url)
+                .call()
+                .with_context(|| format!("Failed to fetch {}", url))?;
+            response
+                .body_mut()
+                .read_to_string()
+                .with_context(|| format!("Failed to read spec from {}", url))
+        })
+        .await?;
+    }
+    std::fs::read_to_string(source).with_context(|| format!("Failed to read spec from {}", source))
+}
+
+/// Parse a tool spec. It has the same fields as a registry entry.
+fn parse_spec(text: &str) -> Result<Tool> {
+    serde_json::from_str(text).context("Invalid tool spec")
 }
 
 fn parse_env_var(s: &str) -> Result<(String, String), String> {
@@ -49,37 +1268,229 @@ fn parse_env_var(s: &str) -> Result<(String, String), String> {
     Ok((s[..pos].to_string(), s[pos + 1..].to_string()))
 }
 
+/// `METHOD=RATE` or `METHOD=unlimited`
+fn parse_notification_limit(s: &str) -> Result<(String, Option<u32>), String> {
+    let (method, rate) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid METHOD=RATE format: {}", s))?;
+    let rate = match rate {
+        "unlimited" => None,
+        rate => Some(
+            rate.parse()
+                .map_err(|_| format!("Invalid rate '{}': a number or 'unlimited'", rate))?,
+        ),
+    };
+    Ok((method.to_string(), rate))
+}
+
+/// `TOOL=N`
+fn parse_tool_count(s: &str) -> Result<(String, u32), String> {
+    let (tool, count) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid TOOL=N format: {}", s))?;
+    let count = count
+        .parse()
+        .map_err(|_| format!("Invalid count '{}': a whole number", count))?;
+    Ok((tool.to_string(), count))
+}
+
+/// `TOOL=GROUP`
+fn parse_merge_group(s: &str) -> Result<(String, String), String> {
+    let (tool, group) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid TOOL=GROUP format: {}", s))?;
+    Ok((tool.to_string(), group.to_string()))
+}
+
+/// `register`'s per-tool concurrency flags as registry entries
+fn tool_concurrency_limits(
+    serialize: Vec<String>,
+    concurrency: Vec<(String, u32)>,
+    queue: Vec<(String, u32)>,
+) -> BTreeMap<String, ToolConcurrency> {
+    let mut limits: BTreeMap<String, ToolConcurrency> = BTreeMap::new();
+    for tool in serialize {
+        limits.entry(tool).or_default().serialize = true;
+    }
+    for (tool, max) in concurrency {
+        limits.entry(tool).or_default().max_concurrency = Some(max);
+    }
+    for (tool, max) in queue {
+        limits.entry(tool).or_default().max_queue = Some(max as usize);
+    }
+    limits
+}
+
 impl Cli {
+    /// Collector to export traces to: `--otel-endpoint`, else `$OTEL_EXPORTER_OTLP_ENDPOINT`
+    #[cfg(feature = "otel")]
+    pub fn otel_endpoint(&self) -> Option<String> {
+        self.otel_endpoint
+            .clone()
+            .or_else(|| std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok())
+            .filter(|e| !e.is_empty())
+    }
+
     pub async fn run(self) -> Result<()> {
+        let out = Output::detect(self.color, self.wide);
         match self.command {
-            Commands::Register { name, command, env } => {
-                let mut registry = Registry::load()?;
-
-                // Resolve the command path
-                let resolved_command = if command[0].contains('/') {
-                    command
+            Commands::Register {
+                name,
+                command,
+                env,
+                dynamic_env,
+                secret_env,
+                zero_downtime,
+                transform,
+                cwd,
+                cwd_from_root,
+                forward_trace_context,
+                priority,
+                wrapper,
+                framing,
+                pre_call,
+                post_call,
+                pin_version,
+                log_level,
+                singleton,
+                no_pipelining,
+                kill_on_abandoned,
+                fallback,
+                read_buffer_size,
+                max_arg_bytes,
+                replicas,
+                shard_by,
+                serialize_tool,
+                tool_concurrency,
+                tool_queue,
+                merge_group,
+                notification_limit,
+                title,
+                icon,
+                client_name,
+                client_version,
+            } => {
+                let secret_env = if secret_env.is_empty() {
+                    Default::default()
                 } else {
-                    let mut resolved = command.clone();
-                    if let Ok(path) = which::which(&command[0]) {
-                        resolved[0] = path.to_string_lossy().to_string();
-                    }
-                    resolved
+                    let key = KeySources::from_environment(&Registry::default_config_dir())
+                        .load_or_create()?;
+                    secret_env
+                        .into_iter()
+                        .map(|(var, value)| {
+                            let sealed = key.seal(&var, &value);
+                            (var, sealed)
+                        })
+                        .collect()
                 };
-
                 let tool = Tool {
-                    name: name.clone(),
-                    command: resolved_command.clone(),
+                    name,
+                    // Kept from an earlier registration under this name
+                    id: None,
+                    command,
                     env: env.into_iter().collect(),
+                    dynamic_env,
+                    secret_env,
+                    zero_downtime,
+                    transform,
+                    cwd,
+                    cwd_from_root,
+                    forward_trace_context,
+                    priority,
+                    wrapper: wrapper.map(|w| w.0),
+                    framing,
+                    pre_call: pre_call.map(|c| c.0),
+                    post_call: post_call.map(|c| c.0),
+                    log_level,
+                    singleton,
+                    no_pipelining,
+                    health_check: None,
+                    kill_on_abandoned,
+                    fallback,
+                    read_buffer_size: read_buffer_size.map(|bytes| bytes as usize),
+                    max_arg_bytes: max_arg_bytes.map(|bytes| bytes as usize),
+                    replicas,
+                    shard_by,
+                    tool_concurrency: tool_concurrency_limits(
+                        serialize_tool,
+                        tool_concurrency,
+                        tool_queue,
+                    ),
+                    merge_groups: merge_group.into_iter().collect(),
+                    notification_limits: notification_limit.into_iter().collect(),
+                    title,
+                    icon,
+                    client_name,
+                    client_version,
                 };
+                let mut tool = prepare_tool(tool)?;
+                if pin_version {
+                    let (pinned, version) =
+                        crate::pinning::pin(&tool, crate::pinning::DEFAULT_HANDSHAKE_TIMEOUT)
+                            .await?;
+                    println!("Pinned '{}' to version {}", tool.name, version);
+                    tool = pinned;
+                }
+                register(tool).await
+            }
 
-                registry.register(tool)?;
-                println!("Registered tool '{}': {:?}", name, resolved_command);
+            Commands::Add {
+                spec,
+                command,
+                env,
+                ephemeral,
+            } => {
+                let live = ephemeral || !command.is_empty();
+                let tool = if command.is_empty() {
+                    if !env.is_empty() {
+                        anyhow::bail!("--env needs a command after '--'; put env in the spec");
+                    }
+                    parse_spec(&read_spec(&spec).await?)?
+                } else {
+                    Tool {
+                        name: spec,
+                        command,
+                        env: env.into_iter().collect(),
+                        ..Default::default()
+                    }
+                };
+                let tool = prepare_tool(tool)?;
+                if !Path::new(&tool.command[0]).is_file() {
+                    anyhow::bail!("Command '{}' not found", tool.command[0]);
+                }
+                if live {
+                    return add_to_running(tool, ephemeral).await;
+                }
+                register(tool).await
+            }
+
+            #[cfg(unix)]
+            Commands::Remove { name } => {
+                let socket = crate::control::find_socket(&Registry::default_path()?)?;
+                let result = crate::control::call(
+                    &socket,
+                    "mcpd/removeBackend",
+                    Some(serde_json::json!({ "backend": name })),
+                )
+                .await?;
+                if result["ephemeral"] == true {
+                    println!("Removed ephemeral tool '{}'", name);
+                } else {
+                    println!("Removed and unregistered tool '{}'", name);
+                }
                 Ok(())
             }
 
+            #[cfg(not(unix))]
+            Commands::Remove { .. } => anyhow::bail!("mcpd remove requires unix domain sockets"),
+
             Commands::Unregister { name } => {
-                let mut registry = Registry::load()?;
-                if registry.unregister(&name)? {
+                let params = serde_json::json!({ "backend": name });
+                let unregistered = match via_running_server("mcpd/unregister", params).await? {
+                    Some(result) => result["unregistered"] == true,
+                    None => Registry::load()?.unregister(&name)?,
+                };
+                if unregistered {
                     println!("Unregistered tool '{}'", name);
                 } else {
                     println!("Tool '{}' not found", name);
@@ -87,7 +1498,58 @@ impl Cli {
                 Ok(())
             }
 
-            Commands::List => {
+            Commands::Rename { name, new_name } => {
+                let params = serde_json::json!({ "backend": name, "name": new_name });
+                if via_running_server("mcpd/rename", params).await?.is_none() {
+                    Registry::load()?.rename(&name, &new_name)?;
+                }
+                println!("Renamed '{}' to '{}'", name, new_name);
+                Ok(())
+            }
+
+            Commands::Reorder {
+                name,
+                before,
+                after,
+                top,
+                bottom,
+            } => {
+                let position = match (before, after) {
+                    (Some(other), _) => Position::Before(other),
+                    (_, Some(other)) => Position::After(other),
+                    _ if top => Position::Top,
+                    _ => {
+                        debug_assert!(bottom);
+                        Position::Bottom
+                    }
+                };
+                let params = serde_json::json!({ "backend": name, "position": position });
+                let order: Vec<String> = match via_running_server("mcpd/reorder", params).await? {
+                    Some(result) => serde_json::from_value(result["order"].clone())
+                        .context("Invalid reorder result")?,
+                    None => {
+                        let mut registry = Registry::load()?;
+                        registry.reorder(&name, &position)?;
+                        registry.list().map(|t| t.name.clone()).collect()
+                    }
+                };
+                println!("Moved '{}': {}", name, order.join(", "));
+                Ok(())
+            }
+
+            Commands::List {
+                show_secrets,
+                secret_env_pattern,
+                max_env_value_len,
+                verbose,
+            } => {
+                let mask = if show_secrets {
+                    None
+                } else {
+                    Some(EnvMask::new(&secret_env_pattern).with_context(|| {
+                        format!("Invalid --secret-env-pattern: {}", secret_env_pattern)
+                    })?)
+                };
                 let registry = Registry::load()?;
 
                 if registry.is_empty() {
@@ -96,27 +1558,443 @@ impl Cli {
                 }
 
                 println!("Registered tools ({}):", registry.len());
-                for tool in registry.list() {
-                    println!("  {} -> {:?}", tool.name, tool.command);
-                    if !tool.env.is_empty() {
-                        for (k, v) in &tool.env {
-                            println!("    {}={}", k, v);
-                        }
-                    }
-                }
+                let tools: Vec<&Tool> = registry.list().collect();
+                print!(
+                    "{}",
+                    render_list(&tools, mask.as_ref(), max_env_value_len, verbose, &out)
+                );
                 Ok(())
             }
 
-            Commands::Serve => {
-                let registry = Registry::load()?;
+            Commands::Validate { config } => {
+                let path =
+                    config.unwrap_or_else(|| Registry::default_config_dir().join("registry.json"));
+                let json = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let problems = crate::validate::check(&json);
+                if problems.is_empty() {
+                    println!("{}: OK", path.display());
+                    return Ok(());
+                }
+                for problem in &problems {
+                    println!("{}: {}", path.display(), problem);
+                }
+                anyhow::bail!(
+                    "{} problem{} in {}",
+                    problems.len(),
+                    if problems.len() == 1 { "" } else { "s" },
+                    path.display()
+                )
+            }
+
+            Commands::Serve { args } => {
+                let registry = args.registry()?;
                 info!(
                     backends = registry.len(),
                     "Starting MCP server (2 meta-tools: list_tools, use_tool)"
                 );
 
-                let server = Server::new(registry);
+                let options = args.into_options()?;
+                let server = Server::with_options(registry, options);
                 server.run().await
             }
+
+            #[cfg(unix)]
+            Commands::Daemon { socket, args } => {
+                let registry = args.registry()?;
+                let socket = match socket {
+                    Some(s) => s,
+                    None => crate::transport::daemon_socket_path(&Registry::default_path()?),
+                };
+                if tokio::net::UnixStream::connect(&socket).await.is_ok() {
+                    anyhow::bail!("A daemon is already listening on {}", socket.display());
+                }
+                let _ = std::fs::remove_file(&socket);
+                let listener = tokio::net::UnixListener::bind(&socket)
+                    .with_context(|| format!("Failed to bind {}", socket.display()))?;
+                info!(
+                    backends = registry.len(),
+                    socket = %socket.display(),
+                    "Starting MCP daemon"
+                );
+
+                let server = Arc::new(Server::with_options(registry, args.into_options()?));
+                let result = server.run_daemon(listener).await;
+                let _ = std::fs::remove_file(&socket);
+                result
+            }
+
+            #[cfg(unix)]
+            Commands::Connect { socket } => {
+                let socket = match socket {
+                    Some(s) => s,
+                    None => crate::transport::daemon_socket_path(&Registry::default_path()?),
+                };
+                let stream = tokio::net::UnixStream::connect(&socket)
+                    .await
+                    .with_context(|| format!("No daemon listening on {}", socket.display()))?;
+                let (mut read, mut write) = stream.into_split();
+                let upstream = async {
+                    tokio::io::copy(&mut tokio::io::stdin(), &mut write).await?;
+                    write.shutdown().await
+                };
+                let mut stdout = tokio::io::stdout();
+                let downstream = tokio::io::copy(&mut read, &mut stdout);
+                // Either side closing ends the session
+                tokio::select! {
+                    r = upstream => r?,
+                    r = downstream => { r?; }
+                }
+                Ok(())
+            }
+
+            #[cfg(not(unix))]
+            Commands::Daemon { .. } | Commands::Connect { .. } => {
+                anyhow::bail!("mcpd daemon requires unix domain sockets")
+            }
+
+            #[cfg(unix)]
+            Commands::Conflicts { timeout } => {
+                let registry = Registry::load()?;
+                let timeout = Duration::from_secs(timeout);
+                let mut listings = tokio::task::JoinSet::new();
+                for tool in registry.list() {
+                    let proxy = ToolProxy::new(tool.clone());
+                    listings.spawn(async move {
+                        let tools = tokio::time::timeout(timeout, proxy.list_tools()).await;
+                        let _ = proxy.stop().await;
+                        (proxy.tool().name.clone(), tools)
+                    });
+                }
+                let mut catalogs = Vec::new();
+                for (name, tools) in listings.join_all().await {
+                    match tools {
+                        Ok(Ok(tools)) => catalogs.push((name, tools)),
+                        Ok(Err(e)) => eprintln!("Skipping '{}': {}", name, e),
+                        Err(_) => eprintln!("Skipping '{}': timed out after {:?}", name, timeout),
+                    }
+                }
+                catalogs.sort_by(|a, b| a.0.cmp(&b.0));
+                print!("{}", crate::conflicts::render(&catalogs, &out));
+                Ok(())
+            }
+
+            Commands::Catalog {
+                json,
+                timeout,
+                name_style,
+            } => {
+                let options = ServeOptions {
+                    list_timeout: Some(Duration::from_secs(timeout)),
+                    name_style,
+                    ..Default::default()
+                };
+                let server = Server::with_options(Registry::load()?, options);
+                let catalog = server.catalog().await;
+                server.stop_all().await;
+                let catalog = catalog?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&catalog)?);
+                } else {
+                    print!("{}", crate::catalog::render(&catalog, &out));
+                }
+                Ok(())
+            }
+
+            Commands::Which {
+                name,
+                timeout,
+                name_style,
+            } => {
+                let options = ServeOptions {
+                    list_timeout: Some(Duration::from_secs(timeout)),
+                    name_style,
+                    ..Default::default()
+                };
+                let registry = Registry::load()?;
+                let commands: HashMap<String, Vec<String>> = registry
+                    .list()
+                    .map(|tool| (tool.name.clone(), tool.command.clone()))
+                    .collect();
+                let server = Server::with_options(registry, options);
+                let catalog = server.catalog().await;
+                server.stop_all().await;
+                let catalog = catalog?;
+                let Some(entry) = catalog.find(&name) else {
+                    anyhow::bail!("{}", catalog.explain_unknown(&name));
+                };
+                let command = &commands[&entry.backend];
+                print!("{}", crate::catalog::render_which(entry, command, &out));
+                Ok(())
+            }
+
+            Commands::Capabilities {
+                name,
+                json,
+                timeout,
+            } => {
+                let registry = Registry::load()?;
+                let tool = registry
+                    .list()
+                    .find(|t| t.name == name)
+                    .cloned()
+                    .with_context(|| format!("Tool '{}' not found", name))?;
+                let proxy = ToolProxy::new(tool);
+                let timeout = Duration::from_secs(timeout);
+                let result = tokio::time::timeout(timeout, proxy.initialize_result()).await;
+                let _ = proxy.stop().await;
+                let result = result
+                    .map_err(|_| {
+                        anyhow::anyhow!("'{}' didn't initialize within {:?}", name, timeout)
+                    })?
+                    .with_context(|| format!("Failed to initialize '{}'", name))?;
+                if json {
+                    let summary = serde_json::json!({
+                        "protocolVersion": result.protocol_version,
+                        "serverInfo": result.server_info,
+                        "capabilities": result.capabilities,
+                    });
+                    println!("{}", serde_json::to_string_pretty(&summary)?);
+                } else {
+                    print!("{}", render_capabilities(&result, &out));
+                }
+                Ok(())
+            }
+
+            Commands::Verify {
+                spec,
+                junit,
+                timeout,
+            } => {
+                let spec = crate::verify::spec::Spec::load(&spec)?;
+                let registry = spec.backend.is_some().then(Registry::load).transpose()?;
+                let tool =
+                    spec.resolve(|name| registry?.list().find(|t| t.name == name).cloned())?;
+                let report = crate::verify::run(&spec, tool, Duration::from_secs(timeout)).await?;
+                print!("{}", crate::verify::render(&report, &out));
+                if let Some(path) = junit {
+                    std::fs::write(&path, crate::verify::junit::render(&report))
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                }
+                let failed = report.failed();
+                if failed > 0 {
+                    anyhow::bail!(
+                        "{} of {} assertions failed",
+                        failed,
+                        report.assertions.len()
+                    );
+                }
+                Ok(())
+            }
+
+            Commands::ExportFunctions {
+                format,
+                timeout,
+                name_style,
+            } => {
+                let options = ServeOptions {
+                    list_timeout: Some(Duration::from_secs(timeout)),
+                    name_style,
+                    ..Default::default()
+                };
+                let server = Server::with_options(Registry::load()?, options);
+                let catalog = server.catalog().await;
+                server.stop_all().await;
+                let catalog = catalog?;
+                let export = crate::functions::export(&catalog, format);
+                for (name, renamed) in &export.renamed {
+                    eprintln!("Renamed '{}' to '{}'", name, renamed);
+                }
+                for failure in &catalog.failures {
+                    eprintln!("Skipping '{}': {}", failure.backend, failure.error);
+                }
+                println!("{}", serde_json::to_string_pretty(&export.tools)?);
+                Ok(())
+            }
+
+            Commands::ExportCatalog {
+                file,
+                timeout,
+                name_style,
+            } => {
+                let options = ServeOptions {
+                    list_timeout: Some(Duration::from_secs(timeout)),
+                    name_style,
+                    ..Default::default()
+                };
+                let server = Server::with_options(Registry::load()?, options);
+                let exported = server.export_catalog().await;
+                server.stop_all().await;
+                let (catalog, failures) = exported?;
+                if !failures.is_empty() {
+                    for failure in &failures {
+                        eprintln!(
+                            "'{}' failed to list tools: {}",
+                            failure.backend, failure.error
+                        );
+                    }
+                    anyhow::bail!("Not writing an incomplete catalog");
+                }
+                let mut json = serde_json::to_string_pretty(&catalog)?;
+                json.push('\n');
+                std::fs::write(&file, json)
+                    .with_context(|| format!("Failed to write {}", file.display()))?;
+                println!(
+                    "Wrote {} tool(s) from {} backend(s) to {}",
+                    catalog.tools().len(),
+                    catalog.backends.len(),
+                    file.display()
+                );
+                Ok(())
+            }
+
+            Commands::Diff { catalog, timeout } => {
+                let recorded = CatalogFile::load(&catalog)?;
+                let options = ServeOptions {
+                    list_timeout: Some(Duration::from_secs(timeout)),
+                    ..Default::default()
+                };
+                let server = Server::with_options(Registry::load()?, options);
+                let exported = server.export_catalog().await;
+                server.stop_all().await;
+                let (live, failures) = exported?;
+                let drift = crate::offline::diff(&recorded, &live, &failures);
+                print!("{}", crate::offline::render_diff(&drift, &out));
+                if !drift.is_empty() {
+                    anyhow::bail!("{} is out of date", catalog.display());
+                }
+                Ok(())
+            }
+
+            Commands::DiffConfig {
+                before,
+                after,
+                json,
+                timeout,
+                name_style,
+            } => {
+                let timeout = Duration::from_secs(timeout);
+                let old = crate::config_diff::load(&before, timeout, name_style).await?;
+                let new = crate::config_diff::load(&after, timeout, name_style).await?;
+                let diff = crate::config_diff::diff(&old, &new);
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&diff)?);
+                } else {
+                    print!("{}", crate::config_diff::render(&diff, &out));
+                }
+                if diff.breaking {
+                    anyhow::bail!(
+                        "{} breaks calls that work under {}",
+                        after.display(),
+                        before.display()
+                    );
+                }
+                Ok(())
+            }
+
+            Commands::Outdated {
+                check_upstream,
+                timeout,
+            } => {
+                let tools = Registry::load()?.list().cloned().collect();
+                let statuses =
+                    crate::pinning::outdated(tools, check_upstream, Duration::from_secs(timeout))
+                        .await;
+                print!("{}", crate::pinning::render(&statuses, &out));
+                Ok(())
+            }
+
+            Commands::Upgrade { name, timeout } => {
+                let mut registry = Registry::load()?;
+                let upgrade =
+                    crate::pinning::upgrade(&mut registry, &name, Duration::from_secs(timeout))
+                        .await?;
+                match upgrade.previous {
+                    Some(previous) if previous == upgrade.version => {
+                        println!("'{}' is already at the latest version, {}", name, previous)
+                    }
+                    Some(previous) => println!(
+                        "Upgraded '{}' from {} to {}",
+                        name, previous, upgrade.version
+                    ),
+                    None => println!("Pinned '{}' to version {}", name, upgrade.version),
+                }
+                Ok(())
+            }
+
+            Commands::Top { once, json } => {
+                let socket = crate::control::find_socket(&Registry::default_path()?)?;
+                if !once {
+                    return crate::top::run(&socket, &out).await;
+                }
+                let snapshot = crate::top::fetch(&socket).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&snapshot)?);
+                } else {
+                    print!("{}", crate::top::render(&snapshot, &out));
+                }
+                Ok(())
+            }
+
+            #[cfg(not(unix))]
+            Commands::Top { .. } => anyhow::bail!("mcpd top requires unix domain sockets"),
+
+            Commands::Transcript {
+                audit_file,
+                format,
+                backend,
+                errors_only,
+                since,
+                max_blob_chars,
+            } => {
+                let since = since
+                    .map(|s| crate::transcript::parse_since(&s, chrono::Utc::now()))
+                    .transpose()
+                    .context("Invalid --since")?;
+                let log = crate::audit::read(&audit_file)?;
+                let options = crate::transcript::Options {
+                    format,
+                    filter: crate::transcript::Filter {
+                        backend,
+                        errors_only,
+                        since,
+                    },
+                    max_blob_chars,
+                };
+                print!("{}", crate::transcript::render(&log, &options));
+                Ok(())
+            }
+
+            Commands::Audit {
+                command: AuditCommand::Verify { file },
+            } => {
+                let report = crate::intent::verify_file(&file)?;
+                print!("{}", crate::intent::summary(&report));
+                if !report.is_intact() {
+                    anyhow::bail!("Intent log {} failed verification", file.display());
+                }
+                Ok(())
+            }
+
+            Commands::Secrets {
+                command: SecretsCommand::Rekey,
+            } => {
+                let mut registry = Registry::load()?;
+                let sources = KeySources::from_environment(&Registry::default_config_dir());
+                let rekeyed = secret_env::rekey(
+                    &mut registry,
+                    &sources,
+                    std::env::var(secret_env::NEW_MASTER_KEY_VAR).ok(),
+                )?;
+                println!(
+                    "Re-encrypted {} values of {} tools under master key {} ({})",
+                    rekeyed.values,
+                    rekeyed.tools,
+                    rekeyed.key.id(),
+                    rekeyed.key.origin()
+                );
+                Ok(())
+            }
         }
     }
 }
@@ -148,4 +2026,349 @@ mod tests {
         let result = parse_env_var("KEYVALUE");
         assert!(result.is_err());
     }
+
+    #[test]
+    fn parse_notification_limits() {
+        assert_eq!(
+            parse_notification_limit("notifications/message=50"),
+            Ok(("notifications/message".to_string(), Some(50)))
+        );
+        assert_eq!(
+            parse_notification_limit("notifications/resources/updated=unlimited"),
+            Ok(("notifications/resources/updated".to_string(), None))
+        );
+        assert!(parse_notification_limit("notifications/message").is_err());
+        assert!(parse_notification_limit("notifications/message=fast").is_err());
+    }
+
+    fn serve_options(args: &[&str]) -> ServeOptions {
+        let cli = Cli::try_parse_from(["mcpd", "serve"].iter().chain(args)).unwrap();
+        match cli.command {
+            Commands::Serve { args } => args.into_options().unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn serve_args(args: &[&str]) -> ServeArgs {
+        match Cli::try_parse_from(["mcpd", "serve"].iter().chain(args))
+            .unwrap()
+            .command
+        {
+            Commands::Serve { args } => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn list_masks_secret_env_values() {
+        let env: HashMap<String, String> = [
+            ("GITHUB_TOKEN", "ghp_abc"),
+            ("LOG_LEVEL", "debug"),
+            ("NOTES", "a long value"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        let mask = EnvMask::default();
+        assert_eq!(
+            env_lines(&env, Some(&mask), 80, &Output::plain()),
+            ["GITHUB_TOKEN=****", "LOG_LEVEL=debug", "NOTES=a long value"]
+        );
+        // --show-secrets, and long values cut
+        assert_eq!(
+            env_lines(&env, None, 6, &Output::plain()),
+            [
+                "GITHUB_TOKEN=ghp_ab... (1 more chars)",
+                "LOG_LEVEL=debug",
+                "NOTES=a long... (6 more chars)"
+            ]
+        );
+        // --secret-env-pattern replaces the default
+        let mask = EnvMask::new("(?i)^notes$").unwrap();
+        assert_eq!(
+            env_lines(&env, Some(&mask), 80, &Output::plain()),
+            ["GITHUB_TOKEN=ghp_abc", "LOG_LEVEL=debug", "NOTES=****"]
+        );
+    }
+
+    #[test]
+    fn capabilities_summary() {
+        let result: InitializeResult = serde_json::from_value(serde_json::json!({
+            "protocolVersion": "2025-06-18",
+            "serverInfo": {"name": "fs", "version": "1.2.0"},
+            "capabilities": {
+                "tools": {"listChanged": true},
+                "resources": {"subscribe": true, "listChanged": true},
+                "logging": {},
+                "experimental": {"vendor/batch": {}}
+            }
+        }))
+        .unwrap();
+        let expected = concat!(
+            "fs 1.2.0\n",
+            "protocol: 2025-06-18\n",
+            "tools: yes (listChanged)\n",
+            "resources: yes (listChanged, subscribe)\n",
+            "prompts: no\n",
+            "logging: yes\n",
+            "completions: no\n",
+            "experimental: vendor/batch\n",
+        );
+        assert_eq!(render_capabilities(&result, &Output::plain()), expected);
+    }
+
+    #[test]
+    fn list_table_snapshot() {
+        let fs = Tool {
+            name: "fs".to_string(),
+            command: vec!["/usr/bin/npx".to_string(), "server-fs".to_string()],
+            env: [("API_KEY".to_string(), "sk-123".to_string())].into(),
+            dynamic_env: vec!["SESSION_TOKEN".to_string()],
+            secret_env: [(
+                "DB_PASSWORD".to_string(),
+                "mcpd:v1:0a1b2c3d:AAAA".to_string(),
+            )]
+            .into(),
+            priority: Priority::High,
+            ..Default::default()
+        };
+        let git = Tool {
+            name: "git".to_string(),
+            command: vec!["/usr/bin/mcp-git".to_string()],
+            ..Default::default()
+        };
+        let mask = EnvMask::default();
+        let expected = concat!(
+            "NAME  COMMAND\n",
+            "git   [\"/usr/bin/mcp-git\"]\n",
+            "fs    [\"/usr/bin/npx\", \"server-fs\"]\n",
+            "    API_KEY=****\n",
+            "    DB_PASSWORD=<encrypted>\n",
+            "    SESSION_TOKEN: from mcpd's environment\n",
+            "    priority: high\n",
+        );
+        assert_eq!(
+            render_list(&[&git, &fs], Some(&mask), 80, false, &Output::plain()),
+            expected
+        );
+
+        let colored = render_list(&[&fs], None, 80, false, &Output::plain().with_color(true));
+        assert!(
+            colored.contains("API_KEY=\x1b[2msk-123\x1b[0m"),
+            "{}",
+            colored
+        );
+        // Cut to the terminal width unless --wide
+        let narrow = render_list(
+            &[&fs],
+            None,
+            80,
+            false,
+            &Output::plain().with_width(Some(20)),
+        );
+        assert!(narrow.contains("fs    [\"/usr/…r-fs\"]"), "{}", narrow);
+    }
+
+    #[test]
+    fn add_takes_command_after_dashes() {
+        let cli = Cli::try_parse_from([
+            "mcpd",
+            "add",
+            "--ephemeral",
+            "-e",
+            "A=1",
+            "tmp",
+            "--",
+            "server",
+            "--flag",
+        ])
+        .unwrap();
+        let Commands::Add {
+            spec,
+            command,
+            env,
+            ephemeral,
+        } = cli.command
+        else {
+            unreachable!()
+        };
+        assert_eq!(spec, "tmp");
+        assert_eq!(command, ["server", "--flag"]);
+        assert_eq!(env, [("A".to_string(), "1".to_string())]);
+        assert!(ephemeral);
+    }
+
+    #[test]
+    fn reorder_needs_exactly_one_position() {
+        let parse =
+            |args: &[&str]| Cli::try_parse_from(["mcpd", "reorder", "fs"].iter().chain(args));
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--top", "--bottom"]).is_err());
+        assert!(parse(&["--before", "git", "--after", "git"]).is_err());
+        for args in [
+            &["--top"][..],
+            &["--bottom"],
+            &["--before", "git"],
+            &["--after", "git"],
+        ] {
+            assert!(parse(args).is_ok(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn chaos_needs_opt_in() {
+        assert_eq!(serve_args(&[]).chaos_options(false).unwrap(), None);
+        let args = serve_args(&["--chaos", "--chaos-error-rate", "25"]);
+        let err = args.chaos_options(false).unwrap_err();
+        assert!(err.to_string().contains("MCPD_ALLOW_CHAOS=1"), "{}", err);
+
+        let args = serve_args(&[
+            "--chaos",
+            "--chaos-latency",
+            "10:250",
+            "--chaos-error-rate",
+            "25",
+            "--chaos-crash-every",
+            "5",
+            "--chaos-seed",
+            "9",
+        ]);
+        assert_eq!(
+            args.chaos_options(true).unwrap(),
+            Some(ChaosOptions {
+                latency: Some((0.1, Duration::from_millis(250))),
+                error_rate: 0.25,
+                drop_progress: 0.0,
+                crash_every: Some(5),
+                seed: 9,
+            })
+        );
+    }
+
+    #[test]
+    fn chaos_flags_validated() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["mcpd", "serve"].iter().chain(args));
+        // Sub-options only with --chaos
+        assert!(parse(&["--chaos-error-rate", "5"]).is_err());
+        assert!(parse(&["--chaos", "--chaos-error-rate", "101"]).is_err());
+        assert!(parse(&["--chaos", "--chaos-latency", "50"]).is_err());
+        assert!(parse(&["--chaos", "--chaos-latency", "50:x"]).is_err());
+    }
+
+    #[test]
+    fn schema_only_flags_go_together() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["mcpd", "serve"].iter().chain(args));
+        assert!(parse(&["--schema-only"]).is_err());
+        assert!(parse(&["--catalog", "c.json"]).is_err());
+        assert!(parse(&["--catalog", "c.json", "--echo-calls"]).is_err());
+        assert!(parse(&["--catalog", "c.json", "--schema-only", "--echo-calls"]).is_ok());
+        assert!(serve_options(&[]).schema_only.is_none());
+    }
+
+    #[test]
+    fn inherit_stderr_changes_spawn_config() {
+        assert_eq!(serve_options(&[]).proxy.stderr, StderrMode::Piped);
+        assert_eq!(
+            serve_options(&["--inherit-stderr"]).proxy.stderr,
+            StderrMode::Inherit
+        );
+    }
+
+    #[test]
+    fn server_info_defaults_to_mcpd() {
+        let options = serve_options(&[]);
+        assert_eq!(options.server_name.as_deref(), Some("mcpd"));
+        assert_eq!(
+            options.server_version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        let options = serve_options(&["--server-name", "work", "--server-version", "1"]);
+        assert_eq!(options.server_name.as_deref(), Some("work"));
+        assert_eq!(options.server_version.as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn client_info_defaults_to_mcpd() {
+        let info = serve_options(&[]).proxy.client_info;
+        assert_eq!(info.name, "mcpd");
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        let options = serve_options(&["--client-name", "claude-ai", "--client-version", "2"]);
+        assert_eq!(options.proxy.client_info.name, "claude-ai");
+        assert_eq!(options.proxy.client_info.version, "2");
+    }
+
+    #[test]
+    fn client_profile_sets_limits_flags_override() {
+        let options = serve_options(&[]);
+        assert_eq!(options.max_tools, None);
+        assert_eq!(options.max_tool_name_length, None);
+        let options = serve_options(&["--client-profile", "cursor"]);
+        assert_eq!(options.max_tools, Some(40));
+        assert_eq!(options.max_tool_name_length, Some(60));
+        let options = serve_options(&["--client-profile", "claude"]);
+        assert_eq!(options.max_tools, None);
+        assert_eq!(options.max_tool_name_length, Some(64));
+        let options = serve_options(&[
+            "--client-profile",
+            "cursor",
+            "--max-tools",
+            "100",
+            "--max-tool-name-length",
+            "48",
+        ]);
+        assert_eq!(options.max_tools, Some(100));
+        assert_eq!(options.max_tool_name_length, Some(48));
+    }
+
+    #[test]
+    fn structured_fallback_is_on_unless_turned_off() {
+        let on = StructuredCompat {
+            fallback: true,
+            strip: false,
+        };
+        assert_eq!(serve_options(&[]).structured, on);
+        assert_eq!(serve_options(&["--structured-fallback"]).structured, on);
+        let options = serve_options(&["--structured-fallback=false", "--strip-structured"]);
+        assert_eq!(
+            options.structured,
+            StructuredCompat {
+                fallback: false,
+                strip: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn add_inline_spec() {
+        let spec =
+            r#"{"name": "fs", "command": ["/bin/sh", "-c", "true"], "env": {"ROOT": "/tmp"}}"#;
+        let tool = prepare_tool(parse_spec(&read_spec(spec).await.unwrap()).unwrap()).unwrap();
+        assert_eq!(tool.name, "fs");
+        assert_eq!(tool.command, vec!["/bin/sh", "-c", "true"]);
+        assert_eq!(tool.env["ROOT"], "/tmp");
+    }
+
+    #[tokio::test]
+    async fn add_spec_from_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("spec.json");
+        std::fs::write(
+            &path,
+            r#"{"name": "git", "command": ["git-mcp"], "zero_downtime": true}"#,
+        )
+        .unwrap();
+        let tool = parse_spec(&read_spec(path.to_str().unwrap()).await.unwrap()).unwrap();
+        assert_eq!(tool.name, "git");
+        assert!(tool.zero_downtime);
+    }
+
+    #[tokio::test]
+    async fn add_rejects_invalid_specs() {
+        assert!(parse_spec(r#"{"name": "x"}"#).is_err());
+        assert!(parse_spec("not json").is_err());
+        let bad_name = parse_spec(r#"{"name": "a__b", "command": ["x"]}"#).unwrap();
+        assert!(prepare_tool(bad_name).is_err());
+        assert!(read_spec("/definitely/not/a/spec.json").await.is_err());
+    }
 }
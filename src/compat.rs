@@ -0,0 +1,577 @@
+//! Whether a change to a tool's input schema breaks calls that worked before.
+//!
+//! A change is breaking when it narrows the schema: some arguments valid
+//! under the old schema are invalid under the new one. `mcpd diff-config`
+//! exits non-zero on any. Each difference is reported at its JSON pointer
+//! into the input schema and classified by these rules:
+//!
+//! | Change                                                  | Breaking |
+//! |---------------------------------------------------------|----------|
+//! | optional property added                                 | no       |
+//! | property made required (new or existing)                | yes      |
+//! | property no longer required                             | no       |
+//! | property removed                                        | only if `additionalProperties` is now `false` |
+//! | `additionalProperties` restricted (`false` or a schema) | yes      |
+//! | `type` changed                                          | unless every old type is still allowed (`integer` fits in `number`) |
+//! | `enum` values removed, or an `enum` added               | yes      |
+//! | `enum` values added, or the `enum` dropped              | no       |
+//! | lower bound (`minimum`, `minLength`, ...) added or raised | yes    |
+//! | upper bound (`maximum`, `maxLength`, ...) added or lowered | yes   |
+//! | `pattern`, `format`, `const`, combinators, `$ref` added or changed | yes |
+//! | anything else (`description`, `default`, `title`, ...)  | no       |
+//!
+//! `properties` and `items` are compared recursively. Other subschemas are
+//! compared whole, which is why a changed `anyOf` counts as breaking even
+//! when it only widened.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Keywords that only ever restrict values, so adding or changing one may
+/// reject arguments that passed before
+const RESTRICTING: &[&str] = &[
+    "pattern",
+    "format",
+    "const",
+    "anyOf",
+    "oneOf",
+    "allOf",
+    "not",
+    "$ref",
+    "if",
+    "then",
+    "else",
+    "uniqueItems",
+    "contains",
+    "prefixItems",
+    "patternProperties",
+    "propertyNames",
+    "dependentRequired",
+    "dependentSchemas",
+    "multipleOf",
+];
+
+const LOWER_BOUNDS: &[&str] = &[
+    "minimum",
+    "exclusiveMinimum",
+    "minLength",
+    "minItems",
+    "minProperties",
+];
+
+const UPPER_BOUNDS: &[&str] = &[
+    "maximum",
+    "exclusiveMaximum",
+    "maxLength",
+    "maxItems",
+    "maxProperties",
+];
+
+/// What changed at one place in a schema
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    PropertyAdded,
+    PropertyRemoved,
+    BecameRequired,
+    NoLongerRequired,
+    EnumChanged {
+        added: Vec<Value>,
+        removed: Vec<Value>,
+    },
+    /// Any other keyword; `None` when it's absent on that side
+    KeywordChanged {
+        before: Option<Value>,
+        after: Option<Value>,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |values: &[Value]| {
+            values
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Change::PropertyAdded => write!(f, "property added"),
+            Change::PropertyRemoved => write!(f, "property removed"),
+            Change::BecameRequired => write!(f, "became required"),
+            Change::NoLongerRequired => write!(f, "no longer required"),
+            Change::EnumChanged { added, removed } => {
+                match (added.is_empty(), removed.is_empty()) {
+                    (false, true) => write!(f, "enum gained {}", list(added)),
+                    (true, false) => write!(f, "enum lost {}", list(removed)),
+                    _ => write!(f, "enum gained {}, lost {}", list(added), list(removed)),
+                }
+            }
+            Change::KeywordChanged { before, after } => match (before, after) {
+                (Some(before), Some(after)) => write!(f, "{} -> {}", before, after),
+                (None, Some(after)) => write!(f, "added {}", after),
+                (Some(before), None) => write!(f, "removed (was {})", before),
+                (None, None) => write!(f, "unchanged"),
+            },
+        }
+    }
+}
+
+/// One difference between two schemas
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SchemaChange {
+    /// JSON pointer into the input schema
+    pub pointer: String,
+    #[serde(flatten)]
+    pub change: Change,
+    pub breaking: bool,
+}
+
+/// Every difference between input schemas `before` and `after`, in the
+/// order the keywords appear
+pub fn compare(before: &Value, after: &Value) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    compare_at("", before, after, &mut changes);
+    changes
+}
+
+fn compare_at(pointer: &str, before: &Value, after: &Value, out: &mut Vec<SchemaChange>) {
+    if before == after {
+        return;
+    }
+    let (Some(old), Some(new)) = (before.as_object(), after.as_object()) else {
+        // Boolean schemas, or something that isn't a schema at all
+        out.push(SchemaChange {
+            pointer: pointer.to_string(),
+            change: Change::KeywordChanged {
+                before: Some(before.clone()),
+                after: Some(after.clone()),
+            },
+            breaking: *after != Value::Bool(true),
+        });
+        return;
+    };
+    compare_properties(pointer, old, new, out);
+
+    let mut keys: Vec<&String> = old.keys().collect();
+    keys.extend(new.keys().filter(|k| !old.contains_key(*k)));
+    for key in keys {
+        let (before, after) = (old.get(key), new.get(key));
+        if before == after || key == "properties" || key == "required" {
+            continue;
+        }
+        let here = format!("{}/{}", pointer, escape(key));
+        match (key.as_str(), before, after) {
+            ("items", Some(before @ Value::Object(_)), Some(after @ Value::Object(_))) => {
+                compare_at(&here, before, after, out)
+            }
+            ("enum", Some(Value::Array(before)), Some(Value::Array(after))) => {
+                let added: Vec<Value> = after
+                    .iter()
+                    .filter(|v| !before.contains(v))
+                    .cloned()
+                    .collect();
+                let removed: Vec<Value> = before
+                    .iter()
+                    .filter(|v| !after.contains(v))
+                    .cloned()
+                    .collect();
+                if added.is_empty() && removed.is_empty() {
+                    // Only the order changed
+                    continue;
+                }
+                out.push(SchemaChange {
+                    pointer: here,
+                    breaking: !removed.is_empty(),
+                    change: Change::EnumChanged { added, removed },
+                });
+            }
+            _ => out.push(SchemaChange {
+                breaking: keyword_narrows(key, before, after),
+                pointer: here,
+                change: Change::KeywordChanged {
+                    before: before.cloned(),
+                    after: after.cloned(),
+                },
+            }),
+        }
+    }
+}
+
+/// Properties added, removed and changed, and changes to which are required
+fn compare_properties(
+    pointer: &str,
+    old: &Map<String, Value>,
+    new: &Map<String, Value>,
+    out: &mut Vec<SchemaChange>,
+) {
+    let empty = Map::new();
+    let properties = |schema: &'_ Map<String, Value>| {
+        schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .unwrap_or(&empty)
+            .clone()
+    };
+    let required = |schema: &Map<String, Value>| -> Vec<String> {
+        schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name.as_str().map(str::to_string))
+            .collect()
+    };
+    let (old_properties, new_properties) = (properties(old), properties(new));
+    let (old_required, new_required) = (required(old), required(new));
+    let closed = new.get("additionalProperties") == Some(&Value::Bool(false));
+    let at = |name: &str| format!("{}/properties/{}", pointer, escape(name));
+
+    for (name, before) in &old_properties {
+        match new_properties.get(name) {
+            Some(after) => compare_at(&at(name), before, after, out),
+            None => out.push(SchemaChange {
+                pointer: at(name),
+                change: Change::PropertyRemoved,
+                breaking: closed,
+            }),
+        }
+    }
+    for name in new_properties.keys() {
+        if !old_properties.contains_key(name) {
+            out.push(SchemaChange {
+                pointer: at(name),
+                change: Change::PropertyAdded,
+                breaking: false,
+            });
+        }
+    }
+    for name in &new_required {
+        if !old_required.contains(name) {
+            out.push(SchemaChange {
+                pointer: at(name),
+                change: Change::BecameRequired,
+                breaking: true,
+            });
+        }
+    }
+    for name in &old_required {
+        if !new_required.contains(name) {
+            out.push(SchemaChange {
+                pointer: at(name),
+                change: Change::NoLongerRequired,
+                breaking: false,
+            });
+        }
+    }
+}
+
+/// Whether changing `key` from `before` to `after` can reject arguments the
+/// old schema accepted
+fn keyword_narrows(key: &str, before: Option<&Value>, after: Option<&Value>) -> bool {
+    let Some(after) = after else {
+        // Dropping a constraint only widens
+        return false;
+    };
+    let number = |value: Option<&Value>| value.and_then(Value::as_f64);
+    match key {
+        "type" => before.is_none_or(|before| !type_widens(before, after)),
+        "additionalProperties" => match after {
+            Value::Bool(true) => false,
+            Value::Bool(false) => true,
+            // A schema in place of `false` only lets more through
+            _ => before != Some(&Value::Bool(false)),
+        },
+        "enum" => before.is_none(),
+        "items" => true,
+        _ if LOWER_BOUNDS.contains(&key) => match (number(before), number(Some(after))) {
+            (Some(before), Some(after)) => after > before,
+            _ => true,
+        },
+        _ if UPPER_BOUNDS.contains(&key) => match (number(before), number(Some(after))) {
+            (Some(before), Some(after)) => after < before,
+            _ => true,
+        },
+        _ => RESTRICTING.contains(&key),
+    }
+}
+
+/// Whether every type `before` allows is still allowed by `after`
+fn type_widens(before: &Value, after: &Value) -> bool {
+    let types = |value: &Value| -> Vec<String> {
+        match value {
+            Value::String(t) => vec![t.clone()],
+            Value::Array(ts) => ts
+                .iter()
+                .filter_map(|t| t.as_str().map(str::to_string))
+                .collect(),
+            _ => Vec::new(),
+        }
+    };
+    let after = types(after);
+    types(before)
+        .iter()
+        .all(|t| after.contains(t) || (t == "integer" && after.iter().any(|a| a == "number")))
+}
+
+/// A property name as a JSON pointer segment
+fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(properties: Value, required: &[&str]) -> Value {
+        json!({"type": "object", "properties": properties, "required": required})
+    }
+
+    #[test]
+    fn changes_are_classified_by_the_documented_rules() {
+        let base = || object(json!({"path": {"type": "string"}}), &["path"]);
+        let change = |pointer: &str, change: Change, breaking| SchemaChange {
+            pointer: pointer.to_string(),
+            change,
+            breaking,
+        };
+        let keyword =
+            |before: Option<Value>, after: Option<Value>| Change::KeywordChanged { before, after };
+        let cases: Vec<(&str, Value, Value, Vec<SchemaChange>)> = vec![
+            ("identical", base(), base(), vec![]),
+            (
+                "key order doesn't matter",
+                base(),
+                json!({"required": ["path"], "properties": {"path": {"type": "string"}}, "type": "object"}),
+                vec![],
+            ),
+            (
+                "optional property added",
+                base(),
+                object(
+                    json!({"path": {"type": "string"}, "lines": {"type": "integer"}}),
+                    &["path"],
+                ),
+                vec![change("/properties/lines", Change::PropertyAdded, false)],
+            ),
+            (
+                "required property added",
+                base(),
+                object(
+                    json!({"path": {"type": "string"}, "mode": {"type": "string"}}),
+                    &["path", "mode"],
+                ),
+                vec![
+                    change("/properties/mode", Change::PropertyAdded, false),
+                    change("/properties/mode", Change::BecameRequired, true),
+                ],
+            ),
+            (
+                "existing property made required",
+                object(json!({"path": {}, "force": {}}), &["path"]),
+                object(json!({"path": {}, "force": {}}), &["path", "force"]),
+                vec![change("/properties/force", Change::BecameRequired, true)],
+            ),
+            (
+                "required property removed",
+                base(),
+                object(json!({}), &[]),
+                vec![
+                    change("/properties/path", Change::PropertyRemoved, false),
+                    change("/properties/path", Change::NoLongerRequired, false),
+                ],
+            ),
+            (
+                "property removed from a closed object",
+                json!({"properties": {"a": {}, "b": {}}, "additionalProperties": false}),
+                json!({"properties": {"a": {}}, "additionalProperties": false}),
+                vec![change("/properties/b", Change::PropertyRemoved, true)],
+            ),
+            (
+                "object closed",
+                json!({"properties": {"a": {}}}),
+                json!({"properties": {"a": {}}, "additionalProperties": false}),
+                vec![change(
+                    "/additionalProperties",
+                    keyword(None, Some(json!(false))),
+                    true,
+                )],
+            ),
+            (
+                "object opened",
+                json!({"additionalProperties": false}),
+                json!({"additionalProperties": {"type": "string"}}),
+                vec![change(
+                    "/additionalProperties",
+                    keyword(Some(json!(false)), Some(json!({"type": "string"}))),
+                    false,
+                )],
+            ),
+            (
+                "type changed",
+                base(),
+                object(json!({"path": {"type": "array"}}), &["path"]),
+                vec![change(
+                    "/properties/path/type",
+                    keyword(Some(json!("string")), Some(json!("array"))),
+                    true,
+                )],
+            ),
+            (
+                "type widened",
+                json!({"type": "integer"}),
+                json!({"type": ["number", "null"]}),
+                vec![change(
+                    "/type",
+                    keyword(Some(json!("integer")), Some(json!(["number", "null"]))),
+                    false,
+                )],
+            ),
+            (
+                "type narrowed",
+                json!({"type": "number"}),
+                json!({"type": "integer"}),
+                vec![change(
+                    "/type",
+                    keyword(Some(json!("number")), Some(json!("integer"))),
+                    true,
+                )],
+            ),
+            (
+                "enum values added and removed",
+                json!({"enum": ["a", "b"]}),
+                json!({"enum": ["b", "c"]}),
+                vec![change(
+                    "/enum",
+                    Change::EnumChanged {
+                        added: vec![json!("c")],
+                        removed: vec![json!("a")],
+                    },
+                    true,
+                )],
+            ),
+            (
+                "enum values added",
+                json!({"enum": ["a"]}),
+                json!({"enum": ["a", "b"]}),
+                vec![change(
+                    "/enum",
+                    Change::EnumChanged {
+                        added: vec![json!("b")],
+                        removed: vec![],
+                    },
+                    false,
+                )],
+            ),
+            (
+                "enum reordered",
+                json!({"enum": ["a", "b"]}),
+                json!({"enum": ["b", "a"]}),
+                vec![],
+            ),
+            (
+                "enum added",
+                json!({"type": "string"}),
+                json!({"type": "string", "enum": ["a"]}),
+                vec![change("/enum", keyword(None, Some(json!(["a"]))), true)],
+            ),
+            (
+                "lower bound raised",
+                json!({"minimum": 1}),
+                json!({"minimum": 5}),
+                vec![change(
+                    "/minimum",
+                    keyword(Some(json!(1)), Some(json!(5))),
+                    true,
+                )],
+            ),
+            (
+                "upper bound raised",
+                json!({"maxLength": 10}),
+                json!({"maxLength": 20}),
+                vec![change(
+                    "/maxLength",
+                    keyword(Some(json!(10)), Some(json!(20))),
+                    false,
+                )],
+            ),
+            (
+                "upper bound added",
+                json!({}),
+                json!({"maxItems": 3}),
+                vec![change("/maxItems", keyword(None, Some(json!(3))), true)],
+            ),
+            (
+                "bound dropped",
+                json!({"minLength": 1}),
+                json!({}),
+                vec![change("/minLength", keyword(Some(json!(1)), None), false)],
+            ),
+            (
+                "pattern added",
+                json!({"type": "string"}),
+                json!({"type": "string", "pattern": "^a"}),
+                vec![change("/pattern", keyword(None, Some(json!("^a"))), true)],
+            ),
+            (
+                "description changed",
+                json!({"description": "old"}),
+                json!({"description": "new"}),
+                vec![change(
+                    "/description",
+                    keyword(Some(json!("old")), Some(json!("new"))),
+                    false,
+                )],
+            ),
+            (
+                "array items compared in place",
+                json!({"items": {"type": "string"}}),
+                json!({"items": {"type": "integer"}}),
+                vec![change(
+                    "/items/type",
+                    keyword(Some(json!("string")), Some(json!("integer"))),
+                    true,
+                )],
+            ),
+            (
+                "nested property, escaped",
+                object(json!({"a/b": object(json!({"x": {}}), &[])}), &[]),
+                object(json!({"a/b": object(json!({"x": {}}), &["x"])}), &[]),
+                vec![change(
+                    "/properties/a~1b/properties/x",
+                    Change::BecameRequired,
+                    true,
+                )],
+            ),
+            (
+                "boolean schema",
+                json!(true),
+                json!(false),
+                vec![change(
+                    "",
+                    keyword(Some(json!(true)), Some(json!(false))),
+                    true,
+                )],
+            ),
+        ];
+        for (name, before, after, expected) in cases {
+            assert_eq!(compare(&before, &after), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn changes_read_as_text_and_json() {
+        let changes = compare(
+            &json!({"enum": ["a"], "maximum": 3}),
+            &json!({"enum": ["b"]}),
+        );
+        let text: Vec<String> = changes.iter().map(|c| c.change.to_string()).collect();
+        assert_eq!(text, ["enum gained \"b\", lost \"a\"", "removed (was 3)"]);
+        assert_eq!(
+            serde_json::to_value(&changes[1]).unwrap(),
+            json!({"pointer": "/maximum", "kind": "keyword_changed", "before": 3, "after": null, "breaking": false})
+        );
+    }
+}
@@ -0,0 +1,673 @@
+//! `serve --enable-compose`: the built-in `mcpd__compose` tool, which runs a
+//! short fixed sequence of tool calls in one `use_tool` call.
+//!
+//! A pipeline is an ordered list of steps, each a tool and its arguments.
+//! String values in the arguments can refer to earlier steps with
+//! `{{steps.N.<path>}}`, where the path walks the step's record
+//! (`tool`, `arguments`, `result`) by `.key` and `[index]`, e.g.
+//! `{{steps.0.result.content[0].text}}`. A string that is just a reference
+//! becomes the referenced value; a reference inside a longer string is
+//! replaced by the value's text. There are no loops or conditionals: steps
+//! run in order and the first failure stops the pipeline.
+
+use crate::error_text;
+use crate::mcp::{CallToolResult, Content, Tool as McpTool};
+use crate::middleware::Outcome;
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::future::Future;
+use std::time::Duration;
+
+/// Name the tool is listed and called by
+pub const TOOL_NAME: &str = "mcpd__compose";
+
+/// Most steps a pipeline may have
+pub const MAX_STEPS: usize = 16;
+
+/// A pipeline, as given in `mcpd__compose`'s arguments
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    pub steps: Vec<Step>,
+    /// Timeout for steps that don't set their own
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// One tool call in a pipeline
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Tool name, as `use_tool` takes it
+    pub tool: String,
+    #[serde(default = "empty_object")]
+    pub arguments: Value,
+    /// How long the call may take. Inherits the pipeline's `timeout_ms`;
+    /// with neither, the call waits like any other.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+fn empty_object() -> Value {
+    json!({})
+}
+
+/// The `list_tools` entry for `mcpd__compose`
+pub fn tool() -> McpTool {
+    McpTool {
+        name: TOOL_NAME.to_string(),
+        title: None,
+        description: Some(format!(
+            "Run up to {} tool calls in order, stopping at the first failure. \
+             String arguments can use an earlier step's output with \
+             {{{{steps.N.result...}}}}, e.g. \"{{{{steps.0.result.content[0].text}}}}\". \
+             Returns every step's result as structuredContent and their text as a summary.",
+            MAX_STEPS
+        )),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "steps": {
+                    "type": "array",
+                    "minItems": 1,
+                    "maxItems": MAX_STEPS,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "tool": {"type": "string", "description": "Tool name, as use_tool takes it"},
+                            "arguments": {"type": "object"},
+                            "timeout_ms": {"type": "integer", "minimum": 1}
+                        },
+                        "required": ["tool"]
+                    }
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Timeout for steps that don't set their own"
+                }
+            },
+            "required": ["steps"]
+        }),
+        annotations: None,
+    }
+}
+
+impl Pipeline {
+    /// Read and check a pipeline: its size, and that every reference is
+    /// well-formed and points at an earlier step. Nothing runs if this fails.
+    pub fn parse(arguments: &Value) -> Result<Self> {
+        let pipeline: Pipeline = serde_json::from_value(arguments.clone())?;
+        if pipeline.steps.is_empty() {
+            bail!("A pipeline needs at least one step");
+        }
+        if pipeline.steps.len() > MAX_STEPS {
+            bail!(
+                "A pipeline has at most {} steps, this one has {}",
+                MAX_STEPS,
+                pipeline.steps.len()
+            );
+        }
+        for (index, step) in pipeline.steps.iter().enumerate() {
+            if step.tool == TOOL_NAME {
+                bail!("Step {}: {} can't be a step", index, TOOL_NAME);
+            }
+            visit_strings(&step.arguments, &mut |text| {
+                for reference in references(text) {
+                    let reference = reference?;
+                    if reference.step >= index {
+                        bail!(
+                            "Step {}: '{}' refers to a step that hasn't run yet",
+                            index,
+                            reference.text
+                        );
+                    }
+                }
+                Ok(())
+            })?;
+        }
+        Ok(pipeline)
+    }
+
+    /// How long `step` may take
+    pub fn step_timeout(&self, step: &Step) -> Option<Duration> {
+        step.timeout_ms
+            .or(self.timeout_ms)
+            .map(Duration::from_millis)
+    }
+
+    /// Run the steps in order through `call`, which makes one tool call the
+    /// way `use_tool` would. Stops at the first step that can't be resolved,
+    /// fails, returns an error result or times out.
+    pub async fn run<F, Fut>(&self, mut call: F) -> CallToolResult
+    where
+        F: FnMut(String, Value) -> Fut,
+        Fut: Future<Output = Outcome>,
+    {
+        let mut records: Vec<Value> = Vec::new();
+        let mut summary = Vec::new();
+        let mut failure = None;
+        for (index, step) in self.steps.iter().enumerate() {
+            let arguments = match resolve(&step.arguments, &records) {
+                Ok(arguments) => arguments,
+                Err(e) => {
+                    failure = Some((index, format!("{:#}", e)));
+                    break;
+                }
+            };
+            let outcome = match self.step_timeout(step) {
+                Some(limit) => {
+                    tokio::time::timeout(limit, call(step.tool.clone(), arguments.clone()))
+                        .await
+                        .unwrap_or_else(|_| Err(format!("Timed out after {}ms", limit.as_millis())))
+                }
+                None => call(step.tool.clone(), arguments.clone()).await,
+            };
+            let mut record = json!({"tool": step.tool, "arguments": arguments});
+            match outcome {
+                Ok(result) => {
+                    let text = result_text(&result);
+                    let is_error = result.is_error;
+                    record["result"] = serde_json::to_value(&result).unwrap_or(Value::Null);
+                    records.push(record);
+                    if is_error {
+                        failure = Some((index, text));
+                        break;
+                    }
+                    summary.push(format!("Step {} ({}): {}", index, step.tool, text));
+                }
+                Err(message) => {
+                    record["error"] = Value::String(message.clone());
+                    records.push(record);
+                    failure = Some((index, message));
+                    break;
+                }
+            }
+        }
+
+        let mut structured = json!({ "steps": records });
+        let mut result = match failure {
+            Some((index, message)) => {
+                structured["failed_step"] = json!(index);
+                summary.push(format!(
+                    "Step {} ({}) failed: {}",
+                    index, self.steps[index].tool, message
+                ));
+                summary.insert(
+                    0,
+                    format!(
+                        "Pipeline stopped at step {} of {}.",
+                        index,
+                        self.steps.len()
+                    ),
+                );
+                error_text::tool_error(summary.join("\n"))
+            }
+            None => CallToolResult {
+                content: vec![Content::Text {
+                    text: summary.join("\n"),
+                }],
+                is_error: false,
+                structured_content: None,
+                meta: None,
+            },
+        };
+        result.structured_content = Some(structured);
+        result
+    }
+}
+
+/// A result's text content, or its `structuredContent` as JSON if it has none
+fn result_text(result: &CallToolResult) -> String {
+    let texts: Vec<&str> = result
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            Content::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    match &result.structured_content {
+        Some(structured) if texts.is_empty() => structured.to_string(),
+        _ => texts.join("\n"),
+    }
+}
+
+/// Call `f` on every string in `value`, stopping at the first error
+fn visit_strings(value: &Value, f: &mut impl FnMut(&str) -> Result<()>) -> Result<()> {
+    match value {
+        Value::String(text) => f(text),
+        Value::Array(items) => items.iter().try_for_each(|item| visit_strings(item, f)),
+        Value::Object(map) => map.values().try_for_each(|item| visit_strings(item, f)),
+        _ => Ok(()),
+    }
+}
+
+/// One step in a reference's path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A parsed `{{steps.N...}}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Reference {
+    /// The whole reference, braces included, for messages
+    text: String,
+    /// Byte range of `text` in the string it came from
+    span: std::ops::Range<usize>,
+    step: usize,
+    path: Vec<Segment>,
+}
+
+/// The references in `text`. `{{` not followed by `steps.` is left as text,
+/// so arguments that are templates themselves pass through.
+fn references(text: &str) -> Vec<Result<Reference>> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(offset) = text[from..].find("{{") {
+        let start = from + offset;
+        let inner = &text[start + 2..];
+        if !inner.trim_start().starts_with("steps.") {
+            from = start + 2;
+            continue;
+        }
+        let Some(len) = inner.find("}}") else {
+            found.push(Err(anyhow!("Unclosed reference '{}'", &text[start..])));
+            break;
+        };
+        let end = start + 2 + len + 2;
+        found.push(parse_reference(&text[start..end], start..end));
+        from = end;
+    }
+    found
+}
+
+fn parse_reference(text: &str, span: std::ops::Range<usize>) -> Result<Reference> {
+    let invalid = || anyhow!("Invalid reference '{}'", text);
+    let body = text[2..text.len() - 2].trim();
+    let rest = body.strip_prefix("steps.").ok_or_else(invalid)?;
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let step = rest[..digits].parse().map_err(|_| invalid())?;
+    let mut rest = &rest[digits..];
+    let mut path = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let len = after.find(['.', '[']).unwrap_or(after.len());
+            if len == 0 {
+                return Err(invalid());
+            }
+            path.push(Segment::Key(after[..len].to_string()));
+            rest = &after[len..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let len = after.find(']').ok_or_else(invalid)?;
+            let index = after[..len].parse().map_err(|_| invalid())?;
+            path.push(Segment::Index(index));
+            rest = &after[len + 1..];
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(Reference {
+        text: text.to_string(),
+        span,
+        step,
+        path,
+    })
+}
+
+/// What `reference` points at in the records of the steps run so far
+fn lookup<'a>(reference: &Reference, records: &'a [Value]) -> Result<&'a Value> {
+    let mut value = records
+        .get(reference.step)
+        .with_context(|| format!("'{}' refers to a step that hasn't run", reference.text))?;
+    for segment in &reference.path {
+        value = match segment {
+            Segment::Key(key) => value.get(key.as_str()),
+            Segment::Index(index) => value.get(*index),
+        }
+        .with_context(|| {
+            format!(
+                "'{}' matches nothing in step {}'s output",
+                reference.text, reference.step
+            )
+        })?;
+    }
+    Ok(value)
+}
+
+/// `arguments` with every reference replaced from `records`
+fn resolve(arguments: &Value, records: &[Value]) -> Result<Value> {
+    Ok(match arguments {
+        Value::String(text) => resolve_string(text, records)?,
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| resolve(item, records))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| Ok((key.clone(), resolve(item, records)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+fn resolve_string(text: &str, records: &[Value]) -> Result<Value> {
+    let references = references(text).into_iter().collect::<Result<Vec<_>>>()?;
+    // A string that is only a reference takes the value's type
+    if let [reference] = &references[..]
+        && reference.span == (0..text.len())
+    {
+        return Ok(lookup(reference, records)?.clone());
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut from = 0;
+    for reference in &references {
+        out.push_str(&text[from..reference.span.start]);
+        match lookup(reference, records)? {
+            Value::String(value) => out.push_str(value),
+            value => out.push_str(&value.to_string()),
+        }
+        from = reference.span.end;
+    }
+    out.push_str(&text[from..]);
+    Ok(Value::String(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn text_result(text: &str) -> CallToolResult {
+        CallToolResult {
+            content: vec![Content::Text {
+                text: text.to_string(),
+            }],
+            is_error: false,
+            structured_content: None,
+            meta: None,
+        }
+    }
+
+    fn records() -> Vec<Value> {
+        vec![json!({
+            "tool": "gh__get_issue",
+            "arguments": {"number": 7},
+            "result": {
+                "content": [{"type": "text", "text": "Crash on start"}],
+                "structuredContent": {"issue": {"number": 7, "labels": ["bug", "p1"]}}
+            }
+        })]
+    }
+
+    /// Steps' tool names and arguments as `run` made them, each answered by
+    /// `answer`
+    async fn run_recorded(
+        pipeline: &Pipeline,
+        answer: impl Fn(&str, &Value) -> Outcome,
+    ) -> (CallToolResult, Vec<(String, Value)>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let result = pipeline
+            .run(|tool, arguments| {
+                let outcome = answer(&tool, &arguments);
+                calls.lock().unwrap().push((tool, arguments));
+                async move { outcome }
+            })
+            .await;
+        let calls = calls.lock().unwrap().clone();
+        (result, calls)
+    }
+
+    fn pipeline(value: Value) -> Pipeline {
+        Pipeline::parse(&value).unwrap()
+    }
+
+    fn parse_error(value: Value) -> String {
+        format!("{:#}", Pipeline::parse(&value).unwrap_err())
+    }
+
+    #[test]
+    fn whole_string_references_keep_the_value_type() {
+        let resolved = resolve(
+            &json!({
+                "number": "{{steps.0.arguments.number}}",
+                "labels": "{{ steps.0.result.structuredContent.issue.labels }}",
+                "title": "{{steps.0.result.content[0].text}}"
+            }),
+            &records(),
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            json!({"number": 7, "labels": ["bug", "p1"], "title": "Crash on start"})
+        );
+    }
+
+    #[test]
+    fn embedded_references_are_replaced_by_text() {
+        let resolved = resolve(
+            &json!({
+                "body": "Re #{{steps.0.arguments.number}}: {{steps.0.result.content[0].text}}",
+                "nested": ["labels {{steps.0.result.structuredContent.issue.labels}}"],
+            }),
+            &records(),
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            json!({
+                "body": "Re #7: Crash on start",
+                "nested": ["labels [\"bug\",\"p1\"]"],
+            })
+        );
+    }
+
+    #[test]
+    fn other_braces_are_left_alone() {
+        let arguments = json!({"template": "Hello {{name}}", "empty": "{{}}", "n": 1});
+        assert_eq!(resolve(&arguments, &records()).unwrap(), arguments);
+    }
+
+    #[test]
+    fn references_that_match_nothing_fail() {
+        for reference in [
+            "{{steps.0.result.content[3].text}}",
+            "{{steps.0.result.missing}}",
+            "{{steps.0.arguments.number.deeper}}",
+            "{{steps.1.result}}",
+        ] {
+            let e = resolve(&json!({ "x": reference }), &records()).unwrap_err();
+            assert!(e.to_string().contains(reference), "{}", e);
+        }
+    }
+
+    #[test]
+    fn malformed_references_fail() {
+        for reference in [
+            "{{steps.x.result}}",
+            "{{steps.0.result..text}}",
+            "{{steps.0.result[one]}}",
+            "{{steps.0.result[0}}",
+            "{{steps.0result}}",
+            "{{steps.0.result",
+        ] {
+            assert!(
+                resolve(&json!(reference), &records()).is_err(),
+                "{}",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn parse_checks_references_and_size_before_running() {
+        let step = json!({"tool": "a__b"});
+        assert!(parse_error(json!({"steps": []})).contains("at least one step"));
+        assert!(
+            parse_error(json!({ "steps": vec![step.clone(); MAX_STEPS + 1] }))
+                .contains("at most 16 steps")
+        );
+        assert!(
+            parse_error(
+                json!({"steps": [{"tool": "a__b", "arguments": {"x": "{{steps.0.result}}"}}]})
+            )
+            .contains("hasn't run yet")
+        );
+        assert!(
+            parse_error(
+                json!({"steps": [step, {"tool": "a__b", "arguments": {"x": "{{steps.2.result}}"}}]})
+            )
+            .contains("Step 1")
+        );
+        assert!(
+            parse_error(json!({"steps": [{"tool": "a__b", "arguments": {"x": "{{steps.0.}}"}}]}))
+                .contains("Invalid reference")
+        );
+        assert!(parse_error(json!({"steps": [{"tool": TOOL_NAME}]})).contains("can't be a step"));
+        assert!(parse_error(json!({"steps": [{"tool": "a__b", "loop": true}]})).contains("loop"));
+        assert!(parse_error(json!({"steps": [{"tool": "a__b"}], "if": "x"})).contains("if"));
+    }
+
+    #[tokio::test]
+    async fn steps_run_in_order_with_earlier_outputs() {
+        let pipeline = pipeline(json!({"steps": [
+            {"tool": "gh__get_issue", "arguments": {"number": 7}},
+            {"tool": "gh__add_comment", "arguments": {
+                "number": "{{steps.0.arguments.number}}",
+                "body": "Seen: {{steps.0.result.content[0].text}}"
+            }},
+        ]}));
+        let (result, calls) = run_recorded(&pipeline, |tool, _| match tool {
+            "gh__get_issue" => Ok(text_result("Crash on start")),
+            _ => Ok(text_result("commented")),
+        })
+        .await;
+        assert_eq!(
+            calls,
+            [
+                ("gh__get_issue".to_string(), json!({"number": 7})),
+                (
+                    "gh__add_comment".to_string(),
+                    json!({"number": 7, "body": "Seen: Crash on start"})
+                ),
+            ]
+        );
+        assert!(!result.is_error);
+        let structured = result.structured_content.as_ref().unwrap();
+        assert_eq!(structured["steps"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            structured["steps"][1]["result"]["content"][0]["text"],
+            "commented"
+        );
+        assert!(structured.get("failed_step").is_none());
+        assert_eq!(
+            result_text(&result),
+            "Step 0 (gh__get_issue): Crash on start\nStep 1 (gh__add_comment): commented"
+        );
+    }
+
+    #[tokio::test]
+    async fn an_error_result_stops_the_pipeline() {
+        let pipeline = pipeline(json!({"steps": [
+            {"tool": "a__one"}, {"tool": "a__two"}, {"tool": "a__three"}
+        ]}));
+        let (result, calls) = run_recorded(&pipeline, |tool, _| match tool {
+            "a__two" => Ok(error_text::tool_error("no such issue")),
+            _ => Ok(text_result("ok")),
+        })
+        .await;
+        assert_eq!(calls.len(), 2);
+        assert!(result.is_error);
+        let structured = result.structured_content.as_ref().unwrap();
+        assert_eq!(structured["failed_step"], 1);
+        // The failed step's result is kept with the ones before it
+        assert_eq!(structured["steps"].as_array().unwrap().len(), 2);
+        assert_eq!(structured["steps"][1]["result"]["is_error"], true);
+        assert_eq!(
+            result_text(&result),
+            "Pipeline stopped at step 1 of 3.\nStep 0 (a__one): ok\nStep 1 (a__two) failed: no such issue"
+        );
+    }
+
+    #[tokio::test]
+    async fn each_step_is_checked_on_its_own() {
+        // The call function stands in for use_tool routing, where middleware
+        // such as --read-only and the concurrency limit see each step
+        let pipeline = pipeline(json!({"steps": [
+            {"tool": "fs__read_file"}, {"tool": "fs__delete_file"}, {"tool": "fs__read_file"}
+        ]}));
+        let (result, calls) = run_recorded(&pipeline, |tool, _| {
+            if tool.contains("delete") {
+                Err("Tool 'fs__delete_file' is blocked in read-only mode".to_string())
+            } else {
+                Ok(text_result("contents"))
+            }
+        })
+        .await;
+        assert_eq!(calls.len(), 2);
+        assert!(result.is_error);
+        let structured = result.structured_content.unwrap();
+        assert_eq!(structured["failed_step"], 1);
+        assert_eq!(
+            structured["steps"][1]["error"],
+            "Tool 'fs__delete_file' is blocked in read-only mode"
+        );
+        assert!(structured["steps"][1].get("result").is_none());
+    }
+
+    #[tokio::test]
+    async fn a_reference_to_missing_output_stops_before_calling() {
+        let pipeline = pipeline(json!({"steps": [
+            {"tool": "a__one"},
+            {"tool": "a__two", "arguments": {"x": "{{steps.0.result.content[5].text}}"}}
+        ]}));
+        let (result, calls) = run_recorded(&pipeline, |_, _| Ok(text_result("ok"))).await;
+        assert_eq!(calls.len(), 1);
+        let structured = result.structured_content.unwrap();
+        assert_eq!(structured["failed_step"], 1);
+        assert_eq!(structured["steps"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn steps_inherit_the_pipeline_timeout() {
+        let pipeline = pipeline(json!({"timeout_ms": 500, "steps": [
+            {"tool": "a__one"}, {"tool": "a__two", "timeout_ms": 50}
+        ]}));
+        assert_eq!(
+            pipeline.step_timeout(&pipeline.steps[0]),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            pipeline.step_timeout(&pipeline.steps[1]),
+            Some(Duration::from_millis(50))
+        );
+        let untimed = self::pipeline(json!({"steps": [{"tool": "a__one"}]}));
+        assert_eq!(untimed.step_timeout(&untimed.steps[0]), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_step_that_runs_too_long_fails() {
+        let pipeline = pipeline(json!({"timeout_ms": 1000, "steps": [
+            {"tool": "a__fast", "timeout_ms": 100}, {"tool": "a__slow"}, {"tool": "a__never"}
+        ]}));
+        let result = pipeline
+            .run(|tool, _| async move {
+                let wait = if tool == "a__fast" { 50 } else { 5000 };
+                tokio::time::sleep(Duration::from_millis(wait)).await;
+                Ok(text_result(&tool))
+            })
+            .await;
+        let structured = result.structured_content.as_ref().unwrap();
+        assert_eq!(structured["failed_step"], 1);
+        assert_eq!(structured["steps"][1]["error"], "Timed out after 1000ms");
+        assert_eq!(structured["steps"].as_array().unwrap().len(), 2);
+    }
+}
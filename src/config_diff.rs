@@ -0,0 +1,534 @@
+//! `mcpd diff-config`: how the tools clients see change between two
+//! configurations.
+//!
+//! Each side is a catalog file (`mcpd export-catalog`) or a registry file
+//! (`registry.json` format), whose backends are started and listed the way
+//! `export-catalog` lists them. Tools are matched by the name clients call
+//! them by. A removed tool and an added one with the same input schema, where
+//! no other removed or added tool shares it, are reported as a rename. Schema
+//! changes are classified by `compat`; removals and renames count as
+//! breaking too, since calls to the old name stop working.
+
+use crate::canonical::{self, Nulls};
+use crate::compat::{self, SchemaChange};
+use crate::naming::NameStyle;
+use crate::offline::{CatalogFile, ExportedTool};
+use crate::output::{Color, Output};
+use crate::registry::Registry;
+use crate::server::{ServeOptions, Server};
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// The tools of the configuration in `path`: read from a catalog file, or
+/// listed from the backends of a registry file
+pub async fn load(path: &Path, timeout: Duration, name_style: NameStyle) -> Result<CatalogFile> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let value: Value = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    if value.get("backends").is_some() {
+        return CatalogFile::load(path);
+    }
+    if value.get("tools").is_none() {
+        bail!(
+            "{} is neither an mcpd catalog nor a registry file",
+            path.display()
+        );
+    }
+    let registry = Registry::from_json(&json)
+        .with_context(|| format!("Invalid registry in {}", path.display()))?;
+    let options = ServeOptions {
+        list_timeout: Some(timeout),
+        name_style,
+        ..Default::default()
+    };
+    let server = Server::with_options(registry, options);
+    let exported = server.export_catalog().await;
+    server.stop_all().await;
+    let (catalog, failures) = exported?;
+    if let Some(failure) = failures.first() {
+        bail!(
+            "'{}' from {} failed to list tools: {}",
+            failure.backend,
+            path.display(),
+            failure.error
+        );
+    }
+    Ok(catalog)
+}
+
+/// A tool by the name clients call it, and its backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ToolRef {
+    pub name: String,
+    pub backend: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Renamed {
+    pub before: ToolRef,
+    pub after: ToolRef,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DescriptionChange {
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A tool under the same name on both sides that isn't the same
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Changed {
+    pub name: String,
+    pub backend: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<DescriptionChange>,
+    pub schema: Vec<SchemaChange>,
+    pub breaking: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendStatus {
+    Added,
+    Removed,
+    Kept,
+}
+
+/// What changed for one backend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackendSummary {
+    pub name: String,
+    pub status: BackendStatus,
+    pub tools_before: usize,
+    pub tools_after: usize,
+    pub added: usize,
+    pub removed: usize,
+    /// Renames away from or into this backend
+    pub renamed: usize,
+    pub changed: usize,
+    pub breaking: bool,
+}
+
+impl BackendSummary {
+    pub fn is_unchanged(&self) -> bool {
+        self.status == BackendStatus::Kept
+            && self.added + self.removed + self.renamed + self.changed == 0
+    }
+}
+
+/// How the tool surface changed from `before` to `after`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigDiff {
+    pub added: Vec<ToolRef>,
+    pub removed: Vec<ToolRef>,
+    pub renamed: Vec<Renamed>,
+    pub changed: Vec<Changed>,
+    /// In `before`'s backend order, then backends new in `after`
+    pub backends: Vec<BackendSummary>,
+    pub breaking: bool,
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+/// Every exposed tool with its backend, in catalog order
+fn tools(catalog: &CatalogFile) -> Vec<(&str, &ExportedTool)> {
+    catalog
+        .backends
+        .iter()
+        .flat_map(|b| b.tools.iter().map(move |t| (b.name.as_str(), t)))
+        .collect()
+}
+
+pub fn diff(before: &CatalogFile, after: &CatalogFile) -> ConfigDiff {
+    let old = tools(before);
+    let new = tools(after);
+    let old_names: HashMap<&str, (&str, &ExportedTool)> = old
+        .iter()
+        .map(|&(b, t)| (t.name.as_str(), (b, t)))
+        .collect();
+    let new_names: HashMap<&str, (&str, &ExportedTool)> = new
+        .iter()
+        .map(|&(b, t)| (t.name.as_str(), (b, t)))
+        .collect();
+    let tool_ref = |backend: &str, tool: &ExportedTool| ToolRef {
+        name: tool.name.clone(),
+        backend: backend.to_string(),
+    };
+
+    let mut removed: Vec<ToolRef> = old
+        .iter()
+        .filter(|(_, t)| !new_names.contains_key(t.name.as_str()))
+        .map(|&(b, t)| tool_ref(b, t))
+        .collect();
+    let mut added: Vec<ToolRef> = new
+        .iter()
+        .filter(|(_, t)| !old_names.contains_key(t.name.as_str()))
+        .map(|&(b, t)| tool_ref(b, t))
+        .collect();
+
+    // Renames: schemas that one removed and one added tool have, and no other
+    let schema_hash = |names: &HashMap<&str, (&str, &ExportedTool)>, tool: &ToolRef| {
+        canonical::hash(&names[tool.name.as_str()].1.tool.input_schema, Nulls::Keep)
+    };
+    let count = |hashes: &[String], hash: &String| hashes.iter().filter(|h| *h == hash).count();
+    let removed_hashes: Vec<String> = removed.iter().map(|t| schema_hash(&old_names, t)).collect();
+    let added_hashes: Vec<String> = added.iter().map(|t| schema_hash(&new_names, t)).collect();
+    let mut renamed = Vec::new();
+    for (i, hash) in removed_hashes.iter().enumerate() {
+        if count(&removed_hashes, hash) != 1 || count(&added_hashes, hash) != 1 {
+            continue;
+        }
+        let j = added_hashes.iter().position(|h| h == hash).unwrap();
+        renamed.push(Renamed {
+            before: removed[i].clone(),
+            after: added[j].clone(),
+        });
+    }
+    removed.retain(|t| !renamed.iter().any(|r| r.before == *t));
+    added.retain(|t| !renamed.iter().any(|r| r.after == *t));
+
+    let mut changed = Vec::new();
+    for &(_, entry) in &old {
+        let Some(&(backend, new_entry)) = new_names.get(entry.name.as_str()) else {
+            continue;
+        };
+        let (old_tool, new_tool) = (&entry.tool, &new_entry.tool);
+        let description =
+            (old_tool.description != new_tool.description).then(|| DescriptionChange {
+                before: old_tool.description.clone(),
+                after: new_tool.description.clone(),
+            });
+        let schema = compat::compare(&old_tool.input_schema, &new_tool.input_schema);
+        if description.is_none() && schema.is_empty() {
+            continue;
+        }
+        changed.push(Changed {
+            name: entry.name.clone(),
+            backend: backend.to_string(),
+            description,
+            breaking: schema.iter().any(|c| c.breaking),
+            schema,
+        });
+    }
+
+    let mut names: Vec<&str> = before.backends.iter().map(|b| b.name.as_str()).collect();
+    names.extend(
+        after
+            .backends
+            .iter()
+            .map(|b| b.name.as_str())
+            .filter(|n| !before.backends.iter().any(|b| b.name == *n)),
+    );
+    let backends = names
+        .into_iter()
+        .map(|name| {
+            let find = |catalog: &CatalogFile| {
+                catalog
+                    .backends
+                    .iter()
+                    .find(|b| b.name == name)
+                    .map(|b| b.tools.len())
+            };
+            let (tools_before, tools_after) = (find(before), find(after));
+            let ours = |t: &ToolRef| t.backend == name;
+            let added = added.iter().filter(|t| ours(t)).count();
+            let removed = removed.iter().filter(|t| ours(t)).count();
+            let renamed = renamed
+                .iter()
+                .filter(|r| ours(&r.before) || ours(&r.after))
+                .count();
+            let changed_here: Vec<&Changed> =
+                changed.iter().filter(|c| c.backend == name).collect();
+            BackendSummary {
+                name: name.to_string(),
+                status: match (tools_before, tools_after) {
+                    (None, _) => BackendStatus::Added,
+                    (_, None) => BackendStatus::Removed,
+                    _ => BackendStatus::Kept,
+                },
+                tools_before: tools_before.unwrap_or(0),
+                tools_after: tools_after.unwrap_or(0),
+                added,
+                removed,
+                renamed,
+                changed: changed_here.len(),
+                breaking: removed > 0 || renamed > 0 || changed_here.iter().any(|c| c.breaking),
+            }
+        })
+        .collect();
+
+    let breaking = !removed.is_empty() || !renamed.is_empty() || changed.iter().any(|c| c.breaking);
+    ConfigDiff {
+        added,
+        removed,
+        renamed,
+        changed,
+        backends,
+        breaking,
+    }
+}
+
+/// Human-readable report for `mcpd diff-config`, by backend
+pub fn render(diff: &ConfigDiff, out: &Output) -> String {
+    let mut text = String::new();
+    if diff.is_empty() {
+        text.push_str("No changes to the tool surface\n");
+        return text;
+    }
+    let breaking = |yes: bool| {
+        if yes {
+            format!(" {}", out.paint("(breaking)", Color::Red))
+        } else {
+            String::new()
+        }
+    };
+    for backend in diff.backends.iter().filter(|b| !b.is_unchanged()) {
+        let name = out.paint(&backend.name, Color::Bold);
+        let _ = match backend.status {
+            BackendStatus::Added => writeln!(text, "{}: added", name),
+            BackendStatus::Removed => writeln!(text, "{}: removed", name),
+            BackendStatus::Kept => writeln!(
+                text,
+                "{}: {} -> {} tools",
+                name, backend.tools_before, backend.tools_after
+            ),
+        };
+        let ours = |t: &ToolRef| t.backend == backend.name;
+        for tool in diff.added.iter().filter(|t| ours(t)) {
+            let _ = writeln!(
+                text,
+                "  {}",
+                out.paint(&format!("+ {}", tool.name), Color::Green)
+            );
+        }
+        for tool in diff.removed.iter().filter(|t| ours(t)) {
+            let line = out.paint(&format!("- {}", tool.name), Color::Red);
+            let _ = writeln!(text, "  {}{}", line, breaking(true));
+        }
+        for rename in diff.renamed.iter().filter(|r| ours(&r.after)) {
+            let line = format!("> {} -> {}", rename.before.name, rename.after.name);
+            let _ = writeln!(
+                text,
+                "  {}{}",
+                out.paint(&line, Color::Yellow),
+                breaking(true)
+            );
+        }
+        for tool in diff.changed.iter().filter(|c| c.backend == backend.name) {
+            let line = out.paint(&format!("~ {}", tool.name), Color::Yellow);
+            let _ = writeln!(text, "  {}{}", line, breaking(tool.breaking));
+            if let Some(description) = &tool.description {
+                let show = |d: &Option<String>| match d {
+                    Some(d) => format!("{:?}", d),
+                    None => "none".to_string(),
+                };
+                let _ = writeln!(
+                    text,
+                    "      description: {} -> {}",
+                    show(&description.before),
+                    show(&description.after)
+                );
+            }
+            for change in &tool.schema {
+                let pointer = if change.pointer.is_empty() {
+                    "(schema)"
+                } else {
+                    &change.pointer
+                };
+                let _ = writeln!(
+                    text,
+                    "      {}: {}{}",
+                    pointer,
+                    change.change,
+                    breaking(change.breaking)
+                );
+            }
+        }
+    }
+    let _ = writeln!(
+        text,
+        "\n{} added, {} removed, {} renamed, {} changed",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.renamed.len(),
+        diff.changed.len()
+    );
+    if diff.breaking {
+        let _ = writeln!(text, "{}", out.paint("Breaking changes", Color::Red));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::Tool as McpTool;
+    use crate::offline::Backend;
+    use serde_json::json;
+
+    fn tool(name: &str, description: &str, schema: Value) -> (String, McpTool) {
+        let own = name.split_once("__").unwrap().1;
+        (
+            name.to_string(),
+            McpTool {
+                name: own.to_string(),
+                title: None,
+                description: Some(description.to_string()),
+                input_schema: schema,
+                annotations: None,
+            },
+        )
+    }
+
+    fn catalog(backends: Vec<(&str, Vec<(String, McpTool)>)>) -> CatalogFile {
+        CatalogFile::new(
+            backends
+                .into_iter()
+                .map(|(name, tools)| Backend::new(name.to_string(), tools, Vec::new(), Vec::new()))
+                .collect(),
+        )
+    }
+
+    fn path_schema(required: &[&str]) -> Value {
+        json!({
+            "type": "object",
+            "properties": {"path": {"type": "string"}, "mode": {"type": "string"}},
+            "required": required
+        })
+    }
+
+    #[test]
+    fn reports_each_kind_of_change_by_backend() {
+        let before = catalog(vec![
+            (
+                "fs",
+                vec![
+                    tool("fs__read", "Read a file", path_schema(&["path"])),
+                    tool("fs__stat", "Stat a file", json!({"type": "object"})),
+                    tool("fs__search", "Search", json!({"properties": {"q": {}}})),
+                ],
+            ),
+            (
+                "git",
+                vec![tool("git__log", "Log", json!({"type": "object"}))],
+            ),
+        ]);
+        let after = catalog(vec![
+            (
+                "fs",
+                vec![
+                    tool(
+                        "fs__read",
+                        "Read a text file",
+                        path_schema(&["path", "mode"]),
+                    ),
+                    tool("fs__find", "Find", json!({"properties": {"q": {}}})),
+                    tool("fs__write", "Write a file", json!({"type": "object"})),
+                ],
+            ),
+            (
+                "web",
+                vec![tool("web__fetch", "Fetch", json!({"type": "object"}))],
+            ),
+        ]);
+        let diff = diff(&before, &after);
+        let r = |name: &str, backend: &str| ToolRef {
+            name: name.to_string(),
+            backend: backend.to_string(),
+        };
+        // Three tools share `{"type": "object"}`, so none of those pair up
+        assert_eq!(
+            diff.renamed,
+            [Renamed {
+                before: r("fs__search", "fs"),
+                after: r("fs__find", "fs"),
+            }]
+        );
+        assert_eq!(diff.removed, [r("fs__stat", "fs"), r("git__log", "git")]);
+        assert_eq!(diff.added, [r("fs__write", "fs"), r("web__fetch", "web")]);
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.changed[0].breaking);
+        assert!(diff.breaking);
+
+        let statuses: Vec<(&str, BackendStatus, bool)> = diff
+            .backends
+            .iter()
+            .map(|b| (b.name.as_str(), b.status, b.breaking))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("fs", BackendStatus::Kept, true),
+                ("git", BackendStatus::Removed, true),
+                ("web", BackendStatus::Added, false),
+            ]
+        );
+
+        assert_eq!(
+            render(&diff, &Output::plain()),
+            concat!(
+                "fs: 3 -> 3 tools\n",
+                "  + fs__write\n",
+                "  - fs__stat (breaking)\n",
+                "  > fs__search -> fs__find (breaking)\n",
+                "  ~ fs__read (breaking)\n",
+                "      description: \"Read a file\" -> \"Read a text file\"\n",
+                "      /properties/mode: became required (breaking)\n",
+                "git: removed\n",
+                "  - git__log (breaking)\n",
+                "web: added\n",
+                "  + web__fetch\n",
+                "\n",
+                "2 added, 2 removed, 1 renamed, 1 changed\n",
+                "Breaking changes\n",
+            )
+        );
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(
+            json["changed"][0]["schema"][0],
+            json!({"pointer": "/properties/mode", "kind": "became_required", "breaking": true})
+        );
+        assert_eq!(json["backends"][2]["status"], "added");
+    }
+
+    #[test]
+    fn widening_changes_are_not_breaking() {
+        let before = catalog(vec![(
+            "fs",
+            vec![tool("fs__read", "Read", path_schema(&["path", "mode"]))],
+        )]);
+        let after = catalog(vec![(
+            "fs",
+            vec![
+                tool("fs__read", "Read", path_schema(&["path"])),
+                tool("fs__write", "Write", json!({"type": "object"})),
+            ],
+        )]);
+        let diff = diff(&before, &after);
+        assert!(!diff.breaking);
+        assert_eq!(diff.changed[0].schema.len(), 1);
+        assert!(!diff.backends[0].breaking);
+
+        let same = super::diff(&before, &before);
+        assert!(same.is_empty());
+        assert!(same.backends[0].is_unchanged());
+        assert_eq!(
+            render(&same, &Output::plain()),
+            "No changes to the tool surface\n"
+        );
+    }
+}
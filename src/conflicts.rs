@@ -0,0 +1,258 @@
+//! Finding tools that more than one backend exposes under the same name.
+//!
+//! mcpd always namespaces tools as `server__tool`, so overlaps never break
+//! routing, but they usually mean two servers cover the same ground, or one
+//! server was registered twice under different names. `mcpd conflicts`
+//! reports them.
+
+use crate::canonical::{self, Nulls};
+use crate::mcp::Tool;
+use crate::output::{Cell, Color, Output, Table};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// One backend's version of a shared tool name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolVariant {
+    pub backend: String,
+    pub description: Option<String>,
+    /// Canonical hash of the input schema, so key order doesn't matter
+    pub schema_hash: String,
+}
+
+/// A tool name exposed by two or more backends
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub tool: String,
+    /// Ordered by backend name
+    pub variants: Vec<ToolVariant>,
+}
+
+impl Conflict {
+    /// Whether every backend describes the tool identically, which usually
+    /// means the same server is registered twice
+    pub fn identical(&self) -> bool {
+        self.variants.windows(2).all(|pair| {
+            pair[0].schema_hash == pair[1].schema_hash && pair[0].description == pair[1].description
+        })
+    }
+}
+
+/// Tool names shared by more than one backend, in name order
+pub fn find(catalogs: &[(String, Vec<Tool>)]) -> Vec<Conflict> {
+    let mut by_name: BTreeMap<&str, Vec<ToolVariant>> = BTreeMap::new();
+    for (backend, tools) in catalogs {
+        for tool in tools {
+            by_name.entry(&tool.name).or_default().push(ToolVariant {
+                backend: backend.clone(),
+                description: tool.description.clone(),
+                schema_hash: canonical::hash(&tool.input_schema, Nulls::Keep),
+            });
+        }
+    }
+    by_name
+        .into_iter()
+        .filter(|(_, variants)| variants.len() > 1)
+        .map(|(tool, mut variants)| {
+            variants.sort_by(|a, b| a.backend.cmp(&b.backend));
+            Conflict {
+                tool: tool.to_string(),
+                variants,
+            }
+        })
+        .collect()
+}
+
+/// Hash of a backend's whole tool catalog: tool names, descriptions and
+/// input schemas, in any order. Catalogs that hash equal describe the same tools.
+pub fn fingerprint(tools: &[Tool]) -> String {
+    let mut tools: Vec<_> = tools
+        .iter()
+        .map(|t| {
+            (
+                t.name.as_str(),
+                t.description.as_deref().unwrap_or(""),
+                canonical::hash(&t.input_schema, Nulls::Keep),
+            )
+        })
+        .collect();
+    tools.sort();
+    let tools: Vec<_> = tools
+        .into_iter()
+        .map(|(name, description, hash)| serde_json::json!([name, description, hash]))
+        .collect();
+    canonical::hash(&serde_json::Value::Array(tools), Nulls::Keep)
+}
+
+/// Backend pairs whose whole catalogs are identical, e.g. `("fs", "files")`
+pub fn duplicate_backends(catalogs: &[(String, Vec<Tool>)]) -> Vec<(String, String)> {
+    let fingerprints: Vec<_> = catalogs
+        .iter()
+        .filter(|(_, tools)| !tools.is_empty())
+        .map(|(name, tools)| (name, fingerprint(tools)))
+        .collect();
+    let mut pairs = Vec::new();
+    for (i, (a, fa)) in fingerprints.iter().enumerate() {
+        for (b, fb) in &fingerprints[i + 1..] {
+            if fa == fb {
+                let mut pair = [a.to_string(), b.to_string()];
+                pair.sort();
+                let [a, b] = pair;
+                pairs.push((a, b));
+            }
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+/// Human-readable report for `mcpd conflicts`
+pub fn render(catalogs: &[(String, Vec<Tool>)], out: &Output) -> String {
+    let conflicts = find(catalogs);
+    let mut text = String::new();
+    if conflicts.is_empty() {
+        text.push_str("No overlapping tool names\n");
+        return text;
+    }
+
+    let _ = writeln!(
+        text,
+        "{} tool name(s) exposed by more than one backend:\n",
+        conflicts.len()
+    );
+    let mut table = Table::new(&["TOOL", "BACKEND", "SCHEMA", "DESCRIPTION"]).truncate(3);
+    for conflict in &conflicts {
+        for (i, variant) in conflict.variants.iter().enumerate() {
+            let tool = if i == 0 { conflict.tool.as_str() } else { "" };
+            let description = match &variant.description {
+                Some(description) => Cell::new(description.as_str()),
+                None => Cell::new("(no description)").color(Color::Dim),
+            };
+            let last = i + 1 == conflict.variants.len();
+            let details = if last && conflict.identical() {
+                vec![out.paint("identical schemas and descriptions", Color::Yellow)]
+            } else {
+                Vec::new()
+            };
+            table.row_with(
+                None,
+                vec![
+                    Cell::new(tool),
+                    Cell::new(variant.backend.as_str()),
+                    Cell::new(&variant.schema_hash[..12]),
+                    description,
+                ],
+                details,
+            );
+        }
+    }
+    text.push_str(&table.render(out));
+
+    let duplicates = duplicate_backends(catalogs);
+    if !duplicates.is_empty() {
+        text.push('\n');
+        for (a, b) in duplicates {
+            let _ = writeln!(
+                text,
+                "{} and {} expose identical tools: probably the same server registered twice. Remove one with `mcpd unregister {}`",
+                a, b, b
+            );
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, description: &str, schema: serde_json::Value) -> Tool {
+        Tool {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            input_schema: schema,
+            title: None,
+            annotations: None,
+        }
+    }
+
+    fn read_file(description: &str) -> Tool {
+        tool(
+            "read_file",
+            description,
+            json!({"type": "object", "properties": {"path": {"type": "string"}}}),
+        )
+    }
+
+    #[test]
+    fn reports_only_shared_names() {
+        let catalogs = vec![
+            (
+                "fs".to_string(),
+                vec![read_file("Read a file"), tool("stat", "Stat", json!({}))],
+            ),
+            (
+                "files".to_string(),
+                vec![
+                    read_file("Read a file from disk"),
+                    tool("glob", "Glob", json!({})),
+                ],
+            ),
+        ];
+        let conflicts = find(&catalogs);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].tool, "read_file");
+        let backends: Vec<_> = conflicts[0].variants.iter().map(|v| &v.backend).collect();
+        assert_eq!(backends, ["files", "fs"]);
+        // Same schema, different description
+        assert_eq!(
+            conflicts[0].variants[0].schema_hash,
+            conflicts[0].variants[1].schema_hash
+        );
+        assert!(!conflicts[0].identical());
+        assert!(duplicate_backends(&catalogs).is_empty());
+    }
+
+    #[test]
+    fn schema_hash_ignores_key_order() {
+        let a = tool("t", "T", json!({"type": "object", "required": ["x"]}));
+        let b = tool("t", "T", json!({"required": ["x"], "type": "object"}));
+        let conflicts = find(&[("a".to_string(), vec![a]), ("b".to_string(), vec![b])]);
+        assert!(conflicts[0].identical());
+    }
+
+    #[test]
+    fn same_server_registered_twice() {
+        let catalog = vec![read_file("Read a file"), tool("stat", "Stat", json!({}))];
+        let catalogs = vec![
+            ("fs".to_string(), catalog.clone()),
+            ("fs2".to_string(), catalog.into_iter().rev().collect()),
+        ];
+        assert_eq!(find(&catalogs).len(), 2);
+        assert!(find(&catalogs).iter().all(Conflict::identical));
+        assert_eq!(
+            duplicate_backends(&catalogs),
+            vec![("fs".to_string(), "fs2".to_string())]
+        );
+
+        let report = render(&catalogs, &Output::plain());
+        assert!(report.contains("2 tool name(s)"), "{}", report);
+        assert!(report.contains("identical schemas and descriptions"));
+        assert!(report.contains("probably the same server registered twice"));
+        assert!(report.contains("mcpd unregister fs2"));
+    }
+
+    #[test]
+    fn no_conflicts() {
+        let catalogs = vec![
+            ("a".to_string(), vec![tool("x", "X", json!({}))]),
+            ("b".to_string(), vec![]),
+        ];
+        assert!(find(&catalogs).is_empty());
+        assert_eq!(
+            render(&catalogs, &Output::plain()),
+            "No overlapping tool names\n"
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! Client connections and routing of server-initiated messages.
+//!
+//! Every connection gets a bounded outbound queue drained by its own writer
+//! task, so a slow or vanished client can't stall notifications to the
+//! others. `list_changed` notifications are broadcast to every initialized
+//! client; progress notifications go only to the connection whose request
+//! carried the progress token.
+//!
+//! Messages are written one per line by default. With pretty output they're
+//! indented over several lines, which breaks newline framing, so each one is
+//! sent behind a `Content-Length` header instead, as in LSP.
+
+use crate::framing::Framing;
+use crate::mcp::{Notification, Response};
+use crate::transport::ClientWriter;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Messages a connection may have queued before notifications to it are dropped
+const OUTBOUND_CAPACITY: usize = 1024;
+
+/// Capacity the writer's serialization buffer keeps between messages. A
+/// bigger message grows it temporarily; it shrinks back afterwards.
+const RETAINED_BUFFER: usize = 64 * 1024;
+
+/// A message waiting in a connection's outbound queue
+enum Outbound {
+    /// Serialized up front, e.g. a notification that may go to many clients
+    Line(String),
+    /// Serialized by the writer task into its reusable buffer, so a large
+    /// response isn't held as both a value and a string while it waits
+    Response(Response),
+}
+
+/// One connected client
+pub struct Connection {
+    id: u64,
+    outbound: mpsc::Sender<Outbound>,
+    /// Set once the client has sent `initialize`; broadcasts skip it until then
+    initialized: AtomicBool,
+    /// Set once the client asked for backend log messages
+    wants_logs: AtomicBool,
+    /// Pretty-print messages behind `Content-Length` headers
+    pretty: bool,
+}
+
+impl Connection {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn mark_initialized(&self) {
+        self.initialized.store(true, Ordering::Release);
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::Acquire)
+    }
+
+    /// Send this client backends' `notifications/message` from now on
+    pub fn receive_logs(&self) {
+        self.wants_logs.store(true, Ordering::Release);
+    }
+
+    pub fn wants_logs(&self) -> bool {
+        self.wants_logs.load(Ordering::Acquire)
+    }
+
+    /// Queue a message, waiting for room. Fails once the client has gone away.
+    pub async fn send(&self, message: &impl serde::Serialize) -> Result<()> {
+        let line = to_line(message, self.pretty)?;
+        self.outbound
+            .send(Outbound::Line(line))
+            .await
+            .map_err(|_| anyhow!("Connection {} closed", self.id))
+    }
+
+    /// Queue a response, waiting for room. It's serialized when written.
+    pub async fn respond(&self, response: Response) -> Result<()> {
+        self.outbound
+            .send(Outbound::Response(response))
+            .await
+            .map_err(|_| anyhow!("Connection {} closed", self.id))
+    }
+
+    /// Queue a message without waiting. Returns false if the client is gone
+    /// or too far behind.
+    fn try_send(&self, message: &impl serde::Serialize) -> bool {
+        match to_line(message, self.pretty) {
+            Ok(line) => self.outbound.try_send(Outbound::Line(line)).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+fn to_line(message: &impl serde::Serialize, pretty: bool) -> Result<String> {
+    if pretty {
+        let json = serde_json::to_vec_pretty(message)?;
+        return Ok(String::from_utf8(Framing::Lsp.encode(&json))?);
+    }
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    Ok(line)
+}
+
+/// Serialize `message` as one line into `buf`, replacing its contents
+pub fn write_line(buf: &mut Vec<u8>, message: &impl serde::Serialize) -> serde_json::Result<()> {
+    buf.clear();
+    serde_json::to_writer(&mut *buf, message)?;
+    buf.push(b'\n');
+    Ok(())
+}
+
+/// Serialize `message` pretty-printed behind a `Content-Length` header into
+/// `buf`, replacing its contents
+pub fn write_framed(buf: &mut Vec<u8>, message: &impl serde::Serialize) -> serde_json::Result<()> {
+    buf.clear();
+    serde_json::to_writer_pretty(&mut *buf, message)?;
+    let header = format!("Content-Length: {}\r\n\r\n", buf.len());
+    buf.splice(0..0, header.into_bytes());
+    Ok(())
+}
+
+/// Write queued messages to the client until the queue closes or a write fails
+async fn write_loop(
+    id: u64,
+    mut outbound: mpsc::Receiver<Outbound>,
+    writer: ClientWriter,
+    pretty: bool,
+) {
+    let mut writer = writer.lock().await;
+    let mut buf = Vec::with_capacity(RETAINED_BUFFER);
+    while let Some(message) = outbound.recv().await {
+        let bytes = match &message {
+            Outbound::Line(line) => line.as_bytes(),
+            Outbound::Response(response) => match if pretty {
+                write_framed(&mut buf, response)
+            } else {
+                write_line(&mut buf, response)
+            } {
+                Ok(()) => &buf[..],
+                Err(e) => {
+                    debug!(connection = id, error = %e, "Failed to serialize response");
+                    continue;
+                }
+            },
+        };
+        let written = async {
+            writer.write_all(bytes).await?;
+            writer.flush().await
+        };
+        if let Err(e) = written.await {
+            debug!(connection = id, error = %e, "Client write failed, dropping its queue");
+            break;
+        }
+        drop(message);
+        buf.clear();
+        buf.shrink_to(RETAINED_BUFFER);
+    }
+}
+
+/// Where to deliver progress for one in-flight call
+struct ProgressRoute {
+    connection: u64,
+    /// The token the client chose
+    token: Value,
+}
+
+/// All connected clients, plus the progress tokens of their in-flight calls
+pub struct Connections {
+    connections: RwLock<HashMap<u64, Arc<Connection>>>,
+    next_id: AtomicU64,
+    /// Keyed by the token mcpd sent to the backend
+    progress: Mutex<HashMap<String, ProgressRoute>>,
+    /// Pretty-print messages to clients behind `Content-Length` headers
+    pretty: bool,
+}
+
+impl Default for Connections {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Connections {
+    pub fn new() -> Self {
+        Self {
+            connections: RwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            progress: Mutex::new(HashMap::new()),
+            pretty: false,
+        }
+    }
+
+    /// Pretty-print messages to clients opened from now on, framing each
+    /// with a `Content-Length` header since it spans several lines
+    pub fn with_pretty_output(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+
+    /// Register a client and start its writer task. The task ends once the
+    /// connection is closed and every handle to it is dropped, after writing
+    /// out whatever was still queued.
+    pub fn open(&self, writer: ClientWriter) -> (Arc<Connection>, JoinHandle<()>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(OUTBOUND_CAPACITY);
+        let connection = Arc::new(Connection {
+            id,
+            outbound: tx,
+            initialized: AtomicBool::new(false),
+            wants_logs: AtomicBool::new(false),
+            pretty: self.pretty,
+        });
+        self.connections
+            .write()
+            .unwrap()
+            .insert(id, Arc::clone(&connection));
+        (
+            connection,
+            tokio::spawn(write_loop(id, rx, writer, self.pretty)),
+        )
+    }
+
+    /// Forget a client. Progress for its in-flight calls is dropped from now on.
+    pub fn close(&self, id: u64) {
+        self.connections.write().unwrap().remove(&id);
+        self.progress
+            .lock()
+            .unwrap()
+            .retain(|_, route| route.connection != id);
+    }
+
+    /// Number of connected clients
+    pub fn len(&self) -> usize {
+        self.connections.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Messages waiting in each client's outbound queue, by connection id
+    pub fn queue_depths(&self) -> Vec<(u64, usize)> {
+        let mut depths: Vec<_> = self
+            .connections
+            .read()
+            .unwrap()
+            .values()
+            .map(|c| (c.id, OUTBOUND_CAPACITY - c.outbound.capacity()))
+            .collect();
+        depths.sort();
+        depths
+    }
+
+    /// Queue a message for one client. Returns false if it's gone or too far behind.
+    pub fn send_to(&self, id: u64, message: &impl serde::Serialize) -> bool {
+        let connection = self.connections.read().unwrap().get(&id).cloned();
+        connection.is_some_and(|c| c.try_send(message))
+    }
+
+    /// Queue a message for every initialized client. Returns how many accepted it.
+    pub fn broadcast(&self, message: &impl serde::Serialize) -> usize {
+        self.broadcast_where(message, |_| true)
+    }
+
+    /// Queue a log message for every initialized client that asked for
+    /// them. Returns how many accepted it.
+    pub fn broadcast_log(&self, message: &impl serde::Serialize) -> usize {
+        self.broadcast_where(message, Connection::wants_logs)
+    }
+
+    fn broadcast_where(
+        &self,
+        message: &impl serde::Serialize,
+        wanted: impl Fn(&Connection) -> bool,
+    ) -> usize {
+        let connections: Vec<_> = self.connections.read().unwrap().values().cloned().collect();
+        connections
+            .iter()
+            .filter(|c| c.is_initialized() && wanted(c))
+            .filter(|c| {
+                let sent = c.try_send(message);
+                if !sent {
+                    debug!(
+                        connection = c.id,
+                        "Dropped broadcast to unresponsive client"
+                    );
+                }
+                sent
+            })
+            .count()
+    }
+
+    /// Route progress reported against `backend_token` to `connection`, under
+    /// the client's own `token`, until the returned guard is dropped.
+    pub fn track_progress(
+        &self,
+        connection: u64,
+        token: Value,
+        backend_token: &str,
+    ) -> ProgressGuard<'_> {
+        self.progress.lock().unwrap().insert(
+            backend_token.to_string(),
+            ProgressRoute { connection, token },
+        );
+        ProgressGuard {
+            connections: self,
+            backend_token: backend_token.to_string(),
+        }
+    }
+
+    /// Deliver a backend's `notifications/progress` to the client that asked
+    /// for it. Progress for finished calls or closed connections is dropped.
+    pub fn forward_progress(&self, mut notification: Notification) {
+        let Some(params) = notification.params.as_mut() else {
+            return;
+        };
+        let Some(backend_token) = params.get("progressToken").and_then(Value::as_str) else {
+            return;
+        };
+        let Some((connection, token)) = self
+            .progress
+            .lock()
+            .unwrap()
+            .get(backend_token)
+            .map(|route| (route.connection, route.token.clone()))
+        else {
+            debug!(token = backend_token, "Dropping progress for unknown token");
+            return;
+        };
+        params["progressToken"] = token;
+        if !self.send_to(connection, &notification) {
+            debug!(connection, "Dropping progress for closed connection");
+        }
+    }
+}
+
+/// Keeps a progress route alive for the duration of a call
+pub struct ProgressGuard<'a> {
+    connections: &'a Connections,
+    backend_token: String,
+}
+
+impl ProgressGuard<'_> {
+    /// The token to send to the backend in place of the client's
+    pub fn backend_token(&self) -> &str {
+        &self.backend_token
+    }
+}
+
+impl Drop for ProgressGuard<'_> {
+    fn drop(&mut self) {
+        self.connections
+            .progress
+            .lock()
+            .unwrap()
+            .remove(&self.backend_token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::RequestId;
+    use crate::transport::Transport;
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader, DuplexStream};
+
+    fn open(
+        connections: &Connections,
+    ) -> (Arc<Connection>, JoinHandle<()>, BufReader<DuplexStream>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (_, writer) = server.split();
+        let (connection, task) = connections.open(writer);
+        (connection, task, BufReader::new(client))
+    }
+
+    async fn next_line(reader: &mut BufReader<DuplexStream>) -> Value {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    fn progress(token: &str) -> Notification {
+        Notification {
+            params: Some(json!({"progressToken": token, "progress": 1})),
+            ..Notification::new("notifications/progress")
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcast_skips_uninitialized() {
+        let connections = Connections::new();
+        let (a, _ta, mut ra) = open(&connections);
+        let (_b, _tb, _rb) = open(&connections);
+        a.mark_initialized();
+
+        let sent = connections.broadcast(&Notification::new("notifications/tools/list_changed"));
+        assert_eq!(sent, 1);
+        assert_eq!(
+            next_line(&mut ra).await["method"],
+            "notifications/tools/list_changed"
+        );
+    }
+
+    #[tokio::test]
+    async fn log_messages_only_to_clients_that_asked() {
+        let connections = Connections::new();
+        let (a, _ta, _ra) = open(&connections);
+        let (b, _tb, mut rb) = open(&connections);
+        a.mark_initialized();
+        b.mark_initialized();
+        let log = Notification::new("notifications/message");
+        assert_eq!(connections.broadcast_log(&log), 0);
+
+        b.receive_logs();
+        assert_eq!(connections.broadcast_log(&log), 1);
+        assert_eq!(next_line(&mut rb).await["method"], "notifications/message");
+    }
+
+    #[tokio::test]
+    async fn progress_routed_with_client_token() {
+        let connections = Connections::new();
+        let (a, _ta, mut ra) = open(&connections);
+        let guard = connections.track_progress(a.id(), json!(7), "mcpd-1-1");
+
+        connections.forward_progress(progress(guard.backend_token()));
+        let line = next_line(&mut ra).await;
+        assert_eq!(line["params"]["progressToken"], 7);
+
+        // Once the call is over its token no longer routes anywhere
+        drop(guard);
+        connections.forward_progress(progress("mcpd-1-1"));
+        assert!(connections.progress.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn closed_connection_drops_progress_quietly() {
+        let connections = Connections::new();
+        let (a, task, reader) = open(&connections);
+        let _guard = connections.track_progress(a.id(), json!("t"), "mcpd-1-1");
+        drop(reader);
+
+        connections.close(a.id());
+        connections.forward_progress(progress("mcpd-1-1"));
+        assert!(!connections.send_to(a.id(), &json!({})));
+        assert!(connections.is_empty());
+
+        drop(a);
+        task.await.unwrap();
+    }
+
+    /// Read one message framed with a `Content-Length` header
+    async fn next_framed(reader: &mut BufReader<DuplexStream>) -> String {
+        let mut header = String::new();
+        reader.read_line(&mut header).await.unwrap();
+        let length: usize = header
+            .strip_prefix("Content-Length: ")
+            .and_then(|rest| rest.trim_end().parse().ok())
+            .unwrap_or_else(|| panic!("not a Content-Length header: {header:?}"));
+        let mut blank = String::new();
+        reader.read_line(&mut blank).await.unwrap();
+        assert_eq!(blank, "\r\n");
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pretty_output_is_framed_by_length() {
+        let connections = Connections::new().with_pretty_output(true);
+        let (a, _ta, mut ra) = open(&connections);
+        a.mark_initialized();
+
+        a.respond(Response::success(
+            RequestId::Number(1),
+            json!({"tools": [{"name": "echo"}]}),
+        ))
+        .await
+        .unwrap();
+        connections.broadcast(&Notification::new("notifications/tools/list_changed"));
+
+        let response = next_framed(&mut ra).await;
+        assert!(
+            response.lines().count() > 1,
+            "not pretty-printed: {response}"
+        );
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["result"]["tools"][0]["name"], "echo");
+        // The next message starts right where the declared length ended
+        let notification: Value = serde_json::from_str(&next_framed(&mut ra).await).unwrap();
+        assert_eq!(notification["method"], "notifications/tools/list_changed");
+    }
+}
@@ -0,0 +1,147 @@
+//! Control socket - lets CLI commands talk to a running `mcpd serve`.
+//!
+//! Every serve process listens on `<config dir>/run/<pid>.sock`. The protocol
+//! is one newline-delimited JSON-RPC request per connection, answered with a
+//! single response.
+
+use anyhow::{Context, Result, anyhow};
+use std::path::{Path, PathBuf};
+
+/// Directory holding the control sockets of running servers, next to the registry file
+pub fn run_dir(registry_path: &Path) -> PathBuf {
+    registry_path
+        .parent()
+        .map(|p| p.join("run"))
+        .unwrap_or_else(|| PathBuf::from("run"))
+}
+
+/// Control socket path for the current process
+pub fn socket_path(registry_path: &Path) -> PathBuf {
+    run_dir(registry_path).join(format!("{}.sock", std::process::id()))
+}
+
+/// Find the control socket of a running server, preferring the most recently
+/// started one. Sockets left behind by dead processes are removed.
+#[cfg(unix)]
+pub fn find_socket(registry_path: &Path) -> Result<PathBuf> {
+    let dir = run_dir(registry_path);
+    let entries = std::fs::read_dir(&dir)
+        .with_context(|| format!("No running mcpd found (no {})", dir.display()))?;
+
+    let mut sockets: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "sock"))
+        .filter_map(|p| {
+            let mtime = std::fs::metadata(&p).and_then(|m| m.modified()).ok()?;
+            Some((mtime, p))
+        })
+        .collect();
+    sockets.sort_by_key(|s| std::cmp::Reverse(s.0));
+
+    for (_, path) in sockets {
+        match std::os::unix::net::UnixStream::connect(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                let _ = std::fs::remove_file(&path);
+            }
+            Err(_) => {}
+        }
+    }
+
+    Err(anyhow!("No running mcpd found in {}", dir.display()))
+}
+
+/// Whether the peer on a control connection runs as the same user as this process
+#[cfg(unix)]
+pub fn same_user(stream: &tokio::net::UnixStream) -> bool {
+    let uid = nix::unistd::geteuid().as_raw();
+    stream.peer_cred().is_ok_and(|cred| cred.uid() == uid)
+}
+
+/// An error the server answered with, as opposed to failing to reach it
+#[derive(Debug, thiserror::Error)]
+#[error("Control error {code}: {message}")]
+pub struct ControlError {
+    pub code: i32,
+    pub message: String,
+}
+
+/// Send one control request and return its result
+#[cfg(unix)]
+pub async fn call(
+    path: &Path,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> Result<serde_json::Value> {
+    use crate::mcp::{Request, Response};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let stream = tokio::net::UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Failed to connect to {}", path.display()))?;
+    let (read, mut write) = stream.into_split();
+
+    let request = Request::new(1_i64, method, params);
+    let mut line = serde_json::to_string(&request)?;
+    line.push('\n');
+    write.write_all(line.as_bytes()).await?;
+    write.flush().await?;
+
+    let mut reader = BufReader::new(read);
+    let mut response_line = String::new();
+    reader.read_line(&mut response_line).await?;
+    let response: Response =
+        serde_json::from_str(&response_line).context("Invalid control response")?;
+
+    if let Some(err) = response.error {
+        return Err(ControlError {
+            code: err.code,
+            message: err.message,
+        }
+        .into());
+    }
+    response
+        .result
+        .ok_or_else(|| anyhow!("No result in control response"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_lives_next_to_registry() {
+        let path = socket_path(Path::new("/cfg/mcpd/registry.json"));
+        assert_eq!(
+            path,
+            PathBuf::from(format!("/cfg/mcpd/run/{}.sock", std::process::id()))
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn same_user_accepts_own_process() {
+        let (a, _b) = tokio::net::UnixStream::pair().unwrap();
+        assert!(same_user(&a));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_socket_skips_stale() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = dir.path().join("registry.json");
+        let run = run_dir(&registry);
+        std::fs::create_dir_all(&run).unwrap();
+
+        // A socket file with no listener behind it
+        let stale = run.join("1.sock");
+        drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+        assert!(find_socket(&registry).is_err());
+        assert!(!stale.exists());
+
+        let live = run.join("2.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&live).unwrap();
+        assert_eq!(find_socket(&registry).unwrap(), live);
+    }
+}
@@ -0,0 +1,345 @@
+//! How messages are delimited on a backend's stdio.
+//!
+//! MCP's stdio transport puts one JSON message on each line, but some servers
+//! (usually ones built on LSP tooling) frame messages the way LSP does: a
+//! `Content-Length` header, a blank line, then exactly that many bytes of
+//! JSON. With `Framing::Auto` the proxy peeks at the first bytes a backend
+//! writes and picks whichever framing they're in. A backend that writes
+//! nothing before it's spoken to is sent line-delimited requests, so a silent
+//! LSP-framed server has to be registered with `--framing lsp`.
+
+use crate::limits::{self, ReadLine, TooLargeToRead};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Cursor};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncReadExt, Chain};
+
+/// Longest header line accepted in an LSP-framed message
+const MAX_HEADER_BYTES: usize = 1024;
+
+/// How a backend delimits its messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// Detect from the backend's first output, newline-delimited until then
+    #[default]
+    Auto,
+    /// One JSON message per line (the MCP stdio transport)
+    Lines,
+    /// `Content-Length` headers, as in LSP
+    Lsp,
+}
+
+impl Framing {
+    pub fn is_auto(&self) -> bool {
+        *self == Framing::Auto
+    }
+
+    /// Frame one serialized message for writing. Auto writes lines.
+    pub fn encode(self, json: &[u8]) -> Vec<u8> {
+        match self {
+            Framing::Lsp => {
+                let mut framed = format!("Content-Length: {}\r\n\r\n", json.len()).into_bytes();
+                framed.extend_from_slice(json);
+                framed
+            }
+            Framing::Auto | Framing::Lines => {
+                let mut framed = Vec::with_capacity(json.len() + 1);
+                framed.extend_from_slice(json);
+                framed.push(b'\n');
+                framed
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Framing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Framing::Auto => "auto",
+            Framing::Lines => "lines",
+            Framing::Lsp => "lsp",
+        })
+    }
+}
+
+const LSP_HEADER: &[u8] = b"content-length:";
+
+/// The framing a backend's output starting with `bytes` is in, or `None`
+/// while `bytes` could still be the start of a `Content-Length` header
+pub fn detect(bytes: &[u8]) -> Option<Framing> {
+    let n = bytes.len().min(LSP_HEADER.len());
+    if !bytes[..n].eq_ignore_ascii_case(&LSP_HEADER[..n]) {
+        return Some(Framing::Lines);
+    }
+    (n == LSP_HEADER.len()).then_some(Framing::Lsp)
+}
+
+/// Settle the framing of `reader`: the declared one if set, otherwise
+/// detected from its first bytes. The returned reader still yields every
+/// byte, including the ones peeked at.
+pub async fn resolve<R: AsyncRead + Unpin>(
+    declared: Framing,
+    mut reader: R,
+) -> io::Result<(Framing, Chain<Cursor<Vec<u8>>, R>)> {
+    let mut peeked = Vec::new();
+    let framing = match declared {
+        Framing::Auto => loop {
+            if let Some(framing) = detect(&peeked) {
+                break framing;
+            }
+            let mut chunk = [0u8; LSP_HEADER.len()];
+            let n = reader
+                .read(&mut chunk[..LSP_HEADER.len() - peeked.len()])
+                .await?;
+            if n == 0 {
+                // Gone before saying anything; the reader will see EOF
+                break Framing::Lines;
+            }
+            peeked.extend_from_slice(&chunk[..n]);
+        },
+        declared => declared,
+    };
+    Ok((framing, Cursor::new(peeked).chain(reader)))
+}
+
+/// Read one message into `buf` (cleared first), keeping at most `max_bytes`
+/// of it. Oversized messages are drained so the stream stays in sync, unless
+/// the message (headers included) runs past `abort_after` bytes: then reading
+/// stops with a `limits::TooLargeToRead` error.
+pub async fn read_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    framing: Framing,
+    buf: &mut Vec<u8>,
+    max_bytes: usize,
+    abort_after: usize,
+) -> io::Result<ReadLine> {
+    match framing {
+        Framing::Lsp => read_lsp_message(reader, buf, max_bytes, abort_after).await,
+        Framing::Auto | Framing::Lines => {
+            limits::read_line_capped(reader, buf, max_bytes, abort_after).await
+        }
+    }
+}
+
+async fn read_lsp_message<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_bytes: usize,
+    abort_after: usize,
+) -> io::Result<ReadLine> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let too_large = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            TooLargeToRead { max: abort_after },
+        )
+    };
+    let mut length = None;
+    let mut in_headers = false;
+    let mut header_bytes = 0usize;
+    loop {
+        match limits::read_line_bounded(reader, buf, MAX_HEADER_BYTES).await? {
+            ReadLine::Eof if !in_headers => return Ok(ReadLine::Eof),
+            ReadLine::Eof => return Err(io::ErrorKind::UnexpectedEof.into()),
+            ReadLine::TooLong { size } => {
+                return Err(invalid(format!("LSP header line of {} bytes", size)));
+            }
+            ReadLine::Line => {}
+        }
+        // Blank lines before the headers count too, so they can't go on forever
+        header_bytes += buf.len() + 1;
+        if header_bytes > abort_after {
+            return Err(too_large());
+        }
+        let line = buf.strip_suffix(b"\r").unwrap_or(buf);
+        if line.is_empty() {
+            // Blank lines between messages are harmless; one after the
+            // headers ends them
+            if in_headers {
+                break;
+            }
+            continue;
+        }
+        in_headers = true;
+        let line = String::from_utf8_lossy(line);
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid(format!("Invalid LSP header: {}", line)))?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            let value = value.trim();
+            length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| invalid(format!("Invalid Content-Length: {}", value)))?,
+            );
+        }
+    }
+    let length = length.ok_or_else(|| invalid("LSP message without Content-Length".to_string()))?;
+    // Known up front, so none of the body is read
+    if header_bytes.saturating_add(length) > abort_after {
+        return Err(too_large());
+    }
+
+    buf.clear();
+    buf.resize(length.min(max_bytes), 0);
+    reader.read_exact(buf).await?;
+    if length > max_bytes {
+        let rest = (length - max_bytes) as u64;
+        tokio::io::copy(&mut reader.take(rest), &mut tokio::io::sink()).await?;
+        return Ok(ReadLine::TooLong { size: length });
+    }
+    Ok(ReadLine::Line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    async fn read_all(framing: Framing, input: &[u8], max_bytes: usize) -> Vec<(ReadLine, String)> {
+        let mut reader = BufReader::new(input);
+        let mut buf = Vec::new();
+        let mut messages = Vec::new();
+        loop {
+            let read = read_message(&mut reader, framing, &mut buf, max_bytes, usize::MAX)
+                .await
+                .unwrap();
+            if read == ReadLine::Eof {
+                return messages;
+            }
+            messages.push((read, String::from_utf8(buf.clone()).unwrap()));
+        }
+    }
+
+    #[test]
+    fn detects_from_first_bytes() {
+        assert_eq!(detect(b"{\"jsonrpc\""), Some(Framing::Lines));
+        assert_eq!(detect(b"Content-Length: 12\r\n"), Some(Framing::Lsp));
+        assert_eq!(detect(b"content-length:"), Some(Framing::Lsp));
+        assert_eq!(detect(b"Conte"), None);
+        assert_eq!(detect(b""), None);
+        assert_eq!(detect(b"Contents"), Some(Framing::Lines));
+    }
+
+    #[tokio::test]
+    async fn resolve_detects_and_keeps_peeked_bytes() {
+        for (input, expected) in [
+            (&b"Content-Length: 2\r\n\r\n{}"[..], Framing::Lsp),
+            (&b"{}\n"[..], Framing::Lines),
+            (&b"Cont"[..], Framing::Lines),
+            (&b""[..], Framing::Lines),
+        ] {
+            let (framing, mut reader) = resolve(Framing::Auto, input).await.unwrap();
+            assert_eq!(framing, expected, "{:?}", input);
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).await.unwrap();
+            assert_eq!(rest, input);
+        }
+    }
+
+    #[tokio::test]
+    async fn declared_framing_wins() {
+        let (framing, _) = resolve(Framing::Lines, &b"Content-Length: 2\r\n\r\n{}"[..])
+            .await
+            .unwrap();
+        assert_eq!(framing, Framing::Lines);
+        let (framing, _) = resolve(Framing::Lsp, &b"{}\n"[..]).await.unwrap();
+        assert_eq!(framing, Framing::Lsp);
+    }
+
+    #[tokio::test]
+    async fn reads_both_framings() {
+        let messages = [r#"{"id":1}"#, r#"{"id":2,"text":"a\nb"}"#];
+        for framing in [Framing::Lines, Framing::Lsp] {
+            let input: Vec<u8> = messages
+                .iter()
+                .flat_map(|m| framing.encode(m.as_bytes()))
+                .collect();
+            let (detected, reader) = resolve(Framing::Auto, &input[..]).await.unwrap();
+            assert_eq!(detected, framing);
+            let mut reader = BufReader::new(reader);
+            let mut buf = Vec::new();
+            for expected in messages {
+                let read = read_message(&mut reader, detected, &mut buf, 1024, usize::MAX)
+                    .await
+                    .unwrap();
+                assert_eq!(read, ReadLine::Line);
+                assert_eq!(buf, expected.as_bytes());
+            }
+            let read = read_message(&mut reader, detected, &mut buf, 1024, usize::MAX).await;
+            assert_eq!(read.unwrap(), ReadLine::Eof);
+        }
+    }
+
+    #[tokio::test]
+    async fn lsp_headers_are_lenient() {
+        let input = b"content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}\r\n\
+                      Content-Length:3\n\n[1]";
+        let messages = read_all(Framing::Lsp, input, 1024).await;
+        assert_eq!(
+            messages,
+            [
+                (ReadLine::Line, "{}".to_string()),
+                (ReadLine::Line, "[1]".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_lsp_message_is_skipped() {
+        let mut input = Framing::Lsp.encode(br#"{"id":7,"pad":"xxxxxxxxxxxxxxxx"}"#);
+        input.extend(Framing::Lsp.encode(b"{}"));
+        let messages = read_all(Framing::Lsp, &input, 10).await;
+        assert_eq!(
+            messages,
+            [
+                (ReadLine::TooLong { size: 33 }, r#"{"id":7,"p"#.to_string()),
+                (ReadLine::Line, "{}".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn messages_past_the_cap_stop_the_read() {
+        let big = format!(r#"{{"id":1,"pad":"{}"}}"#, "x".repeat(1000));
+        for framing in [Framing::Lines, Framing::Lsp] {
+            let mut input = framing.encode(b"{}");
+            input.extend(framing.encode(big.as_bytes()));
+            let mut reader = BufReader::with_capacity(16, &input[..]);
+            let mut buf = Vec::new();
+            let read = read_message(&mut reader, framing, &mut buf, 10, 100).await;
+            assert_eq!(read.unwrap(), ReadLine::Line, "{}", framing);
+            let e = read_message(&mut reader, framing, &mut buf, 10, 100)
+                .await
+                .unwrap_err();
+            assert_eq!(
+                TooLargeToRead::find(&e),
+                Some(&TooLargeToRead { max: 100 }),
+                "{}",
+                framing
+            );
+            // Most of the message was never read
+            assert!(reader.get_ref().len() > 800, "{}", framing);
+        }
+        // Endless blank lines before LSP headers count too
+        let blank = b"\r\n".repeat(1000);
+        let mut reader = BufReader::new(&blank[..]);
+        let read = read_message(&mut reader, Framing::Lsp, &mut Vec::new(), 10, 100).await;
+        assert!(TooLargeToRead::find(&read.unwrap_err()).is_some());
+    }
+
+    #[tokio::test]
+    async fn malformed_lsp_messages_are_errors() {
+        for input in [
+            &b"Content-Type: json\r\n\r\n{}"[..],
+            b"Content-Length: x\r\n\r\n{}",
+            b"{}\r\n\r\n",
+            b"Content-Length: 10\r\n\r\n{}",
+        ] {
+            let mut reader = BufReader::new(input);
+            let read =
+                read_message(&mut reader, Framing::Lsp, &mut Vec::new(), 1024, usize::MAX).await;
+            assert!(read.is_err(), "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+}
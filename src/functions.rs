@@ -0,0 +1,242 @@
+//! `mcpd export-functions`: the catalog as an LLM provider's function-calling
+//! tool definitions, for bridging mcpd's tools into those APIs.
+//!
+//! OpenAI and Anthropic both limit tool names to 64 characters from
+//! `[a-zA-Z0-9_-]`. Names that don't fit are rewritten: other characters
+//! become `_`, and names that are too long, or that clash once rewritten, are
+//! cut and end in a short hash of the original so they stay distinct. Every
+//! rename is reported, so a provider's tool call can be mapped back to the
+//! name mcpd exposes.
+
+use crate::catalog::{Catalog, Entry};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// Longest tool name either provider accepts
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Hex digits of the hash that ends a shortened or clashing name
+const HASH_LEN: usize = 8;
+
+/// Whose function-calling schema to emit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Format {
+    /// `[{"type": "function", "function": {name, description, parameters}}]`
+    #[default]
+    Openai,
+    /// `[{name, description, input_schema}]`
+    Anthropic,
+}
+
+/// The emitted tool definitions, and the names that had to change
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Export {
+    pub tools: Vec<Value>,
+    /// `(exposed name, provider name)` for each tool that was renamed
+    pub renamed: Vec<(String, String)>,
+}
+
+/// `name` with characters providers refuse replaced by `_`
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// `name` cut short enough to end in `_<hash of original>`
+fn hashed(name: &str, original: &str) -> String {
+    let digest = Sha256::digest(original.as_bytes());
+    let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    let keep = MAX_NAME_LEN - HASH_LEN - 1;
+    let prefix: String = name.chars().take(keep).collect();
+    format!("{}_{}", prefix, &hash[..HASH_LEN])
+}
+
+/// A JSON Schema both providers take as parameters: an object schema, with
+/// `"type": "object"` added when a backend left it out
+fn parameters(schema: &Value) -> Value {
+    match schema {
+        Value::Object(map) if map.contains_key("type") => schema.clone(),
+        Value::Object(map) => {
+            let mut map = map.clone();
+            map.insert("type".to_string(), json!("object"));
+            Value::Object(map)
+        }
+        _ => json!({"type": "object", "properties": {}}),
+    }
+}
+
+fn definition(entry: &Entry, name: &str, format: Format) -> Value {
+    let mut tool = json!({"name": name});
+    if let Some(description) = &entry.description {
+        tool["description"] = json!(description);
+    }
+    let schema = parameters(&entry.input_schema);
+    match format {
+        Format::Openai => {
+            tool["parameters"] = schema;
+            json!({"type": "function", "function": tool})
+        }
+        Format::Anthropic => {
+            tool["input_schema"] = schema;
+            tool
+        }
+    }
+}
+
+/// Every catalog tool as `format` describes tools, in catalog order
+pub fn export(catalog: &Catalog, format: Format) -> Export {
+    let mut export = Export::default();
+    let mut taken = HashSet::new();
+    for entry in &catalog.tools {
+        let clean = sanitize(&entry.name);
+        let name = if clean.len() > MAX_NAME_LEN || taken.contains(&clean) {
+            hashed(&clean, &entry.name)
+        } else {
+            clean
+        };
+        if name != entry.name {
+            export.renamed.push((entry.name.clone(), name.clone()));
+        }
+        export.tools.push(definition(entry, &name, format));
+        taken.insert(name);
+    }
+    export
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, schema: Value) -> Entry {
+        Entry {
+            name: name.to_string(),
+            backend: "fs".to_string(),
+            tool: name.to_string(),
+            description: Some("Read a file".to_string()),
+            input_schema: schema,
+        }
+    }
+
+    fn catalog(entries: Vec<Entry>) -> Catalog {
+        Catalog {
+            tools: entries,
+            failures: Vec::new(),
+        }
+    }
+
+    /// The name rule both providers document
+    fn acceptable(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= MAX_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+    }
+
+    #[test]
+    fn openai_shape() {
+        let schema = json!({"type": "object", "properties": {"path": {"type": "string"}}});
+        let export = export(
+            &catalog(vec![entry("fs__read", schema.clone())]),
+            Format::Openai,
+        );
+        assert_eq!(
+            export.tools,
+            vec![json!({
+                "type": "function",
+                "function": {
+                    "name": "fs__read",
+                    "description": "Read a file",
+                    "parameters": schema
+                }
+            })]
+        );
+        assert!(export.renamed.is_empty());
+    }
+
+    #[test]
+    fn anthropic_shape() {
+        let mut tool = entry("fs__stat", json!({"type": "object"}));
+        tool.description = None;
+        let export = export(&catalog(vec![tool]), Format::Anthropic);
+        assert_eq!(
+            export.tools,
+            vec![json!({"name": "fs__stat", "input_schema": {"type": "object"}})]
+        );
+    }
+
+    #[test]
+    fn parameters_are_always_object_schemas() {
+        let tools = vec![
+            entry("a", json!({"properties": {"x": {"type": "number"}}})),
+            entry("b", json!(true)),
+        ];
+        let export = export(&catalog(tools), Format::Openai);
+        assert_eq!(
+            export.tools[0]["function"]["parameters"],
+            json!({"type": "object", "properties": {"x": {"type": "number"}}})
+        );
+        assert_eq!(
+            export.tools[1]["function"]["parameters"],
+            json!({"type": "object", "properties": {}})
+        );
+    }
+
+    #[test]
+    fn names_are_made_acceptable_and_reported() {
+        let long = format!("web__{}", "x".repeat(80));
+        let tools = vec![
+            entry("v1.2__get", json!({})),
+            entry(&long, json!({})),
+            entry("ok__name", json!({})),
+        ];
+        for format in [Format::Openai, Format::Anthropic] {
+            let export = export(&catalog(tools.clone()), format);
+            let names: Vec<&str> = export
+                .tools
+                .iter()
+                .map(|t| match format {
+                    Format::Openai => t["function"]["name"].as_str().unwrap(),
+                    Format::Anthropic => t["name"].as_str().unwrap(),
+                })
+                .collect();
+            assert!(names.iter().all(|n| acceptable(n)), "{:?}", names);
+            assert_eq!(names[0], "v1_2__get");
+            assert_eq!(names[1].len(), MAX_NAME_LEN);
+            assert_eq!(names[2], "ok__name");
+            assert_eq!(
+                export.renamed,
+                vec![
+                    ("v1.2__get".to_string(), names[0].to_string()),
+                    (long.clone(), names[1].to_string()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn names_that_clash_once_rewritten_stay_distinct() {
+        let long = |c: char| format!("srv__{}{}", "y".repeat(70), c);
+        let tools = vec![
+            entry("a.b__c", json!({})),
+            entry("a_b__c", json!({})),
+            entry(&long('1'), json!({})),
+            entry(&long('2'), json!({})),
+        ];
+        let export = export(&catalog(tools), Format::Anthropic);
+        let names: HashSet<&str> = export
+            .tools
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names.len(), 4, "{:?}", names);
+        assert!(names.iter().all(|n| acceptable(n)), "{:?}", names);
+        // The first to claim a name keeps it
+        assert!(names.contains("a_b__c"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Custom per-backend health checks.
+//!
+//! A process that answers pings can still be useless, like a database server
+//! whose connection pool is gone. A backend's `health_check` names one of its
+//! own tools to call on an interval. After `failure_threshold` failures in a
+//! row the backend is degraded: it stays listed, but its tool descriptions
+//! carry a warning and `mcpd top` shows the last failure. One passing check
+//! clears it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// How long one health check call may take before it counts as failed
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Appended to the descriptions of a degraded backend's tools
+pub const DEGRADED_SUFFIX: &str = "[degraded]";
+
+/// Failures in a row that mark a backend degraded, unless its check says
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 2;
+
+/// A tool call that proves a backend can do real work
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// The backend's own name for the tool to call
+    pub tool: String,
+    #[serde(default = "empty_object")]
+    pub arguments: Value,
+    #[serde(default = "default_interval")]
+    pub interval_secs: u64,
+    /// Consecutive failures before the backend is degraded
+    #[serde(default = "default_threshold")]
+    pub failure_threshold: u32,
+    /// What to do once the backend is degraded, besides marking it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_degraded: Option<OnDegraded>,
+}
+
+fn empty_object() -> Value {
+    Value::Object(Default::default())
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn default_threshold() -> u32 {
+    DEFAULT_FAILURE_THRESHOLD
+}
+
+impl HealthCheck {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    /// Why this check can't run, if it can't
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.tool.is_empty() {
+            Some("no tool")
+        } else if self.interval_secs == 0 {
+            Some("an interval of 0")
+        } else if self.failure_threshold == 0 {
+            Some("a failure threshold of 0")
+        } else if !self.arguments.is_object() {
+            Some("arguments that aren't an object")
+        } else {
+            None
+        }
+    }
+}
+
+/// Action taken when a backend becomes degraded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnDegraded {
+    /// Restart the backend (warm replacement for zero-downtime backends)
+    Restart,
+}
+
+/// A change in a backend's health worth acting on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Degraded,
+    Recovered,
+}
+
+/// Health of one backend, from its recent checks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Health {
+    /// Failures since the last passing check
+    failures: u32,
+    degraded: bool,
+    last_failure: Option<String>,
+}
+
+impl Health {
+    /// Count one check's outcome. Returns the transition it caused, if any.
+    pub fn record(&mut self, outcome: Result<(), String>, threshold: u32) -> Option<Transition> {
+        match outcome {
+            Ok(()) => {
+                let was_degraded = self.degraded;
+                *self = Health::default();
+                was_degraded.then_some(Transition::Recovered)
+            }
+            Err(failure) => {
+                self.failures = self.failures.saturating_add(1);
+                self.last_failure = Some(failure);
+                let crossed = !self.degraded && self.failures >= threshold;
+                self.degraded |= crossed;
+                crossed.then_some(Transition::Degraded)
+            }
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Failures since the last passing check
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// What the most recent failing check reported, until a check passes
+    pub fn last_failure(&self) -> Option<&str> {
+        self.last_failure.as_deref()
+    }
+}
+
+/// `description` with the degraded warning after it
+pub fn degraded_description(description: Option<&str>) -> String {
+    match description {
+        Some(description) if !description.is_empty() => {
+            format!("{} {}", description, DEGRADED_SUFFIX)
+        }
+        _ => DEGRADED_SUFFIX.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail() -> Result<(), String> {
+        Err("pool closed".to_string())
+    }
+
+    #[test]
+    fn degrades_at_threshold_and_recovers_on_one_pass() {
+        let mut health = Health::default();
+        assert_eq!(health.record(fail(), 2), None);
+        assert!(!health.is_degraded());
+        assert_eq!(health.record(fail(), 2), Some(Transition::Degraded));
+        assert!(health.is_degraded());
+        assert_eq!(health.last_failure(), Some("pool closed"));
+        // Further failures don't repeat the transition
+        assert_eq!(health.record(fail(), 2), None);
+        assert_eq!(health.failures(), 3);
+
+        assert_eq!(health.record(Ok(()), 2), Some(Transition::Recovered));
+        assert_eq!(health, Health::default());
+    }
+
+    #[test]
+    fn a_pass_resets_the_count() {
+        let mut health = Health::default();
+        health.record(fail(), 2);
+        assert_eq!(health.record(Ok(()), 2), None);
+        assert_eq!(health.record(fail(), 2), None);
+        assert!(!health.is_degraded());
+    }
+
+    #[test]
+    fn threshold_of_one_degrades_at_once() {
+        let mut health = Health::default();
+        assert_eq!(health.record(fail(), 1), Some(Transition::Degraded));
+    }
+
+    #[test]
+    fn spec_defaults() {
+        let check: HealthCheck = serde_json::from_str(r#"{"tool": "query"}"#).unwrap();
+        assert_eq!(check.arguments, serde_json::json!({}));
+        assert_eq!(check.interval(), Duration::from_secs(300));
+        assert_eq!(check.failure_threshold, 2);
+        assert_eq!(check.on_degraded, None);
+        assert_eq!(check.problem(), None);
+
+        let check: HealthCheck = serde_json::from_str(
+            r#"{"tool": "q", "failure_threshold": 0, "on_degraded": "restart"}"#,
+        )
+        .unwrap();
+        assert_eq!(check.on_degraded, Some(OnDegraded::Restart));
+        assert!(check.problem().is_some());
+    }
+
+    #[test]
+    fn degraded_description_appends_warning() {
+        assert_eq!(degraded_description(Some("Run SQL")), "Run SQL [degraded]");
+        assert_eq!(degraded_description(None), "[degraded]");
+    }
+}
@@ -0,0 +1,187 @@
+//! Per-backend call hooks: external commands registered with a tool
+//! (`register --pre-call` / `--post-call`) that see each call's arguments or
+//! result as JSON on stdin and print the JSON to use instead on stdout.
+//!
+//! A hook that prints nothing leaves the JSON unchanged, so a logging hook
+//! doesn't have to echo its input. A hook that exits non-zero, times out or
+//! prints something that isn't JSON fails the call; for a `post_call` hook
+//! that keeps an unredacted result from reaching the client.
+//!
+//! Hooks also get `MCPD_HOOK` (`pre_call` or `post_call`), `MCPD_BACKEND`,
+//! `MCPD_TOOL` and `MCPD_CORRELATION_ID` in their environment.
+
+use crate::error_text::ForeignText;
+use anyhow::{Context, Result, anyhow, bail};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// How long a hook may run when `serve --hook-timeout` isn't given
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most stderr quoted from a failing hook
+const MAX_STDERR_IN_ERROR: usize = 500;
+
+/// Which side of the backend call a hook runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Gets the call's arguments, prints the arguments to send
+    PreCall,
+    /// Gets the backend's result, prints the result to return
+    PostCall,
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Stage::PreCall => "pre_call",
+            Stage::PostCall => "post_call",
+        })
+    }
+}
+
+/// The call a hook runs for
+#[derive(Debug, Clone, Copy)]
+pub struct Call<'a> {
+    pub backend: &'a str,
+    pub tool: &'a str,
+    pub correlation_id: &'a str,
+}
+
+/// Run a hook command on `input`. Returns the JSON it printed, or `None` if
+/// it printed nothing.
+pub async fn run(
+    command: &[String],
+    stage: Stage,
+    call: Call<'_>,
+    input: &serde_json::Value,
+    timeout: Duration,
+) -> Result<Option<serde_json::Value>> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("{} hook has no command", stage))?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .env("MCPD_HOOK", stage.to_string())
+        .env("MCPD_BACKEND", call.backend)
+        .env("MCPD_TOOL", call.tool)
+        .env("MCPD_CORRELATION_ID", call.correlation_id)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to start {} hook '{}'", stage, program))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = serde_json::to_vec(input)?;
+    // Hooks that don't read their input close the pipe early; that's fine
+    let write = async move {
+        let _ = stdin.write_all(&input).await;
+    };
+    let output = match tokio::time::timeout(timeout, async {
+        tokio::join!(write, child.wait_with_output()).1
+    })
+    .await
+    {
+        Ok(output) => output.with_context(|| format!("{} hook '{}' failed", stage, program))?,
+        Err(_) => bail!("{} hook '{}' timed out after {:?}", stage, program, timeout),
+    };
+
+    if !output.status.success() {
+        let failed = format!("{} hook '{}' exited with {}", stage, program, output.status);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = crate::limits::truncate_for_log(stderr.trim(), MAX_STDERR_IN_ERROR);
+        if stderr.is_empty() {
+            bail!(failed);
+        }
+        // Quoted to clients framed as untrusted (see error_text)
+        return Err(anyhow::Error::new(ForeignText {
+            origin: "hook stderr",
+            text: stderr,
+        })
+        .context(failed));
+    }
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    serde_json::from_slice(&output.stdout)
+        .map(Some)
+        .with_context(|| format!("{} hook '{}' printed invalid JSON", stage, program))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const CALL: Call = Call {
+        backend: "fs",
+        tool: "read",
+        correlation_id: "mcpd-1-1",
+    };
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    async fn run_sh(script: &str, timeout: Duration) -> Result<Option<serde_json::Value>> {
+        run(
+            &sh(script),
+            Stage::PreCall,
+            CALL,
+            &json!({"path": "/tmp"}),
+            timeout,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn output_replaces_input() {
+        let out = run_sh("cat", DEFAULT_HOOK_TIMEOUT).await.unwrap();
+        assert_eq!(out, Some(json!({"path": "/tmp"})));
+        let out = run_sh(
+            r#"printf '{"tool":"%s","hook":"%s"}' "$MCPD_TOOL" "$MCPD_HOOK""#,
+            DEFAULT_HOOK_TIMEOUT,
+        )
+        .await
+        .unwrap();
+        assert_eq!(out, Some(json!({"tool": "read", "hook": "pre_call"})));
+    }
+
+    #[tokio::test]
+    async fn silent_hook_keeps_input() {
+        let out = run_sh("cat > /dev/null", DEFAULT_HOOK_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(out, None);
+    }
+
+    #[tokio::test]
+    async fn failures_are_errors() {
+        let err = run_sh(
+            "echo 'no paths under /tmp' >&2; exit 3",
+            DEFAULT_HOOK_TIMEOUT,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("no paths under /tmp"),
+            "{}",
+            err
+        );
+        assert!(err.downcast_ref::<ForeignText>().is_some());
+
+        let err = run_sh("echo not json", DEFAULT_HOOK_TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid JSON"), "{}", err);
+
+        let started = std::time::Instant::now();
+        let err = run_sh("sleep 5", Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,217 @@
+//! Backend icons, kept in the registry as `data:` URIs.
+//!
+//! `register --icon` takes a `data:` URI or an image file. Files are read and
+//! base64-embedded so the registry stays self-contained. Either way the bytes
+//! must look like a PNG, JPEG, GIF, WebP or SVG image (judged by content, not
+//! by file name or declared type) and fit in `MAX_ICON_BYTES`.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Largest icon, in decoded bytes
+pub const MAX_ICON_BYTES: usize = 64 * 1024;
+
+/// Why an icon can't be used
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum IconError {
+    #[error("Couldn't read icon file {path}: {error}")]
+    Unreadable { path: String, error: String },
+    #[error("Icon is {size} bytes; icons can be at most {max} bytes")]
+    TooLarge { size: usize, max: usize },
+    #[error("Icon isn't a PNG, JPEG, GIF, WebP or SVG image")]
+    NotAnImage,
+    #[error("Icon data: URI isn't base64 (expected data:<type>;base64,<data>)")]
+    NotBase64,
+}
+
+/// The image type `bytes` start like, if any
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if bytes.starts_with(PNG) {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if is_svg(bytes) {
+        Some("image/svg+xml")
+    } else {
+        None
+    }
+}
+
+/// Whether `bytes` open an SVG document: markup whose first element, after
+/// any XML declaration, comments or doctype, is `<svg`
+fn is_svg(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(1024)];
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // The 1 KiB cut can land inside a character
+        Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap_or_default(),
+    };
+    let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+    while rest.starts_with("<?") || rest.starts_with("<!") {
+        let Some(end) = rest.find('>') else {
+            return false;
+        };
+        rest = rest[end + 1..].trim_start();
+    }
+    rest.starts_with("<svg")
+}
+
+/// `bytes` as a `data:` URI, if they're a usable image
+fn encode(bytes: &[u8]) -> Result<String, IconError> {
+    if bytes.len() > MAX_ICON_BYTES {
+        return Err(IconError::TooLarge {
+            size: bytes.len(),
+            max: MAX_ICON_BYTES,
+        });
+    }
+    let mime = sniff(bytes).ok_or(IconError::NotAnImage)?;
+    Ok(format!("data:{};base64,{}", mime, STANDARD.encode(bytes)))
+}
+
+/// The bytes a base64 `data:` URI holds
+fn decode(uri: &str) -> Result<Vec<u8>, IconError> {
+    let (header, data) = uri
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+        .ok_or(IconError::NotBase64)?;
+    if !header.ends_with(";base64") {
+        return Err(IconError::NotBase64);
+    }
+    STANDARD.decode(data).map_err(|_| IconError::NotBase64)
+}
+
+/// The icon `source` names, a `data:` URI or an image file, as a `data:` URI
+/// typed by its content
+pub fn embed(source: &str) -> Result<String, IconError> {
+    if source.starts_with("data:") {
+        return encode(&decode(source)?);
+    }
+    let unreadable = |e: std::io::Error| IconError::Unreadable {
+        path: source.to_string(),
+        error: e.to_string(),
+    };
+    // Refuse big files before reading them in
+    let size = std::fs::metadata(source).map_err(unreadable)?.len();
+    if size > MAX_ICON_BYTES as u64 {
+        return Err(IconError::TooLarge {
+            size: size as usize,
+            max: MAX_ICON_BYTES,
+        });
+    }
+    encode(&std::fs::read(source).map_err(unreadable)?)
+}
+
+/// The image type of an embedded icon, checking it's one `embed` would make
+pub fn check(uri: &str) -> Result<&'static str, IconError> {
+    let bytes = decode(uri)?;
+    if bytes.len() > MAX_ICON_BYTES {
+        return Err(IconError::TooLarge {
+            size: bytes.len(),
+            max: MAX_ICON_BYTES,
+        });
+    }
+    sniff(&bytes).ok_or(IconError::NotAnImage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// A 1x1 PNG
+    const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+    /// A 1x1 GIF
+    const GIF: &str = "R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7";
+    const SVG: &str = r#"<?xml version="1.0"?>
+<!-- logo -->
+<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1"/>"#;
+
+    fn write(dir: &Path, name: &str, bytes: &[u8]) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn sniffs_images_by_content() {
+        let png = STANDARD.decode(PNG).unwrap();
+        assert_eq!(sniff(&png), Some("image/png"));
+        assert_eq!(sniff(&STANDARD.decode(GIF).unwrap()), Some("image/gif"));
+        assert_eq!(sniff(b"\xff\xd8\xff\xe0\0\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff(SVG.as_bytes()), Some("image/svg+xml"));
+        assert_eq!(sniff(b"<html><svg/></html>"), None);
+        assert_eq!(sniff(b"hello"), None);
+        assert_eq!(sniff(&png[1..]), None);
+    }
+
+    #[test]
+    fn embeds_image_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // The extension doesn't matter, the content does
+        let path = write(dir.path(), "logo.dat", &STANDARD.decode(PNG).unwrap());
+        let uri = embed(&path).unwrap();
+        assert_eq!(uri, format!("data:image/png;base64,{}", PNG));
+        assert_eq!(check(&uri), Ok("image/png"));
+
+        let path = write(dir.path(), "logo.svg", SVG.as_bytes());
+        assert!(
+            embed(&path)
+                .unwrap()
+                .starts_with("data:image/svg+xml;base64,")
+        );
+    }
+
+    #[test]
+    fn data_uris_are_retyped_by_content() {
+        let uri = embed(&format!("data:image/png;base64,{}", GIF)).unwrap();
+        assert_eq!(uri, format!("data:image/gif;base64,{}", GIF));
+        assert_eq!(
+            embed("data:image/png,not-base64"),
+            Err(IconError::NotBase64)
+        );
+        assert_eq!(
+            embed("data:image/png;base64,%%%"),
+            Err(IconError::NotBase64)
+        );
+    }
+
+    #[test]
+    fn refuses_non_images() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = write(dir.path(), "logo.png", b"#!/bin/sh\necho hi\n");
+        assert_eq!(embed(&path), Err(IconError::NotAnImage));
+        let uri = format!("data:image/png;base64,{}", STANDARD.encode("text"));
+        assert_eq!(check(&uri), Err(IconError::NotAnImage));
+        assert!(matches!(
+            embed(&dir.path().join("missing.png").to_string_lossy()),
+            Err(IconError::Unreadable { .. })
+        ));
+    }
+
+    #[test]
+    fn refuses_oversized_icons() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut big = STANDARD.decode(PNG).unwrap();
+        big.resize(MAX_ICON_BYTES + 1, 0);
+        let path = write(dir.path(), "big.png", &big);
+        let too_large = IconError::TooLarge {
+            size: MAX_ICON_BYTES + 1,
+            max: MAX_ICON_BYTES,
+        };
+        assert_eq!(embed(&path), Err(too_large.clone()));
+        let uri = format!("data:image/png;base64,{}", STANDARD.encode(&big));
+        assert_eq!(embed(&uri), Err(too_large.clone()));
+        assert_eq!(check(&uri), Err(too_large));
+
+        // Right at the cap is fine
+        big.truncate(MAX_ICON_BYTES);
+        let path = write(dir.path(), "max.png", &big);
+        assert!(embed(&path).is_ok());
+    }
+}
@@ -0,0 +1,556 @@
+//! Write-ahead intent log for destructive tool calls (`serve --intent-log`).
+//!
+//! Before a call classified destructive is forwarded, an `intent` entry is
+//! appended and synced to disk; when the call comes back, an `outcome` entry
+//! follows. So the log shows every destructive call a backend may have run,
+//! even if mcpd died before it saw the answer: such an intent simply has no
+//! outcome. Each start of mcpd appends an `open` entry first, which tells a
+//! call cut off by a crash apart from one still in flight.
+//!
+//! Entries are JSONL, numbered by `seq` and hash-chained: `hash` is the
+//! SHA-256 of the entry's canonical encoding (without `hash`), which
+//! includes `prev`, the hash of the entry before it. Editing, removing or
+//! reordering entries breaks the chain; `verify` finds where. Cutting whole
+//! entries off the end can't be seen from the file alone, so `verify`
+//! reports the head (last `seq` and hash) to compare with a copy kept
+//! elsewhere.
+
+use crate::canonical::{self, Nulls};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// `prev` of the first entry
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Position in the log, from 1
+    pub seq: u64,
+    /// `hash` of the entry before, or `GENESIS`
+    pub prev: String,
+    #[serde(flatten)]
+    pub record: Record,
+    /// SHA-256 of this entry's canonical encoding without `hash`
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Record {
+    /// mcpd started writing to the log
+    Open {
+        timestamp: DateTime<Utc>,
+        pid: u32,
+        /// The file ended partway through an entry, left by a crash mid-write
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        torn_tail: bool,
+    },
+    /// A destructive call is about to be forwarded
+    Intent {
+        timestamp: DateTime<Utc>,
+        correlation_id: String,
+        backend: String,
+        tool: String,
+        /// Hex SHA-256 of the canonical encoding of the arguments sent
+        arguments_sha256: String,
+        /// The arguments sent, with secret-looking values redacted
+        arguments: Value,
+        /// Why the call was classified destructive
+        decision: String,
+    },
+    /// How the call with intent `intent` ended
+    Outcome {
+        timestamp: DateTime<Utc>,
+        correlation_id: String,
+        /// `seq` of the intent entry
+        intent: u64,
+        status: Status,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Success,
+    /// An error result, or a failure before there was one
+    Error,
+    /// The backend didn't answer, or didn't start, within the call's timeout
+    Timeout,
+    /// The client stopped waiting; the backend may or may not have finished
+    Abandoned,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Status::Success => "success",
+            Status::Error => "error",
+            Status::Timeout => "timeout",
+            Status::Abandoned => "abandoned",
+        })
+    }
+}
+
+/// The hash an entry's `hash` should hold, from its JSON form
+fn entry_hash(entry: &Value) -> String {
+    let mut entry = entry.clone();
+    if let Some(fields) = entry.as_object_mut() {
+        fields.remove("hash");
+    }
+    canonical::hash(&entry, Nulls::Keep)
+}
+
+/// An open intent log, appended to by the calls of one mcpd
+#[derive(Debug)]
+pub struct IntentLog {
+    state: Mutex<Writer>,
+}
+
+#[derive(Debug)]
+struct Writer {
+    file: File,
+    /// `seq` and `hash` of the last entry
+    seq: u64,
+    head: String,
+}
+
+impl IntentLog {
+    /// Open `path` for appending, creating it if needed, and record the start
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open intent log {}", path.display()))?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)
+            .with_context(|| format!("Failed to read intent log {}", path.display()))?;
+
+        // Carry on the chain from the last whole entry. Anything after it was
+        // cut short by a crash; `verify` accepts that only when the `open`
+        // entry that follows says so.
+        let lines: Vec<&str> = text.split_terminator('\n').collect();
+        let last = lines
+            .iter()
+            .rposition(|line| serde_json::from_str::<Entry>(line).is_ok());
+        let (seq, head) = match last {
+            Some(i) => {
+                let entry: Entry = serde_json::from_str(lines[i]).unwrap();
+                (entry.seq, entry.hash)
+            }
+            None => (0, GENESIS.to_string()),
+        };
+        let torn_tail = last.map_or(!lines.is_empty(), |i| i + 1 < lines.len());
+        if !text.is_empty() && !text.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+
+        let log = Self {
+            state: Mutex::new(Writer { file, seq, head }),
+        };
+        log.append(Record::Open {
+            timestamp: Utc::now(),
+            pid: std::process::id(),
+            torn_tail,
+        })?;
+        Ok(log)
+    }
+
+    /// Append `record` and sync it to disk, returning its `seq`. Blocks on
+    /// the disk; async callers should run it on a blocking thread.
+    pub fn append(&self, record: Record) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let mut entry = serde_json::to_value(Entry {
+            seq: state.seq + 1,
+            prev: state.head.clone(),
+            record,
+            hash: String::new(),
+        })?;
+        let hash = entry_hash(&entry);
+        entry["hash"] = Value::String(hash.clone());
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        state
+            .file
+            .write_all(&line)
+            .and_then(|()| state.file.sync_data())
+            .context("Failed to write intent log")?;
+        state.seq += 1;
+        state.head = hash;
+        Ok(state.seq)
+    }
+}
+
+/// An intent without an outcome
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interrupted {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    pub correlation_id: String,
+    pub backend: String,
+    pub tool: String,
+    /// mcpd was restarted after it, so the call won't get an outcome. If
+    /// not, the call may still be running.
+    pub restarted: bool,
+}
+
+/// What `verify` found
+#[derive(Debug, Default)]
+pub struct Report {
+    pub entries: usize,
+    pub opens: usize,
+    pub intents: usize,
+    pub outcomes: BTreeMap<Status, usize>,
+    pub interrupted: Vec<Interrupted>,
+    /// Entries cut short by a crash and acknowledged by the next `open`
+    pub recovered_tails: usize,
+    /// The file ends partway through an entry
+    pub torn_tail: bool,
+    /// Ways the file isn't the log mcpd wrote, by line number
+    pub problems: Vec<String>,
+    /// `seq` and `hash` of the last entry
+    pub head: Option<(u64, String)>,
+}
+
+impl Report {
+    /// Whether the chain is unbroken and the file ends on a whole entry
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty() && !self.torn_tail
+    }
+}
+
+/// Read and verify an intent log
+pub fn verify_file(path: &Path) -> Result<Report> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read intent log {}", path.display()))?;
+    Ok(verify(&String::from_utf8_lossy(&bytes)))
+}
+
+/// Check an intent log's hash chain and match intents with outcomes
+pub fn verify(text: &str) -> Report {
+    let mut report = Report::default();
+    let mut pending: BTreeMap<u64, Interrupted> = BTreeMap::new();
+    let (mut seq, mut head) = (0, GENESIS.to_string());
+    // A line that isn't an entry, waiting to see if an `open` explains it
+    let mut broken: Option<usize> = None;
+
+    let lines: Vec<&str> = text.split_terminator('\n').collect();
+    for (i, line) in lines.iter().enumerate() {
+        let number = i + 1;
+        let entry = serde_json::from_str::<Value>(line)
+            .ok()
+            .and_then(|value| Some((serde_json::from_value::<Entry>(value.clone()).ok()?, value)));
+        let Some((entry, value)) = entry else {
+            if i + 1 == lines.len() && !text.ends_with('\n') {
+                report.torn_tail = true;
+            } else if let Some(earlier) = broken.replace(number) {
+                report
+                    .problems
+                    .push(format!("line {}: not an intent log entry", earlier));
+            }
+            continue;
+        };
+        if let Some(line) = broken.take() {
+            if !matches!(
+                entry.record,
+                Record::Open {
+                    torn_tail: true,
+                    ..
+                }
+            ) {
+                report
+                    .problems
+                    .push(format!("line {}: not an intent log entry", line));
+            } else {
+                report.recovered_tails += 1;
+            }
+        }
+
+        report.entries += 1;
+        if entry_hash(&value) != entry.hash {
+            report.problems.push(format!(
+                "line {}: hash doesn't match the entry (edited?)",
+                number
+            ));
+        }
+        if entry.prev != head || entry.seq != seq + 1 {
+            report.problems.push(format!(
+                "line {}: chain broken before entry {} (entries missing, added or reordered)",
+                number, entry.seq
+            ));
+        }
+        (seq, head) = (entry.seq, entry.hash.clone());
+
+        match entry.record {
+            Record::Open { .. } => {
+                report.opens += 1;
+                for call in pending.values_mut() {
+                    call.restarted = true;
+                }
+            }
+            Record::Intent {
+                timestamp,
+                correlation_id,
+                backend,
+                tool,
+                ..
+            } => {
+                report.intents += 1;
+                pending.insert(
+                    entry.seq,
+                    Interrupted {
+                        seq: entry.seq,
+                        timestamp,
+                        correlation_id,
+                        backend,
+                        tool,
+                        restarted: false,
+                    },
+                );
+            }
+            Record::Outcome { intent, status, .. } => {
+                *report.outcomes.entry(status).or_default() += 1;
+                if pending.remove(&intent).is_none() {
+                    report.problems.push(format!(
+                        "line {}: outcome for entry {}, which isn't an open intent",
+                        number, intent
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(line) = broken {
+        report
+            .problems
+            .push(format!("line {}: not an intent log entry", line));
+    }
+    report.interrupted = pending.into_values().collect();
+    report.head = (seq > 0).then_some((seq, head));
+    report
+}
+
+/// What `mcpd audit verify` prints
+pub fn summary(report: &Report) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    let outcomes: usize = report.outcomes.values().sum();
+    writeln!(
+        out,
+        "{} entries from {} mcpd start(s): {} destructive calls, {} outcomes",
+        report.entries, report.opens, report.intents, outcomes
+    )
+    .unwrap();
+    for (status, count) in &report.outcomes {
+        writeln!(out, "  {}: {}", status, count).unwrap();
+    }
+    if !report.interrupted.is_empty() {
+        writeln!(out, "Calls without an outcome:").unwrap();
+        for call in &report.interrupted {
+            let why = if call.restarted {
+                "interrupted, mcpd restarted"
+            } else {
+                "in flight, or mcpd stopped"
+            };
+            writeln!(
+                out,
+                "  #{} {} {} {}:{} ({})",
+                call.seq,
+                call.timestamp.to_rfc3339(),
+                call.correlation_id,
+                call.backend,
+                call.tool,
+                why
+            )
+            .unwrap();
+        }
+    }
+    if report.recovered_tails > 0 {
+        writeln!(
+            out,
+            "{} entry(s) cut short by a crash, acknowledged on restart",
+            report.recovered_tails
+        )
+        .unwrap();
+    }
+    for problem in &report.problems {
+        writeln!(out, "Problem: {}", problem).unwrap();
+    }
+    if report.torn_tail {
+        writeln!(
+            out,
+            "Problem: the file ends partway through an entry (cut, or mcpd died mid-write)"
+        )
+        .unwrap();
+    }
+    if let Some((seq, hash)) = &report.head {
+        writeln!(out, "Head: #{} {}", seq, hash).unwrap();
+    }
+    writeln!(
+        out,
+        "{}",
+        if report.is_intact() {
+            "Chain intact"
+        } else {
+            "Chain NOT intact"
+        }
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn intent(correlation_id: &str) -> Record {
+        Record::Intent {
+            timestamp: Utc::now(),
+            correlation_id: correlation_id.to_string(),
+            backend: "fs".to_string(),
+            tool: "delete_file".to_string(),
+            arguments_sha256: canonical::hash(&json!({"path": "/tmp/a"}), Nulls::Keep),
+            arguments: json!({"path": "/tmp/a"}),
+            decision: "name matches 'delete'".to_string(),
+        }
+    }
+
+    fn outcome(intent: u64, status: Status) -> Record {
+        Record::Outcome {
+            timestamp: Utc::now(),
+            correlation_id: "mcpd-1-1".to_string(),
+            intent,
+            status,
+            error: None,
+        }
+    }
+
+    /// A log with one finished call and one still in flight
+    fn sample(dir: &Path) -> std::path::PathBuf {
+        let path = dir.join("intents.jsonl");
+        let log = IntentLog::open(&path).unwrap();
+        let first = log.append(intent("mcpd-1-1")).unwrap();
+        log.append(outcome(first, Status::Success)).unwrap();
+        log.append(intent("mcpd-1-2")).unwrap();
+        path
+    }
+
+    #[test]
+    fn entries_are_hash_chained() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let text = std::fs::read_to_string(sample(dir.path())).unwrap();
+        let entries: Vec<Entry> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries[0].prev, GENESIS);
+        for pair in entries.windows(2) {
+            assert_eq!(pair[1].prev, pair[0].hash);
+            assert_eq!(pair[1].seq, pair[0].seq + 1);
+        }
+
+        let report = verify(&text);
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!((report.entries, report.opens, report.intents), (4, 1, 2));
+        assert_eq!(report.outcomes[&Status::Success], 1);
+        assert_eq!(report.interrupted.len(), 1);
+        assert_eq!(report.interrupted[0].correlation_id, "mcpd-1-2");
+        assert!(!report.interrupted[0].restarted);
+        assert_eq!(report.head, Some((4, entries[3].hash.clone())));
+    }
+
+    #[test]
+    fn reopening_continues_the_chain() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = sample(dir.path());
+        let log = IntentLog::open(&path).unwrap();
+        log.append(intent("mcpd-2-1")).unwrap();
+
+        let report = verify_file(&path).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!(report.opens, 2);
+        // The intent left in flight by the first mcpd never finishes
+        let restarted: Vec<_> = report.interrupted.iter().map(|c| c.restarted).collect();
+        assert_eq!(restarted, [true, false]);
+    }
+
+    #[test]
+    fn detects_edits_and_removed_entries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let text = std::fs::read_to_string(sample(dir.path())).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        let edited = text.replace("/tmp/a", "/tmp/b");
+        let report = verify(&edited);
+        assert!(!report.is_intact());
+        assert!(report.problems[0].contains("hash doesn't match"));
+
+        let removed = format!("{}\n{}\n{}\n", lines[0], lines[1], lines[3]);
+        let report = verify(&removed);
+        assert_eq!(
+            report.problems,
+            ["line 3: chain broken before entry 4 (entries missing, added or reordered)"]
+        );
+
+        let garbage = format!("{}\nnot json\n{}\n", lines[0], lines[1]);
+        assert_eq!(
+            verify(&garbage).problems,
+            ["line 2: not an intent log entry"]
+        );
+    }
+
+    #[test]
+    fn truncated_file_fails_verification() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = sample(dir.path());
+        let text = std::fs::read_to_string(&path).unwrap();
+        let cut = &text[..text.len() - 20];
+        let report = verify(cut);
+        assert!(report.torn_tail);
+        assert!(report.problems.is_empty());
+        assert!(!report.is_intact());
+        // The cut-off intent doesn't count, the call before it is whole
+        assert_eq!((report.entries, report.intents), (3, 1));
+        assert_eq!(report.outcomes[&Status::Success], 1);
+
+        // Cut between entries, what's left is a shorter valid log; only the
+        // head tells
+        let lines: Vec<&str> = text.lines().collect();
+        let report = verify(&format!("{}\n{}\n", lines[0], lines[1]));
+        assert!(report.is_intact());
+        assert_eq!(report.head.unwrap().0, 2);
+    }
+
+    #[test]
+    fn open_after_a_torn_write_recovers() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = sample(dir.path());
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, &text[..text.len() - 20]).unwrap();
+
+        let log = IntentLog::open(&path).unwrap();
+        let seq = log.append(intent("mcpd-2-1")).unwrap();
+        log.append(outcome(seq, Status::Error)).unwrap();
+
+        let report = verify_file(&path).unwrap();
+        assert!(report.is_intact(), "{:?}", report.problems);
+        assert_eq!(report.recovered_tails, 1);
+        assert_eq!(report.outcomes[&Status::Error], 1);
+        // The torn line needs the acknowledgment of the open after it
+        let text = std::fs::read_to_string(&path).unwrap();
+        let unacknowledged = text.replace(r#","torn_tail":true"#, "");
+        assert!(!verify(&unacknowledged).is_intact());
+    }
+}
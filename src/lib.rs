@@ -1,5 +1,52 @@
+#![forbid(unsafe_code)]
+
+pub mod activity;
+pub mod audit;
+pub mod balance;
+pub mod builtins;
+pub mod canonical;
+pub mod catalog;
+pub mod chaos;
 pub mod cli;
+pub mod compat;
+pub mod compose;
+pub mod config_diff;
+pub mod conflicts;
+pub mod connections;
+pub mod control;
+pub mod error_text;
+pub mod framing;
+pub mod functions;
+pub mod health;
+pub mod hooks;
+pub mod icons;
+pub mod intent;
+pub mod limits;
 pub mod mcp;
+pub mod merge;
+pub mod middleware;
+pub mod naming;
+pub mod offline;
+pub mod output;
+pub mod ping;
+pub mod pinning;
 pub mod proxy;
+pub mod ratelimit;
 pub mod registry;
+pub mod roots;
+pub mod scheduler;
+pub mod schema;
+pub mod secret_env;
+pub mod secrets;
 pub mod server;
+pub mod structured;
+pub mod subscriptions;
+pub mod telemetry;
+pub mod tool_concurrency;
+pub mod top;
+pub mod transcript;
+pub mod transform;
+pub mod transport;
+pub mod validate;
+pub mod verify;
+pub mod watchdog;
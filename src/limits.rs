@@ -0,0 +1,373 @@
+//! Guards against oversized or pathologically nested JSON messages.
+//!
+//! Both ingestion paths (client stdin and backend stdout) read lines through
+//! `read_line_bounded` and run `JsonLimits::check_depth` before handing bytes
+//! to serde, so a hostile peer can't exhaust memory or the stack.
+
+use crate::mcp::RequestId;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Size and nesting limits applied to every incoming JSON message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JsonLimits {
+    /// Maximum nesting depth of arrays/objects
+    pub max_depth: usize,
+    /// Maximum size of a single message in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for JsonLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// Why a message was rejected
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LimitViolation {
+    #[error("message exceeds maximum nesting depth of {max}")]
+    TooDeep { max: usize },
+    #[error("message of {size} bytes exceeds maximum size of {max} bytes")]
+    TooLarge { size: usize, max: usize },
+}
+
+/// A message ran past the most bytes a reader will go through for one
+/// message, so it stopped reading instead of draining the rest. Returned
+/// inside an `io::Error` of kind `InvalidData`; the stream is out of sync
+/// afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("message exceeds {max} bytes; stopped reading it")]
+pub struct TooLargeToRead {
+    pub max: usize,
+}
+
+impl TooLargeToRead {
+    /// The `TooLargeToRead` inside `error`, if that's what it is
+    pub fn find(error: &std::io::Error) -> Option<&Self> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl JsonLimits {
+    /// Check nesting depth with a linear pre-scan, without recursing.
+    pub fn check_depth(&self, bytes: &[u8]) -> Result<(), LimitViolation> {
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for &b in bytes {
+            if in_string {
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'"' => in_string = true,
+                b'[' | b'{' => {
+                    depth += 1;
+                    if depth > self.max_depth {
+                        return Err(LimitViolation::TooDeep {
+                            max: self.max_depth,
+                        });
+                    }
+                }
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of `read_line_bounded`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadLine {
+    /// End of stream, nothing read
+    Eof,
+    /// A complete line (without the trailing newline) is in the buffer
+    Line,
+    /// The line exceeded the limit. The buffer holds only the first
+    /// `max_bytes`; the rest was consumed and discarded.
+    TooLong { size: usize },
+}
+
+/// Read one newline-terminated line into `buf` (cleared first), keeping at most
+/// `max_bytes`. Oversized lines are drained to the next newline so the stream
+/// stays in sync.
+pub async fn read_line_bounded<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_bytes: usize,
+) -> std::io::Result<ReadLine> {
+    read_line_capped(reader, buf, max_bytes, usize::MAX).await
+}
+
+/// `read_line_bounded`, but a line longer than `abort_after` bytes isn't
+/// drained: reading stops with a `TooLargeToRead` error once it's past that.
+pub async fn read_line_capped<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max_bytes: usize,
+    abort_after: usize,
+) -> std::io::Result<ReadLine> {
+    buf.clear();
+    let mut size = 0usize;
+    let mut saw_any = false;
+
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break;
+        }
+        saw_any = true;
+
+        let (chunk, done) = match available.iter().position(|&b| b == b'\n') {
+            Some(pos) => (&available[..pos], pos + 1),
+            None => (available, available.len()),
+        };
+        let found_newline = done > chunk.len();
+
+        size += chunk.len();
+        let room = max_bytes.saturating_sub(buf.len());
+        buf.extend_from_slice(&chunk[..chunk.len().min(room)]);
+
+        reader.consume(done);
+        if size > abort_after {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                TooLargeToRead { max: abort_after },
+            ));
+        }
+        if found_newline {
+            break;
+        }
+    }
+
+    if !saw_any {
+        return Ok(ReadLine::Eof);
+    }
+    if size > max_bytes {
+        return Ok(ReadLine::TooLong { size });
+    }
+    Ok(ReadLine::Line)
+}
+
+/// Best-effort extraction of the top-level `"id"` from a message that can't be
+/// fully parsed (too deep, truncated). Only numeric and plain string ids are
+/// recognized.
+pub fn peek_id(bytes: &[u8]) -> Option<RequestId> {
+    let mut depth = 0usize;
+    let mut i = 0usize;
+    let mut last_key: Option<&[u8]> = None;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let start = i + 1;
+                let mut j = start;
+                while j < bytes.len() && bytes[j] != b'"' {
+                    if bytes[j] == b'\\' {
+                        j += 1;
+                    }
+                    j += 1;
+                }
+                if j >= bytes.len() {
+                    return None;
+                }
+                if depth == 1 {
+                    last_key = Some(&bytes[start..j]);
+                }
+                i = j + 1;
+                continue;
+            }
+            b'{' | b'[' => {
+                depth += 1;
+                last_key = None;
+            }
+            b'}' | b']' => {
+                depth = depth.saturating_sub(1);
+                last_key = None;
+            }
+            b',' => last_key = None,
+            b':' if depth == 1 && last_key == Some(b"id".as_slice()) => {
+                return parse_id_value(&bytes[i + 1..]);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn parse_id_value(bytes: &[u8]) -> Option<RequestId> {
+    let rest = std::str::from_utf8(bytes).ok().or_else(|| {
+        // Truncated buffers may end mid-codepoint; ids live near the start anyway
+        let valid = std::str::from_utf8(bytes).err()?.valid_up_to();
+        std::str::from_utf8(&bytes[..valid]).ok()
+    })?;
+    let rest = rest.trim_start();
+
+    if let Some(s) = rest.strip_prefix('"') {
+        let end = s.find('"')?;
+        let id = &s[..end];
+        if id.contains('\\') {
+            return None;
+        }
+        return Some(RequestId::String(id.to_string()));
+    }
+
+    let end = rest
+        .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+        .unwrap_or(rest.len());
+    rest[..end].parse::<i64>().ok().map(RequestId::Number)
+}
+
+/// Shorten text for logs and error messages, marking how much was cut.
+pub fn truncate_for_log(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(max_chars).collect();
+    format!("{}... ({} more chars)", head, total - max_chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::BufReader;
+
+    fn nested(depth: usize) -> String {
+        format!("{}{}", "[".repeat(depth), "]".repeat(depth))
+    }
+
+    #[test]
+    fn depth_within_limit() {
+        let limits = JsonLimits::default();
+        assert!(limits.check_depth(nested(128).as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn depth_over_limit() {
+        let limits = JsonLimits::default();
+        assert_eq!(
+            limits.check_depth(nested(100_000).as_bytes()),
+            Err(LimitViolation::TooDeep { max: 128 })
+        );
+    }
+
+    #[test]
+    fn depth_ignores_brackets_in_strings() {
+        let limits = JsonLimits {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let msg = r#"{"text": "[[[[{{{{ \"[[[[\" "}"#;
+        assert!(limits.check_depth(msg.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn wide_document_is_fine() {
+        let limits = JsonLimits::default();
+        let wide = format!("[{}]", vec!["1"; 100_000].join(","));
+        assert!(limits.check_depth(wide.as_bytes()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn read_line_bounded_lines_and_eof() {
+        let mut reader = BufReader::new(&b"one\ntwo\n"[..]);
+        let mut buf = Vec::new();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut buf, 10).await.unwrap(),
+            ReadLine::Line
+        );
+        assert_eq!(buf, b"one");
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut buf, 10).await.unwrap(),
+            ReadLine::Line
+        );
+        assert_eq!(buf, b"two");
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut buf, 10).await.unwrap(),
+            ReadLine::Eof
+        );
+    }
+
+    #[tokio::test]
+    async fn read_line_bounded_too_long_stays_in_sync() {
+        let input = format!("{}\nnext\n", "x".repeat(50));
+        let mut reader = BufReader::with_capacity(8, input.as_bytes());
+        let mut buf = Vec::new();
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut buf, 10).await.unwrap(),
+            ReadLine::TooLong { size: 50 }
+        );
+        assert_eq!(buf.len(), 10);
+        assert_eq!(
+            read_line_bounded(&mut reader, &mut buf, 10).await.unwrap(),
+            ReadLine::Line
+        );
+        assert_eq!(buf, b"next");
+    }
+
+    #[tokio::test]
+    async fn read_line_capped_stops_reading_past_the_cap() {
+        let input = format!("short\n{}\nnext\n", "x".repeat(1000));
+        let mut reader = BufReader::with_capacity(8, input.as_bytes());
+        let mut buf = Vec::new();
+        assert_eq!(
+            read_line_capped(&mut reader, &mut buf, 10, 100)
+                .await
+                .unwrap(),
+            ReadLine::Line
+        );
+        let e = read_line_capped(&mut reader, &mut buf, 10, 100)
+            .await
+            .unwrap_err();
+        assert_eq!(TooLargeToRead::find(&e), Some(&TooLargeToRead { max: 100 }));
+        // Stopped soon after the cap instead of draining the line
+        let rest = reader.fill_buf().await.unwrap().len() + reader.get_ref().len();
+        assert!(rest > 800, "{} bytes left", rest);
+    }
+
+    #[test]
+    fn peek_id_number_and_string() {
+        let deep = format!(r#"{{"jsonrpc":"2.0","id":42,"result":{}}}"#, nested(500));
+        assert_eq!(peek_id(deep.as_bytes()), Some(RequestId::Number(42)));
+        assert_eq!(
+            peek_id(br#"{"id": "abc", "method": "x"}"#),
+            Some(RequestId::String("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn peek_id_ignores_nested_ids() {
+        assert_eq!(peek_id(br#"{"params": {"id": 7}}"#), None);
+        assert_eq!(
+            peek_id(br#"{"params": {"id": 7}, "id": 8}"#),
+            Some(RequestId::Number(8))
+        );
+    }
+
+    #[test]
+    fn peek_id_truncated() {
+        assert_eq!(
+            peek_id(br#"{"jsonrpc":"2.0","id":5,"result":{"a":"unterminated"#),
+            Some(RequestId::Number(5))
+        );
+        assert_eq!(peek_id(br#"{"jsonrpc":"2."#), None);
+    }
+
+    #[test]
+    fn truncate_for_log_bounds_output() {
+        assert_eq!(truncate_for_log("short", 10), "short");
+        assert_eq!(truncate_for_log("abcdefghij", 4), "abcd... (6 more chars)");
+    }
+}
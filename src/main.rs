@@ -1,15 +1,41 @@
+#![forbid(unsafe_code)]
+
 use anyhow::Result;
 use clap::Parser;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::registry()
+fn main() -> Result<()> {
+    let cli = mcpd::cli::Cli::parse();
+
+    // Built before the runtime starts: the OTLP exporter's blocking client can't
+    // be created or dropped from inside it
+    #[cfg(feature = "otel")]
+    let tracer_provider = cli
+        .otel_endpoint()
+        .map(|endpoint| mcpd::telemetry::otlp_provider(&endpoint))
+        .transpose()?;
+
+    let subscriber = tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "mcpd=info".into()),
         ))
-        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
-        .init();
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr));
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(tracer_provider.as_ref().map(mcpd::telemetry::layer));
+    subscriber.init();
+
+    let result = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(cli.run());
+
+    // Flush spans that are still batched
+    #[cfg(feature = "otel")]
+    if let Some(provider) = tracer_provider
+        && let Err(e) = provider.shutdown()
+    {
+        eprintln!("Failed to flush traces: {}", e);
+    }
 
-    mcpd::cli::Cli::parse().run().await
+    result
 }
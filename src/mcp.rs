@@ -43,12 +43,14 @@ pub struct RpcError {
     pub data: Option<Value>,
 }
 
-/// Request ID can be string or number
+/// Request ID can be string or number. `Null` is only used in error
+/// responses when the request's id couldn't be determined.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(untagged)]
 pub enum RequestId {
     Number(i64),
     String(String),
+    Null,
 }
 
 impl Request {
@@ -119,7 +121,35 @@ pub struct InitializeParams {
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub struct ClientCapabilities {}
+pub struct ClientCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roots: Option<RootsCapability>,
+    /// Not a client capability in the spec; clients that send it want
+    /// `notifications/message` from backends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootsCapability {
+    #[serde(default)]
+    pub list_changed: bool,
+}
+
+/// A directory the client is working in, as returned by `roots/list`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Root {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ListRootsResult {
+    #[serde(default)]
+    pub roots: Vec<Root>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientInfo {
@@ -133,6 +163,9 @@ pub struct InitializeResult {
     pub protocol_version: String,
     pub capabilities: ServerCapabilities,
     pub server_info: ServerInfo,
+    /// Free-form guidance on how to use the server, intended for the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -143,6 +176,13 @@ pub struct ServerCapabilities {
     pub resources: Option<ResourcesCapability>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub prompts: Option<PromptsCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logging: Option<LoggingCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<CompletionsCapability>,
+    /// Non-standard capabilities, by name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<serde_json::Map<String, Value>>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -157,6 +197,9 @@ pub struct ToolsCapability {
 pub struct ResourcesCapability {
     #[serde(default)]
     pub list_changed: bool,
+    /// Whether clients can watch a resource with `resources/subscribe`
+    #[serde(default)]
+    pub subscribe: bool,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -166,20 +209,144 @@ pub struct PromptsCapability {
     pub list_changed: bool,
 }
 
+/// The server sends `notifications/message` and accepts `logging/setLevel`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoggingCapability {}
+
+/// The server suggests argument values through `completion/complete`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompletionsCapability {}
+
+/// Severity of a log message, lowest first (the syslog levels)
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LoggingLevel {
+    #[default]
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+    Alert,
+    Emergency,
+}
+
+impl LoggingLevel {
+    pub fn is_debug(&self) -> bool {
+        *self == LoggingLevel::Debug
+    }
+}
+
+impl std::fmt::Display for LoggingLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LoggingLevel::Debug => "debug",
+            LoggingLevel::Info => "info",
+            LoggingLevel::Notice => "notice",
+            LoggingLevel::Warning => "warning",
+            LoggingLevel::Error => "error",
+            LoggingLevel::Critical => "critical",
+            LoggingLevel::Alert => "alert",
+            LoggingLevel::Emergency => "emergency",
+        })
+    }
+}
+
+/// Params of `notifications/message`
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggingMessageParams {
+    pub level: LoggingLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logger: Option<String>,
+    pub data: Value,
+}
+
+/// Params of `logging/setLevel`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetLevelParams {
+    pub level: LoggingLevel,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ServerInfo {
     pub name: String,
     pub version: String,
+    /// Display name, for UIs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub icons: Vec<Icon>,
+}
+
+/// An image a client may show for a server or tool
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Icon {
+    /// URL or `data:` URI of the image
+    pub src: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Tool {
     pub name: String,
+    /// Display name, for UIs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     #[serde(default)]
     pub description: Option<String>,
     #[serde(default)]
     pub input_schema: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+impl Tool {
+    /// The name a UI should show: `title`, else `annotations.title`
+    pub fn display_title(&self) -> Option<&str> {
+        self.title
+            .as_deref()
+            .or_else(|| self.annotations.as_ref()?.title.as_deref())
+    }
+
+    /// Whether the tool is annotated `destructiveHint: true` (and not
+    /// `readOnlyHint: true`). Unannotated tools aren't assumed destructive.
+    pub fn is_annotated_destructive(&self) -> bool {
+        let Some(annotations) = &self.annotations else {
+            return false;
+        };
+        let hint = |name| annotations.hints.get(name) == Some(&Value::Bool(true));
+        hint("destructiveHint") && !hint("readOnlyHint")
+    }
+}
+
+/// Hints about a tool's behavior. Only the title is read; the other hints
+/// are kept as they came.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ToolAnnotations {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub hints: serde_json::Map<String, Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -203,6 +370,9 @@ pub struct Resource {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListResourcesResult {
     pub resources: Vec<Resource>,
+    /// Listing metadata, such as backends that couldn't be asked
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -210,21 +380,45 @@ pub struct ReadResourceParams {
     pub uri: String,
 }
 
+/// Params of `resources/subscribe` and `resources/unsubscribe`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeParams {
+    pub uri: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReadResourceResult {
     pub contents: Vec<ResourceContent>,
+    /// Result metadata from the backend, passed through to the client
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One of the contents of a resource. A read can return several, of
+/// either kind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceContent {
     pub uri: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub text: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub blob: Option<String>,
+    #[serde(flatten)]
+    pub body: ResourceBody,
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+/// What a resource content holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResourceBody {
+    Text {
+        text: String,
+    },
+    /// Base64-encoded bytes
+    Blob {
+        blob: String,
+    },
 }
 
 // Prompt types
@@ -250,6 +444,9 @@ pub struct PromptArgument {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListPromptsResult {
     pub prompts: Vec<Prompt>,
+    /// Listing metadata, such as backends that couldn't be asked
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -285,6 +482,9 @@ pub struct CallToolParams {
     pub name: String,
     #[serde(default)]
     pub arguments: Value,
+    /// Request metadata, e.g. a W3C `traceparent`
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -292,14 +492,70 @@ pub struct CallToolResult {
     pub content: Vec<Content>,
     #[serde(default)]
     pub is_error: bool,
+    /// The result as JSON, for clients that read it instead of `content`
+    #[serde(
+        rename = "structuredContent",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub structured_content: Option<Value>,
+    /// Result metadata from the backend, passed through to the client
+    #[serde(rename = "_meta", default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Value>,
+}
+
+impl CallToolResult {
+    /// Convert to a JSON value, moving text content into it rather than
+    /// copying it the way `serde_json::to_value` does
+    pub fn into_value(mut self) -> serde_json::Result<Value> {
+        let texts: Vec<String> = self
+            .content
+            .iter_mut()
+            .map(|content| match content {
+                Content::Text { text } => std::mem::take(text),
+                _ => String::new(),
+            })
+            .collect();
+        let mut value = serde_json::to_value(&self)?;
+        if let Some(Value::Array(content)) = value.get_mut("content") {
+            for (item, text) in content.iter_mut().zip(texts) {
+                if let Some(slot) = item.get_mut("text") {
+                    *slot = Value::String(text);
+                }
+            }
+        }
+        Ok(value)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Content {
-    Text { text: String },
-    Image { data: String, mime_type: String },
-    Resource { resource: Value },
+    Text {
+        text: String,
+    },
+    Image {
+        data: String,
+        mime_type: String,
+    },
+    Resource {
+        resource: Value,
+    },
+    /// A reference to a resource, read with `resources/read`
+    #[serde(rename = "resource_link", rename_all = "camelCase")]
+    ResourceLink {
+        uri: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mime_type: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<u64>,
+    },
 }
 
 /// Protocol version we support
@@ -335,6 +591,100 @@ mod tests {
         assert_eq!(parsed.id, RequestId::Number(42));
     }
 
+    #[test]
+    fn call_tool_meta_roundtrip() {
+        let params: CallToolParams = serde_json::from_value(json!({
+            "name": "t",
+            "arguments": {},
+            "_meta": {"progressToken": 3, "vendor/x": [1]}
+        }))
+        .unwrap();
+        assert_eq!(
+            params.meta,
+            Some(json!({"progressToken": 3, "vendor/x": [1]}))
+        );
+        assert_eq!(
+            serde_json::to_value(&params).unwrap()["_meta"]["vendor/x"],
+            json!([1])
+        );
+
+        let result: CallToolResult = serde_json::from_value(json!({
+            "content": [],
+            "_meta": {"vendor/cost": 0.5}
+        }))
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&result).unwrap()["_meta"],
+            json!({"vendor/cost": 0.5})
+        );
+
+        // Absent stays absent
+        let result: CallToolResult = serde_json::from_value(json!({"content": []})).unwrap();
+        assert!(
+            serde_json::to_value(&result)
+                .unwrap()
+                .get("_meta")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn call_tool_result_into_value_matches_to_value() {
+        let result = CallToolResult {
+            content: vec![
+                Content::Text {
+                    text: "one \"quoted\"".to_string(),
+                },
+                Content::Image {
+                    data: "AAAA".to_string(),
+                    mime_type: "image/png".to_string(),
+                },
+                Content::Text {
+                    text: String::new(),
+                },
+            ],
+            is_error: true,
+            structured_content: None,
+            meta: Some(json!({"k": 1})),
+        };
+        let expected = serde_json::to_value(&result).unwrap();
+        assert_eq!(result.into_value().unwrap(), expected);
+    }
+
+    #[test]
+    fn resource_link_roundtrip() {
+        let wire = json!({
+            "type": "resource_link",
+            "uri": "file:///project/src/main.rs",
+            "name": "main.rs",
+            "mimeType": "text/x-rust"
+        });
+        let content: Content = serde_json::from_value(wire.clone()).unwrap();
+        let Content::ResourceLink {
+            uri,
+            name,
+            mime_type,
+            description,
+            ..
+        } = &content
+        else {
+            panic!("not a resource link: {:?}", content);
+        };
+        assert_eq!(uri, "file:///project/src/main.rs");
+        assert_eq!(name.as_deref(), Some("main.rs"));
+        assert_eq!(mime_type.as_deref(), Some("text/x-rust"));
+        assert_eq!(description, &None);
+        assert_eq!(serde_json::to_value(&content).unwrap(), wire);
+
+        // Only the URI is required
+        let bare: Content =
+            serde_json::from_value(json!({"type": "resource_link", "uri": "db://t"})).unwrap();
+        assert_eq!(
+            serde_json::to_value(&bare).unwrap(),
+            json!({"type": "resource_link", "uri": "db://t"})
+        );
+    }
+
     #[test]
     fn response_success_roundtrip() {
         let resp = Response::success(RequestId::Number(1), json!({"tools": []}));
@@ -381,6 +731,13 @@ mod tests {
         assert_eq!(parsed, id);
     }
 
+    #[test]
+    fn request_id_null_serde() {
+        let resp = Response::error(RequestId::Null, -32600, "Invalid request");
+        let json_val = serde_json::to_value(&resp).unwrap();
+        assert_eq!(json_val["id"], Value::Null);
+    }
+
     #[test]
     fn initialize_result_camel_case() {
         let result = InitializeResult {
@@ -389,11 +746,68 @@ mod tests {
             server_info: ServerInfo {
                 name: "test".to_string(),
                 version: "0.1.0".to_string(),
+                title: None,
+                website_url: Some("https://example.com".to_string()),
+                icons: Vec::new(),
             },
+            instructions: None,
         };
         let json_val = serde_json::to_value(&result).unwrap();
         assert!(json_val.get("protocolVersion").is_some());
-        assert!(json_val.get("serverInfo").is_some());
+        assert_eq!(
+            json_val["serverInfo"],
+            serde_json::json!({"name": "test", "version": "0.1.0", "websiteUrl": "https://example.com"})
+        );
+        assert!(json_val.get("instructions").is_none());
+    }
+
+    #[test]
+    fn tool_annotations_keep_unknown_hints() {
+        let tool: Tool = serde_json::from_value(serde_json::json!({
+            "name": "rm",
+            "inputSchema": {"type": "object"},
+            "annotations": {"title": "Remove file", "destructiveHint": true}
+        }))
+        .unwrap();
+        assert_eq!(tool.display_title(), Some("Remove file"));
+        let value = serde_json::to_value(&tool).unwrap();
+        assert_eq!(
+            value["annotations"],
+            serde_json::json!({"title": "Remove file", "destructiveHint": true})
+        );
+        assert!(value.get("title").is_none());
+    }
+
+    #[test]
+    fn destructive_only_when_annotated_so() {
+        let tool = |annotations: Value| -> Tool {
+            serde_json::from_value(serde_json::json!({"name": "rm", "annotations": annotations}))
+                .unwrap()
+        };
+        assert!(tool(serde_json::json!({"destructiveHint": true})).is_annotated_destructive());
+        assert!(
+            !tool(serde_json::json!({"destructiveHint": true, "readOnlyHint": true}))
+                .is_annotated_destructive()
+        );
+        assert!(!tool(serde_json::json!({"destructiveHint": false})).is_annotated_destructive());
+        // The spec's default of true isn't assumed
+        assert!(!tool(serde_json::json!({})).is_annotated_destructive());
+        assert!(!tool(Value::Null).is_annotated_destructive());
+    }
+
+    #[test]
+    fn initialize_result_parses_instructions() {
+        let json_str = r#"{
+            "protocolVersion": "2025-11-25",
+            "capabilities": {},
+            "serverInfo": {"name": "x", "version": "1"},
+            "instructions": "Call search before fetch."
+        }"#;
+        let result: InitializeResult = serde_json::from_str(json_str).unwrap();
+        assert_eq!(
+            result.instructions.as_deref(),
+            Some("Call search before fetch.")
+        );
     }
 
     #[test]
@@ -431,6 +845,63 @@ mod tests {
         assert_eq!(json_val, json!({}));
     }
 
+    #[test]
+    fn resources_capability_subscribe_defaults_false() {
+        let caps: ServerCapabilities =
+            serde_json::from_value(json!({"resources": {"listChanged": true}})).unwrap();
+        assert!(!caps.resources.unwrap().subscribe);
+        let caps: ServerCapabilities =
+            serde_json::from_value(json!({"resources": {"subscribe": true}})).unwrap();
+        assert!(caps.resources.unwrap().subscribe);
+    }
+
+    #[test]
+    fn logging_levels_order_by_severity() {
+        assert!(LoggingLevel::Debug < LoggingLevel::Info);
+        assert!(LoggingLevel::Warning < LoggingLevel::Error);
+        assert!(LoggingLevel::Alert < LoggingLevel::Emergency);
+
+        let params: LoggingMessageParams =
+            serde_json::from_value(json!({"level": "warning", "data": {"n": 1}})).unwrap();
+        assert_eq!(params.level, LoggingLevel::Warning);
+        assert_eq!(params.logger, None);
+        assert_eq!(
+            serde_json::to_value(&params).unwrap(),
+            json!({"level": "warning", "data": {"n": 1}})
+        );
+        assert!(serde_json::from_value::<SetLevelParams>(json!({"level": "loud"})).is_err());
+    }
+
+    #[test]
+    fn resource_contents_are_text_or_blob() {
+        let wire = json!({
+            "contents": [
+                {"uri": "file:///a.txt", "mimeType": "text/plain", "text": "hi"},
+                {"uri": "file:///a.png", "blob": "iVBORw0K", "_meta": {"k": 1}}
+            ],
+            "_meta": {"page": 2}
+        });
+        let result: ReadResourceResult = serde_json::from_value(wire.clone()).unwrap();
+        assert_eq!(
+            result.contents[0].body,
+            ResourceBody::Text {
+                text: "hi".to_string()
+            }
+        );
+        assert_eq!(
+            result.contents[1].body,
+            ResourceBody::Blob {
+                blob: "iVBORw0K".to_string()
+            }
+        );
+        assert_eq!(result.contents[1].meta, Some(json!({"k": 1})));
+        assert_eq!(serde_json::to_value(&result).unwrap(), wire);
+
+        // Neither is not a content
+        let bare = json!({"uri": "file:///a"});
+        assert!(serde_json::from_value::<ResourceContent>(bare).is_err());
+    }
+
     #[test]
     fn resource_optional_fields_skip() {
         let r = Resource {
@@ -0,0 +1,218 @@
+//! Merge groups: one exposed tool answered by several backends.
+//!
+//! When servers expose the same tool, say a `search` over different indexes,
+//! a registry entry can put its tool in a merge group with `merge_groups`.
+//! `list_tools` then shows the group once, under the group's own name with
+//! no server prefix, described by its first member. A call to it goes to
+//! every member at once, each as a call of its own through the middleware
+//! chain, and their results are combined:
+//!
+//! - Content comes in member order (registry order of their servers), not in
+//!   the order the answers arrive. Structured content is dropped, since
+//!   there's no one right way to combine it.
+//! - If some members fail, the call still succeeds with the others' content
+//!   and a note naming the ones that failed. Their errors are logged.
+//! - If every member fails, the call fails with each member's error.
+//!
+//! `_meta["mcpd/merged"]` lists which servers answered and which failed.
+
+use crate::mcp::{CallToolResult, Content, Tool as McpTool};
+use serde_json::json;
+use tracing::warn;
+
+/// One member of a merge group: its server and the server's tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    pub backend: String,
+    pub tool: String,
+}
+
+/// How a merge group is listed: its first member's tool under the group's
+/// name, saying where calls go
+pub fn listed(group: &str, first: &McpTool, backends: &[String]) -> McpTool {
+    let note = format!("Answered by {} together.", backends.join(", "));
+    McpTool {
+        name: group.to_string(),
+        description: Some(match &first.description {
+            Some(description) => format!("{} ({})", description, note),
+            None => note,
+        }),
+        ..first.clone()
+    }
+}
+
+/// Combine the members' outcomes, given in member order
+pub fn combine(
+    group: &str,
+    outcomes: Vec<(Member, Result<CallToolResult, String>)>,
+) -> CallToolResult {
+    let mut content = Vec::new();
+    let mut answered = Vec::new();
+    let mut failed = Vec::new();
+    let mut errors = Vec::new();
+    for (member, outcome) in outcomes {
+        match outcome {
+            Ok(result) if !result.is_error => {
+                content.extend(result.content);
+                answered.push(member.backend);
+            }
+            Ok(result) => {
+                errors.extend(result.content);
+                failed.push(member.backend);
+            }
+            Err(message) => {
+                errors.push(Content::Text { text: message });
+                failed.push(member.backend);
+            }
+        }
+    }
+    let meta = Some(json!({ "mcpd/merged": { "answered": answered, "failed": failed } }));
+
+    if answered.is_empty() {
+        let mut all = vec![Content::Text {
+            text: format!("Every server in merge group '{}' failed:", group),
+        }];
+        all.extend(errors);
+        return CallToolResult {
+            content: all,
+            is_error: true,
+            structured_content: None,
+            meta,
+        };
+    }
+    if !failed.is_empty() {
+        warn!(group, failed = ?failed, errors = ?errors, "Some members of a merge group failed, returning partial results");
+        content.push(Content::Text {
+            text: format!(
+                "Partial results: {} of merge group '{}' failed.",
+                failed.join(", "),
+                group
+            ),
+        });
+    }
+    CallToolResult {
+        content,
+        is_error: false,
+        structured_content: None,
+        meta,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(backend: &str) -> Member {
+        Member {
+            backend: backend.to_string(),
+            tool: "search".to_string(),
+        }
+    }
+
+    fn text(text: &str, is_error: bool) -> CallToolResult {
+        CallToolResult {
+            content: vec![Content::Text {
+                text: text.to_string(),
+            }],
+            is_error,
+            structured_content: Some(json!({"hits": 1})),
+            meta: None,
+        }
+    }
+
+    fn texts(result: &CallToolResult) -> Vec<&str> {
+        result
+            .content
+            .iter()
+            .map(|c| match c {
+                Content::Text { text } => text.as_str(),
+                _ => panic!("expected text"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn content_is_concatenated_in_member_order() {
+        let result = combine(
+            "search",
+            vec![
+                (member("docs"), Ok(text("from docs", false))),
+                (member("code"), Ok(text("from code", false))),
+            ],
+        );
+        assert!(!result.is_error);
+        assert_eq!(texts(&result), ["from docs", "from code"]);
+        assert_eq!(result.structured_content, None);
+        assert_eq!(
+            result.meta.unwrap()["mcpd/merged"],
+            json!({"answered": ["docs", "code"], "failed": []})
+        );
+    }
+
+    #[test]
+    fn partial_failure_still_succeeds_and_says_so() {
+        let result = combine(
+            "search",
+            vec![
+                (
+                    member("docs"),
+                    Err("Tool call failed: timed out".to_string()),
+                ),
+                (member("code"), Ok(text("from code", false))),
+                (member("web"), Ok(text("quota exceeded", true))),
+            ],
+        );
+        assert!(!result.is_error);
+        assert_eq!(
+            texts(&result),
+            [
+                "from code",
+                "Partial results: docs, web of merge group 'search' failed."
+            ]
+        );
+        assert_eq!(
+            result.meta.unwrap()["mcpd/merged"],
+            json!({"answered": ["code"], "failed": ["docs", "web"]})
+        );
+    }
+
+    #[test]
+    fn total_failure_is_an_error_with_every_members_error() {
+        let result = combine(
+            "search",
+            vec![
+                (
+                    member("docs"),
+                    Err("Tool call failed: timed out".to_string()),
+                ),
+                (member("web"), Ok(text("quota exceeded", true))),
+            ],
+        );
+        assert!(result.is_error);
+        assert_eq!(
+            texts(&result),
+            [
+                "Every server in merge group 'search' failed:",
+                "Tool call failed: timed out",
+                "quota exceeded"
+            ]
+        );
+    }
+
+    #[test]
+    fn listed_under_the_group_name() {
+        let first: McpTool = serde_json::from_value(json!({
+            "name": "search",
+            "description": "Search the index",
+            "inputSchema": {"type": "object"}
+        }))
+        .unwrap();
+        let tool = listed("find", &first, &["docs".to_string(), "code".to_string()]);
+        assert_eq!(tool.name, "find");
+        assert_eq!(
+            tool.description.as_deref(),
+            Some("Search the index (Answered by docs, code together.)")
+        );
+        assert_eq!(tool.input_schema, first.input_schema);
+    }
+}
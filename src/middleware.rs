@@ -0,0 +1,916 @@
+//! Middleware around tool calls.
+//!
+//! Every `use_tool` call passes through an ordered chain of [`CallMiddleware`]s.
+//! Going in, each middleware's `before` runs in chain order and can change
+//! the arguments or `_meta`, answer the call itself, or reject it. Coming back,
+//! `after` runs in reverse order, and only for middlewares whose `before`
+//! let the call through. A middleware that answers or rejects the call skips
+//! its own `after` and everything later in the chain, including the backend.
+//! Backend failures come back as `Err` and go through `after` like results.
+//!
+//! [`builtins`] returns mcpd's own features as a chain, in this order:
+//!
+//! 1. `audit`: records every call (`serve --audit-log`) with the arguments
+//!    the client sent and the outcome the client gets, including calls the
+//!    rest of the chain rejects
+//! 2. `read_only`: rejects destructive-looking tools (`serve --read-only`),
+//!    before anything else sees the call
+//! 3. `transform`: the backend's jq transform (`register --transform`),
+//!    applied last on the way out, to what the hooks returned
+//! 4. `hooks`: the backend's `pre_call` and `post_call` commands
+//! 5. `secret_scan`: the outbound secret scan (`serve --secret-policy`),
+//!    last on the way in, so it sees the arguments a `pre_call` hook produced
+//! 6. `intent_log`: records destructive calls (`serve --intent-log`) once
+//!    everything else has let them through, with the arguments the backend
+//!    gets, and their outcome before anything rewrites it
+//!
+//! Embedders pass their own chain to `Server::with_middleware`, usually the
+//! built-ins with their middleware inserted where it belongs.
+
+use crate::audit::{self, AuditLog};
+use crate::canonical::{self, Nulls};
+use crate::error_text::{ErrorText, ForeignText};
+use crate::hooks::{self, Stage};
+use crate::intent::{IntentLog, Record, Status};
+use crate::mcp::CallToolResult;
+use crate::proxy::ToolProxy;
+use crate::registry::Tool;
+use crate::secrets::{SecretPolicy, SecretScanner};
+use crate::server::ServeOptions;
+use serde_json::Value;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// What a call produced: a tool result, or an error message for the client
+pub type Outcome = Result<CallToolResult, String>;
+
+/// Left in a call's `extensions` by the server when the call ran out of
+/// time waiting for its backend, so `after` can tell a timeout from other
+/// failures
+#[derive(Debug, Clone, Copy)]
+pub struct TimedOut(pub Duration);
+
+/// The future a middleware method returns. Middlewares are used as trait
+/// objects, so their methods can't be `async fn`s.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// What `before` decides about a call
+#[derive(Debug)]
+pub enum Flow {
+    /// Pass the call on to the next middleware
+    Continue,
+    /// Answer the call with this result without calling the backend
+    Respond(CallToolResult),
+    /// Fail the call with this message
+    Reject(String),
+}
+
+/// Values middlewares attach to a call, one per type. A middleware's
+/// `before` can leave something for its own `after` (a start time, a cache
+/// key) or for middlewares later in the chain.
+#[derive(Default)]
+pub struct Extensions(HashMap<TypeId, Box<dyn Any + Send + Sync>>);
+
+impl Extensions {
+    /// Store `value`, returning the one of the same type it replaced
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.0
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.0.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.0.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.0
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+}
+
+impl std::fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+/// One tool call on its way through the chain
+pub struct CallContext {
+    /// Backend (registered server) name
+    pub backend: String,
+    /// Tool name as the backend knows it
+    pub tool: String,
+    /// Tool name as the client called it
+    pub exposed_name: String,
+    /// Arguments that will be sent to the backend
+    pub arguments: Value,
+    /// The client's `_meta` for the call
+    pub meta: Option<Value>,
+    pub correlation_id: String,
+    pub extensions: Extensions,
+    proxy: Arc<ToolProxy>,
+}
+
+impl CallContext {
+    pub fn new(
+        proxy: Arc<ToolProxy>,
+        exposed_name: &str,
+        tool: &str,
+        correlation_id: &str,
+        arguments: Value,
+        meta: Option<Value>,
+    ) -> Self {
+        Self {
+            backend: proxy.tool().name.clone(),
+            tool: tool.to_string(),
+            exposed_name: exposed_name.to_string(),
+            arguments,
+            meta,
+            correlation_id: correlation_id.to_string(),
+            extensions: Extensions::default(),
+            proxy,
+        }
+    }
+
+    /// The backend's registry entry
+    pub fn registration(&self) -> &Tool {
+        self.proxy.tool()
+    }
+
+    fn hook_call(&self) -> hooks::Call<'_> {
+        hooks::Call {
+            backend: &self.backend,
+            tool: &self.tool,
+            correlation_id: &self.correlation_id,
+        }
+    }
+}
+
+/// Logic that runs around every tool call. Both methods default to doing
+/// nothing, so a middleware implements only the side it needs.
+pub trait CallMiddleware: Send + Sync {
+    /// Short name, used in logs and to tell middlewares apart
+    fn name(&self) -> &str;
+
+    /// Runs before the backend is called, in chain order
+    fn before<'a>(&'a self, _ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+        Box::pin(async { Flow::Continue })
+    }
+
+    /// Runs after the backend (or a later middleware) answered, in reverse
+    /// chain order
+    fn after<'a>(&'a self, _ctx: &'a mut CallContext, outcome: Outcome) -> BoxFuture<'a, Outcome> {
+        Box::pin(async { outcome })
+    }
+}
+
+/// A middleware that does nothing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMiddleware;
+
+impl CallMiddleware for NoopMiddleware {
+    fn name(&self) -> &str {
+        "noop"
+    }
+}
+
+/// Run the `before` side of `chain`. Returns how many middlewares let the
+/// call through, and the outcome if one of them answered or rejected it.
+pub(crate) async fn before(
+    chain: &[Arc<dyn CallMiddleware>],
+    ctx: &mut CallContext,
+) -> (usize, Option<Outcome>) {
+    for (i, middleware) in chain.iter().enumerate() {
+        match middleware.before(ctx).await {
+            Flow::Continue => {}
+            Flow::Respond(result) => return (i, Some(Ok(result))),
+            Flow::Reject(message) => return (i, Some(Err(message))),
+        }
+    }
+    (chain.len(), None)
+}
+
+/// Run the `after` side of `entered`, the middlewares that let the call
+/// through, innermost first
+pub(crate) async fn after(
+    entered: &[Arc<dyn CallMiddleware>],
+    ctx: &mut CallContext,
+    mut outcome: Outcome,
+) -> Outcome {
+    for middleware in entered.iter().rev() {
+        outcome = middleware.after(ctx, outcome).await;
+    }
+    outcome
+}
+
+/// mcpd's own features as a chain, in the order documented at the top of
+/// this module. Features that are switched off are left out.
+pub fn builtins(options: &ServeOptions) -> Vec<Arc<dyn CallMiddleware>> {
+    let mut chain: Vec<Arc<dyn CallMiddleware>> = Vec::new();
+    if let Some(log) = &options.audit_log {
+        chain.push(Arc::new(AuditLogger {
+            log: Arc::clone(log),
+            redact_args: options.audit_redact_args,
+            scanner: options.secrets.clone(),
+        }));
+    }
+    if let Some(patterns) = &options.read_only_patterns {
+        chain.push(Arc::new(ReadOnly {
+            patterns: patterns.clone(),
+        }));
+    }
+    chain.push(Arc::new(TransformMiddleware));
+    chain.push(Arc::new(HookMiddleware {
+        timeout: options.hook_timeout.unwrap_or(hooks::DEFAULT_HOOK_TIMEOUT),
+        error_text: options.error_text,
+    }));
+    chain.push(Arc::new(SecretScan {
+        scanner: options.secrets.clone(),
+        policy: options.secret_policy,
+    }));
+    if let Some(log) = &options.intent_log {
+        chain.push(Arc::new(IntentLogger::new(
+            Arc::clone(log),
+            options.intent_log_patterns.clone(),
+            options.secrets.clone(),
+        )));
+    }
+    chain
+}
+
+/// `serve --audit-log`: writes an audit record for every call once its
+/// outcome is known
+pub struct AuditLogger {
+    pub log: Arc<AuditLog>,
+    /// Leave arguments out of records (`serve --audit-redact-args`)
+    pub redact_args: bool,
+    /// Redacts secrets from recorded arguments
+    pub scanner: SecretScanner,
+}
+
+/// A call's audit record, waiting for its outcome. Dropped without one, the
+/// call was abandoned (its client went away) and is recorded as failed.
+struct PendingRecord {
+    log: Arc<AuditLog>,
+    record: Option<audit::Record>,
+    started: Instant,
+}
+
+impl PendingRecord {
+    fn write(&mut self, is_error: bool, error: Option<String>, content: Vec<Value>) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        record.is_error = is_error;
+        record.error = error;
+        record.content = content;
+        record.latency_ms = self.started.elapsed().as_millis() as u64;
+        if let Err(e) = self.log.append(&record) {
+            warn!(correlation_id = record.correlation_id, error = %e, "Failed to write audit log");
+        }
+    }
+}
+
+impl Drop for PendingRecord {
+    fn drop(&mut self) {
+        self.write(
+            true,
+            Some("Call abandoned before it finished".to_string()),
+            Vec::new(),
+        );
+    }
+}
+
+impl CallMiddleware for AuditLogger {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    fn before<'a>(&'a self, ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+        Box::pin(async move {
+            let arguments = (!self.redact_args).then(|| {
+                let mut arguments = ctx.arguments.clone();
+                self.scanner.redact(&ctx.backend, &ctx.tool, &mut arguments);
+                arguments
+            });
+            ctx.extensions.insert(PendingRecord {
+                log: Arc::clone(&self.log),
+                record: Some(audit::Record {
+                    timestamp: chrono::Utc::now(),
+                    correlation_id: Some(ctx.correlation_id.clone()),
+                    name: ctx.exposed_name.clone(),
+                    backend: ctx.backend.clone(),
+                    tool: ctx.tool.clone(),
+                    arguments,
+                    is_error: false,
+                    timed_out: false,
+                    error: None,
+                    latency_ms: 0,
+                    content: Vec::new(),
+                }),
+                started: Instant::now(),
+            });
+            Flow::Continue
+        })
+    }
+
+    fn after<'a>(&'a self, ctx: &'a mut CallContext, outcome: Outcome) -> BoxFuture<'a, Outcome> {
+        Box::pin(async move {
+            let Some(mut pending) = ctx.extensions.remove::<PendingRecord>() else {
+                return outcome;
+            };
+            match &outcome {
+                Ok(result) => pending.write(
+                    result.is_error,
+                    None,
+                    result
+                        .content
+                        .iter()
+                        .filter_map(|c| serde_json::to_value(c).ok())
+                        .collect(),
+                ),
+                Err(message) => {
+                    if let Some(record) = pending.record.as_mut() {
+                        record.timed_out = ctx.extensions.get::<TimedOut>().is_some();
+                    }
+                    pending.write(true, Some(message.clone()), Vec::new())
+                }
+            }
+            outcome
+        })
+    }
+}
+
+/// `serve --read-only`: rejects tools whose names look destructive
+pub struct ReadOnly {
+    pub patterns: Vec<String>,
+}
+
+impl CallMiddleware for ReadOnly {
+    fn name(&self) -> &str {
+        "read_only"
+    }
+
+    fn before<'a>(&'a self, ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+        Box::pin(async move {
+            if crate::server::matches_destructive(&ctx.tool, &self.patterns) {
+                return Flow::Reject(format!(
+                    "Tool '{}' is blocked: mcpd is running in read-only mode and this tool looks destructive.",
+                    ctx.exposed_name
+                ));
+            }
+            Flow::Continue
+        })
+    }
+}
+
+/// The backend's `register --transform`, applied to successful results
+pub struct TransformMiddleware;
+
+impl CallMiddleware for TransformMiddleware {
+    fn name(&self) -> &str {
+        "transform"
+    }
+
+    fn after<'a>(&'a self, ctx: &'a mut CallContext, outcome: Outcome) -> BoxFuture<'a, Outcome> {
+        Box::pin(async move {
+            let result = outcome?;
+            match ctx.proxy.transform().map_err(|e| e.to_string())? {
+                // A transform that doesn't fit this output shouldn't hide it
+                Some(transform) => Ok(transform.apply_to_result(&result).unwrap_or_else(|e| {
+                    warn!(correlation_id = ctx.correlation_id, tool = %ctx.exposed_name, error = %e, "Result transform failed, returning raw result");
+                    result
+                })),
+                None => Ok(result),
+            }
+        })
+    }
+}
+
+/// The backend's `register --pre-call` and `--post-call` commands
+pub struct HookMiddleware {
+    pub timeout: Duration,
+    /// How a failing hook's stderr is quoted to the client
+    pub error_text: ErrorText,
+}
+
+impl HookMiddleware {
+    /// Pass `input` through a hook command, keeping it if the hook prints nothing
+    async fn run(
+        &self,
+        ctx: &CallContext,
+        command: &[String],
+        stage: Stage,
+        input: Value,
+    ) -> anyhow::Result<Value> {
+        let call = ctx.hook_call();
+        let output = hooks::run(command, stage, call, &input, self.timeout)
+            .await
+            .inspect_err(|e| {
+                warn!(correlation_id = call.correlation_id, backend = call.backend, tool = call.tool, %stage, error = format!("{:#}", e), "Hook failed");
+            })?;
+        Ok(output.unwrap_or(input))
+    }
+}
+
+impl CallMiddleware for HookMiddleware {
+    fn name(&self) -> &str {
+        "hooks"
+    }
+
+    fn before<'a>(&'a self, ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+        Box::pin(async move {
+            let Some(hook) = ctx.registration().pre_call.clone() else {
+                return Flow::Continue;
+            };
+            let arguments = std::mem::take(&mut ctx.arguments);
+            match self.run(ctx, &hook, Stage::PreCall, arguments).await {
+                Ok(arguments) => {
+                    ctx.arguments = arguments;
+                    Flow::Continue
+                }
+                Err(e) => Flow::Reject(self.error_text.describe(
+                    "Call rejected",
+                    &e,
+                    Some(&ctx.correlation_id),
+                )),
+            }
+        })
+    }
+
+    fn after<'a>(&'a self, ctx: &'a mut CallContext, outcome: Outcome) -> BoxFuture<'a, Outcome> {
+        Box::pin(async move {
+            let result = outcome?;
+            let Some(hook) = ctx.registration().post_call.clone() else {
+                return Ok(result);
+            };
+            let value = serde_json::to_value(&result).map_err(|e| e.to_string())?;
+            let value = self
+                .run(ctx, &hook, Stage::PostCall, value)
+                .await
+                .map_err(|e| {
+                    self.error_text
+                        .describe("Tool call failed", &e, Some(&ctx.correlation_id))
+                })?;
+            // serde quotes the values it trips over, which came from the hook
+            serde_json::from_value(value).map_err(|e| {
+                let e = anyhow::Error::new(ForeignText {
+                    origin: "post_call hook output error",
+                    text: e.to_string(),
+                });
+                self.error_text.describe(
+                    "Tool call failed: post_call hook printed an invalid result",
+                    &e,
+                    Some(&ctx.correlation_id),
+                )
+            })
+        })
+    }
+}
+
+/// `serve --secret-policy`: scans outbound arguments for credentials.
+/// Errors name the argument paths, never the values.
+pub struct SecretScan {
+    pub scanner: SecretScanner,
+    pub policy: SecretPolicy,
+}
+
+impl CallMiddleware for SecretScan {
+    fn name(&self) -> &str {
+        "secret_scan"
+    }
+
+    fn before<'a>(&'a self, ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+        Box::pin(async move {
+            let (backend, tool) = (ctx.backend.as_str(), ctx.tool.as_str());
+            let findings = match self.policy {
+                SecretPolicy::Redact => self.scanner.redact(backend, tool, &mut ctx.arguments),
+                SecretPolicy::Warn | SecretPolicy::Block => {
+                    self.scanner.scan(backend, tool, &ctx.arguments)
+                }
+            };
+            if findings.is_empty() {
+                return Flow::Continue;
+            }
+
+            let correlation_id = ctx.correlation_id.as_str();
+            let summary = findings
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            match self.policy {
+                SecretPolicy::Warn => {
+                    warn!(correlation_id, backend, tool, findings = %summary, "Possible secret in tool arguments");
+                }
+                SecretPolicy::Redact => {
+                    warn!(correlation_id, backend, tool, findings = %summary, "Redacted possible secret from tool arguments");
+                }
+                SecretPolicy::Block => {
+                    warn!(correlation_id, backend, tool, findings = %summary, "Blocked tool call with possible secret in arguments");
+                    return Flow::Reject(format!(
+                        "Call blocked: argument {} looks like it contains a secret. Remove it or ask the user to allowlist this argument.",
+                        summary
+                    ));
+                }
+            }
+            Flow::Continue
+        })
+    }
+}
+
+/// `serve --intent-log`: appends an intent entry for each destructive call
+/// before it goes to the backend, and its outcome after. A call whose intent
+/// can't be written isn't forwarded.
+pub struct IntentLogger {
+    log: Arc<IntentLog>,
+    /// Name fragments that make a call destructive
+    patterns: Vec<String>,
+    /// Redacts secrets from the logged arguments
+    scanner: SecretScanner,
+}
+
+/// The outcome an intent is waiting for. Dropped without one, the call was
+/// abandoned (its client went away) and that's recorded instead.
+struct PendingOutcome {
+    log: Arc<IntentLog>,
+    intent: u64,
+    correlation_id: String,
+    done: bool,
+}
+
+impl PendingOutcome {
+    fn record(&self, status: Status, error: Option<String>) -> Record {
+        Record::Outcome {
+            timestamp: chrono::Utc::now(),
+            correlation_id: self.correlation_id.clone(),
+            intent: self.intent,
+            status,
+            error,
+        }
+    }
+}
+
+impl Drop for PendingOutcome {
+    fn drop(&mut self) {
+        if !self.done
+            && let Err(e) = self.log.append(self.record(Status::Abandoned, None))
+        {
+            warn!(correlation_id = self.correlation_id, error = %e, "Failed to record abandoned call in intent log");
+        }
+    }
+}
+
+impl IntentLogger {
+    pub fn new(log: Arc<IntentLog>, patterns: Vec<String>, scanner: SecretScanner) -> Self {
+        Self {
+            log,
+            patterns,
+            scanner,
+        }
+    }
+
+    /// Why the call counts as destructive, if it does
+    fn decision(&self, ctx: &CallContext) -> Option<String> {
+        let name = ctx.tool.to_lowercase();
+        if let Some(pattern) = self
+            .patterns
+            .iter()
+            .find(|p| !p.is_empty() && name.contains(&p.to_lowercase()))
+        {
+            return Some(format!("name matches '{}'", pattern));
+        }
+        // Annotations as of the backend's last listing, so classifying a
+        // call never starts a cold backend ahead of the call's own deadline
+        ctx.proxy
+            .annotated_destructive(&ctx.tool)
+            .unwrap_or(false)
+            .then(|| "destructiveHint annotation".to_string())
+    }
+
+    /// Append on a blocking thread, since each append waits for the disk
+    async fn append(log: &Arc<IntentLog>, record: Record) -> anyhow::Result<u64> {
+        let log = Arc::clone(log);
+        tokio::task::spawn_blocking(move || log.append(record)).await?
+    }
+}
+
+impl CallMiddleware for IntentLogger {
+    fn name(&self) -> &str {
+        "intent_log"
+    }
+
+    fn before<'a>(&'a self, ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+        Box::pin(async move {
+            let Some(decision) = self.decision(ctx) else {
+                return Flow::Continue;
+            };
+            let mut arguments = ctx.arguments.clone();
+            self.scanner.redact(&ctx.backend, &ctx.tool, &mut arguments);
+            let intent = Record::Intent {
+                timestamp: chrono::Utc::now(),
+                correlation_id: ctx.correlation_id.clone(),
+                backend: ctx.backend.clone(),
+                tool: ctx.tool.clone(),
+                arguments_sha256: canonical::hash(&ctx.arguments, Nulls::Keep),
+                arguments,
+                decision,
+            };
+            match Self::append(&self.log, intent).await {
+                Ok(intent) => {
+                    ctx.extensions.insert(PendingOutcome {
+                        log: Arc::clone(&self.log),
+                        intent,
+                        correlation_id: ctx.correlation_id.clone(),
+                        done: false,
+                    });
+                    Flow::Continue
+                }
+                Err(e) => {
+                    warn!(correlation_id = ctx.correlation_id, error = %e, "Failed to write intent log, not forwarding call");
+                    Flow::Reject(format!(
+                        "Call not forwarded: mcpd couldn't record it in the intent log ({:#})",
+                        e
+                    ))
+                }
+            }
+        })
+    }
+
+    fn after<'a>(&'a self, ctx: &'a mut CallContext, outcome: Outcome) -> BoxFuture<'a, Outcome> {
+        Box::pin(async move {
+            let Some(mut pending) = ctx.extensions.remove::<PendingOutcome>() else {
+                return outcome;
+            };
+            pending.done = true;
+            let record = match &outcome {
+                Ok(result) if !result.is_error => pending.record(Status::Success, None),
+                Ok(_) => pending.record(Status::Error, None),
+                Err(message) if ctx.extensions.get::<TimedOut>().is_some() => {
+                    pending.record(Status::Timeout, Some(message.clone()))
+                }
+                Err(message) => pending.record(Status::Error, Some(message.clone())),
+            };
+            if let Err(e) = Self::append(&self.log, record).await {
+                warn!(correlation_id = ctx.correlation_id, error = %e, "Failed to record call outcome in intent log");
+            }
+            outcome
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::Content;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    fn context() -> CallContext {
+        let proxy = Arc::new(ToolProxy::new(Tool {
+            name: "fs".to_string(),
+            command: vec!["fs-server".to_string()],
+            ..Default::default()
+        }));
+        CallContext::new(
+            proxy,
+            "fs__read",
+            "read",
+            "mcpd-1-1",
+            json!({"path": "/tmp"}),
+            None,
+        )
+    }
+
+    fn text(text: &str) -> CallToolResult {
+        CallToolResult {
+            content: vec![Content::Text {
+                text: text.to_string(),
+            }],
+            is_error: false,
+            structured_content: None,
+            meta: None,
+        }
+    }
+
+    /// Records each side it runs on, and answers the call when `respond` is set
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+        respond: bool,
+    }
+
+    impl CallMiddleware for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn before<'a>(&'a self, _ctx: &'a mut CallContext) -> BoxFuture<'a, Flow> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("before {}", self.name));
+            let flow = if self.respond {
+                Flow::Respond(text(self.name))
+            } else {
+                Flow::Continue
+            };
+            Box::pin(async { flow })
+        }
+
+        fn after<'a>(
+            &'a self,
+            _ctx: &'a mut CallContext,
+            outcome: Outcome,
+        ) -> BoxFuture<'a, Outcome> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("after {}", self.name));
+            Box::pin(async { outcome })
+        }
+    }
+
+    fn recorders(log: &Arc<Mutex<Vec<String>>>, respond: &str) -> Vec<Arc<dyn CallMiddleware>> {
+        ["a", "b", "c"]
+            .into_iter()
+            .map(|name| {
+                Arc::new(Recorder {
+                    name,
+                    log: Arc::clone(log),
+                    respond: name == respond,
+                }) as Arc<dyn CallMiddleware>
+            })
+            .collect()
+    }
+
+    async fn run(chain: &[Arc<dyn CallMiddleware>], ctx: &mut CallContext) -> Outcome {
+        let (entered, early) = before(chain, ctx).await;
+        let outcome = early.unwrap_or_else(|| Ok(text("backend")));
+        after(&chain[..entered], ctx, outcome).await
+    }
+
+    #[tokio::test]
+    async fn after_runs_in_reverse_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let result = run(&recorders(&log, ""), &mut context()).await.unwrap();
+        assert!(matches!(&result.content[0], Content::Text { text } if text == "backend"));
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "before a", "before b", "before c", "after c", "after b", "after a"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn answering_skips_the_rest_of_the_chain() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let result = run(&recorders(&log, "b"), &mut context()).await.unwrap();
+        assert!(matches!(&result.content[0], Content::Text { text } if text == "b"));
+        assert_eq!(*log.lock().unwrap(), ["before a", "before b", "after a"]);
+    }
+
+    #[tokio::test]
+    async fn noop_passes_everything_through() {
+        let chain: Vec<Arc<dyn CallMiddleware>> = vec![Arc::new(NoopMiddleware)];
+        let mut ctx = context();
+        let result = run(&chain, &mut ctx).await.unwrap();
+        assert!(matches!(&result.content[0], Content::Text { text } if text == "backend"));
+        assert_eq!(ctx.arguments, json!({"path": "/tmp"}));
+    }
+
+    #[test]
+    fn builtin_order_is_pinned() {
+        let names = |options: &ServeOptions| -> Vec<String> {
+            builtins(options)
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect()
+        };
+        let options = ServeOptions {
+            read_only_patterns: Some(vec!["delete".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&options),
+            ["read_only", "transform", "hooks", "secret_scan"]
+        );
+        assert_eq!(
+            names(&ServeOptions::default()),
+            ["transform", "hooks", "secret_scan"]
+        );
+        let dir = tempfile::TempDir::new().unwrap();
+        let log = IntentLog::open(&dir.path().join("intents.jsonl")).unwrap();
+        let options = ServeOptions {
+            intent_log: Some(Arc::new(log)),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&options),
+            ["transform", "hooks", "secret_scan", "intent_log"]
+        );
+        let audit = AuditLog::open(&dir.path().join("audit.jsonl"), None).unwrap();
+        let options = ServeOptions {
+            audit_log: Some(Arc::new(audit)),
+            read_only_patterns: Some(vec!["delete".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&options),
+            ["audit", "read_only", "transform", "hooks", "secret_scan"]
+        );
+    }
+
+    /// An intent logger treating tools with `pattern` in their name as
+    /// destructive, and a function returning the kinds of what it logged
+    fn intent_logger(pattern: &str) -> (IntentLogger, impl Fn() -> Vec<String>, tempfile::TempDir) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("intents.jsonl");
+        let log = Arc::new(IntentLog::open(&path).unwrap());
+        let logger = IntentLogger::new(log, vec![pattern.to_string()], SecretScanner::default());
+        let kinds = move || {
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let entry: Value = serde_json::from_str(line).unwrap();
+                    match entry["status"].as_str() {
+                        Some(status) => format!("outcome {}", status),
+                        None => entry["kind"].as_str().unwrap().to_string(),
+                    }
+                })
+                .collect()
+        };
+        (logger, kinds, dir)
+    }
+
+    #[tokio::test]
+    async fn intent_is_on_disk_before_the_call_goes_on() {
+        let (logger, kinds, _dir) = intent_logger("read");
+        let mut ctx = context();
+        assert!(matches!(logger.before(&mut ctx).await, Flow::Continue));
+        // Read back from the file, as a crash right now would leave it
+        assert_eq!(kinds(), ["open", "intent"]);
+        logger.after(&mut ctx, Ok(text("done"))).await.unwrap();
+        assert_eq!(kinds(), ["open", "intent", "outcome success"]);
+        drop(ctx);
+        assert_eq!(kinds().len(), 3);
+
+        // A call dropped before its answer, as when the client disconnects
+        let mut ctx = context();
+        logger.before(&mut ctx).await;
+        drop(ctx);
+        assert_eq!(kinds()[3..], ["intent", "outcome abandoned"]);
+    }
+
+    #[tokio::test]
+    async fn intent_log_skips_calls_that_arent_destructive() {
+        let (logger, kinds, _dir) = intent_logger("delete");
+        let mut ctx = context();
+        // Not in the name, and the backend can't be asked for annotations
+        assert!(matches!(logger.before(&mut ctx).await, Flow::Continue));
+        logger.after(&mut ctx, Ok(text("done"))).await.unwrap();
+        assert_eq!(kinds(), ["open"]);
+    }
+
+    #[tokio::test]
+    async fn read_only_rejects_destructive_tools() {
+        let read_only = ReadOnly {
+            patterns: vec!["read".to_string()],
+        };
+        let Flow::Reject(message) = read_only.before(&mut context()).await else {
+            panic!("expected a rejection");
+        };
+        assert!(message.contains("'fs__read' is blocked"), "{}", message);
+    }
+
+    #[test]
+    fn extensions_are_keyed_by_type() {
+        #[derive(Debug, PartialEq)]
+        struct Started(u32);
+
+        let mut extensions = Extensions::default();
+        assert_eq!(extensions.insert(Started(1)), None);
+        assert_eq!(extensions.insert(Started(2)), Some(Started(1)));
+        extensions.insert("label");
+        extensions.get_mut::<Started>().unwrap().0 += 1;
+        assert_eq!(extensions.get::<Started>(), Some(&Started(3)));
+        assert_eq!(extensions.get::<&str>(), Some(&"label"));
+        assert_eq!(extensions.remove::<Started>(), Some(Started(3)));
+        assert_eq!(extensions.get::<Started>(), None);
+    }
+}
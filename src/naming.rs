@@ -0,0 +1,372 @@
+//! How backend tool names are shown to clients (`serve --name-style`).
+//!
+//! Exposed names are `<server><separator><tool>`, where the server name is
+//! kept as registered and the tool name is restyled. Restyling isn't
+//! reversible on its own (`read_file` and `readFile` are both `readFile` in
+//! camel case), so routing goes through a map from exposed names back to the
+//! backend's own names. Tools whose styled names would collide keep their
+//! original names instead.
+//!
+//! Under a name length limit (`serve --max-tool-name-length` or a
+//! `--client-profile`), exposed names over it are shortened to fit: the tool
+//! part is cut and ends in a separator and the first 8 hex digits of the
+//! SHA-256 of the backend's own tool name, so the same tool gets the same
+//! short name every session and tools sharing a long prefix stay distinct.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Hex digits of the tool name's hash that end a shortened name
+const HASH_DIGITS: usize = 8;
+
+/// Limits of well-known clients (`serve --client-profile`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClientProfile {
+    /// Claude: tool names up to 64 characters
+    Claude,
+    /// Cursor: at most 40 tools, names up to 60 characters
+    Cursor,
+}
+
+impl ClientProfile {
+    /// Longest exposed tool name the client accepts
+    pub fn max_tool_name_length(self) -> usize {
+        match self {
+            ClientProfile::Claude => 64,
+            ClientProfile::Cursor => 60,
+        }
+    }
+
+    /// Most tools the client accepts, if it has a limit
+    pub fn max_tools(self) -> Option<usize> {
+        match self {
+            ClientProfile::Claude => None,
+            ClientProfile::Cursor => Some(40),
+        }
+    }
+}
+
+/// Naming convention for exposed tool names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum NameStyle {
+    /// `server__tool_name`, exactly as the backend names it
+    #[default]
+    AsIs,
+    /// `server__tool_name`
+    Snake,
+    /// `server--tool-name`
+    Kebab,
+    /// `server__toolName`
+    Camel,
+}
+
+impl NameStyle {
+    /// What goes between the server and tool name
+    pub fn separator(self) -> &'static str {
+        match self {
+            NameStyle::Kebab => "--",
+            NameStyle::AsIs | NameStyle::Snake | NameStyle::Camel => "__",
+        }
+    }
+
+    /// A backend tool name in this style
+    pub fn apply(self, name: &str) -> String {
+        let words = words(name);
+        match self {
+            NameStyle::AsIs => name.to_string(),
+            NameStyle::Snake => join_lower(&words, "_"),
+            NameStyle::Kebab => join_lower(&words, "-"),
+            NameStyle::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, word)| {
+                    let lower = word.to_lowercase();
+                    if i == 0 {
+                        return lower;
+                    }
+                    let mut chars = lower.chars();
+                    chars
+                        .next()
+                        .map(|first| first.to_uppercase().chain(chars).collect())
+                        .unwrap_or_default()
+                })
+                .collect(),
+        }
+    }
+
+    /// The name a client sees for `tool`, given its exposed tool part
+    pub fn join(self, server: &str, tool: &str) -> String {
+        format!("{}{}{}", server, self.separator(), tool)
+    }
+
+    /// Split an exposed name into server and exposed tool part
+    pub fn split(self, exposed: &str) -> Option<(&str, &str)> {
+        match self {
+            // Server names may contain "--" but styled tool parts never do
+            NameStyle::Kebab => exposed.rsplit_once("--"),
+            // Server names never contain "__"
+            NameStyle::AsIs | NameStyle::Snake | NameStyle::Camel => exposed.split_once("__"),
+        }
+    }
+}
+
+fn join_lower(words: &[&str], separator: &str) -> String {
+    words
+        .iter()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Split a name into words at `_`, `-`, `.`, spaces and case changes
+/// (`getHTTPResponse` is `get`, `HTTP`, `Response`)
+fn words(name: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in name.split(['_', '-', '.', ' ']) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for i in 1..chars.len() {
+            let (at, c) = chars[i];
+            let prev = chars[i - 1].1;
+            let next_lower = chars.get(i + 1).is_some_and(|(_, n)| n.is_lowercase());
+            let boundary = c.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_ascii_digit()
+                    || (prev.is_uppercase() && next_lower));
+            if boundary {
+                words.push(&part[start..at]);
+                start = at;
+            }
+        }
+        if start < part.len() {
+            words.push(&part[start..]);
+        }
+    }
+    words
+}
+
+/// Exposed tool parts for one backend's tools, mapped to the backend's own
+/// names. Tools whose styled names collide keep their original names; the
+/// colliding originals are returned second so they can be reported.
+pub fn expose<'a>(
+    style: NameStyle,
+    names: impl IntoIterator<Item = &'a str>,
+) -> (HashMap<String, String>, Vec<Vec<String>>) {
+    let names: Vec<&str> = names.into_iter().collect();
+    let mut by_styled: HashMap<String, Vec<&str>> = HashMap::new();
+    for name in &names {
+        by_styled.entry(style.apply(name)).or_default().push(name);
+    }
+
+    let mut exposed = HashMap::new();
+    let mut collisions = Vec::new();
+    // Uncontested styled names first, so they win over originals kept below
+    for (styled, originals) in &by_styled {
+        if let [original] = originals[..] {
+            exposed.insert(styled.clone(), original.to_string());
+        }
+    }
+    for originals in by_styled.into_values().filter(|o| o.len() > 1) {
+        for original in &originals {
+            if !exposed.contains_key(*original) {
+                exposed.insert(original.to_string(), original.to_string());
+            }
+        }
+        let mut originals: Vec<String> = originals.into_iter().map(String::from).collect();
+        originals.sort();
+        collisions.push(originals);
+    }
+    collisions.sort();
+    (exposed, collisions)
+}
+
+/// Shorten the exposed tool parts in `exposed` (as `expose` returns them)
+/// whose full names under `server` are longer than `max` characters.
+/// Returns the backend tool names that can't be made to fit, either because
+/// the server name leaves no room or because the short name is taken; those
+/// keep their long names.
+pub fn fit(
+    style: NameStyle,
+    server: &str,
+    exposed: &mut HashMap<String, String>,
+    max: usize,
+) -> Vec<String> {
+    let room = max.saturating_sub(server.chars().count() + style.separator().len());
+    let mut long: Vec<(String, String)> = exposed
+        .iter()
+        .filter(|(part, _)| part.chars().count() > room)
+        .map(|(part, original)| (part.clone(), original.clone()))
+        .collect();
+    // In name order, so which one keeps its long name on a clash is stable
+    long.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let mut unfit = Vec::new();
+    for (part, original) in long {
+        match shorten(style, &part, &original, room) {
+            Some(short) if !exposed.contains_key(&short) => {
+                exposed.remove(&part);
+                exposed.insert(short, original);
+            }
+            _ => unfit.push(original),
+        }
+    }
+    unfit
+}
+
+/// `part` cut to at most `room` characters, ending in a separator and the
+/// hash of `original`. `None` if `room` can't hold the hash and some prefix.
+fn shorten(style: NameStyle, part: &str, original: &str, room: usize) -> Option<String> {
+    let keep = room.checked_sub(HASH_DIGITS + 1).filter(|&keep| keep > 0)?;
+    let digest = Sha256::digest(original.as_bytes());
+    let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    // No trailing separator, so the cut never makes a `__` or `--`
+    let prefix: String = part.chars().take(keep).collect();
+    let prefix = prefix.trim_end_matches(['_', '-']);
+    if prefix.is_empty() {
+        return None;
+    }
+    let separator = if style == NameStyle::Kebab { '-' } else { '_' };
+    Some(format!("{}{}{}", prefix, separator, &hash[..HASH_DIGITS]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles() {
+        for (name, snake, kebab, camel) in [
+            ("read_file", "read_file", "read-file", "readFile"),
+            ("readFile", "read_file", "read-file", "readFile"),
+            (
+                "get-HTTPResponse",
+                "get_http_response",
+                "get-http-response",
+                "getHttpResponse",
+            ),
+            (
+                "list.v2Items",
+                "list_v2_items",
+                "list-v2-items",
+                "listV2Items",
+            ),
+            ("Search", "search", "search", "search"),
+            ("a__b", "a_b", "a-b", "aB"),
+        ] {
+            assert_eq!(NameStyle::AsIs.apply(name), name);
+            assert_eq!(NameStyle::Snake.apply(name), snake, "{}", name);
+            assert_eq!(NameStyle::Kebab.apply(name), kebab, "{}", name);
+            assert_eq!(NameStyle::Camel.apply(name), camel, "{}", name);
+        }
+    }
+
+    #[test]
+    fn join_and_split_round_trip() {
+        for style in [
+            NameStyle::AsIs,
+            NameStyle::Snake,
+            NameStyle::Kebab,
+            NameStyle::Camel,
+        ] {
+            for server in ["fs", "my-fs", "my--fs", "a.b"] {
+                if style != NameStyle::Kebab && server.contains("--") {
+                    continue;
+                }
+                let tool = style.apply("read_file");
+                let exposed = style.join(server, &tool);
+                assert_eq!(style.split(&exposed), Some((server, tool.as_str())));
+            }
+        }
+        assert_eq!(NameStyle::Kebab.join("fs", "read-file"), "fs--read-file");
+    }
+
+    #[test]
+    fn exposed_names_map_back() {
+        let (exposed, collisions) = expose(NameStyle::Camel, ["read_file", "list-dir"]);
+        assert_eq!(exposed["readFile"], "read_file");
+        assert_eq!(exposed["listDir"], "list-dir");
+        assert!(collisions.is_empty());
+    }
+
+    #[test]
+    fn collisions_keep_original_names() {
+        let (exposed, collisions) = expose(
+            NameStyle::Snake,
+            ["read_file", "readFile", "read-file", "stat"],
+        );
+        assert_eq!(collisions, vec![vec!["read-file", "readFile", "read_file"]]);
+        // Each original stays reachable under its own name
+        for name in ["read_file", "readFile", "read-file", "stat"] {
+            assert_eq!(exposed[name], name);
+        }
+        assert_eq!(exposed.len(), 4);
+    }
+
+    #[test]
+    fn as_is_never_collides() {
+        let (exposed, collisions) = expose(NameStyle::AsIs, ["a", "A"]);
+        assert_eq!(exposed.len(), 2);
+        assert!(collisions.is_empty());
+    }
+
+    #[test]
+    fn long_names_are_shortened_with_a_stable_hash() {
+        let long = "search_repository_issues_by_label_and_milestone";
+        let (mut exposed, _) = expose(NameStyle::AsIs, [long, "stat"]);
+        assert!(fit(NameStyle::AsIs, "github", &mut exposed, 32).is_empty());
+        let (short, original) = exposed.iter().find(|(_, o)| *o == long).unwrap();
+        assert_eq!(original, long);
+        assert_eq!(NameStyle::AsIs.join("github", short).len(), 32);
+        assert!(short.starts_with("search_reposito_"), "{}", short);
+        // Short enough names are untouched
+        assert_eq!(exposed["stat"], "stat");
+
+        // The same input gives the same name every time
+        let (mut again, _) = expose(NameStyle::AsIs, [long, "stat"]);
+        fit(NameStyle::AsIs, "github", &mut again, 32);
+        assert_eq!(again, exposed);
+    }
+
+    #[test]
+    fn shortened_names_sharing_a_prefix_stay_distinct() {
+        let names = [
+            "search_repository_issues_by_label",
+            "search_repository_issues_by_milestone",
+        ];
+        let (mut exposed, _) = expose(NameStyle::Kebab, names);
+        assert!(fit(NameStyle::Kebab, "github", &mut exposed, 32).is_empty());
+        assert_eq!(exposed.len(), 2);
+        for (short, original) in &exposed {
+            let full = NameStyle::Kebab.join("github", short);
+            assert!(full.len() <= 32, "{}", full);
+            assert!(short.starts_with("search-reposito-"), "{}", short);
+            // Still splits back into server and tool part
+            assert_eq!(
+                NameStyle::Kebab.split(&full),
+                Some(("github", short.as_str()))
+            );
+            assert!(names.contains(&original.as_str()));
+        }
+    }
+
+    #[test]
+    fn names_that_cant_fit_keep_their_long_names() {
+        let (mut exposed, _) = expose(NameStyle::AsIs, ["read_file"]);
+        let unfit = fit(NameStyle::AsIs, "a_very_long_server_name", &mut exposed, 30);
+        assert_eq!(unfit, ["read_file"]);
+        assert_eq!(exposed["read_file"], "read_file");
+    }
+
+    #[test]
+    fn shortened_name_already_taken_keeps_the_long_name() {
+        let long = "abcdefghijklmnopqrstuvwxyz";
+        let digest = Sha256::digest(long.as_bytes());
+        let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        let taken = format!("abcde_{}", &hash[..HASH_DIGITS]);
+        let (mut exposed, _) = expose(NameStyle::AsIs, [long, taken.as_str()]);
+        let unfit = fit(NameStyle::AsIs, "s", &mut exposed, 17);
+        assert_eq!(unfit, [long]);
+        assert_eq!(exposed[long], long);
+        assert_eq!(exposed[&taken], taken);
+    }
+}
@@ -0,0 +1,571 @@
+//! Schema-only mode: serving a catalog file captured from live backends,
+//! without starting any of them.
+//!
+//! `mcpd export-catalog` writes every backend's tools (as clients see them),
+//! prompts and resources to a versioned JSON file, with a fingerprint of each
+//! backend's tools. `mcpd serve --catalog <file> --schema-only` answers the
+//! list methods from the file and never executes a call; with
+//! `--echo-calls`, `use_tool` checks the arguments against the tool's input
+//! schema and returns them instead. `mcpd diff --catalog <file>` compares the
+//! fingerprints against the live backends to catch drift.
+
+use crate::canonical::{self, Nulls};
+use crate::catalog::Failure;
+use crate::conflicts;
+use crate::error_text;
+use crate::mcp::{CallToolResult, Content, Prompt, Resource, Tool as McpTool};
+use crate::output::{Color, Output};
+use crate::schema;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Catalog file format version. Bumped on incompatible changes; older mcpd
+/// refuses files with a newer version.
+pub const VERSION: u32 = 1;
+
+/// What `mcpd export-catalog` writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogFile {
+    pub version: u32,
+    /// In registry order
+    pub backends: Vec<Backend>,
+}
+
+/// One backend's part of a catalog file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backend {
+    pub name: String,
+    /// `conflicts::fingerprint` of the backend's tools
+    pub fingerprint: String,
+    #[serde(default)]
+    pub tools: Vec<ExportedTool>,
+    /// As the backend names them, without the `backend__` prefix
+    #[serde(default)]
+    pub prompts: Vec<Prompt>,
+    /// With the backend's own URIs and names
+    #[serde(default)]
+    pub resources: Vec<Resource>,
+}
+
+/// A tool with the name clients call it by
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedTool {
+    pub name: String,
+    /// As the backend lists it
+    pub tool: McpTool,
+}
+
+impl Backend {
+    /// A backend's entry, fingerprinting its tools
+    pub fn new(
+        name: String,
+        tools: Vec<(String, McpTool)>,
+        prompts: Vec<Prompt>,
+        resources: Vec<Resource>,
+    ) -> Self {
+        let tools: Vec<ExportedTool> = tools
+            .into_iter()
+            .map(|(name, tool)| ExportedTool { name, tool })
+            .collect();
+        Self {
+            fingerprint: fingerprint(&tools),
+            name,
+            tools,
+            prompts,
+            resources,
+        }
+    }
+}
+
+fn fingerprint(tools: &[ExportedTool]) -> String {
+    let tools: Vec<McpTool> = tools.iter().map(|t| t.tool.clone()).collect();
+    conflicts::fingerprint(&tools)
+}
+
+impl CatalogFile {
+    pub fn new(backends: Vec<Backend>) -> Self {
+        Self {
+            version: VERSION,
+            backends,
+        }
+    }
+
+    /// Read a catalog file written by `mcpd export-catalog`
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read catalog from {}", path.display()))?;
+        let value: Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse catalog from {}", path.display()))?;
+        match value.get("version").and_then(Value::as_u64) {
+            Some(version) if version > VERSION as u64 => anyhow::bail!(
+                "Catalog {} has version {}, but this mcpd reads up to version {}",
+                path.display(),
+                version,
+                VERSION
+            ),
+            Some(_) => {}
+            None => anyhow::bail!("{} isn't an mcpd catalog file (no version)", path.display()),
+        }
+        serde_json::from_value(value)
+            .with_context(|| format!("Failed to parse catalog from {}", path.display()))
+    }
+
+    /// Every tool as `(exposed name, tool)` pairs, in file order
+    pub fn tools(&self) -> Vec<(String, McpTool)> {
+        self.backends
+            .iter()
+            .flat_map(|b| b.tools.iter().map(|t| (t.name.clone(), t.tool.clone())))
+            .collect()
+    }
+
+    /// The tool clients call `name`
+    pub fn find(&self, name: &str) -> Option<&ExportedTool> {
+        self.backends
+            .iter()
+            .flat_map(|b| &b.tools)
+            .find(|t| t.name == name)
+    }
+}
+
+/// `serve --schema-only`: the catalog to serve, and what `use_tool` does
+#[derive(Debug, Clone)]
+pub struct SchemaOnly {
+    pub catalog: Arc<CatalogFile>,
+    /// Return checked arguments instead of refusing calls
+    pub echo_calls: bool,
+}
+
+impl SchemaOnly {
+    /// What `use_tool` answers in place of running `name`
+    pub fn call(&self, name: &str, arguments: &Value) -> CallToolResult {
+        let Some(tool) = self.catalog.find(name) else {
+            return error_text::call_failed(&format!(
+                "Unknown tool '{}'. Use list_tools to see available tools.",
+                name
+            ));
+        };
+        if !self.echo_calls {
+            return error_text::tool_error(format!(
+                "schema-only mode, call not executed: mcpd is serving '{}' from a catalog file",
+                name
+            ));
+        }
+        match schema::check_arguments(&tool.tool.input_schema, arguments) {
+            Ok(()) => CallToolResult {
+                content: vec![Content::Text {
+                    text: serde_json::to_string_pretty(arguments).unwrap_or_default(),
+                }],
+                is_error: false,
+                structured_content: None,
+                meta: None,
+            },
+            Err(problems) => error_text::tool_error(format!(
+                "Invalid arguments for '{}' (schema-only mode, call not executed):\n- {}",
+                name,
+                problems.join("\n- ")
+            )),
+        }
+    }
+}
+
+/// How a live backend differs from its catalog file entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// In the file, but not registered any more
+    Unregistered { backend: String },
+    /// In the file, but its tools couldn't be listed
+    Unavailable { backend: String, error: String },
+    /// Registered, but not in the file
+    Added { backend: String },
+    /// Listed tools don't match the fingerprint. Tools are named as the
+    /// backend names them.
+    Changed {
+        backend: String,
+        added: Vec<String>,
+        removed: Vec<String>,
+        changed: Vec<String>,
+    },
+}
+
+/// Drift of `live` (with the backends that failed to list) from `recorded`,
+/// in the recorded file's backend order, then backends new since
+pub fn diff(recorded: &CatalogFile, live: &CatalogFile, failures: &[Failure]) -> Vec<Drift> {
+    let live_backends: HashMap<&str, &Backend> =
+        live.backends.iter().map(|b| (b.name.as_str(), b)).collect();
+    let mut drift = Vec::new();
+    for old in &recorded.backends {
+        let backend = old.name.clone();
+        if let Some(failure) = failures.iter().find(|f| f.backend == old.name) {
+            drift.push(Drift::Unavailable {
+                backend,
+                error: failure.error.clone(),
+            });
+            continue;
+        }
+        let Some(new) = live_backends.get(old.name.as_str()) else {
+            drift.push(Drift::Unregistered { backend });
+            continue;
+        };
+        if new.fingerprint == old.fingerprint {
+            continue;
+        }
+        let tools = |b: &'_ Backend| -> HashMap<String, (Option<String>, String)> {
+            b.tools
+                .iter()
+                .map(|t| {
+                    let tool = &t.tool;
+                    let schema = canonical::hash(&tool.input_schema, Nulls::Keep);
+                    (tool.name.clone(), (tool.description.clone(), schema))
+                })
+                .collect()
+        };
+        let (before, after) = (tools(old), tools(new));
+        let sorted = |mut names: Vec<String>| {
+            names.sort();
+            names
+        };
+        drift.push(Drift::Changed {
+            backend,
+            added: sorted(
+                after
+                    .keys()
+                    .filter(|n| !before.contains_key(*n))
+                    .cloned()
+                    .collect(),
+            ),
+            removed: sorted(
+                before
+                    .keys()
+                    .filter(|n| !after.contains_key(*n))
+                    .cloned()
+                    .collect(),
+            ),
+            changed: sorted(
+                before
+                    .iter()
+                    .filter(|(n, tool)| after.get(*n).is_some_and(|t| t != *tool))
+                    .map(|(n, _)| n.clone())
+                    .collect(),
+            ),
+        });
+    }
+    let recorded_names: Vec<&str> = recorded.backends.iter().map(|b| b.name.as_str()).collect();
+    for backend in live
+        .backends
+        .iter()
+        .map(|b| &b.name)
+        .chain(failures.iter().map(|f| &f.backend))
+    {
+        if !recorded_names.contains(&backend.as_str()) {
+            drift.push(Drift::Added {
+                backend: backend.clone(),
+            });
+        }
+    }
+    drift
+}
+
+/// Human-readable report for `mcpd diff`
+pub fn render_diff(drift: &[Drift], out: &Output) -> String {
+    let mut text = String::new();
+    if drift.is_empty() {
+        text.push_str("Live backends match the catalog\n");
+        return text;
+    }
+    let _ = writeln!(
+        text,
+        "{} backend(s) drifted from the catalog:\n",
+        drift.len()
+    );
+    for entry in drift {
+        match entry {
+            Drift::Unregistered { backend } => {
+                let _ = writeln!(
+                    text,
+                    "{}: in the catalog but not registered",
+                    out.paint(backend, Color::Bold)
+                );
+            }
+            Drift::Unavailable { backend, error } => {
+                let _ = writeln!(
+                    text,
+                    "{}: couldn't list its tools: {}",
+                    out.paint(backend, Color::Bold),
+                    error
+                );
+            }
+            Drift::Added { backend } => {
+                let _ = writeln!(
+                    text,
+                    "{}: registered but not in the catalog",
+                    out.paint(backend, Color::Bold)
+                );
+            }
+            Drift::Changed {
+                backend,
+                added,
+                removed,
+                changed,
+            } => {
+                let _ = writeln!(text, "{}: tools changed", out.paint(backend, Color::Bold));
+                for (names, mark, color) in [
+                    (added, "+", Color::Green),
+                    (removed, "-", Color::Red),
+                    (changed, "~", Color::Yellow),
+                ] {
+                    for name in names {
+                        let _ = writeln!(
+                            text,
+                            "  {}",
+                            out.paint(&format!("{} {}", mark, name), color)
+                        );
+                    }
+                }
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A catalog file as `mcpd export-catalog` writes it
+    const FIXTURE: &str = r#"{
+  "version": 1,
+  "backends": [
+    {
+      "name": "fs",
+      "fingerprint": "",
+      "tools": [
+        {
+          "name": "fs__read_file",
+          "tool": {
+            "name": "read_file",
+            "description": "Read a file",
+            "inputSchema": {
+              "type": "object",
+              "properties": {"path": {"type": "string"}, "lines": {"type": "integer"}},
+              "required": ["path"]
+            }
+          }
+        },
+        {
+          "name": "fs__stat",
+          "tool": {"name": "stat", "inputSchema": {"type": "object"}}
+        }
+      ],
+      "prompts": [{"name": "summarize", "description": "Summarize a file"}],
+      "resources": [{"uri": "file:///readme", "name": "readme", "mimeType": "text/markdown"}]
+    },
+    {
+      "name": "git",
+      "fingerprint": "",
+      "tools": [
+        {"name": "git__log", "tool": {"name": "log", "inputSchema": {"type": "object"}}}
+      ]
+    }
+  ]
+}"#;
+
+    /// The fixture, with fingerprints that match its tools
+    fn fixture() -> CatalogFile {
+        let mut catalog: CatalogFile = serde_json::from_str(FIXTURE).unwrap();
+        for backend in &mut catalog.backends {
+            backend.fingerprint = fingerprint(&backend.tools);
+        }
+        catalog
+    }
+
+    fn text(result: &CallToolResult) -> &str {
+        match &result.content[0] {
+            Content::Text { text } => text,
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn loads_fixture_and_refuses_other_versions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("catalog.json");
+        std::fs::write(&path, FIXTURE).unwrap();
+        let catalog = CatalogFile::load(&path).unwrap();
+        let names: Vec<String> = catalog.tools().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["fs__read_file", "fs__stat", "git__log"]);
+        assert_eq!(catalog.find("git__log").unwrap().tool.name, "log");
+        assert_eq!(catalog.backends[0].prompts[0].name, "summarize");
+        assert_eq!(catalog.backends[0].resources[0].uri, "file:///readme");
+        assert!(catalog.backends[1].prompts.is_empty());
+
+        std::fs::write(&path, FIXTURE.replace("\"version\": 1", "\"version\": 2")).unwrap();
+        let error = CatalogFile::load(&path).unwrap_err().to_string();
+        assert!(error.contains("has version 2"), "{}", error);
+
+        std::fs::write(&path, r#"{"tools": []}"#).unwrap();
+        let error = CatalogFile::load(&path).unwrap_err().to_string();
+        assert!(error.contains("isn't an mcpd catalog file"), "{}", error);
+    }
+
+    #[test]
+    fn backends_are_fingerprinted_by_their_tools() {
+        let tool = |name: &str| McpTool {
+            name: name.to_string(),
+            title: None,
+            description: None,
+            input_schema: json!({"type": "object"}),
+            annotations: None,
+        };
+        let backend = Backend::new(
+            "fs".to_string(),
+            vec![("fs__stat".to_string(), tool("stat"))],
+            Vec::new(),
+            Vec::new(),
+        );
+        assert_eq!(backend.fingerprint, conflicts::fingerprint(&[tool("stat")]));
+        assert_eq!(
+            serde_json::to_value(CatalogFile::new(vec![backend])).unwrap(),
+            json!({
+                "version": VERSION,
+                "backends": [{
+                    "name": "fs",
+                    "fingerprint": conflicts::fingerprint(&[tool("stat")]),
+                    "tools": [{
+                        "name": "fs__stat",
+                        "tool": {"name": "stat", "description": null, "inputSchema": {"type": "object"}}
+                    }],
+                    "prompts": [],
+                    "resources": []
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn schema_only_refuses_calls() {
+        let schema_only = SchemaOnly {
+            catalog: Arc::new(fixture()),
+            echo_calls: false,
+        };
+        let result = schema_only.call("fs__read_file", &json!({"path": "a.txt"}));
+        assert!(result.is_error);
+        assert!(
+            text(&result).starts_with("schema-only mode, call not executed"),
+            "{}",
+            text(&result)
+        );
+
+        let result = schema_only.call("fs__write_file", &json!({}));
+        assert!(result.is_error);
+        assert!(text(&result).contains("Unknown tool 'fs__write_file'"));
+    }
+
+    #[test]
+    fn echo_calls_returns_checked_arguments() {
+        let schema_only = SchemaOnly {
+            catalog: Arc::new(fixture()),
+            echo_calls: true,
+        };
+        let arguments = json!({"path": "a.txt", "lines": 10});
+        let result = schema_only.call("fs__read_file", &arguments);
+        assert!(!result.is_error);
+        assert_eq!(
+            serde_json::from_str::<Value>(text(&result)).unwrap(),
+            arguments
+        );
+
+        let result = schema_only.call("fs__read_file", &json!({"lines": "ten"}));
+        assert!(result.is_error);
+        let message = text(&result);
+        assert!(message.contains("call not executed"), "{}", message);
+        assert!(
+            message.contains("\"path\" is a required property"),
+            "{}",
+            message
+        );
+        assert!(message.contains(" at /lines"), "{}", message);
+    }
+
+    #[test]
+    fn diff_reports_drift_per_backend() {
+        let recorded = fixture();
+        assert_eq!(diff(&recorded, &recorded, &[]), []);
+
+        let mut live = fixture();
+        let fs = &mut live.backends[0];
+        fs.tools[0].tool.input_schema["properties"]["encoding"] = json!({"type": "string"});
+        fs.tools.remove(1);
+        fs.tools.push(ExportedTool {
+            name: "fs__write_file".to_string(),
+            tool: McpTool {
+                name: "write_file".to_string(),
+                title: None,
+                description: None,
+                input_schema: json!({"type": "object"}),
+                annotations: None,
+            },
+        });
+        fs.fingerprint = fingerprint(&fs.tools);
+        // Presentation that isn't part of the fingerprint doesn't count
+        live.backends[1].tools[0].tool.title = Some("Log".to_string());
+        live.backends.push(Backend::new(
+            "web".to_string(),
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+        ));
+
+        let drift = diff(&recorded, &live, &[]);
+        assert_eq!(
+            drift,
+            [
+                Drift::Changed {
+                    backend: "fs".to_string(),
+                    added: vec!["write_file".to_string()],
+                    removed: vec!["stat".to_string()],
+                    changed: vec!["read_file".to_string()],
+                },
+                Drift::Added {
+                    backend: "web".to_string()
+                },
+            ]
+        );
+        let report = render_diff(&drift, &Output::plain());
+        assert_eq!(
+            report,
+            "2 backend(s) drifted from the catalog:
+
+fs: tools changed
+  + write_file
+  - stat
+  ~ read_file
+web: registered but not in the catalog
+"
+        );
+
+        live.backends.remove(0);
+        let failures = [Failure {
+            backend: "git".to_string(),
+            error: "timed out after 30s".to_string(),
+        }];
+        live.backends.remove(0);
+        assert_eq!(
+            diff(&recorded, &live, &failures)[..2],
+            [
+                Drift::Unregistered {
+                    backend: "fs".to_string()
+                },
+                Drift::Unavailable {
+                    backend: "git".to_string(),
+                    error: "timed out after 30s".to_string(),
+                },
+            ]
+        );
+    }
+}
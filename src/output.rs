@@ -0,0 +1,457 @@
+//! Terminal output for the CLI: aligned tables, color and status glyphs.
+//!
+//! Color is on when stdout is a terminal and `NO_COLOR` isn't set, or as
+//! forced by `--color`. Tables are cut to the terminal's width only when
+//! writing to a terminal (and not with `--wide`); piped output is plain,
+//! aligned and complete. Widths are display widths, so wide characters and
+//! emoji line up.
+
+use std::io::IsTerminal;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Width assumed for a terminal that won't report its size
+const DEFAULT_WIDTH: usize = 100;
+
+/// Narrowest a truncated column gets, however small the terminal
+const MIN_TRUNCATED_WIDTH: usize = 12;
+
+/// Space between table columns
+const GAP: &str = "  ";
+
+/// When to color output (`--color`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// When stdout is a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Colors and text styles used by the CLI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Green,
+    Yellow,
+    Red,
+    Dim,
+    Bold,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Red => "31",
+            Color::Dim => "2",
+            Color::Bold => "1",
+        }
+    }
+}
+
+/// Outcome of a check, shown as a glyph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// How output is rendered for the current stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Output {
+    color: bool,
+    /// Width to fit tables into; `None` never truncates
+    width: Option<usize>,
+}
+
+impl Output {
+    /// Settle color and width for stdout from `--color`, `--wide`,
+    /// `NO_COLOR` and whether stdout is a terminal
+    pub fn detect(color: ColorChoice, wide: bool) -> Self {
+        let terminal = std::io::stdout().is_terminal();
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self {
+            color: match color {
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+                ColorChoice::Auto => terminal && !no_color,
+            },
+            width: (terminal && !wide).then(|| terminal_width().unwrap_or(DEFAULT_WIDTH)),
+        }
+    }
+
+    /// No color and no truncation, as when piped
+    pub fn plain() -> Self {
+        Self {
+            color: false,
+            width: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_width(mut self, width: Option<usize>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// `text` in `color`, or unchanged without color
+    pub fn paint(&self, text: &str, color: Color) -> String {
+        if self.color && !text.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// A status dot: green when good, red when not. Plain output spells it out.
+    pub fn dot(&self, ok: bool) -> String {
+        match (self.color, ok) {
+            (true, true) => self.paint("●", Color::Green),
+            (true, false) => self.paint("●", Color::Red),
+            (false, true) => "+".to_string(),
+            (false, false) => "-".to_string(),
+        }
+    }
+
+    /// Glyph for a check's outcome
+    pub fn check(&self, check: Check) -> String {
+        match check {
+            Check::Pass => self.paint("✓", Color::Green),
+            Check::Warn => self.paint("!", Color::Yellow),
+            Check::Fail => self.paint("✗", Color::Red),
+        }
+    }
+}
+
+fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS").ok().and_then(|c| c.parse().ok()) {
+        return Some(columns);
+    }
+    terminal_size::terminal_size_of(std::io::stdout())
+        .map(|(terminal_size::Width(columns), _)| columns as usize)
+        .filter(|&columns| columns > 0)
+}
+
+/// Shorten `text` to `max` display columns by cutting out its middle, so
+/// both the program and the last arguments of a command stay visible
+pub fn truncate_middle(text: &str, max: usize) -> String {
+    if text.width() <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let keep = max - 1;
+
+    let mut head = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w > keep.div_ceil(2) {
+            break;
+        }
+        width += w;
+        head.push(c);
+    }
+    // The tail gets whatever the head couldn't use
+    let tail_width = keep - width;
+    let mut tail = Vec::new();
+    let mut width = 0;
+    for c in text.chars().rev() {
+        let w = c.width().unwrap_or(0);
+        if width + w > tail_width {
+            break;
+        }
+        width += w;
+        tail.push(c);
+    }
+    head.push('…');
+    head.extend(tail.into_iter().rev());
+    head
+}
+
+/// One table cell, optionally colored
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+}
+
+impl Cell {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            color: None,
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::new(text)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::new(text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+}
+
+struct Row {
+    cells: Vec<Cell>,
+    /// Prefix rendered before the first cell, outside the column layout
+    /// (e.g. a status dot)
+    marker: Option<String>,
+    /// Indented lines shown under the row, already styled
+    details: Vec<String>,
+}
+
+/// Column-aligned table with optional per-row detail lines
+pub struct Table {
+    headers: Vec<String>,
+    align: Vec<Align>,
+    /// Column cut in the middle when the table is wider than the output
+    truncate: Option<usize>,
+    rows: Vec<Row>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            align: vec![Align::Left; headers.len()],
+            truncate: None,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Right-align a column, for numbers
+    pub fn right(mut self, column: usize) -> Self {
+        self.align[column] = Align::Right;
+        self
+    }
+
+    /// Cut this column's values in the middle to fit the output width
+    pub fn truncate(mut self, column: usize) -> Self {
+        self.truncate = Some(column);
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<Cell>) -> &mut Self {
+        self.rows.push(Row {
+            cells,
+            marker: None,
+            details: Vec::new(),
+        });
+        self
+    }
+
+    /// A row with a marker before it and lines shown under it
+    pub fn row_with(
+        &mut self,
+        marker: Option<String>,
+        cells: Vec<Cell>,
+        details: Vec<String>,
+    ) -> &mut Self {
+        self.rows.push(Row {
+            cells,
+            marker,
+            details,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn render(&self, out: &Output) -> String {
+        let columns = self.headers.len();
+        let has_markers = self.rows.iter().any(|r| r.marker.is_some());
+        // Markers are one column wide plus a space
+        let indent = if has_markers { 2 } else { 0 };
+
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.width()).collect();
+        for row in &self.rows {
+            for (i, cell) in row.cells.iter().enumerate().take(columns) {
+                widths[i] = widths[i].max(cell.text.width());
+            }
+        }
+        if let (Some(column), Some(max)) = (self.truncate, out.width) {
+            let total = indent + widths.iter().sum::<usize>() + GAP.len() * (columns - 1);
+            if total > max {
+                let floor = MIN_TRUNCATED_WIDTH.min(widths[column]);
+                widths[column] = widths[column].saturating_sub(total - max).max(floor);
+            }
+        }
+
+        let mut text = String::new();
+        let header: Vec<Cell> = self
+            .headers
+            .iter()
+            .map(|h| Cell::new(h.as_str()).color(Color::Bold))
+            .collect();
+        self.render_line(&mut text, out, &widths, " ".repeat(indent), &header);
+        for row in &self.rows {
+            let marker = match &row.marker {
+                Some(marker) => format!("{} ", marker),
+                None => " ".repeat(indent),
+            };
+            self.render_line(&mut text, out, &widths, marker, &row.cells);
+            for detail in &row.details {
+                text.push_str(&" ".repeat(indent + 4));
+                text.push_str(detail);
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    fn render_line(
+        &self,
+        text: &mut String,
+        out: &Output,
+        widths: &[usize],
+        marker: String,
+        cells: &[Cell],
+    ) {
+        let mut line = marker;
+        let last = cells.len().min(widths.len()).saturating_sub(1);
+        for (i, cell) in cells.iter().enumerate().take(widths.len()) {
+            let value = if self.truncate == Some(i) {
+                truncate_middle(&cell.text, widths[i])
+            } else {
+                cell.text.clone()
+            };
+            let pad = " ".repeat(widths[i].saturating_sub(value.width()));
+            let painted = match cell.color {
+                Some(color) => out.paint(&value, color),
+                None => value,
+            };
+            match self.align[i] {
+                Align::Right => {
+                    line.push_str(&pad);
+                    line.push_str(&painted);
+                }
+                // No trailing spaces after the last column
+                Align::Left if i == last => line.push_str(&painted),
+                Align::Left => {
+                    line.push_str(&painted);
+                    line.push_str(&pad);
+                }
+            }
+            if i < last {
+                line.push_str(GAP);
+            }
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fixed table exercising alignment, markers, details, color and wide
+    /// characters
+    fn fixture() -> Table {
+        let mut table = Table::new(&["NAME", "COMMAND", "CALLS"])
+            .right(2)
+            .truncate(1);
+        table.row_with(
+            Some("+".to_string()),
+            vec![
+                Cell::new("fs"),
+                Cell::new(r#"["npx", "-y", "@modelcontextprotocol/server-filesystem", "/tmp"]"#),
+                Cell::new("12"),
+            ],
+            vec!["API_KEY=****".to_string()],
+        );
+        table.row_with(
+            Some("-".to_string()),
+            vec![
+                Cell::new("日本語").color(Color::Red),
+                Cell::new(r#"["jp"]"#),
+                Cell::new("3"),
+            ],
+            Vec::new(),
+        );
+        table
+    }
+
+    #[test]
+    fn plain_table_snapshot() {
+        let expected = concat!(
+            "  NAME    COMMAND                                                           CALLS\n",
+            "+ fs      [\"npx\", \"-y\", \"@modelcontextprotocol/server-filesystem\", \"/tmp\"]     12\n",
+            "      API_KEY=****\n",
+            "- 日本語  [\"jp\"]                                                                3\n",
+        );
+        assert_eq!(fixture().render(&Output::plain()), expected);
+    }
+
+    #[test]
+    fn colored_table_snapshot() {
+        let out = Output::plain().with_color(true);
+        let expected = concat!(
+            "  \x1b[1mNAME\x1b[0m    \x1b[1mCOMMAND\x1b[0m                                                           \x1b[1mCALLS\x1b[0m\n",
+            "+ fs      [\"npx\", \"-y\", \"@modelcontextprotocol/server-filesystem\", \"/tmp\"]     12\n",
+            "      API_KEY=****\n",
+            "- \x1b[31m日本語\x1b[0m  [\"jp\"]                                                                3\n",
+        );
+        assert_eq!(fixture().render(&out), expected);
+    }
+
+    #[test]
+    fn truncates_to_width_in_the_middle() {
+        let out = Output::plain().with_width(Some(40));
+        let expected = concat!(
+            "  NAME    COMMAND                  CALLS\n",
+            "+ fs      [\"npx\", \"-y…m\", \"/tmp\"]     12\n",
+            "      API_KEY=****\n",
+            "- 日本語  [\"jp\"]                       3\n",
+        );
+        let text = fixture().render(&out);
+        assert_eq!(text, expected);
+        assert!(text.lines().all(|l| l.width() <= 40), "{}", text);
+    }
+
+    #[test]
+    fn truncate_middle_counts_display_width() {
+        assert_eq!(truncate_middle("abcdefghij", 10), "abcdefghij");
+        assert_eq!(truncate_middle("abcdefghij", 5), "ab…ij");
+        assert_eq!(truncate_middle("日本語テキスト", 7), "日…スト");
+        assert!(truncate_middle("日本語テキスト", 7).width() <= 7);
+        assert_eq!(truncate_middle("abc", 0), "");
+    }
+
+    #[test]
+    fn glyphs_and_dots() {
+        let plain = Output::plain();
+        assert_eq!(plain.check(Check::Pass), "✓");
+        assert_eq!(plain.check(Check::Fail), "✗");
+        assert_eq!(plain.dot(true), "+");
+        let color = plain.with_color(true);
+        assert_eq!(color.check(Check::Warn), "\x1b[33m!\x1b[0m");
+        assert_eq!(color.dot(false), "\x1b[31m●\x1b[0m");
+    }
+}
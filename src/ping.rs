@@ -0,0 +1,179 @@
+//! `serve --enable-ping-tool`: the built-in `mcpd__ping_backends` tool, a
+//! quick look at which backends answer right now without listing their
+//! tools. Running backends get an MCP `ping`; stopped ones are started and
+//! pinged only when asked with `include_stopped`. Every ping also counts as
+//! a health check of its backend (see `health`).
+
+use crate::mcp::{CallToolResult, Content, Tool as McpTool};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Name the tool is listed and called by
+pub const TOOL_NAME: &str = "mcpd__ping_backends";
+
+/// How long each backend gets, including starting it with `include_stopped`
+pub const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `mcpd__ping_backends`'s arguments
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Args {
+    /// Start backends that aren't running to ping them too
+    #[serde(default)]
+    pub include_stopped: bool,
+}
+
+impl Args {
+    pub fn parse(arguments: &Value) -> Result<Self> {
+        Args::deserialize(arguments).context("expected {\"include_stopped\": <bool>}")
+    }
+}
+
+/// How one backend answered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub backend: String,
+    pub reachable: bool,
+    /// Round trip of the ping itself, not counting a start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Not running, and not started to be pinged
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub skipped: bool,
+}
+
+impl Report {
+    pub fn answered(backend: &str, latency: Duration) -> Self {
+        Self {
+            backend: backend.to_string(),
+            reachable: true,
+            latency_ms: Some(latency.as_millis() as u64),
+            error: None,
+            skipped: false,
+        }
+    }
+
+    pub fn failed(backend: &str, error: String) -> Self {
+        Self {
+            backend: backend.to_string(),
+            reachable: false,
+            latency_ms: None,
+            error: Some(error),
+            skipped: false,
+        }
+    }
+
+    pub fn skipped(backend: &str) -> Self {
+        Self {
+            backend: backend.to_string(),
+            reachable: false,
+            latency_ms: None,
+            error: None,
+            skipped: true,
+        }
+    }
+}
+
+/// The `list_tools` entry for `mcpd__ping_backends`
+pub fn tool() -> McpTool {
+    McpTool {
+        name: TOOL_NAME.to_string(),
+        title: None,
+        description: Some(format!(
+            "Check which backend servers are responsive right now, without listing their tools. \
+             Pings every running backend at once, waiting at most {}s for each; stopped backends \
+             are only started and pinged with include_stopped. Returns each backend's \
+             reachability, latency and error as structuredContent.",
+            PING_TIMEOUT.as_secs()
+        )),
+        input_schema: json!({
+            "type": "object",
+            "properties": {
+                "include_stopped": {
+                    "type": "boolean",
+                    "description": "Also start backends that aren't running and ping them"
+                }
+            },
+            "additionalProperties": false
+        }),
+        annotations: None,
+    }
+}
+
+/// The tool's result: the reports as structuredContent, a line each as text
+pub fn result(reports: &[Report]) -> CallToolResult {
+    let pinged = reports.iter().filter(|r| !r.skipped).count();
+    let reachable = reports.iter().filter(|r| r.reachable).count();
+    let mut text = format!("{} of {} pinged backends reachable", reachable, pinged);
+    for report in reports {
+        let line = match report {
+            Report { skipped: true, .. } => {
+                "not running (pass include_stopped to start it)".to_string()
+            }
+            Report {
+                latency_ms: Some(ms),
+                ..
+            } => format!("ok ({} ms)", ms),
+            Report { error, .. } => {
+                format!("unreachable: {}", error.as_deref().unwrap_or("no answer"))
+            }
+        };
+        text.push_str(&format!("\n{}: {}", report.backend, line));
+    }
+    CallToolResult {
+        content: vec![Content::Text { text }],
+        is_error: false,
+        structured_content: Some(json!({ "backends": reports })),
+        meta: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_default_to_running_backends_only() {
+        assert!(!Args::parse(&json!({})).unwrap().include_stopped);
+        assert!(
+            Args::parse(&json!({"include_stopped": true}))
+                .unwrap()
+                .include_stopped
+        );
+        assert!(Args::parse(&json!({"include_stoped": true})).is_err());
+        assert!(Args::parse(&json!({"include_stopped": "yes"})).is_err());
+    }
+
+    #[test]
+    fn result_lists_every_backend() {
+        let reports = [
+            Report::answered("fs", Duration::from_millis(3)),
+            Report::failed("git", "timed out after 2s".to_string()),
+            Report::skipped("slack"),
+        ];
+        let result = result(&reports);
+        let Content::Text { text } = &result.content[0] else {
+            panic!("expected text");
+        };
+        assert_eq!(
+            text,
+            "1 of 2 pinged backends reachable\n\
+             fs: ok (3 ms)\n\
+             git: unreachable: timed out after 2s\n\
+             slack: not running (pass include_stopped to start it)"
+        );
+        assert_eq!(
+            result.structured_content.unwrap(),
+            json!({"backends": [
+                {"backend": "fs", "reachable": true, "latency_ms": 3},
+                {"backend": "git", "reachable": false, "error": "timed out after 2s"},
+                {"backend": "slack", "reachable": false, "skipped": true}
+            ]})
+        );
+        assert!(!result.is_error);
+    }
+}
@@ -0,0 +1,697 @@
+//! Pinning servers launched through npx, uvx or `pipx run` to one version.
+//!
+//! `npx -y @modelcontextprotocol/server-github` runs whatever was published
+//! last, so a release can rename tools under a working setup. These rules
+//! find the package in such a command and rewrite it to an exact version
+//! (`@modelcontextprotocol/server-github@1.2.3`). `mcpd register
+//! --pin-version`, `mcpd outdated` and `mcpd upgrade` build on them.
+
+use crate::output::{Cell, Color, Output, Table};
+use crate::proxy::ToolProxy;
+use crate::registry::{Registry, Tool};
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+use std::time::Duration;
+
+/// How long a test handshake may take, package download included
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A package runner that fetches what it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Launcher {
+    Npx,
+    Uvx,
+    PipxRun,
+}
+
+impl Launcher {
+    /// What goes between package name and version in a pinned spec
+    fn separator(self) -> &'static str {
+        match self {
+            Launcher::Npx | Launcher::Uvx => "@",
+            Launcher::PipxRun => "==",
+        }
+    }
+
+    /// Options that name the package when the command run differs from it
+    fn package_options(self) -> &'static [&'static str] {
+        match self {
+            Launcher::Npx => &["-p", "--package"],
+            Launcher::Uvx => &["--from"],
+            Launcher::PipxRun => &["--spec"],
+        }
+    }
+
+    /// Other options that take a value, so it isn't mistaken for the package
+    fn value_options(self) -> &'static [&'static str] {
+        match self {
+            Launcher::Npx => &["-c", "--call", "--registry", "--cache", "--userconfig"],
+            Launcher::Uvx => &[
+                "-w",
+                "--with",
+                "--with-editable",
+                "--with-requirements",
+                "-p",
+                "--python",
+                "--index",
+                "--index-url",
+                "--default-index",
+                "--extra-index-url",
+                "-c",
+                "--constraints",
+                "--directory",
+                "--cache-dir",
+            ],
+            Launcher::PipxRun => &["--python", "--pip-args", "-i", "--index-url", "--backend"],
+        }
+    }
+
+    /// Whether packages come from npm (or else PyPI)
+    fn is_npm(self) -> bool {
+        self == Launcher::Npx
+    }
+}
+
+impl std::fmt::Display for Launcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Launcher::Npx => "npx",
+            Launcher::Uvx => "uvx",
+            Launcher::PipxRun => "pipx run",
+        })
+    }
+}
+
+/// The package a launcher command runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub launcher: Launcher,
+    /// As written, extras included (`mcp-server-git[cli]`)
+    pub name: String,
+    /// What the command asks for after the name: an exact version, a tag
+    /// like `latest` or a range. `None` floats to the latest release.
+    pub version: Option<String>,
+    /// Index of the command argument holding the spec
+    index: usize,
+    /// Kept in front of the spec when rewriting, for `--from=spec`
+    prefix: String,
+}
+
+impl Package {
+    /// The package an npx, uvx or `pipx run` command runs, or `None` for
+    /// other commands
+    pub fn detect(command: &[String]) -> Option<Package> {
+        let program = Path::new(command.first()?).file_name()?.to_str()?;
+        let (launcher, start) = match program {
+            "npx" => (Launcher::Npx, 1),
+            "uvx" => (Launcher::Uvx, 1),
+            "pipx" if command.get(1).is_some_and(|a| a == "run") => (Launcher::PipxRun, 2),
+            _ => return None,
+        };
+
+        let mut i = start;
+        while i < command.len() {
+            let arg = &command[i];
+            if arg == "--" {
+                return Self::at(launcher, command, i + 1, "");
+            }
+            if !arg.starts_with('-') || arg == "-" {
+                return Self::at(launcher, command, i, "");
+            }
+            let (option, inline_value) = match arg.split_once('=') {
+                Some((option, _)) => (option, true),
+                None => (arg.as_str(), false),
+            };
+            if launcher.package_options().contains(&option) {
+                return if inline_value {
+                    Self::at(launcher, command, i, &arg[..=option.len()])
+                } else {
+                    Self::at(launcher, command, i + 1, "")
+                };
+            }
+            if !inline_value && launcher.value_options().contains(&option) {
+                i += 1;
+            }
+            i += 1;
+        }
+        None
+    }
+
+    fn at(launcher: Launcher, command: &[String], index: usize, prefix: &str) -> Option<Package> {
+        let spec = command.get(index)?.strip_prefix(prefix)?;
+        let (name, version) = if launcher.is_npm() {
+            split_npm_spec(spec)
+        } else {
+            split_python_spec(spec)
+        };
+        if name.is_empty() {
+            return None;
+        }
+        Some(Package {
+            launcher,
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            index,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// The version the command is pinned to, when it names an exact one
+    pub fn pinned_version(&self) -> Option<&str> {
+        self.version.as_deref().filter(|v| is_exact_version(v))
+    }
+
+    /// `command` running exactly `version` of the package
+    pub fn pinned(&self, command: &[String], version: &str) -> Vec<String> {
+        self.rewrite(command, Some(version))
+    }
+
+    /// `command` running the latest release. npx and uvx are asked for
+    /// `@latest` so a cached copy isn't reused; `pipx run` has no such tag.
+    pub fn floating(&self, command: &[String]) -> Vec<String> {
+        match self.launcher {
+            Launcher::Npx | Launcher::Uvx => self.rewrite(command, Some("latest")),
+            Launcher::PipxRun => self.rewrite(command, None),
+        }
+    }
+
+    fn rewrite(&self, command: &[String], version: Option<&str>) -> Vec<String> {
+        let mut command = command.to_vec();
+        command[self.index] = match version {
+            Some(version) => format!(
+                "{}{}{}{}",
+                self.prefix,
+                self.name,
+                self.launcher.separator(),
+                version
+            ),
+            None => format!("{}{}", self.prefix, self.name),
+        };
+        command
+    }
+
+    /// Where the package registry answers with the latest release
+    pub fn upstream_url(&self) -> String {
+        if self.launcher.is_npm() {
+            format!(
+                "https://registry.npmjs.org/{}/latest",
+                self.name.replace('/', "%2F")
+            )
+        } else {
+            let project = self.name.split('[').next().unwrap_or(&self.name);
+            format!("https://pypi.org/pypi/{}/json", project)
+        }
+    }
+
+    /// The latest release according to the package registry
+    pub async fn upstream_version(&self) -> Result<String> {
+        let url = self.upstream_url();
+        let is_npm = self.launcher.is_npm();
+        tokio::task::spawn_blocking(move || -> Result<String> {
+            let body = ureq::get(&url)
+                .call()
+                .with_context(|| format!("Failed to fetch {}", url))?
+                .body_mut()
+                .read_to_string()
+                .with_context(|| format!("Failed to read {}", url))?;
+            parse_upstream(is_npm, &body)
+        })
+        .await?
+    }
+}
+
+/// `@scope/name@1.2.3` → (`@scope/name`, `1.2.3`)
+fn split_npm_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.get(1..).and_then(|rest| rest.rfind('@')) {
+        Some(at) => (&spec[..=at], Some(&spec[at + 2..])),
+        None => (spec, None),
+    }
+}
+
+/// `name[extra]==1.2.3` or `name@1.2.3` → (`name[extra]`, `1.2.3`). Other
+/// specifiers (`>=1.2`) count as unpinned and are dropped on rewrite.
+fn split_python_spec(spec: &str) -> (&str, Option<&str>) {
+    if let Some((name, version)) = spec.split_once("==") {
+        return (name.trim(), Some(version.trim()));
+    }
+    if let Some((name, version)) = spec.split_once('@') {
+        return (name.trim(), Some(version.trim()));
+    }
+    let end = spec
+        .find(['<', '>', '=', '!', '~', ';', ' '])
+        .unwrap_or(spec.len());
+    (spec[..end].trim(), None)
+}
+
+/// Whether `version` names one release (`1.2.3`, `0.6.0-rc.1`) rather
+/// than a tag or range
+pub fn is_exact_version(version: &str) -> bool {
+    version.starts_with(|c: char| c.is_ascii_digit())
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+}
+
+/// The version in a package registry's answer: npm's `/latest` document
+/// or PyPI's project JSON
+fn parse_upstream(is_npm: bool, body: &str) -> Result<String> {
+    let value: serde_json::Value =
+        serde_json::from_str(body).context("Package registry sent invalid JSON")?;
+    let version = if is_npm {
+        &value["version"]
+    } else {
+        &value["info"]["version"]
+    };
+    version
+        .as_str()
+        .map(str::to_string)
+        .context("Package registry answer has no version")
+}
+
+/// Start `tool`, complete the MCP handshake and return the version it
+/// reports in its serverInfo
+pub async fn handshake(tool: &Tool, timeout: Duration) -> Result<String> {
+    let proxy = ToolProxy::new(tool.clone());
+    let info = tokio::time::timeout(timeout, proxy.server_info()).await;
+    let _ = proxy.stop().await;
+    match info {
+        Ok(info) => Ok(info?.version),
+        Err(_) => bail!("Handshake timed out after {:?}", timeout),
+    }
+}
+
+/// `tool` with its command pinned to the version it runs now. The version
+/// comes from the server's serverInfo, and the pinned command must complete
+/// a handshake too, since servers don't always report their package version.
+/// Returns the pinned tool and version; a command that's already pinned is
+/// returned as is.
+pub async fn pin(tool: &Tool, timeout: Duration) -> Result<(Tool, String)> {
+    let package = Package::detect(&tool.command)
+        .context("Only npx, uvx and 'pipx run' commands can be pinned")?;
+    if let Some(version) = package.pinned_version() {
+        return Ok((tool.clone(), version.to_string()));
+    }
+
+    let version = handshake(tool, timeout)
+        .await
+        .with_context(|| format!("'{}' failed its test handshake", tool.name))?;
+    if !is_exact_version(&version) {
+        bail!(
+            "'{}' reports version '{}', which isn't a release of {}; pin it by hand",
+            tool.name,
+            version,
+            package.name
+        );
+    }
+    let pinned = Tool {
+        command: package.pinned(&tool.command, &version),
+        ..tool.clone()
+    };
+    handshake(&pinned, timeout).await.with_context(|| {
+        format!(
+            "'{}' reports version {}, but {}{}{} failed its test handshake; pin it by hand",
+            tool.name,
+            version,
+            package.name,
+            package.launcher.separator(),
+            version
+        )
+    })?;
+    Ok((pinned, version))
+}
+
+/// What `mcpd upgrade` did
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upgrade {
+    pub previous: Option<String>,
+    pub version: String,
+}
+
+/// Re-pin registry entry `name` to its latest release. The registry is only
+/// written once the new version has passed its test handshake, so a failed
+/// upgrade leaves the old pin in place.
+pub async fn upgrade(registry: &mut Registry, name: &str, timeout: Duration) -> Result<Upgrade> {
+    let tool = registry
+        .list()
+        .find(|t| t.name == name)
+        .cloned()
+        .with_context(|| format!("Tool '{}' not found", name))?;
+    let package = Package::detect(&tool.command).with_context(|| {
+        format!(
+            "'{}' isn't launched through npx, uvx or 'pipx run': {:?}",
+            name, tool.command
+        )
+    })?;
+    let previous = package.pinned_version().map(str::to_string);
+    let latest = Tool {
+        command: package.floating(&tool.command),
+        ..tool
+    };
+    let (upgraded, version) = pin(&latest, timeout).await?;
+    if previous.as_deref() != Some(version.as_str()) {
+        registry.register(upgraded)?;
+    }
+    Ok(Upgrade { previous, version })
+}
+
+/// One registry entry in `mcpd outdated`
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub name: String,
+    pub package: Package,
+    /// Version the entry reports when started, or why that failed
+    pub running: Result<String, String>,
+    /// Latest release in the package registry, with `--check-upstream`
+    pub latest: Option<Result<String, String>>,
+}
+
+impl Status {
+    /// A version worth upgrading to: the latest release when it's known,
+    /// else what the entry actually runs when that isn't its pin
+    pub fn update(&self) -> Option<&str> {
+        let pinned = self.package.pinned_version()?;
+        let newer = match &self.latest {
+            Some(latest) => latest.as_deref().ok(),
+            None => self.running.as_deref().ok(),
+        };
+        newer.filter(|v| *v != pinned)
+    }
+}
+
+/// Check every registry entry launched through a package runner. Entries
+/// are started concurrently; with `check_upstream` the package registry
+/// is asked for the latest release too.
+pub async fn outdated(tools: Vec<Tool>, check_upstream: bool, timeout: Duration) -> Vec<Status> {
+    let mut checks = tokio::task::JoinSet::new();
+    for (i, tool) in tools.into_iter().enumerate() {
+        let Some(package) = Package::detect(&tool.command) else {
+            continue;
+        };
+        checks.spawn(async move {
+            let running = handshake(&tool, timeout)
+                .await
+                .map_err(|e| format!("{:#}", e));
+            let latest = if check_upstream {
+                Some(
+                    package
+                        .upstream_version()
+                        .await
+                        .map_err(|e| format!("{:#}", e)),
+                )
+            } else {
+                None
+            };
+            let status = Status {
+                name: tool.name,
+                package,
+                running,
+                latest,
+            };
+            (i, status)
+        });
+    }
+    let mut statuses = checks.join_all().await;
+    statuses.sort_by_key(|(i, _)| *i);
+    statuses.into_iter().map(|(_, status)| status).collect()
+}
+
+/// Table for `mcpd outdated`, in registry order
+pub fn render(statuses: &[Status], out: &Output) -> String {
+    if statuses.is_empty() {
+        return "No servers launched through npx, uvx or 'pipx run'\n".to_string();
+    }
+    let with_latest = statuses.iter().any(|s| s.latest.is_some());
+    let mut headers = vec!["NAME", "PACKAGE", "PINNED", "RUNNING"];
+    if with_latest {
+        headers.push("LATEST");
+    }
+    let mut table = Table::new(&headers).truncate(1);
+    let version_cell = |version: &Result<String, String>| match version {
+        Ok(v) => Cell::new(v.as_str()),
+        Err(_) => Cell::new("error").color(Color::Red),
+    };
+    let mut updates = Vec::new();
+    for status in statuses {
+        let pinned = match status.package.pinned_version() {
+            Some(v) => Cell::new(v),
+            None => Cell::new("unpinned").color(Color::Yellow),
+        };
+        let mut cells = vec![
+            Cell::new(status.name.as_str()),
+            Cell::new(format!(
+                "{} ({})",
+                status.package.name, status.package.launcher
+            )),
+            pinned,
+            version_cell(&status.running),
+        ];
+        if let Some(latest) = &status.latest {
+            cells.push(version_cell(latest));
+        } else if with_latest {
+            cells.push(Cell::new(""));
+        }
+        let errors = [Some(&status.running), status.latest.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.as_ref().err().cloned())
+            .collect();
+        table.row_with(None, cells, errors);
+        if let Some(version) = status.update() {
+            updates.push(format!("  mcpd upgrade {}  # {}", status.name, version));
+        }
+    }
+
+    let mut text = table.render(out);
+    if !updates.is_empty() {
+        text.push_str(&format!("\n{} update(s) available:\n", updates.len()));
+        for update in updates {
+            text.push_str(&update);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn detect(args: &[&str]) -> Package {
+        Package::detect(&argv(args)).unwrap()
+    }
+
+    #[test]
+    fn npx_scoped_package() {
+        let command = argv(&["/usr/bin/npx", "-y", "@modelcontextprotocol/server-github"]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.launcher, Launcher::Npx);
+        assert_eq!(package.name, "@modelcontextprotocol/server-github");
+        assert_eq!(package.version, None);
+        assert_eq!(
+            package.pinned(&command, "1.2.3"),
+            [
+                "/usr/bin/npx",
+                "-y",
+                "@modelcontextprotocol/server-github@1.2.3"
+            ]
+        );
+        assert_eq!(
+            package.floating(&command)[2],
+            "@modelcontextprotocol/server-github@latest"
+        );
+    }
+
+    #[test]
+    fn npx_versions_and_tags() {
+        let package = detect(&["npx", "server-fs@0.6.2", "/tmp"]);
+        assert_eq!(package.name, "server-fs");
+        assert_eq!(package.pinned_version(), Some("0.6.2"));
+
+        let package = detect(&["npx", "--yes", "@scope/pkg@latest"]);
+        assert_eq!(package.name, "@scope/pkg");
+        assert_eq!(package.version.as_deref(), Some("latest"));
+        assert_eq!(package.pinned_version(), None);
+
+        assert_eq!(
+            detect(&["npx", "pkg@^1.0"]).pinned_version(),
+            None,
+            "ranges aren't pins"
+        );
+    }
+
+    #[test]
+    fn npx_package_option() {
+        let command = argv(&["npx", "-p", "@scope/tools@1.0.0", "tools-mcp", "--stdio"]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.name, "@scope/tools");
+        assert_eq!(
+            package.pinned(&command, "2.0.0"),
+            ["npx", "-p", "@scope/tools@2.0.0", "tools-mcp", "--stdio"]
+        );
+
+        let command = argv(&["npx", "--package=pkg", "bin"]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.name, "pkg");
+        assert_eq!(package.pinned(&command, "3.1.0")[1], "--package=pkg@3.1.0");
+    }
+
+    #[test]
+    fn uvx_specs() {
+        let command = argv(&["uvx", "mcp-server-git", "--repository", "."]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.launcher, Launcher::Uvx);
+        assert_eq!(package.name, "mcp-server-git");
+        assert_eq!(
+            package.pinned(&command, "0.6.2"),
+            ["uvx", "mcp-server-git@0.6.2", "--repository", "."]
+        );
+        assert_eq!(package.floating(&command)[1], "mcp-server-git@latest");
+
+        assert_eq!(
+            detect(&["uvx", "mcp-server-fetch==2025.1.17"]).pinned_version(),
+            Some("2025.1.17")
+        );
+        let package = detect(&["uvx", "mcp-server-time>=0.5"]);
+        assert_eq!(package.name, "mcp-server-time");
+        assert_eq!(package.version, None);
+    }
+
+    #[test]
+    fn uvx_options() {
+        let package = detect(&[
+            "uvx",
+            "--python",
+            "3.12",
+            "--with",
+            "rich",
+            "mcp-server-time",
+        ]);
+        assert_eq!(package.name, "mcp-server-time");
+
+        let command = argv(&["uvx", "--from", "mcp-tools[cli]==1.0", "mcp-tools-server"]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.name, "mcp-tools[cli]");
+        assert_eq!(package.pinned_version(), Some("1.0"));
+        assert_eq!(
+            package.pinned(&command, "1.1"),
+            ["uvx", "--from", "mcp-tools[cli]@1.1", "mcp-tools-server"]
+        );
+        assert_eq!(
+            package.upstream_url(),
+            "https://pypi.org/pypi/mcp-tools/json"
+        );
+    }
+
+    #[test]
+    fn pipx_run_specs() {
+        let command = argv(&[
+            "pipx",
+            "run",
+            "--python",
+            "3.11",
+            "mcp-server-sqlite",
+            "--db",
+            "x",
+        ]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.launcher, Launcher::PipxRun);
+        assert_eq!(package.name, "mcp-server-sqlite");
+        assert_eq!(
+            package.pinned(&command, "0.4.0"),
+            [
+                "pipx",
+                "run",
+                "--python",
+                "3.11",
+                "mcp-server-sqlite==0.4.0",
+                "--db",
+                "x"
+            ]
+        );
+        // pipx has no tag for the latest release
+        assert_eq!(package.floating(&command)[4], "mcp-server-sqlite");
+
+        let command = argv(&["pipx", "run", "--spec=tool==1.0", "tool-mcp"]);
+        let package = Package::detect(&command).unwrap();
+        assert_eq!(package.pinned_version(), Some("1.0"));
+        assert_eq!(package.pinned(&command, "1.2")[2], "--spec=tool==1.2");
+    }
+
+    #[test]
+    fn other_commands_are_not_detected() {
+        for command in [
+            &["node", "server.js"][..],
+            &["pipx", "install", "tool"],
+            &["npx", "-y"],
+            &["/opt/uvx-wrapper", "pkg"],
+        ] {
+            assert_eq!(Package::detect(&argv(command)), None, "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn exact_versions() {
+        for version in ["1.2.3", "0.6.0-rc.1", "2025.4.8", "1.0.0+build"] {
+            assert!(is_exact_version(version), "{}", version);
+        }
+        for version in ["latest", "^1.0", "~2", "", "1.x || 2", "dev"] {
+            assert!(!is_exact_version(version), "{}", version);
+        }
+    }
+
+    #[test]
+    fn upstream_answers() {
+        assert_eq!(
+            detect(&["npx", "@scope/pkg"]).upstream_url(),
+            "https://registry.npmjs.org/@scope%2Fpkg/latest"
+        );
+        assert_eq!(
+            parse_upstream(true, r#"{"name": "pkg", "version": "1.4.0"}"#).unwrap(),
+            "1.4.0"
+        );
+        assert_eq!(
+            parse_upstream(false, r#"{"info": {"version": "0.7.1"}}"#).unwrap(),
+            "0.7.1"
+        );
+        assert!(parse_upstream(true, r#"{"error": "not found"}"#).is_err());
+    }
+
+    fn status(pinned: &str, running: &str, latest: Option<&str>) -> Status {
+        let spec = format!("pkg@{}", pinned);
+        Status {
+            name: "pkg".to_string(),
+            package: detect(&["npx", &spec]),
+            running: Ok(running.to_string()),
+            latest: latest.map(|v| Ok(v.to_string())),
+        }
+    }
+
+    #[test]
+    fn updates_compare_against_latest_or_running() {
+        assert_eq!(status("1.0.0", "1.0.0", None).update(), None);
+        assert_eq!(status("1.0.0", "1.1.0", None).update(), Some("1.1.0"));
+        assert_eq!(
+            status("1.0.0", "1.0.0", Some("2.0.0")).update(),
+            Some("2.0.0")
+        );
+        assert_eq!(status("2.0.0", "2.0.0", Some("2.0.0")).update(), None);
+        // Unpinned entries have nothing to upgrade from
+        assert_eq!(status("latest", "3.0.0", Some("3.0.0")).update(), None);
+    }
+
+    #[test]
+    fn render_lists_updates() {
+        let text = render(&[status("1.0.0", "1.0.0", Some("1.2.0"))], &Output::plain());
+        assert!(text.contains("LATEST"), "{}", text);
+        assert!(text.contains("pkg (npx)"), "{}", text);
+        assert!(text.contains("mcpd upgrade pkg  # 1.2.0"), "{}", text);
+        assert_eq!(
+            render(&[], &Output::plain()),
+            "No servers launched through npx, uvx or 'pipx run'\n"
+        );
+    }
+}
@@ -1,66 +1,828 @@
 //! Tool proxy - manages subprocess communication with MCP tool servers.
 
+use crate::activity::{BackendState, InstanceSnapshot, LifecycleCounters};
+use crate::balance;
+use crate::framing::{self, Framing};
+use crate::limits::{self, JsonLimits, ReadLine, TooLargeToRead};
 use crate::mcp::{
     self, CallToolParams, CallToolResult, GetPromptParams, GetPromptResult, InitializeParams,
-    InitializeResult, ListPromptsResult, ListResourcesResult, ListToolsResult, Notification,
-    PROTOCOL_VERSION, Prompt, ReadResourceParams, ReadResourceResult, Request, RequestId, Resource,
-    Response, Tool as McpTool,
+    InitializeResult, ListPromptsResult, ListResourcesResult, ListToolsResult, LoggingLevel,
+    Notification, PROTOCOL_VERSION, Prompt, ReadResourceParams, ReadResourceResult, Request,
+    RequestId, Resource, Response, ServerCapabilities, ServerInfo, SetLevelParams, SubscribeParams,
+    Tool as McpTool,
 };
-use crate::registry::Tool;
-use anyhow::{Context, Result, anyhow};
+use crate::registry::{Registry, Tool, ToolValidationError};
+use crate::secret_env::{self, KeySources};
+use crate::subscriptions::Subscriptions;
+use crate::transform::Transform;
+use anyhow::{Context, Result, anyhow, bail};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicI64, Ordering};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::process::{Child, ChildStdin, Command};
-use tokio::sync::{Mutex, oneshot};
-use tracing::{debug, info, warn};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, SetOnce, mpsc, oneshot};
+use tracing::{Instrument, debug, info, info_span, warn};
+
+/// Settings shared by every proxy a server creates
+#[derive(Debug, Clone)]
+pub struct ProxyOptions {
+    /// Limits applied to each message read from the backend's stdout
+    pub json_limits: JsonLimits,
+    /// Most bytes read for one message from a backend. Past this the read
+    /// stops, pending calls fail and the backend is killed, where a message
+    /// only over `json_limits.max_bytes` is drained and skipped.
+    pub max_response_bytes: usize,
+    /// How long a write to the backend's stdin may take before the backend
+    /// is considered wedged
+    pub write_timeout: Duration,
+    /// Most messages waiting to be written to one backend's stdin. Past
+    /// this, new requests fail with `WriteQueueFull` instead of queueing.
+    pub max_queued_writes: usize,
+    /// Where backend stderr goes
+    pub stderr: StderrMode,
+    /// Wrapper for backends that don't set their own (see `Tool::wrapped_command`)
+    pub default_wrapper: Option<Vec<String>>,
+    /// How long calls fail fast after the backend answered `initialize` with an error
+    pub init_failure_cooldown: Duration,
+    /// How long a `kill_on_abandoned` backend gets to answer a cancelled
+    /// request before it's stopped
+    pub abandoned_call_grace: Duration,
+    /// Bytes buffered per read of a backend's stdout, for backends that
+    /// don't set `read_buffer_size`
+    pub read_buffer_size: usize,
+    /// Fail tool calls with `StillInitializing` while another call is
+    /// initializing the backend, instead of queueing them behind it
+    pub fail_fast_uninitialized: bool,
+    /// `clientInfo` sent in `initialize` to backends that don't set their own
+    pub client_info: mcp::ClientInfo,
+    /// Where the master key for backends' encrypted env values comes from
+    pub key_sources: KeySources,
+    /// Where backends' `dynamic_env` values are read from on every start
+    pub env_source: EnvSource,
+}
+
+impl Default for ProxyOptions {
+    fn default() -> Self {
+        Self {
+            json_limits: JsonLimits::default(),
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            write_timeout: Duration::from_secs(10),
+            max_queued_writes: DEFAULT_MAX_QUEUED_WRITES,
+            stderr: StderrMode::default(),
+            default_wrapper: None,
+            init_failure_cooldown: Duration::from_secs(30),
+            abandoned_call_grace: Duration::from_secs(10),
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            fail_fast_uninitialized: false,
+            client_info: mcp::ClientInfo {
+                name: "mcpd".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            key_sources: KeySources::from_environment(&Registry::default_config_dir()),
+            env_source: EnvSource::default(),
+        }
+    }
+}
+
+/// Looks up a `dynamic_env` variable's current value. mcpd's own
+/// environment by default; tests give their own so they never have to
+/// change the process environment.
+#[derive(Clone)]
+pub struct EnvSource(Arc<EnvLookup>);
+
+type EnvLookup = dyn Fn(&str) -> Option<OsString> + Send + Sync;
+
+impl EnvSource {
+    pub fn new(lookup: impl Fn(&str) -> Option<OsString> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(lookup))
+    }
+
+    pub fn get(&self, key: &str) -> Option<OsString> {
+        (self.0)(key)
+    }
+}
+
+impl Default for EnvSource {
+    fn default() -> Self {
+        Self::new(|key| std::env::var_os(key))
+    }
+}
+
+impl std::fmt::Debug for EnvSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EnvSource")
+    }
+}
+
+/// Default for `ProxyOptions::max_response_bytes` (`serve --max-response-bytes`)
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Default for `ProxyOptions::max_queued_writes`
+pub const DEFAULT_MAX_QUEUED_WRITES: usize = 1024;
+
+/// Default stdout read buffer, the same as tokio's. `benches/read_buffer.rs`
+/// compares sizes for large results.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Where a tool call's time went in the proxy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallTimings {
+    /// Waiting for the backend to be ready: spawning and initializing it,
+    /// or a singleton backend's turn
+    pub ready: Duration,
+    /// Sending the request and waiting for the answer
+    pub round_trip: Duration,
+}
+
+/// A JSON-RPC error object a backend answered a request with
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("RPC error {code}: {message}")]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+/// A tool call turned away under `fail_fast_uninitialized` because the
+/// backend was still initializing; trying again shortly should work
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Backend '{backend}' is still initializing; retry shortly")]
+pub struct StillInitializing {
+    pub backend: String,
+}
+
+/// A tool call whose deadline passed while its backend was still starting.
+/// The start goes on in the background, so trying again later should work.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "Backend '{backend}' was still starting when the call timed out after waiting {}ms for it; retry shortly",
+    .waited.as_millis()
+)]
+pub struct NotReadyInTime {
+    pub backend: String,
+    pub waited: Duration,
+}
+
+/// Where a backend subprocess's stderr is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StderrMode {
+    /// Captured by mcpd
+    #[default]
+    Piped,
+    /// Shared with mcpd's own stderr, so backend output shows up live
+    Inherit,
+}
+
+impl StderrMode {
+    fn stdio(self) -> Stdio {
+        match self {
+            StderrMode::Piped => Stdio::piped(),
+            StderrMode::Inherit => Stdio::inherit(),
+        }
+    }
+}
+
+/// A write to a backend's stdin that didn't finish in time, usually because
+/// the backend stopped reading it
+#[derive(Debug, thiserror::Error)]
+#[error("Timed out after {0:?} writing to subprocess stdin")]
+struct WriteTimeout(Duration);
+
+/// Write a full message and flush, failing if it doesn't complete within `timeout`
+async fn write_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    message: &[u8],
+    timeout: Duration,
+) -> Result<()> {
+    tokio::time::timeout(timeout, async {
+        writer.write_all(message).await?;
+        writer.flush().await
+    })
+    .await
+    .map_err(|_| WriteTimeout(timeout))??;
+    Ok(())
+}
+
+/// Most queued messages the writer task puts into one write
+const MAX_WRITE_BATCH: usize = 32;
+
+/// Why a queued message wasn't written. Every message in a failed batch
+/// gets the same one.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{message}")]
+struct WriteFailed {
+    message: String,
+    /// The backend stopped reading its stdin, rather than closing it
+    timed_out: bool,
+}
+
+/// A request found the backend without a process: it was stopped (an idle
+/// sweep, a restart) after the caller got it ready
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Process not started")]
+pub struct NotStarted;
+
+/// A backend's stdin queue is full: it has stopped reading, or can't keep
+/// up with what's sent to it
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "Backend '{backend}' isn't reading its input fast enough ({queued} messages waiting); try again later"
+)]
+pub struct WriteQueueFull {
+    pub backend: String,
+    pub queued: usize,
+}
+
+/// A framed message for the writer task, and who to tell how writing it went
+struct Outbound {
+    message: Vec<u8>,
+    written: oneshot::Sender<std::result::Result<(), WriteFailed>>,
+}
+
+/// Write queued messages to a backend's stdin, in order, until the queue
+/// closes or a write fails. Messages queued while a write was under way go
+/// out together in the next one, up to `batch` of them, so a burst of calls
+/// costs one write and flush instead of one each.
+async fn write_queued<W: AsyncWrite + Unpin>(
+    mut stdin: W,
+    mut queue: mpsc::Receiver<Outbound>,
+    batch: usize,
+    timeout: Duration,
+) {
+    let mut messages = Vec::new();
+    let mut buffer = Vec::new();
+    while queue.recv_many(&mut messages, batch).await > 0 {
+        buffer.clear();
+        for outbound in &messages {
+            buffer.extend_from_slice(&outbound.message);
+        }
+        let result = write_message(&mut stdin, &buffer, timeout)
+            .await
+            .map_err(|e| WriteFailed {
+                message: format!("{:#}", e),
+                timed_out: e.is::<WriteTimeout>(),
+            });
+        for outbound in messages.drain(..) {
+            let _ = outbound.written.send(result.clone());
+        }
+        if let Err(failed) = result {
+            // Nothing more gets through this stdin
+            queue.close();
+            while let Some(outbound) = queue.recv().await {
+                let _ = outbound.written.send(Err(failed.clone()));
+            }
+            return;
+        }
+    }
+}
+
+/// How long the first message to a backend of unknown framing waits for the
+/// backend's own first output to detect it from
+const FRAMING_DETECT_WINDOW: Duration = Duration::from_millis(100);
+
+/// Attempts at spawning a backend while the OS is short of resources
+const SPAWN_ATTEMPTS: u32 = 4;
+
+/// Wait before retrying a transient spawn failure, doubled for each retry
+const SPAWN_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Whether a spawn failed for lack of resources (processes, memory, file
+/// descriptors) and may work a moment later, rather than for a reason
+/// retrying can't fix, like a missing binary
+fn is_transient_spawn_error(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    if matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::OutOfMemory | ErrorKind::Interrupted
+    ) {
+        return true;
+    }
+    #[cfg(unix)]
+    if let Some(code) = error.raw_os_error() {
+        return [
+            libc::EAGAIN,
+            libc::ENOMEM,
+            libc::EINTR,
+            libc::EMFILE,
+            libc::ENFILE,
+            libc::ETXTBSY,
+        ]
+        .contains(&code);
+    }
+    false
+}
 
 /// Proxy for communicating with a single MCP tool subprocess
 pub struct ToolProxy {
     tool: Tool,
-    state: Mutex<ProxyState>,
+    options: ProxyOptions,
+    state: Arc<Mutex<ProxyState>>,
     /// Serializes initialization attempts so only one caller performs the handshake.
     /// Separate from `state` because `initialize()` needs to acquire `state` internally.
     init_lock: Mutex<()>,
+    /// Held for the whole of each tool call when `tool.singleton` is set,
+    /// so the backend sees one call at a time and the rest queue in order
+    call_lock: Mutex<()>,
+    /// Held from sending each request until its answer when
+    /// `tool.no_pipelining` is set
+    request_lock: Mutex<()>,
     next_id: AtomicI64,
+    /// Lock-free view of the lifecycle state, shared with the reader task
+    backend_state: Arc<AtomicU8>,
+    /// Spawn/restart/crash counters, usually shared with the server's `Activity`
+    lifecycle: Arc<LifecycleCounters>,
+    /// Resources clients watch, usually shared with the server. Each new
+    /// process is subscribed to this backend's URIs after initialize.
+    subscriptions: Arc<Subscriptions>,
+    /// Working directory for the subprocess; `tool.cwd` unless overridden
+    cwd: Option<PathBuf>,
+    /// Compiled `tool.transform`, or why it failed to compile
+    transform: Result<Option<Transform>, String>,
+    /// Receives notifications the backend sends (progress, list_changed)
+    notifications: Option<NotificationHandler>,
+    /// When and how the backend last rejected `initialize`. Calls fail fast
+    /// for `init_failure_cooldown` after it instead of respawning.
+    init_failure: std::sync::Mutex<Option<(Instant, RpcError)>>,
+    /// The backend's other processes when `tool.replicas` is over 1, each
+    /// a proxy of its own; this proxy is the first. Tool calls go to each
+    /// in turn; every other request goes to this one.
+    replicas: Vec<ToolProxy>,
+    /// Turn counter for `replicas`
+    next_replica: AtomicUsize,
+    /// This process's place among the backend's replicas, passed to it as
+    /// `MCPD_INSTANCE_INDEX`. `None` when the backend runs one process.
+    instance: Option<usize>,
+    /// Set when the process went away without `stop` (it crashed or mcpd
+    /// killed it), until it's started again. Tool calls go to the other
+    /// replicas meanwhile, and the next spawn counts as a restart.
+    crashed: Arc<AtomicBool>,
+    /// Set while `revive` is starting crashed replicas again
+    reviving: AtomicBool,
+    /// Tool calls this process has taken, for per-replica metrics
+    load: CallLoad,
+    /// The backend's tools at its last listing, and whether each was
+    /// annotated destructive
+    destructive: std::sync::Mutex<HashMap<String, bool>>,
+    /// Tool calls routed to this proxy that haven't returned, counted from
+    /// before they queue, so a replaced proxy is stopped only once idle
+    routed: Arc<AtomicUsize>,
+}
+
+/// Tool calls one backend process has taken
+#[derive(Debug, Default)]
+struct CallLoad {
+    calls: AtomicU64,
+    active: AtomicU64,
+    busy_ms: AtomicU64,
+}
+
+impl CallLoad {
+    /// Count a call as active until the guard is dropped, whether the call
+    /// finished or was dropped with it
+    fn start(&self) -> ActiveCall<'_> {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        ActiveCall {
+            load: self,
+            started: Instant::now(),
+        }
+    }
 }
 
+/// A call counted in a `CallLoad`, recorded when dropped
+struct ActiveCall<'a> {
+    load: &'a CallLoad,
+    started: Instant,
+}
+
+impl Drop for ActiveCall<'_> {
+    fn drop(&mut self) {
+        self.load
+            .busy_ms
+            .fetch_add(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        self.load.active.fetch_sub(1, Ordering::Relaxed);
+        self.load.calls.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A tool call counted in its proxy's `routed` until dropped
+pub struct RoutedCall {
+    routed: Arc<AtomicUsize>,
+}
+
+impl Drop for RoutedCall {
+    fn drop(&mut self) {
+        self.routed.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Environment variable telling each replica its index, from 0
+pub const INSTANCE_INDEX_VAR: &str = "MCPD_INSTANCE_INDEX";
+
+/// Callback for notifications from a backend, given the backend's name
+pub type NotificationHandler = Arc<dyn Fn(&str, Notification) + Send + Sync>;
+
+/// A backend's response to a call, or why mcpd gave up on getting one
+type Answer = std::result::Result<Response, String>;
+
+/// Calls waiting for the backend's answer, by request id
+type Pending = Mutex<HashMap<i64, oneshot::Sender<Answer>>>;
+
 struct ProxyState {
     process: Option<Child>,
-    stdin: Option<ChildStdin>,
-    pending: Arc<Mutex<HashMap<i64, oneshot::Sender<Response>>>>,
+    /// Queue of the task writing to the process's stdin
+    writer: Option<mpsc::Sender<Outbound>>,
+    /// That task, aborted when the process goes so a write stuck on a full
+    /// pipe can't outlive it
+    writer_task: Option<tokio::task::JoinHandle<()>>,
+    pending: Arc<Pending>,
+    /// Set once the reader has stopped, so nothing answers new calls
+    reader_done: Arc<AtomicBool>,
     initialized: bool,
     reader_task: Option<tokio::task::JoinHandle<()>>,
+    /// Result of the most recent successful initialize handshake
+    init_result: Option<InitializeResult>,
+    /// Framing of the running process, once declared or detected
+    framing: Arc<SetOnce<Framing>>,
+}
+
+impl ProxyState {
+    /// Frame a message for the backend. Until the backend has written
+    /// anything, this waits briefly for it to show its framing, then falls
+    /// back to lines.
+    async fn frame(&self, message: &impl serde::Serialize) -> Result<Vec<u8>> {
+        let json = serde_json::to_vec(message)?;
+        let framing = match self.framing.get() {
+            Some(framing) => *framing,
+            None => tokio::time::timeout(FRAMING_DETECT_WINDOW, self.framing.wait())
+                .await
+                .map_or(Framing::Lines, |framing| *framing),
+        };
+        Ok(framing.encode(&json))
+    }
+
+    /// Queue a framed message for the backend's stdin, behind everything
+    /// queued before it. The receiver says how writing it went. This never
+    /// waits: with the queue full it fails with `WriteQueueFull`.
+    fn queue(&self, tool: &str, message: Vec<u8>) -> Result<Written> {
+        let writer = self.writer.as_ref().ok_or(NotStarted)?;
+        let (written, receiver) = oneshot::channel();
+        writer
+            .try_send(Outbound { message, written })
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => WriteQueueFull {
+                    backend: tool.to_string(),
+                    queued: writer.max_capacity(),
+                }
+                .into(),
+                mpsc::error::TrySendError::Closed(_) => anyhow!("Subprocess stdin is closed"),
+            })?;
+        Ok(receiver)
+    }
+
+    /// Kill a process whose stdin can't be written: it stopped reading it,
+    /// or closed it. A write cut short may have left part of a message in
+    /// the pipe, so nothing more may go to this process. The reader sees
+    /// EOF and counts a crash, and the next call starts a fresh process.
+    async fn kill_unwritable(&mut self, tool: &str, error: &WriteFailed) {
+        if error.timed_out {
+            warn!(tool, %error, "Backend isn't reading its stdin, killing it");
+        } else {
+            warn!(tool, %error, "Writing to backend's stdin failed, killing it");
+        }
+        self.writer.take();
+        if let Some(task) = self.writer_task.take() {
+            task.abort();
+        }
+        if let Some(child) = self.process.as_mut() {
+            kill_process_group(child);
+            let _ = child.kill().await;
+        }
+    }
+
+    /// Queue a notification that the caller of request `id` stopped waiting
+    async fn queue_cancelled(&self, tool: &str, id: i64) -> Result<Written> {
+        let notification = Notification {
+            params: Some(serde_json::json!({
+                "requestId": id,
+                "reason": "The client stopped waiting for the response",
+            })),
+            ..Notification::new("notifications/cancelled")
+        };
+        let message = self.frame(&notification).await?;
+        self.queue(tool, message)
+    }
+
+    /// End the subprocess and fail its pending calls, without the reader
+    /// counting a crash. Requests whose callers already gave up are
+    /// cancelled first, in case their cancellation hasn't gone out yet.
+    async fn shut_down(&mut self, tool: &str) {
+        let abandoned: Vec<i64> = self
+            .pending
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, waiter)| waiter.is_closed())
+            .map(|(id, _)| *id)
+            .collect();
+        let mut cancellations = Vec::new();
+        for id in abandoned {
+            match self.queue_cancelled(tool, id).await {
+                Ok(written) => cancellations.push(written),
+                Err(e) => debug!(tool, id, error = %e, "Couldn't cancel abandoned request"),
+            }
+        }
+        // A moment for them to go out, but a backend that isn't reading
+        // can't hold up the stop
+        let _ = tokio::time::timeout(CANCEL_FLUSH, async {
+            for written in cancellations {
+                let _ = written.await;
+            }
+        })
+        .await;
+
+        self.writer.take();
+        if let Some(task) = self.writer_task.take() {
+            task.abort();
+        }
+
+        if let Some(handle) = self.reader_task.take() {
+            handle.abort();
+        }
+
+        if let Some(mut child) = self.process.take() {
+            info!(tool, "Stopping tool subprocess");
+            kill_process_group(&child);
+            let _ = child.kill().await;
+        }
+
+        // Cancel all pending requests
+        {
+            let mut pending = self.pending.lock().await;
+            for (_, tx) in pending.drain() {
+                let _ = tx.send(Err("Proxy stopped".to_string()));
+            }
+        }
+
+        self.initialized = false;
+        self.init_result = None;
+    }
+}
+
+/// How a queued message's write went
+type Written = oneshot::Receiver<std::result::Result<(), WriteFailed>>;
+
+/// How long stopping a backend waits for its last cancellations to be written
+const CANCEL_FLUSH: Duration = Duration::from_millis(100);
+
+/// Wait for a queued message to be written, without holding the state lock.
+/// After a failed write the process `pid` is killed, unless it was already
+/// replaced.
+async fn await_written(
+    state: &Mutex<ProxyState>,
+    tool: &str,
+    pid: Option<u32>,
+    written: Written,
+) -> Result<()> {
+    let Err(failed) = written
+        .await
+        .unwrap_or_else(|_| Err(WriteFailed::stopped()))
+    else {
+        return Ok(());
+    };
+    let mut state = state.lock().await;
+    if pid.is_some() && state.process.as_ref().and_then(Child::id) == pid {
+        state.kill_unwritable(tool, &failed).await;
+    }
+    Err(failed.into())
+}
+
+/// Whether `notification` is a log message less severe than `min`
+fn below_log_level(notification: &Notification, min: LoggingLevel) -> bool {
+    notification.method == "notifications/message"
+        && notification
+            .params
+            .as_ref()
+            .and_then(|p| p.get("level"))
+            .and_then(|level| serde_json::from_value::<LoggingLevel>(level.clone()).ok())
+            .is_some_and(|level| level < min)
+}
+
+/// Fail every call still waiting for a response, once the reader is done.
+/// `done` is set first, so a call registered after the drain sees it.
+async fn fail_pending(pending: &Pending, done: &AtomicBool, message: &str) {
+    done.store(true, Ordering::SeqCst);
+    for (_, tx) in pending.lock().await.drain() {
+        let _ = tx.send(Err(message.to_string()));
+    }
+}
+
+impl WriteFailed {
+    /// The writer task ended without writing the message
+    fn stopped() -> Self {
+        Self {
+            message: "Subprocess stdin is closed".to_string(),
+            timed_out: false,
+        }
+    }
 }
 
 impl ToolProxy {
     pub fn new(tool: Tool) -> Self {
+        Self::with_options(tool, ProxyOptions::default())
+    }
+
+    pub fn with_options(tool: Tool, options: ProxyOptions) -> Self {
+        let count = tool.replicas.unwrap_or(1);
+        let mut proxy = Self::single(tool, options);
+        if count > 1 {
+            let one = Tool {
+                replicas: None,
+                ..proxy.tool.clone()
+            };
+            proxy.instance = Some(0);
+            proxy.replicas = (1..count)
+                .map(|index| {
+                    let mut replica = Self::single(one.clone(), proxy.options.clone())
+                        .with_lifecycle(Arc::clone(&proxy.lifecycle));
+                    replica.instance = Some(index as usize);
+                    replica
+                })
+                .collect();
+        }
+        proxy
+    }
+
+    /// A proxy for one process of `tool`, whatever its `replicas`
+    fn single(tool: Tool, options: ProxyOptions) -> Self {
+        let transform = tool
+            .transform
+            .as_deref()
+            .map(Transform::compile)
+            .transpose()
+            .map_err(|e| e.to_string());
         Self {
+            cwd: tool.cwd.clone(),
             tool,
-            state: Mutex::new(ProxyState {
+            options,
+            state: Arc::new(Mutex::new(ProxyState {
                 process: None,
-                stdin: None,
+                writer: None,
+                writer_task: None,
                 pending: Arc::new(Mutex::new(HashMap::new())),
+                reader_done: Arc::default(),
                 initialized: false,
                 reader_task: None,
-            }),
+                init_result: None,
+                framing: Arc::new(SetOnce::new()),
+            })),
             init_lock: Mutex::new(()),
+            call_lock: Mutex::new(()),
+            request_lock: Mutex::new(()),
             next_id: AtomicI64::new(1),
+            backend_state: Arc::new(AtomicU8::new(BackendState::Stopped.as_u8())),
+            lifecycle: Arc::default(),
+            subscriptions: Arc::default(),
+            transform,
+            notifications: None,
+            init_failure: std::sync::Mutex::new(None),
+            replicas: Vec::new(),
+            next_replica: AtomicUsize::new(0),
+            instance: None,
+            crashed: Arc::default(),
+            reviving: AtomicBool::new(false),
+            load: CallLoad::default(),
+            destructive: std::sync::Mutex::default(),
+            routed: Arc::default(),
         }
     }
 
+    /// Pass notifications from the backend to `handler`. Without one they're
+    /// logged and dropped.
+    pub fn with_notification_handler(mut self, handler: NotificationHandler) -> Self {
+        self.replicas = std::mem::take(&mut self.replicas)
+            .into_iter()
+            .map(|r| r.with_notification_handler(Arc::clone(&handler)))
+            .collect();
+        self.notifications = Some(handler);
+        self
+    }
+
+    /// The argv this proxy spawns, wrapper included
+    pub fn command(&self) -> Vec<std::ffi::OsString> {
+        self.tool
+            .wrapped_command(self.options.default_wrapper.as_deref(), self.cwd.as_deref())
+    }
+
+    /// Record lifecycle events into `counters` instead of a private set
+    pub fn with_lifecycle(mut self, counters: Arc<LifecycleCounters>) -> Self {
+        self.replicas = std::mem::take(&mut self.replicas)
+            .into_iter()
+            .map(|r| r.with_lifecycle(Arc::clone(&counters)))
+            .collect();
+        self.lifecycle = counters;
+        self
+    }
+
+    /// Subprocess spawn/restart/crash counters
+    pub fn lifecycle(&self) -> &LifecycleCounters {
+        &self.lifecycle
+    }
+
+    /// Take the resources to keep subscribed from `subscriptions` instead of
+    /// a private table. Only the first replica subscribes, since resource
+    /// requests all go to it.
+    pub fn with_subscriptions(mut self, subscriptions: Arc<Subscriptions>) -> Self {
+        self.subscriptions = subscriptions;
+        self
+    }
+
+    /// Run the subprocess in `cwd` (or mcpd's own directory if `None`)
+    pub fn with_cwd(mut self, cwd: Option<PathBuf>) -> Self {
+        self.replicas = std::mem::take(&mut self.replicas)
+            .into_iter()
+            .map(|r| r.with_cwd(cwd.clone()))
+            .collect();
+        self.cwd = cwd;
+        self
+    }
+
+    /// Working directory the subprocess is started in
+    pub fn cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    /// The registry entry this proxy runs
+    pub fn tool(&self) -> &Tool {
+        &self.tool
+    }
+
+    /// The registered result transform, if any. Fails if it doesn't compile.
+    pub fn transform(&self) -> Result<Option<&Transform>> {
+        self.transform
+            .as_ref()
+            .map(Option::as_ref)
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Current lifecycle state, readable without taking any locks
+    pub fn backend_state(&self) -> BackendState {
+        BackendState::from_u8(self.backend_state.load(Ordering::Relaxed))
+    }
+
+    /// Requests sent to the backend that are still waiting for an answer
+    pub async fn pending_requests(&self) -> usize {
+        let pending = Arc::clone(&self.state.lock().await.pending);
+        pending.lock().await.len()
+    }
+
+    /// Count a tool call as routed here until the guard is dropped, whether
+    /// it's still queued, running or was abandoned
+    pub fn route(&self) -> RoutedCall {
+        self.routed.fetch_add(1, Ordering::Relaxed);
+        RoutedCall {
+            routed: Arc::clone(&self.routed),
+        }
+    }
+
+    /// Whether any tool call routed here hasn't returned, or any request is
+    /// still waiting on an answer from one of the backend's processes
+    pub async fn in_flight(&self) -> bool {
+        if self.routed.load(Ordering::Relaxed) > 0 {
+            return true;
+        }
+        for replica in std::iter::once(self).chain(&self.replicas) {
+            if replica.pending_requests().await > 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn set_backend_state(&self, state: BackendState) {
+        self.backend_state.store(state.as_u8(), Ordering::Relaxed);
+    }
+
     /// Start the subprocess if not already running
     pub async fn start(&self) -> Result<()> {
         let mut state = self.state.lock().await;
 
         // Check if already running
-        if let Some(ref mut child) = state.process
+        let reader_done = state.reader_done.load(Ordering::SeqCst);
+        if let Some(child) = state.process.as_mut()
             && child.try_wait()?.is_none()
         {
-            return Ok(());
+            if !reader_done {
+                return Ok(());
+            }
+            // Nothing reads its output any more (it closed it, or sent an
+            // oversized message), so it could never answer
+            kill_process_group(child);
+            let _ = child.kill().await;
         }
 
         // Abort old reader task if any
@@ -68,20 +830,103 @@ impl ToolProxy {
             handle.abort();
         }
 
-        info!(tool = %self.tool.name, command = ?self.tool.command, "Starting tool subprocess");
+        let command = self.command();
+        info!(tool = %self.tool.name, ?command, "Starting tool subprocess");
 
-        let mut cmd = Command::new(&self.tool.command[0]);
-        if self.tool.command.len() > 1 {
-            cmd.args(&self.tool.command[1..]);
-        }
+        // Only reachable with a tool that skipped `Tool::validate`
+        let Some((program, args)) = command.split_first() else {
+            return Err(ToolValidationError::NoCommand {
+                name: self.tool.name.clone(),
+            }
+            .into());
+        };
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        // Own process group, so stopping a wrapped backend reaches the real
+        // server and not just the wrapper
+        #[cfg(unix)]
+        cmd.process_group(0);
         cmd.stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+            .stderr(self.options.stderr.stdio())
             .envs(&self.tool.env);
+        // Read now rather than at registration, so a restart picks up a
+        // rotated value
+        for key in &self.tool.dynamic_env {
+            match self.options.env_source.get(key) {
+                Some(value) => {
+                    cmd.env(key, value);
+                }
+                None => {
+                    warn!(tool = %self.tool.name, var = %key, "Dynamic env variable isn't set in mcpd's environment")
+                }
+            }
+        }
+        // Opened here and nowhere else, so a missing master key only fails
+        // the backends that have encrypted values
+        match secret_env::open_all(
+            &self.tool.name,
+            &self.tool.secret_env,
+            &self.options.key_sources,
+        ) {
+            Ok(values) => {
+                cmd.envs(values);
+            }
+            Err(e) => {
+                self.set_backend_state(BackendState::Unavailable);
+                return Err(e);
+            }
+        }
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        if let Some(index) = self.instance {
+            cmd.env(INSTANCE_INDEX_VAR, index.to_string());
+        }
 
-        let mut child = cmd
-            .spawn()
-            .with_context(|| format!("Failed to spawn tool: {}", self.tool.name))?;
+        let mut attempt = 1;
+        let mut child = loop {
+            let e = match cmd.spawn() {
+                Ok(child) => break child,
+                Err(e) => e,
+            };
+            let transient = is_transient_spawn_error(&e);
+            if transient && attempt < SPAWN_ATTEMPTS {
+                let delay = SPAWN_RETRY_DELAY * 2u32.pow(attempt - 1);
+                warn!(tool = %self.tool.name, error = %e, attempt, ?delay, "Transient spawn failure, retrying");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            self.set_backend_state(BackendState::Unavailable);
+            let name = &self.tool.name;
+            let context = match e.kind() {
+                std::io::ErrorKind::NotFound => match &self.cwd {
+                    Some(cwd) if !cwd.is_dir() => format!(
+                        "Failed to spawn tool {}: working directory {} doesn't exist",
+                        name,
+                        cwd.display()
+                    ),
+                    _ => format!(
+                        "Failed to spawn tool {}: command {:?} not found",
+                        name, program
+                    ),
+                },
+                std::io::ErrorKind::PermissionDenied => format!(
+                    "Failed to spawn tool {}: {:?} isn't executable",
+                    name, program
+                ),
+                _ if transient => format!(
+                    "Failed to spawn tool {} after {} attempts",
+                    name, SPAWN_ATTEMPTS
+                ),
+                _ => format!("Failed to spawn tool: {}", name),
+            };
+            return Err(e).context(context);
+        };
+        self.set_backend_state(BackendState::Starting);
+        self.lifecycle
+            .spawned(self.crashed.swap(false, Ordering::Relaxed));
 
         info!(tool = %self.tool.name, pid = ?child.id(), "Tool subprocess started");
 
@@ -94,70 +939,175 @@ impl ToolProxy {
             .take()
             .ok_or_else(|| anyhow!("Failed to capture stdout"))?;
 
+        let pid = child.id();
         state.process = Some(child);
-        state.stdin = Some(stdin);
+        let (writer, queue) = mpsc::channel(self.options.max_queued_writes.max(1));
+        let batch = if self.tool.no_pipelining {
+            1
+        } else {
+            MAX_WRITE_BATCH
+        };
+        if let Some(old) = state.writer_task.replace(tokio::spawn(write_queued(
+            stdin,
+            queue,
+            batch,
+            self.options.write_timeout,
+        ))) {
+            old.abort();
+        }
+        state.writer = Some(writer);
         state.initialized = false;
+        // A new process may speak a different framing than the last one
+        state.framing = Arc::new(match self.tool.framing {
+            Framing::Auto => SetOnce::new(),
+            declared => SetOnce::new_with(Some(declared)),
+        });
 
         // Clear old pending requests
         {
             let mut pending = state.pending.lock().await;
             for (_, tx) in pending.drain() {
-                let _ = tx.send(Response::error(RequestId::Number(0), -1, "Proxy restarted"));
+                let _ = tx.send(Err("Proxy restarted".to_string()));
             }
         }
 
         // Spawn background reader task that owns stdout and dispatches responses
+        state.reader_done = Arc::default();
         let pending = Arc::clone(&state.pending);
+        let done = Arc::clone(&state.reader_done);
         let tool_name = self.tool.name.clone();
+        let json_limits = self.options.json_limits;
+        let max_response_bytes = self.options.max_response_bytes;
+        let backend_state = Arc::clone(&self.backend_state);
+        let lifecycle = Arc::clone(&self.lifecycle);
+        let crashed = Arc::clone(&self.crashed);
+        let notifications = self.notifications.clone();
+        let declared = self.tool.framing;
+        let min_log_level = self.tool.log_level;
+        let detected = Arc::clone(&state.framing);
+        let read_buffer_size = self
+            .tool
+            .read_buffer_size
+            .unwrap_or(self.options.read_buffer_size);
         state.reader_task = Some(tokio::spawn(async move {
-            let mut reader = BufReader::new(stdout);
+            let (framing, stdout) = match framing::resolve(declared, stdout).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    warn!(tool = %tool_name, error = %e, "Read error from subprocess");
+                    fail_pending(&pending, &done, "Read error from subprocess").await;
+                    return;
+                }
+            };
+            if declared.is_auto() {
+                debug!(tool = %tool_name, %framing, "Detected framing");
+                let _ = detected.set(framing);
+            }
+            let mut reader = BufReader::with_capacity(read_buffer_size, stdout);
+            let mut line = Vec::new();
             loop {
-                let mut line = String::new();
-                match reader.read_line(&mut line).await {
-                    Ok(0) => {
+                let read = framing::read_message(
+                    &mut reader,
+                    framing,
+                    &mut line,
+                    json_limits.max_bytes,
+                    max_response_bytes,
+                )
+                .await;
+                match read {
+                    Ok(ReadLine::Eof) => {
                         debug!(tool = %tool_name, "EOF from subprocess reader");
+                        // `stop` aborts this task first, so EOF means the process went away on its own
+                        lifecycle.crashed();
+                        crashed.store(true, Ordering::Relaxed);
+                        backend_state.store(BackendState::Stopped.as_u8(), Ordering::Relaxed);
                         // Cancel all pending requests on EOF
-                        let mut pending = pending.lock().await;
-                        for (_, tx) in pending.drain() {
-                            let _ = tx.send(Response::error(
-                                RequestId::Number(0),
-                                -1,
-                                "EOF from subprocess",
-                            ));
-                        }
+                        fail_pending(&pending, &done, "EOF from subprocess").await;
                         break;
                     }
-                    Ok(_) => {
-                        debug!(tool = %tool_name, line = %line.trim(), "Received line");
+                    Ok(read) => {
+                        let text = String::from_utf8_lossy(&line);
+                        debug!(tool = %tool_name, line = %limits::truncate_for_log(text.trim(), 500), "Received line");
 
-                        let response: Response = match serde_json::from_str(&line) {
+                        let violation = match read {
+                            ReadLine::TooLong { size } => Some(limits::LimitViolation::TooLarge {
+                                size,
+                                max: json_limits.max_bytes,
+                            }),
+                            _ => json_limits.check_depth(&line).err(),
+                        };
+                        if let Some(violation) = violation {
+                            warn!(tool = %tool_name, error = %violation, "Rejected message from subprocess");
+                            if let Some(RequestId::Number(id)) = limits::peek_id(&line)
+                                && let Some(tx) = pending.lock().await.remove(&id)
+                            {
+                                let _ = tx
+                                    .send(Err(format!("Backend response rejected: {}", violation)));
+                            }
+                            continue;
+                        }
+
+                        let response: Response = match serde_json::from_slice(&line) {
                             Ok(r) => r,
                             Err(e) => {
-                                warn!(tool = %tool_name, error = %e, line = %line.trim(), "Invalid JSON from subprocess");
+                                // Messages without an id are notifications
+                                if let Ok(notification) =
+                                    serde_json::from_slice::<Notification>(&line)
+                                {
+                                    if below_log_level(&notification, min_log_level) {
+                                        debug!(tool = %tool_name, "Dropping log message below the backend's log level");
+                                        continue;
+                                    }
+                                    match &notifications {
+                                        Some(handler) => handler(&tool_name, notification),
+                                        None => {
+                                            debug!(tool = %tool_name, method = %notification.method, "Ignoring notification from subprocess")
+                                        }
+                                    }
+                                    continue;
+                                }
+                                warn!(tool = %tool_name, error = %e, line = %limits::truncate_for_log(text.trim(), 200), "Invalid JSON from subprocess");
                                 continue;
                             }
                         };
 
+                        // Nothing waits for an id mcpd didn't send (or has
+                        // given up on), so a buggy backend's stray answers,
+                        // even ones ahead of its initialize result, are dropped
                         let response_id = match &response.id {
                             RequestId::Number(n) => *n,
-                            RequestId::String(_) => continue,
+                            RequestId::String(_) | RequestId::Null => {
+                                debug!(tool = %tool_name, id = ?response.id, "Ignoring response with an id mcpd never sends");
+                                continue;
+                            }
                         };
 
                         let mut pending = pending.lock().await;
-                        if let Some(tx) = pending.remove(&response_id) {
-                            let _ = tx.send(response);
+                        match pending.remove(&response_id) {
+                            Some(tx) => {
+                                let _ = tx.send(Ok(response));
+                            }
+                            None => {
+                                debug!(tool = %tool_name, id = response_id, "Ignoring response to no pending request")
+                            }
                         }
                     }
+                    Err(ref e) if let Some(too_large) = TooLargeToRead::find(e) => {
+                        // The rest of the message is still coming; the next
+                        // call gets a fresh process instead
+                        warn!(tool = %tool_name, error = %too_large, "Backend sent an oversized message, killing it");
+                        let message = format!(
+                            "Backend response rejected: {} (--max-response-bytes); backend restarted",
+                            too_large
+                        );
+                        fail_pending(&pending, &done, &message).await;
+                        kill_group(pid);
+                        crashed.store(true, Ordering::Relaxed);
+                        backend_state.store(BackendState::Stopped.as_u8(), Ordering::Relaxed);
+                        break;
+                    }
                     Err(e) => {
                         warn!(tool = %tool_name, error = %e, "Read error from subprocess");
-                        let mut pending = pending.lock().await;
-                        for (_, tx) in pending.drain() {
-                            let _ = tx.send(Response::error(
-                                RequestId::Number(0),
-                                -1,
-                                "Read error from subprocess",
-                            ));
-                        }
+                        fail_pending(&pending, &done, "Read error from subprocess").await;
                         break;
                     }
                 }
@@ -167,31 +1117,35 @@ impl ToolProxy {
         Ok(())
     }
 
-    /// Stop the subprocess
+    /// Stop the subprocess. This also ends an initialize failure cooldown,
+    /// so a restart tries again right away.
     pub async fn stop(&self) -> Result<()> {
-        let mut state = self.state.lock().await;
-
-        state.stdin.take();
-
-        if let Some(handle) = state.reader_task.take() {
-            handle.abort();
+        for replica in &self.replicas {
+            Box::pin(replica.stop()).await?;
         }
+        *self.init_failure.lock().unwrap() = None;
+        self.state.lock().await.shut_down(&self.tool.name).await;
+        self.set_backend_state(BackendState::Stopped);
+        self.crashed.store(false, Ordering::Relaxed);
+        Ok(())
+    }
 
-        if let Some(mut child) = state.process.take() {
-            info!(tool = %self.tool.name, "Stopping tool subprocess");
+    /// Kill the subprocess the way a crash would: no shutdown, and the reader
+    /// sees EOF and counts a crash. The next call starts a new one.
+    pub async fn kill(&self) {
+        for replica in &self.replicas {
+            Box::pin(replica.kill()).await;
+        }
+        let mut state = self.state.lock().await;
+        if let Some(child) = state.process.as_mut() {
+            warn!(tool = %self.tool.name, "Killing tool subprocess");
+            kill_process_group(child);
             let _ = child.kill().await;
         }
-
-        // Cancel all pending requests
-        {
-            let mut pending = state.pending.lock().await;
-            for (_, tx) in pending.drain() {
-                let _ = tx.send(Response::error(RequestId::Number(0), -1, "Proxy stopped"));
-            }
+        // Let the reader finish with the EOF before anything restarts the backend
+        if let Some(reader) = state.reader_task.take() {
+            let _ = tokio::time::timeout(Duration::from_secs(1), reader).await;
         }
-
-        state.initialized = false;
-        Ok(())
     }
 
     /// Perform MCP initialization handshake
@@ -200,8 +1154,12 @@ impl ToolProxy {
             protocol_version: PROTOCOL_VERSION.to_string(),
             capabilities: Default::default(),
             client_info: mcp::ClientInfo {
-                name: "mcpd".to_string(),
-                version: env!("CARGO_PKG_VERSION").to_string(),
+                name: (self.tool.client_name.as_ref())
+                    .unwrap_or(&self.options.client_info.name)
+                    .clone(),
+                version: (self.tool.client_version.as_ref())
+                    .unwrap_or(&self.options.client_info.version)
+                    .clone(),
             },
         };
 
@@ -226,6 +1184,60 @@ impl ToolProxy {
     /// Uses a dedicated init_lock to serialize initialization attempts without
     /// holding the state lock (which initialize() needs internally).
     pub async fn ensure_ready(&self) -> Result<()> {
+        self.ready(false).await
+    }
+
+    /// `ensure_ready` for every process of the backend, by `deadline`. A
+    /// start still running then goes on in the background, so the next call
+    /// finds the backend ready, and this fails with `NotReadyInTime`.
+    pub async fn ensure_ready_by(self: &Arc<Self>, deadline: Instant) -> Result<()> {
+        let all_ready = std::iter::once(&**self)
+            .chain(&self.replicas)
+            .all(|replica| replica.backend_state() == BackendState::Ready);
+        if all_ready {
+            return Ok(());
+        }
+        let proxy = Arc::clone(self);
+        let mut starting = tokio::spawn(async move {
+            let fail_fast = proxy.options.fail_fast_uninitialized;
+            let starts = std::iter::once(&*proxy)
+                .chain(&proxy.replicas)
+                .map(|replica| replica.ready(fail_fast));
+            let mut results = futures::future::join_all(starts).await.into_iter();
+            // The first process is the one everything but tool calls uses
+            let first = results.next().expect("a backend has at least one process");
+            if first.is_ok() || results.any(|r| r.is_ok()) {
+                Ok(())
+            } else {
+                first
+            }
+        });
+        let waiting = Instant::now();
+        match tokio::time::timeout_at(deadline.into(), &mut starting).await {
+            Ok(started) => started.context("Backend start was cancelled")?,
+            Err(_) => {
+                let waited = waiting.elapsed();
+                warn!(tool = %self.tool.name, ?waited, "Backend still starting when the call's deadline passed");
+                Err(NotReadyInTime {
+                    backend: self.tool.name.clone(),
+                    waited,
+                }
+                .into())
+            }
+        }
+    }
+
+    /// `ensure_ready`, but when `fail_fast` is set and another caller is
+    /// already initializing the backend, fail with `StillInitializing`
+    /// rather than wait for it
+    async fn ready(&self, fail_fast: bool) -> Result<()> {
+        if let Some(error) = self.init_failure() {
+            // The backend's message stays a separate cause, for error_text to frame
+            return Err(anyhow::Error::new(error).context(format!(
+                "Backend rejected initialize; not retrying for {:?}",
+                self.options.init_failure_cooldown
+            )));
+        }
         self.start().await?;
 
         // Fast path: already initialized
@@ -237,7 +1249,13 @@ impl ToolProxy {
         }
 
         // Slow path: acquire init_lock to serialize concurrent init attempts
-        let _init_guard = self.init_lock.lock().await;
+        let _init_guard = if fail_fast {
+            self.init_lock.try_lock().map_err(|_| StillInitializing {
+                backend: self.tool.name.clone(),
+            })?
+        } else {
+            self.init_lock.lock().await
+        };
 
         // Re-check under init_lock — another caller may have finished first
         {
@@ -247,28 +1265,142 @@ impl ToolProxy {
             }
         }
 
-        self.initialize().await?;
+        let result = match self.initialize().await {
+            Ok(result) => result,
+            Err(e) => {
+                // Don't leave a half-initialized backend around; the next call
+                // starts a fresh subprocess instead of waiting on this one.
+                warn!(tool = %self.tool.name, error = %e, "Initialization failed, stopping subprocess");
+                let _ = self.stop().await;
+                self.set_backend_state(BackendState::Unavailable);
+                // A real error answer will most likely be the same next time
+                if let Some(error) = e.downcast_ref::<RpcError>() {
+                    *self.init_failure.lock().unwrap() = Some((Instant::now(), error.clone()));
+                }
+                return Err(e);
+            }
+        };
 
         let mut state = self.state.lock().await;
         state.initialized = true;
+        state.init_result = Some(result);
+        self.set_backend_state(BackendState::Ready);
+        drop(state);
 
+        self.resubscribe().await;
         Ok(())
     }
 
-    /// Send a notification (no response expected)
-    async fn notify(&self, method: &str) -> Result<()> {
-        let mut state = self.state.lock().await;
-        let stdin = state
-            .stdin
-            .as_mut()
-            .ok_or_else(|| anyhow!("Process not started"))?;
+    /// Subscribe a fresh process to every resource still watched through
+    /// this backend. Failures are logged; the process is usable regardless.
+    async fn resubscribe(&self) {
+        for uri in self.subscriptions.uris(&self.tool.name) {
+            match self.subscription_call("resources/subscribe", &uri).await {
+                Ok(()) => debug!(tool = %self.tool.name, uri, "Resubscribed to resource"),
+                Err(e) => {
+                    warn!(tool = %self.tool.name, uri, error = %e, "Failed to resubscribe to resource")
+                }
+            }
+        }
+    }
+
+    /// Send `resources/subscribe` or `resources/unsubscribe` for `uri`
+    async fn subscription_call(&self, method: &str, uri: &str) -> Result<()> {
+        let params = SubscribeParams {
+            uri: uri.to_string(),
+        };
+        let _: Value = self
+            .call(method, Some(serde_json::to_value(params)?))
+            .await?;
+        Ok(())
+    }
 
-        let notification = Notification::new(method);
-        let mut line = serde_json::to_string(&notification)?;
-        line.push('\n');
+    /// The error the backend answered `initialize` with, while calls are
+    /// failing fast because of it
+    pub fn init_failure(&self) -> Option<RpcError> {
+        let failure = self.init_failure.lock().unwrap();
+        failure
+            .as_ref()
+            .filter(|(at, _)| at.elapsed() < self.options.init_failure_cooldown)
+            .map(|(_, error)| error.clone())
+    }
 
-        stdin.write_all(line.as_bytes()).await?;
-        stdin.flush().await?;
+    /// Instructions the backend returned during initialization, if any.
+    /// Starts and initializes the backend if it isn't already running.
+    pub async fn instructions(&self) -> Result<Option<String>> {
+        self.ensure_ready().await?;
+        let state = self.state.lock().await;
+        Ok(state
+            .init_result
+            .as_ref()
+            .and_then(|r| r.instructions.clone()))
+    }
+
+    /// Capabilities the backend declared during initialization.
+    /// Starts and initializes the backend if it isn't already running.
+    pub async fn capabilities(&self) -> Result<ServerCapabilities> {
+        self.ensure_ready().await?;
+        let state = self.state.lock().await;
+        Ok(state
+            .init_result
+            .as_ref()
+            .map(|r| r.capabilities.clone())
+            .unwrap_or_default())
+    }
+
+    /// Everything the backend answered `initialize` with.
+    /// Starts and initializes the backend if it isn't already running.
+    pub async fn initialize_result(&self) -> Result<InitializeResult> {
+        self.ensure_ready().await?;
+        let state = self.state.lock().await;
+        state
+            .init_result
+            .clone()
+            .context("Backend is not initialized")
+    }
+
+    /// The name and version the backend reported during initialization.
+    /// Starts and initializes the backend if it isn't already running.
+    pub async fn server_info(&self) -> Result<ServerInfo> {
+        self.ensure_ready().await?;
+        let state = self.state.lock().await;
+        state
+            .init_result
+            .as_ref()
+            .map(|r| r.server_info.clone())
+            .context("Backend is not initialized")
+    }
+
+    /// Send the backend an MCP `ping`, returning how long it took to answer.
+    /// Starts and initializes the backend if it isn't already running; that
+    /// isn't part of the time returned.
+    pub async fn ping(&self) -> Result<Duration> {
+        self.ensure_ready().await?;
+        let sent = Instant::now();
+        let _: Value = self.call("ping", None).await?;
+        Ok(sent.elapsed())
+    }
+
+    /// Ask the backend to send log messages from `level` up. Returns false,
+    /// without asking, when the backend doesn't declare logging support.
+    pub async fn set_log_level(&self, level: LoggingLevel) -> Result<bool> {
+        if self.capabilities().await?.logging.is_none() {
+            return Ok(false);
+        }
+        let params = serde_json::to_value(SetLevelParams { level })?;
+        let _: Value = self.call("logging/setLevel", Some(params)).await?;
+        Ok(true)
+    }
+
+    /// Send a notification (no response expected)
+    async fn notify(&self, method: &str) -> Result<()> {
+        let (written, pid) = {
+            let state = self.state.lock().await;
+            let message = state.frame(&Notification::new(method)).await?;
+            let written = state.queue(&self.tool.name, message)?;
+            (written, state.process.as_ref().and_then(Child::id))
+        };
+        await_written(&self.state, &self.tool.name, pid, written).await?;
 
         debug!(tool = %self.tool.name, method, "Sent notification");
         Ok(())
@@ -280,36 +1412,85 @@ impl ToolProxy {
         method: &str,
         params: Option<Value>,
     ) -> Result<T> {
+        let span = info_span!("backend_call", backend = %self.tool.name, method);
+        self.call_inner(method, params).instrument(span).await
+    }
+
+    async fn call_inner<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<T> {
+        let _one_at_a_time = if self.tool.no_pipelining {
+            Some(self.request_lock.lock().await)
+        } else {
+            None
+        };
         let id = self.next_id.fetch_add(1, Ordering::SeqCst);
         let request = Request::new(id, method, params);
 
-        let rx = {
-            let mut state = self.state.lock().await;
-            let stdin = state
-                .stdin
-                .as_mut()
-                .ok_or_else(|| anyhow!("Process not started"))?;
-
-            let mut line = serde_json::to_string(&request)?;
-            line.push('\n');
-
-            stdin.write_all(line.as_bytes()).await?;
-            stdin.flush().await?;
-
-            debug!(tool = %self.tool.name, id, method, "Sent request");
+        let (rx, written, pid, mut entry) = {
+            let state = self.state.lock().await;
+            let message = state.frame(&request).await?;
 
-            // Set up response channel
+            // Set up the response channel first, since a quick backend can
+            // answer before the write returns
             let (tx, rx) = oneshot::channel();
             state.pending.lock().await.insert(id, tx);
+            let entry = PendingEntry {
+                id,
+                pending: Some(Arc::clone(&state.pending)),
+            };
+            // A backend that already went away would never answer
+            if state.reader_done.load(Ordering::SeqCst)
+                && state.pending.lock().await.remove(&id).is_some()
+            {
+                bail!("Subprocess closed its output");
+            }
 
-            rx
+            // Queued in order under the state lock, then written without
+            // it, so concurrent calls share writes
+            let written = state.queue(&self.tool.name, message)?;
+            let pid = state.process.as_ref().and_then(Child::id);
+            (rx, written, pid, entry)
         };
 
+        await_written(&self.state, &self.tool.name, pid, written).await?;
+        debug!(tool = %self.tool.name, id, method, "Sent request");
+
+        // The handshake isn't cancellable
+        let in_flight = (method != "initialize").then(|| InFlight {
+            call: Some(Abandoned {
+                id,
+                pid,
+                tool: self.tool.name.clone(),
+                state: Arc::clone(&self.state),
+                backend_state: Arc::clone(&self.backend_state),
+                stop_after: self
+                    .tool
+                    .kill_on_abandoned
+                    .then_some(self.options.abandoned_call_grace),
+            }),
+        });
+        if in_flight.is_some() {
+            entry.handed_off();
+        }
+
         // Wait for the background reader to deliver our response
-        let response = rx.await.map_err(|_| anyhow!("Response channel closed"))?;
+        let response = rx.await;
+        if let Some(in_flight) = in_flight {
+            in_flight.finished();
+        }
+        let response = response
+            .map_err(|_| anyhow!("Response channel closed"))?
+            .map_err(|reason| anyhow!(reason))?;
 
         if let Some(err) = response.error {
-            return Err(anyhow!("RPC error {}: {}", err.code, err.message));
+            return Err(RpcError {
+                code: err.code,
+                message: err.message,
+            }
+            .into());
         }
 
         let result = response
@@ -322,19 +1503,180 @@ impl ToolProxy {
     /// List tools from this server
     pub async fn list_tools(&self) -> Result<Vec<McpTool>> {
         self.ensure_ready().await?;
-        let result: ListToolsResult = self.call("tools/list", None).await?;
+        let result: ListToolsResult = match self.call("tools/list", None).await {
+            Err(e) if e.is::<NotStarted>() => {
+                self.restarted_under("tools/list").await?;
+                self.call("tools/list", None).await?
+            }
+            result => result?,
+        };
+        *self.destructive.lock().unwrap() = result
+            .tools
+            .iter()
+            .map(|t| (t.name.clone(), t.is_annotated_destructive()))
+            .collect();
         Ok(result.tools)
     }
 
+    /// Whether `tool` was annotated destructive when the backend last listed
+    /// its tools; `None` if it hasn't listed them or didn't list `tool`.
+    /// Never starts the backend.
+    pub fn annotated_destructive(&self, tool: &str) -> Option<bool> {
+        self.destructive.lock().unwrap().get(tool).copied()
+    }
+
+    /// Get a backend that was stopped under a request ready again, so the
+    /// request can be retried. Callers retry once, so a backend stopped
+    /// over and over still fails.
+    async fn restarted_under(&self, method: &str) -> Result<()> {
+        debug!(tool = %self.tool.name, method, "Backend stopped before the request went out, starting it again");
+        self.ensure_ready().await
+    }
+
     /// Call a tool
     pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<CallToolResult> {
-        self.ensure_ready().await?;
+        self.call_tool_with_meta(name, arguments, None).await
+    }
+
+    /// Call a tool, passing `meta` along as the request's `_meta`
+    pub async fn call_tool_with_meta(
+        &self,
+        name: &str,
+        arguments: Value,
+        meta: Option<Value>,
+    ) -> Result<CallToolResult> {
+        self.call_tool_timed(name, arguments, meta, &mut CallTimings::default())
+            .await
+    }
+
+    /// `call_tool_with_meta`, recording in `timings` how long the call
+    /// waited for the backend and how long the backend took
+    pub async fn call_tool_timed(
+        &self,
+        name: &str,
+        arguments: Value,
+        meta: Option<Value>,
+        timings: &mut CallTimings,
+    ) -> Result<CallToolResult> {
+        let replica = self.next_replica(&arguments);
+        let _load = replica.load.start();
+        replica.call_tool_here(name, arguments, meta, timings).await
+    }
+
+    /// The replica to take a tool call with `arguments`: the one its
+    /// `shard_by` argument hashes to, else the one whose turn it is (see
+    /// `balance`). Replicas that failed to start, were rejected at
+    /// initialize or have crashed are passed over while any other is left.
+    fn next_replica(&self, arguments: &Value) -> &ToolProxy {
+        if self.replicas.is_empty() {
+            return self;
+        }
+        let all: Vec<&ToolProxy> = std::iter::once(self).chain(&self.replicas).collect();
+        let available: Vec<bool> = all
+            .iter()
+            .map(|replica| {
+                replica.backend_state() != BackendState::Unavailable
+                    && replica.init_failure().is_none()
+                    && !replica.crashed.load(Ordering::Relaxed)
+            })
+            .collect();
+        let key = self
+            .tool
+            .shard_by
+            .as_ref()
+            .and_then(|argument| arguments.get(argument));
+        let index = match key {
+            Some(key) => balance::by_key(key, &available),
+            None => {
+                let turn = self.next_replica.fetch_add(1, Ordering::Relaxed);
+                balance::round_robin(turn, &available)
+            }
+        };
+        all[index]
+    }
+
+    /// Whether a replica crashed and `revive` should start it again
+    pub fn needs_revival(&self) -> bool {
+        !self.replicas.is_empty()
+            && std::iter::once(self)
+                .chain(&self.replicas)
+                .any(|replica| replica.crashed.load(Ordering::Relaxed))
+    }
+
+    /// Start every crashed replica again, while tool calls go to the
+    /// others. Does nothing if another `revive` is already at it.
+    pub async fn revive(&self) {
+        if self.reviving.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        for replica in std::iter::once(self).chain(&self.replicas) {
+            if !replica.crashed.load(Ordering::Relaxed) {
+                continue;
+            }
+            match replica.ensure_ready().await {
+                Ok(()) => {
+                    info!(tool = %self.tool.name, instance = ?replica.instance, "Restarted crashed replica")
+                }
+                Err(e) => {
+                    warn!(tool = %self.tool.name, instance = ?replica.instance, error = %e, "Failed to restart crashed replica")
+                }
+            }
+        }
+        self.reviving.store(false, Ordering::Release);
+    }
+
+    /// Calls taken by each replica, empty for a backend with one process
+    pub fn instances(&self) -> Vec<InstanceSnapshot> {
+        if self.replicas.is_empty() {
+            return Vec::new();
+        }
+        std::iter::once(self)
+            .chain(&self.replicas)
+            .enumerate()
+            .map(|(index, replica)| InstanceSnapshot {
+                index,
+                state: replica.backend_state(),
+                crashed: replica.crashed.load(Ordering::Relaxed),
+                calls: replica.load.calls.load(Ordering::Relaxed),
+                active: replica.load.active.load(Ordering::Relaxed),
+                busy_ms: replica.load.busy_ms.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// `call_tool_timed` on this proxy's own process
+    async fn call_tool_here(
+        &self,
+        name: &str,
+        arguments: Value,
+        meta: Option<Value>,
+        timings: &mut CallTimings,
+    ) -> Result<CallToolResult> {
+        let started = Instant::now();
+        let _turn = if self.tool.singleton {
+            Some(self.call_lock.lock().await)
+        } else {
+            None
+        };
+        let ready = self.ready(self.options.fail_fast_uninitialized).await;
+        timings.ready = started.elapsed();
+        ready?;
         let params = CallToolParams {
             name: name.to_string(),
             arguments,
+            meta,
         };
-        self.call("tools/call", Some(serde_json::to_value(params)?))
-            .await
+        let sent = Instant::now();
+        let request = || serde_json::to_value(&params).map(Some);
+        let result = match self.call("tools/call", request()?).await {
+            Err(e) if e.is::<NotStarted>() => {
+                self.restarted_under("tools/call").await?;
+                self.call("tools/call", request()?).await
+            }
+            result => result,
+        };
+        timings.round_trip = sent.elapsed();
+        result
     }
 
     /// List resources from this server
@@ -354,6 +1696,21 @@ impl ToolProxy {
             .await
     }
 
+    /// Ask the backend to send `notifications/resources/updated` for `uri`
+    pub async fn subscribe_resource(&self, uri: &str) -> Result<()> {
+        self.ensure_ready().await?;
+        self.subscription_call("resources/subscribe", uri).await
+    }
+
+    /// Stop updates for `uri`. A backend that isn't running has no
+    /// subscriptions left to end, so it isn't started for this.
+    pub async fn unsubscribe_resource(&self, uri: &str) -> Result<()> {
+        if self.backend_state() != BackendState::Ready {
+            return Ok(());
+        }
+        self.subscription_call("resources/unsubscribe", uri).await
+    }
+
     /// List prompts from this server
     pub async fn list_prompts(&self) -> Result<Vec<Prompt>> {
         self.ensure_ready().await?;
@@ -377,6 +1734,141 @@ impl ToolProxy {
     }
 }
 
+/// A request waiting for the backend's answer. If the caller stops waiting
+/// (its future is dropped, as when the client disconnects), dropping this
+/// tells the backend the request is cancelled.
+struct InFlight {
+    call: Option<Abandoned>,
+}
+
+impl InFlight {
+    /// The answer arrived; there's nothing to cancel
+    fn finished(mut self) {
+        self.call = None;
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if let Some(call) = self.call.take()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn(call.cancel());
+        }
+    }
+}
+
+/// A request's slot in `pending`, removed when the call ends without an
+/// answer: the write failed or was cut short, or the caller stopped
+/// waiting. Once an [`InFlight`] exists, it decides when the slot goes.
+struct PendingEntry {
+    id: i64,
+    pending: Option<Arc<Pending>>,
+}
+
+impl PendingEntry {
+    fn handed_off(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let id = self.id;
+        if let Ok(mut waiting) = pending.try_lock() {
+            waiting.remove(&id);
+            return;
+        }
+        // The reader holds it for a moment
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                pending.lock().await.remove(&id);
+            });
+        }
+    }
+}
+
+/// What cancelling a request nobody waits for anymore needs
+struct Abandoned {
+    id: i64,
+    /// The process the request was sent to
+    pid: Option<u32>,
+    tool: String,
+    state: Arc<Mutex<ProxyState>>,
+    backend_state: Arc<AtomicU8>,
+    /// With `kill_on_abandoned`, how long the backend may take to answer
+    /// before it's stopped
+    stop_after: Option<Duration>,
+}
+
+impl Abandoned {
+    async fn cancel(self) {
+        let state = self.state.lock().await;
+        let pending = Arc::clone(&state.pending);
+        let (tx, answer) = oneshot::channel();
+        match pending.lock().await.get_mut(&self.id) {
+            // Watch for the answer in place of the caller
+            Some(waiter) => *waiter = tx,
+            // Answered (or failed) as the caller gave up
+            None => return,
+        }
+
+        let written = state.queue_cancelled(&self.tool, self.id).await;
+        drop(state);
+        let sent = match written {
+            Ok(written) => await_written(&self.state, &self.tool, self.pid, written).await,
+            Err(e) => Err(e),
+        };
+        match sent {
+            Ok(()) => debug!(tool = %self.tool, id = self.id, "Cancelled abandoned request"),
+            Err(e) => {
+                debug!(tool = %self.tool, id = self.id, error = %e, "Couldn't cancel abandoned request")
+            }
+        }
+
+        let Some(grace) = self.stop_after else {
+            pending.lock().await.remove(&self.id);
+            return;
+        };
+        if tokio::time::timeout(grace, answer).await.is_ok() {
+            return;
+        }
+        let mut state = self.state.lock().await;
+        pending.lock().await.remove(&self.id);
+        // Leave a process that has already been replaced alone
+        if self.pid.is_none() || state.process.as_ref().and_then(Child::id) != self.pid {
+            return;
+        }
+        warn!(tool = %self.tool, id = self.id, ?grace, "Backend still busy with an abandoned request, stopping it");
+        state.shut_down(&self.tool).await;
+        self.backend_state
+            .store(BackendState::Stopped.as_u8(), Ordering::Relaxed);
+    }
+}
+
+/// Kill everything in a backend's process group (wrappers and whatever they started)
+fn kill_process_group(child: &Child) {
+    kill_group(child.id());
+}
+
+/// Kill the process group led by the backend process `pid`
+#[cfg(unix)]
+fn kill_group(pid: Option<u32>) {
+    use nix::sys::signal::{Signal, killpg};
+    use nix::unistd::Pid;
+
+    // The child leads its own group (`process_group(0)` at spawn)
+    if let Some(pid) = pid {
+        let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_pid: Option<u32>) {}
+
 impl Drop for ToolProxy {
     fn drop(&mut self) {
         // Abort the reader task
@@ -385,8 +1877,280 @@ impl Drop for ToolProxy {
                 handle.abort();
             }
             if let Some(mut child) = state.process.take() {
+                kill_process_group(&child);
                 let _ = child.start_kill();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn write_message_completes() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        write_message(&mut writer, b"hello\n", Duration::from_secs(1))
+            .await
+            .unwrap();
+        let mut buf = [0u8; 6];
+        tokio::io::AsyncReadExt::read_exact(&mut reader, &mut buf)
+            .await
+            .unwrap();
+        assert_eq!(&buf, b"hello\n");
+    }
+
+    #[tokio::test]
+    async fn write_message_times_out_when_reader_stalls() {
+        // Nobody drains the other end, so the 8-byte buffer fills and the write blocks
+        let (mut writer, _reader) = tokio::io::duplex(8);
+        let err = write_message(&mut writer, &[b'x'; 64], Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+    }
+
+    /// Records each write it's given
+    #[derive(Default, Clone)]
+    struct Writes(Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+
+    impl AsyncWrite for Writes {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.0.lock().unwrap().push(buf.to_vec());
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Queue `messages` at once, then let `write_queued` write them
+    async fn write_burst(
+        writer: impl AsyncWrite + Unpin,
+        messages: &[&str],
+        batch: usize,
+    ) -> Vec<std::result::Result<(), WriteFailed>> {
+        let (queue, receiver) = mpsc::channel(messages.len());
+        let mut written = Vec::new();
+        for message in messages {
+            let (tx, rx) = oneshot::channel();
+            queue
+                .try_send(Outbound {
+                    message: message.as_bytes().to_vec(),
+                    written: tx,
+                })
+                .unwrap();
+            written.push(rx);
+        }
+        drop(queue);
+        write_queued(writer, receiver, batch, Duration::from_millis(50)).await;
+        let mut results = Vec::new();
+        for rx in written {
+            results.push(rx.await.unwrap());
+        }
+        results
+    }
+
+    #[tokio::test]
+    async fn queued_messages_share_writes_in_order() {
+        let writes = Writes::default();
+        let messages = ["a\n", "b\n", "c\n", "d\n", "e\n"];
+        let results = write_burst(writes.clone(), &messages, 2).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(
+            *writes.0.lock().unwrap(),
+            [b"a\nb\n".to_vec(), b"c\nd\n".to_vec(), b"e\n".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn batches_of_one_write_each_message_alone() {
+        let writes = Writes::default();
+        write_burst(writes.clone(), &["a\n", "b\n", "c\n"], 1).await;
+        assert_eq!(
+            *writes.0.lock().unwrap(),
+            [b"a\n".to_vec(), b"b\n".to_vec(), b"c\n".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_closed_pipe_fails_writes_without_waiting() {
+        let (writer, reader) = tokio::io::duplex(64);
+        drop(reader);
+        let results = write_burst(writer, &["first\n", "second\n"], 1).await;
+        for result in results {
+            let failed = result.unwrap_err();
+            assert!(!failed.timed_out, "{}", failed);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_fails_everything_queued_behind_it() {
+        // Nobody drains the other end, so the first write times out
+        let (writer, _reader) = tokio::io::duplex(4);
+        let results = write_burst(writer, &["first\n", "second\n", "third\n"], 2).await;
+        for result in results {
+            let failed = result.unwrap_err();
+            assert!(failed.timed_out, "{}", failed);
+            assert!(failed.message.contains("Timed out"));
+        }
+    }
+
+    #[test]
+    fn spawn_errors_are_classified() {
+        use std::io::{Error, ErrorKind};
+        assert!(is_transient_spawn_error(&Error::from(
+            ErrorKind::WouldBlock
+        )));
+        assert!(is_transient_spawn_error(&Error::from(
+            ErrorKind::OutOfMemory
+        )));
+        assert!(!is_transient_spawn_error(&Error::from(ErrorKind::NotFound)));
+        assert!(!is_transient_spawn_error(&Error::from(
+            ErrorKind::PermissionDenied
+        )));
+        #[cfg(unix)]
+        {
+            for code in [libc::EAGAIN, libc::ENOMEM, libc::EMFILE, libc::ETXTBSY] {
+                assert!(is_transient_spawn_error(&Error::from_raw_os_error(code)));
+            }
+            for code in [libc::ENOENT, libc::EACCES, libc::ENOEXEC, libc::E2BIG] {
+                assert!(!is_transient_spawn_error(&Error::from_raw_os_error(code)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_command_fails_at_once_with_its_name() {
+        let proxy = ToolProxy::new(Tool {
+            name: "ghost".to_string(),
+            command: vec!["/nonexistent/mcpd-test-server".to_string()],
+            ..Default::default()
+        });
+        let started = Instant::now();
+        let err = proxy.start().await.unwrap_err();
+        assert!(started.elapsed() < SPAWN_RETRY_DELAY);
+        assert!(
+            format!("{:#}", err).contains("\"/nonexistent/mcpd-test-server\" not found"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_command_is_an_error_not_a_panic() {
+        let proxy = ToolProxy::new(Tool {
+            name: "hollow".to_string(),
+            ..Default::default()
+        });
+        let err = proxy.list_tools().await.unwrap_err();
+        assert_eq!(err.to_string(), "Tool 'hollow' has no command");
+    }
+
+    #[test]
+    fn replicas_take_turns_passing_over_unhealthy_ones() {
+        let proxy = ToolProxy::new(Tool {
+            name: "pool".to_string(),
+            command: vec!["srv".to_string()],
+            replicas: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(proxy.replicas.len(), 2);
+        assert!(proxy.replicas.iter().all(|r| r.tool.replicas.is_none()));
+        let turns = |n| -> Vec<*const ToolProxy> {
+            (0..n)
+                .map(|_| proxy.next_replica(&Value::Null) as *const _)
+                .collect()
+        };
+        let [a, b, c]: [*const ToolProxy; 3] = [
+            &proxy as *const _,
+            &proxy.replicas[0] as *const _,
+            &proxy.replicas[1] as *const _,
+        ];
+        assert_eq!(turns(4), [a, b, c, a]);
+
+        // Failed to start, and rejected at initialize
+        proxy.replicas[0].set_backend_state(BackendState::Unavailable);
+        *proxy.replicas[1].init_failure.lock().unwrap() = Some((
+            Instant::now(),
+            RpcError {
+                code: -32600,
+                message: "no".to_string(),
+            },
+        ));
+        assert_eq!(turns(3), [a, a, a]);
+
+        // With none healthy, the turn order holds
+        proxy.set_backend_state(BackendState::Unavailable);
+        assert_eq!(turns(3), [b, c, a]);
+    }
+
+    #[test]
+    fn shard_by_pins_calls_and_crashed_replicas_are_passed_over() {
+        let proxy = ToolProxy::new(Tool {
+            name: "pool".to_string(),
+            command: vec!["srv".to_string()],
+            replicas: Some(3),
+            shard_by: Some("path".to_string()),
+            ..Default::default()
+        });
+        let instances: Vec<Option<usize>> = std::iter::once(&proxy)
+            .chain(&proxy.replicas)
+            .map(|r| r.instance)
+            .collect();
+        assert_eq!(instances, [Some(0), Some(1), Some(2)]);
+
+        let pick = |args: Value| proxy.next_replica(&args).instance.unwrap();
+        let first = pick(serde_json::json!({"path": "src/main.rs"}));
+        for _ in 0..5 {
+            assert_eq!(pick(serde_json::json!({"path": "src/main.rs"})), first);
+        }
+        // Without the argument calls take turns
+        let turns: Vec<usize> = (0..3).map(|_| pick(Value::Null)).collect();
+        assert_eq!(turns, [0, 1, 2]);
+
+        let all: Vec<&ToolProxy> = std::iter::once(&proxy).chain(&proxy.replicas).collect();
+        all[first].crashed.store(true, Ordering::Relaxed);
+        assert!(proxy.needs_revival());
+        let moved = pick(serde_json::json!({"path": "src/main.rs"}));
+        assert_ne!(moved, first);
+        assert!(proxy.instances()[first].crashed);
+        all[first].crashed.store(false, Ordering::Relaxed);
+        assert!(!proxy.needs_revival());
+        assert_eq!(pick(serde_json::json!({"path": "src/main.rs"})), first);
+    }
+
+    #[test]
+    fn log_level_filter_only_applies_to_log_messages() {
+        let log = |level: &str| Notification {
+            params: Some(serde_json::json!({"level": level, "data": "x"})),
+            ..Notification::new("notifications/message")
+        };
+        assert!(below_log_level(&log("info"), LoggingLevel::Warning));
+        assert!(!below_log_level(&log("warning"), LoggingLevel::Warning));
+        assert!(!below_log_level(&log("critical"), LoggingLevel::Warning));
+        // Unknown levels and other notifications pass through
+        assert!(!below_log_level(&log("chatty"), LoggingLevel::Warning));
+        let progress = Notification {
+            params: Some(serde_json::json!({"level": "debug"})),
+            ..Notification::new("notifications/progress")
+        };
+        assert!(!below_log_level(&progress, LoggingLevel::Error));
+    }
+}
@@ -0,0 +1,264 @@
+//! Rate limits on what backends send unasked. `TokenBucket` is the limiter;
+//! `NotificationLimiter` keeps one per notification method for a backend
+//! and decides which of its notifications are relayed to clients, so a
+//! backend logging hundreds of lines a second can't crowd responses out of
+//! the clients' outbound queues.
+//!
+//! Log messages are limited to `DEFAULT_LOG_RATE` a second unless the
+//! backend's `notification_limits` say otherwise; other methods are
+//! unlimited unless configured. `PRIORITY_METHODS` are never limited.
+//! Dropped notifications are counted and reported in a summary with the
+//! first notification of that method to arrive `SUMMARY_INTERVAL` or more
+//! after the first drop it covers, so a flood is reported about once an
+//! interval however long it lasts.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Log messages a backend may send per second by default
+pub const DEFAULT_LOG_RATE: u32 = 20;
+
+/// Shortest time between summaries of one method's drops
+pub const SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Methods never limited: dropping them loses a caller's progress or
+/// leaves clients with a stale list
+pub const PRIORITY_METHODS: [&str; 4] = [
+    "notifications/progress",
+    "notifications/tools/list_changed",
+    "notifications/resources/list_changed",
+    "notifications/prompts/list_changed",
+];
+
+const LOG_METHOD: &str = "notifications/message";
+
+/// Allows `per_second` events a second on average, and bursts of up to a
+/// second's worth
+#[derive(Debug)]
+pub struct TokenBucket {
+    per_second: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket. `per_second` is at least 1.
+    pub fn new(per_second: u32, now: Instant) -> Self {
+        let per_second = f64::from(per_second.max(1));
+        Self {
+            per_second,
+            tokens: per_second,
+            refilled: now,
+        }
+    }
+
+    /// Take a token if there is one
+    pub fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.per_second);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Drops reported together: how many notifications of `method` were
+/// dropped for going over `per_second`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub method: String,
+    pub dropped: u64,
+    pub per_second: u32,
+}
+
+impl Summary {
+    /// What's logged and sent to clients
+    pub fn message(&self, backend: &str) -> String {
+        let what = match self.method.as_str() {
+            LOG_METHOD => "logging",
+            method => method,
+        };
+        format!(
+            "Dropped {} {} notifications from backend '{}' (over its limit of {}/s)",
+            self.dropped, what, backend, self.per_second
+        )
+    }
+}
+
+/// What to do with one notification
+#[derive(Debug, PartialEq, Eq)]
+pub struct Verdict {
+    /// Relay it
+    pub relay: bool,
+    /// Drops to report first
+    pub summary: Option<Summary>,
+}
+
+struct Limited {
+    bucket: TokenBucket,
+    per_second: u32,
+    dropped: u64,
+    first_dropped: Option<Instant>,
+}
+
+/// Per-method limits on one backend's notifications
+pub struct NotificationLimiter {
+    /// Method → notifications a second, `None` for unlimited
+    limits: HashMap<String, Option<u32>>,
+    limited: Mutex<HashMap<String, Limited>>,
+}
+
+impl NotificationLimiter {
+    /// The defaults, overridden by a backend's `notification_limits`
+    pub fn new(configured: &BTreeMap<String, Option<u32>>) -> Self {
+        let mut limits = HashMap::from([(LOG_METHOD.to_string(), Some(DEFAULT_LOG_RATE))]);
+        limits.extend(configured.iter().map(|(m, l)| (m.clone(), *l)));
+        for method in PRIORITY_METHODS {
+            limits.remove(method);
+        }
+        Self {
+            limits,
+            limited: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a notification of `method` arriving at `now` is relayed
+    pub fn check(&self, method: &str, now: Instant) -> Verdict {
+        let Some(Some(per_second)) = self.limits.get(method).copied() else {
+            return Verdict {
+                relay: true,
+                summary: None,
+            };
+        };
+        let mut limited = self.limited.lock().unwrap();
+        let state = limited
+            .entry(method.to_string())
+            .or_insert_with(|| Limited {
+                bucket: TokenBucket::new(per_second, now),
+                per_second,
+                dropped: 0,
+                first_dropped: None,
+            });
+        let relay = state.bucket.try_take(now);
+        if !relay {
+            state.dropped += 1;
+            state.first_dropped.get_or_insert(now);
+        }
+        let due = state
+            .first_dropped
+            .is_some_and(|first| now.saturating_duration_since(first) >= SUMMARY_INTERVAL);
+        if !due {
+            return Verdict {
+                relay,
+                summary: None,
+            };
+        }
+        state.first_dropped = None;
+        let summary = Some(Summary {
+            method: method.to_string(),
+            dropped: std::mem::take(&mut state.dropped),
+            per_second: state.per_second,
+        });
+        Verdict { relay, summary }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(configured: &[(&str, Option<u32>)]) -> NotificationLimiter {
+        NotificationLimiter::new(
+            &configured
+                .iter()
+                .map(|(m, l)| (m.to_string(), *l))
+                .collect(),
+        )
+    }
+
+    fn relayed(limiter: &NotificationLimiter, method: &str, count: usize, now: Instant) -> usize {
+        (0..count)
+            .filter(|_| limiter.check(method, now).relay)
+            .count()
+    }
+
+    #[test]
+    fn buckets_refill_at_their_rate_up_to_a_second_of_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(10, start);
+        assert_eq!((0..20).filter(|_| bucket.try_take(start)).count(), 10);
+        let later = start + Duration::from_millis(250);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+        // Idle time doesn't bank more than a second's worth
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!((0..20).filter(|_| bucket.try_take(much_later)).count(), 10);
+    }
+
+    #[test]
+    fn log_messages_are_limited_by_default_and_priority_methods_never() {
+        let now = Instant::now();
+        let limiter = limiter(&[
+            ("notifications/progress", Some(1)),
+            ("notifications/resources/updated", Some(2)),
+        ]);
+        assert_eq!(
+            relayed(&limiter, "notifications/message", 100, now),
+            DEFAULT_LOG_RATE as usize
+        );
+        assert_eq!(relayed(&limiter, "notifications/progress", 100, now), 100);
+        assert_eq!(
+            relayed(&limiter, "notifications/tools/list_changed", 100, now),
+            100
+        );
+        assert_eq!(
+            relayed(&limiter, "notifications/resources/updated", 100, now),
+            2
+        );
+
+        let unlimited = self::limiter(&[("notifications/message", None)]);
+        assert_eq!(relayed(&unlimited, "notifications/message", 100, now), 100);
+    }
+
+    #[test]
+    fn drops_are_summarized_about_once_an_interval() {
+        let start = Instant::now();
+        let limiter = limiter(&[("notifications/message", Some(2))]);
+        assert_eq!(relayed(&limiter, "notifications/message", 5, start), 2);
+
+        // Too soon to report the three drops, even with one let through
+        let soon = start + SUMMARY_INTERVAL / 2;
+        assert_eq!(
+            limiter.check("notifications/message", soon),
+            Verdict {
+                relay: true,
+                summary: None
+            }
+        );
+        let later = start + SUMMARY_INTERVAL;
+        let verdict = limiter.check("notifications/message", later);
+        assert!(verdict.relay);
+        let summary = verdict.summary.unwrap();
+        assert_eq!(summary.dropped, 3);
+        assert_eq!(
+            summary.message("fs"),
+            "Dropped 3 logging notifications from backend 'fs' (over its limit of 2/s)"
+        );
+        assert_eq!(limiter.check("notifications/message", later).summary, None);
+
+        // A steady flood of 100/s for ten seconds, the first drop 10ms in
+        let mut summaries = Vec::new();
+        for ms in (10..=10_000).step_by(10) {
+            let now = later + Duration::from_millis(ms);
+            summaries.extend(limiter.check("notifications/message", now).summary);
+        }
+        assert_eq!(summaries.len(), 9, "{:?}", summaries);
+        assert!(summaries.iter().all(|s| s.dropped >= 90), "{:?}", summaries);
+    }
+}
@@ -1,29 +1,847 @@
 //! Tool registry - persistent storage of registered MCP tools.
 
-use anyhow::{Context, Result};
+use crate::framing::Framing;
+use crate::health::HealthCheck;
+use crate::icons::{self, IconError};
+use crate::mcp::LoggingLevel;
+use crate::ratelimit;
+use crate::scheduler::Priority;
+use crate::secret_env;
+use crate::secrets::EnvMask;
+use crate::tool_concurrency::ToolConcurrency;
+use anyhow::{Context, Result, bail};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use uuid::Uuid;
 
-/// A registered MCP tool server
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A registered MCP tool server. Its `Debug` output masks secret-looking
+/// env values, so logging a tool doesn't leak credentials.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
+    /// Stable identity, assigned when the server is registered and kept
+    /// when it's edited or renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
     pub command: Vec<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    /// Environment variables passed on from mcpd's own environment as it is
+    /// each time the server starts, for values like rotating tokens that
+    /// shouldn't be frozen into the registry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dynamic_env: Vec<String>,
+    /// Environment variables stored encrypted (see `secret_env`), opened
+    /// only when the server starts
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secret_env: BTreeMap<String, String>,
+    /// Restart by warming up a replacement instance before switching over
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub zero_downtime: bool,
+    /// jq expression applied to the JSON in successful tool results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// Working directory for the subprocess (default: mcpd's own)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Run in the client's first root directory when it reports one, falling back to `cwd`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cwd_from_root: bool,
+    /// Pass the client's `_meta` (with mcpd's trace context) on to this backend's tool calls
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub forward_trace_context: bool,
+    /// Admission priority of this backend's calls under `--max-concurrent-calls`
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+    /// Command prefix the server runs under, e.g. a sandbox like `bwrap ... --`.
+    /// `None` uses the serve-wide default; an empty list opts out of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapper: Option<Vec<String>>,
+    /// How the server delimits messages on stdio; detected unless declared
+    #[serde(default, skip_serializing_if = "Framing::is_auto")]
+    pub framing: Framing,
+    /// Command that gets each call's arguments as JSON and prints the arguments to send
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_call: Option<Vec<String>>,
+    /// Command that gets each call's result as JSON and prints the result to return
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_call: Option<Vec<String>>,
+    /// Lowest level of the server's log messages passed on to clients
+    #[serde(default, skip_serializing_if = "LoggingLevel::is_debug")]
+    pub log_level: LoggingLevel,
+    /// Send the server one tool call at a time, queueing the rest, for
+    /// servers that can't handle concurrent calls
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub singleton: bool,
+    /// Send the server one request at a time, each in its own write, for
+    /// servers that misbehave when a request arrives before they've answered
+    /// the last one
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_pipelining: bool,
+    /// Tool call made on an interval to check the server can do real work
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_check: Option<HealthCheck>,
+    /// Stop the server when a call the client stopped waiting for isn't
+    /// answered within the abandoned-call grace period, so the next call
+    /// gets a fresh process instead of one still busy with the old work
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub kill_on_abandoned: bool,
+    /// Another registered server to send a call to when this one can't be
+    /// reached (it won't start or initialize, or its process fails)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// Bytes buffered per read of the server's stdout, overriding
+    /// `serve --read-buffer-size`; larger buffers help servers that return
+    /// multi-megabyte results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_buffer_size: Option<usize>,
+    /// Largest `arguments` of a tool call, serialized, that's forwarded to
+    /// the server, overriding `serve --max-arg-bytes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_arg_bytes: Option<usize>,
+    /// Processes to run for the server, with tool calls sent to each in
+    /// turn (default 1)
+    #[serde(default, alias = "instances", skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<u32>,
+    /// Argument whose value picks the replica a tool call goes to, so calls
+    /// with the same value go to the same process. Calls without it take
+    /// their turn.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard_by: Option<String>,
+    /// Tool name → how many calls to it run at once, for servers where
+    /// only some tools can't take concurrent calls (see `tool_concurrency`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tool_concurrency: BTreeMap<String, ToolConcurrency>,
+    /// Tool name → merge group. Tools of any servers in the same group are
+    /// listed once, under the group's name, and a call goes to all of them
+    /// (see `merge`).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub merge_groups: BTreeMap<String, String>,
+    /// Notification method → how many a second are relayed to clients,
+    /// `null` for no limit, overriding `ratelimit`'s defaults
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub notification_limits: BTreeMap<String, Option<u32>>,
+    /// Name clients show for the server instead of `name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The server's icon as a `data:` URI (see `icons::embed`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// `clientInfo.name` sent to the server in `initialize`, overriding
+    /// `serve --client-name`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// `clientInfo.version` sent to the server in `initialize`, overriding
+    /// `serve --client-version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_version: Option<String>,
+}
+
+impl std::fmt::Debug for Tool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        static MASK: LazyLock<EnvMask> = LazyLock::new(EnvMask::default);
+        let env: BTreeMap<&str, &str> = self
+            .env
+            .iter()
+            .map(|(k, v)| (k.as_str(), MASK.show(k, v)))
+            .collect();
+        f.debug_struct("Tool")
+            .field("name", &self.name)
+            .field("id", &self.id)
+            .field("command", &self.command)
+            .field("env", &env)
+            .field("dynamic_env", &self.dynamic_env)
+            .field(
+                "secret_env",
+                &self
+                    .secret_env
+                    .keys()
+                    .map(|k| (k.as_str(), secret_env::ENCRYPTED_MARKER))
+                    .collect::<BTreeMap<_, _>>(),
+            )
+            .field("zero_downtime", &self.zero_downtime)
+            .field("transform", &self.transform)
+            .field("cwd", &self.cwd)
+            .field("cwd_from_root", &self.cwd_from_root)
+            .field("forward_trace_context", &self.forward_trace_context)
+            .field("priority", &self.priority)
+            .field("wrapper", &self.wrapper)
+            .field("framing", &self.framing)
+            .field("pre_call", &self.pre_call)
+            .field("post_call", &self.post_call)
+            .field("log_level", &self.log_level)
+            .field("singleton", &self.singleton)
+            .field("no_pipelining", &self.no_pipelining)
+            .field("health_check", &self.health_check)
+            .field("kill_on_abandoned", &self.kill_on_abandoned)
+            .field("fallback", &self.fallback)
+            .field("read_buffer_size", &self.read_buffer_size)
+            .field("max_arg_bytes", &self.max_arg_bytes)
+            .field("replicas", &self.replicas)
+            .field("shard_by", &self.shard_by)
+            .field("tool_concurrency", &self.tool_concurrency)
+            .field("merge_groups", &self.merge_groups)
+            .field("notification_limits", &self.notification_limits)
+            .field("title", &self.title)
+            // The whole image would drown out everything else
+            .field(
+                "icon",
+                &self
+                    .icon
+                    .as_ref()
+                    .map(|icon| format!("{} bytes", icon.len())),
+            )
+            .field("client_name", &self.client_name)
+            .field("client_version", &self.client_version)
+            .finish()
+    }
+}
+
+/// Why a tool can't be registered or spawned, from `Tool::validate`
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ToolValidationError {
+    #[error("Tool name is empty")]
+    EmptyName,
+    #[error("Tool name '{name}' contains '{found}'")]
+    InvalidNameChar { name: String, found: char },
+    #[error(
+        "Tool name '{name}' can't contain '__' or start or end with '_' (mcpd uses '__' to separate server and tool names)"
+    )]
+    ReservedUnderscore { name: String },
+    #[error("Tool '{name}' has no command")]
+    NoCommand { name: String },
+    #[error("Tool '{name}' has an empty command")]
+    EmptyProgram { name: String },
+    #[error("Tool '{name}' has an empty {hook} hook")]
+    EmptyHook { name: String, hook: &'static str },
+    #[error("Tool '{name}' has a command argument containing a NUL byte")]
+    NulInCommand { name: String },
+    #[error("Tool '{name}' has an invalid environment variable name {key:?}")]
+    InvalidEnvName { name: String, key: String },
+    #[error("Environment variable {key} of tool '{name}' contains a NUL byte")]
+    NulInEnvValue { name: String, key: String },
+    #[error("Tool '{name}' sets {key} in env and also reads it from mcpd's environment")]
+    EnvSetTwice { name: String, key: String },
+    #[error("Tool '{name}' sets {key} as a secret and also in env or from mcpd's environment")]
+    SecretEnvSetTwice { name: String, key: String },
+    #[error("Secret env value {key} of tool '{name}' isn't encrypted")]
+    SecretEnvNotEncrypted { name: String, key: String },
+    #[error(
+        "Wrapper argument {arg:?} of tool '{name}' has {{command}} inside it; {{command}} must be an argument of its own"
+    )]
+    EmbeddedCommandPlaceholder { name: String, arg: String },
+    #[error(
+        "Wrapper argument {arg:?} of tool '{name}' has unknown placeholder {{{placeholder}}} (known: {{command}}, {{name}}, {{cwd}})"
+    )]
+    UnknownPlaceholder {
+        name: String,
+        arg: String,
+        placeholder: String,
+    },
+    #[error("Tool '{name}' has a health check with {problem}")]
+    InvalidHealthCheck { name: String, problem: &'static str },
+    #[error("Tool '{name}' can't be its own fallback")]
+    SelfFallback { name: String },
+    #[error("Tool '{name}' has a read buffer size of 0")]
+    ZeroReadBuffer { name: String },
+    #[error("Tool '{name}' has an argument size limit of 0")]
+    ZeroMaxArgBytes { name: String },
+    #[error("Tool '{name}' has 0 replicas")]
+    ZeroReplicas { name: String },
+    #[error("Tool '{name}' shards by an empty argument name")]
+    EmptyShardBy { name: String },
+    #[error("Tool '{name}' limits its tool '{tool}' to 0 concurrent calls")]
+    ZeroToolConcurrency { name: String, tool: String },
+    #[error("Tool '{name}' both serializes its tool '{tool}' and allows it {max} concurrent calls")]
+    ConflictingToolConcurrency {
+        name: String,
+        tool: String,
+        max: u32,
+    },
+    #[error(
+        "Tool '{name}' merges its tool '{tool}' into group '{group}', which isn't a usable tool name (letters, digits, '-', '.' and '_', without '__' or '--')"
+    )]
+    InvalidMergeGroup {
+        name: String,
+        tool: String,
+        group: String,
+    },
+    #[error("Tool '{name}' limits {method} notifications to 0 a second")]
+    ZeroNotificationLimit { name: String, method: String },
+    #[error("Tool '{name}' can't limit {method} notifications; they're never dropped")]
+    PriorityNotificationLimit { name: String, method: String },
+    #[error("Tool '{name}' has an empty title")]
+    EmptyTitle { name: String },
+    #[error("Tool '{name}' has an empty client name")]
+    EmptyClientName { name: String },
+    #[error("Tool '{name}': {error}")]
+    InvalidIcon { name: String, error: IconError },
+}
+
+/// Placeholders `Tool::wrapped_command` expands in wrapper arguments
+const WRAPPER_PLACEHOLDERS: [&str; 3] = ["command", "name", "cwd"];
+
+/// The first `{word}` in `arg` that looks like a placeholder but isn't one
+/// mcpd knows. Shell-style `${VAR}` is left alone.
+fn unknown_placeholder(arg: &str) -> Option<&str> {
+    let mut rest = arg;
+    while let Some(open) = rest.find('{') {
+        let after = &rest[open + 1..];
+        let word_len = after
+            .find(|c: char| !(c.is_ascii_lowercase() || c == '_'))
+            .unwrap_or(after.len());
+        let word = &after[..word_len];
+        let is_shell = rest[..open].ends_with('$');
+        if !word.is_empty()
+            && after[word_len..].starts_with('}')
+            && !is_shell
+            && !WRAPPER_PLACEHOLDERS.contains(&word)
+        {
+            return Some(word);
+        }
+        rest = after;
+    }
+    None
+}
+
+impl Tool {
+    /// Start building a tool in code. `build` checks it the same way
+    /// `mcpd register` does.
+    ///
+    /// ```
+    /// use mcpd::registry::{Tool, ToolValidationError};
+    ///
+    /// let tool = Tool::builder("github")
+    ///     .command(["npx", "-y", "@modelcontextprotocol/server-github"])
+    ///     .env("GITHUB_TOKEN", "ghp_example")
+    ///     .cwd("/srv/work")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(tool.command[0], "npx");
+    ///
+    /// let err = Tool::builder("github").build().unwrap_err();
+    /// assert_eq!(err, ToolValidationError::NoCommand { name: "github".into() });
+    /// ```
+    pub fn builder(name: impl Into<String>) -> ToolBuilder {
+        ToolBuilder {
+            tool: Tool {
+                name: name.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Check the name, command, env and wrapper. Names become the
+    /// `name__tool` prefix and the `mcpd://name/` authority, so they're
+    /// limited to letters, digits, `-`, `.` and single underscores.
+    pub fn validate(&self) -> Result<(), ToolValidationError> {
+        match self.problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(()),
+        }
+    }
+
+    /// Everything `validate` finds wrong with the tool, not just the first
+    pub fn problems(&self) -> Vec<ToolValidationError> {
+        use ToolValidationError as E;
+        let name = &self.name;
+        let mut problems = Vec::new();
+        if name.is_empty() {
+            problems.push(E::EmptyName);
+        }
+        if let Some(found) = name
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_')))
+        {
+            problems.push(E::InvalidNameChar {
+                name: name.clone(),
+                found,
+            });
+        }
+        if name.contains("__") || name.starts_with('_') || name.ends_with('_') {
+            problems.push(E::ReservedUnderscore { name: name.clone() });
+        }
+        match self.command.first() {
+            None => problems.push(E::NoCommand { name: name.clone() }),
+            Some(program) if program.trim().is_empty() => {
+                problems.push(E::EmptyProgram { name: name.clone() });
+            }
+            Some(_) => {}
+        }
+        for (hook, command) in [("pre_call", &self.pre_call), ("post_call", &self.post_call)] {
+            if command.as_ref().is_some_and(|c| c.is_empty()) {
+                problems.push(E::EmptyHook {
+                    name: name.clone(),
+                    hook,
+                });
+            }
+        }
+        // These would only fail later, when the backend is spawned
+        if self.command.iter().any(|arg| arg.contains('\0')) {
+            problems.push(E::NulInCommand { name: name.clone() });
+        }
+        for (key, value) in &self.env {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                problems.push(E::InvalidEnvName {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+            if value.contains('\0') {
+                problems.push(E::NulInEnvValue {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+        }
+        for key in &self.dynamic_env {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                problems.push(E::InvalidEnvName {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+            if self.env.contains_key(key) {
+                problems.push(E::EnvSetTwice {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+        }
+        for (key, value) in &self.secret_env {
+            if key.is_empty() || key.contains(['=', '\0']) {
+                problems.push(E::InvalidEnvName {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+            if self.env.contains_key(key) || self.dynamic_env.contains(key) {
+                problems.push(E::SecretEnvSetTwice {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+            if !secret_env::is_sealed(value) {
+                problems.push(E::SecretEnvNotEncrypted {
+                    name: name.clone(),
+                    key: key.clone(),
+                });
+            }
+        }
+        // A misspelled or embedded placeholder would be passed on literally
+        for arg in self.wrapper.iter().flatten() {
+            if arg != "{command}" && arg.contains("{command}") {
+                problems.push(E::EmbeddedCommandPlaceholder {
+                    name: name.clone(),
+                    arg: arg.clone(),
+                });
+            }
+            if let Some(placeholder) = unknown_placeholder(arg) {
+                problems.push(E::UnknownPlaceholder {
+                    name: name.clone(),
+                    arg: arg.clone(),
+                    placeholder: placeholder.to_string(),
+                });
+            }
+        }
+        if let Some(problem) = self.health_check.as_ref().and_then(HealthCheck::problem) {
+            problems.push(E::InvalidHealthCheck {
+                name: name.clone(),
+                problem,
+            });
+        }
+        if self.fallback.as_ref() == Some(name) {
+            problems.push(E::SelfFallback { name: name.clone() });
+        }
+        if self.read_buffer_size == Some(0) {
+            problems.push(E::ZeroReadBuffer { name: name.clone() });
+        }
+        if self.max_arg_bytes == Some(0) {
+            problems.push(E::ZeroMaxArgBytes { name: name.clone() });
+        }
+        if self.replicas == Some(0) {
+            problems.push(E::ZeroReplicas { name: name.clone() });
+        }
+        if self.shard_by.as_ref().is_some_and(|arg| arg.is_empty()) {
+            problems.push(E::EmptyShardBy { name: name.clone() });
+        }
+        for (tool, limits) in &self.tool_concurrency {
+            match limits.max_concurrency {
+                Some(0) => problems.push(E::ZeroToolConcurrency {
+                    name: name.clone(),
+                    tool: tool.clone(),
+                }),
+                Some(max) if limits.serialize && max != 1 => {
+                    problems.push(E::ConflictingToolConcurrency {
+                        name: name.clone(),
+                        tool: tool.clone(),
+                        max,
+                    })
+                }
+                _ => {}
+            }
+        }
+        for (tool, group) in &self.merge_groups {
+            let usable = !group.is_empty()
+                && group
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+                && !group.contains("__")
+                && !group.contains("--");
+            if !usable {
+                problems.push(E::InvalidMergeGroup {
+                    name: name.clone(),
+                    tool: tool.clone(),
+                    group: group.clone(),
+                });
+            }
+        }
+        for (method, limit) in &self.notification_limits {
+            if ratelimit::PRIORITY_METHODS.contains(&method.as_str()) {
+                problems.push(E::PriorityNotificationLimit {
+                    name: name.clone(),
+                    method: method.clone(),
+                });
+            }
+            if *limit == Some(0) {
+                problems.push(E::ZeroNotificationLimit {
+                    name: name.clone(),
+                    method: method.clone(),
+                });
+            }
+        }
+        if self.title.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            problems.push(E::EmptyTitle { name: name.clone() });
+        }
+        if self
+            .client_name
+            .as_ref()
+            .is_some_and(|n| n.trim().is_empty())
+        {
+            problems.push(E::EmptyClientName { name: name.clone() });
+        }
+        if let Some(Err(error)) = self.icon.as_deref().map(icons::check) {
+            problems.push(E::InvalidIcon {
+                name: name.clone(),
+                error,
+            });
+        }
+        problems
+    }
+}
+
+/// Assembles a `Tool` field by field; see `Tool::builder`
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ToolBuilder {
+    tool: Tool,
+}
+
+impl ToolBuilder {
+    /// The program and its arguments
+    pub fn command<I, S>(mut self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tool.command = argv.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Add one argument to the command
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.tool.command.push(arg.into());
+        self
+    }
+
+    /// Set an environment variable for the server
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tool.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Pass an environment variable on from mcpd's environment, read each
+    /// time the server starts
+    pub fn dynamic_env(mut self, key: impl Into<String>) -> Self {
+        self.tool.dynamic_env.push(key.into());
+        self
+    }
+
+    /// Set an environment variable to a value `secret_env::MasterKey::seal` sealed
+    pub fn secret_env(mut self, key: impl Into<String>, sealed: impl Into<String>) -> Self {
+        self.tool.secret_env.insert(key.into(), sealed.into());
+        self
+    }
+
+    /// Working directory for the server process
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.tool.cwd = Some(cwd.into());
+        self
+    }
+
+    /// Run in the client's first root directory when it reports one
+    pub fn cwd_from_root(mut self, on: bool) -> Self {
+        self.tool.cwd_from_root = on;
+        self
+    }
+
+    /// Restart by warming up a replacement before switching over
+    pub fn zero_downtime(mut self, on: bool) -> Self {
+        self.tool.zero_downtime = on;
+        self
+    }
+
+    /// jq expression applied to JSON tool results
+    pub fn transform(mut self, expression: impl Into<String>) -> Self {
+        self.tool.transform = Some(expression.into());
+        self
+    }
+
+    /// Pass the client's `_meta` on to the server's tool calls
+    pub fn forward_trace_context(mut self, on: bool) -> Self {
+        self.tool.forward_trace_context = on;
+        self
+    }
+
+    /// Admission priority under `--max-concurrent-calls`
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.tool.priority = priority;
+        self
+    }
+
+    /// Command prefix to run the server under; empty opts out of the serve-wide default
+    pub fn wrapper<I, S>(mut self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tool.wrapper = Some(argv.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// How the server delimits messages on stdio
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.tool.framing = framing;
+        self
+    }
+
+    /// Command run on each call's arguments before the call
+    pub fn pre_call<I, S>(mut self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tool.pre_call = Some(argv.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Command run on each call's result after the call
+    pub fn post_call<I, S>(mut self, argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tool.post_call = Some(argv.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Lowest level of the server's log messages passed on to clients
+    pub fn log_level(mut self, level: LoggingLevel) -> Self {
+        self.tool.log_level = level;
+        self
+    }
+
+    /// Send the server one tool call at a time
+    pub fn singleton(mut self, on: bool) -> Self {
+        self.tool.singleton = on;
+        self
+    }
+
+    /// Send the server one request at a time instead of pipelining them
+    pub fn no_pipelining(mut self, on: bool) -> Self {
+        self.tool.no_pipelining = on;
+        self
+    }
+
+    /// Tool call made on an interval to check the server can do real work
+    pub fn health_check(mut self, check: HealthCheck) -> Self {
+        self.tool.health_check = Some(check);
+        self
+    }
+
+    /// Stop the server when an abandoned call isn't answered in time
+    pub fn kill_on_abandoned(mut self, on: bool) -> Self {
+        self.tool.kill_on_abandoned = on;
+        self
+    }
+
+    /// Server to send calls to when this one can't be reached
+    pub fn fallback(mut self, name: impl Into<String>) -> Self {
+        self.tool.fallback = Some(name.into());
+        self
+    }
+
+    /// Bytes buffered per read of the server's stdout
+    pub fn read_buffer_size(mut self, bytes: usize) -> Self {
+        self.tool.read_buffer_size = Some(bytes);
+        self
+    }
+
+    /// Largest serialized call arguments forwarded to the server
+    pub fn max_arg_bytes(mut self, bytes: usize) -> Self {
+        self.tool.max_arg_bytes = Some(bytes);
+        self
+    }
+
+    /// Processes to spread tool calls across
+    pub fn replicas(mut self, count: u32) -> Self {
+        self.tool.replicas = Some(count);
+        self
+    }
+
+    /// Argument whose value picks the replica a call goes to
+    pub fn shard_by(mut self, argument: impl Into<String>) -> Self {
+        self.tool.shard_by = Some(argument.into());
+        self
+    }
+
+    /// How many calls to the server's `tool` run at once
+    pub fn tool_concurrency(mut self, tool: impl Into<String>, limits: ToolConcurrency) -> Self {
+        self.tool.tool_concurrency.insert(tool.into(), limits);
+        self
+    }
+
+    /// List the server's `tool` once with others of `group`, calling them all
+    pub fn merge_group(mut self, tool: impl Into<String>, group: impl Into<String>) -> Self {
+        self.tool.merge_groups.insert(tool.into(), group.into());
+        self
+    }
+
+    /// Notifications of `method` relayed a second, `None` for no limit
+    pub fn notification_limit(mut self, method: impl Into<String>, limit: Option<u32>) -> Self {
+        self.tool.notification_limits.insert(method.into(), limit);
+        self
+    }
+
+    /// Name clients show for the server
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.tool.title = Some(title.into());
+        self
+    }
+
+    /// The server's icon, a `data:` URI (`icons::embed` makes one from a file)
+    pub fn icon(mut self, icon: impl Into<String>) -> Self {
+        self.tool.icon = Some(icon.into());
+        self
+    }
+
+    /// The `clientInfo` mcpd introduces itself to the server with
+    pub fn client_info(mut self, name: impl Into<String>, version: impl Into<String>) -> Self {
+        self.tool.client_name = Some(name.into());
+        self.tool.client_version = Some(version.into());
+        self
+    }
+
+    /// The tool, if it passes `Tool::validate`
+    pub fn build(self) -> Result<Tool, ToolValidationError> {
+        self.tool.validate()?;
+        Ok(self.tool)
+    }
+}
+
+impl Tool {
+    /// The argv actually spawned: the tool's command under its wrapper (or
+    /// `default_wrapper`). In the wrapper, an argument that is exactly
+    /// `{command}` is replaced by the tool's command; without one the command
+    /// is appended. `{name}` and `{cwd}` are expanded inside wrapper arguments.
+    ///
+    /// Returned as OS strings so a working directory that isn't valid UTF-8
+    /// is passed through intact.
+    pub fn wrapped_command(
+        &self,
+        default_wrapper: Option<&[String]>,
+        cwd: Option<&Path>,
+    ) -> Vec<OsString> {
+        let Some(wrapper) = self
+            .wrapper
+            .as_deref()
+            .or(default_wrapper)
+            .filter(|w| !w.is_empty())
+        else {
+            return self.command.iter().map(OsString::from).collect();
+        };
+        let cwd = cwd
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        // Placeholders are found in the (UTF-8) wrapper text; the path is
+        // spliced in as an OS string
+        let expand = |arg: &String| {
+            let mut out = OsString::new();
+            for (i, piece) in arg.replace("{name}", &self.name).split("{cwd}").enumerate() {
+                if i > 0 {
+                    out.push(cwd.as_os_str());
+                }
+                out.push(piece);
+            }
+            out
+        };
+
+        let mut argv = Vec::new();
+        let mut embedded = false;
+        for arg in wrapper {
+            if arg == "{command}" {
+                argv.extend(self.command.iter().map(OsString::from));
+                embedded = true;
+            } else {
+                argv.push(expand(arg));
+            }
+        }
+        if !embedded {
+            argv.extend(self.command.iter().map(OsString::from));
+        }
+        argv
+    }
 }
 
 /// Registry file format
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct RegistryData {
+    /// Bumped by every write, so a reader can tell whether the file changed
+    /// since it last read or wrote it
+    #[serde(default)]
+    pub generation: u64,
+    /// In the order `list_tools` and `mcpd list` show them
     #[serde(default)]
-    pub tools: HashMap<String, Tool>,
+    pub tools: IndexMap<String, Tool>,
+}
+
+/// Where `Registry::reorder` moves a tool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    Top,
+    Bottom,
+    Before(String),
+    After(String),
 }
 
-/// Tool registry with JSON file persistence
+/// Tool registry with JSON file persistence.
+///
+/// Several processes may share the file: a running server and CLI commands.
+/// Every change is a read-modify-write under an exclusive lock on
+/// `<registry>.lock`, so a change made by another process since this one
+/// loaded the file is kept rather than overwritten. The file is replaced by
+/// rename, so readers never see it half-written.
+///
+/// A registry made with `in_memory` has no file: changes stay in this
+/// process and `reload` never finds any.
 pub struct Registry {
-    path: PathBuf,
+    path: Option<PathBuf>,
     data: RegistryData,
 }
 
@@ -34,25 +852,71 @@ impl Registry {
         Self::load_from(path)
     }
 
-    /// Load registry from a specific path
+    /// Load registry from a specific path. Tools registered before ids
+    /// existed get one, saved right away so it stays the same.
     pub fn load_from(path: PathBuf) -> Result<Self> {
-        let data = if path.exists() {
-            let content = std::fs::read_to_string(&path)
-                .with_context(|| format!("Failed to read registry from {}", path.display()))?;
-            serde_json::from_str(&content)
-                .with_context(|| format!("Failed to parse registry from {}", path.display()))?
-        } else {
-            RegistryData::default()
+        let data = read_data(&path)?;
+        let mut registry = Self {
+            path: Some(path),
+            data,
         };
+        if registry.list().any(|tool| tool.id.is_none())
+            && let Err(e) = registry.update(|_| Ok(false))
+        {
+            tracing::warn!(
+                "Failed to save ids for registered tools, they'll change on every load: {:#}",
+                e
+            );
+            assign_ids(&mut registry.data.tools);
+        }
+        Ok(registry)
+    }
 
-        Ok(Self { path, data })
+    /// A registry held only in memory
+    pub fn in_memory(mut data: RegistryData) -> Self {
+        assign_ids(&mut data.tools);
+        Self { path: None, data }
+    }
+
+    /// An in-memory registry from a registry document, as given to `serve
+    /// --registry-json`. Tools must pass `Tool::validate` and be keyed by
+    /// their own names.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let data: RegistryData = serde_json::from_str(json).context("Failed to parse registry")?;
+        for (key, tool) in &data.tools {
+            if *key != tool.name {
+                bail!(
+                    "Registry entry '{}' is for a tool named '{}'",
+                    key,
+                    tool.name
+                );
+            }
+            tool.validate()?;
+        }
+        Ok(Self::in_memory(data))
+    }
+
+    /// The config directory the default registry lives in, without
+    /// creating it
+    pub fn default_config_dir() -> PathBuf {
+        config_dir(|key| std::env::var_os(key), dirs::config_dir()).0
     }
 
     /// Get the default registry path
     pub fn default_path() -> Result<PathBuf> {
-        let config_dir = dirs::config_dir()
-            .context("Could not determine config directory")?
-            .join("mcpd");
+        let (config_dir, source) = config_dir(|key| std::env::var_os(key), dirs::config_dir());
+        if source == ConfigDirSource::WorkingDir {
+            tracing::warn!(
+                "No config directory available (set MCPD_CONFIG_DIR or HOME), using {}",
+                config_dir.display()
+            );
+        } else {
+            tracing::debug!(
+                "Using config directory {} ({})",
+                config_dir.display(),
+                source
+            );
+        }
 
         std::fs::create_dir_all(&config_dir).with_context(|| {
             format!(
@@ -64,34 +928,122 @@ impl Registry {
         Ok(config_dir.join("registry.json"))
     }
 
-    /// Save registry to disk
-    pub fn save(&self) -> Result<()> {
-        let content = serde_json::to_string_pretty(&self.data)?;
-        std::fs::write(&self.path, content)
-            .with_context(|| format!("Failed to write registry to {}", self.path.display()))?;
-        Ok(())
+    /// Path of the backing registry file, `None` for an in-memory registry
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Apply `change` to the tools on disk and save them, unless `change`
+    /// returns false and every tool already has an id. The file is re-read
+    /// under the registry lock first, so changes other processes made since
+    /// this registry was loaded survive.
+    fn update(
+        &mut self,
+        change: impl FnOnce(&mut IndexMap<String, Tool>) -> Result<bool>,
+    ) -> Result<bool> {
+        let Some(path) = &self.path else {
+            let changed = change(&mut self.data.tools)?;
+            let assigned = assign_ids(&mut self.data.tools) > 0;
+            if changed || assigned {
+                self.data.generation += 1;
+            }
+            return Ok(changed);
+        };
+        let _lock = FileLock::acquire(&lock_path(path))?;
+        let mut data = read_data(path)?;
+        let changed = change(&mut data.tools)?;
+        let assigned = assign_ids(&mut data.tools);
+        if assigned > 0 {
+            tracing::info!(count = assigned, "Assigned ids to registered tools");
+        }
+        if changed || assigned > 0 {
+            data.generation += 1;
+            write_data(path, &data)?;
+        }
+        self.data = data;
+        Ok(changed)
     }
 
-    /// Register a new tool
-    pub fn register(&mut self, tool: Tool) -> Result<()> {
-        self.data.tools.insert(tool.name.clone(), tool);
-        self.save()
+    /// Register a new tool at the end, or replace one in place, keeping its
+    /// id. Tools that fail `Tool::validate` are refused.
+    pub fn register(&mut self, mut tool: Tool) -> Result<()> {
+        tool.validate()?;
+        self.update(|tools| {
+            if tool.id.is_none() {
+                tool.id = tools.get(&tool.name).and_then(|existing| existing.id);
+            }
+            tools.insert(tool.name.clone(), tool);
+            Ok(true)
+        })?;
+        Ok(())
     }
 
     /// Unregister a tool by name
     pub fn unregister(&mut self, name: &str) -> Result<bool> {
-        let removed = self.data.tools.remove(name).is_some();
-        if removed {
-            self.save()?;
-        }
-        Ok(removed)
+        self.update(|tools| Ok(tools.shift_remove(name).is_some()))
+    }
+
+    /// Give the tool `from` the name `to`, keeping its id and position
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        self.update(|tools| {
+            let Some(index) = tools.get_index_of(from) else {
+                bail!("Tool '{}' not found", from);
+            };
+            if from == to {
+                return Ok(false);
+            }
+            if tools.contains_key(to) {
+                bail!("Tool '{}' is already registered", to);
+            }
+            let mut tool = tools[index].clone();
+            tool.name = to.to_string();
+            tool.validate()?;
+            tools.shift_remove_index(index);
+            tools.shift_insert(index, tool.name.clone(), tool);
+            // Servers falling back to this one follow it to its new name
+            for other in tools.values_mut() {
+                if other.fallback.as_deref() == Some(from) {
+                    other.fallback = Some(to.to_string());
+                }
+            }
+            Ok(true)
+        })?;
+        Ok(())
     }
 
-    /// List all registered tools
+    /// Apply `change` to every tool and save, all under one lock. Returns
+    /// how many tools `change` changed; if it fails for any, none are saved.
+    pub fn update_each(
+        &mut self,
+        mut change: impl FnMut(&mut Tool) -> Result<bool>,
+    ) -> Result<usize> {
+        let mut changed = 0;
+        self.update(|tools| {
+            for tool in tools.values_mut() {
+                if change(tool)? {
+                    tool.validate()?;
+                    changed += 1;
+                }
+            }
+            Ok(changed > 0)
+        })?;
+        Ok(changed)
+    }
+
+    /// List all registered tools, in registry order
     pub fn list(&self) -> impl Iterator<Item = &Tool> {
         self.data.tools.values()
     }
 
+    /// Move a tool to a new position and save
+    pub fn reorder(&mut self, name: &str, position: &Position) -> Result<()> {
+        self.update(|tools| {
+            move_tool(tools, name, position)?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
     /// Number of registered tools
     pub fn len(&self) -> usize {
         self.data.tools.len()
@@ -102,18 +1054,25 @@ impl Registry {
         self.data.tools.is_empty()
     }
 
-    /// Reload registry from disk. Returns the set of current tool names.
-    pub fn reload(&mut self) -> Result<()> {
-        let data = if self.path.exists() {
-            let content = std::fs::read_to_string(&self.path)
-                .with_context(|| format!("Failed to read registry from {}", self.path.display()))?;
-            serde_json::from_str(&content)
-                .with_context(|| format!("Failed to parse registry from {}", self.path.display()))?
-        } else {
-            RegistryData::default()
+    /// Reload registry from disk. Returns whether it changed since this
+    /// registry last read or wrote it: another writer bumped the generation,
+    /// or the file was edited by hand.
+    pub fn reload(&mut self) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
         };
+        let data = read_data(path)?;
+        if data.generation == self.data.generation && data.tools == self.data.tools {
+            return Ok(false);
+        }
         self.data = data;
-        Ok(())
+        Ok(true)
+    }
+
+    /// How many times the file had been written when this registry last
+    /// read or wrote it
+    pub fn generation(&self) -> u64 {
+        self.data.generation
     }
 
     /// Get the set of registered tool names
@@ -122,6 +1081,143 @@ impl Registry {
     }
 }
 
+/// Give each tool without an id a new one. Returns how many got one.
+fn assign_ids(tools: &mut IndexMap<String, Tool>) -> usize {
+    let mut assigned = 0;
+    for tool in tools.values_mut().filter(|tool| tool.id.is_none()) {
+        tool.id = Some(Uuid::new_v4());
+        assigned += 1;
+    }
+    assigned
+}
+
+/// Move `name` within `tools` as `position` says
+fn move_tool(tools: &mut IndexMap<String, Tool>, name: &str, position: &Position) -> Result<()> {
+    let Some(from) = tools.get_index_of(name) else {
+        bail!("Tool '{}' not found", name);
+    };
+    let anchor = |other: &str| {
+        if other == name {
+            bail!("Can't move '{}' relative to itself", name);
+        }
+        tools
+            .get_index_of(other)
+            .ok_or_else(|| anyhow::anyhow!("Tool '{}' not found", other))
+    };
+    let last = tools.len() - 1;
+    // `move_index` shifts the tools in between, so an anchor after `from`
+    // ends up one place earlier
+    let to = match position {
+        Position::Top => 0,
+        Position::Bottom => last,
+        Position::Before(other) => {
+            let at = anchor(other)?;
+            if at > from { at - 1 } else { at }
+        }
+        Position::After(other) => {
+            let at = anchor(other)?;
+            if at > from { at } else { at + 1 }
+        }
+    };
+    tools.move_index(from, to);
+    Ok(())
+}
+
+fn read_data(path: &Path) -> Result<RegistryData> {
+    if !path.exists() {
+        return Ok(RegistryData::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read registry from {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse registry from {}", path.display()))
+}
+
+/// Write the registry to a temporary file next to it, then rename it into place
+fn write_data(path: &Path, data: &RegistryData) -> Result<()> {
+    let content = serde_json::to_string_pretty(data)?;
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, content)
+        .and_then(|()| std::fs::rename(&temp, path))
+        .with_context(|| format!("Failed to write registry to {}", path.display()))
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    path.with_extension("json.lock")
+}
+
+/// An exclusive advisory lock on a file, held until dropped. Processes that
+/// don't take it aren't kept out.
+struct FileLock(std::fs::File);
+
+impl FileLock {
+    fn acquire(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        file.lock()
+            .with_context(|| format!("Failed to lock {}", path.display()))?;
+        Ok(Self(file))
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+/// Where the config directory came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigDirSource {
+    /// `MCPD_CONFIG_DIR`, used as is
+    Env,
+    /// `$XDG_CONFIG_HOME/mcpd`
+    XdgConfigHome,
+    /// The platform config directory (`dirs::config_dir()`) plus `mcpd`
+    Platform,
+    /// `./.mcpd`, when nothing else is available
+    WorkingDir,
+}
+
+impl std::fmt::Display for ConfigDirSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConfigDirSource::Env => "from MCPD_CONFIG_DIR",
+            ConfigDirSource::XdgConfigHome => "from XDG_CONFIG_HOME",
+            ConfigDirSource::Platform => "platform default",
+            ConfigDirSource::WorkingDir => "fallback",
+        })
+    }
+}
+
+/// Pick the config directory: `MCPD_CONFIG_DIR`, then `XDG_CONFIG_HOME`, then
+/// the platform directory, then `./.mcpd`. Empty variables are ignored, as is a
+/// relative `XDG_CONFIG_HOME` (the XDG spec says to).
+fn config_dir(
+    env: impl Fn(&str) -> Option<std::ffi::OsString>,
+    platform: Option<PathBuf>,
+) -> (PathBuf, ConfigDirSource) {
+    let var = |key| env(key).filter(|v| !v.is_empty()).map(PathBuf::from);
+    if let Some(dir) = var("MCPD_CONFIG_DIR") {
+        return (dir, ConfigDirSource::Env);
+    }
+    if let Some(dir) = var("XDG_CONFIG_HOME").filter(|d| d.is_absolute()) {
+        return (dir.join("mcpd"), ConfigDirSource::XdgConfigHome);
+    }
+    if let Some(dir) = platform {
+        return (dir.join("mcpd"), ConfigDirSource::Platform);
+    }
+    (PathBuf::from(".mcpd"), ConfigDirSource::WorkingDir)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,10 +1234,22 @@ mod tests {
         Tool {
             name: name.to_string(),
             command: vec!["/usr/bin/echo".to_string(), "hello".to_string()],
-            env: HashMap::new(),
+            ..Default::default()
         }
     }
 
+    #[test]
+    fn debug_output_masks_secret_env() {
+        let mut tool = sample_tool("api");
+        tool.env
+            .insert("API_KEY".to_string(), "sk-live-123".to_string());
+        tool.env.insert("REGION".to_string(), "eu".to_string());
+        let debug = format!("{:?}", tool);
+        assert!(debug.contains(r#""API_KEY": "****""#), "{}", debug);
+        assert!(debug.contains(r#""REGION": "eu""#), "{}", debug);
+        assert!(!debug.contains("sk-live"));
+    }
+
     #[test]
     fn empty_registry() {
         let (reg, _dir) = temp_registry();
@@ -159,6 +1267,122 @@ mod tests {
         assert_eq!(tools[0].name, "test");
     }
 
+    #[test]
+    fn tools_from_before_ids_get_one_once() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("registry.json");
+        std::fs::write(
+            &path,
+            r#"{"tools": {
+                "fs": {"name": "fs", "command": ["fs-server"]},
+                "git": {"name": "git", "command": ["git-server"]}
+            }, "generation": 4}"#,
+        )
+        .unwrap();
+
+        let first = Registry::load_from(path.clone()).unwrap();
+        let ids: Vec<_> = first.list().map(|tool| tool.id.unwrap()).collect();
+        assert_ne!(ids[0], ids[1]);
+        assert_eq!(first.generation(), 5);
+        let saved = std::fs::read_to_string(&path).unwrap();
+
+        // Loading again finds nothing to do
+        let second = Registry::load_from(path.clone()).unwrap();
+        assert_eq!(
+            second
+                .list()
+                .map(|tool| tool.id.unwrap())
+                .collect::<Vec<_>>(),
+            ids
+        );
+        assert_eq!(second.generation(), 5);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), saved);
+    }
+
+    #[test]
+    fn edits_and_renames_keep_the_id() {
+        let (mut reg, _dir) = temp_registry();
+        for name in ["a", "b", "c"] {
+            reg.register(sample_tool(name)).unwrap();
+        }
+        let id = reg.list().nth(1).unwrap().id.unwrap();
+
+        let mut edited = sample_tool("b");
+        edited.command.push("--verbose".to_string());
+        reg.register(edited).unwrap();
+        reg.rename("b", "bee").unwrap();
+
+        let names: Vec<_> = reg.list().map(|tool| tool.name.as_str()).collect();
+        assert_eq!(names, ["a", "bee", "c"]);
+        let renamed = reg.list().nth(1).unwrap();
+        assert_eq!(renamed.id, Some(id));
+        assert_eq!(renamed.command.last().unwrap(), "--verbose");
+
+        // Registered again after being removed, it's a different server
+        reg.unregister("bee").unwrap();
+        reg.register(sample_tool("bee")).unwrap();
+        assert_ne!(reg.list().last().unwrap().id, Some(id));
+    }
+
+    #[test]
+    fn renaming_a_fallback_updates_servers_using_it() {
+        let (mut reg, _dir) = temp_registry();
+        reg.register(sample_tool("backup")).unwrap();
+        let mut primary = sample_tool("primary");
+        primary.fallback = Some("backup".to_string());
+        reg.register(primary).unwrap();
+
+        reg.rename("backup", "spare").unwrap();
+        let primary = reg.list().find(|tool| tool.name == "primary").unwrap();
+        assert_eq!(primary.fallback.as_deref(), Some("spare"));
+    }
+
+    #[test]
+    fn rename_refuses_missing_taken_and_invalid_names() {
+        let (mut reg, _dir) = temp_registry();
+        reg.register(sample_tool("a")).unwrap();
+        reg.register(sample_tool("b")).unwrap();
+        let generation = reg.generation();
+
+        let err = reg.rename("nope", "c").unwrap_err();
+        assert_eq!(err.to_string(), "Tool 'nope' not found");
+        let err = reg.rename("a", "b").unwrap_err();
+        assert_eq!(err.to_string(), "Tool 'b' is already registered");
+        assert!(reg.rename("a", "").is_err());
+        reg.rename("a", "a").unwrap();
+        assert_eq!(reg.generation(), generation);
+    }
+
+    #[test]
+    fn in_memory_registry_keeps_changes_to_itself() {
+        let json = r#"{"tools": {"a": {"name": "a", "command": ["/usr/bin/echo"]}}}"#;
+        let mut reg = Registry::from_json(json).unwrap();
+        assert_eq!(reg.path(), None);
+        reg.register(sample_tool("b")).unwrap();
+        reg.reorder("b", &Position::Top).unwrap();
+        assert!(!reg.reload().unwrap());
+        let names: Vec<_> = reg.list().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["b", "a"]);
+        assert_eq!(reg.generation(), 2);
+        assert!(reg.unregister("a").unwrap());
+        assert_eq!(reg.len(), 1);
+    }
+
+    #[test]
+    fn registry_json_is_checked() {
+        let misfiled = r#"{"tools": {"a": {"name": "b", "command": ["/usr/bin/echo"]}}}"#;
+        let err = Registry::from_json(misfiled).err().unwrap();
+        assert!(
+            err.to_string().contains("'a' is for a tool named 'b'"),
+            "{}",
+            err
+        );
+
+        let no_command = r#"{"tools": {"a": {"name": "a", "command": []}}}"#;
+        assert!(Registry::from_json(no_command).is_err());
+        assert!(Registry::from_json("not json").is_err());
+    }
+
     #[test]
     fn register_persists_to_disk() {
         let dir = TempDir::new().unwrap();
@@ -198,8 +1422,9 @@ mod tests {
         reg.register(sample_tool("original")).unwrap();
 
         let new_data = RegistryData {
+            generation: 0,
             tools: {
-                let mut m = HashMap::new();
+                let mut m = IndexMap::new();
                 m.insert("external".to_string(), sample_tool("external"));
                 m
             },
@@ -225,6 +1450,132 @@ mod tests {
         assert!(names.contains("b"));
     }
 
+    fn names_in_order(reg: &Registry) -> Vec<&str> {
+        reg.list().map(|t| t.name.as_str()).collect()
+    }
+
+    /// A saved registry holding a, b, c, d in that order
+    fn abcd() -> (Registry, TempDir) {
+        let (mut reg, dir) = temp_registry();
+        for name in ["a", "b", "c", "d"] {
+            reg.register(sample_tool(name)).unwrap();
+        }
+        (reg, dir)
+    }
+
+    #[test]
+    fn order_survives_reload_and_reregistration() {
+        let (mut reg, _dir) = abcd();
+        reg.register(sample_tool("b")).unwrap();
+        reg.unregister("c").unwrap();
+        reg.reload().unwrap();
+        assert_eq!(names_in_order(&reg), ["a", "b", "d"]);
+    }
+
+    #[test]
+    fn writers_keep_changes_made_since_they_loaded() {
+        let (mut server, dir) = abcd();
+        let path = dir.path().join("registry.json");
+        // Loaded before the other side's change
+        let mut cli = Registry::load_from(path.clone()).unwrap();
+        server.unregister("b").unwrap();
+        cli.register(sample_tool("e")).unwrap();
+        assert_eq!(names_in_order(&cli), ["a", "c", "d", "e"]);
+
+        let writers: Vec<_> = (0..16)
+            .map(|i| {
+                let mut registry = Registry::load_from(path.clone()).unwrap();
+                std::thread::spawn(move || {
+                    registry.register(sample_tool(&format!("t{}", i))).unwrap();
+                    if i == 0 {
+                        registry.unregister("a").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let on_disk = Registry::load_from(path).unwrap();
+        let mut names: Vec<_> = names_in_order(&on_disk);
+        names.sort();
+        let mut expected = vec!["c".to_string(), "d".to_string(), "e".to_string()];
+        expected.extend((0..16).map(|i| format!("t{}", i)));
+        expected.sort();
+        assert_eq!(names, expected);
+        assert_eq!(on_disk.generation(), 4 + 2 + 17);
+        // Only the registry and its lock file are left
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn reload_reports_only_other_writers_changes() {
+        let (mut reg, dir) = abcd();
+        let path = dir.path().join("registry.json");
+        assert!(!reg.reload().unwrap());
+
+        let mut other = Registry::load_from(path.clone()).unwrap();
+        other.unregister("a").unwrap();
+        assert!(reg.reload().unwrap());
+        assert_eq!(reg.generation(), other.generation());
+        assert!(!reg.reload().unwrap());
+
+        // A hand edit that leaves the generation alone still counts
+        let edited = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace("\"hello\"", "\"bye\"");
+        std::fs::write(&path, edited).unwrap();
+        assert!(reg.reload().unwrap());
+        assert_eq!(reg.list().next().unwrap().command[1], "bye");
+    }
+
+    #[test]
+    fn reorder_top_and_bottom() {
+        let (mut reg, _dir) = abcd();
+        reg.reorder("c", &Position::Top).unwrap();
+        assert_eq!(names_in_order(&reg), ["c", "a", "b", "d"]);
+        reg.reorder("a", &Position::Bottom).unwrap();
+        assert_eq!(names_in_order(&reg), ["c", "b", "d", "a"]);
+        reg.reload().unwrap();
+        assert_eq!(names_in_order(&reg), ["c", "b", "d", "a"]);
+    }
+
+    #[test]
+    fn reorder_before() {
+        let (mut reg, _dir) = abcd();
+        reg.reorder("d", &Position::Before("b".to_string()))
+            .unwrap();
+        assert_eq!(names_in_order(&reg), ["a", "d", "b", "c"]);
+        reg.reorder("a", &Position::Before("c".to_string()))
+            .unwrap();
+        assert_eq!(names_in_order(&reg), ["d", "b", "a", "c"]);
+    }
+
+    #[test]
+    fn reorder_after() {
+        let (mut reg, _dir) = abcd();
+        reg.reorder("a", &Position::After("c".to_string())).unwrap();
+        assert_eq!(names_in_order(&reg), ["b", "c", "a", "d"]);
+        reg.reorder("d", &Position::After("b".to_string())).unwrap();
+        assert_eq!(names_in_order(&reg), ["b", "d", "c", "a"]);
+    }
+
+    #[test]
+    fn reorder_rejects_unknown_names() {
+        let (mut reg, _dir) = abcd();
+        let err = reg.reorder("x", &Position::Top).unwrap_err();
+        assert!(err.to_string().contains("'x' not found"), "{}", err);
+        let err = reg
+            .reorder("a", &Position::After("x".to_string()))
+            .unwrap_err();
+        assert!(err.to_string().contains("'x' not found"), "{}", err);
+        assert!(
+            reg.reorder("a", &Position::Before("a".to_string()))
+                .is_err()
+        );
+        assert_eq!(names_in_order(&reg), ["a", "b", "c", "d"]);
+    }
+
     #[test]
     fn register_overwrites_existing() {
         let (mut reg, _dir) = temp_registry();
@@ -237,6 +1588,14 @@ mod tests {
         assert_eq!(tools[0].command, vec!["/usr/bin/true".to_string()]);
     }
 
+    #[test]
+    fn zero_downtime_defaults_off_and_is_omitted() {
+        let tool: Tool = serde_json::from_str(r#"{"name":"a","command":["x"]}"#).unwrap();
+        assert!(!tool.zero_downtime);
+        let json = serde_json::to_value(&tool).unwrap();
+        assert!(json.get("zero_downtime").is_none());
+    }
+
     #[test]
     fn tool_with_env_vars_persists() {
         let (mut reg, _dir) = temp_registry();
@@ -248,4 +1607,435 @@ mod tests {
         let tools: Vec<_> = reg.list().collect();
         assert_eq!(tools[0].env.get("API_KEY").unwrap(), "secret");
     }
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<std::ffi::OsString> + 'a {
+        move |key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.into())
+    }
+
+    #[test]
+    fn config_dir_env_override_wins() {
+        let vars = [
+            ("MCPD_CONFIG_DIR", "/srv/mcpd"),
+            ("XDG_CONFIG_HOME", "/xdg"),
+        ];
+        assert_eq!(
+            config_dir(env(&vars), Some(PathBuf::from("/home/me/.config"))),
+            (PathBuf::from("/srv/mcpd"), ConfigDirSource::Env)
+        );
+    }
+
+    #[test]
+    fn config_dir_xdg_then_platform() {
+        let platform = Some(PathBuf::from("/home/me/.config"));
+        assert_eq!(
+            config_dir(env(&[("XDG_CONFIG_HOME", "/xdg")]), platform.clone()),
+            (PathBuf::from("/xdg/mcpd"), ConfigDirSource::XdgConfigHome)
+        );
+        assert_eq!(
+            config_dir(env(&[]), platform),
+            (
+                PathBuf::from("/home/me/.config/mcpd"),
+                ConfigDirSource::Platform
+            )
+        );
+    }
+
+    #[test]
+    fn config_dir_ignores_empty_and_relative_vars() {
+        let vars = [("MCPD_CONFIG_DIR", ""), ("XDG_CONFIG_HOME", "relative/dir")];
+        assert_eq!(
+            config_dir(env(&vars), None),
+            (PathBuf::from(".mcpd"), ConfigDirSource::WorkingDir)
+        );
+    }
+
+    #[test]
+    fn validate_accepts_reasonable_names() {
+        for name in ["github", "my-server", "fs_local", "v1.2"] {
+            let tool = Tool {
+                name: name.to_string(),
+                command: vec!["srv".to_string()],
+                ..Default::default()
+            };
+            assert!(tool.validate().is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_names_and_commands() {
+        let tool = |name: &str, command: &[&str]| Tool {
+            name: name.to_string(),
+            command: command.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        for name in ["", "a__b", "_a", "a_", "a/b", "a b"] {
+            assert!(tool(name, &["srv"]).validate().is_err(), "{:?}", name);
+        }
+        assert!(tool("ok", &[]).validate().is_err());
+        assert!(tool("ok", &["srv", "a\0b"]).validate().is_err());
+        assert!(tool("ok", &[" "]).validate().is_err());
+    }
+
+    #[test]
+    fn builder_defaults_match_a_bare_tool() {
+        let tool = Tool::builder("fs")
+            .command(["srv", "--root"])
+            .build()
+            .unwrap();
+        assert_eq!(
+            tool,
+            Tool {
+                name: "fs".to_string(),
+                command: vec!["srv".to_string(), "--root".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn builder_sets_fields() {
+        let tool = Tool::builder("fs")
+            .command(["srv"])
+            .arg("--root")
+            .env("KEY", "val")
+            .cwd("/work")
+            .singleton(true)
+            .priority(Priority::High)
+            .wrapper(["bwrap", "--", "{command}", "{cwd}"])
+            .build()
+            .unwrap();
+        assert_eq!(tool.command, ["srv", "--root"]);
+        assert_eq!(tool.env["KEY"], "val");
+        assert_eq!(tool.cwd, Some(PathBuf::from("/work")));
+        assert!(tool.singleton);
+        assert_eq!(tool.priority, Priority::High);
+    }
+
+    #[test]
+    fn builder_reports_which_rule_failed() {
+        use ToolValidationError as E;
+        let name = || "ok".to_string();
+        let cases = [
+            (Tool::builder("").command(["srv"]), E::EmptyName),
+            (
+                Tool::builder("a/b").command(["srv"]),
+                E::InvalidNameChar {
+                    name: "a/b".to_string(),
+                    found: '/',
+                },
+            ),
+            (
+                Tool::builder("a__b").command(["srv"]),
+                E::ReservedUnderscore {
+                    name: "a__b".to_string(),
+                },
+            ),
+            (Tool::builder("ok"), E::NoCommand { name: name() }),
+            (
+                Tool::builder("ok").command([" "]),
+                E::EmptyProgram { name: name() },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .pre_call::<_, String>([]),
+                E::EmptyHook {
+                    name: name(),
+                    hook: "pre_call",
+                },
+            ),
+            (
+                Tool::builder("ok").command(["srv", "a\0b"]),
+                E::NulInCommand { name: name() },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).env("A=B", "x"),
+                E::InvalidEnvName {
+                    name: name(),
+                    key: "A=B".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).env("A", "x\0y"),
+                E::NulInEnvValue {
+                    name: name(),
+                    key: "A".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .wrapper(["sh", "-c", "exec {command}"]),
+                E::EmbeddedCommandPlaceholder {
+                    name: name(),
+                    arg: "exec {command}".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .wrapper(["jail", "--dir={workdir}"]),
+                E::UnknownPlaceholder {
+                    name: name(),
+                    arg: "--dir={workdir}".to_string(),
+                    placeholder: "workdir".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .health_check(HealthCheck {
+                        tool: "query".to_string(),
+                        arguments: serde_json::json!({}),
+                        interval_secs: 0,
+                        failure_threshold: 2,
+                        on_degraded: None,
+                    }),
+                E::InvalidHealthCheck {
+                    name: name(),
+                    problem: "an interval of 0",
+                },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).fallback("ok"),
+                E::SelfFallback { name: name() },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).read_buffer_size(0),
+                E::ZeroReadBuffer { name: name() },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).max_arg_bytes(0),
+                E::ZeroMaxArgBytes { name: name() },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).replicas(0),
+                E::ZeroReplicas { name: name() },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).tool_concurrency(
+                    "navigate",
+                    ToolConcurrency {
+                        max_concurrency: Some(0),
+                        ..Default::default()
+                    },
+                ),
+                E::ZeroToolConcurrency {
+                    name: name(),
+                    tool: "navigate".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).tool_concurrency(
+                    "navigate",
+                    ToolConcurrency {
+                        serialize: true,
+                        max_concurrency: Some(4),
+                        max_queue: None,
+                    },
+                ),
+                E::ConflictingToolConcurrency {
+                    name: name(),
+                    tool: "navigate".to_string(),
+                    max: 4,
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .merge_group("search", "web__search"),
+                E::InvalidMergeGroup {
+                    name: name(),
+                    tool: "search".to_string(),
+                    group: "web__search".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .notification_limit("notifications/message", Some(0)),
+                E::ZeroNotificationLimit {
+                    name: name(),
+                    method: "notifications/message".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .notification_limit("notifications/progress", None),
+                E::PriorityNotificationLimit {
+                    name: name(),
+                    method: "notifications/progress".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).dynamic_env("A=B"),
+                E::InvalidEnvName {
+                    name: name(),
+                    key: "A=B".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .env("TOKEN", "x")
+                    .dynamic_env("TOKEN"),
+                E::EnvSetTwice {
+                    name: name(),
+                    key: "TOKEN".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .secret_env("TOKEN", "sk-plain"),
+                E::SecretEnvNotEncrypted {
+                    name: name(),
+                    key: "TOKEN".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .env("TOKEN", "x")
+                    .secret_env("TOKEN", "mcpd:v1:0a1b2c3d:AAAA"),
+                E::SecretEnvSetTwice {
+                    name: name(),
+                    key: "TOKEN".to_string(),
+                },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).title(" "),
+                E::EmptyTitle { name: name() },
+            ),
+            (
+                Tool::builder("ok").command(["srv"]).client_info("", "1.0"),
+                E::EmptyClientName { name: name() },
+            ),
+            (
+                Tool::builder("ok")
+                    .command(["srv"])
+                    .icon("data:image/png;base64,aGVsbG8="),
+                E::InvalidIcon {
+                    name: name(),
+                    error: IconError::NotAnImage,
+                },
+            ),
+        ];
+        for (builder, expected) in cases {
+            assert_eq!(builder.build().unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn known_and_shell_placeholders_pass() {
+        for arg in [
+            "{command}",
+            "--bind={cwd}",
+            "{name}-{cwd}",
+            "${HOME}",
+            "{}",
+            "{{.Name}}",
+        ] {
+            let built = Tool::builder("ok")
+                .command(["srv"])
+                .wrapper(["w", arg])
+                .build();
+            assert!(built.is_ok(), "{:?}: {:?}", arg, built);
+        }
+    }
+
+    #[test]
+    fn register_refuses_invalid_tools() {
+        let (mut reg, _dir) = temp_registry();
+        let err = reg.register(sample_tool("a b")).unwrap_err();
+        assert!(err.to_string().contains("contains ' '"), "{}", err);
+        assert!(reg.is_empty());
+    }
+
+    fn wrapped(wrapper: Option<&[&str]>, default: Option<&[&str]>) -> Vec<OsString> {
+        let owned = |w: &[&str]| w.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let tool = Tool {
+            name: "fs".to_string(),
+            command: vec!["/bin/fs-server".to_string(), "--root".to_string()],
+            wrapper: wrapper.map(owned),
+            ..Default::default()
+        };
+        let default = default.map(owned);
+        tool.wrapped_command(default.as_deref(), Some(Path::new("/work")))
+    }
+
+    #[test]
+    fn wrapped_command_prepends_or_embeds() {
+        assert_eq!(wrapped(None, None), vec!["/bin/fs-server", "--root"]);
+        assert_eq!(
+            wrapped(Some(&["bwrap", "--tmpfs", "/tmp", "--"]), None),
+            vec!["bwrap", "--tmpfs", "/tmp", "--", "/bin/fs-server", "--root"]
+        );
+        assert_eq!(
+            wrapped(
+                Some(&[
+                    "nsjail",
+                    "--cwd",
+                    "{cwd}",
+                    "--",
+                    "{command}",
+                    "--log={name}.log"
+                ]),
+                None
+            ),
+            vec![
+                "nsjail",
+                "--cwd",
+                "/work",
+                "--",
+                "/bin/fs-server",
+                "--root",
+                "--log=fs.log"
+            ]
+        );
+    }
+
+    #[test]
+    fn wrapped_command_default_and_opt_out() {
+        assert_eq!(
+            wrapped(None, Some(&["firejail"])),
+            vec!["firejail", "/bin/fs-server", "--root"]
+        );
+        assert_eq!(
+            wrapped(Some(&[]), Some(&["firejail"])),
+            vec!["/bin/fs-server", "--root"]
+        );
+    }
+
+    #[test]
+    fn validate_rejects_unspawnable_env() {
+        for (key, value) in [("", "x"), ("A=B", "x"), ("A\0", "x"), ("A", "x\0y")] {
+            let tool = Tool {
+                name: "ok".to_string(),
+                command: vec!["srv".to_string()],
+                env: HashMap::from([(key.to_string(), value.to_string())]),
+                ..Default::default()
+            };
+            assert!(tool.validate().is_err(), "{:?}={:?}", key, value);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wrapped_command_keeps_non_utf8_cwd() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        let tool = Tool {
+            name: "fs".to_string(),
+            command: vec!["srv".to_string()],
+            wrapper: Some(vec!["jail".to_string(), "--dir={cwd}/{name}".to_string()]),
+            ..Default::default()
+        };
+        let cwd = Path::new(OsStr::from_bytes(b"/data/caf\xe9"));
+        let argv = tool.wrapped_command(None, Some(cwd));
+        assert_eq!(argv[1].clone().into_vec(), b"--dir=/data/caf\xe9/fs");
+        assert_eq!(argv[2], "srv");
+    }
 }
@@ -0,0 +1,183 @@
+//! Client roots - the directories an MCP client says it's working in. Used to
+//! pick the working directory of `cwd_from_root` backends.
+
+use crate::mcp::ListRootsResult;
+use std::path::PathBuf;
+
+/// Convert a `file://` root URI to a local path. Returns `None` for other
+/// schemes, remote hosts and malformed percent-encoding.
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let path = percent_decode(path)?;
+    local_path(host, &path)
+}
+
+#[cfg(not(windows))]
+fn local_path(host: &str, path: &str) -> Option<PathBuf> {
+    if !(host.is_empty() || host.eq_ignore_ascii_case("localhost")) || path.is_empty() {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+#[cfg(windows)]
+fn local_path(host: &str, path: &str) -> Option<PathBuf> {
+    if !(host.is_empty() || host.eq_ignore_ascii_case("localhost")) {
+        // file://server/share/dir is a UNC path
+        return Some(PathBuf::from(format!(
+            r"\\{}{}",
+            host,
+            path.replace('/', "\\")
+        )));
+    }
+    // file:///C:/Users/me -> C:\Users\me
+    let path = path.strip_prefix('/')?;
+    let bytes = path.as_bytes();
+    if bytes.len() < 2 || !bytes[0].is_ascii_alphabetic() || bytes[1] != b':' {
+        return None;
+    }
+    Some(PathBuf::from(path.replace('/', "\\")))
+}
+
+/// Decode `%XX` escapes. Fails on truncated escapes or if the result isn't UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let hex = std::str::from_utf8(hex).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Local directories from a `roots/list` result, in the client's order.
+/// Roots that aren't `file://` URIs or don't exist are skipped.
+pub fn existing_dirs(result: &ListRootsResult) -> Vec<PathBuf> {
+    result
+        .roots
+        .iter()
+        .filter_map(|root| uri_to_path(&root.uri))
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::Root;
+
+    #[test]
+    fn rejects_non_file_uris() {
+        assert_eq!(uri_to_path("https://example.com/x"), None);
+        assert_eq!(uri_to_path("/plain/path"), None);
+        assert_eq!(uri_to_path("file://"), None);
+    }
+
+    #[test]
+    fn rejects_bad_percent_encoding() {
+        assert_eq!(uri_to_path("file:///tmp/bad%2"), None);
+        assert_eq!(uri_to_path("file:///tmp/bad%zz"), None);
+        assert_eq!(uri_to_path("file:///tmp/%ff%fe"), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn unix_paths() {
+        assert_eq!(
+            uri_to_path("file:///home/me/project"),
+            Some(PathBuf::from("/home/me/project"))
+        );
+        assert_eq!(
+            uri_to_path("file://localhost/srv/app"),
+            Some(PathBuf::from("/srv/app"))
+        );
+        assert_eq!(uri_to_path("file://fileserver/share"), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn unix_percent_encoding_and_spaces() {
+        assert_eq!(
+            uri_to_path("file:///home/me/my%20project"),
+            Some(PathBuf::from("/home/me/my project"))
+        );
+        assert_eq!(
+            uri_to_path("file:///home/me/raw space"),
+            Some(PathBuf::from("/home/me/raw space"))
+        );
+        assert_eq!(
+            uri_to_path("file:///tmp/caf%C3%A9/100%25"),
+            Some(PathBuf::from("/tmp/café/100%"))
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_drive_letters() {
+        assert_eq!(
+            uri_to_path("file:///C:/Users/me/My%20Project"),
+            Some(PathBuf::from(r"C:\Users\me\My Project"))
+        );
+        assert_eq!(
+            uri_to_path("file:///c%3A/src"),
+            Some(PathBuf::from(r"c:\src"))
+        );
+        assert_eq!(uri_to_path("file:///no/drive"), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_unc() {
+        assert_eq!(
+            uri_to_path("file://server/share/dir"),
+            Some(PathBuf::from(r"\\server\share\dir"))
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn existing_dirs_filters_and_keeps_order() {
+        let a = tempfile::TempDir::new().unwrap();
+        let b = tempfile::TempDir::new().unwrap();
+        let uri = |p: &std::path::Path| format!("file://{}", p.display());
+        let result = ListRootsResult {
+            roots: vec![
+                Root {
+                    uri: "file:///definitely/not/here".to_string(),
+                    name: None,
+                },
+                Root {
+                    uri: uri(b.path()),
+                    name: Some("b".to_string()),
+                },
+                Root {
+                    uri: "https://example.com".to_string(),
+                    name: None,
+                },
+                Root {
+                    uri: uri(a.path()),
+                    name: None,
+                },
+            ],
+        };
+        assert_eq!(
+            existing_dirs(&result),
+            vec![b.path().to_path_buf(), a.path().to_path_buf()]
+        );
+    }
+}
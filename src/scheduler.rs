@@ -0,0 +1,364 @@
+//! Priority admission for tool calls under `--max-concurrent-calls`.
+//!
+//! When every slot is taken, waiting calls are admitted highest priority
+//! first, FIFO within a priority. A waiting call gains one priority level for
+//! every `aging` interval it has waited, so low-priority calls still run
+//! while high-priority traffic keeps arriving.
+
+use crate::activity::QueueSnapshot;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+/// Scheduling priority of a backend's calls
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub fn is_normal(&self) -> bool {
+        *self == Priority::Normal
+    }
+
+    fn level(self) -> u64 {
+        self as u64
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The priority asked for in a request's `_meta["mcpd/priority"]`, if any
+    pub fn from_meta(meta: Option<&serde_json::Value>) -> Option<Self> {
+        meta?
+            .get("mcpd/priority")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        })
+    }
+}
+
+struct Waiter {
+    seq: u64,
+    priority: Priority,
+    enqueued: Instant,
+    grant: oneshot::Sender<()>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct WaitStats {
+    admitted: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+struct State {
+    running: usize,
+    waiting: Vec<Waiter>,
+    next_seq: u64,
+    stats: [WaitStats; 3],
+}
+
+impl State {
+    fn record(&mut self, priority: Priority, wait: Duration) {
+        let stats = &mut self.stats[priority.index()];
+        stats.admitted += 1;
+        stats.total_wait += wait;
+        stats.max_wait = stats.max_wait.max(wait);
+    }
+}
+
+/// Limits concurrent calls, admitting waiters by (aged) priority
+pub struct Scheduler {
+    limit: usize,
+    aging: Duration,
+    state: Mutex<State>,
+}
+
+impl Scheduler {
+    /// Allow `limit` calls at once. Waiters move up a level every `aging`.
+    pub fn new(limit: usize, aging: Duration) -> Self {
+        Self {
+            limit: limit.max(1),
+            aging,
+            state: Mutex::new(State {
+                running: 0,
+                waiting: Vec::new(),
+                next_seq: 0,
+                stats: [WaitStats::default(); 3],
+            }),
+        }
+    }
+
+    /// Wait for a slot. The slot is released when the permit is dropped.
+    pub async fn acquire(&self, priority: Priority) -> Permit<'_> {
+        let grant = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.limit && state.waiting.is_empty() {
+                state.running += 1;
+                state.record(priority, Duration::ZERO);
+                return Permit { scheduler: self };
+            }
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                seq,
+                priority,
+                enqueued: Instant::now(),
+                grant: tx,
+            });
+            rx
+        };
+        let mut pending = Pending {
+            scheduler: self,
+            grant: Some(grant),
+        };
+        // The sender is only dropped after a send, so this can't fail
+        let _ = pending.grant.as_mut().unwrap().await;
+        pending.grant = None;
+        Permit { scheduler: self }
+    }
+
+    /// Hand a finished call's slot to the best waiter, or free it
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        while !state.waiting.is_empty() {
+            let best = state
+                .waiting
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, w)| (self.effective_level(w, now), std::cmp::Reverse(w.seq)))
+                .map(|(i, _)| i)
+                .unwrap();
+            let waiter = state.waiting.remove(best);
+            if waiter.grant.send(()).is_ok() {
+                state.record(waiter.priority, now - waiter.enqueued);
+                return;
+            }
+            // That caller gave up waiting; try the next one
+        }
+        state.running -= 1;
+    }
+
+    fn effective_level(&self, waiter: &Waiter, now: Instant) -> u64 {
+        let aged = if self.aging.is_zero() {
+            0
+        } else {
+            ((now - waiter.enqueued).as_nanos() / self.aging.as_nanos()) as u64
+        };
+        (waiter.priority.level() + aged).min(Priority::High.level())
+    }
+
+    /// Queue length and wait times per priority, highest first
+    pub fn snapshot(&self) -> Vec<QueueSnapshot> {
+        let state = self.state.lock().unwrap();
+        Priority::ALL
+            .iter()
+            .map(|&priority| {
+                let stats = state.stats[priority.index()];
+                QueueSnapshot {
+                    priority: priority.to_string(),
+                    waiting: state
+                        .waiting
+                        .iter()
+                        .filter(|w| w.priority == priority)
+                        .count(),
+                    admitted: stats.admitted,
+                    total_wait_ms: stats.total_wait.as_millis() as u64,
+                    max_wait_ms: stats.max_wait.as_millis() as u64,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A running call's slot
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// A caller still waiting for a slot. If it gives up (its future is dropped)
+/// after a slot was already handed to it, the slot is passed on.
+struct Pending<'a> {
+    scheduler: &'a Scheduler,
+    grant: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        if let Some(mut grant) = self.grant.take() {
+            grant.close();
+            if grant.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Start a call that records its label once admitted, then holds its
+    /// slot until `done` fires
+    fn spawn_call(
+        scheduler: &Arc<Scheduler>,
+        priority: Priority,
+        label: &'static str,
+        order: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> (tokio::task::JoinHandle<()>, oneshot::Sender<()>) {
+        let (done_tx, done_rx) = oneshot::channel::<()>();
+        let scheduler = Arc::clone(scheduler);
+        let order = Arc::clone(order);
+        let task = tokio::spawn(async move {
+            let _permit = scheduler.acquire(priority).await;
+            order.lock().unwrap().push(label);
+            let _ = done_rx.await;
+        });
+        (task, done_tx)
+    }
+
+    /// Let spawned tasks run until they block
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn admits_high_before_normal_before_low() {
+        let scheduler = Arc::new(Scheduler::new(1, Duration::from_secs(3600)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let (_busy, release_busy) = spawn_call(&scheduler, Priority::Low, "busy", &order);
+        settle().await;
+        let mut calls = Vec::new();
+        for (priority, label) in [
+            (Priority::Low, "low"),
+            (Priority::Normal, "normal-1"),
+            (Priority::High, "high"),
+            (Priority::Normal, "normal-2"),
+        ] {
+            calls.push(spawn_call(&scheduler, priority, label, &order));
+            settle().await;
+        }
+
+        // Finish calls one at a time; each finish admits exactly one waiter
+        release_busy.send(()).unwrap();
+        for (_, done) in calls {
+            settle().await;
+            let _ = done.send(());
+        }
+        settle().await;
+        assert_eq!(
+            *order.lock().unwrap(),
+            vec!["busy", "high", "normal-1", "normal-2", "low"]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn aging_lets_low_priority_through() {
+        let scheduler = Arc::new(Scheduler::new(1, Duration::from_secs(1)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let (_busy, release_busy) = spawn_call(&scheduler, Priority::High, "busy", &order);
+        settle().await;
+        let (_low, release_low) = spawn_call(&scheduler, Priority::Low, "low", &order);
+        settle().await;
+        // After two aging steps the low call ranks as high, and it has waited
+        // longer than the high call that arrives now
+        tokio::time::advance(Duration::from_secs(2)).await;
+        let (_high, release_high) = spawn_call(&scheduler, Priority::High, "high", &order);
+        settle().await;
+
+        release_busy.send(()).unwrap();
+        settle().await;
+        release_low.send(()).unwrap();
+        settle().await;
+        release_high.send(()).unwrap();
+        settle().await;
+        assert_eq!(*order.lock().unwrap(), vec!["busy", "low", "high"]);
+
+        let low = &scheduler.snapshot()[2];
+        assert_eq!(low.priority, "low");
+        assert_eq!(low.admitted, 1);
+        assert_eq!(low.max_wait_ms, 2000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abandoned_waiter_does_not_leak_slot() {
+        let scheduler = Arc::new(Scheduler::new(1, Duration::from_secs(60)));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let (_busy, release_busy) = spawn_call(&scheduler, Priority::Normal, "busy", &order);
+        settle().await;
+        let (gave_up, _) = spawn_call(&scheduler, Priority::High, "gave-up", &order);
+        settle().await;
+        gave_up.abort();
+        settle().await;
+        let (_next, _release_next) = spawn_call(&scheduler, Priority::Low, "next", &order);
+        settle().await;
+
+        release_busy.send(()).unwrap();
+        settle().await;
+        assert_eq!(*order.lock().unwrap(), vec!["busy", "next"]);
+    }
+
+    #[tokio::test]
+    async fn under_the_limit_calls_run_immediately() {
+        let scheduler = Scheduler::new(2, Duration::from_secs(1));
+        let _a = scheduler.acquire(Priority::Low).await;
+        let _b = scheduler.acquire(Priority::Low).await;
+        let snapshot = scheduler.snapshot();
+        assert_eq!(snapshot[2].admitted, 2);
+        assert_eq!(snapshot[2].total_wait_ms, 0);
+    }
+
+    #[test]
+    fn priority_from_meta() {
+        let meta = serde_json::json!({"mcpd/priority": "high"});
+        assert_eq!(Priority::from_meta(Some(&meta)), Some(Priority::High));
+        let meta = serde_json::json!({"mcpd/priority": "urgent"});
+        assert_eq!(Priority::from_meta(Some(&meta)), None);
+        assert_eq!(Priority::from_meta(None), None);
+    }
+}
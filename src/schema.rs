@@ -0,0 +1,566 @@
+//! Best-effort repair of tool input schemas that aren't valid JSON Schema.
+//!
+//! Some backends emit schemas with well-known mistakes that lenient clients
+//! shrug off and strict ones reject. [`sanitize`] fixes what it can:
+//!
+//! - type names that are common typos or aliases (`"str"`, `"int"`, `"dict"`, ...)
+//! - `required` given as a single string instead of an array
+//! - a missing top-level `"type": "object"` when `properties` are present
+//! - local `$ref`s that point nowhere, which are removed
+//!
+//! The result is checked against the JSON Schema meta-schema. A schema that
+//! still doesn't pass is replaced by a permissive `{"type": "object"}` with a
+//! description saying so, which keeps the tool callable.
+
+use serde_json::{Map, Value, json};
+
+/// Type names JSON Schema knows
+const TYPE_NAMES: &[&str] = &[
+    "string", "integer", "number", "boolean", "object", "array", "null",
+];
+
+/// Misspelled or borrowed type names, and what they mean. Matched case-insensitively.
+const TYPE_ALIASES: &[(&str, &str)] = &[
+    ("str", "string"),
+    ("text", "string"),
+    ("int", "integer"),
+    ("long", "integer"),
+    ("float", "number"),
+    ("double", "number"),
+    ("decimal", "number"),
+    ("bool", "boolean"),
+    ("dict", "object"),
+    ("map", "object"),
+    ("list", "array"),
+    ("tuple", "array"),
+    ("none", "null"),
+];
+
+/// Keywords whose value is a subschema
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "additionalItems",
+    "additionalProperties",
+    "contains",
+    "else",
+    "if",
+    "items",
+    "not",
+    "propertyNames",
+    "then",
+    "unevaluatedItems",
+    "unevaluatedProperties",
+];
+
+/// Keywords whose value is an array of subschemas
+const SCHEMA_ARRAY_KEYWORDS: &[&str] = &["allOf", "anyOf", "oneOf", "prefixItems", "items"];
+
+/// Keywords whose value maps names to subschemas
+const SCHEMA_MAP_KEYWORDS: &[&str] = &[
+    "$defs",
+    "definitions",
+    "dependentSchemas",
+    "patternProperties",
+    "properties",
+];
+
+/// One change made to a schema. Paths are JSON pointers into the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repair {
+    TypeName {
+        path: String,
+        from: String,
+        to: String,
+    },
+    RequiredString {
+        path: String,
+    },
+    MissingObjectType,
+    DanglingRef {
+        path: String,
+        reference: String,
+    },
+    /// The schema couldn't be repaired and was replaced by a permissive one
+    Replaced {
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Repair::TypeName { path, from, to } => {
+                write!(f, "type '{}' -> '{}' at {}", from, to, at(path))
+            }
+            Repair::RequiredString { path } => {
+                write!(f, "required string -> array at {}", at(path))
+            }
+            Repair::MissingObjectType => write!(f, "added top-level type 'object'"),
+            Repair::DanglingRef { path, reference } => {
+                write!(f, "removed dangling $ref '{}' at {}", reference, at(path))
+            }
+            Repair::Replaced { reason } => write!(f, "replaced invalid schema ({})", reason),
+        }
+    }
+}
+
+fn at(path: &str) -> &str {
+    if path.is_empty() { "/" } else { path }
+}
+
+/// A schema after [`sanitize`], with what was changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sanitized {
+    pub schema: Value,
+    pub repairs: Vec<Repair>,
+}
+
+/// Repair a tool's input schema. Valid schemas come back unchanged with no repairs.
+pub fn sanitize(schema: &Value) -> Sanitized {
+    let mut repairs = Vec::new();
+    let mut fixed = schema.clone();
+    if fixed.is_object() {
+        walk(&mut fixed, String::new(), &mut repairs);
+        prune_refs(schema, &mut fixed, String::new(), &mut repairs);
+        if let Value::Object(map) = &mut fixed
+            && map.contains_key("properties")
+            && !map.contains_key("type")
+        {
+            map.insert("type".to_string(), json!("object"));
+            repairs.push(Repair::MissingObjectType);
+        }
+    }
+
+    match check(&fixed) {
+        Ok(()) => Sanitized {
+            schema: fixed,
+            repairs,
+        },
+        Err(reason) => {
+            repairs.push(Repair::Replaced {
+                reason: reason.clone(),
+            });
+            Sanitized {
+                schema: permissive(&reason),
+                repairs,
+            }
+        }
+    }
+}
+
+/// Whether `schema` is acceptable as a tool input schema: an object that
+/// passes meta-schema validation
+pub fn check(schema: &Value) -> Result<(), String> {
+    if !schema.is_object() {
+        return Err(format!("expected an object schema, got {}", kind(schema)));
+    }
+    jsonschema::meta::validate(schema).map_err(|e| {
+        let path = e.instance_path().to_string();
+        format!("{} at {}", e, at(&path))
+    })
+}
+
+/// What's wrong with `arguments` as input to a tool with this input schema,
+/// one message per problem
+pub fn check_arguments(schema: &Value, arguments: &Value) -> Result<(), Vec<String>> {
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| vec![format!("the tool's input schema is unusable: {}", e)])?;
+    let problems: Vec<String> = validator
+        .iter_errors(arguments)
+        .map(|e| {
+            let path = e.instance_path().to_string();
+            format!("{} at {}", e, at(&path))
+        })
+        .collect();
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+/// Stand-in for a schema that couldn't be repaired
+fn permissive(reason: &str) -> Value {
+    json!({
+        "type": "object",
+        "description": format!(
+            "mcpd replaced this tool's input schema because it isn't valid JSON Schema ({}). Pass arguments as the tool's description explains.",
+            reason
+        ),
+    })
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Apply the type-name and required fixes to `schema` and its subschemas
+fn walk(schema: &mut Value, path: String, repairs: &mut Vec<Repair>) {
+    let Value::Object(map) = schema else {
+        return;
+    };
+    fix_types(map, &path, repairs);
+    if let Some(Value::String(name)) = map.get("required") {
+        let name = name.clone();
+        map.insert("required".to_string(), json!([name]));
+        repairs.push(Repair::RequiredString { path: path.clone() });
+    }
+
+    for_each_subschema(map, &path, &mut |sub, sub_path| {
+        walk(sub, sub_path, repairs)
+    });
+}
+
+fn fix_types(map: &mut Map<String, Value>, path: &str, repairs: &mut Vec<Repair>) {
+    let mut fix = |name: &mut String| {
+        if let Some(to) = canonical_type(name)
+            && to != name
+        {
+            repairs.push(Repair::TypeName {
+                path: path.to_string(),
+                from: std::mem::replace(name, to.to_string()),
+                to: to.to_string(),
+            });
+        }
+    };
+    match map.get_mut("type") {
+        Some(Value::String(name)) => fix(name),
+        Some(Value::Array(names)) => names.iter_mut().for_each(|n| {
+            if let Value::String(name) = n {
+                fix(name)
+            }
+        }),
+        _ => {}
+    }
+}
+
+/// The JSON Schema type `name` most likely means, if any
+fn canonical_type(name: &str) -> Option<&'static str> {
+    let lower = name.to_ascii_lowercase();
+    TYPE_NAMES
+        .iter()
+        .find(|t| **t == lower)
+        .or_else(|| {
+            TYPE_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == lower)
+                .map(|(_, t)| t)
+        })
+        .copied()
+}
+
+/// Remove local `$ref`s that don't resolve against `root`. Other refs are
+/// left alone, since resolving them would mean fetching.
+fn prune_refs(root: &Value, schema: &mut Value, path: String, repairs: &mut Vec<Repair>) {
+    let Value::Object(map) = schema else {
+        return;
+    };
+    if let Some(Value::String(reference)) = map.get("$ref")
+        && let Some(pointer) = reference.strip_prefix('#')
+        && pointer.starts_with('/')
+        && root.pointer(&decode_fragment(pointer)).is_none()
+    {
+        let reference = reference.clone();
+        map.remove("$ref");
+        repairs.push(Repair::DanglingRef {
+            path: path.clone(),
+            reference,
+        });
+    }
+
+    for_each_subschema(map, &path, &mut |sub, sub_path| {
+        prune_refs(root, sub, sub_path, repairs)
+    });
+}
+
+/// Undo percent-encoding in a URI fragment, which JSON pointers in `$ref` may use
+fn decode_fragment(fragment: &str) -> String {
+    let bytes = fragment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Call `f` on every direct subschema of `map`, with its JSON pointer
+fn for_each_subschema(
+    map: &mut Map<String, Value>,
+    path: &str,
+    f: &mut dyn FnMut(&mut Value, String),
+) {
+    let escape = |key: &str| key.replace('~', "~0").replace('/', "~1");
+    for (key, value) in map.iter_mut() {
+        let key_path = format!("{}/{}", path, escape(key));
+        let keyword = key.as_str();
+        match value {
+            Value::Object(_) if SCHEMA_KEYWORDS.contains(&keyword) => f(value, key_path),
+            Value::Array(items) if SCHEMA_ARRAY_KEYWORDS.contains(&keyword) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    f(item, format!("{}/{}", key_path, i));
+                }
+            }
+            Value::Object(entries) if SCHEMA_MAP_KEYWORDS.contains(&keyword) => {
+                for (name, sub) in entries.iter_mut() {
+                    f(sub, format!("{}/{}", key_path, escape(name)));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_schema_unchanged() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string"},
+                "depth": {"type": ["integer", "null"], "default": {"type": "str"}}
+            },
+            "required": ["path"],
+            "$defs": {"entry": {"type": "string"}},
+            "additionalProperties": {"$ref": "#/$defs/entry"}
+        });
+        let sanitized = sanitize(&schema);
+        assert_eq!(sanitized.repairs, []);
+        assert_eq!(sanitized.schema, schema);
+    }
+
+    #[test]
+    fn repair_rules() {
+        let table = [
+            (
+                json!({"type": "object", "properties": {"a": {"type": "str"}}}),
+                json!({"type": "object", "properties": {"a": {"type": "string"}}}),
+                vec![Repair::TypeName {
+                    path: "/properties/a".to_string(),
+                    from: "str".to_string(),
+                    to: "string".to_string(),
+                }],
+            ),
+            (
+                json!({"type": "object", "properties": {"a": {"type": ["Int", "null"]}}}),
+                json!({"type": "object", "properties": {"a": {"type": ["integer", "null"]}}}),
+                vec![Repair::TypeName {
+                    path: "/properties/a".to_string(),
+                    from: "Int".to_string(),
+                    to: "integer".to_string(),
+                }],
+            ),
+            (
+                json!({"type": "Object", "anyOf": [{"type": "dict"}]}),
+                json!({"type": "object", "anyOf": [{"type": "object"}]}),
+                vec![
+                    Repair::TypeName {
+                        path: String::new(),
+                        from: "Object".to_string(),
+                        to: "object".to_string(),
+                    },
+                    Repair::TypeName {
+                        path: "/anyOf/0".to_string(),
+                        from: "dict".to_string(),
+                        to: "object".to_string(),
+                    },
+                ],
+            ),
+            (
+                json!({"type": "object", "properties": {"a": {}}, "required": "a"}),
+                json!({"type": "object", "properties": {"a": {}}, "required": ["a"]}),
+                vec![Repair::RequiredString {
+                    path: String::new(),
+                }],
+            ),
+            (
+                json!({"properties": {"a": {"type": "string"}}}),
+                json!({"type": "object", "properties": {"a": {"type": "string"}}}),
+                vec![Repair::MissingObjectType],
+            ),
+            (
+                json!({"type": "object", "properties": {"a": {"$ref": "#/definitions/missing"}}}),
+                json!({"type": "object", "properties": {"a": {}}}),
+                vec![Repair::DanglingRef {
+                    path: "/properties/a".to_string(),
+                    reference: "#/definitions/missing".to_string(),
+                }],
+            ),
+            (
+                json!({"type": "object", "properties": {"a/b": {"$ref": "#/$defs/a%20b"}}, "$defs": {"a b": {}}}),
+                json!({"type": "object", "properties": {"a/b": {"$ref": "#/$defs/a%20b"}}, "$defs": {"a b": {}}}),
+                vec![],
+            ),
+            // Remote refs aren't fetched, so they're kept
+            (
+                json!({"type": "object", "properties": {"a": {"$ref": "https://example.com/a.json"}}}),
+                json!({"type": "object", "properties": {"a": {"$ref": "https://example.com/a.json"}}}),
+                vec![],
+            ),
+        ];
+        for (input, expected, repairs) in table {
+            let sanitized = sanitize(&input);
+            assert_eq!(sanitized.schema, expected, "{}", input);
+            assert_eq!(sanitized.repairs, repairs, "{}", input);
+        }
+    }
+
+    #[test]
+    fn unrepairable_schema_replaced() {
+        for schema in [
+            json!(null),
+            json!("object"),
+            json!({"type": "object", "properties": {"a": {"type": "thing"}}}),
+            json!({"type": "object", "minProperties": -1}),
+        ] {
+            let sanitized = sanitize(&schema);
+            assert!(
+                matches!(sanitized.repairs.last(), Some(Repair::Replaced { .. })),
+                "{}: {:?}",
+                schema,
+                sanitized.repairs
+            );
+            assert_eq!(sanitized.schema["type"], "object");
+            assert!(
+                sanitized.schema["description"]
+                    .as_str()
+                    .unwrap()
+                    .contains("isn't valid JSON Schema")
+            );
+        }
+    }
+
+    #[test]
+    fn repairs_read_well() {
+        let sanitized = sanitize(&json!({"properties": {"a": {"type": "str"}}, "required": "a"}));
+        let summary: Vec<String> = sanitized.repairs.iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            summary,
+            [
+                "required string -> array at /",
+                "type 'str' -> 'string' at /properties/a",
+                "added top-level type 'object'",
+            ]
+        );
+    }
+
+    #[test]
+    fn arguments_checked_against_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"path": {"type": "string"}, "lines": {"type": "integer"}},
+            "required": ["path"]
+        });
+        assert_eq!(check_arguments(&schema, &json!({"path": "a.txt"})), Ok(()));
+
+        let problems = check_arguments(&schema, &json!({"lines": "ten"})).unwrap_err();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.contains("\"path\" is a required property at /"))
+        );
+        assert!(problems.iter().any(|p| p.ends_with(" at /lines")));
+
+        let problems = check_arguments(&json!({"type": "wat"}), &json!({})).unwrap_err();
+        assert!(
+            problems[0].starts_with("the tool's input schema is unusable"),
+            "{:?}",
+            problems
+        );
+    }
+
+    /// Small deterministic PRNG so the property test is reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[self.below(items.len() as u64) as usize]
+        }
+    }
+
+    /// A schema-ish value mixing valid keywords, known mistakes and junk
+    fn random_schema(rng: &mut Rng, depth: u32) -> Value {
+        let types = [
+            "string", "str", "Int", "dict", "list", "thing", "null", "NUMBER",
+        ];
+        let mut map = Map::new();
+        for _ in 0..rng.below(5) {
+            let (key, value) = match rng.below(if depth == 0 { 6 } else { 10 }) {
+                0 => ("type", json!(rng.pick(&types))),
+                1 => ("type", json!([rng.pick(&types), rng.pick(&types)])),
+                2 => ("required", json!(format!("p{}", rng.below(3)))),
+                3 => ("required", json!([format!("p{}", rng.below(3))])),
+                4 => (
+                    "$ref",
+                    json!(*rng.pick(&["#/$defs/d0", "#/$defs/d1", "#/nowhere", "#"])),
+                ),
+                5 => (
+                    *rng.pick(&["minLength", "description", "enum", "default"]),
+                    json!(*rng.pick(&[json!(-1), json!(3), json!("x"), json!([1])])),
+                ),
+                6 => ("items", random_schema(rng, depth - 1)),
+                7 => ("anyOf", json!([random_schema(rng, depth - 1)])),
+                8 => ("$defs", json!({"d0": random_schema(rng, depth - 1)})),
+                _ => (
+                    "properties",
+                    json!({format!("p{}", rng.below(3)): random_schema(rng, depth - 1)}),
+                ),
+            };
+            map.insert(key.to_string(), value);
+        }
+        Value::Object(map)
+    }
+
+    #[test]
+    fn property_sanitized_schemas_pass_meta_validation() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        let mut repaired = 0;
+        for _ in 0..500 {
+            let schema = random_schema(&mut rng, 3);
+            let sanitized = sanitize(&schema);
+            assert_eq!(check(&sanitized.schema), Ok(()), "{}", schema);
+            if !sanitized.repairs.is_empty()
+                && !matches!(sanitized.repairs.last(), Some(Repair::Replaced { .. }))
+            {
+                repaired += 1;
+            }
+            // Sanitizing is idempotent
+            assert_eq!(sanitize(&sanitized.schema).repairs, [], "{}", schema);
+        }
+        assert!(repaired > 50, "{}", repaired);
+    }
+}
@@ -0,0 +1,700 @@
+//! Env values stored encrypted in the registry (`register --secret-env`).
+//!
+//! Values are sealed with ChaCha20-Poly1305 under a key derived (HKDF-SHA256)
+//! from a master secret, with the variable's name as associated data so a
+//! value can't be moved to another variable. A sealed value records the id
+//! of the key that sealed it: `mcpd:v1:<key id>:<base64 nonce+ciphertext>`.
+//!
+//! The master secret comes from the first of these that has one: the OS
+//! keychain (with the `keyring` feature), `$MCPD_MASTER_KEY`, or the key
+//! file `master.key` in the config directory. Registering a secret when none
+//! has one creates the key file (0600). Nothing looks for a key until a
+//! backend with sealed values starts (`open_all`, from `ToolProxy::start`),
+//! so a missing key only fails the servers that need it.
+
+use crate::registry::Registry;
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, AeadCore, OsRng, Payload, rand_core::RngCore};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::debug;
+
+/// Environment variable holding the master secret
+pub const MASTER_KEY_VAR: &str = "MCPD_MASTER_KEY";
+
+/// Environment variable `mcpd secrets rekey` reads the new secret from when
+/// the current one comes from `MASTER_KEY_VAR`
+pub const NEW_MASTER_KEY_VAR: &str = "MCPD_NEW_MASTER_KEY";
+
+/// Name of the key file in the config directory
+pub const KEY_FILE: &str = "master.key";
+
+/// What `mcpd list` shows for a sealed value
+pub const ENCRYPTED_MARKER: &str = "<encrypted>";
+
+const PREFIX: &str = "mcpd:v1:";
+const HKDF_SALT: &[u8] = b"mcpd secret env";
+const NONCE_LEN: usize = 12;
+
+/// Where a master key came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyOrigin {
+    Keychain,
+    Env,
+    File(PathBuf),
+}
+
+impl fmt::Display for KeyOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyOrigin::Keychain => write!(f, "the OS keychain"),
+            KeyOrigin::Env => write!(f, "${}", MASTER_KEY_VAR),
+            KeyOrigin::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The key sealed values are encrypted under
+pub struct MasterKey {
+    key: Key,
+    id: String,
+    origin: KeyOrigin,
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MasterKey")
+            .field("id", &self.id)
+            .field("origin", &self.origin)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MasterKey {
+    /// The key derived from a master secret
+    pub fn from_secret(secret: &str, origin: KeyOrigin) -> Result<Self> {
+        let secret = secret.trim();
+        if secret.is_empty() {
+            bail!("The master key from {} is empty", origin);
+        }
+        let mut key = Key::default();
+        hkdf::Hkdf::<Sha256>::new(Some(HKDF_SALT), secret.as_bytes())
+            .expand(b"v1", &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        let digest = Sha256::digest(key);
+        let id = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+        Ok(Self { key, id, origin })
+    }
+
+    /// A new random master secret
+    pub fn generate_secret() -> String {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        BASE64.encode(bytes)
+    }
+
+    /// Short fingerprint recorded in the values it seals
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn origin(&self) -> &KeyOrigin {
+        &self.origin
+    }
+
+    /// `value` of variable `var`, encrypted
+    pub fn seal(&self, var: &str, value: &str) -> String {
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: var.as_bytes(),
+        };
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            cipher
+                .encrypt(&nonce, payload)
+                .expect("encrypting into a Vec can't fail"),
+        );
+        format!("{}{}:{}", PREFIX, self.id, BASE64.encode(sealed))
+    }
+
+    /// The value `seal` sealed for `var`
+    pub fn open(&self, var: &str, sealed: &str) -> Result<String> {
+        let Some((id, data)) = parse(sealed) else {
+            bail!("{} isn't an encrypted value", var);
+        };
+        if id != self.id {
+            bail!(
+                "{} was encrypted with master key {}, but the key from {} is {}",
+                var,
+                id,
+                self.origin,
+                self.id
+            );
+        }
+        let data = BASE64
+            .decode(data)
+            .ok()
+            .filter(|data| data.len() > NONCE_LEN)
+            .with_context(|| format!("{} is corrupted", var))?;
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(&self.key);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: var.as_bytes(),
+        };
+        let plain = cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| anyhow::anyhow!("{} is corrupted or belongs to another variable", var))?;
+        String::from_utf8(plain).with_context(|| format!("{} isn't UTF-8", var))
+    }
+}
+
+/// The key id and data of a sealed value
+fn parse(sealed: &str) -> Option<(&str, &str)> {
+    sealed.strip_prefix(PREFIX)?.split_once(':')
+}
+
+/// Whether `value` looks like something `MasterKey::seal` made
+pub fn is_sealed(value: &str) -> bool {
+    parse(value).is_some()
+}
+
+/// An OS keychain entry holding the master secret. A trait so tests (and
+/// builds without the `keyring` feature) can stand in for the real one.
+pub trait Keychain: Send + Sync {
+    /// The stored secret, if there is one
+    fn get(&self) -> Result<Option<String>>;
+    /// Store `secret`, replacing any there
+    fn set(&self, secret: &str) -> Result<()>;
+}
+
+/// The `mcpd` entry in the platform's keychain
+#[cfg(feature = "keyring")]
+pub struct OsKeychain;
+
+#[cfg(feature = "keyring")]
+impl OsKeychain {
+    fn entry() -> Result<keyring::Entry> {
+        keyring::Entry::new("mcpd", "master-key").context("Failed to open the OS keychain")
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl Keychain for OsKeychain {
+    fn get(&self) -> Result<Option<String>> {
+        match Self::entry()?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e).context("Failed to read the OS keychain"),
+        }
+    }
+
+    fn set(&self, secret: &str) -> Result<()> {
+        Self::entry()?
+            .set_password(secret)
+            .context("Failed to write the OS keychain")
+    }
+}
+
+/// Where to look for the master key, in order
+#[derive(Clone, Default)]
+pub struct KeySources {
+    keychain: Option<Arc<dyn Keychain>>,
+    env: Option<String>,
+    key_file: Option<PathBuf>,
+}
+
+impl fmt::Debug for KeySources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeySources")
+            .field("keychain", &self.keychain.is_some())
+            .field("env", &self.env.is_some())
+            .field("key_file", &self.key_file)
+            .finish()
+    }
+}
+
+impl KeySources {
+    /// The keychain (with the `keyring` feature), `$MCPD_MASTER_KEY` and the
+    /// key file in `config_dir`
+    pub fn from_environment(config_dir: &Path) -> Self {
+        let sources = Self::default()
+            .with_env(std::env::var(MASTER_KEY_VAR).ok())
+            .with_key_file(config_dir.join(KEY_FILE));
+        #[cfg(feature = "keyring")]
+        let sources = sources.with_keychain(Arc::new(OsKeychain));
+        sources
+    }
+
+    pub fn with_keychain(mut self, keychain: Arc<dyn Keychain>) -> Self {
+        self.keychain = Some(keychain);
+        self
+    }
+
+    /// The value of `$MCPD_MASTER_KEY`, if set
+    pub fn with_env(mut self, secret: Option<String>) -> Self {
+        self.env = secret.filter(|s| !s.is_empty());
+        self
+    }
+
+    pub fn with_key_file(mut self, path: PathBuf) -> Self {
+        self.key_file = Some(path);
+        self
+    }
+
+    /// The master key from the first source that has one. A keychain that
+    /// can't be read (no secret service running, say) is passed over.
+    pub fn load(&self) -> Result<Option<MasterKey>> {
+        self.load_secret()?
+            .map(|(secret, origin)| MasterKey::from_secret(&secret, origin))
+            .transpose()
+    }
+
+    fn load_secret(&self) -> Result<Option<(String, KeyOrigin)>> {
+        if let Some(keychain) = &self.keychain {
+            match keychain.get() {
+                Ok(Some(secret)) => return Ok(Some((secret, KeyOrigin::Keychain))),
+                Ok(None) => {}
+                Err(e) => debug!(error = %format!("{:#}", e), "Skipping the OS keychain"),
+            }
+        }
+        if let Some(secret) = &self.env {
+            return Ok(Some((secret.clone(), KeyOrigin::Env)));
+        }
+        if let Some(path) = &self.key_file {
+            match std::fs::read_to_string(path) {
+                Ok(secret) => return Ok(Some((secret, KeyOrigin::File(path.clone())))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to read master key {}", path.display()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The master key, creating the key file when no source has one
+    pub fn load_or_create(&self) -> Result<MasterKey> {
+        if let Some(key) = self.load()? {
+            return Ok(key);
+        }
+        let Some(path) = &self.key_file else {
+            bail!("{}", self.missing());
+        };
+        let secret = MasterKey::generate_secret();
+        let origin = KeyOrigin::File(path.clone());
+        self.store(&origin, &secret)?;
+        tracing::info!(path = %path.display(), "Created master key");
+        MasterKey::from_secret(&secret, origin)
+    }
+
+    /// Replace the secret in the source `origin` names
+    pub fn store(&self, origin: &KeyOrigin, secret: &str) -> Result<()> {
+        match origin {
+            KeyOrigin::Keychain => match &self.keychain {
+                Some(keychain) => keychain.set(secret),
+                None => bail!("No OS keychain to store the master key in"),
+            },
+            KeyOrigin::Env => bail!(
+                "The master key comes from ${}; it can't be changed from here",
+                MASTER_KEY_VAR
+            ),
+            KeyOrigin::File(path) => write_key_file(path, secret),
+        }
+    }
+
+    /// Why no key was found, and what would provide one
+    fn missing(&self) -> String {
+        let mut places = Vec::new();
+        if self.keychain.is_some() {
+            places.push("store it in the OS keychain".to_string());
+        }
+        places.push(format!("set {}", MASTER_KEY_VAR));
+        if let Some(path) = &self.key_file {
+            places.push(format!("restore the key file {}", path.display()));
+        }
+        format!("no master key is available ({})", places.join(", or "))
+    }
+}
+
+/// Write a key file only its owner can read, replacing any there
+fn write_key_file(path: &Path, secret: &str) -> Result<()> {
+    use std::io::Write;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let temp = path.with_extension("key.tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&temp)
+        .with_context(|| format!("Failed to create {}", temp.display()))?;
+    writeln!(file, "{}", secret)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("Failed to write {}", temp.display()))?;
+    std::fs::rename(&temp, path)
+        .with_context(|| format!("Failed to write master key {}", path.display()))
+}
+
+/// The plain values of a tool's `secret_env`, for its child process. Looks
+/// for the master key only when there's something to open.
+pub fn open_all(
+    tool: &str,
+    sealed: &BTreeMap<String, String>,
+    sources: &KeySources,
+) -> Result<Vec<(String, String)>> {
+    if sealed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let names = sealed.keys().cloned().collect::<Vec<_>>().join(", ");
+    let Some(key) = sources.load()? else {
+        bail!(
+            "Tool '{}' has encrypted env values ({}), but {}",
+            tool,
+            names,
+            sources.missing()
+        );
+    };
+    sealed
+        .iter()
+        .map(|(var, value)| Ok((var.clone(), key.open(var, value)?)))
+        .collect::<Result<_>>()
+        .with_context(|| format!("Failed to decrypt env values of tool '{}'", tool))
+}
+
+/// `sealed` re-encrypted under `to`, having been sealed under `from`
+pub fn reseal(
+    sealed: &BTreeMap<String, String>,
+    from: &MasterKey,
+    to: &MasterKey,
+) -> Result<BTreeMap<String, String>> {
+    sealed
+        .iter()
+        .map(|(var, value)| Ok((var.clone(), to.seal(var, &from.open(var, value)?))))
+        .collect()
+}
+
+/// What `rekey` did
+#[derive(Debug)]
+pub struct Rekeyed {
+    /// The new key
+    pub key: MasterKey,
+    pub tools: usize,
+    pub values: usize,
+}
+
+/// Re-encrypt every sealed value in `registry` under a new master key,
+/// stored where the current one came from. A key from `$MCPD_MASTER_KEY`
+/// can't be replaced from here, so the new secret is `new_env_secret`
+/// (`$MCPD_NEW_MASTER_KEY`) instead. Every value is opened before anything
+/// changes, and the old key is put back if the registry can't be saved.
+pub fn rekey(
+    registry: &mut Registry,
+    sources: &KeySources,
+    new_env_secret: Option<String>,
+) -> Result<Rekeyed> {
+    let Some((old_secret, origin)) = sources.load_secret()? else {
+        bail!("Nothing to rekey: {}", sources.missing());
+    };
+    let old = MasterKey::from_secret(&old_secret, origin.clone())?;
+    let secret = match origin {
+        KeyOrigin::Env => new_env_secret.filter(|s| !s.is_empty()).with_context(|| {
+            format!(
+                "The master key comes from ${}; set ${} to the new key",
+                MASTER_KEY_VAR, NEW_MASTER_KEY_VAR
+            )
+        })?,
+        _ => MasterKey::generate_secret(),
+    };
+    let new = MasterKey::from_secret(&secret, origin.clone())?;
+    for tool in registry.list() {
+        reseal(&tool.secret_env, &old, &new)
+            .with_context(|| format!("Failed to decrypt env values of tool '{}'", tool.name))?;
+    }
+
+    let stores = origin != KeyOrigin::Env;
+    if stores {
+        sources.store(&origin, &secret)?;
+    }
+    let mut values = 0;
+    let resealed = registry.update_each(|tool| {
+        if tool.secret_env.is_empty() {
+            return Ok(false);
+        }
+        tool.secret_env = reseal(&tool.secret_env, &old, &new)?;
+        values += tool.secret_env.len();
+        Ok(true)
+    });
+    match resealed {
+        Ok(tools) => Ok(Rekeyed {
+            key: new,
+            tools,
+            values,
+        }),
+        Err(e) if stores => match sources.store(&origin, &old_secret) {
+            Ok(()) => Err(e.context("Failed to re-encrypt; the old master key is still in use")),
+            Err(restore) => Err(e.context(format!(
+                "Failed to re-encrypt, and to put the old master key back in {}: {:#}",
+                origin, restore
+            ))),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn key(secret: &str) -> MasterKey {
+        MasterKey::from_secret(secret, KeyOrigin::Env).unwrap()
+    }
+
+    /// A keychain in memory, counting reads
+    #[derive(Default)]
+    struct FakeKeychain {
+        secret: Mutex<Option<String>>,
+        broken: bool,
+        reads: Mutex<usize>,
+    }
+
+    impl Keychain for FakeKeychain {
+        fn get(&self) -> Result<Option<String>> {
+            *self.reads.lock().unwrap() += 1;
+            if self.broken {
+                bail!("no secret service");
+            }
+            Ok(self.secret.lock().unwrap().clone())
+        }
+
+        fn set(&self, secret: &str) -> Result<()> {
+            *self.secret.lock().unwrap() = Some(secret.to_string());
+            Ok(())
+        }
+    }
+
+    fn keychain(secret: Option<&str>) -> Arc<FakeKeychain> {
+        Arc::new(FakeKeychain {
+            secret: Mutex::new(secret.map(str::to_string)),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn values_round_trip_and_stay_with_their_variable() {
+        let key = key("correct horse");
+        let sealed = key.seal("API_KEY", "sk-123");
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("sk-123"));
+        assert!(sealed.starts_with(&format!("mcpd:v1:{}:", key.id())));
+        assert_ne!(key.seal("API_KEY", "sk-123"), sealed, "nonces differ");
+        assert_eq!(key.open("API_KEY", &sealed).unwrap(), "sk-123");
+
+        let moved = key.open("OTHER", &sealed).unwrap_err().to_string();
+        assert!(moved.contains("belongs to another variable"), "{}", moved);
+        let mut tampered = sealed.clone();
+        tampered.pop();
+        tampered.push(if sealed.ends_with('A') { 'B' } else { 'A' });
+        assert!(key.open("API_KEY", &tampered).is_err());
+        assert!(!is_sealed("sk-123"));
+    }
+
+    #[test]
+    fn the_wrong_key_is_named() {
+        let sealed = key("one").seal("API_KEY", "sk-123");
+        let other = key("two");
+        let error = other.open("API_KEY", &sealed).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!(
+                "API_KEY was encrypted with master key {}, but the key from $MCPD_MASTER_KEY is {}",
+                key("one").id(),
+                other.id()
+            )
+        );
+    }
+
+    #[test]
+    fn sources_are_tried_keychain_then_env_then_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(KEY_FILE);
+        std::fs::write(&path, "from-file\n").unwrap();
+        let all = |keychain: Arc<FakeKeychain>, env: Option<&str>| {
+            KeySources::default()
+                .with_keychain(keychain)
+                .with_env(env.map(str::to_string))
+                .with_key_file(path.clone())
+                .load()
+                .unwrap()
+                .unwrap()
+        };
+
+        let loaded = all(keychain(Some("from-keychain")), Some("from-env"));
+        assert_eq!(loaded.origin(), &KeyOrigin::Keychain);
+        assert_eq!(loaded.id(), key("from-keychain").id());
+
+        let loaded = all(keychain(None), Some("from-env"));
+        assert_eq!(loaded.origin(), &KeyOrigin::Env);
+        let broken = Arc::new(FakeKeychain {
+            broken: true,
+            ..Default::default()
+        });
+        assert_eq!(all(broken, Some("from-env")).origin(), &KeyOrigin::Env);
+
+        let loaded = all(keychain(None), None);
+        assert_eq!(loaded.origin(), &KeyOrigin::File(path.clone()));
+        // Surrounding whitespace isn't part of the secret
+        assert_eq!(loaded.id(), key("from-file").id());
+
+        std::fs::remove_file(&path).unwrap();
+        let none = KeySources::default()
+            .with_keychain(keychain(None))
+            .with_env(Some(String::new()))
+            .with_key_file(path);
+        assert!(none.load().unwrap().is_none());
+    }
+
+    #[test]
+    fn the_key_file_is_created_once_for_its_owner_only() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config").join(KEY_FILE);
+        let sources = KeySources::default().with_key_file(path.clone());
+        let created = sources.load_or_create().unwrap();
+        assert_eq!(created.origin(), &KeyOrigin::File(path.clone()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert_eq!(sources.load_or_create().unwrap().id(), created.id());
+    }
+
+    #[test]
+    fn a_missing_key_only_matters_with_something_to_open() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(KEY_FILE);
+        let keychain = keychain(None);
+        let sources = KeySources::default()
+            .with_keychain(keychain.clone())
+            .with_key_file(path.clone());
+        assert!(
+            open_all("plain", &BTreeMap::new(), &sources)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(*keychain.reads.lock().unwrap(), 0);
+
+        let sealed = BTreeMap::from([("API_KEY".to_string(), key("x").seal("API_KEY", "v"))]);
+        let error = format!("{:#}", open_all("api", &sealed, &sources).unwrap_err());
+        assert_eq!(
+            error,
+            format!(
+                "Tool 'api' has encrypted env values (API_KEY), but no master key is available (store it in the OS keychain, or set MCPD_MASTER_KEY, or restore the key file {})",
+                path.display()
+            )
+        );
+
+        let sources = sources.with_env(Some("x".to_string()));
+        assert_eq!(
+            open_all("api", &sealed, &sources).unwrap(),
+            [("API_KEY".to_string(), "v".to_string())]
+        );
+    }
+
+    #[test]
+    fn resealing_moves_values_to_the_new_key() {
+        let (old, new) = (key("old"), key("new"));
+        let sealed = BTreeMap::from([
+            ("A".to_string(), old.seal("A", "1")),
+            ("B".to_string(), old.seal("B", "2")),
+        ]);
+        let resealed = reseal(&sealed, &old, &new).unwrap();
+        assert_eq!(new.open("A", &resealed["A"]).unwrap(), "1");
+        assert_eq!(new.open("B", &resealed["B"]).unwrap(), "2");
+        assert!(old.open("A", &resealed["A"]).is_err());
+        // Nothing is resealed under the wrong starting key
+        assert!(reseal(&resealed, &old, &new).is_err());
+
+        let keychain = keychain(None);
+        let sources = KeySources::default().with_keychain(keychain.clone());
+        sources.store(&KeyOrigin::Keychain, "stored").unwrap();
+        assert_eq!(sources.load().unwrap().unwrap().id(), key("stored").id());
+        assert!(sources.store(&KeyOrigin::Env, "x").is_err());
+    }
+
+    #[test]
+    fn rekey_replaces_the_key_and_every_value() {
+        use crate::registry::Tool;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let key_file = dir.path().join(KEY_FILE);
+        let sources = KeySources::default().with_key_file(key_file.clone());
+        let old = sources.load_or_create().unwrap();
+        let mut registry = Registry::load_from(dir.path().join("registry.json")).unwrap();
+        let secret = Tool::builder("api")
+            .command(["srv"])
+            .secret_env("TOKEN", old.seal("TOKEN", "sk-1"))
+            .build()
+            .unwrap();
+        registry.register(secret).unwrap();
+        let plain = Tool::builder("plain").command(["srv"]).build().unwrap();
+        registry.register(plain).unwrap();
+
+        let rekeyed = rekey(&mut registry, &sources, None).unwrap();
+        assert_eq!((rekeyed.tools, rekeyed.values), (1, 1));
+        let new = sources.load().unwrap().unwrap();
+        assert_eq!(new.id(), rekeyed.key.id());
+        assert_ne!(new.id(), old.id());
+        let reloaded = Registry::load_from(dir.path().join("registry.json")).unwrap();
+        let tool = reloaded.list().find(|t| t.name == "api").unwrap();
+        assert_eq!(
+            new.open("TOKEN", &tool.secret_env["TOKEN"]).unwrap(),
+            "sk-1"
+        );
+
+        // A key from the environment is replaced by the one given for it
+        let env = KeySources::default().with_env(Some("env-old".to_string()));
+        let sealed = key("env-old").seal("TOKEN", "sk-2");
+        let mut registry = Registry::load_from(dir.path().join("env.json")).unwrap();
+        registry
+            .register(
+                Tool::builder("api")
+                    .command(["srv"])
+                    .secret_env("TOKEN", sealed)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        let error = rekey(&mut registry, &env, None).unwrap_err().to_string();
+        assert!(error.contains(NEW_MASTER_KEY_VAR), "{}", error);
+        rekey(&mut registry, &env, Some("env-new".to_string())).unwrap();
+        let tool = registry.list().find(|t| t.name == "api").unwrap();
+        assert_eq!(
+            key("env-new")
+                .open("TOKEN", &tool.secret_env["TOKEN"])
+                .unwrap(),
+            "sk-2"
+        );
+
+        // Values sealed under some other key stop the rekey before anything changes
+        let stray = KeySources::default().with_env(Some("unrelated".to_string()));
+        let before = registry.list().find(|t| t.name == "api").unwrap().clone();
+        assert!(rekey(&mut registry, &stray, Some("x".to_string())).is_err());
+        assert_eq!(registry.list().find(|t| t.name == "api").unwrap(), &before);
+    }
+}
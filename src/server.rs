@@ -1,31 +1,508 @@
 //! Aggregating MCP server - exposes two meta-tools (list_tools, use_tool) and
 //! natively proxies resources and prompts from all registered backends.
 
+use crate::activity::{self, Activity, BackendState, CallGuard, Snapshot};
+use crate::audit::AuditLog;
+use crate::builtins::{self, BuiltinCall, BuiltinTool};
+use crate::catalog::{self, Catalog};
+use crate::chaos::{Chaos, ChaosOptions};
+use crate::connections::{Connection, Connections};
+use crate::error_text::{self, ErrorText};
+use crate::health::{self, Health, HealthCheck, OnDegraded, Transition};
+use crate::intent::IntentLog;
+use crate::limits::{self, JsonLimits, LimitViolation, ReadLine};
 use crate::mcp::{
-    CallToolParams, CallToolResult, Content, GetPromptParams, InitializeResult, ListPromptsResult,
-    ListResourcesResult, ListToolsResult, Notification, PROTOCOL_VERSION, PromptsCapability,
-    ReadResourceParams, Request, RequestId, ResourcesCapability, Response, ServerCapabilities,
-    ServerInfo, Tool as McpTool, ToolsCapability,
+    CallToolParams, CallToolResult, ClientCapabilities, Content, GetPromptParams, InitializeResult,
+    ListPromptsResult, ListResourcesResult, ListRootsResult, ListToolsResult, LoggingCapability,
+    LoggingLevel, LoggingMessageParams, Notification, PROTOCOL_VERSION, Prompt, PromptsCapability,
+    ReadResourceParams, Request, RequestId, Resource, ResourcesCapability, Response,
+    ServerCapabilities, ServerInfo, SetLevelParams, SubscribeParams, Tool as McpTool,
+    ToolsCapability,
 };
-use crate::proxy::ToolProxy;
-use crate::registry::Registry;
-use anyhow::Result;
+use crate::merge;
+use crate::middleware::{self, CallContext, CallMiddleware, Outcome};
+use crate::naming::{self, NameStyle};
+use crate::offline::{self, CatalogFile, SchemaOnly};
+use crate::ping;
+use crate::proxy::{CallTimings, NotReadyInTime, ProxyOptions, RpcError, ToolProxy};
+use crate::ratelimit::{NotificationLimiter, Summary};
+use crate::registry::{Position, Registry, Tool};
+use crate::scheduler::{Priority, Scheduler};
+use crate::schema;
+use crate::secrets::{SecretPolicy, SecretScanner};
+use crate::structured::StructuredCompat;
+use crate::subscriptions::Subscriptions;
+use crate::telemetry::{self, TraceParent};
+use crate::tool_concurrency::ToolLimiters;
+use crate::transport::{Stdio, Transport};
+use crate::watchdog::{QueueDepth, StallReport, StalledCall, Watchdog, WatchdogOptions};
+use anyhow::{Context, Result, anyhow, bail};
+use indexmap::IndexMap;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::{Mutex, RwLock};
-use tracing::{debug, error, info, warn};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::sync::{RwLock, mpsc};
+use tracing::{Instrument, debug, error, info, info_span, warn};
+
+/// Options controlling how `mcpd serve` behaves
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    /// Start every backend during initialize and merge their `instructions`
+    /// into the instructions mcpd returns to the client.
+    pub aggregate_instructions: bool,
+    /// Tool name patterns blocked in read-only mode. `None` disables read-only mode.
+    pub read_only_patterns: Option<Vec<String>>,
+    /// Size and nesting limits for messages from the client
+    pub json_limits: JsonLimits,
+    /// Settings applied to every backend proxy
+    pub proxy: ProxyOptions,
+    /// Patterns and allowlist for the outbound secret scan of `use_tool` arguments
+    pub secrets: SecretScanner,
+    /// What to do when the secret scan finds something
+    pub secret_policy: SecretPolicy,
+    /// How long `list_tools` waits for each backend before leaving it out.
+    /// `None` waits indefinitely.
+    pub list_timeout: Option<Duration>,
+    /// Cap on concurrent tool calls across all clients. Calls over the cap
+    /// wait, highest backend priority first.
+    pub max_concurrent_calls: Option<usize>,
+    /// Warn when `list_tools` would return more tools than this
+    pub max_tools_warn: Option<usize>,
+    /// Return at most this many tools from `list_tools`
+    pub max_tools: Option<usize>,
+    /// Largest serialized `use_tool` arguments forwarded to a backend that
+    /// doesn't set `max_arg_bytes`. `None` forwards any size.
+    pub max_arg_bytes: Option<usize>,
+    /// How long a tool call waits for its backend to answer before it's
+    /// cancelled. `None` waits indefinitely.
+    pub call_timeout: Option<Duration>,
+    /// Longest timeout a client may set for one call with `TIMEOUT_META`.
+    /// `None` allows any.
+    pub max_call_timeout: Option<Duration>,
+    /// Pretty-print messages to clients, each behind a `Content-Length`
+    /// header instead of on its own line
+    pub pretty_output: bool,
+    /// How backend tool names are restyled for clients
+    pub name_style: NameStyle,
+    /// Longest exposed tool name; longer ones are shortened (see `naming::fit`)
+    pub max_tool_name_length: Option<usize>,
+    /// Failure injection for client testing. Never set outside of tests.
+    pub chaos: Option<ChaosOptions>,
+    /// Repair backend input schemas that aren't valid JSON Schema
+    pub sanitize_schemas: bool,
+    /// How long a backend's `pre_call`/`post_call` hook may run.
+    /// `None` uses `hooks::DEFAULT_HOOK_TIMEOUT`.
+    pub hook_timeout: Option<Duration>,
+    /// Name reported in `serverInfo` (default: `mcpd`)
+    pub server_name: Option<String>,
+    /// Version reported in `serverInfo` (default: mcpd's own)
+    pub server_version: Option<String>,
+    /// Serve tools, prompts and resources from a catalog file instead of
+    /// backends, which are never started
+    pub schema_only: Option<SchemaOnly>,
+    /// Write-ahead log of calls to destructive tools
+    pub intent_log: Option<Arc<IntentLog>>,
+    /// Tool name patterns that make a call destructive for the intent log,
+    /// besides a `destructiveHint` annotation
+    pub intent_log_patterns: Vec<String>,
+    /// How backend and hook text in errors for clients is framed, or left out
+    pub error_text: ErrorText,
+    /// Record of every tool call
+    pub audit_log: Option<Arc<AuditLog>>,
+    /// Leave arguments out of audit records
+    pub audit_redact_args: bool,
+    /// Log where each `tools/call` spent its time, at debug level
+    pub profile: bool,
+    /// How `structuredContent` in tool results is adapted for clients
+    pub structured: StructuredCompat,
+    /// List and run the built-in `mcpd__compose` tool
+    pub compose: bool,
+    /// List and run the built-in `mcpd__ping_backends` tool
+    pub ping_tool: bool,
+    /// List and run the built-in `mcpd__list_backends` tool
+    pub list_backends_tool: bool,
+    /// List and run the built-in `mcpd__echo` tool
+    pub echo_tool: bool,
+    /// Ping the server's own machinery and report stalls. `None` disables it.
+    pub watchdog: Option<WatchdogOptions>,
+}
+
+/// Where a `use_tool` call spent its time, logged under `serve --profile`
+#[derive(Debug, Default)]
+pub(crate) struct CallProfile {
+    correlation_id: Option<String>,
+    /// Waiting for a slot under `--max-concurrent-calls`
+    queued: Duration,
+    /// Readiness wait and backend round trip, from the proxy
+    backend: CallTimings,
+}
+
+/// Tool name fragments treated as destructive by `serve --read-only`
+pub const DEFAULT_DESTRUCTIVE_PATTERNS: &[&str] =
+    &["delete", "write", "create", "update", "remove", "exec"];
+
+/// Default for `serve --max-tools-warn`. Models tend to pick tools worse
+/// when offered hundreds of them.
+pub const DEFAULT_MAX_TOOLS_WARN: usize = 128;
+
+/// `tools/call` `_meta` key in which a client sets one call's timeout, in
+/// milliseconds, overriding `serve --call-timeout` up to `--max-call-timeout`
+pub const TIMEOUT_META: &str = "mcpd_timeout_ms";
+
+/// Default for `serve --max-call-timeout`
+pub const DEFAULT_MAX_CALL_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A tool call its backend didn't answer in time
+#[derive(Debug, Clone, Copy, thiserror::Error)]
+#[error("Timed out after {}ms waiting for the backend to answer", .0.as_millis())]
+struct CallTimedOut(Duration);
+
+/// Truncate an aggregated tool list to `cap`, keeping the first entries.
+/// Returns whether the full list was over the `warn_at` soft limit.
+fn limit_tools<T>(tools: &mut Vec<T>, warn_at: Option<usize>, cap: Option<usize>) -> bool {
+    let over = warn_at.is_some_and(|limit| tools.len() > limit);
+    if let Some(cap) = cap {
+        tools.truncate(cap);
+    }
+    over
+}
+
+/// Drop tools a backend listed more than once, keeping the first of each
+/// name. Returns the names that were repeated.
+fn dedupe_tools(tools: &mut Vec<McpTool>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut repeated = Vec::new();
+    tools.retain(|tool| {
+        let first = seen.insert(tool.name.clone());
+        if !first && !repeated.contains(&tool.name) {
+            repeated.push(tool.name.clone());
+        }
+        first
+    });
+    repeated
+}
+
+/// Check whether a tool name matches any destructive pattern (case-insensitive substring).
+pub(crate) fn matches_destructive(tool_name: &str, patterns: &[String]) -> bool {
+    let name = tool_name.to_lowercase();
+    patterns
+        .iter()
+        .any(|p| !p.is_empty() && name.contains(&p.to_lowercase()))
+}
 
 /// Aggregating MCP server that exposes two static tools:
 /// - `list_tools`: discover all available tools from registered backends
 /// - `use_tool`: call any discovered tool by name
 pub struct Server {
+    options: ServeOptions,
     registry: Arc<RwLock<Registry>>,
     proxies: RwLock<HashMap<String, Arc<ToolProxy>>>,
-    initialized: RwLock<bool>,
-    /// Shared stdout handle for sending notifications outside request handling
-    stdout: Arc<Mutex<tokio::io::Stdout>>,
+    /// Backends attached via `mcpd/addBackend` that live only in this process,
+    /// listed after the registry's in the order they were added
+    ephemeral: std::sync::Mutex<IndexMap<String, Tool>>,
+    /// Connected clients. Shared with proxies so backend notifications can be routed.
+    connections: Arc<Connections>,
+    /// Resources clients watch. Shared with proxies, which resubscribe after a restart.
+    subscriptions: Arc<Subscriptions>,
+    /// In-flight calls and counters, reported via the control socket and
+    /// read by the watchdog's thread
+    activity: Arc<Activity>,
+    /// Backends with a zero-downtime replacement in progress
+    restarting: std::sync::Mutex<HashSet<String>>,
+    /// Existing directories from the most recent `roots/list` answer of any client
+    roots: std::sync::Mutex<Vec<PathBuf>>,
+    /// Admission control for `max_concurrent_calls`
+    scheduler: Option<Arc<Scheduler>>,
+    /// Per-tool limits from backends' `tool_concurrency`, made on first call
+    tool_limiters: ToolLimiters,
+    /// Per backend, exposed tool names back to the backend's own names.
+    /// Only used when names are restyled or shortened (`renames_tools`).
+    tool_names: std::sync::Mutex<HashMap<String, HashMap<String, String>>>,
+    /// Failure injection, from `options.chaos`
+    chaos: Option<Arc<Chaos>>,
+    /// Per backend, the schema repairs last logged, so each is logged once
+    schema_repairs: std::sync::Mutex<HashMap<String, String>>,
+    /// Runs around every routed tool call, in order
+    middleware: Vec<Arc<dyn CallMiddleware>>,
+    /// Tools answered by mcpd itself, listed after the backends' tools
+    builtins: Vec<Arc<dyn BuiltinTool>>,
+    /// Per backend with a `health_check`, when it was last checked and how it went
+    health: std::sync::Mutex<HashMap<String, (Instant, Health)>>,
+    /// Per backend left out of a resource or prompt listing because it
+    /// couldn't be reached, the list_changed notifications owed once it can
+    unavailable: std::sync::Mutex<HashMap<String, HashSet<&'static str>>>,
+    /// Stall detection, from `options.watchdog`
+    watchdog: Option<Arc<Watchdog>>,
+}
+
+/// One backend's answer to `tools/list`, as `(exposed name, tool)` pairs
+type Listing = Result<Vec<(String, McpTool)>>;
+
+/// Whether `error` from `proxy` means the backend couldn't be reached, not
+/// that it answered with a JSON-RPC error (as a backend without prompts does)
+fn unreachable(proxy: &ToolProxy, error: &anyhow::Error) -> bool {
+    let answered = error.chain().any(|cause| cause.is::<RpcError>());
+    !answered || proxy.init_failure().is_some()
+}
+
+/// `_meta` for a listing that came back empty while some backends that
+/// might have filled it couldn't be reached, naming them
+fn unavailable_meta(empty: bool, mut unavailable: Vec<String>) -> Option<serde_json::Value> {
+    if !empty || unavailable.is_empty() {
+        return None;
+    }
+    unavailable.sort();
+    Some(json!({ "mcpd/unavailableBackends": unavailable }))
+}
+
+/// How long a queued call waits before it's treated as one priority level higher
+const PRIORITY_AGING: Duration = Duration::from_secs(5);
+
+/// How often the health loop looks for backends due a check
+const HEALTH_TICK: Duration = Duration::from_secs(1);
+
+/// How often the health loop tries to start backends left out of a listing
+const UNAVAILABLE_RETRY: Duration = Duration::from_secs(5);
+
+/// How long a replaced backend may keep serving in-flight calls before it's stopped
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Wait for in-flight calls on a replaced proxy to finish, then stop it
+async fn drain_and_stop(name: String, old: Arc<ToolProxy>) {
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while old.in_flight().await && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    if old.in_flight().await {
+        warn!(tool = %name, "Calls still running on replaced backend after drain timeout, stopping anyway");
+    }
+    let _ = old.stop().await;
+    debug!(tool = %name, "Stopped replaced backend");
+}
+
+/// State of one connected client
+struct Session {
+    connection: Arc<Connection>,
+    /// Whether the client advertised the roots capability
+    supports_roots: AtomicBool,
+    /// Id of our latest `roots/list` request; answers to older ones are ignored
+    roots_request: std::sync::Mutex<Option<RequestId>>,
+    next_request: AtomicU64,
+}
+
+/// Pass a backend's notification on to clients: progress goes to the client
+/// whose call it belongs to, resource updates to the clients watching the
+/// resource, log messages to clients that asked for them, list changes to
+/// everyone
+fn route_backend_notification(
+    connections: &Connections,
+    subscriptions: &Subscriptions,
+    chaos: Option<&Chaos>,
+    backend: &str,
+    mut notification: Notification,
+) {
+    match notification.method.as_str() {
+        "notifications/progress" => {
+            if let Some(chaos) = chaos
+                && let Some(params) = &notification.params
+                && let Some(token) = params.get("progressToken").and_then(|t| t.as_str())
+                && chaos.drop_progress(token, &params["progress"])
+            {
+                warn!(
+                    chaos = true,
+                    correlation_id = token,
+                    backend,
+                    "Injected: dropped progress notification"
+                );
+                return;
+            }
+            connections.forward_progress(notification)
+        }
+        "notifications/tools/list_changed"
+        | "notifications/resources/list_changed"
+        | "notifications/prompts/list_changed" => {
+            connections.broadcast(&Notification::new(notification.method));
+        }
+        "notifications/resources/updated" => {
+            let Some(params) = notification.params.as_mut() else {
+                return;
+            };
+            let Some(uri) = params
+                .get("uri")
+                .and_then(|u| u.as_str())
+                .map(str::to_string)
+            else {
+                return;
+            };
+            let watchers = subscriptions.watchers(backend, &uri);
+            if watchers.is_empty() {
+                debug!(
+                    backend,
+                    uri, "Dropping update for a resource nobody watches"
+                );
+                return;
+            }
+            params["uri"] = json!(Server::namespace_uri(backend, &uri));
+            for connection in watchers {
+                if !connections.send_to(connection, &notification) {
+                    debug!(
+                        connection,
+                        uri, "Dropping resource update for closed connection"
+                    );
+                }
+            }
+        }
+        "notifications/message" => {
+            relay_log(connections, backend, notification);
+        }
+        method => debug!(backend, method, "Ignoring notification from backend"),
+    }
+}
+
+/// Tell mcpd's log, and the clients that asked for log messages, how many
+/// of a backend's notifications its rate limits dropped
+fn report_dropped_notifications(connections: &Connections, backend: &str, summary: &Summary) {
+    let text = summary.message(backend);
+    warn!(
+        backend,
+        method = %summary.method,
+        dropped = summary.dropped,
+        "{}",
+        text
+    );
+    let params = LoggingMessageParams {
+        level: LoggingLevel::Warning,
+        logger: Some("mcpd".to_string()),
+        data: json!(text),
+    };
+    connections.broadcast_log(&Notification {
+        params: serde_json::to_value(&params).ok(),
+        ..Notification::new("notifications/message")
+    });
+}
+
+/// Pass a backend's log message to the clients that asked for log messages,
+/// with the backend's name in front of its logger. When none did, it goes
+/// to mcpd's own log at the same level. Returns whether a client took it.
+fn relay_log(connections: &Connections, backend: &str, notification: Notification) -> bool {
+    let Some(mut params) = notification
+        .params
+        .and_then(|p| serde_json::from_value::<LoggingMessageParams>(p).ok())
+    else {
+        debug!(backend, "Ignoring malformed log message from backend");
+        return false;
+    };
+    params.logger = Some(match params.logger.take() {
+        Some(logger) => format!("{}/{}", backend, logger),
+        None => backend.to_string(),
+    });
+    let message = Notification {
+        params: serde_json::to_value(&params).ok(),
+        ..Notification::new("notifications/message")
+    };
+    if connections.broadcast_log(&message) > 0 {
+        return true;
+    }
+
+    let logger = params.logger.as_deref().unwrap_or(backend);
+    let data = &params.data;
+    match params.level {
+        LoggingLevel::Debug => debug!(backend, logger, %data, "Backend log message"),
+        LoggingLevel::Info | LoggingLevel::Notice => {
+            info!(backend, logger, %data, "Backend log message")
+        }
+        LoggingLevel::Warning => warn!(backend, logger, %data, "Backend log message"),
+        _ => error!(backend, logger, level = %params.level, %data, "Backend log message"),
+    }
+    false
+}
+
+/// The `_meta` to send with a backend call made inside `span`: the client's
+/// `_meta` minus mcpd's own `mcpd/*` keys. Backends that opted into trace
+/// context get a `traceparent` pointing at our span (or the client's own
+/// when spans aren't exported); others get none.
+fn forwarded_meta(
+    client_meta: Option<&serde_json::Value>,
+    span: &tracing::Span,
+    forward_trace_context: bool,
+) -> Option<serde_json::Value> {
+    let mut meta = match client_meta {
+        Some(serde_json::Value::Object(map)) => map.clone(),
+        _ => serde_json::Map::new(),
+    };
+    meta.retain(|key, _| !key.starts_with("mcpd/") && key != TIMEOUT_META);
+    let inbound = TraceParent::from_meta(client_meta);
+    match telemetry::outgoing(span, inbound.as_ref()).filter(|_| forward_trace_context) {
+        Some(tp) => {
+            meta.insert("traceparent".to_string(), json!(tp.to_header()));
+        }
+        None => {
+            meta.remove("traceparent");
+        }
+    }
+    (!meta.is_empty()).then_some(serde_json::Value::Object(meta))
+}
+
+/// A backend tool as `list_tools` shows it. Fields are in alphabetical
+/// order, which is how the listing has always been printed.
+#[derive(serde::Serialize)]
+struct ListedTool<'a> {
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+}
+
+/// The title of `tool` from a backend with a title: `[Backend] Tool title`,
+/// falling back to the tool's name
+fn titled(backend_title: &str, tool: &McpTool) -> String {
+    let own = tool.display_title().unwrap_or(&tool.name);
+    format!("[{}] {}", backend_title, own)
+}
+
+/// Serializes `(exposed name, tool)` pairs one entry at a time, borrowing
+/// from the backend listings instead of building a JSON value for each tool
+struct ToolList<'a>(&'a [(String, McpTool)]);
+
+impl serde::Serialize for ToolList<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|(name, tool)| ListedTool {
+            description: tool.description.as_deref().unwrap_or_default(),
+            input_schema: &tool.input_schema,
+            name,
+            title: tool.display_title(),
+        }))
+    }
+}
+
+/// The pretty-printed text `list_tools` returns for `(exposed name, tool)` pairs
+pub fn render_tool_list(tools: &[(String, McpTool)]) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&ToolList(tools))
+}
+
+/// Point the resource links in a backend's tool result at their namespaced
+/// URIs, the ones `resources/list` shows, so reading one routes back to the
+/// backend
+fn namespace_resource_links(proxy_name: &str, result: &mut CallToolResult) {
+    for content in &mut result.content {
+        if let Content::ResourceLink { uri, .. } = content {
+            *uri = Server::namespace_uri(proxy_name, uri);
+        }
+    }
+}
+
+/// A `tools/call` success response carrying `result`. Text content is moved
+/// into the response rather than copied.
+fn tool_result_response(id: RequestId, result: CallToolResult) -> Response {
+    match result.into_value() {
+        Ok(value) => Response::success(id, value),
+        Err(e) => Response::error(id, -32603, format!("Serialization failed: {}", e)),
+    }
 }
 
 /// Serialize a result to a JSON-RPC success response, returning an internal error response on failure.
@@ -36,38 +513,229 @@ fn success_or_internal_error(id: RequestId, result: &impl serde::Serialize) -> R
     }
 }
 
+/// Merge per-backend instructions into a single document, one section per
+/// backend, ordered by backend name.
+fn merge_instructions(mut sections: Vec<(String, String)>) -> Option<String> {
+    if sections.is_empty() {
+        return None;
+    }
+    sections.sort_by(|a, b| a.0.cmp(&b.0));
+    let merged = sections
+        .iter()
+        .map(|(name, text)| format!("## {}\n{}", name, text.trim()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Some(merged)
+}
+
 impl Server {
     pub fn new(registry: Registry) -> Self {
+        Self::with_options(registry, ServeOptions::default())
+    }
+
+    /// A server with mcpd's built-in middleware chain (see `middleware::builtins`)
+    pub fn with_options(registry: Registry, options: ServeOptions) -> Self {
+        let middleware = middleware::builtins(&options);
+        Self::with_middleware(registry, options, middleware)
+    }
+
+    /// A server whose tool calls go through `middleware` instead of the
+    /// built-in chain. Start from `middleware::builtins` to keep mcpd's own
+    /// features.
+    pub fn with_middleware(
+        registry: Registry,
+        options: ServeOptions,
+        middleware: Vec<Arc<dyn CallMiddleware>>,
+    ) -> Self {
+        let scheduler = options
+            .max_concurrent_calls
+            .map(|limit| Arc::new(Scheduler::new(limit, PRIORITY_AGING)));
+        let chaos = options.chaos.clone().map(|chaos| {
+            warn!(options = ?chaos, "Chaos mode: injecting failures into tool calls");
+            Arc::new(Chaos::new(chaos))
+        });
+        let builtins = builtins::defaults(&options);
+        let connections = Connections::new().with_pretty_output(options.pretty_output);
+        let watchdog = options.watchdog.map(|w| Arc::new(Watchdog::new(w)));
         Self {
+            chaos,
+            scheduler,
+            tool_limiters: ToolLimiters::new(),
+            options,
             registry: Arc::new(RwLock::new(registry)),
             proxies: RwLock::new(HashMap::new()),
-            initialized: RwLock::new(false),
-            stdout: Arc::new(Mutex::new(tokio::io::stdout())),
+            ephemeral: std::sync::Mutex::new(IndexMap::new()),
+            connections: Arc::new(connections),
+            subscriptions: Arc::new(Subscriptions::new()),
+            restarting: std::sync::Mutex::new(HashSet::new()),
+            roots: std::sync::Mutex::new(Vec::new()),
+            activity: Arc::new(Activity::new()),
+            tool_names: std::sync::Mutex::new(HashMap::new()),
+            schema_repairs: std::sync::Mutex::new(HashMap::new()),
+            middleware,
+            builtins,
+            health: std::sync::Mutex::new(HashMap::new()),
+            unavailable: std::sync::Mutex::new(HashMap::new()),
+            watchdog,
+        }
+    }
+
+    /// Also list and answer `tool`, after the built-ins `options` switch on.
+    /// It replaces a built-in of the same name.
+    pub fn with_builtin(mut self, tool: Arc<dyn BuiltinTool>) -> Self {
+        self.builtins.retain(|t| t.name() != tool.name());
+        self.builtins.push(tool);
+        self
+    }
+
+    /// Build a proxy that reports lifecycle events into this server's counters
+    fn new_proxy(&self, tool: Tool) -> ToolProxy {
+        let lifecycle = self.activity.lifecycle(&tool.name);
+        let cwd = self.backend_cwd(&tool);
+        let connections = Arc::clone(&self.connections);
+        let subscriptions = Arc::clone(&self.subscriptions);
+        let chaos = self.chaos.clone();
+        let limiter = NotificationLimiter::new(&tool.notification_limits);
+        let counters = Arc::clone(&lifecycle);
+        ToolProxy::with_options(tool, self.options.proxy.clone())
+            .with_lifecycle(lifecycle)
+            .with_subscriptions(Arc::clone(&self.subscriptions))
+            .with_cwd(cwd)
+            .with_notification_handler(Arc::new(move |backend, notification| {
+                let verdict = limiter.check(&notification.method, Instant::now());
+                if let Some(summary) = verdict.summary {
+                    report_dropped_notifications(&connections, backend, &summary);
+                }
+                if !verdict.relay {
+                    counters.dropped_notification();
+                    return;
+                }
+                route_backend_notification(
+                    &connections,
+                    &subscriptions,
+                    chaos.as_deref(),
+                    backend,
+                    notification,
+                )
+            }))
+    }
+
+    /// Working directory for a backend: the first client root for
+    /// `cwd_from_root` backends, otherwise the registered `cwd`
+    fn backend_cwd(&self, tool: &Tool) -> Option<PathBuf> {
+        if tool.cwd_from_root
+            && let Some(root) = self.roots.lock().unwrap().first()
+        {
+            return Some(root.clone());
+        }
+        tool.cwd.clone()
+    }
+
+    /// Ask a client for its roots. The answer is handled in `serve_session`.
+    async fn request_roots(&self, session: &Session) {
+        let n = session.next_request.fetch_add(1, Ordering::Relaxed);
+        let id = RequestId::String(format!("mcpd-roots-{}", n));
+        *session.roots_request.lock().unwrap() = Some(id.clone());
+        let request = Request::new(id, "roots/list", None);
+        if let Err(e) = session.connection.send(&request).await {
+            warn!(session = session.connection.id(), error = %e, "Failed to request roots");
+        }
+    }
+
+    /// Handle a client's response to one of our requests
+    async fn handle_client_response(&self, session: &Session, response: Response) {
+        let expected = session.roots_request.lock().unwrap().clone();
+        if expected.as_ref() != Some(&response.id) {
+            debug!(id = ?response.id, "Ignoring unexpected response from client");
+            return;
+        }
+        if let Some(err) = response.error {
+            warn!(code = err.code, error = %err.message, "Client failed to list roots");
+            return;
+        }
+        let result: ListRootsResult = match response.result.map(serde_json::from_value) {
+            Some(Ok(result)) => result,
+            _ => {
+                warn!("Invalid roots/list result from client");
+                return;
+            }
+        };
+        self.set_roots(crate::roots::existing_dirs(&result)).await;
+    }
+
+    /// Record new client roots. `cwd_from_root` backends whose directory
+    /// changes are swapped for fresh proxies, so they restart in the new
+    /// directory on their next call; the old process finishes in-flight calls first.
+    async fn set_roots(&self, roots: Vec<PathBuf>) {
+        info!(?roots, "Client roots updated");
+        *self.roots.lock().unwrap() = roots;
+
+        let mut proxies = self.proxies.write().await;
+        for (name, proxy) in proxies.iter_mut() {
+            if !proxy.tool().cwd_from_root {
+                continue;
+            }
+            let cwd = self.backend_cwd(proxy.tool());
+            if proxy.cwd() == cwd.as_deref() {
+                continue;
+            }
+            info!(backend = %name, cwd = ?cwd, "Working directory changed, restarting backend on next call");
+            let replacement = Arc::new(self.new_proxy(proxy.tool().clone()));
+            let old = std::mem::replace(proxy, replacement);
+            tokio::spawn(drain_and_stop(name.clone(), old));
         }
     }
 
     /// Reload registry from disk, sync proxies, and notify client if anything changed.
     async fn sync_registry(&self) -> Result<()> {
+        if self.options.schema_only.is_some() {
+            return Ok(());
+        }
         let mut registry = self.registry.write().await;
-        registry.reload()?;
+        let old_order: Vec<String> = registry.list().map(|t| t.name.clone()).collect();
+        if registry.reload()? {
+            debug!(
+                generation = registry.generation(),
+                "Registry changed on disk"
+            );
+        }
         let new_names = registry.names();
+        let ephemeral: HashSet<String> = self.ephemeral.lock().unwrap().keys().cloned().collect();
 
         let mut proxies = self.proxies.write().await;
-        let mut changed = false;
+        // A reorder alone changes the order of tools/list
+        let mut changed = !registry.list().map(|t| &t.name).eq(old_order.iter());
 
-        // Add proxies for newly registered servers
+        // Add proxies for newly registered servers, and replace those whose
+        // registration changed
+        let mut warm_replacements = Vec::new();
         for tool in registry.list() {
-            if !proxies.contains_key(&tool.name) {
-                info!(tool = %tool.name, "Creating proxy for new backend");
-                proxies.insert(tool.name.clone(), Arc::new(ToolProxy::new(tool.clone())));
-                changed = true;
+            if ephemeral.contains(&tool.name) {
+                debug!(tool = %tool.name, "Registered backend shadowed by an ephemeral one");
+                continue;
+            }
+            match proxies.get(&tool.name) {
+                None => {
+                    info!(tool = %tool.name, "Creating proxy for new backend");
+                }
+                Some(existing) if existing.tool() != tool => {
+                    if tool.zero_downtime {
+                        warm_replacements.push(tool.clone());
+                        continue;
+                    }
+                    info!(tool = %tool.name, "Registration changed, replacing backend");
+                    let _ = existing.stop().await;
+                }
+                Some(_) => continue,
             }
+            proxies.insert(tool.name.clone(), Arc::new(self.new_proxy(tool.clone())));
+            changed = true;
         }
 
         // Remove proxies for unregistered servers
         let stale: Vec<String> = proxies
             .keys()
-            .filter(|name| !new_names.contains(*name))
+            .filter(|name| !new_names.contains(*name) && !ephemeral.contains(*name))
             .cloned()
             .collect();
 
@@ -79,53 +747,675 @@ impl Server {
             changed = true;
         }
 
+        // Limits on tools whose backend no longer sets them, or is gone
+        self.tool_limiters.retain(|backend, tool| {
+            proxies
+                .get(backend)
+                .is_some_and(|proxy| proxy.tool().tool_concurrency.contains_key(tool))
+        });
+
         // Drop locks before sending notifications
         drop(proxies);
         drop(registry);
 
-        if changed {
-            let initialized = *self.initialized.read().await;
-            if initialized {
-                info!("Registry changed, notifying client");
-                self.send_notification("notifications/tools/list_changed")
-                    .await?;
-                self.send_notification("notifications/resources/list_changed")
-                    .await?;
-                self.send_notification("notifications/prompts/list_changed")
-                    .await?;
+        for tool in warm_replacements {
+            let name = tool.name.clone();
+            if let Err(e) = self.warm_replace(tool).await {
+                warn!(tool = %name, error = %e, "Zero-downtime replacement failed");
             }
         }
 
+        if changed {
+            info!("Registry changed, notifying clients");
+            self.notify_backends_changed();
+        }
+
         Ok(())
     }
 
-    /// Send a JSON-RPC notification to the client via stdout
-    async fn send_notification(&self, method: &str) -> Result<()> {
-        let notification = Notification::new(method);
-        let mut line = serde_json::to_string(&notification)?;
-        line.push('\n');
-        let mut stdout = self.stdout.lock().await;
-        stdout.write_all(line.as_bytes()).await?;
-        stdout.flush().await?;
-        debug!(method, "Sent notification to client");
+    /// Tell clients that tools, resources and prompts may have changed
+    fn notify_backends_changed(&self) {
+        self.send_notification("notifications/tools/list_changed");
+        self.send_notification("notifications/resources/list_changed");
+        self.send_notification("notifications/prompts/list_changed");
+    }
+
+    /// Attach a backend to the running server. Ephemeral backends get a proxy
+    /// but are never written to the registry; others are registered like
+    /// `mcpd register` would. Names already in use are rejected.
+    pub async fn add_backend(&self, tool: Tool, ephemeral: bool) -> Result<()> {
+        tool.validate()?;
+        // Holding the registry lock keeps a concurrent sync from racing the check
+        let mut registry = self.registry.write().await;
+        registry.reload()?;
+        let mut proxies = self.proxies.write().await;
+        let name = tool.name.clone();
+        if registry.names().contains(&name)
+            || proxies.contains_key(&name)
+            || self.ephemeral.lock().unwrap().contains_key(&name)
+        {
+            bail!("Backend '{}' already exists", name);
+        }
+
+        if !ephemeral {
+            registry.register(tool)?;
+            drop(proxies);
+            drop(registry);
+            info!(tool = %name, "Backend registered via control socket");
+            return self.sync_registry().await;
+        }
+
+        proxies.insert(name.clone(), Arc::new(self.new_proxy(tool.clone())));
+        self.ephemeral.lock().unwrap().insert(name.clone(), tool);
+        drop(proxies);
+        drop(registry);
+        info!(tool = %name, "Ephemeral backend added");
+        self.notify_backends_changed();
         Ok(())
     }
 
+    /// Detach a backend added with `add_backend`, or unregister a registered one.
+    /// Returns whether it was ephemeral.
+    pub async fn remove_backend(&self, name: &str) -> Result<bool> {
+        let mut registry = self.registry.write().await;
+        if self.ephemeral.lock().unwrap().shift_remove(name).is_none() {
+            registry.reload()?;
+            if !registry.unregister(name)? {
+                bail!("Unknown backend '{}'", name);
+            }
+            drop(registry);
+            info!(tool = %name, "Backend unregistered via control socket");
+            self.sync_registry().await?;
+            return Ok(false);
+        }
+
+        let proxy = self.proxies.write().await.remove(name);
+        drop(registry);
+        if let Some(proxy) = proxy {
+            let _ = proxy.stop().await;
+        }
+        self.tool_names.lock().unwrap().remove(name);
+        info!(tool = %name, "Ephemeral backend removed");
+        self.notify_backends_changed();
+        Ok(true)
+    }
+
+    /// Register a backend, or replace its registration, on behalf of `mcpd
+    /// register` while this server runs. Going through the server keeps it
+    /// the only writer of the registry file.
+    pub async fn register_backend(&self, tool: Tool) -> Result<()> {
+        let name = tool.name.clone();
+        self.registry.write().await.register(tool)?;
+        info!(tool = %name, "Backend registered via control socket");
+        self.sync_registry().await
+    }
+
+    /// Unregister a backend on behalf of `mcpd unregister`. Returns whether
+    /// it was registered. Ephemeral backends aren't touched.
+    pub async fn unregister_backend(&self, name: &str) -> Result<bool> {
+        if !self.registry.write().await.unregister(name)? {
+            return Ok(false);
+        }
+        info!(tool = %name, "Backend unregistered via control socket");
+        self.sync_registry().await?;
+        Ok(true)
+    }
+
+    /// Rename a registered backend on behalf of `mcpd rename`. It's stopped
+    /// and starts again under the new name on its next call.
+    pub async fn rename_backend(&self, name: &str, new_name: &str) -> Result<()> {
+        self.registry.write().await.rename(name, new_name)?;
+        info!(tool = %name, new_name, "Backend renamed via control socket");
+        self.sync_registry().await
+    }
+
+    /// Move a registered backend on behalf of `mcpd reorder`. Returns the
+    /// new registry order.
+    pub async fn reorder_backend(&self, name: &str, position: &Position) -> Result<Vec<String>> {
+        let order = {
+            let mut registry = self.registry.write().await;
+            registry.reorder(name, position)?;
+            registry.list().map(|tool| tool.name.clone()).collect()
+        };
+        info!(tool = %name, ?position, "Backend moved via control socket");
+        self.sync_registry().await?;
+        Ok(order)
+    }
+
+    /// Restart a backend. Zero-downtime backends are replaced by a warmed-up
+    /// standby; others are stopped and start again on their next call.
+    /// Returns whether the warm path was used.
+    async fn restart_backend(&self, name: &str) -> Result<bool> {
+        let proxy = self
+            .proxies
+            .read()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown backend '{}'", name))?;
+
+        if proxy.tool().zero_downtime {
+            self.warm_replace(proxy.tool().clone()).await?;
+            Ok(true)
+        } else {
+            proxy.stop().await?;
+            self.activity.lifecycle(name).restarted();
+            Ok(false)
+        }
+    }
+
+    /// Replace a backend with a fresh instance of `tool`. The replacement is
+    /// fully initialized before routing switches to it; the old instance keeps
+    /// serving its in-flight calls (up to `DRAIN_TIMEOUT`) and is then stopped.
+    async fn warm_replace(&self, tool: Tool) -> Result<()> {
+        let name = tool.name.clone();
+        if !self.restarting.lock().unwrap().insert(name.clone()) {
+            debug!(tool = %name, "Replacement already in progress");
+            return Ok(());
+        }
+        let result = self.warm_replace_inner(tool).await;
+        self.restarting.lock().unwrap().remove(&name);
+        result
+    }
+
+    async fn warm_replace_inner(&self, tool: Tool) -> Result<()> {
+        let name = tool.name.clone();
+        let old = self
+            .proxies
+            .read()
+            .await
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown backend '{}'", name))?;
+
+        info!(tool = %name, "Warming up replacement backend");
+        let new = Arc::new(self.new_proxy(tool));
+        let new_tools = match new.list_tools().await {
+            Ok(tools) => tools,
+            Err(e) => {
+                let _ = new.stop().await;
+                return Err(e.context("Replacement failed to start; keeping the current instance"));
+            }
+        };
+        let old_tools = match old.backend_state() {
+            BackendState::Ready => old.list_tools().await.ok(),
+            _ => None,
+        };
+
+        {
+            let mut proxies = self.proxies.write().await;
+            match proxies.get(&name) {
+                Some(current) if Arc::ptr_eq(current, &old) => {
+                    proxies.insert(name.clone(), Arc::clone(&new));
+                }
+                _ => {
+                    drop(proxies);
+                    let _ = new.stop().await;
+                    bail!("Backend '{}' changed during restart", name);
+                }
+            }
+        }
+        info!(tool = %name, "Switched to replacement backend");
+        self.activity.lifecycle(&name).restarted();
+        tokio::spawn(drain_and_stop(name, old));
+
+        let names =
+            |tools: &[McpTool]| tools.iter().map(|t| t.name.clone()).collect::<HashSet<_>>();
+        if old_tools.as_deref().map(names) != Some(names(&new_tools)) {
+            self.send_notification("notifications/tools/list_changed");
+        }
+        Ok(())
+    }
+
+    /// Whether a backend's health checks have failed it
+    fn is_degraded(&self, backend: &str) -> bool {
+        self.health
+            .lock()
+            .unwrap()
+            .get(backend)
+            .is_some_and(|(_, health)| health.is_degraded())
+    }
+
+    /// Running backends with a health check, and their checks
+    async fn health_checked(&self) -> Vec<(String, Arc<ToolProxy>, HealthCheck)> {
+        self.proxies
+            .read()
+            .await
+            .iter()
+            .filter(|(_, proxy)| proxy.backend_state() == BackendState::Ready)
+            .filter_map(|(name, proxy)| {
+                let check = proxy.tool().health_check.clone()?;
+                Some((name.clone(), Arc::clone(proxy), check))
+            })
+            .collect()
+    }
+
+    /// Run the health check of every running backend that has one, due or
+    /// not. Backends that aren't running are left alone rather than started.
+    pub async fn check_health(&self) {
+        for (name, proxy, check) in self.health_checked().await {
+            self.check_backend_health(&name, &proxy, &check).await;
+        }
+    }
+
+    /// Run the health checks whose interval has passed, forever
+    async fn health_loop(&self) {
+        let mut retried = Instant::now();
+        loop {
+            tokio::time::sleep(HEALTH_TICK).await;
+            if retried.elapsed() >= UNAVAILABLE_RETRY {
+                self.retry_unavailable().await;
+                retried = Instant::now();
+            }
+            for (name, proxy, check) in self.health_checked().await {
+                let due = self
+                    .health
+                    .lock()
+                    .unwrap()
+                    .get(&name)
+                    .is_none_or(|(checked, _)| checked.elapsed() >= check.interval());
+                if due {
+                    self.check_backend_health(&name, &proxy, &check).await;
+                }
+            }
+        }
+    }
+
+    /// Ping the server through its shared locks and the runtime every
+    /// watchdog interval, while the watchdog's thread reports pings that
+    /// don't come back. Never returns; pending forever without a watchdog.
+    pub async fn watchdog_loop(&self) {
+        let Some(watchdog) = &self.watchdog else {
+            return std::future::pending().await;
+        };
+        let activity = Arc::clone(&self.activity);
+        let connections = Arc::clone(&self.connections);
+        let scheduler = self.scheduler.clone();
+        let _watching = watchdog.watch(move || StallReport {
+            active_calls: activity
+                .active_calls()
+                .into_iter()
+                .map(|(backend, call)| StalledCall {
+                    backend,
+                    correlation_id: call.correlation_id,
+                    tool: call.tool,
+                    elapsed_ms: call.elapsed_ms,
+                    stage: call.stage,
+                })
+                .collect(),
+            outbound_queues: connections
+                .queue_depths()
+                .into_iter()
+                .map(|(connection, queued)| QueueDepth { connection, queued })
+                .collect(),
+            waiting_for_slot: scheduler
+                .as_ref()
+                .map_or(0, |s| s.snapshot().iter().map(|q| q.waiting).sum()),
+            ..Default::default()
+        });
+        loop {
+            tokio::time::sleep(watchdog.options().interval).await;
+            self.self_ping(watchdog).await;
+        }
+    }
+
+    /// One watchdog ping: the locks every call takes, then a spawned task.
+    /// It touches no client, backend or counter.
+    async fn self_ping(&self, watchdog: &Watchdog) {
+        watchdog.send("the backends lock");
+        drop(self.proxies.read().await);
+        watchdog.parked_on("the registry lock");
+        drop(self.registry.read().await);
+        watchdog.parked_on("a spawned task");
+        let _ = tokio::spawn(async {}).await;
+        watchdog.answered();
+    }
+
+    /// The most recent stall the watchdog detected
+    pub fn last_stall(&self) -> Option<StallReport> {
+        self.watchdog.as_ref().and_then(|w| w.last_stall())
+    }
+
+    /// Try to start every backend left out of a resource or prompt listing
+    /// for being unreachable. Clients are told to list again for each one
+    /// that starts, since some stop asking after an empty answer.
+    pub async fn retry_unavailable(&self) {
+        let waiting: Vec<String> = self.unavailable.lock().unwrap().keys().cloned().collect();
+        for name in waiting {
+            let proxy = self.proxies.read().await.get(&name).cloned();
+            let Some(proxy) = proxy else {
+                // Unregistered since
+                self.unavailable.lock().unwrap().remove(&name);
+                continue;
+            };
+            if let Err(e) = proxy.ensure_ready().await {
+                debug!(backend = %name, error = %e, "Backend is still unavailable");
+                continue;
+            }
+            let Some(owed) = self.unavailable.lock().unwrap().remove(&name) else {
+                continue;
+            };
+            info!(backend = %name, "Unavailable backend started, asking clients to list again");
+            for method in owed {
+                self.send_notification(method);
+            }
+        }
+    }
+
+    /// Record whether a backend made it into a listing, so clients hear
+    /// about it (`notification`) once an unreachable one comes back
+    fn track_listing(&self, backend: &str, notification: &'static str, reached: bool) {
+        let mut unavailable = self.unavailable.lock().unwrap();
+        if reached {
+            if let Some(owed) = unavailable.get_mut(backend) {
+                owed.remove(notification);
+                if owed.is_empty() {
+                    unavailable.remove(backend);
+                }
+            }
+        } else {
+            unavailable
+                .entry(backend.to_string())
+                .or_default()
+                .insert(notification);
+        }
+    }
+
+    /// Call a backend's health check tool and act on the outcome. The call
+    /// goes straight to the proxy, so it isn't counted as a client call.
+    async fn check_backend_health(&self, name: &str, proxy: &ToolProxy, check: &HealthCheck) {
+        let started = Instant::now();
+        let call = proxy.call_tool(&check.tool, check.arguments.clone());
+        let outcome = match tokio::time::timeout(health::HEALTH_CHECK_TIMEOUT, call).await {
+            Err(_) => Err(format!(
+                "timed out after {:?}",
+                health::HEALTH_CHECK_TIMEOUT
+            )),
+            Ok(Err(e)) => Err(format!("{:#}", e)),
+            Ok(Ok(result)) if result.is_error => Err(result
+                .content
+                .iter()
+                .filter_map(|c| match c {
+                    Content::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(" ")),
+            Ok(Ok(_)) => Ok(()),
+        };
+        if let Err(failure) = &outcome {
+            debug!(backend = name, tool = %check.tool, %failure, "Health check failed");
+        }
+        self.record_health(
+            name,
+            started,
+            outcome,
+            check.failure_threshold,
+            check.on_degraded,
+        )
+        .await;
+    }
+
+    /// Count a check of a backend (`started` when it began) toward its
+    /// health, warning clients and restarting it as `on_degraded` says when
+    /// that tips it over `threshold` failures
+    async fn record_health(
+        &self,
+        name: &str,
+        started: Instant,
+        outcome: std::result::Result<(), String>,
+        threshold: u32,
+        on_degraded: Option<OnDegraded>,
+    ) {
+        let transition = {
+            let mut health = self.health.lock().unwrap();
+            let (checked, state) = health
+                .entry(name.to_string())
+                .or_insert_with(|| (started, Health::default()));
+            *checked = started;
+            state.record(outcome, threshold)
+        };
+        match transition {
+            Some(Transition::Degraded) => {
+                warn!(
+                    backend = name,
+                    failures = threshold,
+                    "Backend failed its health check, marking it degraded"
+                );
+                self.send_notification("notifications/tools/list_changed");
+                if on_degraded == Some(OnDegraded::Restart) {
+                    match self.restart_backend(name).await {
+                        // A new process starts with a clean record
+                        Ok(_) => {
+                            self.health.lock().unwrap().remove(name);
+                        }
+                        Err(e) => {
+                            warn!(backend = name, error = %e, "Failed to restart degraded backend")
+                        }
+                    }
+                }
+            }
+            Some(Transition::Recovered) => {
+                info!(backend = name, "Backend passed its health check again");
+                self.send_notification("notifications/tools/list_changed");
+            }
+            None => {}
+        }
+    }
+
+    /// Ping every running backend at once, and the stopped ones too with
+    /// `include_stopped`, giving each `ping::PING_TIMEOUT`. Each ping counts
+    /// as a health check, with the backend's own threshold if it has one.
+    pub async fn ping_backends(&self, include_stopped: bool) -> Vec<ping::Report> {
+        if let Err(e) = self.sync_registry().await {
+            warn!(error = %e, "Failed to sync registry before pinging backends");
+        }
+        let proxies: Vec<(String, Arc<ToolProxy>)> = self
+            .proxies
+            .read()
+            .await
+            .iter()
+            .map(|(name, proxy)| (name.clone(), proxy.clone()))
+            .collect();
+        let mut pings = tokio::task::JoinSet::new();
+        for (name, proxy) in proxies {
+            pings.spawn(
+                async move {
+                    let running = matches!(
+                        proxy.backend_state(),
+                        BackendState::Ready | BackendState::Starting
+                    );
+                    if !running && !include_stopped {
+                        return (name, proxy, None);
+                    }
+                    let started = Instant::now();
+                    let outcome = match tokio::time::timeout(ping::PING_TIMEOUT, proxy.ping()).await
+                    {
+                        Err(_) => Err(format!("timed out after {:?}", ping::PING_TIMEOUT)),
+                        Ok(Err(e)) => Err(format!("{:#}", e)),
+                        Ok(Ok(latency)) => Ok(latency),
+                    };
+                    (name, proxy, Some((started, outcome)))
+                }
+                .in_current_span(),
+            );
+        }
+        let mut pings = pings.join_all().await;
+        pings.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+        let mut reports = Vec::new();
+        for (name, proxy, pinged) in pings {
+            let Some((started, outcome)) = pinged else {
+                reports.push(ping::Report::skipped(&name));
+                continue;
+            };
+            reports.push(match &outcome {
+                Ok(latency) => ping::Report::answered(&name, *latency),
+                Err(error) => {
+                    debug!(backend = %name, %error, "Ping failed");
+                    ping::Report::failed(&name, error.clone())
+                }
+            });
+            let check = proxy.tool().health_check.as_ref();
+            self.record_health(
+                &name,
+                started,
+                outcome.map(|_| ()),
+                check.map_or(health::DEFAULT_FAILURE_THRESHOLD, |c| c.failure_threshold),
+                check.and_then(|c| c.on_degraded),
+            )
+            .await;
+        }
+        reports
+    }
+
+    /// Send a JSON-RPC notification to every initialized client. Clients
+    /// that are gone or not keeping up are skipped.
+    fn send_notification(&self, method: &str) {
+        let sent = self.connections.broadcast(&Notification::new(method));
+        debug!(method, clients = sent, "Sent notification to clients");
+    }
+
+    /// Collect instructions from every backend. Backends that fail to start
+    /// or don't provide instructions are skipped.
+    async fn aggregate_instructions(&self) -> Option<String> {
+        if let Err(e) = self.sync_registry().await {
+            warn!(error = %e, "Failed to sync registry while collecting instructions");
+            return None;
+        }
+
+        let proxies = self.proxies.read().await;
+        let mut sections = Vec::new();
+        for (proxy_name, proxy) in proxies.iter() {
+            match proxy.instructions().await {
+                Ok(Some(text)) if !text.trim().is_empty() => {
+                    sections.push((proxy_name.clone(), text));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(proxy = %proxy_name, error = %e, "Failed to get instructions from proxy");
+                }
+            }
+        }
+
+        merge_instructions(sections)
+    }
+
+    /// Whether any backend lets clients subscribe to its resources. Every
+    /// backend is started to find out, each bounded by `list_timeout`.
+    async fn any_backend_subscribes(&self) -> bool {
+        self.on_every_backend(|proxy| async move { proxy.capabilities().await })
+            .await
+            .into_iter()
+            .filter(|(proxy_name, capabilities)| match capabilities {
+                Ok(capabilities) => capabilities.resources.as_ref().is_some_and(|r| r.subscribe),
+                Err(e) => {
+                    debug!(proxy = %proxy_name, error = %e, "Couldn't get backend capabilities");
+                    false
+                }
+            })
+            .count()
+            > 0
+    }
+
+    /// Pass a client's `logging/setLevel` on to every backend that supports
+    /// logging. Failures are logged; the client's request succeeds regardless.
+    async fn set_backend_log_level(&self, level: LoggingLevel) {
+        let results = self
+            .on_every_backend(move |proxy| async move { proxy.set_log_level(level).await })
+            .await;
+        for (proxy_name, result) in results {
+            match result {
+                Ok(true) => debug!(proxy = %proxy_name, %level, "Set backend log level"),
+                Ok(false) => debug!(proxy = %proxy_name, "Backend doesn't support logging"),
+                Err(e) => warn!(proxy = %proxy_name, error = %e, "Failed to set backend log level"),
+            }
+        }
+    }
+
+    /// Run `f` on every backend at once, starting them as needed, each
+    /// bounded by `list_timeout`. Returns each backend's name and result.
+    async fn on_every_backend<T, F, Fut>(&self, f: F) -> Vec<(String, Result<T>)>
+    where
+        T: Send + 'static,
+        F: Fn(Arc<ToolProxy>) -> Fut,
+        Fut: std::future::Future<Output = Result<T>> + Send + 'static,
+    {
+        if let Err(e) = self.sync_registry().await {
+            warn!(error = %e, "Failed to sync registry");
+            return Vec::new();
+        }
+        let proxies: Vec<(String, Arc<ToolProxy>)> = self
+            .proxies
+            .read()
+            .await
+            .iter()
+            .map(|(name, proxy)| (name.clone(), Arc::clone(proxy)))
+            .collect();
+        let mut tasks = tokio::task::JoinSet::new();
+        for (proxy_name, proxy) in proxies {
+            let timeout = self.options.list_timeout;
+            let work = f(proxy);
+            tasks.spawn(
+                async move {
+                    let result = match timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, work)
+                            .await
+                            .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", timeout))),
+                        None => work.await,
+                    };
+                    (proxy_name, result)
+                }
+                .in_current_span(),
+            );
+        }
+        tasks.join_all().await
+    }
+
     /// Handle initialize request
-    async fn handle_initialize(&self, id: RequestId) -> Response {
-        *self.initialized.write().await = true;
+    async fn handle_initialize(&self, session: &Session, id: RequestId) -> Response {
+        let instructions = if self.options.aggregate_instructions {
+            self.aggregate_instructions().await
+        } else {
+            None
+        };
+
+        let subscribe = self.any_backend_subscribes().await;
+
+        session.connection.mark_initialized();
 
         let result = InitializeResult {
             protocol_version: PROTOCOL_VERSION.to_string(),
             capabilities: ServerCapabilities {
                 tools: Some(ToolsCapability { list_changed: true }),
-                resources: Some(ResourcesCapability { list_changed: true }),
+                resources: Some(ResourcesCapability {
+                    list_changed: true,
+                    subscribe,
+                }),
                 prompts: Some(PromptsCapability { list_changed: true }),
+                logging: Some(LoggingCapability::default()),
+                ..Default::default()
             },
             server_info: ServerInfo {
-                name: "mcpd".to_string(),
-                version: env!("CARGO_PKG_VERSION").to_string(),
+                name: self
+                    .options
+                    .server_name
+                    .clone()
+                    .unwrap_or_else(|| "mcpd".to_string()),
+                version: self
+                    .options
+                    .server_version
+                    .clone()
+                    .unwrap_or_else(|| env!("CARGO_PKG_VERSION").to_string()),
+                title: None,
+                // Only mcpd under its own name is mcpd's project
+                website_url: self
+                    .options
+                    .server_name
+                    .is_none()
+                    .then(|| env!("CARGO_PKG_HOMEPAGE").to_string()),
+                icons: Vec::new(),
             },
+            instructions,
         };
 
         success_or_internal_error(id, &result)
@@ -136,6 +1426,7 @@ impl Server {
         let tools = vec![
             McpTool {
                 name: "list_tools".to_string(),
+                title: None,
                 description: Some(
                     "List all available tools from registered MCP backends. \
                      Returns tool names, descriptions, and input schemas. \
@@ -148,9 +1439,11 @@ impl Server {
                     "properties": {},
                     "additionalProperties": false
                 }),
+                annotations: None,
             },
             McpTool {
                 name: "use_tool".to_string(),
+                title: None,
                 description: Some(
                     "Invoke a tool by name. Use `list_tools` first to discover \
                      available tools and their expected arguments."
@@ -171,42 +1464,181 @@ impl Server {
                     "required": ["tool_name"],
                     "additionalProperties": false
                 }),
+                annotations: None,
             },
         ];
 
-        info!(count = 2, "Serving static meta-tools");
+        info!(count = 2, "Serving static meta-tools");
+
+        let result = ListToolsResult { tools };
+        success_or_internal_error(id, &result)
+    }
+
+    /// Whether read-only mode blocks this (backend-side) tool name
+    fn is_blocked(&self, tool_name: &str) -> bool {
+        self.options
+            .read_only_patterns
+            .as_ref()
+            .is_some_and(|patterns| matches_destructive(tool_name, patterns))
+    }
+
+    /// Whether exposed tool names can differ from `<server>__<tool>`, so
+    /// calls are routed through `tool_names`
+    fn renames_tools(&self) -> bool {
+        self.options.name_style != NameStyle::AsIs || self.options.max_tool_name_length.is_some()
+    }
+
+    /// Remember how one backend's tools are exposed under the name style and
+    /// length limit. Returns each backend tool name's exposed tool part.
+    fn expose_tool_names(&self, backend: &str, tools: &[McpTool]) -> HashMap<String, String> {
+        let style = self.options.name_style;
+        let (mut exposed, collisions) =
+            naming::expose(style, tools.iter().map(|t| t.name.as_str()));
+        for names in collisions {
+            warn!(backend, tools = ?names, style = ?style, "Tool names collide after restyling, keeping their original names");
+        }
+        if let Some(max) = self.options.max_tool_name_length {
+            let unfit = naming::fit(style, backend, &mut exposed, max);
+            if !unfit.is_empty() {
+                warn!(backend, tools = ?unfit, max, "Tool names can't be shortened to --max-tool-name-length, keeping them as they are");
+            }
+        }
+        let by_original = exposed
+            .iter()
+            .map(|(exposed, original)| (original.clone(), exposed.clone()))
+            .collect();
+        self.tool_names
+            .lock()
+            .unwrap()
+            .insert(backend.to_string(), exposed);
+        by_original
+    }
+
+    /// The backend's own name for an exposed tool part
+    async fn resolve_tool_name(&self, backend: &str, proxy: &ToolProxy, exposed: &str) -> String {
+        if !self.renames_tools() {
+            return exposed.to_string();
+        }
+        let known = |names: &HashMap<String, HashMap<String, String>>| {
+            names.get(backend).and_then(|m| m.get(exposed)).cloned()
+        };
+        if let Some(original) = known(&self.tool_names.lock().unwrap()) {
+            return original;
+        }
+        // Called before the client listed tools, or the backend's tools changed
+        if let Ok(tools) = proxy.list_tools().await {
+            self.expose_tool_names(backend, &tools);
+        }
+        known(&self.tool_names.lock().unwrap()).unwrap_or_else(|| exposed.to_string())
+    }
 
-        let result = ListToolsResult { tools };
-        success_or_internal_error(id, &result)
+    /// Each backend's place in the listing order: registered ones in
+    /// registry order, then ephemeral ones
+    async fn backend_order(&self) -> HashMap<String, usize> {
+        let registry = self.registry.read().await;
+        let ephemeral = self.ephemeral.lock().unwrap();
+        registry
+            .list()
+            .map(|tool| &tool.name)
+            .chain(ephemeral.keys())
+            .enumerate()
+            .map(|(i, name)| (name.clone(), i))
+            .collect()
     }
 
-    /// Aggregate tools from all backend proxies
-    async fn aggregate_backend_tools(&self) -> Result<Vec<serde_json::Value>, String> {
-        if let Err(e) = self.sync_registry().await {
-            return Err(format!("Failed to ensure proxies: {}", e));
+    /// Every merge group backends put their tools in, with its members in
+    /// backend order
+    async fn merge_groups(&self) -> IndexMap<String, Vec<merge::Member>> {
+        let order = self.backend_order().await;
+        let mut proxies: Vec<(String, Arc<ToolProxy>)> = self
+            .proxies
+            .read()
+            .await
+            .iter()
+            .filter(|(_, proxy)| !proxy.tool().merge_groups.is_empty())
+            .map(|(name, proxy)| (name.clone(), Arc::clone(proxy)))
+            .collect();
+        proxies.sort_by_key(|(name, _)| order.get(name).copied().unwrap_or(usize::MAX));
+        let mut groups: IndexMap<String, Vec<merge::Member>> = IndexMap::new();
+        for (backend, proxy) in proxies {
+            for (tool, group) in &proxy.tool().merge_groups {
+                groups
+                    .entry(group.clone())
+                    .or_default()
+                    .push(merge::Member {
+                        backend: backend.clone(),
+                        tool: tool.clone(),
+                    });
+            }
         }
+        groups
+    }
 
-        let proxies = self.proxies.read().await;
+    /// Aggregate tools from all backend proxies, as `(exposed name, tool)`
+    /// pairs. Tools in a merge group are listed once, under the group's name.
+    async fn aggregate_backend_tools(&self) -> Result<Vec<(String, McpTool)>, String> {
+        let listings = self.backend_listings().await?;
+        let groups: HashMap<(String, String), String> = self
+            .merge_groups()
+            .await
+            .into_iter()
+            .flat_map(|(group, members)| {
+                members
+                    .into_iter()
+                    .map(move |m| ((m.backend, m.tool), group.clone()))
+            })
+            .collect();
         let mut all_tools = Vec::new();
-
-        for (proxy_name, proxy) in proxies.iter() {
-            match proxy.list_tools().await {
+        // Per group, where it's listed and the backends that listed it
+        let mut merged: HashMap<String, (usize, Vec<String>)> = HashMap::new();
+        for (proxy_name, listing) in listings {
+            match listing {
                 Ok(tools) => {
-                    for tool in tools {
-                        let prefixed_name = format!("{}__{}", proxy_name, tool.name);
-                        all_tools.push(json!({
-                            "name": prefixed_name,
-                            "description": tool.description.unwrap_or_default(),
-                            "input_schema": tool.input_schema,
-                        }));
+                    for (exposed, tool) in tools {
+                        let key = (proxy_name.clone(), tool.name.clone());
+                        let Some(group) = groups.get(&key) else {
+                            all_tools.push((exposed, tool));
+                            continue;
+                        };
+                        match merged.get_mut(group) {
+                            Some((_, backends)) => backends.push(proxy_name.clone()),
+                            None => {
+                                merged.insert(
+                                    group.clone(),
+                                    (all_tools.len(), vec![proxy_name.clone()]),
+                                );
+                                all_tools.push((group.clone(), tool));
+                            }
+                        }
                     }
                 }
                 Err(e) => {
-                    warn!(proxy = %proxy_name, error = %e, "Failed to list tools from proxy");
+                    warn!(proxy = %proxy_name, error = %e, "Failed to list tools from proxy, leaving it out");
                 }
             }
         }
+        for (group, (at, backends)) in merged {
+            all_tools[at].1 = merge::listed(&group, &all_tools[at].1, &backends);
+        }
 
+        let total = all_tools.len();
+        if limit_tools(
+            &mut all_tools,
+            self.options.max_tools_warn,
+            self.options.max_tools,
+        ) {
+            warn!(
+                count = total,
+                limit = self.options.max_tools_warn,
+                "Backends expose a lot of tools, which makes it harder for models to pick the right one. Consider unregistering unused servers, --read-only, or a result transform"
+            );
+        }
+        if all_tools.len() < total {
+            warn!(
+                shown = all_tools.len(),
+                total, "Truncated list_tools result to --max-tools"
+            );
+        }
         info!(
             count = all_tools.len(),
             "Aggregated tools from all backends"
@@ -214,45 +1646,532 @@ impl Server {
         Ok(all_tools)
     }
 
+    /// Every backend's tools as `mcpd catalog` documents them, with the
+    /// backends that couldn't list theirs. Unlike `list_tools`, the
+    /// catalog is never cut to `--max-tools`.
+    pub async fn catalog(&self) -> Result<Catalog> {
+        let mut catalog = Catalog::default();
+        for (backend, listing) in self.backend_listings().await.map_err(|e| anyhow!(e))? {
+            match listing {
+                Ok(tools) => {
+                    catalog
+                        .tools
+                        .extend(tools.into_iter().map(|(name, tool)| catalog::Entry {
+                            name,
+                            backend: backend.clone(),
+                            tool: tool.name,
+                            description: tool.description,
+                            input_schema: tool.input_schema,
+                        }))
+                }
+                Err(e) => catalog.failures.push(catalog::Failure {
+                    backend,
+                    error: format!("{:#}", e),
+                }),
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// Every backend's tools, prompts and resources for `mcpd
+    /// export-catalog`, with the backends that couldn't list their tools
+    pub async fn export_catalog(&self) -> Result<(CatalogFile, Vec<catalog::Failure>)> {
+        let mut backends = Vec::new();
+        let mut failures = Vec::new();
+        for (backend, listing) in self.backend_listings().await.map_err(|e| anyhow!(e))? {
+            let tools = match listing {
+                Ok(tools) => tools,
+                Err(e) => {
+                    failures.push(catalog::Failure {
+                        backend,
+                        error: format!("{:#}", e),
+                    });
+                    continue;
+                }
+            };
+            let proxy = self.proxies.read().await.get(&backend).cloned();
+            let (prompts, resources) = match proxy {
+                // Backends without prompts or resources fail to list them
+                Some(proxy) => (
+                    proxy.list_prompts().await.unwrap_or_default(),
+                    proxy.list_resources().await.unwrap_or_default(),
+                ),
+                None => Default::default(),
+            };
+            backends.push(offline::Backend::new(backend, tools, prompts, resources));
+        }
+        Ok((CatalogFile::new(backends), failures))
+    }
+
+    /// Ask every backend for its tools, in registry order (ephemeral
+    /// backends last). Each listing is as clients see it: read-only hiding,
+    /// schema repair and exposed names are applied.
+    async fn backend_listings(&self) -> Result<Vec<(String, Listing)>, String> {
+        if let Err(e) = self.sync_registry().await {
+            return Err(format!("Failed to ensure proxies: {}", e));
+        }
+
+        // Ask every backend at once so the slowest one bounds the wait
+        let proxies: Vec<(String, Arc<ToolProxy>)> = self
+            .proxies
+            .read()
+            .await
+            .iter()
+            .map(|(name, proxy)| (name.clone(), Arc::clone(proxy)))
+            .collect();
+        let titles: HashMap<String, String> = proxies
+            .iter()
+            .filter_map(|(name, proxy)| Some((name.clone(), proxy.tool().title.clone()?)))
+            .collect();
+        let mut listings = tokio::task::JoinSet::new();
+        for (proxy_name, proxy) in proxies {
+            let timeout = self.options.list_timeout;
+            listings.spawn(
+                async move {
+                    let tools = match timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, proxy.list_tools())
+                            .await
+                            .unwrap_or_else(|_| Err(anyhow!("timed out after {:?}", timeout))),
+                        None => proxy.list_tools().await,
+                    };
+                    (proxy_name, tools)
+                }
+                .in_current_span(),
+            );
+        }
+        let mut listings = listings.join_all().await;
+        let order = self.backend_order().await;
+        listings.sort_by_key(|(name, _)| order.get(name).copied().unwrap_or(usize::MAX));
+
+        Ok(listings
+            .into_iter()
+            .map(|(proxy_name, listing)| {
+                let listing = listing.map(|mut tools| {
+                    // Limits on tools the backend no longer has
+                    self.tool_limiters.retain(|backend, tool| {
+                        backend != proxy_name || tools.iter().any(|t| t.name == tool)
+                    });
+                    let repeated = dedupe_tools(&mut tools);
+                    if !repeated.is_empty() {
+                        warn!(proxy = %proxy_name, tools = ?repeated, "Backend listed tools more than once, keeping the first of each");
+                    }
+                    tools.retain(|tool| {
+                        let blocked = self.is_blocked(&tool.name);
+                        if blocked {
+                            debug!(proxy = %proxy_name, tool = %tool.name, "Hiding tool in read-only mode");
+                        }
+                        !blocked
+                    });
+                    if self.options.sanitize_schemas {
+                        self.sanitize_schemas(&proxy_name, &mut tools);
+                    }
+                    let style = self.options.name_style;
+                    let mut exposed = self
+                        .renames_tools()
+                        .then(|| self.expose_tool_names(&proxy_name, &tools));
+                    let degraded = self.is_degraded(&proxy_name);
+                    let backend_title = titles.get(&proxy_name);
+                    tools
+                        .into_iter()
+                        .map(|mut tool| {
+                            if degraded {
+                                tool.description = Some(health::degraded_description(
+                                    tool.description.as_deref(),
+                                ));
+                            }
+                            if let Some(backend_title) = backend_title {
+                                tool.title = Some(titled(backend_title, &tool));
+                            }
+                            let part = exposed
+                                .as_mut()
+                                .and_then(|e| e.remove(&tool.name))
+                                .unwrap_or_else(|| tool.name.clone());
+                            (style.join(&proxy_name, &part), tool)
+                        })
+                        .collect()
+                });
+                (proxy_name, listing)
+            })
+            .collect())
+    }
+
+    /// Repair a backend's tool input schemas in place, logging what changed
+    /// the first time the backend's repairs are seen
+    fn sanitize_schemas(&self, backend: &str, tools: &mut [McpTool]) {
+        let mut summary = Vec::new();
+        for tool in tools.iter_mut() {
+            let sanitized = schema::sanitize(&tool.input_schema);
+            if sanitized.repairs.is_empty() {
+                continue;
+            }
+            let repairs: Vec<String> = sanitized.repairs.iter().map(|r| r.to_string()).collect();
+            summary.push(format!("{}: {}", tool.name, repairs.join(", ")));
+            tool.input_schema = sanitized.schema;
+        }
+        if summary.is_empty() {
+            return;
+        }
+        let summary = summary.join("; ");
+        let mut logged = self.schema_repairs.lock().unwrap();
+        if logged.get(backend) != Some(&summary) {
+            warn!(proxy = %backend, %summary, "Repaired invalid tool input schemas");
+            logged.insert(backend.to_string(), summary);
+        }
+    }
+
     /// Route a use_tool call to the appropriate backend
-    async fn route_tool_call(
+    pub(crate) async fn route_tool_call(
         &self,
+        connection: u64,
         tool_name: &str,
         arguments: serde_json::Value,
+        meta: Option<&serde_json::Value>,
+        profile: &mut CallProfile,
     ) -> Result<CallToolResult, String> {
         // Parse "proxyname__toolname" format
-        let (proxy_name, original_name) = tool_name
-            .split_once("__")
-            .ok_or_else(|| format!(
-                "Invalid tool name '{}'. Expected format: server__tool. Use list_tools to see available tools.",
-                tool_name
-            ))?;
-
-        let proxy = {
+        let style = self.options.name_style;
+        let (proxy_name, exposed_name) = style.split(tool_name).ok_or_else(|| {
+            format!(
+                "Invalid tool name '{}'. Expected format: {}. Use list_tools to see available tools.",
+                tool_name,
+                style.join("server", "tool")
+            )
+        })?;
+
+        // Counted as routed before the lock is released, so a replacement
+        // swapped in afterwards waits for this call before stopping the proxy
+        let (proxy, _routed) = {
             if let Err(e) = self.sync_registry().await {
                 return Err(format!("Failed to ensure proxies: {}", e));
             }
             let proxies = self.proxies.read().await;
-            proxies.get(proxy_name).cloned().ok_or_else(|| {
+            let proxy = proxies.get(proxy_name).cloned().ok_or_else(|| {
                 format!(
                     "Unknown server '{}'. Use list_tools to see available tools.",
                     proxy_name
                 )
-            })?
+            })?;
+            let routed = proxy.route();
+            (proxy, routed)
+        };
+        let original_name = &self
+            .resolve_tool_name(proxy_name, &proxy, exposed_name)
+            .await;
+
+        if proxy.needs_revival() {
+            // Calls go to the other replicas while the crashed ones start
+            let proxy = Arc::clone(&proxy);
+            tokio::spawn(async move { proxy.revive().await });
+        }
+
+        let mut call = self.activity.begin(connection, proxy_name, original_name);
+        profile.correlation_id = Some(call.correlation_id().to_string());
+        debug!(correlation_id = call.correlation_id(), tool = %tool_name, "Routing tool call");
+        let span = info_span!(
+            "use_tool",
+            backend = proxy_name,
+            tool = original_name,
+            correlation_id = call.correlation_id(),
+            is_error = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+        );
+        let mut ctx = CallContext::new(
+            Arc::clone(&proxy),
+            tool_name,
+            original_name,
+            call.correlation_id(),
+            arguments,
+            meta.cloned(),
+        );
+        let (entered, early) = middleware::before(&self.middleware, &mut ctx)
+            .instrument(span.clone())
+            .await;
+        let outcome = match early {
+            Some(outcome) => outcome,
+            None => {
+                let outcome = self
+                    .dispatch(connection, &proxy, &mut ctx, &span, &call, profile)
+                    .await;
+                call.enter(activity::STAGE_MIDDLEWARE);
+                outcome
+            }
         };
+        let result = middleware::after(&self.middleware[..entered], &mut ctx, outcome)
+            .instrument(span.clone())
+            .await
+            .inspect_err(|_| {
+                span.record("is_error", true);
+            })?;
+        span.record("is_error", result.is_error);
+        if !result.is_error {
+            call.succeeded();
+        }
+        Ok(result)
+    }
 
-        proxy
-            .call_tool(original_name, arguments)
+    /// Send a call that made it through the `before` side of the middleware
+    /// chain to its backend, waiting for a slot under `--max-concurrent-calls`
+    async fn dispatch(
+        &self,
+        connection: u64,
+        proxy: &Arc<ToolProxy>,
+        ctx: &mut CallContext,
+        span: &tracing::Span,
+        call: &CallGuard<'_>,
+        profile: &mut CallProfile,
+    ) -> Outcome {
+        // A request may lower its backend's priority but not raise it
+        let meta = ctx.meta.as_ref();
+        let priority = Priority::from_meta(meta)
+            .map_or(proxy.tool().priority, |p| p.min(proxy.tool().priority));
+        let progress = meta.and_then(|m| m.get("progressToken")).map(|token| {
+            self.connections
+                .track_progress(connection, token.clone(), &ctx.correlation_id)
+        });
+        let meta_for = |proxy: &ToolProxy| {
+            let mut meta =
+                forwarded_meta(ctx.meta.as_ref(), span, proxy.tool().forward_trace_context);
+            if let Some(progress) = &progress {
+                // Backends report progress against our token, which is unique
+                // across clients, and it's mapped back on the way out
+                meta.get_or_insert_with(|| json!({}))["progressToken"] =
+                    json!(progress.backend_token());
+            }
+            meta
+        };
+        let queued = Instant::now();
+        call.enter(activity::STAGE_QUEUED);
+        let limits = proxy.tool().tool_concurrency.get(&ctx.tool);
+        let _turn = match self
+            .tool_limiters
+            .acquire(&proxy.tool().name, &ctx.tool, limits)
+            .instrument(span.clone())
             .await
-            .map_err(|e| format!("Tool call failed: {}", e))
+        {
+            Ok(turn) => turn,
+            Err(busy) => {
+                warn!(correlation_id = ctx.correlation_id, error = %busy, "Refused a call over its tool's queue limit");
+                let mut result = error_text::tool_error(busy.to_string());
+                result.meta = Some(json!({ "mcpd/retryable": true }));
+                return Ok(result);
+            }
+        };
+        let _permit = match &self.scheduler {
+            Some(scheduler) => Some(scheduler.acquire(priority).instrument(span.clone()).await),
+            None => None,
+        };
+        profile.queued = queued.elapsed();
+        call.enter(activity::STAGE_BACKEND);
+        let started = Instant::now();
+        let mut answered_by = proxy.tool().name.clone();
+        let deadline = self
+            .call_timeout(meta)
+            .map(|timeout| (timeout, started + timeout));
+        let backend = async {
+            // A cold start counts against the call's timeout, and one that
+            // runs past it goes on in the background for the next call
+            let warming = Instant::now();
+            let ready = match deadline {
+                Some((_, deadline)) => {
+                    proxy
+                        .ensure_ready_by(deadline)
+                        .instrument(span.clone())
+                        .await
+                }
+                None => Ok(()),
+            };
+            let warmed = warming.elapsed();
+            let mut outcome = match ready {
+                Ok(()) => {
+                    let outcome = self
+                        .call_backend(
+                            proxy,
+                            &ctx.tool,
+                            ctx.arguments.clone(),
+                            meta_for(proxy),
+                            &ctx.correlation_id,
+                            &mut profile.backend,
+                        )
+                        .instrument(span.clone())
+                        .await;
+                    profile.backend.ready += warmed;
+                    outcome
+                }
+                Err(e) => {
+                    profile.backend.ready = warmed;
+                    Err(e)
+                }
+            };
+            // With the deadline already passed there's no time for a fallback
+            if let Err(e) = &outcome
+                && !e.is::<NotReadyInTime>()
+                && let Some(fallback) = self.fallback_for(proxy, &ctx.tool, e).await
+            {
+                warn!(
+                    correlation_id = ctx.correlation_id,
+                    backend = %proxy.tool().name,
+                    fallback = %fallback.tool().name,
+                    error = %e,
+                    "Backend unavailable, calling its fallback"
+                );
+                answered_by = fallback.tool().name.clone();
+                outcome = self
+                    .call_backend(
+                        &fallback,
+                        &ctx.tool,
+                        ctx.arguments.clone(),
+                        meta_for(&fallback),
+                        &ctx.correlation_id,
+                        &mut profile.backend,
+                    )
+                    .instrument(span.clone())
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "'{}' was unavailable and its fallback '{}' failed too",
+                            proxy.tool().name,
+                            answered_by
+                        )
+                    });
+            }
+            outcome
+        };
+        // Dropping the call on timeout cancels it at the backend
+        let outcome = match deadline {
+            Some((timeout, deadline)) => tokio::time::timeout_at(deadline.into(), backend)
+                .await
+                .unwrap_or_else(|_| Err(CallTimedOut(timeout).into())),
+            None => backend.await,
+        };
+        span.record("latency_ms", started.elapsed().as_millis() as u64);
+        if let (Some((timeout, _)), Err(e)) = (deadline, &outcome)
+            && (e.is::<CallTimedOut>() || e.is::<NotReadyInTime>())
+        {
+            ctx.extensions.insert(middleware::TimedOut(timeout));
+        }
+        outcome
+            .map(|mut result| {
+                namespace_resource_links(&answered_by, &mut result);
+                result
+            })
+            .map_err(|e| {
+                self.options
+                    .error_text
+                    .describe("Tool call failed", &e, Some(&ctx.correlation_id))
+            })
+    }
+
+    /// The backend to retry a call to `tool` with after `proxy` failed with
+    /// `error`: its registered fallback, if the failure means `proxy` couldn't
+    /// be reached (not a JSON-RPC error answer to the call itself) and the
+    /// fallback has a tool of the same name
+    async fn fallback_for(
+        &self,
+        proxy: &ToolProxy,
+        tool: &str,
+        error: &anyhow::Error,
+    ) -> Option<Arc<ToolProxy>> {
+        let name = proxy.tool().fallback.as_ref()?;
+        if !unreachable(proxy, error) {
+            return None;
+        }
+        let Some(fallback) = self.proxies.read().await.get(name).cloned() else {
+            warn!(backend = %proxy.tool().name, fallback = %name, "Fallback is not a registered server");
+            return None;
+        };
+        match fallback.list_tools().await {
+            Ok(tools) if tools.iter().any(|t| t.name == tool) => Some(fallback),
+            Ok(_) => {
+                warn!(backend = %proxy.tool().name, fallback = %name, tool, "Fallback has no tool of this name");
+                None
+            }
+            Err(e) => {
+                warn!(backend = %proxy.tool().name, fallback = %name, error = %e, "Fallback is unavailable too");
+                None
+            }
+        }
+    }
+
+    /// Call a backend tool. Every routed call passes through here, which is
+    /// where chaos mode injects its failures.
+    async fn call_backend(
+        &self,
+        proxy: &ToolProxy,
+        tool: &str,
+        arguments: serde_json::Value,
+        meta: Option<serde_json::Value>,
+        correlation_id: &str,
+        timings: &mut CallTimings,
+    ) -> Result<CallToolResult> {
+        let Some(chaos) = &self.chaos else {
+            return proxy.call_tool_timed(tool, arguments, meta, timings).await;
+        };
+        let backend = &proxy.tool().name;
+        if chaos.crash() {
+            warn!(
+                chaos = true,
+                correlation_id, backend, "Injected: killed backend"
+            );
+            proxy.kill().await;
+            bail!("Backend process died (injected by mcpd chaos mode)");
+        }
+        if let Some(delay) = chaos.latency(correlation_id) {
+            warn!(
+                chaos = true,
+                correlation_id,
+                backend,
+                delay_ms = delay.as_millis() as u64,
+                "Injected: latency"
+            );
+            tokio::time::sleep(delay).await;
+        }
+        let result = proxy
+            .call_tool_timed(tool, arguments, meta, timings)
+            .await?;
+        if !result.is_error && chaos.fail(correlation_id) {
+            warn!(
+                chaos = true,
+                correlation_id, backend, "Injected: error result"
+            );
+            return Ok(error_text::tool_error(
+                "Tool failed (injected by mcpd chaos mode)",
+            ));
+        }
+        Ok(result)
     }
 
     /// Handle tools/call request - dispatches list_tools and use_tool
-    async fn handle_call_tool(&self, id: RequestId, params: CallToolParams) -> Response {
+    async fn handle_call_tool(
+        &self,
+        session: &Session,
+        id: RequestId,
+        params: CallToolParams,
+    ) -> Response {
         match params.name.as_str() {
+            "list_tools" if let Some(schema_only) = &self.options.schema_only => {
+                match render_tool_list(&schema_only.catalog.tools()) {
+                    Ok(text) => tool_result_response(
+                        id,
+                        CallToolResult {
+                            content: vec![Content::Text { text }],
+                            is_error: false,
+                            structured_content: None,
+                            meta: None,
+                        },
+                    ),
+                    Err(e) => {
+                        Response::error(id, -32603, format!("Failed to serialize tools: {}", e))
+                    }
+                }
+            }
             "list_tools" => match self.aggregate_backend_tools().await {
-                Ok(tools) => {
-                    let text = match serde_json::to_string_pretty(&tools) {
+                Ok(mut tools) => {
+                    tools.extend(
+                        self.builtins
+                            .iter()
+                            .map(|builtin| (builtin.name().to_string(), builtin.tool())),
+                    );
+                    let text = match render_tool_list(&tools) {
                         Ok(t) => t,
                         Err(e) => {
                             return Response::error(
@@ -265,30 +2184,26 @@ impl Server {
                     let result = CallToolResult {
                         content: vec![Content::Text { text }],
                         is_error: false,
+                        structured_content: None,
+                        meta: None,
                     };
-                    success_or_internal_error(id, &result)
-                }
-                Err(e) => {
-                    let result = CallToolResult {
-                        content: vec![Content::Text {
-                            text: format!("Error listing tools: {}", e),
-                        }],
-                        is_error: true,
-                    };
-                    success_or_internal_error(id, &result)
+                    tool_result_response(id, result)
                 }
+                Err(e) => tool_result_response(
+                    id,
+                    error_text::tool_error(format!("Error listing tools: {}", e)),
+                ),
             },
             "use_tool" => {
                 let tool_name = match params.arguments.get("tool_name").and_then(|v| v.as_str()) {
                     Some(name) => name.to_string(),
                     None => {
-                        let result = CallToolResult {
-                            content: vec![Content::Text {
-                                text: "Missing required parameter 'tool_name'. Use list_tools to discover available tools.".to_string(),
-                            }],
-                            is_error: true,
-                        };
-                        return success_or_internal_error(id, &result);
+                        return tool_result_response(
+                            id,
+                            error_text::tool_error(
+                                "Missing required parameter 'tool_name'. Use list_tools to discover available tools.",
+                            ),
+                        );
                     }
                 };
 
@@ -298,33 +2213,170 @@ impl Server {
                     .cloned()
                     .unwrap_or(json!({}));
 
-                match self.route_tool_call(&tool_name, arguments).await {
-                    Ok(result) => success_or_internal_error(id, &result),
+                if let Some(schema_only) = &self.options.schema_only {
+                    return tool_result_response(id, schema_only.call(&tool_name, &arguments));
+                }
+                if let Some(builtin) = self.builtins.iter().find(|t| t.name() == tool_name) {
+                    let call = BuiltinCall {
+                        arguments,
+                        meta: params.meta.as_ref(),
+                        connection: session.connection.id(),
+                    };
+                    let mut result = builtin.call(self, call).await;
+                    self.options.structured.apply(&mut result);
+                    return tool_result_response(id, result);
+                }
+                if self.options.name_style.split(&tool_name).is_none()
+                    && let Some(members) = self.merge_groups().await.swap_remove(&tool_name)
+                {
+                    let mut result = self
+                        .call_merge_group(
+                            session.connection.id(),
+                            &tool_name,
+                            members,
+                            arguments,
+                            params.meta.as_ref(),
+                        )
+                        .await;
+                    self.options.structured.apply(&mut result);
+                    return tool_result_response(id, result);
+                }
+                if let Err(message) = self.check_arg_size(&tool_name, &arguments).await {
+                    return Response::error(id, -32602, message);
+                }
+                let started = Instant::now();
+                let mut profile = CallProfile::default();
+                let mut result = match self
+                    .route_tool_call(
+                        session.connection.id(),
+                        &tool_name,
+                        arguments,
+                        params.meta.as_ref(),
+                        &mut profile,
+                    )
+                    .await
+                {
+                    Ok(result) => result,
                     Err(e) => {
                         error!(tool = %tool_name, error = %e, "use_tool failed");
-                        let result = CallToolResult {
-                            content: vec![Content::Text {
-                                text: format!("Error: {}", e),
-                            }],
-                            is_error: true,
-                        };
-                        success_or_internal_error(id, &result)
+                        error_text::call_failed(&e)
                     }
+                };
+                self.options.structured.apply(&mut result);
+                let routed = started.elapsed();
+                let response = tool_result_response(id, result);
+                if self.options.profile {
+                    let CallProfile {
+                        correlation_id,
+                        queued,
+                        backend,
+                    } = profile;
+                    let total = started.elapsed();
+                    debug!(
+                        correlation_id,
+                        tool = %tool_name,
+                        ?total,
+                        ?queued,
+                        ready = ?backend.ready,
+                        backend = ?backend.round_trip,
+                        serialize = ?(total - routed),
+                        // Routing, middleware and whatever else
+                        other = ?routed.saturating_sub(queued + backend.ready + backend.round_trip),
+                        "Call profile"
+                    );
                 }
+                response
             }
-            other => {
-                let result = CallToolResult {
-                    content: vec![Content::Text {
-                        text: format!(
-                            "Unknown tool '{}'. mcpd exposes two tools: list_tools and use_tool.",
-                            other
-                        ),
-                    }],
-                    is_error: true,
+            other => tool_result_response(
+                id,
+                error_text::tool_error(format!(
+                    "Unknown tool '{}'. mcpd exposes two tools: list_tools and use_tool.",
+                    other
+                )),
+            ),
+        }
+    }
+
+    /// How long a call with `meta` may wait for its backend: the client's
+    /// `TIMEOUT_META`, capped at `max_call_timeout`, else `call_timeout`
+    fn call_timeout(&self, meta: Option<&serde_json::Value>) -> Option<Duration> {
+        let asked = meta
+            .and_then(|m| m.get(TIMEOUT_META))
+            .and_then(|ms| ms.as_u64())
+            .filter(|&ms| ms > 0)
+            .map(Duration::from_millis);
+        match (asked, self.options.max_call_timeout) {
+            (Some(asked), Some(max)) => Some(asked.min(max)),
+            (Some(asked), None) => Some(asked),
+            (None, _) => self.options.call_timeout,
+        }
+    }
+
+    /// Call every member of merge group `group` at once, each as a routed
+    /// call of its own, and combine what they return (see `merge`)
+    async fn call_merge_group(
+        &self,
+        connection: u64,
+        group: &str,
+        members: Vec<merge::Member>,
+        arguments: serde_json::Value,
+        meta: Option<&serde_json::Value>,
+    ) -> CallToolResult {
+        let style = self.options.name_style;
+        let calls = members.into_iter().map(|member| {
+            let arguments = arguments.clone();
+            async move {
+                let tool_name = style.join(&member.backend, &member.tool);
+                let outcome = match self.check_arg_size(&tool_name, &arguments).await {
+                    Err(message) => Err(message),
+                    Ok(()) => {
+                        let mut profile = CallProfile::default();
+                        self.route_tool_call(connection, &tool_name, arguments, meta, &mut profile)
+                            .await
+                    }
                 };
-                success_or_internal_error(id, &result)
+                (member, outcome)
             }
+        });
+        let outcomes = futures::future::join_all(calls).await;
+        merge::combine(group, outcomes)
+    }
+
+    /// Refuse `arguments` for `tool_name` that serialize to more than its
+    /// backend's `max_arg_bytes` (else `serve --max-arg-bytes`). Names that
+    /// don't route anywhere pass, for `route_tool_call` to report.
+    async fn check_arg_size(
+        &self,
+        tool_name: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<(), String> {
+        let Some((proxy_name, _)) = self.options.name_style.split(tool_name) else {
+            return Ok(());
+        };
+        let own_limit = async || {
+            let proxies = self.proxies.read().await;
+            proxies.get(proxy_name).map(|p| p.tool().max_arg_bytes)
+        };
+        let own = match own_limit().await {
+            Some(limit) => limit,
+            // Not started yet: its registration may be newer than ours
+            None => {
+                let _ = self.sync_registry().await;
+                own_limit().await.flatten()
+            }
+        };
+        let Some(limit) = own.or(self.options.max_arg_bytes) else {
+            return Ok(());
+        };
+        let size = serde_json::to_vec(arguments).map_or(0, |bytes| bytes.len());
+        if size > limit {
+            warn!(tool = %tool_name, size, limit, "Rejected oversized arguments");
+            return Err(format!(
+                "Arguments for '{}' are {} bytes, over the {}-byte limit of server '{}'",
+                tool_name, size, limit, proxy_name
+            ));
         }
+        Ok(())
     }
 
     /// Namespace a backend URI into mcpd:// format, avoiding double-prefixing
@@ -338,15 +2390,42 @@ impl Server {
 
     /// Aggregate resources from all backends, namespacing URIs
     async fn handle_list_resources(&self, id: RequestId) -> Response {
+        if let Some(schema_only) = &self.options.schema_only {
+            let resources = schema_only
+                .catalog
+                .backends
+                .iter()
+                .flat_map(|backend| {
+                    backend.resources.iter().map(|resource| Resource {
+                        uri: Self::namespace_uri(&backend.name, &resource.uri),
+                        name: format!("{}__{}", backend.name, resource.name),
+                        ..resource.clone()
+                    })
+                })
+                .collect();
+            return success_or_internal_error(
+                id,
+                &ListResourcesResult {
+                    resources,
+                    meta: None,
+                },
+            );
+        }
         if let Err(e) = self.sync_registry().await {
             return Response::error(id, -32603, format!("Failed to ensure proxies: {}", e));
         }
 
         let proxies = self.proxies.read().await;
         let mut all_resources = Vec::new();
+        let mut unavailable = Vec::new();
 
         for (proxy_name, proxy) in proxies.iter() {
-            match proxy.list_resources().await {
+            let listing = proxy.list_resources().await;
+            let reached = listing
+                .as_ref()
+                .map_or_else(|e| !unreachable(proxy, e), |_| true);
+            self.track_listing(proxy_name, "notifications/resources/list_changed", reached);
+            match listing {
                 Ok(resources) => {
                     for mut resource in resources {
                         // Namespace the URI: mcpd://server/original-uri
@@ -355,6 +2434,10 @@ impl Server {
                         all_resources.push(resource);
                     }
                 }
+                Err(e) if !reached => {
+                    warn!(proxy = %proxy_name, error = %e, "Backend is unavailable, leaving its resources out");
+                    unavailable.push(proxy_name.clone());
+                }
                 Err(e) => {
                     debug!(proxy = %proxy_name, error = %e, "Backend doesn't support resources (skipping)");
                 }
@@ -365,59 +2448,68 @@ impl Server {
             count = all_resources.len(),
             "Aggregated resources from all backends"
         );
+        let meta = unavailable_meta(all_resources.is_empty(), unavailable);
         let result = ListResourcesResult {
             resources: all_resources,
+            meta,
         };
         success_or_internal_error(id, &result)
     }
 
-    /// Route a resources/read call to the appropriate backend
-    async fn handle_read_resource(&self, id: RequestId, params: ReadResourceParams) -> Response {
-        // Parse "mcpd://server/original-uri"
-        let uri = &params.uri;
-        let stripped = match uri.strip_prefix("mcpd://") {
-            Some(s) => s,
-            None => {
-                return Response::error(
-                    id,
-                    -32602,
-                    format!(
-                        "Invalid resource URI '{}'. Expected mcpd://server/uri format.",
-                        uri
-                    ),
-                );
-            }
-        };
+    /// Split "mcpd://server/original-uri" into the server and its own URI
+    fn split_resource_uri(uri: &str) -> Option<(&str, &str)> {
+        uri.strip_prefix("mcpd://")?.split_once('/')
+    }
 
-        let (proxy_name, original_uri) = match stripped.split_once('/') {
-            Some((name, rest)) => (name, rest),
-            None => {
-                return Response::error(
-                    id,
-                    -32602,
-                    format!(
-                        "Invalid resource URI '{}'. Expected mcpd://server/uri format.",
-                        uri
-                    ),
-                );
-            }
+    /// The backend owning a namespaced resource URI, and the URI it knows
+    /// the resource by. Errors are the response to send instead.
+    async fn resource_backend<'u>(
+        &self,
+        id: &RequestId,
+        uri: &'u str,
+    ) -> Result<(Arc<ToolProxy>, &'u str, &'u str), Response> {
+        if self.options.schema_only.is_some() {
+            return Err(Response::error(
+                id.clone(),
+                -32603,
+                "schema-only mode, resource not read: mcpd is serving a catalog file",
+            ));
+        }
+        let Some((proxy_name, original_uri)) = Self::split_resource_uri(uri) else {
+            return Err(Response::error(
+                id.clone(),
+                -32602,
+                format!(
+                    "Invalid resource URI '{}'. Expected mcpd://server/uri format.",
+                    uri
+                ),
+            ));
         };
 
-        let proxy = {
-            if let Err(e) = self.sync_registry().await {
-                return Response::error(id, -32603, format!("Failed to ensure proxies: {}", e));
-            }
-            let proxies = self.proxies.read().await;
-            match proxies.get(proxy_name).cloned() {
-                Some(p) => p,
-                None => {
-                    return Response::error(
-                        id,
-                        -32602,
-                        format!("Unknown server '{}' in resource URI.", proxy_name),
-                    );
-                }
-            }
+        if let Err(e) = self.sync_registry().await {
+            return Err(Response::error(
+                id.clone(),
+                -32603,
+                format!("Failed to ensure proxies: {}", e),
+            ));
+        }
+        let proxies = self.proxies.read().await;
+        match proxies.get(proxy_name).cloned() {
+            Some(proxy) => Ok((proxy, proxy_name, original_uri)),
+            None => Err(Response::error(
+                id.clone(),
+                -32602,
+                format!("Unknown server '{}' in resource URI.", proxy_name),
+            )),
+        }
+    }
+
+    /// Route a resources/read call to the appropriate backend
+    async fn handle_read_resource(&self, id: RequestId, params: ReadResourceParams) -> Response {
+        let (proxy, proxy_name, original_uri) = match self.resource_backend(&id, &params.uri).await
+        {
+            Ok(backend) => backend,
+            Err(response) => return response,
         };
 
         match proxy.read_resource(original_uri).await {
@@ -428,7 +2520,93 @@ impl Server {
                 }
                 success_or_internal_error(id, &result)
             }
-            Err(e) => Response::error(id, -32603, format!("Failed to read resource: {}", e)),
+            Err(e) => self.backend_error(id, "Failed to read resource", &e),
+        }
+    }
+
+    /// An internal-error answer for a backend request that failed with `e`
+    fn backend_error(&self, id: RequestId, context: &str, e: &anyhow::Error) -> Response {
+        Response::error(
+            id,
+            -32603,
+            self.options.error_text.describe(context, e, None),
+        )
+    }
+
+    /// Route a resources/subscribe call to the backend owning the resource.
+    /// The backend is subscribed once however many clients watch the URI.
+    async fn handle_subscribe(
+        &self,
+        session: &Session,
+        id: RequestId,
+        params: SubscribeParams,
+    ) -> Response {
+        let (proxy, proxy_name, original_uri) = match self.resource_backend(&id, &params.uri).await
+        {
+            Ok(backend) => backend,
+            Err(response) => return response,
+        };
+
+        // Start the backend before recording the watch, so its post-initialize
+        // resubscription doesn't subscribe this URI too. The watch is recorded
+        // before subscribing, since the backend may report an update right away.
+        if let Err(e) = proxy.ensure_ready().await {
+            return self.backend_error(id, "Failed to subscribe", &e);
+        }
+        let first = !self.subscriptions.is_watched(proxy_name, original_uri);
+        let connection = session.connection.id();
+        self.subscriptions.add(connection, proxy_name, original_uri);
+        if first && let Err(e) = proxy.subscribe_resource(original_uri).await {
+            self.subscriptions
+                .remove(connection, proxy_name, original_uri);
+            return self.backend_error(id, "Failed to subscribe", &e);
+        }
+        debug!(
+            backend = proxy_name,
+            uri = original_uri,
+            "Client subscribed to resource"
+        );
+        Response::success(id, json!({}))
+    }
+
+    /// Route a resources/unsubscribe call. The backend subscription ends
+    /// with its last watcher.
+    async fn handle_unsubscribe(
+        &self,
+        session: &Session,
+        id: RequestId,
+        params: SubscribeParams,
+    ) -> Response {
+        let (proxy, proxy_name, original_uri) = match self.resource_backend(&id, &params.uri).await
+        {
+            Ok(backend) => backend,
+            Err(response) => return response,
+        };
+
+        if self
+            .subscriptions
+            .remove(session.connection.id(), proxy_name, original_uri)
+            && let Err(e) = proxy.unsubscribe_resource(original_uri).await
+        {
+            return self.backend_error(id, "Failed to unsubscribe", &e);
+        }
+        Response::success(id, json!({}))
+    }
+
+    /// End the backend subscriptions a disconnected client was the last to watch
+    async fn drop_subscriptions(&self, connection: u64) {
+        let unwatched = self.subscriptions.close(connection);
+        if unwatched.is_empty() {
+            return;
+        }
+        let proxies = self.proxies.read().await;
+        for (backend, uri) in unwatched {
+            let Some(proxy) = proxies.get(&backend) else {
+                continue;
+            };
+            if let Err(e) = proxy.unsubscribe_resource(&uri).await {
+                debug!(backend, uri, error = %e, "Failed to unsubscribe after client left");
+            }
         }
     }
 
@@ -436,21 +2614,51 @@ impl Server {
 
     /// Aggregate prompts from all backends, namespacing names
     async fn handle_list_prompts(&self, id: RequestId) -> Response {
+        if let Some(schema_only) = &self.options.schema_only {
+            let prompts = schema_only
+                .catalog
+                .backends
+                .iter()
+                .flat_map(|backend| {
+                    backend.prompts.iter().map(|prompt| Prompt {
+                        name: format!("{}__{}", backend.name, prompt.name),
+                        ..prompt.clone()
+                    })
+                })
+                .collect();
+            return success_or_internal_error(
+                id,
+                &ListPromptsResult {
+                    prompts,
+                    meta: None,
+                },
+            );
+        }
         if let Err(e) = self.sync_registry().await {
             return Response::error(id, -32603, format!("Failed to ensure proxies: {}", e));
         }
 
         let proxies = self.proxies.read().await;
         let mut all_prompts = Vec::new();
+        let mut unavailable = Vec::new();
 
         for (proxy_name, proxy) in proxies.iter() {
-            match proxy.list_prompts().await {
+            let listing = proxy.list_prompts().await;
+            let reached = listing
+                .as_ref()
+                .map_or_else(|e| !unreachable(proxy, e), |_| true);
+            self.track_listing(proxy_name, "notifications/prompts/list_changed", reached);
+            match listing {
                 Ok(prompts) => {
                     for mut prompt in prompts {
                         prompt.name = format!("{}__{}", proxy_name, prompt.name);
                         all_prompts.push(prompt);
                     }
                 }
+                Err(e) if !reached => {
+                    warn!(proxy = %proxy_name, error = %e, "Backend is unavailable, leaving its prompts out");
+                    unavailable.push(proxy_name.clone());
+                }
                 Err(e) => {
                     debug!(proxy = %proxy_name, error = %e, "Backend doesn't support prompts (skipping)");
                 }
@@ -461,14 +2669,23 @@ impl Server {
             count = all_prompts.len(),
             "Aggregated prompts from all backends"
         );
+        let meta = unavailable_meta(all_prompts.is_empty(), unavailable);
         let result = ListPromptsResult {
             prompts: all_prompts,
+            meta,
         };
         success_or_internal_error(id, &result)
     }
 
     /// Route a prompts/get call to the appropriate backend
     async fn handle_get_prompt(&self, id: RequestId, params: GetPromptParams) -> Response {
+        if self.options.schema_only.is_some() {
+            return Response::error(
+                id,
+                -32603,
+                "schema-only mode, prompt not rendered: mcpd is serving a catalog file",
+            );
+        }
         let (proxy_name, original_name) = match params.name.split_once("__") {
             Some((server, name)) => (server.to_string(), name.to_string()),
             None => {
@@ -505,16 +2722,30 @@ impl Server {
 
         match proxy.get_prompt(&original_name, params.arguments).await {
             Ok(result) => success_or_internal_error(id, &result),
-            Err(e) => Response::error(id, -32603, format!("Failed to get prompt: {}", e)),
+            Err(e) => self.backend_error(id, "Failed to get prompt", &e),
         }
     }
 
     /// Handle a single request
-    async fn handle_request(&self, request: Request) -> Response {
+    async fn handle_request(&self, session: &Session, request: Request) -> Response {
         debug!(method = %request.method, id = ?request.id, "Handling request");
 
         match request.method.as_str() {
-            "initialize" => self.handle_initialize(request.id).await,
+            "initialize" => {
+                let capabilities = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("capabilities"))
+                    .and_then(|c| serde_json::from_value::<ClientCapabilities>(c.clone()).ok())
+                    .unwrap_or_default();
+                session
+                    .supports_roots
+                    .store(capabilities.roots.is_some(), Ordering::Relaxed);
+                if capabilities.logging.is_some() {
+                    session.connection.receive_logs();
+                }
+                self.handle_initialize(session, request.id).await
+            }
             "tools/list" => self.handle_list_tools(request.id).await,
             "tools/call" => {
                 let params: CallToolParams = match request.params {
@@ -532,11 +2763,51 @@ impl Server {
                         return Response::error(request.id, -32602, "Missing params");
                     }
                 };
-                self.handle_call_tool(request.id, params).await
+                self.handle_call_tool(session, request.id, params).await
+            }
+            "resources/list" => self.handle_list_resources(request.id).await,
+            "resources/read" => {
+                let params: ReadResourceParams = match request.params {
+                    Some(p) => match serde_json::from_value(p) {
+                        Ok(params) => params,
+                        Err(e) => {
+                            return Response::error(
+                                request.id,
+                                -32602,
+                                format!("Invalid params: {}", e),
+                            );
+                        }
+                    },
+                    None => {
+                        return Response::error(request.id, -32602, "Missing params");
+                    }
+                };
+                self.handle_read_resource(request.id, params).await
+            }
+            method @ ("resources/subscribe" | "resources/unsubscribe") => {
+                let params: SubscribeParams = match request.params {
+                    Some(p) => match serde_json::from_value(p) {
+                        Ok(params) => params,
+                        Err(e) => {
+                            return Response::error(
+                                request.id,
+                                -32602,
+                                format!("Invalid params: {}", e),
+                            );
+                        }
+                    },
+                    None => {
+                        return Response::error(request.id, -32602, "Missing params");
+                    }
+                };
+                if method == "resources/subscribe" {
+                    self.handle_subscribe(session, request.id, params).await
+                } else {
+                    self.handle_unsubscribe(session, request.id, params).await
+                }
             }
-            "resources/list" => self.handle_list_resources(request.id).await,
-            "resources/read" => {
-                let params: ReadResourceParams = match request.params {
+            "logging/setLevel" => {
+                let params: SetLevelParams = match request.params {
                     Some(p) => match serde_json::from_value(p) {
                         Ok(params) => params,
                         Err(e) => {
@@ -551,7 +2822,10 @@ impl Server {
                         return Response::error(request.id, -32602, "Missing params");
                     }
                 };
-                self.handle_read_resource(request.id, params).await
+                // Setting a level is asking for log messages
+                session.connection.receive_logs();
+                self.set_backend_log_level(params.level).await;
+                Response::success(request.id, json!({}))
             }
             "prompts/list" => self.handle_list_prompts(request.id).await,
             "prompts/get" => {
@@ -581,12 +2855,20 @@ impl Server {
     }
 
     /// Handle a notification (no response)
-    async fn handle_notification(&self, notification: Notification) {
+    async fn handle_notification(&self, session: &Session, notification: Notification) {
         debug!(method = %notification.method, "Handling notification");
 
         match notification.method.as_str() {
             "notifications/initialized" => {
                 info!("Client initialized");
+                if session.supports_roots.load(Ordering::Relaxed) {
+                    self.request_roots(session).await;
+                }
+            }
+            "notifications/roots/list_changed" => {
+                if session.supports_roots.load(Ordering::Relaxed) {
+                    self.request_roots(session).await;
+                }
             }
             "notifications/cancelled" => {
                 // Handle cancellation if needed
@@ -597,59 +2879,528 @@ impl Server {
         }
     }
 
+    /// Snapshot of backend states and in-flight calls
+    pub async fn snapshot(&self) -> Snapshot {
+        let (states, init_errors, mut instances): (Vec<_>, HashMap<_, _>, HashMap<_, _>) = {
+            let proxies = self.proxies.read().await;
+            let states = proxies
+                .iter()
+                .map(|(name, proxy)| (name.clone(), proxy.backend_state()))
+                .collect();
+            let init_errors = proxies
+                .iter()
+                .filter_map(|(name, proxy)| Some((name.clone(), proxy.init_failure()?.to_string())))
+                .collect();
+            let instances = proxies
+                .iter()
+                .map(|(name, proxy)| (name.clone(), proxy.instances()))
+                .collect();
+            (states, init_errors, instances)
+        };
+        let mut snapshot = self.activity.snapshot(&states);
+        let health = self.health.lock().unwrap().clone();
+        for backend in &mut snapshot.backends {
+            backend.init_error = init_errors.get(&backend.name).cloned();
+            backend.instances = instances.remove(&backend.name).unwrap_or_default();
+            backend.degraded = health
+                .get(&backend.name)
+                .filter(|(_, health)| health.is_degraded())
+                .map(|(_, health)| health.last_failure().unwrap_or_default().to_string());
+        }
+        if let Some(scheduler) = &self.scheduler {
+            snapshot.queues = scheduler.snapshot();
+        }
+        snapshot
+    }
+
+    /// Handle a request received on the control socket
+    pub async fn handle_control(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            "mcpd/inspect" => {
+                if let Err(e) = self.sync_registry().await {
+                    warn!(error = %e, "Failed to sync registry for inspect");
+                }
+                let snapshot = self.snapshot().await;
+                success_or_internal_error(request.id, &snapshot)
+            }
+            "mcpd/restart" => {
+                let Some(name) = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("backend"))
+                    .and_then(|v| v.as_str())
+                else {
+                    return Response::error(request.id, -32602, "Missing params.backend");
+                };
+                if !self.proxies.read().await.contains_key(name) {
+                    return Response::error(
+                        request.id,
+                        -32602,
+                        format!("Unknown backend '{}'", name),
+                    );
+                }
+                info!(tool = %name, "Restart requested via control socket");
+                match self.restart_backend(name).await {
+                    // Non-warm restarts start again lazily on the next call
+                    Ok(zero_downtime) => Response::success(
+                        request.id,
+                        json!({"restarted": name, "zero_downtime": zero_downtime}),
+                    ),
+                    Err(e) => Response::error(request.id, -32603, format!("Restart failed: {}", e)),
+                }
+            }
+            "mcpd/addBackend" => {
+                #[derive(serde::Deserialize)]
+                struct AddBackend {
+                    #[serde(flatten)]
+                    tool: Tool,
+                    #[serde(default)]
+                    ephemeral: bool,
+                }
+                let params = request.params.unwrap_or(serde_json::Value::Null);
+                let add = match serde_json::from_value::<AddBackend>(params) {
+                    Ok(add) => add,
+                    Err(e) => {
+                        return Response::error(
+                            request.id,
+                            -32602,
+                            format!("Invalid params: {}", e),
+                        );
+                    }
+                };
+                let name = add.tool.name.clone();
+                match self.add_backend(add.tool, add.ephemeral).await {
+                    Ok(()) => Response::success(
+                        request.id,
+                        json!({"added": name, "ephemeral": add.ephemeral}),
+                    ),
+                    Err(e) => Response::error(request.id, -32602, format!("{:#}", e)),
+                }
+            }
+            "mcpd/register" => {
+                let params = request.params.unwrap_or(serde_json::Value::Null);
+                let tool = match serde_json::from_value::<Tool>(params) {
+                    Ok(tool) => tool,
+                    Err(e) => {
+                        return Response::error(
+                            request.id,
+                            -32602,
+                            format!("Invalid params: {}", e),
+                        );
+                    }
+                };
+                let name = tool.name.clone();
+                match self.register_backend(tool).await {
+                    Ok(()) => Response::success(request.id, json!({"registered": name})),
+                    Err(e) => Response::error(request.id, -32602, format!("{:#}", e)),
+                }
+            }
+            "mcpd/unregister" => {
+                let Some(name) = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("backend"))
+                    .and_then(|v| v.as_str())
+                else {
+                    return Response::error(request.id, -32602, "Missing params.backend");
+                };
+                match self.unregister_backend(name).await {
+                    Ok(unregistered) => {
+                        Response::success(request.id, json!({"unregistered": unregistered}))
+                    }
+                    Err(e) => Response::error(request.id, -32603, format!("{:#}", e)),
+                }
+            }
+            "mcpd/rename" => {
+                #[derive(serde::Deserialize)]
+                struct Rename {
+                    backend: String,
+                    name: String,
+                }
+                let params = request.params.unwrap_or(serde_json::Value::Null);
+                let rename = match serde_json::from_value::<Rename>(params) {
+                    Ok(rename) => rename,
+                    Err(e) => {
+                        return Response::error(
+                            request.id,
+                            -32602,
+                            format!("Invalid params: {}", e),
+                        );
+                    }
+                };
+                match self.rename_backend(&rename.backend, &rename.name).await {
+                    Ok(()) => Response::success(request.id, json!({"renamed": true})),
+                    Err(e) => Response::error(request.id, -32602, format!("{:#}", e)),
+                }
+            }
+            "mcpd/reorder" => {
+                #[derive(serde::Deserialize)]
+                struct Reorder {
+                    backend: String,
+                    position: Position,
+                }
+                let params = request.params.unwrap_or(serde_json::Value::Null);
+                let reorder = match serde_json::from_value::<Reorder>(params) {
+                    Ok(reorder) => reorder,
+                    Err(e) => {
+                        return Response::error(
+                            request.id,
+                            -32602,
+                            format!("Invalid params: {}", e),
+                        );
+                    }
+                };
+                match self
+                    .reorder_backend(&reorder.backend, &reorder.position)
+                    .await
+                {
+                    Ok(order) => Response::success(request.id, json!({"order": order})),
+                    Err(e) => Response::error(request.id, -32602, format!("{:#}", e)),
+                }
+            }
+            "mcpd/removeBackend" => {
+                let Some(name) = request
+                    .params
+                    .as_ref()
+                    .and_then(|p| p.get("backend"))
+                    .and_then(|v| v.as_str())
+                else {
+                    return Response::error(request.id, -32602, "Missing params.backend");
+                };
+                match self.remove_backend(name).await {
+                    Ok(ephemeral) => Response::success(
+                        request.id,
+                        json!({"removed": name, "ephemeral": ephemeral}),
+                    ),
+                    Err(e) => Response::error(request.id, -32602, format!("{:#}", e)),
+                }
+            }
+            _ => Response::error(
+                request.id,
+                -32601,
+                format!("Unknown control method: {}", request.method),
+            ),
+        }
+    }
+
+    /// Accept control connections until the server shuts down. Each connection
+    /// carries a single request, so connections are handled one at a time.
+    #[cfg(unix)]
+    async fn serve_control(&self, listener: &tokio::net::UnixListener) {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!(error = %e, "Control socket accept failed");
+                    continue;
+                }
+            };
+            // The socket controls which commands mcpd runs, so only its own user may use it
+            if !crate::control::same_user(&stream) {
+                warn!("Rejected control connection from another user");
+                continue;
+            }
+
+            let (read, mut write) = stream.into_split();
+            let mut reader = BufReader::new(read);
+            let mut buf = Vec::new();
+            let read = tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                limits::read_line_bounded(&mut reader, &mut buf, 64 * 1024),
+            )
+            .await;
+            if !matches!(read, Ok(Ok(ReadLine::Line))) {
+                continue;
+            }
+
+            let response = match serde_json::from_slice::<Request>(&buf) {
+                Ok(request) => self.handle_control(request).await,
+                Err(e) => Response::error(RequestId::Null, -32700, format!("Parse error: {}", e)),
+            };
+            if let Ok(mut line) = serde_json::to_string(&response) {
+                line.push('\n');
+                let _ = write.write_all(line.as_bytes()).await;
+            }
+        }
+    }
+
+    /// Bind this process's control socket, if possible
+    #[cfg(unix)]
+    async fn bind_control(&self) -> Option<(tokio::net::UnixListener, std::path::PathBuf)> {
+        // Nothing else can find a server whose registry has no file, and
+        // registry changes sent to it would be lost when it exits
+        let path = match self.registry.read().await.path() {
+            Some(registry) => crate::control::socket_path(registry),
+            None => {
+                debug!("Registry held in memory, not binding a control socket");
+                return None;
+            }
+        };
+        if let Some(dir) = path.parent()
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            warn!(error = %e, "Failed to create control socket directory");
+            return None;
+        }
+        let _ = std::fs::remove_file(&path);
+        match tokio::net::UnixListener::bind(&path) {
+            Ok(listener) => {
+                debug!(path = %path.display(), "Control socket listening");
+                Some((listener, path))
+            }
+            Err(e) => {
+                warn!(error = %e, path = %path.display(), "Failed to bind control socket");
+                None
+            }
+        }
+    }
+
     /// Run the server on stdio
     pub async fn run(&self) -> Result<()> {
-        let stdin = tokio::io::stdin();
-        let mut reader = BufReader::new(stdin);
-
         info!("MCP server starting on stdio");
 
-        loop {
-            let mut line = String::new();
-            let bytes_read = reader.read_line(&mut line).await?;
+        #[cfg(unix)]
+        let result = match self.bind_control().await {
+            Some((listener, path)) => {
+                let result = tokio::select! {
+                    r = self.serve_stdio() => r,
+                    _ = self.serve_control(&listener) => Ok(()),
+                };
+                let _ = std::fs::remove_file(path);
+                result
+            }
+            None => self.serve_stdio().await,
+        };
+        #[cfg(not(unix))]
+        let result = self.serve_stdio().await;
 
-            if bytes_read == 0 {
-                info!("EOF received, shutting down");
-                break;
+        self.stop_all().await;
+        result
+    }
+
+    /// Serve the stdio client, running health checks alongside
+    async fn serve_stdio(&self) -> Result<()> {
+        tokio::select! {
+            r = self.serve_transport(Stdio) => r,
+            _ = self.health_loop() => Ok(()),
+            _ = self.watchdog_loop() => Ok(()),
+        }
+    }
+
+    /// Run as a long-lived daemon accepting any number of clients on a unix
+    /// socket. All clients share the same backend proxies. Returns on ctrl-c.
+    #[cfg(unix)]
+    pub async fn run_daemon(self: Arc<Self>, listener: tokio::net::UnixListener) -> Result<()> {
+        info!("MCP daemon accepting connections");
+
+        let control = self.bind_control().await;
+        let accept = async {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let server = Arc::clone(&self);
+                        tokio::spawn(async move {
+                            if let Err(e) = server.serve_transport(stream).await {
+                                warn!(error = %e, "Client connection failed");
+                            }
+                        });
+                    }
+                    Err(e) => warn!(error = %e, "Accept failed"),
+                }
+            }
+        };
+        let control_loop = async {
+            match control {
+                Some((ref listener, _)) => self.serve_control(listener).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            _ = accept => {}
+            _ = control_loop => {}
+            _ = self.health_loop() => {}
+            _ = self.watchdog_loop() => {}
+            r = tokio::signal::ctrl_c() => {
+                r?;
+                info!("Interrupted, shutting down");
+            }
+        }
+
+        if let Some((_, path)) = &control {
+            let _ = std::fs::remove_file(path);
+        }
+        self.stop_all().await;
+        Ok(())
+    }
+
+    /// Stop every backend subprocess and forget ephemeral backends
+    pub async fn stop_all(&self) {
+        self.ephemeral.lock().unwrap().clear();
+        let proxies = self.proxies.read().await;
+        for proxy in proxies.values() {
+            let _ = proxy.stop().await;
+        }
+    }
+
+    /// Serve one client connection until it disconnects
+    pub async fn serve_transport(&self, transport: impl Transport) -> Result<()> {
+        let (reader, writer) = transport.split();
+        let (connection, writer_task) = self.connections.open(writer);
+        let id = connection.id();
+        let session = Session {
+            connection,
+            supports_roots: AtomicBool::new(false),
+            roots_request: std::sync::Mutex::new(None),
+            next_request: AtomicU64::new(1),
+        };
+        debug!(
+            session = id,
+            clients = self.connections.len(),
+            "Client connected"
+        );
+
+        let (lines, reader_task) = read_client(reader, self.options.json_limits.max_bytes);
+        let result = self.serve_session(&session, lines).await;
+        reader_task.abort();
+
+        self.connections.close(id);
+        self.drop_subscriptions(id).await;
+        drop(session);
+        // Let the writer finish sending whatever is still queued
+        let _ = writer_task.await;
+        debug!(session = id, "Client disconnected");
+        result
+    }
+
+    /// Dispatch messages from one client until EOF. A client that disconnects
+    /// while a request is being handled ends the session at once; dropping
+    /// the request cancels whatever backend calls it was waiting on.
+    async fn serve_session(
+        &self,
+        session: &Session,
+        mut lines: mpsc::Receiver<std::io::Result<ClientLine>>,
+    ) -> Result<()> {
+        let max_bytes = self.options.json_limits.max_bytes;
+        // Lines that arrived while a request was being handled
+        let mut queued = VecDeque::new();
+        loop {
+            let next = match queued.pop_front() {
+                Some(line) => Some(line),
+                None => lines.recv().await,
+            };
+            let (read, buf) = next.unwrap_or(Ok((ReadLine::Eof, Vec::new())))?;
+
+            let violation = match read {
+                ReadLine::Eof => {
+                    info!(
+                        session = session.connection.id(),
+                        "EOF received, closing session"
+                    );
+                    break;
+                }
+                ReadLine::TooLong { size } => Some(LimitViolation::TooLarge {
+                    size,
+                    max: max_bytes,
+                }),
+                ReadLine::Line => self.options.json_limits.check_depth(&buf).err(),
+            };
+
+            if let Some(violation) = violation {
+                warn!(error = %violation, "Rejected message from client");
+                let id = limits::peek_id(&buf).unwrap_or(RequestId::Null);
+                let response =
+                    Response::error(id, -32600, format!("Invalid request: {}", violation));
+                session.connection.respond(response).await?;
+                continue;
             }
 
-            let line = line.trim();
+            let text = String::from_utf8_lossy(&buf);
+            let line = text.trim();
             if line.is_empty() {
                 continue;
             }
 
-            debug!(line = %line, "Received message");
+            debug!(line = %limits::truncate_for_log(line, 500), "Received message");
 
             // Try to parse as request first
             if let Ok(request) = serde_json::from_str::<Request>(line) {
-                let response = self.handle_request(request).await;
-                let mut response_line = serde_json::to_string(&response)?;
-                response_line.push('\n');
-                let mut stdout = self.stdout.lock().await;
-                stdout.write_all(response_line.as_bytes()).await?;
-                stdout.flush().await?;
+                let span = info_span!("handle_request", method = %request.method, session = session.connection.id());
+                let meta = request.params.as_ref().and_then(|p| p.get("_meta"));
+                if let Some(parent) = TraceParent::from_meta(meta) {
+                    telemetry::set_parent(&span, &parent);
+                }
+                let handling = self.handle_request(session, request).instrument(span);
+                tokio::pin!(handling);
+                let response = loop {
+                    tokio::select! {
+                        response = &mut handling => break Some(response),
+                        line = lines.recv(), if queued.len() < CLIENT_LOOKAHEAD => match line {
+                            Some(Ok((ReadLine::Eof, _))) | None => break None,
+                            Some(line) => queued.push_back(line),
+                        },
+                    }
+                };
+                let Some(response) = response else {
+                    info!(
+                        session = session.connection.id(),
+                        "Client disconnected mid-request, cancelling its backend calls"
+                    );
+                    break;
+                };
+                session.connection.respond(response).await?;
                 continue;
             }
 
             // Try as notification
             if let Ok(notification) = serde_json::from_str::<Notification>(line) {
-                self.handle_notification(notification).await;
+                self.handle_notification(session, notification).await;
                 continue;
             }
 
-            warn!(line = %line, "Failed to parse message");
-        }
+            // Answers to requests we sent the client (roots/list)
+            if let Ok(response) = serde_json::from_str::<Response>(line) {
+                self.handle_client_response(session, response).await;
+                continue;
+            }
 
-        // Clean up proxies
-        let proxies = self.proxies.read().await;
-        for proxy in proxies.values() {
-            let _ = proxy.stop().await;
+            warn!(line = %limits::truncate_for_log(line, 200), "Failed to parse message");
         }
 
         Ok(())
     }
 }
 
+/// One message read from a client, or the end of its input
+type ClientLine = (ReadLine, Vec<u8>);
+
+/// Lines read ahead of the request being handled, so a disconnect behind
+/// them is still noticed
+const CLIENT_LOOKAHEAD: usize = 64;
+
+/// Read the client's messages on a task of their own, so EOF is seen even
+/// while a request is still being handled
+fn read_client(
+    mut reader: crate::transport::ClientReader,
+    max_bytes: usize,
+) -> (
+    mpsc::Receiver<std::io::Result<ClientLine>>,
+    tokio::task::JoinHandle<()>,
+) {
+    let (tx, rx) = mpsc::channel(1);
+    let task = tokio::spawn(async move {
+        loop {
+            let mut buf = Vec::new();
+            let read = limits::read_line_bounded(&mut reader, &mut buf, max_bytes).await;
+            let last = !matches!(read, Ok(ReadLine::Line | ReadLine::TooLong { .. }));
+            if tx.send(read.map(|read| (read, buf))).await.is_err() || last {
+                break;
+            }
+        }
+    });
+    (rx, task)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -674,6 +3425,212 @@ mod tests {
         assert_eq!(result, "mcpd://srv/");
     }
 
+    #[test]
+    fn merge_instructions_empty() {
+        assert!(merge_instructions(vec![]).is_none());
+    }
+
+    #[test]
+    fn merge_instructions_sorted_sections() {
+        let merged = merge_instructions(vec![
+            ("zeta".to_string(), "Use z.\n".to_string()),
+            ("alpha".to_string(), "Use a.".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(merged, "## alpha\nUse a.\n\n## zeta\nUse z.");
+    }
+
+    fn test_server(options: ServeOptions) -> Server {
+        let registry = Registry::load_from("/nonexistent/registry.json".into()).unwrap();
+        Server::with_options(registry, options)
+    }
+
+    fn default_patterns() -> Vec<String> {
+        DEFAULT_DESTRUCTIVE_PATTERNS
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tool_list_text_unchanged() {
+        let tools = vec![
+            (
+                "fs__read_file".to_string(),
+                McpTool {
+                    name: "read_file".to_string(),
+                    description: Some("Read a \"file\"\nfrom disk".to_string()),
+                    input_schema: json!({
+                        "type": "object",
+                        "properties": {"path": {"type": "string"}, "lines": {"type": "integer"}},
+                        "required": ["path"]
+                    }),
+                    title: None,
+                    annotations: None,
+                },
+            ),
+            (
+                "git__status".to_string(),
+                McpTool {
+                    name: "status".to_string(),
+                    description: None,
+                    input_schema: serde_json::Value::Null,
+                    title: None,
+                    annotations: None,
+                },
+            ),
+        ];
+        // What list_tools used to build: one JSON value per tool
+        let values: Vec<_> = tools
+            .iter()
+            .map(|(name, tool)| {
+                json!({
+                    "name": name,
+                    "description": tool.description.clone().unwrap_or_default(),
+                    "input_schema": tool.input_schema,
+                })
+            })
+            .collect();
+        assert_eq!(
+            render_tool_list(&tools).unwrap(),
+            serde_json::to_string_pretty(&values).unwrap()
+        );
+        assert_eq!(render_tool_list(&[]).unwrap(), "[]");
+    }
+
+    #[test]
+    fn tool_limits() {
+        let mut tools: Vec<u32> = (0..5).collect();
+        assert!(!limit_tools(&mut tools, Some(5), None));
+        assert!(limit_tools(&mut tools, Some(4), None));
+        assert_eq!(tools.len(), 5);
+        assert!(!limit_tools(&mut tools, None, None));
+
+        // The cap keeps the first entries, and the warning counts the full list
+        assert!(limit_tools(&mut tools, Some(3), Some(2)));
+        assert_eq!(tools, vec![0, 1]);
+        assert!(!limit_tools(&mut tools, Some(3), Some(10)));
+        assert_eq!(tools, vec![0, 1]);
+    }
+
+    #[test]
+    fn destructive_default_patterns() {
+        let patterns = default_patterns();
+        assert!(matches_destructive("delete_file", &patterns));
+        assert!(matches_destructive("WriteFile", &patterns));
+        assert!(matches_destructive("create_issue", &patterns));
+        assert!(matches_destructive("exec_command", &patterns));
+        assert!(!matches_destructive("read_file", &patterns));
+        assert!(!matches_destructive("search", &patterns));
+    }
+
+    #[test]
+    fn destructive_override_patterns() {
+        let patterns = vec!["drop".to_string()];
+        assert!(matches_destructive("drop_table", &patterns));
+        assert!(!matches_destructive("delete_file", &patterns));
+    }
+
+    #[test]
+    fn destructive_ignores_empty_pattern() {
+        assert!(!matches_destructive("anything", &["".to_string()]));
+    }
+
+    #[test]
+    fn backend_title_prefixes_tool_titles() {
+        let mut tool = McpTool {
+            name: "create_issue".to_string(),
+            title: None,
+            description: None,
+            input_schema: json!({"type": "object"}),
+            annotations: None,
+        };
+        assert_eq!(titled("GitHub", &tool), "[GitHub] create_issue");
+        tool.annotations = Some(crate::mcp::ToolAnnotations {
+            title: Some("Create issue".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(titled("GitHub", &tool), "[GitHub] Create issue");
+
+        tool.title = Some(titled("GitHub", &tool));
+        let text = render_tool_list(&[("gh2__create_issue".to_string(), tool)]).unwrap();
+        let listed: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(listed[0]["title"], "[GitHub] Create issue");
+    }
+
+    #[test]
+    fn dedupe_keeps_first_of_each_name() {
+        let tool = |name: &str, description: &str| McpTool {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            input_schema: json!({"type": "object"}),
+            title: None,
+            annotations: None,
+        };
+        let mut tools = vec![
+            tool("read", "first"),
+            tool("write", "only"),
+            tool("read", "second"),
+            tool("read", "third"),
+        ];
+        assert_eq!(dedupe_tools(&mut tools), ["read"]);
+        let kept: Vec<_> = tools
+            .iter()
+            .map(|t| (t.name.as_str(), t.description.as_deref().unwrap()))
+            .collect();
+        assert_eq!(kept, [("read", "first"), ("write", "only")]);
+    }
+
+    #[test]
+    fn read_only_disabled_blocks_nothing() {
+        let server = test_server(ServeOptions::default());
+        assert!(!server.is_blocked("delete_file"));
+    }
+
+    #[test]
+    fn read_only_enabled_blocks_destructive() {
+        let options = ServeOptions {
+            read_only_patterns: Some(default_patterns()),
+            ..Default::default()
+        };
+        let server = test_server(options);
+        assert!(server.is_blocked("delete_file"));
+        assert!(!server.is_blocked("read_file"));
+    }
+
+    #[tokio::test]
+    async fn control_inspect_returns_snapshot() {
+        let server = test_server(ServeOptions::default());
+        let response = server
+            .handle_control(Request::new(1_i64, "mcpd/inspect", None))
+            .await;
+        let snapshot: Snapshot = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert!(snapshot.backends.is_empty());
+        assert_eq!(snapshot.pid, std::process::id());
+    }
+
+    #[tokio::test]
+    async fn control_restart_unknown_backend() {
+        let server = test_server(ServeOptions::default());
+        let response = server
+            .handle_control(Request::new(
+                1_i64,
+                "mcpd/restart",
+                Some(json!({"backend": "nope"})),
+            ))
+            .await;
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn control_unknown_method() {
+        let server = test_server(ServeOptions::default());
+        let response = server
+            .handle_control(Request::new(1_i64, "mcpd/bogus", None))
+            .await;
+        assert_eq!(response.error.unwrap().code, -32601);
+    }
+
     #[test]
     fn success_or_internal_error_with_valid_value() {
         let id = RequestId::Number(1);
@@ -700,4 +3657,201 @@ mod tests {
         assert_eq!(err.code, -32603);
         assert!(err.message.contains("Serialization failed"));
     }
+
+    #[test]
+    fn log_messages_without_takers_go_to_tracing() {
+        let connections = Connections::new();
+        let log = Notification {
+            params: Some(json!({"level": "error", "data": "boom"})),
+            ..Notification::new("notifications/message")
+        };
+        assert!(!relay_log(&connections, "fs", log));
+        let malformed = Notification {
+            params: Some(json!({"level": "loud"})),
+            ..Notification::new("notifications/message")
+        };
+        assert!(!relay_log(&connections, "fs", malformed));
+    }
+
+    #[test]
+    fn non_string_map_keys_become_internal_error() {
+        // JSON object keys must be strings; serde_json refuses tuple keys
+        let result: HashMap<(u8, u8), &str> = [((1, 2), "x")].into_iter().collect();
+        let response = success_or_internal_error(RequestId::Number(7), &result);
+        let err = response.error.unwrap();
+        assert_eq!(err.code, -32603);
+        assert!(
+            err.message.contains("key must be a string"),
+            "{}",
+            err.message
+        );
+        assert_eq!(response.id, RequestId::Number(7));
+    }
+
+    /// A client end of an in-memory session: lines written to `input` reach
+    /// the server, and its messages come out of `output`
+    struct Client {
+        input: tokio::io::DuplexStream,
+        output: BufReader<tokio::io::DuplexStream>,
+        session: tokio::task::JoinHandle<Result<()>>,
+    }
+
+    impl Client {
+        fn connect(server: &Arc<Server>) -> Self {
+            let (input, server_input) = tokio::io::duplex(1 << 20);
+            let (server_output, output) = tokio::io::duplex(1 << 20);
+            let server = Arc::clone(server);
+            let session =
+                tokio::spawn(
+                    async move { server.serve_transport((server_input, server_output)).await },
+                );
+            Self {
+                input,
+                output: BufReader::new(output),
+                session,
+            }
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.input.write_all(line.as_bytes()).await.unwrap();
+            self.input.write_all(b"\n").await.unwrap();
+        }
+
+        async fn recv(&mut self) -> serde_json::Value {
+            use tokio::io::AsyncBufReadExt;
+            let mut line = String::new();
+            let read =
+                tokio::time::timeout(Duration::from_secs(5), self.output.read_line(&mut line));
+            read.await.expect("no message from the server").unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        async fn request(&mut self, id: i64, method: &str) -> serde_json::Value {
+            let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {}});
+            self.send(&request.to_string()).await;
+            let response = self.recv().await;
+            assert_eq!(response["id"], id, "{}", response);
+            response
+        }
+    }
+
+    fn in_memory_server() -> Arc<Server> {
+        Arc::new(test_server(ServeOptions::default()))
+    }
+
+    #[tokio::test]
+    async fn session_answers_requests_in_order() {
+        let server = in_memory_server();
+        let mut client = Client::connect(&server);
+
+        let init = client.request(1, "initialize").await;
+        assert_eq!(init["result"]["serverInfo"]["name"], "mcpd");
+        assert_eq!(
+            init["result"]["capabilities"]["prompts"]["listChanged"],
+            true
+        );
+
+        // Notifications get no answer
+        client
+            .send(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#)
+            .await;
+        let tools = client.request(2, "tools/list").await;
+        let names: Vec<&str> = tools["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["list_tools", "use_tool"]);
+
+        let unknown = client.request(3, "bogus/method").await;
+        assert_eq!(unknown["error"]["code"], -32601);
+    }
+
+    #[tokio::test]
+    async fn session_survives_unparseable_and_batched_messages() {
+        let server = in_memory_server();
+        let mut client = Client::connect(&server);
+
+        // Neither is answered; the next request is
+        client.send("this is not json").await;
+        client
+            .send(r#"[{"jsonrpc":"2.0","id":1,"method":"tools/list"}]"#)
+            .await;
+        client.send(r#"{"jsonrpc":"2.0"}"#).await;
+        client.send("").await;
+        let tools = client.request(2, "tools/list").await;
+        assert!(tools["result"]["tools"].is_array());
+    }
+
+    #[tokio::test]
+    async fn session_answers_pathological_messages_with_errors() {
+        let server = in_memory_server();
+        let mut client = Client::connect(&server);
+
+        let depth = 100_000;
+        let deep = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"tools/list","params":{}{}}}"#,
+            "[".repeat(depth),
+            "]".repeat(depth)
+        );
+        let wide = format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"tools/list","params":{{"pad":[{}]}}}}"#,
+            vec!["0"; 100_000].join(",")
+        );
+        let huge = format!(
+            r#"{{"jsonrpc":"2.0","id":3,"method":"tools/list","params":{{"pad":"{}"}}}}"#,
+            "x".repeat(server.options.json_limits.max_bytes)
+        );
+        client.send(&deep).await;
+        let deep = client.recv().await;
+        assert_eq!(deep["id"], 1);
+        assert_eq!(deep["error"]["code"], -32600);
+
+        client.send(&wide).await;
+        let wide = client.recv().await;
+        assert_eq!(wide["id"], 2);
+        assert!(wide["result"].is_object());
+
+        client.send(&huge).await;
+        let huge = client.recv().await;
+        assert_eq!(huge["id"], 3);
+        assert_eq!(huge["error"]["code"], -32600);
+
+        let init = client.request(4, "initialize").await;
+        assert_eq!(init["result"]["serverInfo"]["name"], "mcpd");
+    }
+
+    #[tokio::test]
+    async fn closing_input_ends_the_session_after_pending_output() {
+        use tokio::io::AsyncBufReadExt;
+
+        let server = in_memory_server();
+        let mut client = Client::connect(&server);
+        client.request(1, "initialize").await;
+        assert_eq!(server.connections.len(), 1);
+
+        client.input.shutdown().await.unwrap();
+        client.session.await.unwrap().unwrap();
+        assert_eq!(server.connections.len(), 0);
+        // The writer was flushed and closed, not abandoned
+        let mut rest = String::new();
+        assert_eq!(client.output.read_line(&mut rest).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_independent() {
+        let server = in_memory_server();
+        let mut a = Client::connect(&server);
+        let mut b = Client::connect(&server);
+        a.request(1, "initialize").await;
+        b.request(1, "initialize").await;
+        assert_eq!(server.connections.len(), 2);
+
+        a.input.shutdown().await.unwrap();
+        a.session.await.unwrap().unwrap();
+        let tools = b.request(2, "tools/list").await;
+        assert!(tools["result"]["tools"].is_array());
+        assert_eq!(server.connections.len(), 1);
+    }
 }
@@ -0,0 +1,158 @@
+//! Compatibility for `structuredContent` in tool results. A backend may
+//! answer with only `structuredContent` and an empty `content` array, which
+//! older clients that read only content blocks take for an empty result;
+//! clients that reject unknown fields fail on it instead.
+
+use crate::limits::truncate_for_log;
+use crate::mcp::{CallToolResult, Content};
+
+/// Longest text rendering synthesized from `structuredContent`, in characters
+pub const FALLBACK_MAX_CHARS: usize = 100_000;
+
+/// How `structuredContent` in tool results is adapted for clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructuredCompat {
+    /// Add a text rendering to results that have `structuredContent` but no
+    /// text content (`serve --structured-fallback`, on by default)
+    pub fallback: bool,
+    /// Remove `structuredContent`, rendering it as text first if nothing
+    /// else would show it (`serve --strip-structured`)
+    pub strip: bool,
+}
+
+impl Default for StructuredCompat {
+    fn default() -> Self {
+        Self {
+            fallback: true,
+            strip: false,
+        }
+    }
+}
+
+impl StructuredCompat {
+    /// Adapt a result on its way to the client
+    pub fn apply(&self, result: &mut CallToolResult) {
+        let Some(structured) = &result.structured_content else {
+            return;
+        };
+        let has_text = result
+            .content
+            .iter()
+            .any(|content| matches!(content, Content::Text { .. }));
+        if (self.fallback || self.strip) && !has_text {
+            let text = serde_json::to_string_pretty(structured).unwrap_or_default();
+            result.content.push(Content::Text {
+                text: truncate_for_log(&text, FALLBACK_MAX_CHARS),
+            });
+        }
+        if self.strip {
+            result.structured_content = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result(value: serde_json::Value) -> CallToolResult {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn only_structured() -> CallToolResult {
+        result(json!({"content": [], "structuredContent": {"temperature": 21}}))
+    }
+
+    fn both() -> CallToolResult {
+        result(json!({
+            "content": [{"type": "text", "text": "21 degrees"}],
+            "structuredContent": {"temperature": 21}
+        }))
+    }
+
+    fn neither() -> CallToolResult {
+        result(json!({"content": [{"type": "text", "text": "21 degrees"}]}))
+    }
+
+    fn texts(result: &CallToolResult) -> Vec<&str> {
+        result
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                Content::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    const FALLBACK: StructuredCompat = StructuredCompat {
+        fallback: true,
+        strip: false,
+    };
+    const STRIP: StructuredCompat = StructuredCompat {
+        fallback: false,
+        strip: true,
+    };
+    const OFF: StructuredCompat = StructuredCompat {
+        fallback: false,
+        strip: false,
+    };
+
+    #[test]
+    fn fallback_renders_structured_only_results_as_text() {
+        let mut r = only_structured();
+        FALLBACK.apply(&mut r);
+        assert_eq!(texts(&r), ["{\n  \"temperature\": 21\n}"]);
+        assert_eq!(r.structured_content, Some(json!({"temperature": 21})));
+    }
+
+    #[test]
+    fn fallback_leaves_results_with_text_alone() {
+        for make in [both, neither] {
+            let mut r = make();
+            FALLBACK.apply(&mut r);
+            assert_eq!(texts(&r), ["21 degrees"]);
+            assert_eq!(r.structured_content, make().structured_content);
+        }
+    }
+
+    #[test]
+    fn fallback_text_is_capped() {
+        let mut r = result(json!({
+            "content": [],
+            "structuredContent": {"blob": "x".repeat(FALLBACK_MAX_CHARS * 2)}
+        }));
+        FALLBACK.apply(&mut r);
+        let [text] = texts(&r)[..] else {
+            panic!("expected one text block");
+        };
+        assert!(text.len() < FALLBACK_MAX_CHARS + 100);
+        assert!(text.ends_with("more chars)"));
+    }
+
+    #[test]
+    fn strip_keeps_a_text_rendering() {
+        let mut r = only_structured();
+        STRIP.apply(&mut r);
+        assert_eq!(texts(&r), ["{\n  \"temperature\": 21\n}"]);
+        assert_eq!(r.structured_content, None);
+
+        let mut r = both();
+        STRIP.apply(&mut r);
+        assert_eq!(texts(&r), ["21 degrees"]);
+        assert_eq!(r.structured_content, None);
+
+        let mut r = neither();
+        STRIP.apply(&mut r);
+        assert_eq!(texts(&r), ["21 degrees"]);
+    }
+
+    #[test]
+    fn switched_off_changes_nothing() {
+        let mut r = only_structured();
+        OFF.apply(&mut r);
+        assert!(r.content.is_empty());
+        assert_eq!(r.structured_content, Some(json!({"temperature": 21})));
+    }
+}
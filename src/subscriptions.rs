@@ -0,0 +1,153 @@
+//! Resource subscriptions clients hold through mcpd.
+//!
+//! Each backend is subscribed to a URI once, however many clients watch it.
+//! The table is shared with the proxies, so a backend that restarts (or is
+//! replaced) subscribes again to everything still being watched, and
+//! `notifications/resources/updated` is relayed only to the connections
+//! watching that URI.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+/// Who watches what: backend name → backend URI → connection ids
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    by_backend: Mutex<HashMap<String, BTreeMap<String, BTreeSet<u64>>>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether any connection watches `uri` on `backend`
+    pub fn is_watched(&self, backend: &str, uri: &str) -> bool {
+        self.by_backend
+            .lock()
+            .unwrap()
+            .get(backend)
+            .is_some_and(|uris| uris.contains_key(uri))
+    }
+
+    /// Record that `connection` watches `uri` on `backend`
+    pub fn add(&self, connection: u64, backend: &str, uri: &str) {
+        self.by_backend
+            .lock()
+            .unwrap()
+            .entry(backend.to_string())
+            .or_default()
+            .entry(uri.to_string())
+            .or_default()
+            .insert(connection);
+    }
+
+    /// Stop `connection` watching `uri` on `backend`. Returns whether that
+    /// was the last watcher, so the backend subscription should end too.
+    pub fn remove(&self, connection: u64, backend: &str, uri: &str) -> bool {
+        let mut by_backend = self.by_backend.lock().unwrap();
+        let Some(uris) = by_backend.get_mut(backend) else {
+            return false;
+        };
+        let Some(connections) = uris.get_mut(uri) else {
+            return false;
+        };
+        if !connections.remove(&connection) || !connections.is_empty() {
+            return false;
+        }
+        uris.remove(uri);
+        if uris.is_empty() {
+            by_backend.remove(backend);
+        }
+        true
+    }
+
+    /// Drop everything a disconnected client watched. Returns the
+    /// `(backend, uri)` pairs nobody watches any more.
+    pub fn close(&self, connection: u64) -> Vec<(String, String)> {
+        let mut by_backend = self.by_backend.lock().unwrap();
+        let mut unwatched = Vec::new();
+        for (backend, uris) in by_backend.iter_mut() {
+            uris.retain(|uri, connections| {
+                if connections.remove(&connection) && connections.is_empty() {
+                    unwatched.push((backend.clone(), uri.clone()));
+                }
+                !connections.is_empty()
+            });
+        }
+        by_backend.retain(|_, uris| !uris.is_empty());
+        unwatched.sort();
+        unwatched
+    }
+
+    /// Connections watching `uri` on `backend`
+    pub fn watchers(&self, backend: &str, uri: &str) -> Vec<u64> {
+        self.by_backend
+            .lock()
+            .unwrap()
+            .get(backend)
+            .and_then(|uris| uris.get(uri))
+            .map(|connections| connections.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Every URI watched on `backend`, in order
+    pub fn uris(&self, backend: &str) -> Vec<String> {
+        self.by_backend
+            .lock()
+            .unwrap()
+            .get(backend)
+            .map(|uris| uris.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_watcher_ends_backend_subscription() {
+        let subs = Subscriptions::new();
+        subs.add(1, "fs", "file:///a");
+        subs.add(2, "fs", "file:///a");
+        subs.add(1, "fs", "file:///b");
+        assert_eq!(subs.watchers("fs", "file:///a"), vec![1, 2]);
+        assert_eq!(subs.uris("fs"), vec!["file:///a", "file:///b"]);
+
+        assert!(!subs.remove(1, "fs", "file:///a"));
+        assert!(subs.is_watched("fs", "file:///a"));
+        assert!(subs.remove(2, "fs", "file:///a"));
+        assert!(!subs.is_watched("fs", "file:///a"));
+        assert_eq!(subs.uris("fs"), vec!["file:///b"]);
+    }
+
+    #[test]
+    fn remove_unknown_is_noop() {
+        let subs = Subscriptions::new();
+        subs.add(1, "fs", "file:///a");
+        assert!(!subs.remove(2, "fs", "file:///a"));
+        assert!(!subs.remove(1, "git", "file:///a"));
+        assert!(!subs.remove(1, "fs", "file:///b"));
+        assert_eq!(subs.watchers("fs", "file:///a"), vec![1]);
+    }
+
+    #[test]
+    fn close_returns_what_nobody_watches() {
+        let subs = Subscriptions::new();
+        subs.add(1, "fs", "file:///a");
+        subs.add(2, "fs", "file:///a");
+        subs.add(1, "git", "repo://head");
+        subs.add(1, "fs", "file:///b");
+
+        assert_eq!(
+            subs.close(1),
+            vec![
+                ("fs".to_string(), "file:///b".to_string()),
+                ("git".to_string(), "repo://head".to_string()),
+            ]
+        );
+        assert_eq!(subs.watchers("fs", "file:///a"), vec![2]);
+        assert!(subs.uris("git").is_empty());
+        assert!(subs.close(1).is_empty());
+    }
+}
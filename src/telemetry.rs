@@ -0,0 +1,209 @@
+//! Distributed tracing. W3C `traceparent` values arrive from clients in a
+//! request's `_meta` and can be forwarded to backends. With the `otel` feature,
+//! spans are exported over OTLP and join the caller's trace.
+
+use serde_json::Value;
+
+/// A parsed W3C `traceparent` (https://www.w3.org/TR/trace-context/)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub flags: u8,
+}
+
+impl TraceParent {
+    /// Parse `version-traceid-parentid-flags`. Rejects the invalid version
+    /// `ff`, all-zero ids, and extra fields on version `00`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = decode_hex::<1>(parts.next()?)?[0];
+        let trace_id = decode_hex::<16>(parts.next()?)?;
+        let span_id = decode_hex::<8>(parts.next()?)?;
+        let flags = decode_hex::<1>(parts.next()?)?[0];
+        if version == 0xff || (version == 0 && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            flags,
+        })
+    }
+
+    pub fn sampled(&self) -> bool {
+        self.flags & 1 == 1
+    }
+
+    /// Format as a version `00` header value
+    pub fn to_header(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            encode_hex(&self.trace_id),
+            encode_hex(&self.span_id),
+            self.flags
+        )
+    }
+
+    /// The `traceparent` in a request's `_meta`, if present and valid
+    pub fn from_meta(meta: Option<&Value>) -> Option<Self> {
+        meta?.get("traceparent")?.as_str().and_then(Self::parse)
+    }
+}
+
+/// Decode exactly `N` bytes of lowercase hex
+fn decode_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let bytes = s.as_bytes();
+    if bytes.len() != N * 2 {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, pair) in bytes.chunks(2).enumerate() {
+        let digit = |b: u8| match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'a'..=b'f' => Some(b - b'a' + 10),
+            _ => None,
+        };
+        out[i] = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(out)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Make `span` a child of a remote parent. A no-op without the `otel` feature.
+#[cfg(feature = "otel")]
+pub fn set_parent(span: &tracing::Span, parent: &TraceParent) {
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let remote = SpanContext::new(
+        TraceId::from_bytes(parent.trace_id),
+        SpanId::from_bytes(parent.span_id),
+        TraceFlags::new(parent.flags),
+        true,
+        TraceState::default(),
+    );
+    let cx = opentelemetry::Context::new().with_remote_span_context(remote);
+    if let Err(e) = span.set_parent(cx) {
+        tracing::debug!(error = %e, "Failed to set remote trace parent");
+    }
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn set_parent(_span: &tracing::Span, _parent: &TraceParent) {}
+
+/// `traceparent` to send to a backend for a call made inside `span`: the
+/// span's own context when spans are exported, otherwise the inbound one.
+#[cfg(feature = "otel")]
+pub fn outgoing(span: &tracing::Span, inbound: Option<&TraceParent>) -> Option<TraceParent> {
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let cx = span.context();
+    let sc = cx.span().span_context().clone();
+    if !sc.is_valid() {
+        return inbound.copied();
+    }
+    Some(TraceParent {
+        trace_id: sc.trace_id().to_bytes(),
+        span_id: sc.span_id().to_bytes(),
+        flags: sc.trace_flags().to_u8(),
+    })
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn outgoing(_span: &tracing::Span, inbound: Option<&TraceParent>) -> Option<TraceParent> {
+    inbound.copied()
+}
+
+/// Build a tracer provider exporting over OTLP/HTTP. `endpoint` is the
+/// collector base URL, like `OTEL_EXPORTER_OTLP_ENDPOINT`.
+///
+/// Call before starting the tokio runtime: the exporter uses a blocking HTTP
+/// client on the batch processor's own thread.
+#[cfg(feature = "otel")]
+pub fn otlp_provider(
+    endpoint: &str,
+) -> anyhow::Result<opentelemetry_sdk::trace::SdkTracerProvider> {
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+    let resource = opentelemetry_sdk::Resource::builder()
+        .with_service_name("mcpd")
+        .build();
+    Ok(opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+/// A `tracing` layer that turns spans into OpenTelemetry spans
+#[cfg(feature = "otel")]
+pub fn layer<S>(
+    provider: &opentelemetry_sdk::trace::SdkTracerProvider,
+) -> impl tracing_subscriber::Layer<S>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    use opentelemetry::trace::TracerProvider;
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("mcpd"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SAMPLE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parse_valid() {
+        let tp = TraceParent::parse(SAMPLE).unwrap();
+        assert_eq!(tp.trace_id[0], 0x4b);
+        assert_eq!(tp.span_id[7], 0xb7);
+        assert!(tp.sampled());
+        assert_eq!(tp.to_header(), SAMPLE);
+    }
+
+    #[test]
+    fn parse_rejects_malformed() {
+        for bad in [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        ] {
+            assert_eq!(TraceParent::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn parse_future_version_allows_extra_fields() {
+        let value = "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-whatever";
+        let tp = TraceParent::parse(value).unwrap();
+        assert!(!tp.sampled());
+    }
+
+    #[test]
+    fn from_meta() {
+        let meta = json!({"traceparent": SAMPLE, "other": 1});
+        assert!(TraceParent::from_meta(Some(&meta)).is_some());
+        assert_eq!(TraceParent::from_meta(Some(&json!({}))), None);
+        assert_eq!(TraceParent::from_meta(None), None);
+    }
+}
@@ -0,0 +1,336 @@
+//! Concurrency limits on individual tools of a backend.
+//!
+//! Some servers cope with concurrent calls except to one stateful tool, like
+//! a browser's `navigate`. `singleton` would serialize every call to them;
+//! instead a registry entry can limit just that tool with `serialize` or
+//! `max_concurrency`. Calls over the limit wait their turn in arrival order,
+//! and once `max_queue` are waiting further calls are refused with an error
+//! that says to try again.
+//!
+//! Limits are enforced by the server before a call reaches its backend or
+//! takes a slot under `--max-concurrent-calls`, so a call waiting on a tool
+//! limit doesn't hold up calls to other tools. A limiter is made on a tool's
+//! first call and dropped once its backend no longer limits the tool or no
+//! longer has it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Concurrency limit on one tool of a server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolConcurrency {
+    /// One call at a time, the same as `max_concurrency: 1`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub serialize: bool,
+    /// Calls the tool runs at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<u32>,
+    /// Calls that may wait for a turn before more are refused (default: no limit)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queue: Option<usize>,
+}
+
+impl ToolConcurrency {
+    /// Calls the tool runs at once, `None` for no limit
+    pub fn limit(&self) -> Option<u32> {
+        if self.serialize {
+            Some(1)
+        } else {
+            self.max_concurrency
+        }
+    }
+}
+
+/// A call refused because too many were already waiting for the tool
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error(
+    "Tool '{tool}' of '{backend}' is busy: {waiting} calls are already waiting for a turn. Try again shortly."
+)]
+pub struct QueueFull {
+    pub backend: String,
+    pub tool: String,
+    pub waiting: usize,
+}
+
+/// A turn at a limited tool, given back when dropped
+#[derive(Debug)]
+pub struct ToolPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+struct Limiter {
+    limits: ToolConcurrency,
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+/// Counts a call as waiting until it gets its turn or gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Limiters for every limited tool that's been called, by backend and tool
+#[derive(Default)]
+pub struct ToolLimiters {
+    limiters: Mutex<HashMap<(String, String), Arc<Limiter>>>,
+}
+
+impl ToolLimiters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for a turn at `backend`'s `tool` under `limits`. `None` when the
+    /// tool isn't limited. Turns are handed out in the order calls arrive.
+    pub async fn acquire(
+        &self,
+        backend: &str,
+        tool: &str,
+        limits: Option<&ToolConcurrency>,
+    ) -> Result<Option<ToolPermit>, QueueFull> {
+        let Some(limits) = limits.filter(|l| l.limit().is_some()) else {
+            return Ok(None);
+        };
+        let limiter = self.limiter(backend, tool, limits);
+        if let Ok(permit) = Arc::clone(&limiter.slots).try_acquire_owned() {
+            return Ok(Some(ToolPermit { _permit: permit }));
+        }
+        let max_queue = limits.max_queue.unwrap_or(usize::MAX);
+        if let Err(waiting) =
+            limiter
+                .waiting
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |waiting| {
+                    (waiting < max_queue).then_some(waiting + 1)
+                })
+        {
+            return Err(QueueFull {
+                backend: backend.to_string(),
+                tool: tool.to_string(),
+                waiting,
+            });
+        }
+        let _waiting = Waiting(&limiter.waiting);
+        let permit = Arc::clone(&limiter.slots)
+            .acquire_owned()
+            .await
+            .expect("tool limiter semaphores are never closed");
+        Ok(Some(ToolPermit { _permit: permit }))
+    }
+
+    /// The limiter for `backend`'s `tool`, made now if it's the first call
+    /// or the limits changed. Calls already waiting on a replaced limiter
+    /// finish under the old limits.
+    fn limiter(&self, backend: &str, tool: &str, limits: &ToolConcurrency) -> Arc<Limiter> {
+        let mut limiters = self.limiters.lock().unwrap();
+        let key = (backend.to_string(), tool.to_string());
+        match limiters.get(&key) {
+            Some(limiter) if limiter.limits == *limits => Arc::clone(limiter),
+            _ => {
+                let limiter = Arc::new(Limiter {
+                    limits: *limits,
+                    slots: Arc::new(Semaphore::new(limits.limit().unwrap_or(1) as usize)),
+                    waiting: AtomicUsize::new(0),
+                });
+                limiters.insert(key, Arc::clone(&limiter));
+                limiter
+            }
+        }
+    }
+
+    /// Drop the limiters `keep` says no for, given backend and tool
+    pub fn retain(&self, mut keep: impl FnMut(&str, &str) -> bool) {
+        self.limiters
+            .lock()
+            .unwrap()
+            .retain(|(backend, tool), _| keep(backend, tool));
+    }
+
+    /// Calls waiting for a turn at `backend`'s `tool`
+    pub fn waiting(&self, backend: &str, tool: &str) -> usize {
+        self.limiters
+            .lock()
+            .unwrap()
+            .get(&(backend.to_string(), tool.to_string()))
+            .map_or(0, |limiter| limiter.waiting.load(Ordering::Acquire))
+    }
+
+    /// Number of limiters in use
+    pub fn len(&self) -> usize {
+        self.limiters.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::{Priority, Scheduler};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    const SERIALIZE: ToolConcurrency = ToolConcurrency {
+        serialize: true,
+        max_concurrency: None,
+        max_queue: None,
+    };
+
+    #[tokio::test]
+    async fn second_call_starts_only_after_the_first_finishes() {
+        let limiters = Arc::new(ToolLimiters::new());
+        let (events, mut log) = mpsc::unbounded_channel();
+        let mut calls = Vec::new();
+        for call in 0..3 {
+            let shared = Arc::clone(&limiters);
+            let events = events.clone();
+            calls.push(tokio::spawn(async move {
+                let _turn = shared
+                    .acquire("browser", "navigate", Some(&SERIALIZE))
+                    .await
+                    .unwrap();
+                events.send(("start", call)).unwrap();
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                events.send(("end", call)).unwrap();
+            }));
+            // Arrive in order: each call is running or queued before the next
+            tokio::task::yield_now().await;
+            while limiters.waiting("browser", "navigate") < call {
+                tokio::task::yield_now().await;
+            }
+        }
+        drop(events);
+        for call in calls {
+            call.await.unwrap();
+        }
+        let mut order = Vec::new();
+        while let Some(event) = log.recv().await {
+            order.push(event);
+        }
+        assert_eq!(
+            order,
+            [
+                ("start", 0),
+                ("end", 0),
+                ("start", 1),
+                ("end", 1),
+                ("start", 2),
+                ("end", 2)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn calls_over_the_queue_depth_are_refused() {
+        let limiters = Arc::new(ToolLimiters::new());
+        let limits = ToolConcurrency {
+            max_concurrency: Some(1),
+            max_queue: Some(1),
+            ..Default::default()
+        };
+        let running = limiters
+            .acquire("browser", "navigate", Some(&limits))
+            .await
+            .unwrap();
+        let waiter = {
+            let limiters = Arc::clone(&limiters);
+            tokio::spawn(async move {
+                limiters
+                    .acquire("browser", "navigate", Some(&limits))
+                    .await
+                    .map(|permit| permit.is_some())
+            })
+        };
+        while limiters.waiting("browser", "navigate") == 0 {
+            tokio::task::yield_now().await;
+        }
+        let refused = limiters
+            .acquire("browser", "navigate", Some(&limits))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            refused.to_string(),
+            "Tool 'navigate' of 'browser' is busy: 1 calls are already waiting for a turn. Try again shortly."
+        );
+
+        drop(running);
+        assert_eq!(waiter.await.unwrap(), Ok(true));
+        assert_eq!(limiters.waiting("browser", "navigate"), 0);
+        assert!(
+            limiters
+                .acquire("browser", "navigate", Some(&limits))
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn other_tools_and_backend_slots_are_not_held_up() {
+        let limiters = Arc::new(ToolLimiters::new());
+        let scheduler = Arc::new(Scheduler::new(1, Duration::from_secs(60)));
+        let _navigating = limiters
+            .acquire("browser", "navigate", Some(&SERIALIZE))
+            .await
+            .unwrap();
+        // A second navigate waits on the tool, not on a backend slot
+        let waiter = {
+            let limiters = Arc::clone(&limiters);
+            let scheduler = Arc::clone(&scheduler);
+            tokio::spawn(async move {
+                let _turn = limiters
+                    .acquire("browser", "navigate", Some(&SERIALIZE))
+                    .await
+                    .unwrap();
+                let _slot = scheduler.acquire(Priority::Normal).await;
+            })
+        };
+        while limiters.waiting("browser", "navigate") == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // Unlimited tools and the same tool on another backend go straight through
+        let other = tokio::time::timeout(Duration::from_secs(1), async {
+            let click = limiters.acquire("browser", "click", None).await.unwrap();
+            let elsewhere = limiters
+                .acquire("other", "navigate", Some(&SERIALIZE))
+                .await
+                .unwrap();
+            let _slot = scheduler.acquire(Priority::Normal).await;
+            (click.is_none(), elsewhere.is_some())
+        })
+        .await
+        .expect("held up by a limited tool");
+        assert_eq!(other, (true, true));
+        assert!(!waiter.is_finished());
+        waiter.abort();
+    }
+
+    #[tokio::test]
+    async fn limiters_are_made_on_first_call_and_dropped_on_retain() {
+        let limiters = ToolLimiters::new();
+        assert!(limiters.is_empty());
+        drop(
+            limiters
+                .acquire("browser", "navigate", Some(&SERIALIZE))
+                .await
+                .unwrap(),
+        );
+        drop(
+            limiters
+                .acquire("browser", "click", Some(&ToolConcurrency::default()))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(limiters.len(), 1);
+        limiters.retain(|_, tool| tool != "navigate");
+        assert!(limiters.is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+//! `mcpd top` - live view of a running server via its control socket.
+
+use crate::activity::{BackendState, Snapshot};
+use crate::output::{Cell, Color, Output, Table};
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Render a snapshot as a screen of tables. Each backend gets a status dot:
+/// green when ready or idle, red when it couldn't be started.
+pub fn render(snapshot: &Snapshot, out: &Output) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "mcpd pid {}  up {}s  calls {}  errors {}  active {}",
+        snapshot.pid,
+        snapshot.uptime_secs,
+        snapshot.totals.calls,
+        snapshot.totals.errors,
+        snapshot.totals.active
+    );
+    let _ = writeln!(text);
+    if snapshot.backends.is_empty() {
+        let _ = writeln!(text, "(no backends)");
+        return text;
+    }
+
+    let mut backends = Table::new(&[
+        "BACKEND", "STATE", "ACTIVE", "CALLS", "ERRORS", "RESTARTS", "CRASHES",
+    ]);
+    for column in 2..=6 {
+        backends = backends.right(column);
+    }
+    for backend in &snapshot.backends {
+        let state = match backend.state {
+            BackendState::Ready => Cell::new("ready").color(Color::Green),
+            BackendState::Starting => Cell::new("starting").color(Color::Yellow),
+            BackendState::Unavailable => Cell::new("unavailable").color(Color::Red),
+            BackendState::Stopped => Cell::new("stopped").color(Color::Dim),
+        };
+        let init_error = backend
+            .init_error
+            .iter()
+            .map(|e| out.paint(&format!("initialize failed: {}", e), Color::Red));
+        let degraded = backend
+            .degraded
+            .iter()
+            .map(|e| out.paint(&format!("degraded: {}", e), Color::Yellow));
+        let dropped = (backend.dropped_notifications > 0).then(|| {
+            format!(
+                "dropped {} notifications over its rate limits",
+                backend.dropped_notifications
+            )
+        });
+        let instances = backend.instances.iter().map(|instance| {
+            let state = if instance.crashed {
+                "crashed".to_string()
+            } else {
+                instance.state.to_string()
+            };
+            format!(
+                "instance {}: {}, {} calls, {} active, {:.1}s busy",
+                instance.index,
+                state,
+                instance.calls,
+                instance.active,
+                instance.busy_ms as f64 / 1000.0
+            )
+        });
+        let calls = backend.active_calls.iter().map(|call| {
+            format!(
+                "{} {} ({:.1}s)",
+                call.correlation_id,
+                call.tool,
+                call.elapsed_ms as f64 / 1000.0
+            )
+        });
+        let details = init_error
+            .chain(degraded)
+            .chain(dropped)
+            .chain(instances)
+            .chain(calls)
+            .collect();
+        backends.row_with(
+            Some(out.dot(backend.state != BackendState::Unavailable)),
+            vec![
+                Cell::new(backend.name.as_str()),
+                state,
+                Cell::new(backend.active_calls.len().to_string()),
+                Cell::new(backend.calls.to_string()),
+                Cell::new(backend.errors.to_string()),
+                Cell::new(backend.restarts.to_string()),
+                Cell::new(backend.crashes.to_string()),
+            ],
+            details,
+        );
+    }
+    text.push_str(&backends.render(out));
+
+    if !snapshot.queues.is_empty() {
+        let _ = writeln!(text);
+        let mut queues = Table::new(&["PRIORITY", "WAITING", "ADMITTED", "AVG WAIT", "MAX WAIT"]);
+        for column in 1..=4 {
+            queues = queues.right(column);
+        }
+        for queue in &snapshot.queues {
+            let avg = queue.total_wait_ms.checked_div(queue.admitted).unwrap_or(0);
+            queues.row(vec![
+                Cell::new(queue.priority.as_str()),
+                Cell::new(queue.waiting.to_string()),
+                Cell::new(queue.admitted.to_string()),
+                Cell::new(format!("{:.1}s", avg as f64 / 1000.0)),
+                Cell::new(format!("{:.1}s", queue.max_wait_ms as f64 / 1000.0)),
+            ]);
+        }
+        text.push_str(&queues.render(out));
+    }
+    text
+}
+
+/// Fetch one snapshot from the server behind `socket`
+#[cfg(unix)]
+pub async fn fetch(socket: &Path) -> Result<Snapshot> {
+    let value = crate::control::call(socket, "mcpd/inspect", None).await?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Refresh the view every second. Commands are read a line at a time from
+/// stdin: `q` quits, `r <backend>` restarts a backend.
+#[cfg(unix)]
+pub async fn run(socket: &Path, out: &Output) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+    let mut status = String::new();
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let snapshot = fetch(socket).await?;
+                print!("\x1b[2J\x1b[H{}", render(&snapshot, out));
+                println!("\n{}", status);
+                println!("q⏎ quit   r <backend>⏎ restart");
+            }
+            line = lines.next_line() => {
+                let Some(line) = line? else { return Ok(()) };
+                let line = line.trim();
+                if line == "q" {
+                    return Ok(());
+                }
+                if let Some(name) = line.strip_prefix("r ") {
+                    let params = serde_json::json!({"backend": name.trim()});
+                    status = match crate::control::call(socket, "mcpd/restart", Some(params)).await {
+                        Ok(_) => format!("restarted {}", name.trim()),
+                        Err(e) => format!("restart failed: {}", e),
+                    };
+                    interval.reset_immediately();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::{
+        ActiveCallSnapshot, BackendSnapshot, BackendState, InstanceSnapshot, QueueSnapshot, Totals,
+    };
+
+    #[test]
+    fn render_lists_backends_and_active_calls() {
+        let snapshot = Snapshot {
+            pid: 42,
+            uptime_secs: 10,
+            backends: vec![BackendSnapshot {
+                name: "fs".to_string(),
+                state: BackendState::Ready,
+                active_calls: vec![ActiveCallSnapshot {
+                    correlation_id: "mcpd-7".to_string(),
+                    tool: "read_file".to_string(),
+                    elapsed_ms: 1500,
+                    stage: String::new(),
+                }],
+                calls: 3,
+                errors: 1,
+                spawns: 3,
+                restarts: 2,
+                crashes: 1,
+                dropped_notifications: 0,
+                init_error: None,
+                degraded: None,
+                instances: Vec::new(),
+            }],
+            totals: Totals {
+                calls: 3,
+                errors: 1,
+                active: 1,
+            },
+            queues: vec![QueueSnapshot {
+                priority: "low".to_string(),
+                waiting: 2,
+                admitted: 4,
+                total_wait_ms: 6000,
+                max_wait_ms: 3000,
+            }],
+        };
+        let expected = concat!(
+            "mcpd pid 42  up 10s  calls 3  errors 1  active 1\n",
+            "\n",
+            "  BACKEND  STATE  ACTIVE  CALLS  ERRORS  RESTARTS  CRASHES\n",
+            "+ fs       ready       1      3       1         2        1\n",
+            "      mcpd-7 read_file (1.5s)\n",
+            "\n",
+            "PRIORITY  WAITING  ADMITTED  AVG WAIT  MAX WAIT\n",
+            "low             2         4      1.5s      3.0s\n",
+        );
+        assert_eq!(render(&snapshot, &Output::plain()), expected);
+
+        let colored = render(&snapshot, &Output::plain().with_color(true));
+        assert!(
+            colored.contains("\x1b[32m●\x1b[0m fs       \x1b[32mready\x1b[0m"),
+            "{}",
+            colored
+        );
+    }
+
+    #[test]
+    fn render_shows_initialize_failure() {
+        let snapshot = Snapshot {
+            pid: 42,
+            uptime_secs: 10,
+            backends: vec![BackendSnapshot {
+                name: "fs".to_string(),
+                state: BackendState::Unavailable,
+                active_calls: Vec::new(),
+                calls: 2,
+                errors: 2,
+                spawns: 1,
+                restarts: 0,
+                crashes: 0,
+                dropped_notifications: 0,
+                init_error: Some("RPC error -32602: unsupported protocol version".to_string()),
+                degraded: None,
+                instances: Vec::new(),
+            }],
+            totals: Totals {
+                calls: 2,
+                errors: 2,
+                active: 0,
+            },
+            queues: Vec::new(),
+        };
+        let text = render(&snapshot, &Output::plain());
+        assert!(
+            text.contains(
+                "- fs       unavailable       0      2       2         0        0\n      initialize failed: RPC error -32602: unsupported protocol version\n"
+            ),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn render_shows_degraded_backend_and_dropped_notifications() {
+        let snapshot = Snapshot {
+            pid: 42,
+            uptime_secs: 10,
+            backends: vec![BackendSnapshot {
+                name: "db".to_string(),
+                state: BackendState::Ready,
+                active_calls: Vec::new(),
+                calls: 0,
+                errors: 0,
+                spawns: 1,
+                restarts: 0,
+                crashes: 0,
+                dropped_notifications: 1200,
+                init_error: None,
+                degraded: Some("connection pool closed".to_string()),
+                instances: vec![
+                    InstanceSnapshot {
+                        index: 0,
+                        state: BackendState::Ready,
+                        crashed: false,
+                        calls: 7,
+                        active: 1,
+                        busy_ms: 2500,
+                    },
+                    InstanceSnapshot {
+                        index: 1,
+                        state: BackendState::Stopped,
+                        crashed: true,
+                        calls: 3,
+                        active: 0,
+                        busy_ms: 900,
+                    },
+                ],
+            }],
+            totals: Totals {
+                calls: 0,
+                errors: 0,
+                active: 0,
+            },
+            queues: Vec::new(),
+        };
+        let text = render(&snapshot, &Output::plain());
+        assert!(
+            text.contains(
+                "\n      degraded: connection pool closed\n      dropped 1200 notifications over its rate limits\n      instance 0: ready, 7 calls, 1 active, 2.5s busy\n      instance 1: crashed, 3 calls, 0 active, 0.9s busy\n"
+            ),
+            "{}",
+            text
+        );
+    }
+}
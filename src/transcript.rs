@@ -0,0 +1,703 @@
+//! `mcpd transcript`: render an audit log as a Markdown or HTML document to
+//! share what happened in a session.
+//!
+//! Everything here is a pure function of the parsed records, so the output
+//! for a given file and set of options never changes. Anything that came from
+//! a backend or client is escaped for the target format, and long text is cut
+//! to `max_blob_chars` with a note saying how much was left out.
+
+use crate::audit::{Log, Record};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Default for `transcript --max-blob-chars`
+pub const DEFAULT_MAX_BLOB_CHARS: usize = 4000;
+
+/// Output document format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Markdown,
+    Html,
+}
+
+/// Which records make it into the transcript
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Only calls to this backend
+    pub backend: Option<String>,
+    /// Only calls that failed
+    pub errors_only: bool,
+    /// Only calls at or after this time
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    pub fn matches(&self, record: &Record) -> bool {
+        self.backend.as_ref().is_none_or(|b| *b == record.backend)
+            && (!self.errors_only || record.is_error)
+            && self.since.is_none_or(|since| record.timestamp >= since)
+    }
+
+    fn describe(&self) -> Vec<String> {
+        let mut parts = Vec::new();
+        if let Some(backend) = &self.backend {
+            parts.push(format!("backend {}", backend));
+        }
+        if self.errors_only {
+            parts.push("errors only".to_string());
+        }
+        if let Some(since) = self.since {
+            parts.push(format!("since {}", timestamp(since)));
+        }
+        parts
+    }
+}
+
+/// How to render a transcript
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub format: Format,
+    pub filter: Filter,
+    /// Longest text, argument JSON or error shown before it's cut
+    pub max_blob_chars: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            format: Format::default(),
+            filter: Filter::default(),
+            max_blob_chars: DEFAULT_MAX_BLOB_CHARS,
+        }
+    }
+}
+
+/// Parse `--since`: an RFC 3339 time, or a duration before `now` like `30m`,
+/// `2h` or `1d`
+pub fn parse_since(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (count, unit) = text.split_at(split);
+    let count: i64 = count
+        .parse()
+        .with_context(|| format!("Expected a time like 2h or 2026-01-02T15:04:05Z: {}", text))?;
+    let delta = match unit {
+        "s" => chrono::TimeDelta::try_seconds(count),
+        "m" => chrono::TimeDelta::try_minutes(count),
+        "h" => chrono::TimeDelta::try_hours(count),
+        "d" => chrono::TimeDelta::try_days(count),
+        _ => bail!("Unknown unit '{}' in {} (use s, m, h or d)", unit, text),
+    };
+    delta
+        .and_then(|delta| now.checked_sub_signed(delta))
+        .with_context(|| format!("{} is too far back", text))
+}
+
+/// Render the records of `log` that pass the filter
+pub fn render(log: &Log, options: &Options) -> String {
+    let records: Vec<&Record> = log
+        .records
+        .iter()
+        .filter(|r| options.filter.matches(r))
+        .collect();
+    let summary = summarize(log, &records, &options.filter);
+    let calls: Vec<Call> = records
+        .iter()
+        .map(|r| Call::new(r, options.max_blob_chars))
+        .collect();
+    match options.format {
+        Format::Markdown => markdown(&summary, &calls),
+        Format::Html => html(&summary, &calls),
+    }
+}
+
+/// The header of a transcript
+struct Summary {
+    lines: Vec<String>,
+    notes: Vec<String>,
+}
+
+fn summarize(log: &Log, records: &[&Record], filter: &Filter) -> Summary {
+    let mut lines = Vec::new();
+    let errors = records.iter().filter(|r| r.is_error).count();
+    let mut calls = format!("Calls: {}", records.len());
+    if records.len() < log.records.len() {
+        write!(calls, " of {}", log.records.len()).unwrap();
+    }
+    if !records.is_empty() {
+        write!(
+            calls,
+            " ({} {}, {:.1}% error rate)",
+            errors,
+            plural(errors, "error", "errors"),
+            100.0 * errors as f64 / records.len() as f64
+        )
+        .unwrap();
+    }
+    lines.push(calls);
+
+    if let (Some(first), Some(last)) = (
+        records.iter().map(|r| r.timestamp).min(),
+        records.iter().map(|r| r.timestamp).max(),
+    ) {
+        lines.push(format!(
+            "Duration: {} ({} to {})",
+            duration((last - first).num_milliseconds()),
+            timestamp(first),
+            timestamp(last)
+        ));
+    }
+
+    let mut backends: IndexMap<&str, (usize, usize)> = IndexMap::new();
+    for record in records {
+        let counts = backends.entry(record.backend.as_str()).or_default();
+        counts.0 += 1;
+        counts.1 += usize::from(record.is_error);
+    }
+    backends.sort_keys();
+    for (backend, (calls, errors)) in backends {
+        lines.push(format!(
+            "Backend {}: {} {}, {} {}",
+            backend,
+            calls,
+            plural(calls, "call", "calls"),
+            errors,
+            plural(errors, "error", "errors")
+        ));
+    }
+
+    let filters = filter.describe();
+    if !filters.is_empty() {
+        lines.push(format!("Filter: {}", filters.join(", ")));
+    }
+
+    let mut notes = Vec::new();
+    if log.skipped > 0 {
+        notes.push(format!(
+            "Skipped {} unreadable {}",
+            log.skipped,
+            plural(log.skipped, "line", "lines")
+        ));
+    }
+    if log.truncated {
+        notes.push("The last record was cut off (was mcpd stopped mid-write?)".to_string());
+    }
+    Summary { lines, notes }
+}
+
+/// One call, reduced to what the transcript shows
+struct Call<'a> {
+    record: &'a Record,
+    arguments: Option<Clipped>,
+    error: Option<Clipped>,
+    content: Vec<Item>,
+}
+
+impl<'a> Call<'a> {
+    fn new(record: &'a Record, max_chars: usize) -> Self {
+        Self {
+            record,
+            arguments: record.arguments.as_ref().map(|args| {
+                let json = serde_json::to_string_pretty(args).unwrap_or_default();
+                Clipped::new(&json, max_chars)
+            }),
+            error: record.error.as_deref().map(|e| Clipped::new(e, max_chars)),
+            content: record
+                .content
+                .iter()
+                .map(|item| Item::new(item, max_chars))
+                .collect(),
+        }
+    }
+
+    fn details(&self) -> Vec<String> {
+        let r = self.record;
+        let mut details = vec![
+            format!("Time: {}", timestamp(r.timestamp)),
+            format!("Backend: {}, tool: {}", r.backend, r.tool),
+            format!("Latency: {}", duration(r.latency_ms as i64)),
+        ];
+        if let Some(id) = &r.correlation_id {
+            details.push(format!("Correlation id: {}", id));
+        }
+        details
+    }
+}
+
+/// Text cut to a maximum length
+struct Clipped {
+    text: String,
+    /// Characters left out
+    omitted: usize,
+}
+
+impl Clipped {
+    fn new(text: &str, max_chars: usize) -> Self {
+        match text.char_indices().nth(max_chars) {
+            Some((end, _)) => Self {
+                text: text[..end].to_string(),
+                omitted: text[end..].chars().count(),
+            },
+            None => Self {
+                text: text.to_string(),
+                omitted: 0,
+            },
+        }
+    }
+
+    fn note(&self) -> Option<String> {
+        (self.omitted > 0).then(|| {
+            format!(
+                "Truncated: {} more {} not shown",
+                self.omitted,
+                plural(self.omitted, "character", "characters")
+            )
+        })
+    }
+}
+
+/// One content item of a result
+enum Item {
+    Text(Clipped),
+    /// Binary data, shown by type and size
+    Binary {
+        kind: &'static str,
+        mime: String,
+        bytes: usize,
+    },
+    Resource {
+        uri: String,
+        text: Option<Clipped>,
+        bytes: Option<usize>,
+    },
+    /// Anything else, as JSON
+    Other(Clipped),
+}
+
+impl Item {
+    fn new(item: &Value, max_chars: usize) -> Self {
+        let str_at = |v: &Value, key: &str| v.get(key).and_then(Value::as_str).map(str::to_string);
+        let mime = |v: &Value| {
+            str_at(v, "mimeType")
+                .or_else(|| str_at(v, "mime_type"))
+                .unwrap_or_else(|| "unknown type".to_string())
+        };
+        let kind = item.get("type").and_then(Value::as_str);
+        if kind == Some("text")
+            && let Some(text) = item.get("text").and_then(Value::as_str)
+        {
+            return Item::Text(Clipped::new(text, max_chars));
+        }
+        if let (Some(kind @ ("image" | "audio")), Some(data)) =
+            (kind, item.get("data").and_then(Value::as_str))
+        {
+            return Item::Binary {
+                kind: if kind == "image" { "Image" } else { "Audio" },
+                mime: mime(item),
+                bytes: base64_len(data),
+            };
+        }
+        if kind == Some("resource")
+            && let Some(resource) = item.get("resource")
+            && let Some(uri) = str_at(resource, "uri")
+        {
+            return Item::Resource {
+                uri,
+                text: str_at(resource, "text").map(|t| Clipped::new(&t, max_chars)),
+                bytes: str_at(resource, "blob").map(|b| base64_len(&b)),
+            };
+        }
+        let json = serde_json::to_string_pretty(item).unwrap_or_default();
+        Item::Other(Clipped::new(&json, max_chars))
+    }
+}
+
+fn markdown(summary: &Summary, calls: &[Call]) -> String {
+    let mut out = String::from("# mcpd transcript\n\n");
+    for line in &summary.lines {
+        writeln!(out, "- {}", md_text(line)).unwrap();
+    }
+    for note in &summary.notes {
+        write!(out, "\n> **Note:** {}\n", md_text(note)).unwrap();
+    }
+
+    for (i, call) in calls.iter().enumerate() {
+        let r = call.record;
+        write!(out, "\n## {}. {}", i + 1, md_code(&r.name)).unwrap();
+        if r.is_error {
+            out.push_str(" (error)");
+        }
+        out.push_str("\n\n");
+        for detail in call.details() {
+            writeln!(out, "- {}", md_text(&detail)).unwrap();
+        }
+
+        match &call.arguments {
+            Some(args) => {
+                out.push_str("\n<details><summary>Arguments</summary>\n\n");
+                md_clipped(&mut out, args, "json");
+                out.push_str("\n</details>\n");
+            }
+            None => out.push_str("\n*Arguments not recorded*\n"),
+        }
+
+        if let Some(error) = &call.error {
+            out.push_str("\n**Error:**\n\n");
+            md_clipped(&mut out, error, "text");
+        }
+        if !call.content.is_empty() {
+            out.push_str(if r.is_error && call.error.is_none() {
+                "\n**Error result:**\n"
+            } else {
+                "\n**Result:**\n"
+            });
+        }
+        for item in &call.content {
+            out.push('\n');
+            match item {
+                Item::Text(text) => md_clipped(&mut out, text, "text"),
+                Item::Binary { kind, mime, bytes } => {
+                    writeln!(out, "*{} ({}, {})*", kind, md_text(mime), size(*bytes)).unwrap()
+                }
+                Item::Resource { uri, text, bytes } => {
+                    write!(out, "*Resource* {}", md_code(uri)).unwrap();
+                    if let Some(bytes) = bytes {
+                        write!(out, " *({})*", size(*bytes)).unwrap();
+                    }
+                    out.push('\n');
+                    if let Some(text) = text {
+                        out.push('\n');
+                        md_clipped(&mut out, text, "text");
+                    }
+                }
+                Item::Other(json) => md_clipped(&mut out, json, "json"),
+            }
+        }
+    }
+    out
+}
+
+/// A fenced code block, plus the truncation note if it was cut
+fn md_clipped(out: &mut String, clipped: &Clipped, lang: &str) {
+    // The fence has to be longer than any run of backticks inside it
+    let fence = "`".repeat(longest_backtick_run(&clipped.text).max(2) + 1);
+    writeln!(out, "{}{}\n{}\n{}", fence, lang, clipped.text, fence).unwrap();
+    if let Some(note) = clipped.note() {
+        writeln!(out, "\n*{}*", note).unwrap();
+    }
+}
+
+/// An inline code span that no backtick in `text` can close early
+fn md_code(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    let ticks = "`".repeat(longest_backtick_run(&text) + 1);
+    let pad = if text.starts_with('`') || text.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{}{}{}{}{}", ticks, pad, text, pad, ticks)
+}
+
+/// Plain text with Markdown and inline HTML syntax escaped
+fn md_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\r' | '\n' => out.push(' '),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '#' | '|' | '~' | '!' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>mcpd transcript</title>
+<style>
+body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; white-space: pre-wrap; }
+section.error { border-left: 4px solid #d1242f; padding-left: 1em; }
+.error-label { color: #d1242f; font-weight: bold; }
+.note { color: #59636e; font-style: italic; }
+</style>
+</head>
+<body>
+<h1>mcpd transcript</h1>
+"#;
+
+fn html(summary: &Summary, calls: &[Call]) -> String {
+    let mut out = String::from(HTML_HEAD);
+    out.push_str("<ul>\n");
+    for line in &summary.lines {
+        writeln!(out, "<li>{}</li>", html_escape(line)).unwrap();
+    }
+    out.push_str("</ul>\n");
+    for note in &summary.notes {
+        writeln!(out, "<p class=\"note\">{}</p>", html_escape(note)).unwrap();
+    }
+
+    for (i, call) in calls.iter().enumerate() {
+        let r = call.record;
+        out.push_str(if r.is_error {
+            "<section class=\"error\">\n"
+        } else {
+            "<section>\n"
+        });
+        write!(out, "<h2>{}. <code>{}</code>", i + 1, html_escape(&r.name)).unwrap();
+        if r.is_error {
+            out.push_str(" <span class=\"error-label\">(error)</span>");
+        }
+        out.push_str("</h2>\n<ul>\n");
+        for detail in call.details() {
+            writeln!(out, "<li>{}</li>", html_escape(&detail)).unwrap();
+        }
+        out.push_str("</ul>\n");
+
+        match &call.arguments {
+            Some(args) => {
+                out.push_str("<details><summary>Arguments</summary>\n");
+                html_clipped(&mut out, args);
+                out.push_str("</details>\n");
+            }
+            None => out.push_str("<p class=\"note\">Arguments not recorded</p>\n"),
+        }
+
+        if let Some(error) = &call.error {
+            out.push_str("<p class=\"error-label\">Error:</p>\n");
+            html_clipped(&mut out, error);
+        }
+        if !call.content.is_empty() {
+            out.push_str(if r.is_error && call.error.is_none() {
+                "<p class=\"error-label\">Error result:</p>\n"
+            } else {
+                "<p><strong>Result:</strong></p>\n"
+            });
+        }
+        for item in &call.content {
+            match item {
+                Item::Text(text) | Item::Other(text) => html_clipped(&mut out, text),
+                Item::Binary { kind, mime, bytes } => writeln!(
+                    out,
+                    "<p class=\"note\">{} ({}, {})</p>",
+                    kind,
+                    html_escape(mime),
+                    size(*bytes)
+                )
+                .unwrap(),
+                Item::Resource { uri, text, bytes } => {
+                    write!(out, "<p>Resource <code>{}</code>", html_escape(uri)).unwrap();
+                    if let Some(bytes) = bytes {
+                        write!(out, " <span class=\"note\">({})</span>", size(*bytes)).unwrap();
+                    }
+                    out.push_str("</p>\n");
+                    if let Some(text) = text {
+                        html_clipped(&mut out, text);
+                    }
+                }
+            }
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn html_clipped(out: &mut String, clipped: &Clipped) {
+    writeln!(out, "<pre>{}</pre>", html_escape(&clipped.text)).unwrap();
+    if let Some(note) = clipped.note() {
+        writeln!(out, "<p class=\"note\">{}</p>", note).unwrap();
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S%.3f UTC").to_string()
+}
+
+fn duration(ms: i64) -> String {
+    if ms < 1_000 {
+        format!("{} ms", ms)
+    } else if ms < 60_000 {
+        format!("{:.1} s", ms as f64 / 1000.0)
+    } else if ms < 3_600_000 {
+        format!("{}m {}s", ms / 60_000, ms % 60_000 / 1000)
+    } else {
+        format!("{}h {}m", ms / 3_600_000, ms % 3_600_000 / 60_000)
+    }
+}
+
+fn size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1_048_576 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / 1_048_576.0)
+    }
+}
+
+/// Decoded size of base64 `data`
+fn base64_len(data: &str) -> usize {
+    let digits = data
+        .bytes()
+        .filter(|b| b.is_ascii_alphanumeric() || *b == b'+' || *b == b'/')
+        .count();
+    digits * 3 / 4
+}
+
+fn plural<'a>(n: usize, one: &'a str, many: &'a str) -> &'a str {
+    if n == 1 { one } else { many }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../test-support/audit-fixture.jsonl");
+
+    fn options(format: Format) -> Options {
+        Options {
+            format,
+            filter: Filter::default(),
+            max_blob_chars: 120,
+        }
+    }
+
+    /// Compare against a file in test-support/, or rewrite it when
+    /// UPDATE_SNAPSHOTS is set
+    fn assert_snapshot(name: &str, actual: &str) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test-support")
+            .join(name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            expected == actual,
+            "{} doesn't match (rerun with UPDATE_SNAPSHOTS=1 to accept):\n{}",
+            name,
+            actual
+        );
+    }
+
+    #[test]
+    fn markdown_snapshot() {
+        let log = crate::audit::parse(FIXTURE);
+        assert!(log.truncated);
+        assert_snapshot("transcript.md", &render(&log, &options(Format::Markdown)));
+    }
+
+    #[test]
+    fn html_snapshot() {
+        let log = crate::audit::parse(FIXTURE);
+        assert_snapshot("transcript.html", &render(&log, &options(Format::Html)));
+    }
+
+    #[test]
+    fn filters_select_records() {
+        let log = crate::audit::parse(FIXTURE);
+        let mut options = options(Format::Markdown);
+        options.filter = Filter {
+            backend: Some("web".to_string()),
+            errors_only: true,
+            since: None,
+        };
+        let out = render(&log, &options);
+        assert!(
+            out.contains("- Calls: 1 of 5 (1 error, 100.0% error rate)"),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains("- Filter: backend web, errors only"),
+            "{}",
+            out
+        );
+        assert_eq!(out.matches("\n## ").count(), 1);
+
+        options.filter = Filter {
+            since: Some(parse_since("2026-10-17T09:00:02Z", Utc::now()).unwrap()),
+            ..Filter::default()
+        };
+        let out = render(&log, &options);
+        assert_eq!(out.matches("\n## ").count(), 3);
+    }
+
+    #[test]
+    fn code_cannot_be_closed_by_content() {
+        assert_eq!(md_code("a``b"), "```a``b```");
+        assert_eq!(md_code("`x"), "`` `x ``");
+        let mut out = String::new();
+        md_clipped(
+            &mut out,
+            &Clipped::new("````\n# not a heading", 100),
+            "text",
+        );
+        assert!(out.starts_with("`````text\n"), "{}", out);
+        assert!(out.ends_with("\n`````\n"), "{}", out);
+        assert_eq!(
+            html_escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn clipping_counts_characters() {
+        let clipped = Clipped::new("héllo wörld", 5);
+        assert_eq!(clipped.text, "héllo");
+        assert_eq!(clipped.omitted, 6);
+        assert_eq!(Clipped::new("short", 5).omitted, 0);
+    }
+
+    #[test]
+    fn since_accepts_times_and_durations() {
+        let now = DateTime::parse_from_rfc3339("2026-10-17T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let at = |s: &str| parse_since(s, now).unwrap().to_rfc3339();
+        assert_eq!(at("30m"), "2026-10-17T11:30:00+00:00");
+        assert_eq!(at("2h"), "2026-10-17T10:00:00+00:00");
+        assert_eq!(at("1d"), "2026-10-16T12:00:00+00:00");
+        assert_eq!(at("2026-10-17T13:00:00+02:00"), "2026-10-17T11:00:00+00:00");
+        assert!(parse_since("2w", now).is_err());
+        assert!(parse_since("yesterday", now).is_err());
+    }
+}
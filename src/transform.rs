@@ -0,0 +1,191 @@
+//! jq-style result transforms - let users reshape a backend's tool output
+//! (`mcpd register --transform '...'`) before it reaches the model.
+
+use anyhow::{Result, anyhow};
+use jaq_core::load::{Arena, File, Loader};
+use jaq_core::{Compiler, Ctx, Native, RcIter};
+use jaq_json::Val;
+use serde_json::Value;
+
+use crate::mcp::{CallToolResult, Content};
+
+/// A compiled jq filter
+pub struct Transform {
+    code: String,
+    filter: jaq_core::Filter<Native<Val>>,
+}
+
+impl std::fmt::Debug for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Transform").field(&self.code).finish()
+    }
+}
+
+impl Transform {
+    /// Parse and compile a jq expression, with the jq standard library available
+    pub fn compile(code: &str) -> Result<Self> {
+        let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+        let arena = Arena::default();
+        let modules = loader
+            .load(&arena, File { code, path: () })
+            .map_err(|errs| anyhow!("Invalid transform '{}': {:?}", code, errs_summary(&errs)))?;
+        let filter = Compiler::default()
+            .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+            .compile(modules)
+            .map_err(|errs| anyhow!("Invalid transform '{}': {:?}", code, errs_summary(&errs)))?;
+        Ok(Self {
+            code: code.to_string(),
+            filter,
+        })
+    }
+
+    /// Run the filter on one value. A single output is returned as is;
+    /// several outputs are collected into an array, none becomes `null`.
+    pub fn apply(&self, input: Value) -> Result<Value> {
+        let inputs = RcIter::new(core::iter::empty());
+        let outputs = self
+            .filter
+            .run((Ctx::new([], &inputs), Val::from(input)))
+            .map(|out| out.map(Value::from).map_err(|e| anyhow!("{}", e)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(match <[Value; 1]>::try_from(outputs) {
+            Ok([single]) => single,
+            Err(outputs) if outputs.is_empty() => Value::Null,
+            Err(outputs) => Value::Array(outputs),
+        })
+    }
+
+    /// Transform a successful result's `structured_content` and every text
+    /// item that holds JSON. Error results and non-JSON text are left
+    /// untouched.
+    pub fn apply_to_result(&self, result: &CallToolResult) -> Result<CallToolResult> {
+        let mut result = result.clone();
+        if result.is_error {
+            return Ok(result);
+        }
+        if let Some(structured) = result.structured_content.take() {
+            result.structured_content = Some(self.apply(structured)?);
+        }
+        for content in &mut result.content {
+            if let Content::Text { text } = content
+                && let Ok(value) = serde_json::from_str::<Value>(text)
+            {
+                *text = serde_json::to_string(&self.apply(value)?)?;
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Keep only the error part of jaq's (file, error) pairs
+fn errs_summary<F, E>(errs: &[(F, E)]) -> Vec<&E> {
+    errs.iter().map(|(_, e)| e).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn text_result(text: &str, is_error: bool) -> CallToolResult {
+        CallToolResult {
+            content: vec![Content::Text {
+                text: text.to_string(),
+            }],
+            is_error,
+            structured_content: None,
+            meta: None,
+        }
+    }
+
+    fn text_of(result: &CallToolResult) -> &str {
+        match &result.content[0] {
+            Content::Text { text } => text,
+            other => panic!("expected text content, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn projection() {
+        let t = Transform::compile("{name, id}").unwrap();
+        let out = t
+            .apply(json!({"name": "a", "id": 1, "noise": [1, 2, 3]}))
+            .unwrap();
+        assert_eq!(out, json!({"name": "a", "id": 1}));
+    }
+
+    #[test]
+    fn std_functions_available() {
+        let t = Transform::compile("map(select(.open)) | length").unwrap();
+        let out = t
+            .apply(json!([{"open": true}, {"open": false}, {"open": true}]))
+            .unwrap();
+        assert_eq!(out, json!(2));
+    }
+
+    #[test]
+    fn multiple_outputs_become_array() {
+        let t = Transform::compile(".[] | .id").unwrap();
+        assert_eq!(
+            t.apply(json!([{"id": 1}, {"id": 2}])).unwrap(),
+            json!([1, 2])
+        );
+        assert_eq!(t.apply(json!([])).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn invalid_expression_rejected() {
+        assert!(Transform::compile("{name,").is_err());
+        assert!(Transform::compile("no_such_function").is_err());
+    }
+
+    #[test]
+    fn runtime_error_reported() {
+        let t = Transform::compile(".name").unwrap();
+        assert!(t.apply(json!([1, 2])).is_err());
+    }
+
+    #[test]
+    fn result_text_json_transformed() {
+        let t = Transform::compile("{name}").unwrap();
+        let result = t
+            .apply_to_result(&text_result(r#"{"name": "x", "big": "..."}"#, false))
+            .unwrap();
+        assert_eq!(text_of(&result), r#"{"name":"x"}"#);
+    }
+
+    #[test]
+    fn structured_content_transformed() {
+        let t = Transform::compile("{name}").unwrap();
+        let only_structured = CallToolResult {
+            content: Vec::new(),
+            is_error: false,
+            structured_content: Some(json!({"name": "x", "big": "..."})),
+            meta: None,
+        };
+        let result = t.apply_to_result(&only_structured).unwrap();
+        assert_eq!(result.structured_content, Some(json!({"name": "x"})));
+        assert!(result.content.is_empty());
+
+        // Both get the same transform, so they still agree
+        let both = CallToolResult {
+            structured_content: Some(json!({"name": "x", "big": "..."})),
+            ..text_result(r#"{"name": "x", "big": "..."}"#, false)
+        };
+        let result = t.apply_to_result(&both).unwrap();
+        assert_eq!(text_of(&result), r#"{"name":"x"}"#);
+        assert_eq!(result.structured_content, Some(json!({"name": "x"})));
+    }
+
+    #[test]
+    fn result_non_json_and_errors_untouched() {
+        let t = Transform::compile("{name}").unwrap();
+        let plain = t.apply_to_result(&text_result("just text", false)).unwrap();
+        assert_eq!(text_of(&plain), "just text");
+
+        let error = t
+            .apply_to_result(&text_result(r#"{"name": "x", "detail": "boom"}"#, true))
+            .unwrap();
+        assert_eq!(text_of(&error), r#"{"name": "x", "detail": "boom"}"#);
+    }
+}
@@ -0,0 +1,74 @@
+//! Client transports - anything that can carry newline-delimited JSON-RPC
+//! between an MCP client and the server.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncWrite, BufReader};
+use tokio::sync::Mutex;
+
+/// Default socket for `mcpd daemon`, next to the registry file
+pub fn daemon_socket_path(registry_path: &std::path::Path) -> std::path::PathBuf {
+    registry_path
+        .parent()
+        .map(|p| p.join("daemon.sock"))
+        .unwrap_or_else(|| "daemon.sock".into())
+}
+
+/// Reading half of a client connection
+pub type ClientReader = Pin<Box<dyn AsyncBufRead + Send>>;
+
+/// Writing half of a client connection, shared between the request loop and
+/// notification senders
+pub type ClientWriter = Arc<Mutex<Pin<Box<dyn AsyncWrite + Send>>>>;
+
+/// A bidirectional client connection
+pub trait Transport {
+    /// Split into a line reader and a shareable writer
+    fn split(self) -> (ClientReader, ClientWriter);
+}
+
+/// The process's own stdin/stdout
+pub struct Stdio;
+
+impl Transport for Stdio {
+    fn split(self) -> (ClientReader, ClientWriter) {
+        into_parts(tokio::io::stdin(), tokio::io::stdout())
+    }
+}
+
+#[cfg(unix)]
+impl Transport for tokio::net::UnixStream {
+    fn split(self) -> (ClientReader, ClientWriter) {
+        let (read, write) = self.into_split();
+        into_parts(read, write)
+    }
+}
+
+impl Transport for tokio::io::DuplexStream {
+    fn split(self) -> (ClientReader, ClientWriter) {
+        let (read, write) = tokio::io::split(self);
+        into_parts(read, write)
+    }
+}
+
+/// Any reader and writer, such as in-memory pipes in tests
+impl<R, W> Transport for (R, W)
+where
+    R: tokio::io::AsyncRead + Send + 'static,
+    W: AsyncWrite + Send + 'static,
+{
+    fn split(self) -> (ClientReader, ClientWriter) {
+        into_parts(self.0, self.1)
+    }
+}
+
+fn into_parts<R, W>(read: R, write: W) -> (ClientReader, ClientWriter)
+where
+    R: tokio::io::AsyncRead + Send + 'static,
+    W: AsyncWrite + Send + 'static,
+{
+    (
+        Box::pin(BufReader::new(read)),
+        Arc::new(Mutex::new(Box::pin(write))),
+    )
+}
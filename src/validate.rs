@@ -0,0 +1,286 @@
+//! `mcpd validate`: a strict check of a registry file that reports every
+//! problem in it, for use as a pre-commit hook.
+//!
+//! Loading a registry stops at the first problem, and silently keeps the
+//! last of two entries with the same key. Here the file is read without
+//! trusting it: duplicate keys anywhere in the document are found while it's
+//! parsed, each entry is decoded on its own so one malformed tool doesn't
+//! hide the others, and every tool goes through `Tool::problems` plus a
+//! check that its `cwd` exists on this machine.
+
+use crate::registry::{Tool, ToolValidationError};
+use indexmap::IndexMap;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Something wrong with a registry file
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Problem {
+    #[error("Not valid JSON: {0}")]
+    Syntax(String),
+    #[error("Key {path} appears more than once; only the last one would be used")]
+    DuplicateKey { path: String },
+    #[error("Not a registry: {0}")]
+    NotARegistry(&'static str),
+    #[error("Registry entry '{key}' can't be read: {error}")]
+    Unreadable { key: String, error: String },
+    #[error("Registry entry '{key}' is for a tool named '{name}'")]
+    KeyMismatch { key: String, name: String },
+    #[error(transparent)]
+    Tool(#[from] ToolValidationError),
+    #[error("Tool '{name}' has cwd {cwd}, which doesn't exist")]
+    MissingCwd { name: String, cwd: String },
+    #[error("Tool '{name}' has cwd {cwd}, which isn't a directory")]
+    CwdNotADirectory { name: String, cwd: String },
+}
+
+/// Every problem in the registry document `json`, in file order. Empty if
+/// it's fine.
+pub fn check(json: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut parser = serde_json::Deserializer::from_str(json);
+    let walked = Walk {
+        path: String::new(),
+        duplicates: &mut duplicates,
+    }
+    .deserialize(&mut parser)
+    .and_then(|()| parser.end());
+    if let Err(e) = walked {
+        return vec![Problem::Syntax(e.to_string())];
+    }
+    problems.extend(
+        duplicates
+            .into_iter()
+            .map(|path| Problem::DuplicateKey { path }),
+    );
+
+    // Syntax is fine, so this only fails on duplicates, which keep the last
+    let Ok(Value::Object(document)) = serde_json::from_str::<Value>(json) else {
+        problems.push(Problem::NotARegistry("expected a JSON object"));
+        return problems;
+    };
+    if document.get("generation").is_some_and(|g| !g.is_u64()) {
+        problems.push(Problem::NotARegistry(
+            "generation must be a non-negative integer",
+        ));
+    }
+    match document.get("tools") {
+        None => return problems,
+        Some(Value::Object(_)) => {}
+        Some(_) => {
+            problems.push(Problem::NotARegistry(
+                "tools must be an object keyed by tool name",
+            ));
+            return problems;
+        }
+    }
+    // Read again for the entries in file order
+    let Ok(Entries { tools }) = serde_json::from_str(json) else {
+        return problems;
+    };
+    for (key, entry) in tools {
+        match serde_json::from_value::<Tool>(entry) {
+            Ok(tool) => check_tool(&key, &tool, &mut problems),
+            Err(e) => problems.push(Problem::Unreadable {
+                key,
+                error: e.to_string(),
+            }),
+        }
+    }
+    problems
+}
+
+#[derive(serde::Deserialize)]
+struct Entries {
+    tools: IndexMap<String, Value>,
+}
+
+fn check_tool(key: &str, tool: &Tool, problems: &mut Vec<Problem>) {
+    if key != tool.name {
+        problems.push(Problem::KeyMismatch {
+            key: key.to_string(),
+            name: tool.name.clone(),
+        });
+    }
+    problems.extend(tool.problems().into_iter().map(Problem::Tool));
+    if let Some(cwd) = &tool.cwd {
+        let name = tool.name.clone();
+        let shown = cwd.display().to_string();
+        if !cwd.exists() {
+            problems.push(Problem::MissingCwd { name, cwd: shown });
+        } else if !cwd.is_dir() {
+            problems.push(Problem::CwdNotADirectory { name, cwd: shown });
+        }
+    }
+}
+
+/// Walks a JSON document as it's parsed, noting keys that appear twice in
+/// the same object. `path` is where the walk is, like `tools.fs.env`.
+struct Walk<'a> {
+    path: String,
+    duplicates: &'a mut Vec<String>,
+}
+
+impl Walk<'_> {
+    fn child(&self, step: &str) -> String {
+        if self.path.is_empty() {
+            step.to_string()
+        } else {
+            format!("{}.{}", self.path, step)
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Walk<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Walk<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        loop {
+            let item = Walk {
+                path: self.child(&index.to_string()),
+                duplicates: &mut *self.duplicates,
+            };
+            if seq.next_element_seed(item)?.is_none() {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut seen = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let path = self.child(&key);
+            if !seen.insert(key) {
+                self.duplicates.push(path.clone());
+            }
+            map.next_value_seed(Walk {
+                path,
+                duplicates: &mut *self.duplicates,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn valid_registry_has_no_problems() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let registry = json!({
+            "generation": 3,
+            "tools": {
+                "fs": {"name": "fs", "command": ["fs-server"], "cwd": dir.path()},
+                "git": {"name": "git", "command": ["git-server", "--repo", "."]},
+            }
+        });
+        assert_eq!(check(&registry.to_string()), vec![]);
+        assert_eq!(check("{}"), vec![]);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        let missing = dir.path().join("missing");
+        // Duplicate keys can't be built with json!, so the text is spelled out
+        let registry = format!(
+            r#"{{
+                "tools": {{
+                    "bad name": {{"name": "bad name", "command": []}},
+                    "fs": {{"name": "fs", "command": ["fs-server"], "cwd": {missing:?}}},
+                    "fs": {{"name": "fs", "command": ["fs-server"], "cwd": {file:?}}},
+                    "git": {{"name": "github", "command": [""], "env": {{"A": "1", "A": "2"}}}},
+                    "broken": {{"name": "broken", "command": "not-a-list"}}
+                }}
+            }}"#,
+        );
+        let problems: Vec<String> = check(&registry).iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            [
+                "Key tools.fs appears more than once; only the last one would be used",
+                "Key tools.git.env.A appears more than once; only the last one would be used",
+                "Tool name 'bad name' contains ' '",
+                "Tool 'bad name' has no command",
+                &format!(
+                    "Tool 'fs' has cwd {}, which isn't a directory",
+                    file.display()
+                ),
+                "Registry entry 'git' is for a tool named 'github'",
+                "Tool 'github' has an empty command",
+                "Registry entry 'broken' can't be read: invalid type: string \"not-a-list\", expected a sequence",
+            ],
+        );
+
+        let registry = json!({"tools": {"fs": {"name": "fs", "command": ["x"], "cwd": missing}}});
+        assert_eq!(
+            check(&registry.to_string()),
+            vec![Problem::MissingCwd {
+                name: "fs".to_string(),
+                cwd: missing.display().to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn documents_that_are_not_registries() {
+        assert!(matches!(&check("{\"tools\": ")[..], [Problem::Syntax(_)]));
+        assert_eq!(
+            check(r#"{"generation": -1, "tools": []}"#),
+            vec![
+                Problem::NotARegistry("generation must be a non-negative integer"),
+                Problem::NotARegistry("tools must be an object keyed by tool name"),
+            ]
+        );
+        assert_eq!(
+            check("[]"),
+            vec![Problem::NotARegistry("expected a JSON object")]
+        );
+    }
+}
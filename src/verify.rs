@@ -0,0 +1,433 @@
+//! `mcpd verify`: contract tests for a backend, written as a YAML spec
+//! (see `spec`) instead of Rust. The backend is started once; its tool and
+//! prompt listings are checked against the spec, then each scripted call is
+//! made in order and its result checked (see `matchers`). Every assertion is
+//! reported on its own, so one failure doesn't hide the rest.
+
+pub mod junit;
+pub mod matchers;
+pub mod spec;
+
+use crate::mcp::{CallToolResult, Prompt, Tool as McpTool};
+use crate::output::{Check, Color, Output};
+use crate::proxy::ToolProxy;
+use crate::registry::Tool;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use spec::{Spec, ToolSpec};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// One checked assertion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assertion {
+    /// What it's about: `tools`, `prompts`, or a call's label
+    pub group: String,
+    pub name: String,
+    /// Time spent on the request it checks, for the one that made it
+    pub elapsed: Duration,
+    /// Why it didn't hold
+    pub failure: Option<String>,
+}
+
+impl Assertion {
+    fn new(group: &str, name: String, outcome: Result<(), String>) -> Self {
+        Self {
+            group: group.to_string(),
+            name,
+            elapsed: Duration::ZERO,
+            failure: outcome.err(),
+        }
+    }
+
+    fn timed(mut self, elapsed: Duration) -> Self {
+        self.elapsed = elapsed;
+        self
+    }
+}
+
+/// Every assertion a spec made about a backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub backend: String,
+    pub assertions: Vec<Assertion>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn failed(&self) -> usize {
+        self.assertions
+            .iter()
+            .filter(|a| a.failure.is_some())
+            .count()
+    }
+}
+
+/// Start `tool`, check everything `spec` expects of it, and stop it.
+/// `timeout` bounds starting the backend and each request after that.
+/// Fails only if the backend doesn't start; everything after is a result.
+pub async fn run(spec: &Spec, tool: Tool, timeout: Duration) -> Result<Report> {
+    let started = Instant::now();
+    let backend = spec.backend_name().to_string();
+    let proxy = ToolProxy::new(tool);
+    let report = check(spec, &proxy, &backend, timeout).await;
+    let _ = proxy.stop().await;
+    let assertions = report?;
+    Ok(Report {
+        backend,
+        assertions,
+        elapsed: started.elapsed(),
+    })
+}
+
+async fn check(
+    spec: &Spec,
+    proxy: &ToolProxy,
+    backend: &str,
+    timeout: Duration,
+) -> Result<Vec<Assertion>> {
+    tokio::time::timeout(timeout, proxy.ensure_ready())
+        .await
+        .map_err(|_| anyhow!("'{}' didn't initialize within {:?}", backend, timeout))?
+        .map_err(|e| e.context(format!("Failed to initialize '{}'", backend)))?;
+
+    let mut assertions = Vec::new();
+    if !spec.tools.is_empty() {
+        let (listed, elapsed) = timed(timeout, proxy.list_tools()).await;
+        assertions.extend(check_tools(
+            &spec.tools,
+            listed.as_deref().map_err(String::as_str),
+            elapsed,
+        ));
+    }
+    if !spec.prompts.is_empty() {
+        let (listed, elapsed) = timed(timeout, proxy.list_prompts()).await;
+        assertions.extend(check_prompts(
+            &spec.prompts,
+            listed.as_deref().map_err(String::as_str),
+            elapsed,
+        ));
+    }
+    for case in &spec.calls {
+        let group = case.label();
+        let (result, elapsed) =
+            timed(timeout, proxy.call_tool(&case.tool, case.arguments.clone())).await;
+        let name = format!("{} returns a result", case.tool);
+        match result {
+            Ok(result) => {
+                assertions.push(Assertion::new(group, name, Ok(())).timed(elapsed));
+                assertions.extend(
+                    case.expect
+                        .evaluate(&result)
+                        .into_iter()
+                        .map(|(name, outcome)| Assertion::new(group, name, outcome)),
+                );
+            }
+            Err(error) => {
+                assertions.push(Assertion::new(group, name, Err(error)).timed(elapsed));
+                // Nothing to check them against, but they still count
+                let nothing = CallToolResult {
+                    content: Vec::new(),
+                    is_error: false,
+                    structured_content: None,
+                    meta: None,
+                };
+                assertions.extend(
+                    case.expect
+                        .evaluate(&nothing)
+                        .into_iter()
+                        .map(|(name, _)| Assertion::new(group, name, Err("no result".to_string()))),
+                );
+            }
+        }
+    }
+    Ok(assertions)
+}
+
+/// Run a request under `timeout`, with how long it took and any error as text
+async fn timed<T>(
+    timeout: Duration,
+    request: impl Future<Output = Result<T>>,
+) -> (Result<T, String>, Duration) {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, request).await {
+        Err(_) => Err(format!("timed out after {:?}", timeout)),
+        Ok(result) => result.map_err(|e| format!("{:#}", e)),
+    };
+    (result, started.elapsed())
+}
+
+fn check_tools(
+    specs: &[ToolSpec],
+    listed: Result<&[McpTool], &str>,
+    elapsed: Duration,
+) -> Vec<Assertion> {
+    let mut assertions = Vec::new();
+    for (i, spec) in specs.iter().enumerate() {
+        let tool = listed.map(|tools| tools.iter().find(|t| t.name == spec.name));
+        let outcome = match tool {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err("not listed".to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        // The listing's time goes to the first assertion, which waited for it
+        let elapsed = if i == 0 { elapsed } else { Duration::ZERO };
+        assertions.push(
+            Assertion::new("tools", format!("{} is listed", spec.name), outcome).timed(elapsed),
+        );
+
+        let schema = match tool {
+            Ok(Some(tool)) => Ok(&tool.input_schema),
+            Ok(None) => Err("tool isn't listed"),
+            Err(_) => Err("tools couldn't be listed"),
+        };
+        for (property, kind) in &spec.properties {
+            let name = match kind {
+                Some(kind) => format!("{} has property {} ({})", spec.name, property, kind),
+                None => format!("{} has property {}", spec.name, property),
+            };
+            let outcome = schema
+                .map_err(str::to_string)
+                .and_then(|schema| check_property(schema, property, kind.as_deref()));
+            assertions.push(Assertion::new("tools", name, outcome));
+        }
+        for property in &spec.required {
+            let outcome = schema.map_err(str::to_string).and_then(|schema| {
+                let required = schema["required"].as_array();
+                if required.is_some_and(|r| r.iter().any(|p| p == property.as_str())) {
+                    Ok(())
+                } else {
+                    Err(format!("required is {}", schema["required"]))
+                }
+            });
+            assertions.push(Assertion::new(
+                "tools",
+                format!("{} requires {}", spec.name, property),
+                outcome,
+            ));
+        }
+    }
+    assertions
+}
+
+fn check_property(schema: &Value, property: &str, kind: Option<&str>) -> Result<(), String> {
+    let Some(declared) = schema["properties"].get(property) else {
+        let properties: Vec<&str> = schema["properties"]
+            .as_object()
+            .map(|p| p.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+        return Err(if properties.is_empty() {
+            "the schema has no properties".to_string()
+        } else {
+            format!("properties are {}", properties.join(", "))
+        });
+    };
+    let Some(kind) = kind else {
+        return Ok(());
+    };
+    // `type` may be one name or a list of them
+    let matches = match &declared["type"] {
+        Value::String(t) => t == kind,
+        Value::Array(types) => types.iter().any(|t| t == kind),
+        _ => false,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(format!("type is {}", declared["type"]))
+    }
+}
+
+fn check_prompts(
+    names: &[String],
+    listed: Result<&[Prompt], &str>,
+    elapsed: Duration,
+) -> Vec<Assertion> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let outcome = match listed {
+                Ok(prompts) if prompts.iter().any(|p| &p.name == name) => Ok(()),
+                Ok(_) => Err("not listed".to_string()),
+                Err(error) => Err(error.to_string()),
+            };
+            let elapsed = if i == 0 { elapsed } else { Duration::ZERO };
+            Assertion::new("prompts", format!("{} is listed", name), outcome).timed(elapsed)
+        })
+        .collect()
+}
+
+/// The report as a test runner prints it: assertions under their group,
+/// failures explained, and a count at the end
+pub fn render(report: &Report, out: &Output) -> String {
+    let mut text = String::new();
+    let mut group = None;
+    for assertion in &report.assertions {
+        if group != Some(&assertion.group) {
+            group = Some(&assertion.group);
+            writeln!(text, "{}", out.paint(&assertion.group, Color::Bold)).unwrap();
+        }
+        let check = match assertion.failure {
+            None => Check::Pass,
+            Some(_) => Check::Fail,
+        };
+        writeln!(text, "  {} {}", out.check(check), assertion.name).unwrap();
+        if let Some(failure) = &assertion.failure {
+            for line in failure.lines() {
+                writeln!(text, "      {}", out.paint(line, Color::Dim)).unwrap();
+            }
+        }
+    }
+    let failed = report.failed();
+    let summary = format!(
+        "{}: {} passed, {} failed ({:.2}s)",
+        report.backend,
+        report.assertions.len() - failed,
+        failed,
+        report.elapsed.as_secs_f64()
+    );
+    let color = if failed == 0 {
+        Color::Green
+    } else {
+        Color::Red
+    };
+    writeln!(text, "\n{}", out.paint(&summary, color)).unwrap();
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str, schema: Value) -> McpTool {
+        McpTool {
+            name: name.to_string(),
+            title: None,
+            description: None,
+            input_schema: schema,
+            annotations: None,
+        }
+    }
+
+    fn tool_spec(yaml: &str) -> Vec<ToolSpec> {
+        serde_yaml_ng::from_str(yaml).unwrap()
+    }
+
+    fn outcomes(assertions: &[Assertion]) -> Vec<(&str, Option<&str>)> {
+        assertions
+            .iter()
+            .map(|a| (a.name.as_str(), a.failure.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn tools_are_checked_down_to_their_schemas() {
+        let listed = [tool(
+            "read",
+            json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}, "lines": {"type": ["integer", "null"]}},
+                "required": ["path"]
+            }),
+        )];
+        let specs = tool_spec(
+            "- name: read\n  properties: {path: string, lines: integer, mode: ~, encoding: ~}\n  required: [path, lines]\n\
+             - name: write\n  properties: {path: string}\n",
+        );
+        let assertions = check_tools(&specs, Ok(&listed), Duration::from_millis(5));
+        assert_eq!(
+            outcomes(&assertions),
+            [
+                ("read is listed", None),
+                ("read has property path (string)", None),
+                ("read has property lines (integer)", None),
+                ("read has property mode", Some("properties are lines, path")),
+                (
+                    "read has property encoding",
+                    Some("properties are lines, path")
+                ),
+                ("read requires path", None),
+                ("read requires lines", Some(r#"required is ["path"]"#)),
+                ("write is listed", Some("not listed")),
+                (
+                    "write has property path (string)",
+                    Some("tool isn't listed")
+                ),
+            ]
+        );
+        assert_eq!(assertions[0].elapsed, Duration::from_millis(5));
+        assert_eq!(assertions[7].elapsed, Duration::ZERO);
+
+        let listed = [tool(
+            "read",
+            json!({"properties": {"path": {"type": "integer"}}}),
+        )];
+        let specs = tool_spec("- name: read\n  properties: {path: string}\n");
+        assert_eq!(
+            outcomes(&check_tools(&specs, Ok(&listed), Duration::ZERO))[1],
+            (
+                "read has property path (string)",
+                Some(r#"type is "integer""#)
+            )
+        );
+        let listed = [tool("read", json!({"type": "object"}))];
+        assert_eq!(
+            outcomes(&check_tools(&specs, Ok(&listed), Duration::ZERO))[1].1,
+            Some("the schema has no properties")
+        );
+    }
+
+    #[test]
+    fn a_failed_listing_fails_everything_that_needed_it() {
+        let specs = tool_spec("- name: read\n  required: [path]\n");
+        assert_eq!(
+            outcomes(&check_tools(
+                &specs,
+                Err("timed out after 1s"),
+                Duration::ZERO
+            )),
+            [
+                ("read is listed", Some("timed out after 1s")),
+                ("read requires path", Some("tools couldn't be listed")),
+            ]
+        );
+        let names = ["greet".to_string()];
+        assert_eq!(
+            outcomes(&check_prompts(
+                &names,
+                Err("Method not found"),
+                Duration::ZERO
+            )),
+            [("greet is listed", Some("Method not found"))]
+        );
+    }
+
+    #[test]
+    fn render_groups_assertions_and_counts_them() {
+        let report = Report {
+            backend: "fs".to_string(),
+            elapsed: Duration::from_millis(250),
+            assertions: vec![
+                Assertion::new("tools", "read is listed".to_string(), Ok(())),
+                Assertion::new(
+                    "tools",
+                    "write is listed".to_string(),
+                    Err("not listed".to_string()),
+                ),
+                Assertion::new(
+                    "reads a file",
+                    "text contains \"x\"".to_string(),
+                    Err("text was \"a\nb\"".to_string()),
+                ),
+            ],
+        };
+        assert_eq!(
+            render(&report, &Output::plain()),
+            "tools\n  ✓ read is listed\n  ✗ write is listed\n      not listed\n\
+             reads a file\n  ✗ text contains \"x\"\n      text was \"a\n      b\"\n\
+             \nfs: 1 passed, 2 failed (0.25s)\n"
+        );
+    }
+}
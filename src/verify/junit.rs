@@ -0,0 +1,128 @@
+//! JUnit XML for `mcpd verify --junit`, the format CI systems read test
+//! results from: one `testsuite` for the backend, one `testcase` per
+//! assertion, classed by what it checks (`<backend>.tools`,
+//! `<backend>.prompts`, `<backend>.<call>`).
+
+use super::Report;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// `report` as a JUnit XML document
+pub fn render(report: &Report) -> String {
+    let tests = report.assertions.len();
+    let failures = report.failed();
+    let time = seconds(report.elapsed);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        xml,
+        r#"<testsuites name="mcpd verify" tests="{}" failures="{}" time="{}">"#,
+        tests, failures, time
+    )
+    .unwrap();
+    writeln!(
+        xml,
+        r#"  <testsuite name="{}" tests="{}" failures="{}" errors="0" time="{}">"#,
+        escape(&report.backend),
+        tests,
+        failures,
+        time
+    )
+    .unwrap();
+    for assertion in &report.assertions {
+        write!(
+            xml,
+            r#"    <testcase classname="{}.{}" name="{}" time="{}""#,
+            escape(&report.backend),
+            escape(&assertion.group),
+            escape(&assertion.name),
+            seconds(assertion.elapsed)
+        )
+        .unwrap();
+        match &assertion.failure {
+            None => xml.push_str("/>\n"),
+            Some(failure) => {
+                writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                    escape(failure),
+                    escape(failure)
+                )
+                .unwrap();
+            }
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// Text safe in both attributes and element content. Characters XML 1.0
+/// can't hold at all become U+FFFD.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => out.push_str("&#13;"),
+            '\t' => out.push_str("&#9;"),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => out.push('\u{FFFD}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::Assertion;
+
+    #[test]
+    fn one_testcase_per_assertion() {
+        let report = Report {
+            backend: "fs".to_string(),
+            elapsed: Duration::from_millis(1500),
+            assertions: vec![
+                Assertion {
+                    group: "tools".to_string(),
+                    name: "read_file is listed".to_string(),
+                    elapsed: Duration::from_millis(12),
+                    failure: None,
+                },
+                Assertion {
+                    group: "reads a file".to_string(),
+                    name: "text contains \"<root>\"".to_string(),
+                    elapsed: Duration::ZERO,
+                    failure: Some("text was \"a & b\"\nand more".to_string()),
+                },
+            ],
+        };
+        assert_eq!(
+            render(&report),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="mcpd verify" tests="2" failures="1" time="1.500">
+  <testsuite name="fs" tests="2" failures="1" errors="0" time="1.500">
+    <testcase classname="fs.tools" name="read_file is listed" time="0.012"/>
+    <testcase classname="fs.reads a file" name="text contains &quot;&lt;root&gt;&quot;" time="0.000">
+      <failure message="text was &quot;a &amp; b&quot;&#10;and more">text was &quot;a &amp; b&quot;&#10;and more</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn characters_xml_cannot_hold_are_replaced() {
+        assert_eq!(escape("a\u{1b}[31mb\u{0}'"), "a\u{FFFD}[31mb\u{FFFD}&apos;");
+        assert_eq!(escape("tab\there"), "tab&#9;here");
+    }
+}
@@ -0,0 +1,353 @@
+//! Expectations on a tool call's result for `mcpd verify`: its error flag,
+//! its text content (substring or regex), and values in its
+//! `structuredContent` picked out by a JSONPath.
+//!
+//! Only the JSONPath subset that names one value is supported: `$`, then
+//! `.key`, `['key']` or `[index]` steps. A path that picks out nothing
+//! fails the expectation rather than comparing equal to `null`.
+
+use crate::mcp::{CallToolResult, Content};
+use crate::output::truncate_middle;
+use anyhow::{Result, bail};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Longest stretch of a result quoted in a failure message
+const MAX_QUOTED_CHARS: usize = 200;
+
+/// What a call's result must look like. Unset fields aren't checked.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    #[serde(default)]
+    pub is_error: Option<bool>,
+    /// Substring of the text content
+    #[serde(default)]
+    pub contains: Option<String>,
+    /// Regex the text content must match somewhere
+    #[serde(default)]
+    pub matches: Option<String>,
+    /// JSONPath into `structuredContent` → the value it must equal
+    #[serde(default)]
+    pub structured: IndexMap<String, Value>,
+}
+
+/// The outcome of one expectation: what it says, and why it didn't hold
+pub type Checked = (String, Result<(), String>);
+
+impl Expect {
+    /// Check every expectation against `result`, in a fixed order. The
+    /// spec is checked when loaded, so patterns and paths compile here.
+    pub fn evaluate(&self, result: &CallToolResult) -> Vec<Checked> {
+        let mut checked = Vec::new();
+        if let Some(is_error) = self.is_error {
+            let outcome = if result.is_error == is_error {
+                Ok(())
+            } else {
+                Err(match result.is_error {
+                    true => format!("was true: {}", quote(&text(result))),
+                    false => "was false".to_string(),
+                })
+            };
+            checked.push((format!("is_error is {}", is_error), outcome));
+        }
+        if let Some(needle) = &self.contains {
+            let text = text(result);
+            let outcome = if text.contains(needle.as_str()) {
+                Ok(())
+            } else {
+                Err(format!("text was {}", quote(&text)))
+            };
+            checked.push((format!("text contains {:?}", needle), outcome));
+        }
+        if let Some(pattern) = &self.matches {
+            let name = format!("text matches /{}/", pattern);
+            let outcome = match regex::Regex::new(pattern) {
+                Ok(regex) => {
+                    let text = text(result);
+                    if regex.is_match(&text) {
+                        Ok(())
+                    } else {
+                        Err(format!("text was {}", quote(&text)))
+                    }
+                }
+                Err(e) => Err(e.to_string()),
+            };
+            checked.push((name, outcome));
+        }
+        for (path, expected) in &self.structured {
+            let name = format!("{} equals {}", path, expected);
+            let outcome = JsonPath::parse(path)
+                .map_err(|e| e.to_string())
+                .and_then(|path| structured_equals(&path, expected, result));
+            checked.push((name, outcome));
+        }
+        checked
+    }
+}
+
+fn structured_equals(
+    path: &JsonPath,
+    expected: &Value,
+    result: &CallToolResult,
+) -> Result<(), String> {
+    let Some(structured) = &result.structured_content else {
+        return Err("the result has no structuredContent".to_string());
+    };
+    match path.select(structured) {
+        None => Err(format!(
+            "matched nothing in {}",
+            quote(&structured.to_string())
+        )),
+        Some(actual) if json_equal(actual, expected) => Ok(()),
+        Some(actual) => Err(format!(
+            "was {}",
+            truncate_middle(&actual.to_string(), MAX_QUOTED_CHARS)
+        )),
+    }
+}
+
+/// Equality that doesn't care how a number was written: YAML's `1` and a
+/// backend's `1.0` are the same value
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a == b || a.as_f64() == b.as_f64(),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).is_some_and(|other| json_equal(v, other)))
+        }
+        _ => a == b,
+    }
+}
+
+/// A result's text content, one block per line
+pub fn text(result: &CallToolResult) -> String {
+    result
+        .content
+        .iter()
+        .filter_map(|c| match c {
+            Content::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn quote(text: &str) -> String {
+    format!("{:?}", truncate_middle(text, MAX_QUOTED_CHARS))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A JSONPath naming at most one value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let Some(mut rest) = path.strip_prefix('$') else {
+            bail!("a path starts with $");
+        };
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    bail!("empty key after '.'");
+                }
+                segments.push(Segment::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let Some(end) = after.find(']') else {
+                    bail!("unclosed '['");
+                };
+                let inside = &after[..end];
+                let quoted = ['\'', '"'].into_iter().find_map(|q| {
+                    inside
+                        .strip_prefix(q)
+                        .and_then(|s| s.strip_suffix(q))
+                        .filter(|_| inside.len() >= 2)
+                });
+                segments.push(match quoted {
+                    Some(key) => Segment::Key(key.to_string()),
+                    None => Segment::Index(inside.parse().map_err(|_| {
+                        anyhow::anyhow!(
+                            "[{}] isn't an index or a quoted key (wildcards, slices and filters aren't supported)",
+                            inside
+                        )
+                    })?),
+                });
+                rest = &after[end + 1..];
+            } else {
+                bail!("expected '.' or '[' at {:?}", rest);
+            }
+        }
+        Ok(Self { segments })
+    }
+
+    /// The value the path names in `value`, if there is one
+    pub fn select<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.segments
+            .iter()
+            .try_fold(value, |value, segment| match segment {
+                Segment::Key(key) => value.as_object()?.get(key),
+                Segment::Index(i) => value.as_array()?.get(*i),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result(text: &str, is_error: bool, structured: Option<Value>) -> CallToolResult {
+        CallToolResult {
+            content: vec![Content::Text {
+                text: text.to_string(),
+            }],
+            is_error,
+            structured_content: structured,
+            meta: None,
+        }
+    }
+
+    fn failures(expect: Expect, result: &CallToolResult) -> Vec<(String, String)> {
+        expect
+            .evaluate(result)
+            .into_iter()
+            .filter_map(|(name, outcome)| Some((name, outcome.err()?)))
+            .collect()
+    }
+
+    #[test]
+    fn paths_pick_out_one_value() {
+        let value = json!({"a": {"b": [10, {"c d": true}]}, "x.y": 1});
+        let select = |path: &str| JsonPath::parse(path).unwrap().select(&value).cloned();
+        assert_eq!(select("$"), Some(value.clone()));
+        assert_eq!(select("$.a.b[0]"), Some(json!(10)));
+        assert_eq!(select("$.a.b[1]['c d']"), Some(json!(true)));
+        assert_eq!(select("$[\"x.y\"]"), Some(json!(1)));
+        assert_eq!(select("$['a'].b[1][\"c d\"]"), Some(json!(true)));
+        assert_eq!(select("$.a.b[2]"), None);
+        assert_eq!(select("$.a.missing"), None);
+        // Indexing an object or keying an array matches nothing
+        assert_eq!(select("$.a[0]"), None);
+        assert_eq!(select("$.a.b.c"), None);
+    }
+
+    #[test]
+    fn paths_outside_the_subset_are_refused() {
+        for (path, error) in [
+            ("a.b", "starts with $"),
+            ("$.", "empty key"),
+            ("$..a", "empty key"),
+            ("$[0", "unclosed"),
+            ("$[*]", "wildcards"),
+            ("$[-1]", "isn't an index"),
+            ("$[']", "isn't an index"),
+            ("$a", "expected '.' or '['"),
+        ] {
+            let message = JsonPath::parse(path).unwrap_err().to_string();
+            assert!(message.contains(error), "{}: {}", path, message);
+        }
+    }
+
+    #[test]
+    fn text_matchers_check_every_text_block() {
+        let mut ok = result("first line", false, None);
+        ok.content.push(Content::Text {
+            text: "id=42".to_string(),
+        });
+        let expect = Expect {
+            is_error: Some(false),
+            contains: Some("line\nid=".to_string()),
+            matches: Some(r"^id=\d+$".to_string()),
+            ..Default::default()
+        };
+        // Multi-line mode isn't on, so ^...$ must match the whole text
+        assert_eq!(
+            failures(expect.clone(), &ok),
+            [(
+                r"text matches /^id=\d+$/".to_string(),
+                r#"text was "first line\nid=42""#.to_string()
+            )]
+        );
+        let expect = Expect {
+            matches: Some(r"(?m)^id=\d+$".to_string()),
+            ..expect
+        };
+        assert!(failures(expect, &ok).is_empty());
+    }
+
+    #[test]
+    fn failures_say_what_was_there_instead() {
+        let failed = result("permission denied", true, None);
+        let expect = Expect {
+            is_error: Some(false),
+            contains: Some("contents".to_string()),
+            structured: IndexMap::from([("$.size".to_string(), json!(3))]),
+            ..Default::default()
+        };
+        assert_eq!(
+            failures(expect, &failed),
+            [
+                (
+                    "is_error is false".to_string(),
+                    r#"was true: "permission denied""#.to_string()
+                ),
+                (
+                    r#"text contains "contents""#.to_string(),
+                    r#"text was "permission denied""#.to_string()
+                ),
+                (
+                    "$.size equals 3".to_string(),
+                    "the result has no structuredContent".to_string()
+                ),
+            ]
+        );
+
+        let long = "x".repeat(1000);
+        let (_, outcome) = Expect {
+            contains: Some("y".to_string()),
+            ..Default::default()
+        }
+        .evaluate(&result(&long, false, None))
+        .remove(0);
+        assert!(outcome.unwrap_err().chars().count() < MAX_QUOTED_CHARS + 20);
+    }
+
+    #[test]
+    fn structured_values_compare_as_json() {
+        let ok = result(
+            "",
+            false,
+            Some(json!({"items": [{"id": 1.0, "tags": ["a"]}], "total": 1})),
+        );
+        let expect = |path: &str, value: Value| Expect {
+            structured: IndexMap::from([(path.to_string(), value)]),
+            ..Default::default()
+        };
+        assert!(failures(expect("$.items[0].id", json!(1)), &ok).is_empty());
+        assert!(failures(expect("$.items[0]", json!({"tags": ["a"], "id": 1})), &ok).is_empty());
+        assert!(failures(expect("$.total", json!(1)), &ok).is_empty());
+        assert_eq!(failures(expect("$.total", json!("1")), &ok)[0].1, "was 1");
+        assert_eq!(
+            failures(expect("$.items[0].tags", json!(["a", "b"])), &ok)[0].1,
+            r#"was ["a"]"#
+        );
+        let missing = &failures(expect("$.items[1]", json!(null)), &ok)[0].1;
+        assert!(missing.starts_with("matched nothing in"), "{}", missing);
+    }
+}
@@ -0,0 +1,247 @@
+//! The `mcpd verify` spec file: which backend to run and what to expect
+//! from it. Specs are YAML and checked in full when loaded, so a typo in a
+//! regex or path fails before the backend starts rather than as a failed
+//! assertion.
+
+use super::matchers::{Expect, JsonPath};
+use crate::registry::Tool;
+use anyhow::{Context, Result, bail};
+use indexmap::IndexMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Name given to a backend run from an inline `command`
+pub const INLINE_NAME: &str = "inline";
+
+/// A contract for one backend
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    /// Registered backend to run
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Command to run instead of a registered backend
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// Environment for `command`
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Tools the backend must list
+    #[serde(default)]
+    pub tools: Vec<ToolSpec>,
+    /// Prompts the backend must list, by name
+    #[serde(default)]
+    pub prompts: Vec<String>,
+    /// Tool calls to make, in order
+    #[serde(default)]
+    pub calls: Vec<CallCase>,
+}
+
+/// A tool that must be listed, and what its input schema must declare
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolSpec {
+    pub name: String,
+    /// Properties the input schema must have, each with the JSON Schema
+    /// type it must declare, or `~` for any
+    #[serde(default)]
+    pub properties: IndexMap<String, Option<String>>,
+    /// Properties the input schema must list as required
+    #[serde(default)]
+    pub required: Vec<String>,
+}
+
+/// A scripted tool call and what its result must look like
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CallCase {
+    /// Shown in the report (default: the tool name)
+    #[serde(default)]
+    pub name: Option<String>,
+    pub tool: String,
+    #[serde(default = "empty_object")]
+    pub arguments: Value,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+fn empty_object() -> Value {
+    Value::Object(Default::default())
+}
+
+impl CallCase {
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.tool)
+    }
+}
+
+impl Spec {
+    /// Parse and check a spec
+    pub fn parse(text: &str) -> Result<Self> {
+        let spec: Spec = serde_yaml_ng::from_str(text)?;
+        spec.check()?;
+        Ok(spec)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid spec {}", path.display()))
+    }
+
+    fn check(&self) -> Result<()> {
+        match (&self.backend, &self.command) {
+            (Some(_), Some(_)) => bail!("give either backend or command, not both"),
+            (None, None) => bail!("missing backend (a registered name) or command"),
+            (Some(_), None) if !self.env.is_empty() => {
+                bail!("env only applies to command; a registered backend has its own")
+            }
+            (None, Some(command)) if command.is_empty() => bail!("command is empty"),
+            _ => {}
+        }
+        if self.tools.is_empty() && self.prompts.is_empty() && self.calls.is_empty() {
+            bail!("nothing to check: add tools, prompts or calls");
+        }
+        for (i, call) in self.calls.iter().enumerate() {
+            if !call.arguments.is_object() {
+                bail!(
+                    "calls[{}] ({}): arguments must be a mapping",
+                    i,
+                    call.label()
+                );
+            }
+            call.expect
+                .check()
+                .with_context(|| format!("calls[{}] ({})", i, call.label()))?;
+        }
+        Ok(())
+    }
+
+    /// Name the backend is reported under
+    pub fn backend_name(&self) -> &str {
+        self.backend.as_deref().unwrap_or(INLINE_NAME)
+    }
+
+    /// The backend to run: `registered` looks up a registered one by name
+    pub fn resolve(&self, registered: impl FnOnce(&str) -> Option<Tool>) -> Result<Tool> {
+        if let Some(name) = &self.backend {
+            return registered(name).with_context(|| format!("Tool '{}' not found", name));
+        }
+        let tool = Tool {
+            name: INLINE_NAME.to_string(),
+            command: self.command.clone().unwrap_or_default(),
+            env: self.env.clone(),
+            ..Default::default()
+        };
+        tool.validate()?;
+        Ok(tool)
+    }
+}
+
+impl Expect {
+    /// Compile what needs compiling, so a bad pattern fails the spec
+    fn check(&self) -> Result<()> {
+        if let Some(pattern) = &self.matches {
+            regex::Regex::new(pattern).context("matches isn't a valid regex")?;
+        }
+        for path in self.structured.keys() {
+            JsonPath::parse(path).with_context(|| format!("structured path '{}'", path))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FULL: &str = r#"
+backend: fs
+tools:
+  - name: read_file
+    properties:
+      path: string
+      encoding: ~
+    required: [path]
+  - name: list_dir
+prompts: [summarize]
+calls:
+  - name: reads a file
+    tool: read_file
+    arguments: {path: /etc/hostname}
+    expect:
+      is_error: false
+      contains: host
+      matches: "^\\w+"
+      structured:
+        $.lines[0]: 1
+  - tool: list_dir
+"#;
+
+    fn parse_error(text: &str) -> String {
+        format!("{:#}", Spec::parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn parses_every_kind_of_assertion() {
+        let spec = Spec::parse(FULL).unwrap();
+        assert_eq!(spec.backend_name(), "fs");
+        assert_eq!(spec.tools[0].properties["path"].as_deref(), Some("string"));
+        assert_eq!(spec.tools[0].properties["encoding"], None);
+        assert_eq!(spec.tools[0].required, ["path"]);
+        assert!(spec.tools[1].properties.is_empty());
+        assert_eq!(spec.prompts, ["summarize"]);
+
+        let call = &spec.calls[0];
+        assert_eq!(call.label(), "reads a file");
+        assert_eq!(call.arguments, json!({"path": "/etc/hostname"}));
+        assert_eq!(call.expect.is_error, Some(false));
+        assert_eq!(call.expect.contains.as_deref(), Some("host"));
+        assert_eq!(call.expect.matches.as_deref(), Some("^\\w+"));
+        assert_eq!(call.expect.structured["$.lines[0]"], json!(1));
+
+        assert_eq!(spec.calls[1].label(), "list_dir");
+        assert_eq!(spec.calls[1].arguments, json!({}));
+        assert_eq!(spec.calls[1].expect, Expect::default());
+    }
+
+    #[test]
+    fn inline_commands_become_a_backend() {
+        let spec =
+            Spec::parse("command: [my-server, --stdio]\nenv: {TOKEN: x}\nprompts: [greet]\n")
+                .unwrap();
+        assert_eq!(spec.backend_name(), INLINE_NAME);
+        let tool = spec.resolve(|_| panic!("not registered")).unwrap();
+        assert_eq!(tool.command, ["my-server", "--stdio"]);
+        assert_eq!(tool.env["TOKEN"], "x");
+
+        let spec = Spec::parse("backend: fs\nprompts: [greet]\n").unwrap();
+        let error = spec.resolve(|_| None).unwrap_err();
+        assert_eq!(error.to_string(), "Tool 'fs' not found");
+    }
+
+    #[test]
+    fn bad_specs_fail_before_anything_runs() {
+        assert!(parse_error("prompts: [greet]").contains("missing backend"));
+        assert!(parse_error("backend: fs\ncommand: [x]\nprompts: [greet]").contains("not both"));
+        assert!(parse_error("backend: fs\nenv: {A: b}\nprompts: [greet]").contains("env only"));
+        assert!(parse_error("command: []\nprompts: [greet]").contains("command is empty"));
+        assert!(parse_error("backend: fs").contains("nothing to check"));
+        assert!(parse_error("backend: fs\nprompt: [greet]").contains("unknown field"));
+        assert!(
+            parse_error("backend: fs\ncalls: [{tool: t, arguments: [1]}]")
+                .contains("must be a mapping")
+        );
+        let error = parse_error("backend: fs\ncalls: [{tool: t, expect: {matches: '('}}]");
+        assert!(
+            error.contains("calls[0] (t): matches isn't a valid regex"),
+            "{}",
+            error
+        );
+        let error = parse_error("backend: fs\ncalls: [{tool: t, expect: {structured: {a.b: 1}}}]");
+        assert!(error.contains("structured path 'a.b'"), "{}", error);
+    }
+}
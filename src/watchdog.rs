@@ -0,0 +1,361 @@
+//! Detecting stalls of mcpd's own event loop.
+//!
+//! A task on the runtime pings the server every interval, taking the same
+//! locks and spawning the same kind of task real traffic does; a thread of
+//! its own, outside the runtime, watches for a ping that doesn't come back
+//! within the threshold. Because the watcher doesn't need the runtime it
+//! still fires when every worker is blocked or a lock is held across an
+//! await. A stall is logged with a dump of what was in flight and, under
+//! `--watchdog-abort`, mcpd exits with `STALL_EXIT_CODE` so a supervisor
+//! restarts it instead of leaving a hung aggregator behind.
+//!
+//! Pings never reach a client or a backend and aren't counted as calls.
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+/// How often the server is pinged
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default for `serve --watchdog-threshold`
+pub const DEFAULT_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Exit status under `--watchdog-abort` (`EX_SOFTWARE` from sysexits.h),
+/// which mcpd uses for nothing else
+pub const STALL_EXIT_CODE: i32 = 70;
+
+/// What the ping is waiting for between pings: its timer to fire
+const PARKED_ON_TIMER: &str = "its next turn on the runtime";
+
+/// Settings for the watchdog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogOptions {
+    /// Time between one ping's answer and the next ping
+    pub interval: Duration,
+    /// How long a ping may go unanswered before it's a stall
+    pub threshold: Duration,
+    /// Exit with `STALL_EXIT_CODE` on a stall, after logging it
+    pub abort: bool,
+}
+
+impl Default for WatchdogOptions {
+    fn default() -> Self {
+        Self {
+            interval: PING_INTERVAL,
+            threshold: DEFAULT_THRESHOLD,
+            abort: false,
+        }
+    }
+}
+
+/// A call in flight when a stall was detected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StalledCall {
+    pub backend: String,
+    pub correlation_id: String,
+    pub tool: String,
+    pub elapsed_ms: u64,
+    /// What the call was waiting on
+    pub stage: String,
+}
+
+/// Messages waiting in one client's outbound queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QueueDepth {
+    pub connection: u64,
+    pub queued: usize,
+}
+
+/// Everything known about a stall when it was detected
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StallReport {
+    /// How long the ping had been waiting
+    pub ping_elapsed_ms: u64,
+    /// The step of the ping that hadn't finished: a lock, a spawned task,
+    /// or the ping's own timer when the runtime never got back to it
+    pub parked_on: String,
+    /// Longest-running first
+    pub active_calls: Vec<StalledCall>,
+    pub outbound_queues: Vec<QueueDepth>,
+    /// Calls waiting for a slot under `--max-concurrent-calls`
+    pub waiting_for_slot: usize,
+}
+
+impl fmt::Display for StallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "ping unanswered for {}ms, waiting on {}",
+            self.ping_elapsed_ms, self.parked_on
+        )?;
+        if self.active_calls.is_empty() {
+            writeln!(f, "no calls in flight")?;
+        }
+        for call in &self.active_calls {
+            writeln!(
+                f,
+                "call {} {}/{}: {}ms, in {}",
+                call.correlation_id, call.backend, call.tool, call.elapsed_ms, call.stage
+            )?;
+        }
+        for queue in self.outbound_queues.iter().filter(|q| q.queued > 0) {
+            writeln!(
+                f,
+                "connection {}: {} messages queued",
+                queue.connection, queue.queued
+            )?;
+        }
+        write!(f, "{} calls waiting for a slot", self.waiting_for_slot)
+    }
+}
+
+/// The ping in progress, or the wait for the next one
+struct Ping {
+    /// When the ping was sent, or when the next one was due
+    since: Instant,
+    parked_on: &'static str,
+    /// Whether this wait was already reported as a stall
+    reported: bool,
+}
+
+/// Pings shared between the pinging task and the watching thread
+pub struct Watchdog {
+    options: WatchdogOptions,
+    ping: Mutex<Ping>,
+    last_stall: Mutex<Option<StallReport>>,
+    stopped: AtomicBool,
+    /// `std::process::exit`, except in tests
+    exit: fn(i32),
+}
+
+impl Watchdog {
+    pub fn new(options: WatchdogOptions) -> Self {
+        Self {
+            options,
+            ping: Mutex::new(Ping {
+                since: Instant::now() + options.interval,
+                parked_on: PARKED_ON_TIMER,
+                reported: false,
+            }),
+            last_stall: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+            exit: |code| std::process::exit(code),
+        }
+    }
+
+    pub fn options(&self) -> &WatchdogOptions {
+        &self.options
+    }
+
+    /// Start a ping, waiting first on `step`
+    pub fn send(&self, step: &'static str) {
+        *self.ping.lock().unwrap() = Ping {
+            since: Instant::now(),
+            parked_on: step,
+            reported: false,
+        };
+    }
+
+    /// Move the ping in progress on to waiting for `step`
+    pub fn parked_on(&self, step: &'static str) {
+        self.ping.lock().unwrap().parked_on = step;
+    }
+
+    /// Finish the ping in progress. The next one is due an interval from now.
+    pub fn answered(&self) {
+        let mut ping = self.ping.lock().unwrap();
+        if ping.reported {
+            warn!(
+                after_ms = ping.since.elapsed().as_millis() as u64,
+                "mcpd recovered from a stall"
+            );
+        }
+        *ping = Ping {
+            since: Instant::now() + self.options.interval,
+            parked_on: PARKED_ON_TIMER,
+            reported: false,
+        };
+    }
+
+    /// The most recent stall, if there was one
+    pub fn last_stall(&self) -> Option<StallReport> {
+        self.last_stall.lock().unwrap().clone()
+    }
+
+    /// Watch pings on a thread of its own until the returned guard is
+    /// dropped. On a stall `diagnostics` fills in the report's calls and
+    /// queues; it runs on that thread, so it mustn't wait on the runtime.
+    pub fn watch(
+        self: &Arc<Self>,
+        diagnostics: impl Fn() -> StallReport + Send + 'static,
+    ) -> WatchGuard {
+        let watchdog = Arc::clone(self);
+        let check_every = (self.options.threshold / 4).max(Duration::from_millis(1));
+        std::thread::Builder::new()
+            .name("mcpd-watchdog".to_string())
+            .spawn(move || {
+                while !watchdog.stopped.load(Ordering::Acquire) {
+                    std::thread::sleep(check_every);
+                    watchdog.check(&diagnostics);
+                }
+            })
+            .expect("failed to spawn the watchdog thread");
+        WatchGuard(Arc::clone(self))
+    }
+
+    /// Report the current wait if it's gone over the threshold, once
+    fn check(&self, diagnostics: &impl Fn() -> StallReport) {
+        let (elapsed, parked_on) = {
+            let mut ping = self.ping.lock().unwrap();
+            let elapsed = Instant::now().saturating_duration_since(ping.since);
+            if ping.reported || elapsed < self.options.threshold {
+                return;
+            }
+            ping.reported = true;
+            (elapsed, ping.parked_on)
+        };
+        let report = StallReport {
+            ping_elapsed_ms: elapsed.as_millis() as u64,
+            parked_on: parked_on.to_string(),
+            ..diagnostics()
+        };
+        error!(
+            threshold_ms = self.options.threshold.as_millis() as u64,
+            "mcpd stalled: {}", report
+        );
+        *self.last_stall.lock().unwrap() = Some(report);
+        if self.options.abort {
+            error!(
+                code = STALL_EXIT_CODE,
+                "Exiting after a stall (--watchdog-abort)"
+            );
+            (self.exit)(STALL_EXIT_CODE);
+        }
+    }
+}
+
+/// Stops the watching thread when dropped
+pub struct WatchGuard(Arc<Watchdog>);
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        self.0.stopped.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicI32;
+
+    fn options(abort: bool) -> WatchdogOptions {
+        WatchdogOptions {
+            interval: Duration::from_millis(10),
+            threshold: Duration::from_millis(50),
+            abort,
+        }
+    }
+
+    fn diagnostics() -> StallReport {
+        StallReport {
+            active_calls: vec![StalledCall {
+                backend: "fs".to_string(),
+                correlation_id: "mcpd-1-1".to_string(),
+                tool: "read_file".to_string(),
+                elapsed_ms: 900,
+                stage: "backend".to_string(),
+            }],
+            outbound_queues: vec![
+                QueueDepth {
+                    connection: 1,
+                    queued: 0,
+                },
+                QueueDepth {
+                    connection: 2,
+                    queued: 12,
+                },
+            ],
+            waiting_for_slot: 3,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn answered_pings_are_not_stalls() {
+        let watchdog = Watchdog::new(options(false));
+        watchdog.send("the backends lock");
+        watchdog.check(&diagnostics);
+        watchdog.answered();
+        std::thread::sleep(Duration::from_millis(40));
+        // Still within threshold of the next ping being due
+        watchdog.check(&diagnostics);
+        assert_eq!(watchdog.last_stall(), None);
+    }
+
+    #[test]
+    fn stall_reported_once_with_where_the_ping_is_parked() {
+        let watchdog = Watchdog::new(options(false));
+        watchdog.send("the backends lock");
+        watchdog.parked_on("the registry lock");
+        std::thread::sleep(Duration::from_millis(60));
+        watchdog.check(&diagnostics);
+
+        let report = watchdog.last_stall().unwrap();
+        assert!(report.ping_elapsed_ms >= 50, "{report:?}");
+        assert_eq!(report.parked_on, "the registry lock");
+        assert_eq!(report.active_calls, diagnostics().active_calls);
+
+        // The same wait isn't reported again
+        *watchdog.last_stall.lock().unwrap() = None;
+        watchdog.check(&diagnostics);
+        assert_eq!(watchdog.last_stall(), None);
+    }
+
+    #[test]
+    fn a_ping_that_never_starts_is_a_stall() {
+        let watchdog = Watchdog::new(options(false));
+        watchdog.answered();
+        std::thread::sleep(Duration::from_millis(70));
+        watchdog.check(&diagnostics);
+        assert_eq!(watchdog.last_stall().unwrap().parked_on, PARKED_ON_TIMER);
+    }
+
+    #[test]
+    fn report_lists_calls_and_busy_queues() {
+        let report = StallReport {
+            ping_elapsed_ms: 12000,
+            parked_on: "the backends lock".to_string(),
+            ..diagnostics()
+        };
+        assert_eq!(
+            report.to_string(),
+            "ping unanswered for 12000ms, waiting on the backends lock\n\
+             call mcpd-1-1 fs/read_file: 900ms, in backend\n\
+             connection 2: 12 messages queued\n\
+             3 calls waiting for a slot"
+        );
+    }
+
+    static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+    #[test]
+    fn abort_exits_with_the_stall_code() {
+        let mut watchdog = Watchdog::new(options(true));
+        watchdog.exit = |code| EXIT_CODE.store(code, Ordering::SeqCst);
+        let watchdog = Arc::new(watchdog);
+        let guard = watchdog.watch(diagnostics);
+        watchdog.send("a spawned task");
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while EXIT_CODE.load(Ordering::SeqCst) == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        drop(guard);
+        assert_eq!(EXIT_CODE.load(Ordering::SeqCst), STALL_EXIT_CODE);
+        assert_eq!(watchdog.last_stall().unwrap().parked_on, "a spawned task");
+    }
+}
@@ -3,31 +3,178 @@
 
 use std::io::{self, BufRead, Write};
 
+/// Write one message, LSP-framed or as a line
+fn send(out: &mut impl Write, lsp: bool, message: &str) {
+    if lsp {
+        write!(out, "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+    } else {
+        writeln!(out, "{}", message).unwrap();
+    }
+    out.flush().unwrap();
+}
+
+/// Read one message, LSP-framed or as a line. None at EOF.
+fn receive(input: &mut impl BufRead, lsp: bool) -> Option<String> {
+    let mut line = String::new();
+    if !lsp {
+        return (input.read_line(&mut line).ok()? > 0).then_some(line);
+    }
+    let mut length = None;
+    loop {
+        line.clear();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        match line.trim().split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                length = value.trim().parse().ok();
+            }
+            _ if line.trim().is_empty() && length.is_some() => break,
+            _ => {}
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    String::from_utf8(body).ok()
+}
+
+/// A `notifications/resources/updated` for `uri`
+fn resource_updated(uri: &str) -> String {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "notifications/resources/updated",
+        "params": {"uri": uri}
+    })
+    .to_string()
+}
+
 fn main() {
+    // Simulate a backend that takes a while to load before it reads anything
+    if let Some(ms) = std::env::var("MOCK_STARTUP_DELAY_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+    }
+
+    // Extra tool names to list; calling one answers with its name
+    let extra_tools: Vec<String> = std::env::var("MOCK_EXTRA_TOOLS")
+        .map(|v| v.split(',').map(String::from).collect())
+        .unwrap_or_default();
+    // Input schema of the extra tools, as JSON
+    let extra_schema: serde_json::Value = std::env::var("MOCK_EXTRA_SCHEMA")
+        .ok()
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_else(|| serde_json::json!({"type": "object"}));
+
+    // Speak LSP framing instead of newline-delimited JSON
+    let lsp = std::env::var("MOCK_LSP").is_ok_and(|v| v == "1");
+
+    // Support logging/setLevel, remembering the level
+    let logging = std::env::var("MOCK_LOGGING").is_ok_and(|v| v == "1");
+    let mut log_level = "unset".to_string();
+
+    // Version reported in serverInfo
+    let version = std::env::var("MOCK_VERSION").unwrap_or_else(|_| "0.1.0".to_string());
+
+    // Offer resource subscriptions
+    let subscribe = std::env::var("MOCK_SUBSCRIBE").is_ok_and(|v| v == "1");
+    let mut subscribed = std::collections::BTreeSet::new();
+
+    // Delay before answering ping, to look hung
+    let ping_delay = std::env::var("MOCK_PING_DELAY_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(std::time::Duration::from_millis);
+
+    // The clientInfo sent with initialize, returned by the client_info tool
+    let mut client_info = serde_json::Value::Null;
+
+    // Refuse a request when more input was already waiting behind it, as a
+    // server that can't take pipelined requests might
+    let reject_pipelined = std::env::var("MOCK_REJECT_PIPELINED").is_ok_and(|v| v == "1");
+
     let stdin = io::stdin();
     let stdout = io::stdout();
     let mut out = stdout.lock();
+    // Buffered here rather than in stdin, so what's waiting can be seen
+    let mut input = io::BufReader::with_capacity(64 * 1024, stdin.lock());
 
-    for line in stdin.lock().lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => break,
-        };
+    // Say something before being spoken to, as some servers do
+    if std::env::var("MOCK_ANNOUNCE").is_ok_and(|v| v == "1") {
+        let hello = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": {"level": "info", "data": "mock ready"}
+        });
+        send(&mut out, lsp, &hello.to_string());
+    }
 
+    while let Some(line) = receive(&mut input, lsp) {
         let msg: serde_json::Value = match serde_json::from_str(&line) {
             Ok(v) => v,
             Err(_) => continue,
         };
 
-        // Notifications have no "id" field — ignore them
+        // Notifications have no "id" field — ignore them, except for
+        // cancellations, recorded in the file MOCK_CANCEL_LOG names
         if msg.get("id").is_none() {
+            if msg["method"] == "notifications/cancelled"
+                && let Some(path) = std::env::var_os("MOCK_CANCEL_LOG")
+            {
+                use std::io::Write;
+                let mut log = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .unwrap();
+                writeln!(log, "{}", msg["params"]["requestId"]).unwrap();
+            }
             continue;
         }
 
         let id = msg["id"].clone();
+        if reject_pipelined && !input.buffer().is_empty() {
+            let error = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": -32000, "message": "pipelined request"}
+            });
+            send(&mut out, lsp, &error.to_string());
+            continue;
+        }
         let method = msg["method"].as_str().unwrap_or("");
+        if method == "initialize" {
+            client_info = msg["params"]["clientInfo"].clone();
+        }
+        // Answer requests nobody sent before the initialize result, as a
+        // racy or buggy server might
+        if method == "initialize"
+            && std::env::var("MOCK_SPURIOUS_RESPONSES").is_ok_and(|v| v == "1")
+        {
+            let stray_id = id.as_i64().map_or(9999, |n| n + 1000);
+            for stray in [
+                serde_json::json!({"jsonrpc": "2.0", "id": stray_id, "result": {}}),
+                serde_json::json!({"jsonrpc": "2.0", "id": "stray", "result": {"tools": []}}),
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32600, "message": "not initialized"}
+                }),
+            ] {
+                send(&mut out, lsp, &stray.to_string());
+            }
+        }
 
         let response = match method {
+            // Refuse to initialize, the way a server rejecting our protocol version would
+            "initialize" if std::env::var("MOCK_INIT_ERROR").is_ok_and(|v| v == "1") => {
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": -32602, "message": "unsupported protocol version"}
+                })
+            }
             "initialize" => serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": id,
@@ -35,33 +182,300 @@ fn main() {
                     "protocolVersion": "2025-11-25",
                     "capabilities": {
                         "tools": {"listChanged": false},
-                        "resources": {"listChanged": false},
-                        "prompts": {"listChanged": false}
+                        "resources": {"listChanged": false, "subscribe": subscribe},
+                        "prompts": {"listChanged": false},
+                        "logging": if logging { Some(serde_json::json!({})) } else { None }
                     },
-                    "serverInfo": {"name": "mock-mcp", "version": "0.1.0"}
+                    "serverInfo": {"name": "mock-mcp", "version": version},
+                    "instructions": "Use echo to test round-trips."
                 }
             }),
-            "tools/list" => serde_json::json!({
-                "jsonrpc": "2.0",
-                "id": id,
-                "result": {
-                    "tools": [
-                        {
-                            "name": "echo",
-                            "description": "Echo back arguments",
-                            "inputSchema": {"type": "object"}
-                        },
-                        {
-                            "name": "fail",
-                            "description": "Always fails",
-                            "inputSchema": {"type": "object"}
-                        }
-                    ]
+            "tools/list" => {
+                // Log MOCK_LIST_FLOOD lines while "scanning plugins", before answering
+                let flood: usize = std::env::var("MOCK_LIST_FLOOD")
+                    .ok()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(0);
+                for i in 0..flood {
+                    let log = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/message",
+                        "params": {"level": "info", "data": format!("scanned plugin {}", i)}
+                    });
+                    send(&mut out, lsp, &log.to_string());
                 }
-            }),
+                let mut tools = vec![
+                    serde_json::json!({
+                        "name": "echo",
+                        "description": "Echo back arguments",
+                        "inputSchema": {"type": "object"}
+                    }),
+                    serde_json::json!({
+                        "name": "fail",
+                        "description": "Always fails",
+                        "inputSchema": {"type": "object"}
+                    }),
+                ];
+                // List echo a second time, as a buggy server might
+                if std::env::var("MOCK_DUPLICATE_ECHO").is_ok_and(|v| v == "1") {
+                    tools.push(serde_json::json!({
+                        "name": "echo",
+                        "description": "Echo back arguments (again)",
+                        "inputSchema": {"type": "object"}
+                    }));
+                }
+                tools.extend(
+                    extra_tools
+                        .iter()
+                        .map(|name| serde_json::json!({"name": name, "inputSchema": extra_schema})),
+                );
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {"tools": tools}
+                })
+            }
             "tools/call" => {
                 let name = msg["params"]["name"].as_str().unwrap_or("");
-                if name == "fail" {
+                if name == "deep" {
+                    // Pathologically nested result, written raw to bypass serde's own limits
+                    let depth = 100_000;
+                    let line = format!(
+                        r#"{{"jsonrpc":"2.0","id":{},"result":{{"content":{}{}}}}}"#,
+                        id,
+                        "[".repeat(depth),
+                        "]".repeat(depth)
+                    );
+                    send(&mut out, lsp, &line);
+                    continue;
+                }
+                if name == "crash" {
+                    std::process::exit(1);
+                }
+                if name == "touch" {
+                    // Change a resource, telling whoever subscribed to it
+                    let uri = msg["params"]["arguments"]["uri"].as_str().unwrap_or("");
+                    if subscribed.contains(uri) {
+                        send(&mut out, lsp, &resource_updated(uri));
+                    }
+                }
+                if name == "log" {
+                    // Emit the arguments as a log message before answering
+                    let log = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/message",
+                        "params": msg["params"]["arguments"]
+                    });
+                    send(&mut out, lsp, &log.to_string());
+                }
+                if name == "flood" {
+                    // A log message and, given a token, a progress report, `count` times
+                    let count = msg["params"]["arguments"]["count"].as_u64().unwrap_or(100);
+                    let token = msg["params"]["_meta"].get("progressToken");
+                    for step in 1..=count {
+                        let log = serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "method": "notifications/message",
+                            "params": {"level": "info", "data": format!("step {}", step)}
+                        });
+                        send(&mut out, lsp, &log.to_string());
+                        if let Some(token) = token {
+                            let progress = serde_json::json!({
+                                "jsonrpc": "2.0",
+                                "method": "notifications/progress",
+                                "params": {"progressToken": token, "progress": step, "total": count}
+                            });
+                            send(&mut out, lsp, &progress.to_string());
+                        }
+                    }
+                }
+                if name == "slow" {
+                    let ms = msg["params"]["arguments"]["ms"].as_u64().unwrap_or(500);
+                    std::thread::sleep(std::time::Duration::from_millis(ms));
+                }
+                if name == "progress"
+                    && let Some(token) = msg["params"]["_meta"].get("progressToken")
+                {
+                    // Report progress against the caller's token before answering
+                    for step in 1..=3 {
+                        let notification = serde_json::json!({
+                            "jsonrpc": "2.0",
+                            "method": "notifications/progress",
+                            "params": {"progressToken": token, "progress": step, "total": 3}
+                        });
+                        send(&mut out, lsp, &notification.to_string());
+                        std::thread::sleep(std::time::Duration::from_millis(20));
+                    }
+                }
+                if extra_tools.iter().any(|t| t == name) {
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": name}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "link" {
+                    // Point at the resource resources/read serves
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{
+                                "type": "resource_link",
+                                "uri": "file:///test.txt",
+                                "name": "test_file",
+                                "mimeType": "text/plain"
+                            }],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "huge" {
+                    // A text result of the requested size
+                    let bytes = msg["params"]["arguments"]["bytes"].as_u64().unwrap_or(0);
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": "x".repeat(bytes as usize)}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "structured" {
+                    // Only structuredContent, as the spec allows: the arguments
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [],
+                            "structuredContent": msg["params"]["arguments"],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "meta" {
+                    // Report the _meta this call arrived with, and attach some of our own
+                    let meta = &msg["params"]["_meta"];
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": meta.to_string()}],
+                            "is_error": false,
+                            "_meta": {"mock/served-by": "mock", "mock/step": 1}
+                        }
+                    })
+                } else if name == "env" {
+                    // An environment variable's raw bytes as hex, so non-UTF-8 values survive
+                    let key = msg["params"]["arguments"]["name"].as_str().unwrap_or("");
+                    let hex = std::env::var_os(key).map(|value| {
+                        value
+                            .as_encoded_bytes()
+                            .iter()
+                            .map(|b| format!("{:02x}", b))
+                            .collect::<String>()
+                    });
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": hex.unwrap_or_default()}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "client_info" {
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": client_info.to_string()}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "log_level" {
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": log_level}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "subscriptions" {
+                    let uris: Vec<_> = subscribed.iter().collect();
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": serde_json::to_string(&uris).unwrap()}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "cwd" {
+                    let cwd = std::env::current_dir().unwrap();
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": cwd.to_string_lossy()}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "wide" {
+                    let content: Vec<_> = (0..50_000)
+                        .map(|i| serde_json::json!({"type": "text", "text": i.to_string()}))
+                        .collect();
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {"content": content}
+                    })
+                } else if name == "health" {
+                    // Unhealthy while the file MOCK_HEALTH_FILE names exists
+                    let sick = std::env::var_os("MOCK_HEALTH_FILE")
+                        .is_some_and(|path| std::path::Path::new(&path).exists());
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": if sick { "pool closed" } else { "ok" }}],
+                            "is_error": sick
+                        }
+                    })
+                } else if name == "exclusive" {
+                    // Fails if another call, in any process, holds the marker
+                    // file: a tool that breaks when calls to it overlap
+                    let args = &msg["params"]["arguments"];
+                    let marker = args["marker"].as_str().unwrap_or("");
+                    let ms = args["ms"].as_u64().unwrap_or(100);
+                    let held = std::fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(marker)
+                        .is_ok();
+                    if held {
+                        std::thread::sleep(std::time::Duration::from_millis(ms));
+                        let _ = std::fs::remove_file(marker);
+                    }
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": if held { "ok" } else { "overlapped" }}],
+                            "is_error": !held
+                        }
+                    })
+                } else if name == "pid" {
+                    // Which process answered, for tests running several
+                    serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "result": {
+                            "content": [{"type": "text", "text": std::process::id().to_string()}],
+                            "is_error": false
+                        }
+                    })
+                } else if name == "fail" {
                     serde_json::json!({
                         "jsonrpc": "2.0",
                         "id": id,
@@ -82,6 +496,12 @@ fn main() {
                     })
                 }
             }
+            "ping" => {
+                if let Some(delay) = ping_delay {
+                    std::thread::sleep(delay);
+                }
+                serde_json::json!({"jsonrpc": "2.0", "id": id, "result": {}})
+            }
             "resources/list" => serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": id,
@@ -93,6 +513,19 @@ fn main() {
                     }]
                 }
             }),
+            // A text and a binary part
+            "resources/read" if std::env::var("MOCK_MIXED_RESOURCE").is_ok_and(|v| v == "1") => {
+                serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "result": {
+                        "contents": [
+                            {"uri": "file:///test.txt", "mimeType": "text/plain", "text": "hello world"},
+                            {"uri": "file:///test.png", "mimeType": "image/png", "blob": "iVBORw0KGgo="}
+                        ]
+                    }
+                })
+            }
             "resources/read" => serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": id,
@@ -103,6 +536,23 @@ fn main() {
                     }]
                 }
             }),
+            "logging/setLevel" if logging => {
+                log_level = msg["params"]["level"].as_str().unwrap_or("").to_string();
+                serde_json::json!({"jsonrpc": "2.0", "id": id, "result": {}})
+            }
+            "resources/subscribe" | "resources/unsubscribe" if subscribe => {
+                let uri = msg["params"]["uri"].as_str().unwrap_or("").to_string();
+                let response = serde_json::json!({"jsonrpc": "2.0", "id": id, "result": {}});
+                send(&mut out, lsp, &response.to_string());
+                // Report the current state right away, as servers often do
+                if method == "resources/subscribe" {
+                    send(&mut out, lsp, &resource_updated(&uri));
+                    subscribed.insert(uri);
+                } else {
+                    subscribed.remove(&uri);
+                }
+                continue;
+            }
             "prompts/list" => serde_json::json!({
                 "jsonrpc": "2.0",
                 "id": id,
@@ -131,7 +581,6 @@ fn main() {
             }),
         };
 
-        writeln!(out, "{}", serde_json::to_string(&response).unwrap()).unwrap();
-        out.flush().unwrap();
+        send(&mut out, lsp, &response.to_string());
     }
 }
@@ -0,0 +1,98 @@
+//! Copies made while building and writing a large `list_tools` response.
+//!
+//! Rather than instrumenting the allocator, this checks the two properties
+//! that keep peak memory down: the rendered text is moved into the response
+//! instead of copied, and the line is serialized straight into the writer's
+//! buffer without an intermediate string.
+
+use mcpd::connections::write_line;
+use mcpd::mcp::{CallToolResult, Content, RequestId, Response, Tool};
+use mcpd::server::render_tool_list;
+use serde_json::json;
+
+/// 1000 generated tools with sizeable schemas, split across 10 backends
+fn synthetic_catalog() -> Vec<(String, Tool)> {
+    (0..1000)
+        .map(|i| {
+            let properties: serde_json::Map<_, _> = (0..20)
+                .map(|p| {
+                    (
+                        format!("field_{}", p),
+                        json!({
+                            "type": "string",
+                            "description": format!("Generated field {} of tool {}, described at some length", p, i),
+                        }),
+                    )
+                })
+                .collect();
+            let tool = Tool {
+                name: format!("tool_{}", i),
+                description: Some(format!("Generated tool number {}", i)),
+                input_schema: json!({"type": "object", "properties": properties}),
+                title: None,
+                annotations: None,
+            };
+            (format!("backend{}__tool_{}", i % 10, i), tool)
+        })
+        .collect()
+}
+
+fn text_result(text: String) -> CallToolResult {
+    CallToolResult {
+        content: vec![Content::Text { text }],
+        is_error: false,
+        structured_content: None,
+        meta: None,
+    }
+}
+
+#[test]
+fn list_tools_response_is_written_without_copying_the_text() {
+    let catalog = synthetic_catalog();
+
+    // Reference output built the straightforward way: a JSON value per tool,
+    // the pretty text, and the response serialized to a separate string
+    let values: Vec<_> = catalog
+        .iter()
+        .map(|(name, tool)| {
+            json!({
+                "name": name,
+                "description": tool.description.clone().unwrap_or_default(),
+                "input_schema": tool.input_schema,
+            })
+        })
+        .collect();
+    let text = serde_json::to_string_pretty(&values).unwrap();
+    let response = Response::success(
+        RequestId::Number(1),
+        serde_json::to_value(text_result(text)).unwrap(),
+    );
+    let mut old_line = serde_json::to_string(&response).unwrap();
+    old_line.push('\n');
+
+    let text = render_tool_list(&catalog).unwrap();
+    let (text_ptr, text_len) = (text.as_ptr(), text.len());
+    let value = text_result(text).into_value().unwrap();
+    let moved = value["content"][0]["text"].as_str().unwrap();
+    assert_eq!(moved.len(), text_len);
+    assert_eq!(moved.as_ptr(), text_ptr, "text must be moved, not copied");
+
+    // A buffer already big enough for the line is written in place
+    let response = Response::success(RequestId::Number(1), value);
+    let mut buf = Vec::with_capacity(old_line.len());
+    let buf_ptr = buf.as_ptr();
+    write_line(&mut buf, &response).unwrap();
+    assert_eq!(buf, old_line.as_bytes(), "output must be byte-identical");
+    assert_eq!(
+        buf.as_ptr(),
+        buf_ptr,
+        "line must be serialized into the buffer"
+    );
+    assert_eq!(buf.capacity(), old_line.len());
+    assert!(
+        text_len * 10 > buf.len() * 9,
+        "the text should be nearly all of the {} byte response, got {} bytes",
+        buf.len(),
+        text_len
+    );
+}